@@ -0,0 +1,59 @@
+//! the scenarios of quickjs_runtime::bench_util measured with criterion
+//!
+//! the queue_round_trip scenarios measure the event loop queue which every facade call goes through
+//! and the string_round_trip scenarios measure the copying of strings into and out of the runtime
+//!
+//! when QJS_BENCH_JSON is set to a path the default configuration is compared with a memory limited configuration
+//! and the report is written to that path as JSON
+
+use criterion::{BenchmarkId, Criterion, Throughput};
+use quickjs_runtime::bench_util::{compare, default_scenarios};
+use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+
+fn configs() -> Vec<(&'static str, QuickJsRuntimeBuilder)> {
+    vec![
+        ("default", QuickJsRuntimeBuilder::new()),
+        (
+            "memory_limit",
+            QuickJsRuntimeBuilder::new().memory_limit(64 * 1024 * 1024),
+        ),
+    ]
+}
+
+fn scenarios(c: &mut Criterion) {
+    let scenarios = default_scenarios();
+    for (config, builder) in configs() {
+        let rt = builder.build();
+        for scenario in &scenarios {
+            scenario.setup(&rt).expect("setup failed");
+            let mut group = c.benchmark_group(scenario.name());
+            group.throughput(Throughput::Elements(scenario.ops()));
+            group.bench_function(BenchmarkId::from_parameter(config), |b| {
+                b.iter(|| scenario.run(&rt).expect("scenario failed"))
+            });
+            group.finish();
+        }
+    }
+}
+
+fn json_report() {
+    if let Ok(path) = std::env::var("QJS_BENCH_JSON") {
+        let report = compare(
+            ("default", QuickJsRuntimeBuilder::new),
+            ("memory_limit", || {
+                QuickJsRuntimeBuilder::new().memory_limit(64 * 1024 * 1024)
+            }),
+            &default_scenarios(),
+            100,
+        )
+        .expect("bench failed");
+        std::fs::write(path, report.to_json()).expect("could not write report");
+    }
+}
+
+fn main() {
+    let mut criterion = Criterion::default().configure_from_args();
+    scenarios(&mut criterion);
+    criterion.final_summary();
+    json_report();
+}
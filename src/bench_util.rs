@@ -0,0 +1,354 @@
+//! reusable benchmark scenarios for the conversion and queue layers of the runtime
+//!
+//! every scenario implements [BenchScenario], [default_scenarios] returns the scenarios which are run by the benches of this crate
+//! * [StringRoundTrip] passes a string into a function and back out, the string is copied both ways
+//! * [ObjectFanOut] converts an object with many properties to a serde value
+//! * [CallFunctionLoop] calls a script function from rust in a hot loop within one task
+//! * [PromiseResolution] waits in rust for a resolved promise of a script
+//! * [TypedArrayTransfer] moves a Uint8Array into a function and copies it back out
+//! * [QueueRoundTrip] runs empty tasks in the event loop, the overhead of every facade call
+//!
+//! [compare] runs the scenarios against two builder configurations side by side and returns a [BenchReport] which can be written as JSON
+//! # Example
+//! ```rust
+//! use quickjs_runtime::bench_util::{compare, BenchScenario, QueueRoundTrip, StringRoundTrip};
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! let scenarios: Vec<Box<dyn BenchScenario>> = vec![Box::new(StringRoundTrip { len: 1024 }), Box::new(QueueRoundTrip { tasks: 10 })];
+//! let report = compare(
+//!     ("default", QuickJsRuntimeBuilder::new),
+//!     ("limited", || QuickJsRuntimeBuilder::new().memory_limit(64 * 1024 * 1024)),
+//!     &scenarios,
+//!     5,
+//! ).expect("bench failed");
+//! assert_eq!(report.results.len(), 4);
+//! assert!(report.to_json().contains("\"string_round_trip/1024\""));
+//! ```
+
+use crate::builder::QuickJsRuntimeBuilder;
+use crate::facades::QuickJsRuntimeFacade;
+use crate::jsutils::{JsError, Script};
+use crate::quickjs_utils::functions;
+use crate::values::JsValueFacade;
+use serde::Serialize;
+use std::time::Instant;
+
+/// a scenario which can be measured against a runtime
+pub trait BenchScenario: Send + Sync {
+    /// the name of the scenario including its size, e.g. `string_round_trip/1024`
+    fn name(&self) -> String;
+    /// prepare the runtime, e.g. define the functions which are called, this is not measured
+    fn setup(&self, _rt: &QuickJsRuntimeFacade) -> Result<(), JsError> {
+        Ok(())
+    }
+    /// run one iteration of the scenario
+    fn run(&self, rt: &QuickJsRuntimeFacade) -> Result<(), JsError>;
+    /// the number of operations in one iteration, used for the throughput
+    fn ops(&self) -> u64 {
+        1
+    }
+}
+
+fn define(rt: &QuickJsRuntimeFacade, code: &str) -> Result<(), JsError> {
+    rt.eval_sync(None, Script::new("bench_setup.js", code))
+        .map(|_| ())
+}
+
+/// pass a string of `len` chars into a function which returns it
+pub struct StringRoundTrip {
+    pub len: usize,
+}
+
+impl BenchScenario for StringRoundTrip {
+    fn name(&self) -> String {
+        format!("string_round_trip/{}", self.len)
+    }
+    fn setup(&self, rt: &QuickJsRuntimeFacade) -> Result<(), JsError> {
+        define(rt, "globalThis.__benchEcho = (value) => value;")
+    }
+    fn run(&self, rt: &QuickJsRuntimeFacade) -> Result<(), JsError> {
+        let res = rt.invoke_function_sync(
+            None,
+            &[],
+            "__benchEcho",
+            vec![JsValueFacade::new_string("x".repeat(self.len))],
+        )?;
+        debug_assert_eq!(res.get_str().len(), self.len);
+        Ok(())
+    }
+}
+
+/// convert an object with `width` properties, which are objects themselves, to a serde value
+pub struct ObjectFanOut {
+    pub width: usize,
+}
+
+impl BenchScenario for ObjectFanOut {
+    fn name(&self) -> String {
+        format!("object_fan_out/{}", self.width)
+    }
+    fn setup(&self, rt: &QuickJsRuntimeFacade) -> Result<(), JsError> {
+        define(
+            rt,
+            format!(
+                "globalThis.__benchObject = {{}}; for (let i = 0; i < {}; i++) {{ __benchObject['p' + i] = {{id: i, name: 'item' + i, tags: ['a', 'b']}}; }}",
+                self.width
+            )
+            .as_str(),
+        )
+    }
+    fn run(&self, rt: &QuickJsRuntimeFacade) -> Result<(), JsError> {
+        rt.loop_realm_sync(None, |_rt, realm| {
+            let object = realm.get_object_property(&realm.get_global()?, "__benchObject")?;
+            realm.value_adapter_to_serde_value(&object).map(|_| ())
+        })
+    }
+    fn ops(&self) -> u64 {
+        self.width as u64
+    }
+}
+
+/// call a script function `calls` times from rust within a single task
+pub struct CallFunctionLoop {
+    pub calls: usize,
+}
+
+impl BenchScenario for CallFunctionLoop {
+    fn name(&self) -> String {
+        format!("call_function_loop/{}", self.calls)
+    }
+    fn setup(&self, rt: &QuickJsRuntimeFacade) -> Result<(), JsError> {
+        define(rt, "globalThis.__benchAdd = (a, b) => a + b;")
+    }
+    fn run(&self, rt: &QuickJsRuntimeFacade) -> Result<(), JsError> {
+        let calls = self.calls;
+        rt.loop_realm_sync(None, move |_rt, realm| {
+            let func = realm.get_object_property(&realm.get_global()?, "__benchAdd")?;
+            for i in 0..calls {
+                functions::call_function_q(
+                    realm,
+                    &func,
+                    &[realm.create_i32(i as i32)?, realm.create_i32(1)?],
+                    None,
+                )?;
+            }
+            Ok(())
+        })
+    }
+    fn ops(&self) -> u64 {
+        self.calls as u64
+    }
+}
+
+/// evaluate a script which returns a resolved promise and wait for its result in rust
+pub struct PromiseResolution;
+
+impl BenchScenario for PromiseResolution {
+    fn name(&self) -> String {
+        "promise_resolution".to_string()
+    }
+    fn run(&self, rt: &QuickJsRuntimeFacade) -> Result<(), JsError> {
+        match rt.eval_sync(
+            None,
+            Script::new("bench_promise.js", "Promise.resolve(42);"),
+        )? {
+            JsValueFacade::JsPromise { cached_promise } => {
+                match cached_promise.get_promise_result_sync()? {
+                    Ok(_) => Ok(()),
+                    Err(_) => Err(JsError::new_str("promise was rejected")),
+                }
+            }
+            _ => Err(JsError::new_str("not a promise")),
+        }
+    }
+}
+
+/// move a Uint8Array of `len` bytes into a function which returns it, the bytes are copied on the way out
+pub struct TypedArrayTransfer {
+    pub len: usize,
+}
+
+impl BenchScenario for TypedArrayTransfer {
+    fn name(&self) -> String {
+        format!("typed_array_transfer/{}", self.len)
+    }
+    fn setup(&self, rt: &QuickJsRuntimeFacade) -> Result<(), JsError> {
+        define(rt, "globalThis.__benchEcho = (value) => value;")
+    }
+    fn run(&self, rt: &QuickJsRuntimeFacade) -> Result<(), JsError> {
+        let res = rt.invoke_function_sync(
+            None,
+            &[],
+            "__benchEcho",
+            vec![JsValueFacade::new_uint8_array(vec![7; self.len])],
+        )?;
+        debug_assert_eq!(res.get_bytes().len(), self.len);
+        Ok(())
+    }
+}
+
+/// run `tasks` empty tasks in the event loop one after another
+pub struct QueueRoundTrip {
+    pub tasks: usize,
+}
+
+impl BenchScenario for QueueRoundTrip {
+    fn name(&self) -> String {
+        format!("queue_round_trip/{}", self.tasks)
+    }
+    fn run(&self, rt: &QuickJsRuntimeFacade) -> Result<(), JsError> {
+        for _ in 0..self.tasks {
+            rt.exe_rt_task_in_event_loop(|_rt| {});
+        }
+        Ok(())
+    }
+    fn ops(&self) -> u64 {
+        self.tasks as u64
+    }
+}
+
+/// the scenarios which are run by the benches of this crate
+pub fn default_scenarios() -> Vec<Box<dyn BenchScenario>> {
+    vec![
+        Box::new(StringRoundTrip { len: 16 }),
+        Box::new(StringRoundTrip { len: 1024 }),
+        Box::new(StringRoundTrip { len: 64 * 1024 }),
+        Box::new(ObjectFanOut { width: 10 }),
+        Box::new(ObjectFanOut { width: 1000 }),
+        Box::new(CallFunctionLoop { calls: 1000 }),
+        Box::new(PromiseResolution),
+        Box::new(TypedArrayTransfer { len: 64 * 1024 }),
+        Box::new(QueueRoundTrip { tasks: 100 }),
+    ]
+}
+
+/// the measurement of a scenario in a configuration
+#[derive(Clone, Debug, Serialize)]
+pub struct BenchResult {
+    /// the name of the builder configuration
+    pub config: String,
+    pub scenario: String,
+    pub iterations: u64,
+    /// the mean duration of an iteration in nanoseconds
+    pub mean_nanos: f64,
+    /// the operations per second, see [BenchScenario::ops]
+    pub ops_per_sec: f64,
+}
+
+/// the mean durations of a scenario in the baseline and the candidate configuration
+#[derive(Clone, Debug, Serialize)]
+pub struct BenchComparison {
+    pub scenario: String,
+    pub baseline_mean_nanos: f64,
+    pub candidate_mean_nanos: f64,
+    /// the baseline duration divided by the candidate duration, above 1.0 means the candidate is faster
+    pub speedup: f64,
+}
+
+/// the results of [compare]
+#[derive(Clone, Debug, Serialize)]
+pub struct BenchReport {
+    pub baseline: String,
+    pub candidate: String,
+    pub results: Vec<BenchResult>,
+    pub comparisons: Vec<BenchComparison>,
+}
+
+impl BenchReport {
+    /// the report as JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("could not serialize bench report")
+    }
+}
+
+/// measure a scenario, the scenario is set up and run once before it is measured
+pub fn measure(
+    config: &str,
+    rt: &QuickJsRuntimeFacade,
+    scenario: &dyn BenchScenario,
+    iterations: u64,
+) -> Result<BenchResult, JsError> {
+    scenario.setup(rt)?;
+    scenario.run(rt)?;
+    let iterations = iterations.max(1);
+    let start = Instant::now();
+    for _ in 0..iterations {
+        scenario.run(rt)?;
+    }
+    let elapsed = start.elapsed();
+    let mean_nanos = elapsed.as_nanos() as f64 / iterations as f64;
+    Ok(BenchResult {
+        config: config.to_string(),
+        scenario: scenario.name(),
+        iterations,
+        mean_nanos,
+        ops_per_sec: (scenario.ops() * iterations) as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+    })
+}
+
+/// measure the scenarios in a runtime of the baseline and a runtime of the candidate configuration
+pub fn compare<A, B>(
+    baseline: (&str, A),
+    candidate: (&str, B),
+    scenarios: &[Box<dyn BenchScenario>],
+    iterations: u64,
+) -> Result<BenchReport, JsError>
+where
+    A: FnOnce() -> QuickJsRuntimeBuilder,
+    B: FnOnce() -> QuickJsRuntimeBuilder,
+{
+    let baseline_rt = (baseline.1)().try_build()?;
+    let candidate_rt = (candidate.1)().try_build()?;
+
+    let mut results = vec![];
+    let mut comparisons = vec![];
+    for scenario in scenarios {
+        let baseline_res = measure(baseline.0, &baseline_rt, scenario.as_ref(), iterations)?;
+        let candidate_res = measure(candidate.0, &candidate_rt, scenario.as_ref(), iterations)?;
+        comparisons.push(BenchComparison {
+            scenario: scenario.name(),
+            baseline_mean_nanos: baseline_res.mean_nanos,
+            candidate_mean_nanos: candidate_res.mean_nanos,
+            speedup: baseline_res.mean_nanos / candidate_res.mean_nanos.max(f64::EPSILON),
+        });
+        results.push(baseline_res);
+        results.push(candidate_res);
+    }
+    Ok(BenchReport {
+        baseline: baseline.0.to_string(),
+        candidate: candidate.0.to_string(),
+        results,
+        comparisons,
+    })
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::bench_util::{compare, default_scenarios};
+    use crate::builder::QuickJsRuntimeBuilder;
+
+    #[test]
+    fn test_compare() {
+        let scenarios = default_scenarios();
+        let report = compare(
+            ("default", QuickJsRuntimeBuilder::new),
+            ("limited", || {
+                QuickJsRuntimeBuilder::new().memory_limit(64 * 1024 * 1024)
+            }),
+            &scenarios,
+            2,
+        )
+        .expect("bench failed");
+        assert_eq!(report.results.len(), scenarios.len() * 2);
+        assert_eq!(report.comparisons.len(), scenarios.len());
+        assert!(report
+            .results
+            .iter()
+            .all(|res| res.iterations == 2 && res.mean_nanos > 0.0));
+
+        let json: serde_json::Value =
+            serde_json::from_str(report.to_json().as_str()).expect("invalid json");
+        assert_eq!(json["baseline"], "default");
+        assert_eq!(json["results"][1]["config"], "limited");
+        assert_eq!(json["comparisons"][0]["scenario"], "string_round_trip/16");
+    }
+}
@@ -1,17 +1,270 @@
 //! contains the QuickJsRuntimeBuilder which may be used to instantiate a new QuickjsRuntimeFacade
 
 use crate::facades::QuickJsRuntimeFacade;
+use crate::features::buffer::{self, BufferModuleLoader};
+use crate::features::commonjs;
+use crate::features::encoding::{self, EncodingModuleLoader};
+use crate::features::kvstore::{self, KvStoreModuleLoader, KvStoreOptions, KvStoreProvider};
+use crate::features::limits;
+use crate::features::memorypressure::{
+    self, MemoryLimitAction, MemoryLimitExceeded, MemoryLimitExceededHandler, MemoryPressureOptions,
+};
+use crate::features::realms::{self, RealmsModuleLoader, RealmsModuleOptions};
+use crate::features::structuredlog::{
+    self, LogRecord, StructuredLogModuleLoader, StructuredLogOptions,
+};
+use crate::features::testing::{AssertModuleLoader, TestSuiteLoader, ASSERT_MODULE_NAME};
+use crate::features::timeslice::{self, CooperativePreProcessor};
+use crate::quickjs_utils::codecs::{JsClassMatcher, ValueCodec};
 use crate::quickjsrealmadapter::QuickJsRealmAdapter;
 use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use crate::quickjsvalueadapter::QuickJsValueAdapter;
 
-use crate::jsutils::modules::{CompiledModuleLoader, NativeModuleLoader, ScriptModuleLoader};
+use crate::jsutils::asyncstacks;
+use crate::jsutils::bytecodecache::CachePolicy;
+use crate::jsutils::compileaudit::{CompileAuditHook, CompileAuditOptions, CompileEvent};
+use crate::jsutils::debugdump;
+use crate::jsutils::executor::JsExecutor;
+use crate::jsutils::idle::IdleCallback;
+use crate::jsutils::microtasks::MicrotaskBudget;
+use crate::jsutils::modulegraph;
+use crate::jsutils::modules::{
+    CompiledModuleLoader, ModuleResolver, NativeModuleLoader, ResolvedSpecifier, RetryPolicy,
+    ScriptModuleLoader,
+};
+use crate::jsutils::redaction::RedactionHook;
+use crate::jsutils::startup::{StartupFailurePolicy, StartupScript};
+use crate::jsutils::taskscope::EvalOptions;
+use crate::jsutils::uncaught::UncaughtErrorHook;
 use crate::jsutils::{JsError, ScriptPreProcessor};
+use crate::values::JsValueFacade;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::fmt::{Debug, Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::Duration;
 
 pub type EsRuntimeInitHooks =
     Vec<Box<dyn FnOnce(&QuickJsRuntimeFacade) -> Result<(), JsError> + Send + 'static>>;
 
+/// a sink for console output which replaces the log crate, see [console_printer](QuickJsRuntimeBuilder::console_printer)
+///
+/// the printer is called with the level, the id of the realm and the args of the console call,
+/// in a console.group the first arg is indented when it is a string, otherwise the indentation is passed as an extra first arg
+pub type ConsolePrinter = Arc<dyn Fn(log::Level, &str, &[JsValueFacade]) + Send + Sync>;
+
+/// a curated bundle of global features, see [QuickJsRuntimeBuilder::web_platform_defaults]
+///
+/// URL is not part of either bundle, this crate has no implementation of it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WebDefaults {
+    /// console, setTimeout, setInterval and queueMicrotask
+    Minimal,
+    /// everything in Minimal plus setImmediate, encoding (atob, btoa, TextEncoder and TextDecoder), structuredClone
+    /// and crypto (crypto.getRandomValues and crypto.randomUUID)
+    Standard,
+}
+
+impl WebDefaults {
+    /// get the names of the features in this bundle
+    /// features which were not compiled into this build (see the crate features) are skipped when installing
+    pub fn get_feature_names(&self) -> Vec<&'static str> {
+        let mut names = vec!["console", "setTimeout", "setInterval", "queueMicrotask"];
+        if self == &WebDefaults::Standard {
+            names.extend(["setImmediate", "encoding", "structuredClone", "crypto"]);
+        }
+        names
+    }
+}
+
+/// a single-valued option which was set twice with different values, see [strict](QuickJsRuntimeBuilder::strict)
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct BuilderConflict {
+    /// the name of the builder method
+    pub option: &'static str,
+    pub previous: String,
+    /// the value which replaced the previous value
+    pub value: String,
+}
+
+impl Display for BuilderConflict {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} was set to {} and then to {}",
+            self.option, self.previous, self.value
+        )
+    }
+}
+
+fn conflict<T: Debug + PartialEq>(
+    option: &'static str,
+    previous: &Option<T>,
+    value: &T,
+) -> Option<BuilderConflict> {
+    match previous {
+        Some(previous) if previous != value => Some(BuilderConflict {
+            option,
+            previous: format!("{previous:?}"),
+            value: format!("{value:?}"),
+        }),
+        _ => None,
+    }
+}
+
+fn hook_conflict(option: &'static str, is_set: bool) -> Option<BuilderConflict> {
+    is_set.then(|| BuilderConflict {
+        option,
+        previous: "a hook".to_string(),
+        value: "another hook".to_string(),
+    })
+}
+
+/// the effective configuration of a builder, see [summary](QuickJsRuntimeBuilder::summary)
+///
+/// hooks and loaders can not be inspected so only their number is included
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct BuilderSummary {
+    pub memory_limit: Option<u64>,
+    pub memory_limit_exceeded_handler: bool,
+    pub gc_threshold: Option<u64>,
+    pub max_stack_size: Option<u64>,
+    pub gc_interval: Option<Duration>,
+    pub script_timeout: Option<Duration>,
+    pub module_eval_timeout: Option<Duration>,
+    pub max_conversion_depth: Option<usize>,
+    pub max_execution_depth: Option<usize>,
+    pub max_queue_size: Option<usize>,
+    pub microtask_budget: Option<String>,
+    /// the min idle time of the idle callback
+    pub idle_callback: Option<Duration>,
+    pub idle_callback_budget: Option<Duration>,
+    pub web_platform_defaults: Option<String>,
+    pub disabled_features: Vec<String>,
+    /// the modules with a fixed name which were enabled, e.g. `quickjs:encoding`
+    pub modules: Vec<String>,
+    pub script_module_loaders: usize,
+    pub native_module_loaders: usize,
+    pub compiled_module_loaders: usize,
+    pub script_pre_processors: usize,
+    pub init_hooks: usize,
+    pub startup_scripts: usize,
+    pub startup_failure_policy: String,
+    pub module_load_retry: Option<String>,
+    pub module_resolver: bool,
+    pub module_cache: bool,
+    pub default_eval_options: Option<String>,
+    pub executor: bool,
+    pub redaction_hook: bool,
+    pub uncaught_error_hook: bool,
+    pub compile_audit: Option<String>,
+    pub console_printer: bool,
+    pub value_codecs: usize,
+    pub interrupt_handler: bool,
+    pub detach_dropped_futures: bool,
+    pub drop_realms_with_last_handle: bool,
+    pub sync_bridge: bool,
+    pub bytecode_cache_dir: Option<String>,
+    pub strict: bool,
+    pub conflicts: Vec<BuilderConflict>,
+}
+
+impl Display for BuilderSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        fn opt<T: Debug>(value: &Option<T>) -> String {
+            match value {
+                Some(value) => format!("{value:?}"),
+                None => "default".to_string(),
+            }
+        }
+        writeln!(
+            f,
+            "memory_limit: {} (exceeded_handler: {})",
+            opt(&self.memory_limit),
+            self.memory_limit_exceeded_handler
+        )?;
+        writeln!(f, "gc_threshold: {}", opt(&self.gc_threshold))?;
+        writeln!(f, "max_stack_size: {}", opt(&self.max_stack_size))?;
+        writeln!(f, "gc_interval: {}", opt(&self.gc_interval))?;
+        writeln!(f, "script_timeout: {}", opt(&self.script_timeout))?;
+        writeln!(f, "module_eval_timeout: {}", opt(&self.module_eval_timeout))?;
+        writeln!(
+            f,
+            "max_conversion_depth: {}",
+            opt(&self.max_conversion_depth)
+        )?;
+        writeln!(f, "max_execution_depth: {}", opt(&self.max_execution_depth))?;
+        writeln!(f, "max_queue_size: {}", opt(&self.max_queue_size))?;
+        writeln!(f, "microtask_budget: {}", opt(&self.microtask_budget))?;
+        writeln!(
+            f,
+            "idle_callback: {} (budget: {})",
+            opt(&self.idle_callback),
+            opt(&self.idle_callback_budget)
+        )?;
+        writeln!(
+            f,
+            "web_platform_defaults: {}",
+            opt(&self.web_platform_defaults)
+        )?;
+        writeln!(f, "disabled_features: {:?}", self.disabled_features)?;
+        writeln!(f, "modules: {:?}", self.modules)?;
+        writeln!(
+            f,
+            "loaders: {} script, {} native, {} compiled",
+            self.script_module_loaders, self.native_module_loaders, self.compiled_module_loaders
+        )?;
+        writeln!(f, "script_pre_processors: {}", self.script_pre_processors)?;
+        writeln!(f, "init_hooks: {}", self.init_hooks)?;
+        writeln!(
+            f,
+            "startup_scripts: {} ({})",
+            self.startup_scripts, self.startup_failure_policy
+        )?;
+        writeln!(
+            f,
+            "module_load_retry: {}, module_resolver: {}, module_cache: {}",
+            opt(&self.module_load_retry),
+            self.module_resolver,
+            self.module_cache
+        )?;
+        writeln!(
+            f,
+            "default_eval_options: {}",
+            opt(&self.default_eval_options)
+        )?;
+        writeln!(
+            f,
+            "executor: {}, redaction_hook: {}, uncaught_error_hook: {}, interrupt_handler: {}",
+            self.executor, self.redaction_hook, self.uncaught_error_hook, self.interrupt_handler
+        )?;
+        writeln!(f, "compile_audit: {}", opt(&self.compile_audit))?;
+        writeln!(f, "console_printer: {}", self.console_printer)?;
+        writeln!(f, "value_codecs: {}", self.value_codecs)?;
+        writeln!(
+            f,
+            "detach_dropped_futures: {}, drop_realms_with_last_handle: {}, sync_bridge: {}",
+            self.detach_dropped_futures, self.drop_realms_with_last_handle, self.sync_bridge
+        )?;
+        writeln!(f, "bytecode_cache_dir: {}", opt(&self.bytecode_cache_dir))?;
+        write!(
+            f,
+            "strict: {}, conflicts: {}",
+            self.strict,
+            self.conflicts.len()
+        )?;
+        for conflict in &self.conflicts {
+            write!(f, "\n  {conflict}")?;
+        }
+        Ok(())
+    }
+}
+
 /// the EsRuntimeBuilder is used to init an EsRuntime
+///
+/// setting a single-valued option (e.g. memory_limit) twice with different values replaces the first value and logs a warning
+/// when the runtime is built, with [strict](Self::strict) building fails instead
 /// # Example
 /// ```rust
 /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
@@ -25,19 +278,74 @@ pub struct QuickJsRuntimeBuilder {
     pub(crate) native_module_loaders: Vec<Box<dyn NativeModuleLoader + Send>>,
     pub(crate) compiled_module_loaders: Vec<Box<dyn CompiledModuleLoader + Send>>,
     pub(crate) opt_memory_limit_bytes: Option<u64>,
+    pub(crate) opt_memory_limit_exceeded_handler: Option<MemoryLimitExceededHandler>,
     pub(crate) opt_gc_threshold: Option<u64>,
     pub(crate) opt_max_stack_size: Option<u64>,
     pub(crate) opt_gc_interval: Option<Duration>,
+    pub(crate) opt_script_timeout: Option<Duration>,
+    pub(crate) opt_module_eval_timeout: Option<Duration>,
+    pub(crate) opt_max_conversion_depth: Option<usize>,
+    pub(crate) opt_max_execution_depth: Option<usize>,
+    pub(crate) opt_max_queue_size: Option<usize>,
+    pub(crate) opt_microtask_budget: Option<MicrotaskBudget>,
+    pub(crate) opt_idle_callback: Option<(Duration, IdleCallback)>,
+    pub(crate) opt_idle_callback_budget: Option<Duration>,
     pub(crate) runtime_init_hooks: EsRuntimeInitHooks,
     pub(crate) script_pre_processors: Vec<Box<dyn ScriptPreProcessor + Send>>,
     #[allow(clippy::type_complexity)]
     pub(crate) interrupt_handler: Option<Box<dyn Fn(&QuickJsRuntimeAdapter) -> bool + Send>>,
+    pub(crate) opt_web_defaults: Option<WebDefaults>,
+    pub(crate) disabled_features: Vec<String>,
+    pub(crate) opt_executor: Option<Arc<dyn JsExecutor>>,
+    pub(crate) opt_module_load_retry: Option<RetryPolicy>,
+    pub(crate) opt_module_resolver: Option<ModuleResolver>,
+    pub(crate) opt_module_cache: Option<bool>,
+    pub(crate) opt_default_eval_options: Option<EvalOptions>,
+    pub(crate) opt_redaction_hook: Option<RedactionHook>,
+    pub(crate) opt_uncaught_error_hook: Option<UncaughtErrorHook>,
+    pub(crate) opt_compile_audit: Option<(CompileAuditOptions, Option<CompileAuditHook>)>,
+    pub(crate) opt_console_printer: Option<ConsolePrinter>,
+    pub(crate) value_codecs: Vec<ValueCodec>,
+    pub(crate) detach_dropped_futures: bool,
+    pub(crate) drop_realms_with_last_handle: bool,
+    pub(crate) allow_sync_bridge: bool,
+    pub(crate) opt_bytecode_cache: Option<(PathBuf, CachePolicy)>,
+    pub(crate) startup_scripts: Vec<StartupScript>,
+    pub(crate) startup_failure_policy: StartupFailurePolicy,
+    pub(crate) single_modules: Vec<&'static str>,
+    pub(crate) strict: bool,
+    pub(crate) conflicts: Vec<BuilderConflict>,
 }
 
 impl QuickJsRuntimeBuilder {
     /// build an EsRuntime
+    /// # Panics
+    /// when a startup script fails and the [StartupFailurePolicy] is FailBuild, use [try_build](Self::try_build) to get the error instead
     pub fn build(self) -> QuickJsRuntimeFacade {
+        self.try_build()
+            .unwrap_or_else(|err| panic!("could not build runtime: {err}"))
+    }
+
+    /// build an EsRuntime, fails when a startup script fails and the [StartupFailurePolicy] is FailBuild
+    pub fn try_build(self) -> Result<QuickJsRuntimeFacade, JsError> {
         log::debug!("QuickJsRuntimeBuilder.build");
+        if !self.conflicts.is_empty() {
+            if self.strict {
+                let conflicts: Vec<String> = self.conflicts.iter().map(|c| c.to_string()).collect();
+                return Err(JsError::new_string(format!(
+                    "conflicting builder options: {}",
+                    conflicts.join("; ")
+                )));
+            }
+            for c in &self.conflicts {
+                log::warn!(
+                    "conflicting builder option {}: {} was replaced by {}",
+                    c.option,
+                    c.previous,
+                    c.value
+                );
+            }
+        }
         QuickJsRuntimeFacade::new(self)
     }
 
@@ -48,15 +356,204 @@ impl QuickJsRuntimeBuilder {
             native_module_loaders: vec![],
             compiled_module_loaders: vec![],
             opt_memory_limit_bytes: None,
+            opt_memory_limit_exceeded_handler: None,
             opt_gc_threshold: None,
             opt_max_stack_size: None,
             opt_gc_interval: None,
+            opt_script_timeout: None,
+            opt_module_eval_timeout: None,
+            opt_max_conversion_depth: None,
+            opt_max_execution_depth: None,
+            opt_max_queue_size: None,
+            opt_microtask_budget: None,
+            opt_idle_callback: None,
+            opt_idle_callback_budget: None,
             runtime_init_hooks: vec![],
             script_pre_processors: vec![],
             interrupt_handler: None,
+            opt_web_defaults: None,
+            disabled_features: vec![],
+            opt_executor: None,
+            opt_module_load_retry: None,
+            opt_module_resolver: None,
+            opt_module_cache: None,
+            opt_default_eval_options: None,
+            opt_redaction_hook: None,
+            opt_uncaught_error_hook: None,
+            opt_compile_audit: None,
+            opt_console_printer: None,
+            value_codecs: vec![],
+            detach_dropped_futures: false,
+            drop_realms_with_last_handle: false,
+            allow_sync_bridge: false,
+            opt_bytecode_cache: None,
+            startup_scripts: vec![],
+            startup_failure_policy: StartupFailurePolicy::default(),
+            single_modules: vec![],
+            strict: false,
+            conflicts: vec![],
         }
     }
 
+    /// fail [try_build](Self::try_build) (and panic in [build](Self::build)) when a single-valued option was set twice with different values
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+    /// let res = QuickJsRuntimeBuilder::new()
+    ///     .memory_limit(1024 * 1024)
+    ///     .memory_limit(2 * 1024 * 1024)
+    ///     .strict(true)
+    ///     .try_build();
+    /// assert!(res.is_err());
+    /// ```
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    /// the single-valued options which were set twice with different values
+    pub fn get_conflicts(&self) -> &[BuilderConflict] {
+        &self.conflicts
+    }
+
+    pub fn get_memory_limit(&self) -> Option<u64> {
+        self.opt_memory_limit_bytes
+    }
+
+    pub fn get_gc_threshold(&self) -> Option<u64> {
+        self.opt_gc_threshold
+    }
+
+    pub fn get_max_stack_size(&self) -> Option<u64> {
+        self.opt_max_stack_size
+    }
+
+    pub fn get_gc_interval(&self) -> Option<Duration> {
+        self.opt_gc_interval
+    }
+
+    pub fn get_web_platform_defaults(&self) -> Option<WebDefaults> {
+        self.opt_web_defaults
+    }
+
+    /// get the effective configuration, e.g. to log it before building, the summary is also included in a [debug_dump](crate::facades::QuickJsRuntimeFacade::debug_dump)
+    pub fn summary(&self) -> BuilderSummary {
+        BuilderSummary {
+            memory_limit: self.opt_memory_limit_bytes,
+            memory_limit_exceeded_handler: self.opt_memory_limit_exceeded_handler.is_some(),
+            gc_threshold: self.opt_gc_threshold,
+            max_stack_size: self.opt_max_stack_size,
+            gc_interval: self.opt_gc_interval,
+            script_timeout: self.opt_script_timeout,
+            module_eval_timeout: self.opt_module_eval_timeout,
+            max_conversion_depth: self.opt_max_conversion_depth,
+            max_execution_depth: self.opt_max_execution_depth,
+            max_queue_size: self.opt_max_queue_size,
+            microtask_budget: self.opt_microtask_budget.as_ref().map(|b| format!("{b:?}")),
+            idle_callback: self
+                .opt_idle_callback
+                .as_ref()
+                .map(|(min_idle, _)| *min_idle),
+            idle_callback_budget: self.opt_idle_callback_budget,
+            web_platform_defaults: self.opt_web_defaults.map(|d| format!("{d:?}")),
+            disabled_features: self.disabled_features.clone(),
+            modules: self.single_modules.iter().map(|m| m.to_string()).collect(),
+            script_module_loaders: self.script_module_loaders.len(),
+            native_module_loaders: self.native_module_loaders.len(),
+            compiled_module_loaders: self.compiled_module_loaders.len(),
+            script_pre_processors: self.script_pre_processors.len(),
+            init_hooks: self.runtime_init_hooks.len(),
+            startup_scripts: self.startup_scripts.len(),
+            startup_failure_policy: format!("{:?}", self.startup_failure_policy),
+            module_load_retry: self.opt_module_load_retry.map(|r| format!("{r:?}")),
+            module_resolver: self.opt_module_resolver.is_some(),
+            module_cache: self.opt_module_cache.unwrap_or(true),
+            default_eval_options: self
+                .opt_default_eval_options
+                .as_ref()
+                .map(|o| format!("{o:?}")),
+            executor: self.opt_executor.is_some(),
+            redaction_hook: self.opt_redaction_hook.is_some(),
+            uncaught_error_hook: self.opt_uncaught_error_hook.is_some(),
+            compile_audit: self
+                .opt_compile_audit
+                .as_ref()
+                .map(|(options, hook)| format!("{options:?} (hook: {})", hook.is_some())),
+            console_printer: self.opt_console_printer.is_some(),
+            value_codecs: self.value_codecs.len(),
+            interrupt_handler: self.interrupt_handler.is_some(),
+            detach_dropped_futures: self.detach_dropped_futures,
+            drop_realms_with_last_handle: self.drop_realms_with_last_handle,
+            sync_bridge: self.allow_sync_bridge,
+            bytecode_cache_dir: self
+                .opt_bytecode_cache
+                .as_ref()
+                .map(|(dir, policy)| format!("{} {policy:?}", dir.display())),
+            strict: self.strict,
+            conflicts: self.conflicts.clone(),
+        }
+    }
+
+    // add the loader of a module with a fixed name, a second registration of the same module is ignored
+    // and is a conflict when the loader has a configuration (e.g. the provider of the kv store)
+    fn single_module_loader<S: NativeModuleLoader + Send + 'static>(
+        mut self,
+        module_name: &'static str,
+        module_loader: S,
+        configured: bool,
+    ) -> Self {
+        if self.single_modules.contains(&module_name) {
+            if configured {
+                self.conflicts.push(BuilderConflict {
+                    option: module_name,
+                    previous: "a loader".to_string(),
+                    value: "another loader which was ignored".to_string(),
+                });
+            }
+            self
+        } else {
+            self.single_modules.push(module_name);
+            self.native_module_loader(module_loader)
+        }
+    }
+
+    /// add a script which is evaluated in the main realm when the runtime is built, after the realm init hooks
+    ///
+    /// startup scripts and modules are evaluated in the order in which they were added, see [startup](crate::jsutils::startup)
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+    /// use quickjs_runtime::jsutils::Script;
+    /// use quickjs_runtime::jsutils::startup::StartupScript;
+    /// let rt = QuickJsRuntimeBuilder::new()
+    ///     .startup_script(Script::new("bootstrap.js", "globalThis.config = {debug: false};"))
+    ///     .startup_script(StartupScript::new(Script::new("polyfills.js", "globalThis.sum = (a, b) => a + b;")).in_new_realms())
+    ///     .try_build()
+    ///     .expect("startup failed");
+    /// let res = rt.eval_sync(Some("other_realm"), Script::new("test.js", "sum(1, 2)")).expect("script failed");
+    /// assert_eq!(res.get_i32(), 3);
+    /// ```
+    pub fn startup_script<S: Into<StartupScript>>(mut self, script: S) -> Self {
+        self.startup_scripts.push(script.into());
+        self
+    }
+
+    /// add a module which is evaluated in the main realm when the runtime is built, see [startup_script](Self::startup_script)
+    pub fn startup_module<S: Into<StartupScript>>(mut self, script: S) -> Self {
+        self.startup_scripts.push(script.into().module());
+        self
+    }
+
+    /// set what happens when a startup script fails, the default is [StartupFailurePolicy::FailBuild]
+    pub fn startup_failure_policy(mut self, policy: StartupFailurePolicy) -> Self {
+        self.startup_failure_policy = policy;
+        self
+    }
+
     /// add a script loaders which will be used to load modules when they are imported from script
     /// # Example
     /// ```rust
@@ -88,6 +585,255 @@ impl QuickJsRuntimeBuilder {
         self
     }
 
+    /// retry failed loads of script modules for dynamic imports (`import('my_module.mes')`)
+    /// static imports are not retried and fail fast
+    /// loaders should implement [try_load_module](ScriptModuleLoader::try_load_module) to report failures
+    /// the backoff between attempts is awaited on the executor, the EventLoop keeps running timers and jobs in the meantime
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+    /// use quickjs_runtime::jsutils::modules::RetryPolicy;
+    /// use std::time::Duration;
+    /// let rt = QuickJsRuntimeBuilder::new()
+    ///     .module_load_retry(RetryPolicy{attempts: 3, backoff: Duration::from_millis(100)})
+    ///     .build();
+    /// ```
+    pub fn module_load_retry(mut self, policy: RetryPolicy) -> Self {
+        self.conflicts.extend(conflict(
+            "module_load_retry",
+            &self.opt_module_load_retry,
+            &policy,
+        ));
+        self.opt_module_load_retry = Some(policy);
+        self
+    }
+
+    /// rewrite the specifiers of static and dynamic imports before the module loaders normalize them, e.g. to pin versions from a lockfile
+    ///
+    /// the resolver is called with the path of the importing script and the specifier, once per pair, later imports reuse its result
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+    /// use quickjs_runtime::jsutils::modules::ResolvedSpecifier;
+    /// let rt = QuickJsRuntimeBuilder::new()
+    ///     .module_resolver(|_base, specifier| match specifier {
+    ///         "lodash" => ResolvedSpecifier::Rewritten("vendored/lodash@4.17.21/index.js".to_string()),
+    ///         _ => ResolvedSpecifier::Unchanged,
+    ///     })
+    ///     .build();
+    /// ```
+    pub fn module_resolver<R: Fn(&str, &str) -> ResolvedSpecifier + Send + 'static>(
+        mut self,
+        resolver: R,
+    ) -> Self {
+        self.conflicts.extend(hook_conflict(
+            "module_resolver",
+            self.opt_module_resolver.is_some(),
+        ));
+        self.opt_module_resolver = Some(Box::new(resolver));
+        self
+    }
+
+    /// keep the modules which were loaded (the default), when disabled every import of a script loads the imported modules again
+    ///
+    /// use this during development when the module loaders read files which are edited, see [modulecache](crate::jsutils::modulecache),
+    /// a single module can be loaded again with [invalidate_module](QuickJsRuntimeFacade::invalidate_module)
+    pub fn module_cache(mut self, enabled: bool) -> Self {
+        self.conflicts
+            .extend(conflict("module_cache", &self.opt_module_cache, &enabled));
+        self.opt_module_cache = Some(enabled);
+        self
+    }
+
+    /// set the default options for [eval_with_options](QuickJsRuntimeFacade::eval_with_options), the options of a call override the fields they set
+    ///
+    /// the timeout of the defaults is also used by the evals, module evals and function invocations without options
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+    /// use quickjs_runtime::jsutils::taskscope::EvalOptions;
+    /// use quickjs_runtime::jsutils::Script;
+    /// use std::time::Duration;
+    /// let rt = QuickJsRuntimeBuilder::new()
+    ///     .default_eval_options(EvalOptions::new().timeout(Duration::from_millis(100)))
+    ///     .build();
+    /// let err = rt.eval_sync(None, Script::new("loop.js", "while (true) {}")).expect_err("script should time out");
+    /// assert_eq!(err.get_name(), "TimeoutError");
+    /// ```
+    pub fn default_eval_options(mut self, options: EvalOptions) -> Self {
+        self.conflicts.extend(conflict(
+            "default_eval_options",
+            &self.opt_default_eval_options,
+            &options,
+        ));
+        self.opt_default_eval_options = Some(options);
+        self
+    }
+
+    /// set a hook which is used to scrub secrets from strings which the runtime emits outward
+    ///
+    /// the hook is applied once to console output, the messages and stacks of errors thrown by scripts and the reasons of unhandled promise rejections
+    /// values which are returned by the facades are not redacted
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+    /// use std::borrow::Cow;
+    /// let rt = QuickJsRuntimeBuilder::new()
+    ///     .redaction_hook(|s| {
+    ///         if s.contains("Bearer ") {
+    ///             Cow::Owned(s.replace("Bearer ", "Bearer [redacted] "))
+    ///         } else {
+    ///             Cow::Borrowed(s)
+    ///         }
+    ///     })
+    ///     .build();
+    /// ```
+    pub fn redaction_hook<H>(mut self, hook: H) -> Self
+    where
+        H: Fn(&str) -> Cow<str> + Send + Sync + 'static,
+    {
+        self.conflicts.extend(hook_conflict(
+            "redaction_hook",
+            self.opt_redaction_hook.is_some(),
+        ));
+        self.opt_redaction_hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// set a hook which is called with the errors which can not be returned to a caller, see [uncaught](crate::jsutils::uncaught)
+    pub fn uncaught_error_hook<H>(mut self, hook: H) -> Self
+    where
+        H: Fn(&str, &str, &JsError) + Send + Sync + 'static,
+    {
+        self.conflicts.extend(hook_conflict(
+            "uncaught_error_hook",
+            self.opt_uncaught_error_hook.is_some(),
+        ));
+        self.opt_uncaught_error_hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// record a [CompileEvent] for every piece of code which is compiled, the events are kept in a ring buffer
+    /// which can be read with [compile_audit_log](QuickJsRuntimeFacade::compile_audit_log), see [compileaudit](crate::jsutils::compileaudit)
+    pub fn compile_audit(mut self, options: CompileAuditOptions) -> Self {
+        self.conflicts.extend(hook_conflict(
+            "compile_audit",
+            self.opt_compile_audit.is_some(),
+        ));
+        self.opt_compile_audit = Some((options, None));
+        self
+    }
+
+    /// pass a [CompileEvent] for every piece of code which is compiled to a hook instead of keeping them,
+    /// see [compileaudit](crate::jsutils::compileaudit)
+    pub fn compile_audit_hook<H>(mut self, options: CompileAuditOptions, hook: H) -> Self
+    where
+        H: Fn(&CompileEvent) + Send + Sync + 'static,
+    {
+        self.conflicts.extend(hook_conflict(
+            "compile_audit",
+            self.opt_compile_audit.is_some(),
+        ));
+        self.opt_compile_audit = Some((options, Some(Arc::new(hook))));
+        self
+    }
+
+    /// set a printer which receives the output of console.log and its variants instead of the log crate
+    ///
+    /// the args are passed as [JsValueFacade](crate::values::JsValueFacade)s so objects are not stringified, strings are redacted
+    /// by the [redaction_hook](Self::redaction_hook), see [console](crate::features::console)
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+    /// let rt = QuickJsRuntimeBuilder::new()
+    ///     .console_printer(|level, realm_id, args| {
+    ///         let strs: Vec<String> = args.iter().map(|a| a.stringify()).collect();
+    ///         println!("{level} [{realm_id}] {}", strs.join(" "));
+    ///     })
+    ///     .build();
+    /// ```
+    pub fn console_printer<P>(mut self, printer: P) -> Self
+    where
+        P: Fn(log::Level, &str, &[JsValueFacade]) + Send + Sync + 'static,
+    {
+        self.conflicts.extend(hook_conflict(
+            "console_printer",
+            self.opt_console_printer.is_some(),
+        ));
+        self.opt_console_printer = Some(Arc::new(printer));
+        self
+    }
+
+    /// convert the instances of a user-defined class with an encoder and a decoder instead of to a [JsObject](crate::values::JsValueFacade::JsObject)
+    ///
+    /// codecs are tried in the order they were registered and only for objects which are not converted by a built-in conversion,
+    /// see [codecs](crate::quickjs_utils::codecs)
+    pub fn register_value_codec<E, D>(
+        mut self,
+        matcher: JsClassMatcher,
+        encoder: E,
+        decoder: D,
+    ) -> Self
+    where
+        E: Fn(&QuickJsRealmAdapter, &QuickJsValueAdapter) -> Result<JsValueFacade, JsError>
+            + Send
+            + Sync
+            + 'static,
+        D: Fn(&QuickJsRealmAdapter, &str, &[u8]) -> Result<Option<QuickJsValueAdapter>, JsError>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.value_codecs.push(ValueCodec {
+            matcher,
+            encoder: Arc::new(encoder),
+            decoder: Arc::new(decoder),
+        });
+        self
+    }
+
+    /// keep running an eval whose future was dropped and convert its result as if it was still awaited
+    ///
+    /// by default a job of [eval](crate::facades::QuickJsRuntimeFacade::eval), [eval_module](crate::facades::QuickJsRuntimeFacade::eval_module)
+    /// or [eval_template](crate::facades::QuickJsRuntimeFacade::eval_template) is skipped when its future was dropped before the job started,
+    /// when the future was dropped while the job ran the result is not converted and an error is passed to the
+    /// [uncaught_error_hook](Self::uncaught_error_hook), use this for fire-and-forget evals which drop the future right away
+    pub fn detach_dropped_futures(mut self) -> Self {
+        self.detach_dropped_futures = true;
+        self
+    }
+
+    /// drop a realm when the last [RealmHandle](crate::jsutils::realmhandle::RealmHandle) to it is dropped
+    ///
+    /// only realms for which a handle was created are dropped, a handle which is not kept (e.g. the result of
+    /// [create_realm](crate::facades::QuickJsRuntimeFacade::create_realm) when it is ignored) drops its realm right away
+    pub fn drop_realms_with_last_handle(mut self) -> Self {
+        self.drop_realms_with_last_handle = true;
+        self
+    }
+
+    /// allow [register_sync_bridge](crate::facades::QuickJsRuntimeFacade::register_sync_bridge)
+    ///
+    /// **a call of a sync bridge blocks the EventLoop** until its future is done, no other job, timer or promise reaction runs
+    /// while it waits, see [syncbridge](crate::jsutils::syncbridge)
+    pub fn allow_sync_bridge(mut self) -> Self {
+        self.allow_sync_bridge = true;
+        self
+    }
+
+    /// cache the bytecode of script modules and startup scripts in a directory, the cache is shared between runs (and runtimes)
+    /// of the same version, see [bytecodecache](crate::jsutils::bytecodecache)
+    pub fn bytecode_cache_dir<P: AsRef<Path>>(mut self, dir: P, policy: CachePolicy) -> Self {
+        let cache = (dir.as_ref().to_path_buf(), policy);
+        self.conflicts.extend(conflict(
+            "bytecode_cache_dir",
+            &self.opt_bytecode_cache,
+            &cache,
+        ));
+        self.opt_bytecode_cache = Some(cache);
+        self
+    }
+
     /// add a ScriptPreProcessor which will be called for all scripts which are evaluated and compiled
     pub fn script_pre_processor<S: ScriptPreProcessor + Send + 'static>(
         mut self,
@@ -157,38 +903,376 @@ impl QuickJsRuntimeBuilder {
         self
     }
 
+    /// set the executor which is used to run the producers of async promises (e.g. [create_resolving_promise](crate::quickjsrealmadapter::QuickJsRealmAdapter::create_resolving_promise))
+    /// when no executor is set the built-in helper thread pool is used
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+    /// use quickjs_runtime::jsutils::executor::TokioExecutor;
+    /// let tokio_rt = tokio::runtime::Runtime::new().unwrap();
+    /// let rt = QuickJsRuntimeBuilder::new()
+    ///     .executor(TokioExecutor::new(tokio_rt.handle().clone()))
+    ///     .build();
+    /// ```
+    pub fn executor<E: JsExecutor + 'static>(mut self, executor: E) -> Self {
+        self.conflicts
+            .extend(hook_conflict("executor", self.opt_executor.is_some()));
+        self.opt_executor = Some(Arc::new(executor));
+        self
+    }
+
+    /// enable the `quickjs:assert` module and [run_tests](crate::facades::QuickJsRuntimeFacade::run_tests)
+    /// see [testing](crate::features::testing)
+    pub fn testing(self) -> Self {
+        if self.single_modules.contains(&ASSERT_MODULE_NAME) {
+            return self;
+        }
+        self.single_module_loader(ASSERT_MODULE_NAME, AssertModuleLoader {}, false)
+            .script_module_loader(TestSuiteLoader {})
+    }
+
+    /// enable the `quickjs:encoding` module which provides native base64, hex and utf8 codecs
+    /// see [encoding](crate::features::encoding)
+    pub fn encoding_module(self) -> Self {
+        self.single_module_loader(encoding::MODULE_NAME, EncodingModuleLoader {}, false)
+    }
+
+    /// enable the `quickjs:buffer` module which provides native concat, compare and indexOf for binary data
+    /// see [buffer](crate::features::buffer)
+    pub fn buffer_module(self) -> Self {
+        self.single_module_loader(buffer::MODULE_NAME, BufferModuleLoader {}, false)
+    }
+
+    /// enable the `quickjs:decimal` module which provides a Decimal class for exact arithmetic
+    /// see [decimal](crate::features::decimal)
+    #[cfg(feature = "decimal")]
+    pub fn decimal_module(self) -> Self {
+        self.single_module_loader(
+            crate::features::decimal::MODULE_NAME,
+            crate::features::decimal::DecimalModuleLoader {},
+            false,
+        )
+    }
+
+    /// enable the `quickjs:store` module which provides a key/value store backed by a [KvStoreProvider]
+    /// see [kvstore](crate::features::kvstore)
+    pub fn kv_store<P: KvStoreProvider + 'static>(
+        self,
+        provider: P,
+        options: KvStoreOptions,
+    ) -> Self {
+        self.single_module_loader(
+            kvstore::MODULE_NAME,
+            KvStoreModuleLoader::new(provider, options),
+            true,
+        )
+    }
+
+    /// enable the `quickjs:log` module which passes structured log records of scripts to a handler
+    /// see [structuredlog](crate::features::structuredlog)
+    pub fn structured_log_handler<H>(self, options: StructuredLogOptions, handler: H) -> Self
+    where
+        H: Fn(LogRecord) + Send + Sync + 'static,
+    {
+        self.single_module_loader(
+            structuredlog::MODULE_NAME,
+            StructuredLogModuleLoader::new(options, handler),
+            true,
+        )
+    }
+
+    /// enable the `quickjs:realms` module which lets trusted scripts create, use and drop child realms within the caps of the options
+    /// see [realms](crate::features::realms)
+    pub fn realms_module(self, options: RealmsModuleOptions) -> Self {
+        self.single_module_loader(realms::MODULE_NAME, RealmsModuleLoader::new(options), true)
+    }
+
+    /// add a CommonJS style global `require()` to every realm which loads the code of modules with a [ScriptModuleLoader]
+    /// see [commonjs](crate::features::commonjs)
+    pub fn enable_commonjs<M: ScriptModuleLoader + Send + 'static>(self, loader: M) -> Self {
+        self.runtime_adapter_init_hook(move |rt| commonjs::init(rt, loader))
+    }
+
+    /// enable cooperative time slicing, long running scripts yield to the event loop with `yieldToHost()` when a slice of this duration is used up
+    /// scripts which start with the `'use cooperative';` directive get these yields added to their loops, see [timeslice](crate::features::timeslice)
+    pub fn time_slice(self, slice: Duration) -> Self {
+        self.script_pre_processor(CooperativePreProcessor::new())
+            .runtime_adapter_init_hook(move |rt| timeslice::init(rt, slice))
+    }
+
+    /// record where promises are created so the stacks of their rejections show where they were awaited, see [asyncstacks](crate::jsutils::asyncstacks)
+    ///
+    /// at most max_depth frames are recorded per promise or timer, recording costs a stack capture per promise so it is off by default
+    pub fn async_stack_traces(self, enabled: bool, max_depth: usize) -> Self {
+        self.runtime_adapter_init_hook(move |rt| asyncstacks::init(rt, enabled, max_depth))
+    }
+
+    /// add the frozen `__limits` global to every realm so scripts can read their memory and time limits and usage, see [limits](crate::features::limits)
+    pub fn limits_global(self) -> Self {
+        self.runtime_adapter_init_hook(limits::init)
+    }
+
+    /// keep the last `capacity` console records and unhandled errors of every realm for [debug_dump](crate::facades::QuickJsRuntimeFacade::debug_dump)
+    ///
+    /// console lines are formatted to be recorded even when their log level is disabled, so this is off by default
+    pub fn debug_records(self, capacity: usize) -> Self {
+        self.runtime_adapter_init_hook(move |rt| debugdump::init(rt, capacity))
+    }
+
+    /// record the imports which are resolved in every realm for [module_graph](crate::facades::QuickJsRuntimeFacade::module_graph)
+    ///
+    /// the recorded graph takes memory proportional to the number of imports, so this is off by default
+    pub fn module_graph(self) -> Self {
+        self.runtime_adapter_init_hook(modulegraph::init)
+    }
+
     /// set max memory the runtime may use
     pub fn memory_limit(mut self, bytes: u64) -> Self {
+        self.conflicts.extend(conflict(
+            "memory_limit",
+            &self.opt_memory_limit_bytes,
+            &bytes,
+        ));
         self.opt_memory_limit_bytes = Some(bytes);
         self
     }
 
+    /// call a handler in the worker thread when an allocation would exceed the [memory_limit](Self::memory_limit),
+    /// the handler may raise the limit so the allocation succeeds, see [memorypressure](crate::features::memorypressure)
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+    /// use quickjs_runtime::features::memorypressure::MemoryLimitAction;
+    /// let rt = QuickJsRuntimeBuilder::new()
+    ///     .memory_limit(16 * 1024 * 1024)
+    ///     .memory_limit_exceeded_handler(|exceeded| {
+    ///         log::error!("tenant exceeded its memory limit: {} bytes used", exceeded.used);
+    ///         MemoryLimitAction::Fail
+    ///     })
+    ///     .build();
+    /// ```
+    pub fn memory_limit_exceeded_handler<H>(mut self, handler: H) -> Self
+    where
+        H: Fn(&MemoryLimitExceeded) -> MemoryLimitAction + Send + Sync + 'static,
+    {
+        self.conflicts.extend(hook_conflict(
+            "memory_limit_exceeded_handler",
+            self.opt_memory_limit_exceeded_handler.is_some(),
+        ));
+        self.opt_memory_limit_exceeded_handler = Some(Arc::new(handler));
+        self
+    }
+
     /// number of allocations before gc is run
     pub fn gc_threshold(mut self, size: u64) -> Self {
+        self.conflicts
+            .extend(conflict("gc_threshold", &self.opt_gc_threshold, &size));
         self.opt_gc_threshold = Some(size);
         self
     }
 
     /// set a max stack size
     pub fn max_stack_size(mut self, size: u64) -> Self {
+        self.conflicts
+            .extend(conflict("max_stack_size", &self.opt_max_stack_size, &size));
         self.opt_max_stack_size = Some(size);
         self
     }
 
     /// set a Garbage Collection interval, this will start a timer thread which will trigger a full GC every set interval
     pub fn gc_interval(mut self, interval: Duration) -> Self {
+        self.conflicts
+            .extend(conflict("gc_interval", &self.opt_gc_interval, &interval));
         self.opt_gc_interval = Some(interval);
         self
     }
 
+    /// check the memory usage against the [memory_limit](Self::memory_limit) and notify a hook or scripts when it gets close, see [memorypressure](crate::features::memorypressure)
+    pub fn memory_pressure(self, options: MemoryPressureOptions) -> Self {
+        self.runtime_adapter_init_hook(move |rt| memorypressure::init(rt, options))
+    }
+
+    /// add a callback which runs in the worker thread when no jobs were added and no timers ran for min_idle, see [idle](crate::jsutils::idle)
+    pub fn idle_callback<C: Fn(&QuickJsRuntimeAdapter) + Send + 'static>(
+        mut self,
+        min_idle: Duration,
+        callback: C,
+    ) -> Self {
+        self.conflicts.extend(hook_conflict(
+            "idle_callback",
+            self.opt_idle_callback.is_some(),
+        ));
+        self.opt_idle_callback = Some((min_idle, Box::new(callback)));
+        self
+    }
+
+    /// set the time the idle callback may take, a warning is logged when it takes longer, defaults to [DEFAULT_IDLE_BUDGET](crate::jsutils::idle::DEFAULT_IDLE_BUDGET)
+    pub fn idle_callback_budget(mut self, budget: Duration) -> Self {
+        self.conflicts.extend(conflict(
+            "idle_callback_budget",
+            &self.opt_idle_callback_budget,
+            &budget,
+        ));
+        self.opt_idle_callback_budget = Some(budget);
+        self
+    }
+
+    /// interrupt a job of the worker thread (e.g. an eval, a timer or a promise reaction) which runs longer than the timeout
+    ///
+    /// the interrupted eval fails with an `InternalError: interrupted` error, the runtime can still be used afterwards
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+    /// use quickjs_runtime::jsutils::Script;
+    /// use std::time::Duration;
+    /// let rt = QuickJsRuntimeBuilder::new().script_timeout(Duration::from_millis(100)).build();
+    /// assert!(rt.eval_sync(None, Script::new("runaway.js", "while (true) {}")).is_err());
+    /// ```
+    pub fn script_timeout(mut self, timeout: Duration) -> Self {
+        self.conflicts.extend(conflict(
+            "script_timeout",
+            &self.opt_script_timeout,
+            &timeout,
+        ));
+        self.opt_script_timeout = Some(timeout);
+        self
+    }
+
+    /// interrupt the top-level code of a module which runs longer than the timeout, every module which is evaluated (including
+    /// the modules it imports and dynamically imported modules) gets the full timeout, independent of the timeout of the eval which imported it
+    ///
+    /// the timed out module is invalidated (see [invalidate_module](crate::quickjsrealmadapter::QuickJsRealmAdapter::invalidate_module))
+    /// so importing it again loads and evaluates it again
+    /// * an [eval_module](crate::quickjsrealmadapter::QuickJsRealmAdapter::eval_module) fails with a `TimeoutError` which names the module
+    /// * a dynamic import is rejected with `InternalError: interrupted`, the load error of the module in the [module graph](crate::jsutils::modulegraph) names it
+    /// # Limitations
+    /// the code after a top-level await runs as a job and is not timed, the timeout of the eval which imported the module does apply to it
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+    /// use quickjs_runtime::jsutils::Script;
+    /// use std::time::Duration;
+    /// let rt = QuickJsRuntimeBuilder::new().module_eval_timeout(Duration::from_millis(100)).build();
+    /// let err = rt.eval_module_sync(None, Script::new("runaway.mes", "while (true) {}")).expect_err("module was not interrupted");
+    /// assert_eq!(err.get_name(), "TimeoutError");
+    /// assert!(err.get_message().contains("runaway.mes"));
+    /// ```
+    pub fn module_eval_timeout(mut self, timeout: Duration) -> Self {
+        self.conflicts.extend(conflict(
+            "module_eval_timeout",
+            &self.opt_module_eval_timeout,
+            &timeout,
+        ));
+        self.opt_module_eval_timeout = Some(timeout);
+        self
+    }
+
+    /// set how deep values may be nested when they are converted between rust and script, e.g. a [JsValueFacade](crate::values::JsValueFacade)
+    /// which is passed to a function, defaults to [DEFAULT_MAX_CONVERSION_DEPTH](crate::quickjs_utils::conversion::DEFAULT_MAX_CONVERSION_DEPTH)
+    ///
+    /// a deeper value fails the conversion with a [DepthExceeded](crate::jsutils::JsError::is_depth_exceeded) error
+    pub fn max_conversion_depth(mut self, max_depth: usize) -> Self {
+        self.conflicts.extend(conflict(
+            "max_conversion_depth",
+            &self.opt_max_conversion_depth,
+            &max_depth,
+        ));
+        self.opt_max_conversion_depth = Some(max_depth);
+        self
+    }
+
+    /// set how deep evals may be nested, e.g. when a native function which is called by a script evals another script,
+    /// defaults to [DEFAULT_MAX_EXECUTION_DEPTH](crate::quickjs_utils::interrupthandler::DEFAULT_MAX_EXECUTION_DEPTH)
+    ///
+    /// an eval which would be nested deeper fails with a `RangeError`, see [execution_depth](crate::quickjsruntimeadapter::QuickJsRuntimeAdapter::execution_depth)
+    pub fn max_execution_depth(mut self, max_depth: usize) -> Self {
+        self.conflicts.extend(conflict(
+            "max_execution_depth",
+            &self.opt_max_execution_depth,
+            &max_depth,
+        ));
+        self.opt_max_execution_depth = Some(max_depth);
+        self
+    }
+
+    /// set the max number of jobs which may be pending in the event queue, the queue is unbounded by default
+    ///
+    /// adds wait or block while the queue is full, adds from the worker thread itself never wait, see [jobqueue](crate::jsutils::jobqueue)
+    pub fn max_queue_size(mut self, max_size: usize) -> Self {
+        self.conflicts.extend(conflict(
+            "max_queue_size",
+            &self.opt_max_queue_size,
+            &max_size,
+        ));
+        self.opt_max_queue_size = Some(max_size);
+        self
+    }
+
+    /// limit the number of promise jobs and the time which the worker spends running them after a task,
+    /// so a chain of promise jobs can not starve the timers and the other tasks, see [microtasks](crate::jsutils::microtasks)
+    pub fn microtask_budget(mut self, budget: MicrotaskBudget) -> Self {
+        self.conflicts.extend(hook_conflict(
+            "microtask_budget",
+            self.opt_microtask_budget.is_some(),
+        ));
+        self.opt_microtask_budget = Some(budget);
+        self
+    }
+
     /// add an interrupt handler, this will be called several times during script execution and may be used to cancel a running script
     pub fn set_interrupt_handler<I: Fn(&QuickJsRuntimeAdapter) -> bool + Send + 'static>(
         mut self,
         interrupt_handler: I,
     ) -> Self {
+        self.conflicts.extend(hook_conflict(
+            "set_interrupt_handler",
+            self.interrupt_handler.is_some(),
+        ));
         self.interrupt_handler = Some(Box::new(interrupt_handler));
         self
     }
+
+    /// install a bundle of global features in every realm instead of the default set (console, setTimeout, setInterval, setImmediate and structuredClone)
+    /// the names of the installed features are available in script as `__runtime.features`
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::builder::{QuickJsRuntimeBuilder, WebDefaults};
+    /// use quickjs_runtime::jsutils::Script;
+    /// let rt = QuickJsRuntimeBuilder::new()
+    ///     .web_platform_defaults(WebDefaults::Minimal)
+    ///     .disable_feature("setInterval")
+    ///     .build();
+    /// let res = rt.eval_sync(None, Script::new("features.js", "__runtime.features.join(',');")).ok().unwrap();
+    /// assert_eq!(res.get_str(), "console,setTimeout,queueMicrotask");
+    /// ```
+    pub fn web_platform_defaults(mut self, web_defaults: WebDefaults) -> Self {
+        self.conflicts.extend(conflict(
+            "web_platform_defaults",
+            &self.opt_web_defaults,
+            &web_defaults,
+        ));
+        self.opt_web_defaults = Some(web_defaults);
+        self
+    }
+
+    /// do not install a feature (e.g. "setImmediate") which would otherwise be installed by default or by the selected [WebDefaults] bundle
+    pub fn disable_feature(mut self, name: &str) -> Self {
+        self.disabled_features.push(name.to_string());
+        self
+    }
+
+    /// get the names of the features which will be installed in every realm
+    pub(crate) fn get_feature_names(&self) -> Vec<String> {
+        let names = match &self.opt_web_defaults {
+            None => crate::features::DEFAULT_FEATURES.to_vec(),
+            Some(web_defaults) => web_defaults.get_feature_names(),
+        };
+        names
+            .into_iter()
+            .filter(|name| !self.disabled_features.iter().any(|d| d.eq(name)))
+            .map(|name| name.to_string())
+            .collect()
+    }
 }
 
 impl Default for QuickJsRuntimeBuilder {
@@ -208,6 +1292,9 @@ impl QuickJsRuntimeBuilder {
         self
     }
 
+    /// add a hook which is called in the worker thread for the main realm and for every realm which is created later
+    ///
+    /// when the hook fails for a new realm the creation of that realm fails and the realm is removed
     pub fn realm_adapter_init_hook<
         H: Fn(&QuickJsRuntimeAdapter, &QuickJsRealmAdapter) -> Result<(), JsError> + Send + 'static,
     >(
@@ -220,6 +1307,21 @@ impl QuickJsRuntimeBuilder {
         })
     }
 
+    /// add a hook which is called in the worker thread before a realm is removed, including the realms which are removed when the runtime is dropped
+    ///
+    /// an error of the hook is logged, the realm is removed anyway
+    pub fn realm_adapter_destroy_hook<
+        H: Fn(&QuickJsRuntimeAdapter, &QuickJsRealmAdapter) -> Result<(), JsError> + Send + 'static,
+    >(
+        self,
+        hook: H,
+    ) -> Self {
+        self.runtime_adapter_init_hook(move |rt| {
+            rt.add_context_destroy_hook(hook);
+            Ok(())
+        })
+    }
+
     pub fn runtime_adapter_init_hook<
         H: FnOnce(&QuickJsRuntimeAdapter) -> Result<(), JsError> + Send + 'static,
     >(
@@ -245,10 +1347,14 @@ impl QuickJsRuntimeBuilder {
 
 #[cfg(test)]
 pub mod tests {
-    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::builder::{QuickJsRuntimeBuilder, WebDefaults};
+    use crate::jsutils::debugdump::DumpOptions;
     use crate::jsutils::modules::ScriptModuleLoader;
-    use crate::jsutils::Script;
+    use crate::jsutils::{JsError, Script};
     use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+    use futures::executor::block_on;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
 
     #[test]
     fn test_module_loader() {
@@ -284,4 +1390,144 @@ pub mod tests {
             Err(e) => panic!("script failed {}", e),
         }
     }
+
+    #[test]
+    fn test_web_platform_defaults() {
+        let rt = QuickJsRuntimeBuilder::new()
+            .web_platform_defaults(WebDefaults::Standard)
+            .disable_feature("setImmediate")
+            .realm_adapter_init_hook(|_rt, realm| {
+                // features may still be overridden per realm
+                realm.eval(Script::new("override.js", "globalThis.setTimeout = 123;"))?;
+                Ok(())
+            })
+            .build();
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "test_web_platform_defaults.js",
+                    "`${__runtime.features.join(',')}:${typeof setImmediate}:${setTimeout}`",
+                ),
+            )
+            .expect("script failed");
+        assert_eq!(
+            res.get_str(),
+            "console,setTimeout,setInterval,queueMicrotask,encoding,structuredClone,crypto:undefined:123"
+        );
+
+        rt.create_realm("other").expect("could not create realm");
+        let res = rt
+            .eval_sync(
+                Some("other"),
+                Script::new("test_web_platform_defaults.js", "__runtime.features.length"),
+            )
+            .expect("script failed");
+        assert_eq!(res.get_i32(), 7);
+    }
+
+    #[test]
+    fn test_realm_hooks() {
+        let destroyed = Arc::new(Mutex::new(vec![]));
+        let hook_destroyed = destroyed.clone();
+        let rt = QuickJsRuntimeBuilder::new()
+            .realm_adapter_init_hook(|_rt, realm| {
+                if realm.get_realm_id().starts_with("broken") {
+                    return Err(JsError::new_str("broken realm"));
+                }
+                realm.eval(Script::new(
+                    "tenant.js",
+                    "globalThis.tenant = {count: 0, inc() {return ++this.count;}};",
+                ))?;
+                Ok(())
+            })
+            .realm_adapter_destroy_hook(move |_rt, realm| {
+                // the realm can still be used in the hook
+                let count = realm.eval(Script::new("count.js", "tenant.count"))?;
+                hook_destroyed.lock().unwrap().push(format!(
+                    "{}:{}",
+                    realm.get_realm_id(),
+                    count.to_i32()
+                ));
+                Ok(())
+            })
+            .build();
+
+        rt.create_realm("tenant_a").expect("could not create realm");
+        rt.create_realm("tenant_b").expect("could not create realm");
+        for _ in 0..3 {
+            rt.eval_sync(Some("tenant_a"), Script::new("inc.js", "tenant.inc()"))
+                .expect("script failed");
+        }
+        let res = rt
+            .eval_sync(Some("tenant_b"), Script::new("inc.js", "tenant.inc()"))
+            .expect("script failed");
+        assert_eq!(res.get_i32(), 1);
+        let res = rt
+            .eval_sync(None, Script::new("main.js", "typeof tenant.inc"))
+            .expect("script failed");
+        assert_eq!(res.get_str(), "function");
+
+        rt.create_realm("broken_realm")
+            .expect_err("realm with a failing hook was created");
+        assert!(!rt.has_realm("broken_realm").expect("has_realm failed"));
+
+        rt.destroy_realm("tenant_a").expect("destroy failed");
+        assert_eq!(*destroyed.lock().unwrap(), vec!["tenant_a:3".to_string()]);
+        drop(rt);
+        let mut destroyed = destroyed.lock().unwrap().clone();
+        destroyed.sort();
+        assert_eq!(
+            destroyed,
+            vec![
+                "__main__:0".to_string(),
+                "tenant_a:3".to_string(),
+                "tenant_b:1".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_builder_conflicts() {
+        let builder = QuickJsRuntimeBuilder::new()
+            .memory_limit(1024 * 1024 * 16)
+            .gc_interval(Duration::from_secs(10))
+            // setting the same value again is not a conflict
+            .gc_interval(Duration::from_secs(10))
+            .memory_limit(1024 * 1024 * 32)
+            .encoding_module()
+            .encoding_module();
+        assert_eq!(builder.get_memory_limit(), Some(1024 * 1024 * 32));
+        assert_eq!(builder.get_conflicts().len(), 1);
+        assert_eq!(
+            builder.get_conflicts()[0].to_string(),
+            "memory_limit was set to 16777216 and then to 33554432"
+        );
+        let summary = builder.summary();
+        assert_eq!(summary.modules, vec!["quickjs:encoding".to_string()]);
+        assert_eq!(summary.native_module_loaders, 1);
+        assert!(summary.to_string().contains("gc_interval: 10s"));
+
+        // last write wins with a warning
+        let rt = builder.debug_records(1).try_build().expect("build failed");
+        let bundle =
+            block_on(rt.debug_dump("__main__", DumpOptions::default())).expect("dump failed");
+        let dumped = bundle.builder.expect("no summary");
+        assert_eq!(dumped.memory_limit, Some(1024 * 1024 * 32));
+        assert_eq!(dumped.conflicts.len(), 1);
+
+        let err = QuickJsRuntimeBuilder::new()
+            .strict(true)
+            .web_platform_defaults(WebDefaults::Minimal)
+            .web_platform_defaults(WebDefaults::Standard)
+            .redaction_hook(|s| s.into())
+            .redaction_hook(|s| s.into())
+            .try_build()
+            .err()
+            .expect("build should fail");
+        assert_eq!(
+            err.get_message(),
+            "conflicting builder options: web_platform_defaults was set to Minimal and then to Standard; redaction_hook was set to a hook and then to another hook"
+        );
+    }
 }
@@ -1,23 +1,64 @@
 //! contains the QuickJsRuntimeFacade
 
 use crate::builder::QuickJsRuntimeBuilder;
-use crate::jsutils::{JsError, Script};
-use crate::quickjs_utils::{functions, objects};
-use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::features::coverage::{self, CoverageReport};
+use crate::features::random::{self, RandomState};
+use crate::features::testing::{self, TestOptions, TestReport};
+use crate::jsutils::binding::{self, BoundObjectHandle};
+use crate::jsutils::bytecodecache::{BytecodeCache, BytecodeCacheStats};
+use crate::jsutils::capabilities::{self, CapabilityHandle};
+use crate::jsutils::compileaudit::{self, CompileAudit, CompileEvent};
+use crate::jsutils::debugdump::{self, DebugBundle, DumpOptions};
+use crate::jsutils::executor::{HelperTaskExecutor, JsExecutor};
+use crate::jsutils::idle::{self, IdleTracker};
+use crate::jsutils::isolation::{self, IsolationOptions};
+use crate::jsutils::jobcontext::{JobContext, JobContextGuard};
+use crate::jsutils::jobqueue::{JobQueue, JobSlot};
+use crate::jsutils::memoize::{self, invalidate_memo_q, memoize_function_q, MemoConfig};
+use crate::jsutils::metrics::{self, MetricsSnapshot};
+use crate::jsutils::microtasks::{MicrotaskBudgetMode, MicrotaskLimiter};
+use crate::jsutils::modulegraph::{self, ModuleGraph};
+use crate::jsutils::preload::{self, PreloadReport};
+use crate::jsutils::realmhandle::{self, RealmHandle};
+use crate::jsutils::redaction;
+use crate::jsutils::remoteref::ReleaseQueue;
+use crate::jsutils::startup;
+use crate::jsutils::suspend::{self, SuspendedRealm};
+use crate::jsutils::syncbridge::{self, SyncBridgeStats};
+use crate::jsutils::taskscope::{self, EvalOptions};
+use crate::jsutils::timers::{self, TimerInfo};
+use crate::jsutils::transaction::{self, TransactionOptions};
+use crate::jsutils::uncaught;
+use crate::jsutils::validation::{self, SyntaxErrorInfo};
+use crate::jsutils::{JsError, Script, ScriptTemplate};
+use crate::quickjs_utils::allocator::{self, Allocator};
+use crate::quickjs_utils::columns::{ColumnType, Columns, TypeMismatchPolicy};
+use crate::quickjs_utils::interrupthandler::{self, JobTimer};
+use crate::quickjs_utils::watch::WatchOptions;
+use crate::quickjs_utils::{arrays, codecs, conversion, functions, objects};
+use crate::quickjsrealmadapter::{QuickJsRealmAdapter, RealmOptions};
 use crate::quickjsruntimeadapter::{
     CompiledModuleLoaderAdapter, MemoryUsage, NativeModuleLoaderAdapter, QuickJsRuntimeAdapter,
     ScriptModuleLoaderAdapter, QJS_RT,
 };
 use crate::quickjsvalueadapter::QuickJsValueAdapter;
 use crate::reflection;
-use crate::values::JsValueFacade;
+use crate::reflection::eventtarget;
+use crate::values::{CachedJsArrayRef, CachedJsObjectRef, JsValueFacade, JsWatch};
 use hirofa_utils::eventloop::EventLoop;
 use hirofa_utils::task_manager::TaskManager;
 use libquickjs_sys as q;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
 use std::future::Future;
 use std::pin::Pin;
 use std::rc::Rc;
-use std::sync::{Arc, Weak};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock, Weak};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
 use tokio::task::JoinError;
 
 lazy_static! {
@@ -28,6 +69,9 @@ lazy_static! {
 impl Drop for QuickJsRuntimeFacade {
     fn drop(&mut self) {
         log::trace!("> EsRuntime::drop");
+        if let Some(tracker) = &self.inner.idle_tracker {
+            tracker.stop();
+        }
         self.clear_contexts();
         log::trace!("< EsRuntime::drop");
     }
@@ -35,9 +79,25 @@ impl Drop for QuickJsRuntimeFacade {
 
 pub struct QuickjsRuntimeFacadeInner {
     event_loop: EventLoop,
+    executor: Arc<dyn JsExecutor>,
+    // isolated evals run one at a time, the semaphore queues them fairly
+    pub(crate) isolation_queue: Semaphore,
+    // counts the jobs for the idle callback, see QuickJsRuntimeBuilder::idle_callback
+    pub(crate) idle_tracker: Option<Arc<IdleTracker>>,
+    // the values which were released by dropped facades, see remoteref
+    pub(crate) released_refs: ReleaseQueue,
+    // counts and bounds the jobs, see jobqueue
+    job_queue: Arc<JobQueue>,
+    // a job which waits for space in the job queue is added by the future, which keeps the runtime alive with this
+    weak_self: Weak<QuickjsRuntimeFacadeInner>,
 }
 
 impl QuickjsRuntimeFacadeInner {
+    /// get the executor which runs the async parts of this runtime, see [QuickJsRuntimeBuilder::executor]
+    pub fn get_executor(&self) -> &Arc<dyn JsExecutor> {
+        &self.executor
+    }
+
     /// this is how you add a closure to the worker thread which has an instance of the QuickJsRuntime
     /// this will run and return synchronously
     /// # example
@@ -96,8 +156,31 @@ impl QuickjsRuntimeFacadeInner {
     where
         C: FnOnce() + Send + 'static,
     {
+        let slot = JobQueue::reserve_sync(&self.job_queue);
+        self.add_void_job(slot, task);
+    }
+
+    /// add a job of the runtime itself which never waits for space in the queue, e.g. releasing the values of dropped facades,
+    /// which may happen on any thread including the threads of an async executor
+    pub(crate) fn add_internal_rt_task_to_event_loop_void<C>(&self, consumer: C)
+    where
+        C: FnOnce(&QuickJsRuntimeAdapter) + Send + 'static,
+    {
+        let slot = JobQueue::reserve_unbounded(&self.job_queue);
+        self.add_void_job(slot, || QuickJsRuntimeAdapter::do_with(consumer));
+    }
+
+    fn add_void_job<C>(&self, slot: JobSlot, task: C)
+    where
+        C: FnOnce() + Send + 'static,
+    {
+        let idle_tracker = self.job_added();
         self.event_loop.add_void(move || {
+            let job_timer = JobTimer::start();
             task();
+            drop(job_timer);
+            job_done(idle_tracker);
+            drop(slot);
             EventLoop::add_local_void(|| {
                 QuickJsRuntimeAdapter::do_with(|q_js_rt| {
                     q_js_rt.run_pending_jobs_if_any();
@@ -110,8 +193,14 @@ impl QuickjsRuntimeFacadeInner {
     where
         C: FnOnce() -> R + Send + 'static,
     {
+        let slot = JobQueue::reserve_sync(&self.job_queue);
+        let idle_tracker = self.job_added();
         self.event_loop.exe(move || {
+            let job_timer = JobTimer::start();
             let res = task();
+            drop(job_timer);
+            job_done(idle_tracker);
+            drop(slot);
             EventLoop::add_local_void(|| {
                 QuickJsRuntimeAdapter::do_with(|q_js_rt| {
                     q_js_rt.run_pending_jobs_if_any();
@@ -121,12 +210,54 @@ impl QuickjsRuntimeFacadeInner {
         })
     }
 
+    /// add a task to the event loop, when the queue is full the task is added when there is space, see [jobqueue](crate::jsutils::jobqueue)
     pub fn add_task_to_event_loop<C, R: Send + 'static>(&self, task: C) -> impl Future<Output = R>
     where
         C: FnOnce() -> R + Send + 'static,
     {
+        // the task is added right away when there is space so it is not delayed until the future is polled
+        let mut task = Some(task);
+        let added = match JobQueue::try_reserve(&self.job_queue) {
+            Ok(slot) => Some(self.add_job(slot, task.take().expect("invalid state"))),
+            Err(_) => None,
+        };
+        let inner = self.weak_self.upgrade().expect("invalid state");
+        async move {
+            match added {
+                Some(added) => added.await,
+                None => {
+                    let slot = JobQueue::reserve(inner.job_queue.clone()).await;
+                    inner
+                        .add_job(slot, task.take().expect("invalid state"))
+                        .await
+                }
+            }
+        }
+    }
+
+    /// add a task to the event loop or fail with a `QueueFull` error when the queue is full
+    pub fn try_add_task_to_event_loop<C, R: Send + 'static>(
+        &self,
+        task: C,
+    ) -> Result<impl Future<Output = R>, JsError>
+    where
+        C: FnOnce() -> R + Send + 'static,
+    {
+        let slot = JobQueue::try_reserve(&self.job_queue)?;
+        Ok(self.add_job(slot, task))
+    }
+
+    fn add_job<C, R: Send + 'static>(&self, slot: JobSlot, task: C) -> impl Future<Output = R>
+    where
+        C: FnOnce() -> R + Send + 'static,
+    {
+        let idle_tracker = self.job_added();
         self.event_loop.add(move || {
+            let job_timer = JobTimer::start();
             let res = task();
+            drop(job_timer);
+            job_done(idle_tracker);
+            drop(slot);
             EventLoop::add_local_void(|| {
                 QuickJsRuntimeAdapter::do_with(|q_js_rt| {
                     q_js_rt.run_pending_jobs_if_any();
@@ -136,6 +267,19 @@ impl QuickjsRuntimeFacadeInner {
         })
     }
 
+    /// the number of jobs which were added and are not done yet
+    pub fn pending_jobs(&self) -> usize {
+        self.job_queue.pending()
+    }
+
+    fn job_added(&self) -> Option<Arc<IdleTracker>> {
+        let tracker = self.idle_tracker.clone();
+        if let Some(tracker) = &tracker {
+            tracker.job_added();
+        }
+        tracker
+    }
+
     /// used to add tasks from the worker threads which require run_pending_jobs_if_any to run after it
     #[allow(dead_code)]
     pub(crate) fn add_local_task_to_event_loop<C>(consumer: C)
@@ -155,6 +299,12 @@ impl QuickjsRuntimeFacadeInner {
     }
 }
 
+fn job_done(idle_tracker: Option<Arc<IdleTracker>>) {
+    if let Some(tracker) = idle_tracker {
+        tracker.job_done(Instant::now());
+    }
+}
+
 /// EsRuntime is the main public struct representing a JavaScript runtime.
 /// You can construct a new QuickJsRuntime by using the [QuickJsRuntimeBuilder] struct
 /// # Example
@@ -166,17 +316,88 @@ pub struct QuickJsRuntimeFacade {
     inner: Arc<QuickjsRuntimeFacadeInner>,
 }
 
+/// options of [invoke_function_with_opts](QuickJsRuntimeFacade::invoke_function_with_opts)
+#[derive(Default)]
+pub struct CallOptions {
+    /// the receiver of the call, the function is called without a receiver when None
+    pub this: Option<CachedJsObjectRef>,
+    /// pass the elements of the last argument, which must be an array, as separate arguments like Function.prototype.apply does
+    pub spread_last_array: bool,
+}
+
+// the result of an eval whose future was dropped, nobody receives it
+fn dropped_future_error() -> JsError {
+    JsError::new_str("the future of the eval was dropped")
+}
+
+// a future which tells its job that it was dropped before it completed
+struct LinkedFuture<R> {
+    future: Pin<Box<dyn Future<Output = R>>>,
+    dropped: Arc<AtomicBool>,
+    done: bool,
+}
+
+impl<R> Future for LinkedFuture<R> {
+    type Output = R;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let res = this.future.as_mut().poll(cx);
+        if res.is_ready() {
+            this.done = true;
+        }
+        res
+    }
+}
+
+impl<R> Drop for LinkedFuture<R> {
+    fn drop(&mut self) {
+        if !self.done {
+            self.dropped.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
 impl QuickJsRuntimeFacade {
-    pub(crate) fn new(mut builder: QuickJsRuntimeBuilder) -> Self {
+    #[allow(dead_code)]
+    pub(crate) fn get_inner(&self) -> &Arc<QuickjsRuntimeFacadeInner> {
+        &self.inner
+    }
+
+    pub(crate) fn new(mut builder: QuickJsRuntimeBuilder) -> Result<Self, JsError> {
+        let summary = builder.summary();
+        let idle_callback = builder.opt_idle_callback.take();
+        let idle_tracker = idle_callback.as_ref().map(|(min_idle, _)| {
+            Arc::new(IdleTracker::new(
+                *min_idle,
+                builder
+                    .opt_idle_callback_budget
+                    .unwrap_or(idle::DEFAULT_IDLE_BUDGET),
+                Instant::now(),
+            ))
+        });
         let ret = Self {
-            inner: Arc::new(QuickjsRuntimeFacadeInner {
+            inner: Arc::new_cyclic(|weak_self| QuickjsRuntimeFacadeInner {
                 event_loop: EventLoop::new(),
+                executor: builder
+                    .opt_executor
+                    .take()
+                    .unwrap_or_else(|| Arc::new(HelperTaskExecutor {})),
+                isolation_queue: Semaphore::new(1),
+                idle_tracker: idle_tracker.clone(),
+                released_refs: ReleaseQueue::default(),
+                job_queue: Arc::new(JobQueue::new(builder.opt_max_queue_size)),
+                weak_self: weak_self.clone(),
             }),
         };
+        // the runtime does not exist yet so no pending jobs are run after this task
+        let worker = ret.inner.event_loop.exe(|| std::thread::current().id());
+        ret.inner.job_queue.set_worker(worker);
 
         ret.exe_task_in_event_loop(|| {
-            let rt_ptr = unsafe { q::JS_NewRuntime() };
-            let rt = QuickJsRuntimeAdapter::new(rt_ptr);
+            let allocator = Allocator::new();
+            let rt_ptr = unsafe { allocator::new_runtime(&allocator) };
+            let rt = QuickJsRuntimeAdapter::new(rt_ptr, allocator);
             QuickJsRuntimeAdapter::init_rt_for_current_thread(rt);
             functions::init_statics();
             reflection::init_statics();
@@ -194,17 +415,14 @@ impl QuickJsRuntimeFacade {
 
         // run single job in eventQueue to init thread_local weak<rtref>
 
-        #[cfg(any(
-            feature = "settimeout",
-            feature = "setinterval",
-            feature = "console",
-            feature = "setimmediate"
-        ))]
-        {
-            let res = crate::features::init(&ret);
-            if res.is_err() {
-                panic!("could not init features: {}", res.err().unwrap());
-            }
+        // features which were not compiled into this build are skipped
+        let res = crate::features::init_features(
+            &ret,
+            builder.get_feature_names(),
+            builder.opt_web_defaults.is_some(),
+        );
+        if res.is_err() {
+            panic!("could not init features: {}", res.err().unwrap());
         }
 
         if let Some(interval) = builder.opt_gc_interval {
@@ -227,7 +445,46 @@ impl QuickJsRuntimeFacade {
             });
         }
 
+        if let Some(tracker) = idle_tracker.clone() {
+            let rti_ref: Weak<QuickjsRuntimeFacadeInner> = Arc::downgrade(&ret.inner);
+            std::thread::spawn(move || loop {
+                std::thread::sleep(tracker.poll_interval());
+                if tracker.is_stopped() {
+                    break;
+                }
+                if let Some(el) = rti_ref.upgrade() {
+                    if tracker.should_queue(Instant::now()) {
+                        let tracker = tracker.clone();
+                        el.event_loop.add_void(move || {
+                            QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+                                idle::run_idle_callback_q(q_js_rt, &tracker);
+                            })
+                        });
+                    }
+                } else {
+                    break;
+                }
+            });
+        }
+
         let init_hooks: Vec<_> = builder.runtime_init_hooks.drain(..).collect();
+        let startup_scripts = std::mem::take(&mut builder.startup_scripts);
+        let startup_failure_policy = builder.startup_failure_policy;
+        let compile_audit = builder.opt_compile_audit.take();
+        let instrument_script_eval = compile_audit
+            .as_ref()
+            .map(|(options, _)| options.script_eval)
+            .unwrap_or(false);
+        let bytecode_cache = builder
+            .opt_bytecode_cache
+            .take()
+            .map(|(dir, policy)| BytecodeCache::open(dir, policy, ret.inner.executor.clone()));
+        if let Some(cache) = bytecode_cache.as_ref() {
+            if !startup_scripts.is_empty() {
+                // the startup scripts run right away, this thread waits for the disk instead of the worker thread
+                cache.wait_until_loaded(Duration::from_secs(5));
+            }
+        }
 
         ret.exe_task_in_event_loop(move || {
             QuickJsRuntimeAdapter::do_with_mut(|q_js_rt| {
@@ -246,13 +503,54 @@ impl QuickJsRuntimeFacade {
                         compiled_module_loader,
                     ));
                 }
+                q_js_rt.builder_summary = Some(summary);
                 q_js_rt.script_pre_processors = builder.script_pre_processors;
-
-                if let Some(limit) = builder.opt_memory_limit_bytes {
-                    unsafe {
-                        q::JS_SetMemoryLimit(q_js_rt.runtime, limit as _);
-                    }
+                q_js_rt.module_load_retry = builder.opt_module_load_retry;
+                q_js_rt.module_resolver = builder.opt_module_resolver;
+                q_js_rt.module_cache = builder.opt_module_cache.unwrap_or(true);
+                q_js_rt.script_timeout = builder.opt_script_timeout;
+                q_js_rt.module_eval_timeout = builder.opt_module_eval_timeout;
+                if let Some(max_depth) = builder.opt_max_conversion_depth {
+                    q_js_rt.max_conversion_depth = max_depth;
+                }
+                if let Some(max_depth) = builder.opt_max_execution_depth {
+                    q_js_rt.max_execution_depth = max_depth;
+                }
+                let strict_microtasks = builder
+                    .opt_microtask_budget
+                    .as_ref()
+                    .map(|budget| budget.get_mode() == MicrotaskBudgetMode::Strict)
+                    .unwrap_or(false);
+                q_js_rt.microtask_limiter = builder.opt_microtask_budget.map(MicrotaskLimiter::new);
+                if builder.opt_script_timeout.is_some()
+                    || builder.opt_module_eval_timeout.is_some()
+                    || strict_microtasks
+                {
+                    interrupthandler::init(q_js_rt);
+                }
+                q_js_rt.default_eval_options = builder.opt_default_eval_options.unwrap_or_default();
+                redaction::set_redaction_hook(builder.opt_redaction_hook);
+                codecs::set_value_codecs(builder.value_codecs);
+                q_js_rt.uncaught_error_hook = builder.opt_uncaught_error_hook;
+                q_js_rt.compile_audit =
+                    compile_audit.map(|(options, hook)| CompileAudit::new(options, hook));
+                #[cfg(feature = "console")]
+                {
+                    q_js_rt.console_printer = builder.opt_console_printer;
                 }
+                q_js_rt.detach_dropped_futures = builder.detach_dropped_futures;
+                q_js_rt.drop_realms_with_last_handle = builder.drop_realms_with_last_handle;
+                q_js_rt.allow_sync_bridge = builder.allow_sync_bridge;
+                q_js_rt.bytecode_cache = bytecode_cache;
+                q_js_rt.idle_callback = idle_callback.map(|(_, callback)| callback);
+                q_js_rt.idle_tracker = idle_tracker;
+
+                q_js_rt
+                    .allocator
+                    .set_memory_limit(builder.opt_memory_limit_bytes);
+                q_js_rt
+                    .allocator
+                    .set_handler(builder.opt_memory_limit_exceeded_handler);
                 if let Some(threshold) = builder.opt_gc_threshold {
                     unsafe {
                         q::JS_SetGCThreshold(q_js_rt.runtime, threshold as _);
@@ -269,6 +567,13 @@ impl QuickJsRuntimeFacade {
             })
         });
 
+        if instrument_script_eval {
+            // eval and the Function constructors are replaced before any other hook or script runs
+            ret.exe_rt_task_in_event_loop(compileaudit::init)?;
+        }
+        // the Proxy constructor is replaced before any other hook or script runs so memoize recognizes every Proxy
+        ret.exe_rt_task_in_event_loop(memoize::init)?;
+
         for hook in init_hooks {
             match hook(&ret) {
                 Ok(_) => {}
@@ -278,10 +583,26 @@ impl QuickJsRuntimeFacade {
             }
         }
 
-        ret
+        if !startup_scripts.is_empty() {
+            ret.exe_rt_task_in_event_loop(move |q_js_rt| {
+                startup::run_startup_scripts(q_js_rt, startup_scripts, startup_failure_policy)
+            })?;
+        }
+
+        Ok(ret)
     }
 
-    /// get memory usage for this runtime
+    /// get memory usage for this runtime, computed in the worker thread
+    ///
+    /// the sizes include garbage which was not yet collected, run [gc](Self::gc) first for the live heap
+    /// # Example
+    /// ```rust
+    /// use futures::executor::block_on;
+    /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+    /// let rt = QuickJsRuntimeBuilder::new().memory_limit(64 * 1024 * 1024).build();
+    /// let usage = block_on(rt.memory_usage());
+    /// assert!(usage.malloc_size < usage.malloc_limit);
+    /// ```
     pub async fn memory_usage(&self) -> MemoryUsage {
         self.loop_async(|rt| rt.memory_usage()).await
     }
@@ -291,7 +612,10 @@ impl QuickJsRuntimeFacade {
         self.exe_task_in_event_loop(|| {
             let context_ids = QuickJsRuntimeAdapter::get_context_ids();
             for id in context_ids {
-                QuickJsRuntimeAdapter::remove_context(id.as_str());
+                // child realms were already removed with their parent, see realms
+                if QuickJsRuntimeAdapter::do_with(|rt| rt.has_context(id.as_str())) {
+                    QuickJsRuntimeAdapter::remove_context(id.as_str());
+                }
             }
         });
     }
@@ -312,6 +636,8 @@ impl QuickJsRuntimeFacade {
         self.inner.exe_task_in_event_loop(task)
     }
 
+    /// add a task to the event loop, when a [max_queue_size](QuickJsRuntimeBuilder::max_queue_size) is set and the queue is full
+    /// the future waits for space before the task is added, see [jobqueue](crate::jsutils::jobqueue)
     pub fn add_task_to_event_loop<C, R: Send + 'static>(&self, task: C) -> impl Future<Output = R>
     where
         C: FnOnce() -> R + Send + 'static,
@@ -319,6 +645,31 @@ impl QuickJsRuntimeFacade {
         self.inner.add_task_to_event_loop(task)
     }
 
+    /// add a task to the event loop or fail with a `QueueFull` error when the queue is full, see [jobqueue](crate::jsutils::jobqueue)
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+    /// let rt = QuickJsRuntimeBuilder::new().max_queue_size(16).build();
+    /// match rt.try_add_task_to_event_loop(|| 1 + 1) {
+    ///     Ok(future) => assert_eq!(futures::executor::block_on(future), 2),
+    ///     Err(e) => assert!(e.is_queue_full()),
+    /// }
+    /// ```
+    pub fn try_add_task_to_event_loop<C, R: Send + 'static>(
+        &self,
+        task: C,
+    ) -> Result<impl Future<Output = R>, JsError>
+    where
+        C: FnOnce() -> R + Send + 'static,
+    {
+        self.inner.try_add_task_to_event_loop(task)
+    }
+
+    /// the number of jobs which were added to the event queue and are not done yet, including the running job
+    pub fn pending_jobs(&self) -> usize {
+        self.inner.pending_jobs()
+    }
+
     /// this is how you add a closure to the worker thread which has an instance of the QuickJsRuntime
     /// this will run asynchronously
     /// # example
@@ -360,7 +711,7 @@ impl QuickJsRuntimeFacade {
         QuickJsRuntimeBuilder::new()
     }
 
-    /// run the garbage collector asynchronously
+    /// run the garbage collector asynchronously, independent of the [gc_interval](QuickJsRuntimeBuilder::gc_interval)
     pub async fn gc(&self) {
         self.add_rt_task_to_event_loop(|q_js_rt| q_js_rt.gc()).await
     }
@@ -469,6 +820,218 @@ impl QuickJsRuntimeFacade {
         })
     }
 
+    /// this adds an async rust function to JavaScript, it is added for all current and future contexts
+    ///
+    /// the function returns a Promise to script which is resolved with the result of the Future, an Err rejects the Promise with an Error
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+    /// use quickjs_runtime::jsutils::Script;
+    /// use quickjs_runtime::values::{JsValueConvertable, JsValueFacade};
+    ///
+    /// let rt = QuickJsRuntimeBuilder::new().build();
+    ///
+    /// rt.set_async_function(&["com", "mycompany", "util"], "queryA", |_q_ctx, args: Vec<JsValueFacade>|{
+    ///     let a = args[0].get_i32();
+    ///     async move {
+    ///         Ok((a * 3).to_js_value_facade())
+    ///     }
+    /// }).expect("set func failed");
+    ///
+    /// let res = rt.eval_sync(None, Script::new("test.es", "com.mycompany.util.queryA(13);")).ok().expect("script failed");
+    ///
+    /// assert!(res.is_js_promise());
+    /// ```
+    pub fn set_async_function<F, R>(
+        &self,
+        namespace: &[&str],
+        name: &str,
+        function: F,
+    ) -> Result<(), JsError>
+    where
+        F: Fn(&QuickJsRealmAdapter, Vec<JsValueFacade>) -> R + Send + 'static,
+        R: Future<Output = Result<JsValueFacade, JsError>> + Send + 'static,
+    {
+        let name = name.to_string();
+
+        let namespace = namespace
+            .iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<String>>();
+
+        self.exe_rt_task_in_event_loop(move |q_js_rt| {
+            let func_rc = Rc::new(function);
+            let name = name.to_string();
+
+            q_js_rt.add_context_init_hook(move |_q_js_rt, realm| {
+                let namespace_slice = namespace.iter().map(|s| s.as_str()).collect::<Vec<&str>>();
+                let ns = objects::get_namespace_q(realm, &namespace_slice, true)?;
+
+                let func_rc = func_rc.clone();
+
+                let func = functions::new_function_q(
+                    realm,
+                    name.as_str(),
+                    move |realm, _this_ref, args| {
+                        let mut args_facades = vec![];
+
+                        for arg_ref in args {
+                            args_facades.push(realm.to_js_value_facade(arg_ref)?);
+                        }
+
+                        let producer = func_rc(realm, args_facades);
+
+                        realm.create_resolving_promise_async(producer, |realm, res| {
+                            realm.from_js_value_facade(res)
+                        })
+                    },
+                    1,
+                )?;
+
+                objects::set_property2_q(realm, &ns, name.as_str(), &func, 0)?;
+
+                Ok(())
+            })
+        })
+    }
+
+    /// add a function to JavaScript which blocks until the Future of the handler is done and returns its value, it is added for all current and future contexts
+    ///
+    /// **a call blocks the EventLoop for up to max_wait**, this needs to be enabled with [allow_sync_bridge](QuickJsRuntimeBuilder::allow_sync_bridge),
+    /// see [syncbridge](crate::jsutils::syncbridge) for how the call behaves
+    pub fn register_sync_bridge<F, R>(
+        &self,
+        namespace: &[&str],
+        name: &str,
+        handler: F,
+        max_wait: Duration,
+    ) -> Result<(), JsError>
+    where
+        F: Fn(Vec<JsValueFacade>) -> R + Send + 'static,
+        R: Future<Output = Result<JsValueFacade, JsError>> + Send + 'static,
+    {
+        let name = name.to_string();
+
+        let namespace = namespace
+            .iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<String>>();
+
+        self.exe_rt_task_in_event_loop(move |q_js_rt| {
+            if !q_js_rt.allow_sync_bridge {
+                return Err(JsError::new_str(
+                    "sync bridges are not allowed, see QuickJsRuntimeBuilder::allow_sync_bridge",
+                ));
+            }
+            let handler_rc = Rc::new(handler);
+
+            q_js_rt.add_context_init_hook(move |_q_js_rt, realm| {
+                let namespace_slice = namespace.iter().map(|s| s.as_str()).collect::<Vec<&str>>();
+                let ns = objects::get_namespace_q(realm, &namespace_slice, true)?;
+
+                let func = syncbridge::new_bridge_function_q(
+                    realm,
+                    name.as_str(),
+                    handler_rc.clone(),
+                    max_wait,
+                )?;
+
+                objects::set_property2_q(realm, &ns, name.as_str(), &func, 0)?;
+
+                Ok(())
+            })
+        })
+    }
+
+    /// get the events which were kept by the compile audit, oldest first
+    ///
+    /// the log is empty when the audit was not enabled with [compile_audit](QuickJsRuntimeBuilder::compile_audit) or when
+    /// a [compile_audit_hook](QuickJsRuntimeBuilder::compile_audit_hook) was set, see [compileaudit](crate::jsutils::compileaudit)
+    pub fn compile_audit_log(&self) -> Vec<CompileEvent> {
+        self.exe_rt_task_in_event_loop(|q_js_rt| {
+            q_js_rt
+                .compile_audit
+                .as_ref()
+                .map(|audit| audit.get_log())
+                .unwrap_or_default()
+        })
+    }
+
+    /// collect the queue, latency, memory, gc, realm and cache metrics of the runtime in one snapshot, see [metrics](crate::jsutils::metrics)
+    pub fn metrics_snapshot(&self) -> MetricsSnapshot {
+        // read before the collecting job is added so it is not included
+        let job_queue = &self.inner.job_queue;
+        let pending_jobs = job_queue.pending();
+        let max_queue_size = job_queue.bound();
+        let job_latency = job_queue.latency.snapshot();
+        self.exe_rt_task_in_event_loop(move |q_js_rt| {
+            metrics::snapshot_q(q_js_rt, pending_jobs, max_queue_size, job_latency)
+        })
+    }
+
+    /// get the hits, misses and evictions of the bytecode cache, None when no [bytecode_cache_dir](QuickJsRuntimeBuilder::bytecode_cache_dir) was set
+    pub fn bytecode_cache_stats(&self) -> Option<BytecodeCacheStats> {
+        self.exe_rt_task_in_event_loop(|q_js_rt| {
+            q_js_rt
+                .bytecode_cache
+                .as_ref()
+                .map(|cache| cache.get_stats())
+        })
+    }
+
+    /// load and evaluate modules in a realm before they are imported, see [preload](crate::jsutils::preload)
+    ///
+    /// a module which fails is reported in the [PreloadReport] and does not fail the other modules
+    pub async fn preload_modules(
+        &self,
+        realm_name: Option<&str>,
+        specifiers: &[&str],
+    ) -> PreloadReport {
+        let specifiers: Vec<String> = specifiers.iter().map(|s| s.to_string()).collect();
+        self.loop_realm(realm_name, move |q_js_rt, realm| {
+            preload::preload_modules_q(q_js_rt, realm, &specifiers)
+        })
+        .await
+    }
+
+    /// keep the bytecode cache entries of a module when other entries are evicted, see [preload](crate::jsutils::preload)
+    ///
+    /// fails when no module loader can load the specifier, without a [bytecode_cache_dir](QuickJsRuntimeBuilder::bytecode_cache_dir) this only checks the specifier
+    pub fn pin_module(&self, realm_name: Option<&str>, specifier: &str) -> Result<(), JsError> {
+        let specifier = specifier.to_string();
+        self.loop_realm_sync(realm_name, move |q_js_rt, realm| {
+            let path = preload::normalize_q(q_js_rt, realm, specifier.as_str())?;
+            if let Some(cache) = &q_js_rt.bytecode_cache {
+                cache.pin(path.as_str());
+            }
+            Ok(())
+        })
+    }
+
+    /// load a module of a realm again on its next import, the modules which import it are loaded again as well, see [modulecache](crate::jsutils::modulecache)
+    ///
+    /// returns the paths of the invalidated modules, starting with absolute_path
+    pub fn invalidate_module(&self, realm_name: Option<&str>, absolute_path: &str) -> Vec<String> {
+        let absolute_path = absolute_path.to_string();
+        self.loop_realm_sync(realm_name, move |_q_js_rt, realm| {
+            realm.invalidate_module(absolute_path.as_str())
+        })
+    }
+
+    /// load every module of every realm again on its next import, see [modulecache](crate::jsutils::modulecache)
+    pub fn clear_module_cache(&self) {
+        self.exe_rt_task_in_event_loop(|q_js_rt| {
+            for realm in q_js_rt.contexts.values() {
+                realm.clear_module_cache();
+            }
+        })
+    }
+
+    /// get the number of calls of the sync bridges and the time they blocked the EventLoop
+    pub fn sync_bridge_stats(&self) -> SyncBridgeStats {
+        self.exe_rt_task_in_event_loop(|q_js_rt| q_js_rt.sync_bridge_stats.borrow().clone())
+    }
+
     /// add a task the the "helper" thread pool
     pub fn add_helper_task<T>(task: T)
     where
@@ -506,30 +1069,193 @@ impl QuickJsRuntimeFacade {
     }
 
     /// drop a context which was created earlier with a call to [create_context()](struct.EsRuntime.html#method.create_context)
-    pub fn drop_context(&self, id: &str) {
+    ///
+    /// the timers of the context are cleared, dropping a context which does not exist is a no-op and the main context can not be dropped
+    pub fn drop_context(&self, id: &str) -> Result<(), JsError> {
+        if id == "__main__" {
+            return Err(JsError::new_str("the main realm can not be dropped"));
+        }
         let id = id.to_string();
-        self.inner
-            .event_loop
-            .exe(move || QuickJsRuntimeAdapter::remove_context(id.as_str()))
+        self.inner.event_loop.exe(move || {
+            if QuickJsRuntimeAdapter::do_with(|q_js_rt| q_js_rt.has_context(id.as_str())) {
+                QuickJsRuntimeAdapter::remove_context(id.as_str());
+            }
+            Ok(())
+        })
     }
-}
 
-fn loop_realm_func<
-    R: Send + 'static,
-    C: FnOnce(&QuickJsRuntimeAdapter, &QuickJsRealmAdapter) -> R + Send + 'static,
->(
-    realm_name: Option<String>,
-    consumer: C,
-) -> R {
-    let res = QuickJsRuntimeAdapter::do_with(|q_js_rt| {
-        if let Some(realm_str) = realm_name.as_ref() {
-            if let Some(realm) = q_js_rt.get_realm(realm_str) {
-                (Some(consumer(q_js_rt, realm)), None)
-            } else {
-                (None, Some(consumer))
-            }
-        } else {
-            (Some(consumer(q_js_rt, q_js_rt.get_main_realm())), None)
+    /// check if a context exists
+    pub fn has_context(&self, id: &str) -> bool {
+        let id = id.to_string();
+        self.exe_rt_task_in_event_loop(move |q_js_rt| q_js_rt.has_context(id.as_str()))
+    }
+
+    /// Evaluate a script asynchronously in a context which was created with [create_context](Self::create_context)
+    ///
+    /// unlike [eval](Self::eval) the context is not created when it does not exist, the eval fails instead
+    #[allow(clippy::type_complexity)]
+    pub fn eval_ctx(
+        &self,
+        id: &str,
+        script: Script,
+    ) -> Pin<Box<dyn Future<Output = Result<JsValueFacade, JsError>>>> {
+        self.eval_linked(RealmRef::Existing(id.to_string()), "eval", |rt, realm| {
+            taskscope::run_with_defaults(rt, || realm.eval(script))
+        })
+    }
+
+    /// evaluate a module asynchronously in a context which was created with [create_context](Self::create_context),
+    /// fails when the context does not exist
+    #[allow(clippy::type_complexity)]
+    pub fn eval_module_ctx(
+        &self,
+        id: &str,
+        script: Script,
+    ) -> Pin<Box<dyn Future<Output = Result<JsValueFacade, JsError>>>> {
+        self.eval_linked(
+            RealmRef::Existing(id.to_string()),
+            "eval_module",
+            |rt, realm| taskscope::run_with_defaults(rt, || realm.eval_module(script)),
+        )
+    }
+
+    /// invoke a function in a context which was created with [create_context](Self::create_context),
+    /// fails when the context does not exist
+    #[allow(clippy::type_complexity)]
+    pub fn invoke_function_ctx(
+        &self,
+        id: &str,
+        namespace: &[&str],
+        method_name: &str,
+        args: Vec<JsValueFacade>,
+    ) -> Pin<Box<dyn Future<Output = Result<JsValueFacade, JsError>>>> {
+        let namespace: Vec<String> = namespace.iter().map(|s| s.to_string()).collect();
+        let method_name = method_name.to_string();
+        self.eval_linked(
+            RealmRef::Existing(id.to_string()),
+            "invoke_function",
+            move |rt, realm| {
+                let args_adapters: Vec<QuickJsValueAdapter> = args
+                    .into_iter()
+                    .map(|jsvf| realm.from_js_value_facade(jsvf))
+                    .collect::<Result<_, _>>()?;
+                let namespace: Vec<&str> = namespace.iter().map(|s| s.as_str()).collect();
+                taskscope::run_with_defaults(rt, || {
+                    realm.invoke_function_by_name(
+                        namespace.as_slice(),
+                        method_name.as_str(),
+                        args_adapters.as_slice(),
+                    )
+                })
+            },
+        )
+    }
+}
+
+// the errors tell whether the receiver, the function or the invocation failed
+fn invoke_function_with_opts_q(
+    realm: &QuickJsRealmAdapter,
+    namespace: &[&str],
+    method_name: &str,
+    args: Vec<JsValueFacade>,
+    opts: CallOptions,
+) -> Result<QuickJsValueAdapter, JsError> {
+    let path = namespace
+        .iter()
+        .chain(std::iter::once(&method_name))
+        .copied()
+        .collect::<Vec<_>>()
+        .join(".");
+
+    let receiver = match opts.this.as_ref() {
+        Some(this) => {
+            if !this.is_realm_alive() {
+                return Err(JsError::new_string(format!(
+                    "could not look up the receiver of {path}: realm {} was dropped",
+                    this.realm_id()
+                )));
+            }
+            if this.realm_id() != realm.get_realm_id() {
+                return Err(JsError::new_string(format!(
+                    "could not look up the receiver of {path}: the object belongs to realm {} and not to realm {}",
+                    this.realm_id(),
+                    realm.get_realm_id()
+                )));
+            }
+            Some(realm.with_cached_object(this.id, |obj| obj.dup()))
+        }
+        None => None,
+    };
+
+    let function = objects::get_namespace_q(realm, namespace, false)
+        .and_then(|ns| realm.get_object_property(&ns, method_name))
+        .map_err(|e| {
+            JsError::new_string(format!(
+                "could not look up function {path}: {}",
+                e.get_message()
+            ))
+        })?;
+    if !functions::is_function_q(realm, &function) {
+        return Err(JsError::new_string(format!(
+            "could not look up function {path}: it is a {} and not a function",
+            function.get_js_type()
+        )));
+    }
+
+    let mut args = args
+        .into_iter()
+        .map(|jsvf| realm.from_js_value_facade(jsvf))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| {
+            JsError::new_string(format!(
+                "invocation of {path} failed, could not convert the arguments: {}",
+                e.get_message()
+            ))
+        })?;
+    if opts.spread_last_array {
+        let last = args.pop().ok_or_else(|| {
+            JsError::new_string(format!(
+                "invocation of {path} failed: spread_last_array was set but there are no arguments"
+            ))
+        })?;
+        if !arrays::is_array_q(realm, &last) {
+            return Err(JsError::new_string(format!(
+                "invocation of {path} failed: spread_last_array was set but the last argument is not an array"
+            )));
+        }
+        for index in 0..arrays::get_length_q(realm, &last)? {
+            args.push(arrays::get_element_q(realm, &last, index)?);
+        }
+    }
+
+    let arg_refs = args.iter().collect::<Vec<_>>();
+    realm
+        .invoke_function(receiver.as_ref(), &function, arg_refs.as_slice())
+        .map_err(|e| {
+            JsError::new(
+                e.get_name().to_string(),
+                format!("invocation of {path} failed: {}", e.get_message()),
+                e.get_stack().to_string(),
+            )
+        })
+}
+
+fn loop_realm_func<
+    R: Send + 'static,
+    C: FnOnce(&QuickJsRuntimeAdapter, &QuickJsRealmAdapter) -> R + Send + 'static,
+>(
+    realm_name: Option<String>,
+    consumer: C,
+) -> R {
+    let res = QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+        if let Some(realm_str) = realm_name.as_ref() {
+            if let Some(realm) = q_js_rt.get_realm(realm_str) {
+                (Some(consumer(q_js_rt, realm)), None)
+            } else {
+                (None, Some(consumer))
+            }
+        } else {
+            (Some(consumer(q_js_rt, q_js_rt.get_main_realm())), None)
         }
     });
 
@@ -562,23 +1288,119 @@ fn loop_realm_func<
     }
 }
 
+// the realm a job runs in, by id or by handle
+enum RealmRef {
+    // the main realm when None, a realm which does not exist is created
+    Id(Option<String>),
+    // a realm which must exist
+    Existing(String),
+    Handle(RealmHandle),
+}
+
+fn loop_realm_ref<
+    R: Send + 'static,
+    C: FnOnce(&QuickJsRuntimeAdapter, &QuickJsRealmAdapter) -> Result<R, JsError> + Send + 'static,
+>(
+    realm: RealmRef,
+    consumer: C,
+) -> Result<R, JsError> {
+    match realm {
+        RealmRef::Id(realm_name) => loop_realm_func(realm_name, consumer),
+        RealmRef::Existing(id) => {
+            QuickJsRuntimeAdapter::do_with(|q_js_rt| match q_js_rt.opt_context(id.as_str()) {
+                Some(realm) => consumer(q_js_rt, realm),
+                None => Err(JsError::new_string(format!("no such realm: {id}"))),
+            })
+        }
+        RealmRef::Handle(handle) => {
+            QuickJsRuntimeAdapter::do_with(|q_js_rt| consumer(q_js_rt, handle.resolve_q(q_js_rt)?))
+        }
+    }
+}
+
 impl QuickJsRuntimeFacade {
-    pub fn create_realm(&self, name: &str) -> Result<(), JsError> {
+    /// create a realm and get a [RealmHandle](crate::jsutils::realmhandle) to it
+    pub fn create_realm(&self, name: &str) -> Result<RealmHandle, JsError> {
         let name = name.to_string();
-        self.inner
-            .event_loop
-            .exe(move || QuickJsRuntimeAdapter::create_context(name.as_str()))
+        self.inner.event_loop.exe(move || {
+            QuickJsRuntimeAdapter::create_context(name.as_str())?;
+            QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+                Ok(realmhandle::handle_q(
+                    q_js_rt,
+                    q_js_rt.get_context(name.as_str()),
+                ))
+            })
+        })
+    }
+
+    /// create a realm and apply the options after the realm init hooks
+    pub fn create_realm_with_options(
+        &self,
+        name: &str,
+        options: RealmOptions,
+    ) -> Result<RealmHandle, JsError> {
+        let name = name.to_string();
+        self.inner.event_loop.exe(move || {
+            QuickJsRuntimeAdapter::create_context(name.as_str())?;
+            QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+                let realm = q_js_rt.get_context(name.as_str());
+                let handle = realmhandle::handle_q(q_js_rt, realm);
+                options.apply(realm)?;
+                Ok(handle)
+            })
+        })
+    }
+
+    /// get a [RealmHandle](crate::jsutils::realmhandle) to an existing realm, use `__main__` for the main realm
+    pub fn realm_handle(&self, realm_id: &str) -> Result<RealmHandle, JsError> {
+        let realm_id = realm_id.to_string();
+        self.exe_rt_task_in_event_loop(move |rt| match rt.get_realm(realm_id.as_str()) {
+            Some(realm) => Ok(realmhandle::handle_q(rt, realm)),
+            None => Err(JsError::new_string(format!("no such realm: {realm_id}"))),
+        })
+    }
+
+    /// drop the realm of a handle, all handles to the realm become invalid, dropping a realm twice is a no-op
+    pub fn drop_realm(&self, handle: &RealmHandle) -> Result<(), JsError> {
+        if handle.id() == "__main__" {
+            return Err(JsError::new_str("the main realm can not be dropped"));
+        }
+        let handle = handle.clone();
+        self.exe_task_in_event_loop(move || {
+            // the realm of a live handle is the realm with its id
+            if handle.is_alive() {
+                QuickJsRuntimeAdapter::remove_context(handle.id());
+            }
+            Ok(())
+        })
+    }
+
+    /// get the state of the seeded Math.random of a realm, None when the realm was not seeded, see [random](crate::features::random)
+    pub fn get_random_state(&self, realm_id: &str) -> Result<Option<RandomState>, JsError> {
+        let realm_id = realm_id.to_string();
+        self.exe_rt_task_in_event_loop(move |rt| match rt.get_realm(realm_id.as_str()) {
+            Some(realm) => Ok(realm.random_state.get()),
+            None => Err(JsError::new_string(format!("no such realm: {realm_id}"))),
+        })
+    }
+
+    /// restore the state of Math.random in a realm, a realm which was not seeded gets the seeded Math.random
+    pub fn set_random_state(&self, realm_id: &str, state: RandomState) -> Result<(), JsError> {
+        let realm_id = realm_id.to_string();
+        self.exe_rt_task_in_event_loop(move |rt| match rt.get_realm(realm_id.as_str()) {
+            Some(realm) => random::install_q(realm, state),
+            None => Err(JsError::new_string(format!("no such realm: {realm_id}"))),
+        })
     }
 
     pub fn destroy_realm(&self, name: &str) -> Result<(), JsError> {
         let name = name.to_string();
         self.exe_task_in_event_loop(move || {
-            QuickJsRuntimeAdapter::do_with_mut(|rt| {
-                if rt.get_realm(name.as_str()).is_some() {
-                    rt.remove_realm(name.as_str());
-                }
-                Ok(())
-            })
+            let exists = QuickJsRuntimeAdapter::do_with(|rt| rt.get_realm(name.as_str()).is_some());
+            if exists && name != "__main__" {
+                QuickJsRuntimeAdapter::remove_context(name.as_str());
+            }
+            Ok(())
         })
     }
 
@@ -662,7 +1484,160 @@ impl QuickJsRuntimeFacade {
         self.add_task_to_event_loop_void(|| loop_realm_func(realm_name, consumer));
     }
 
+    /// add a job for the realm of a handle to the eventloop, fails with a DeadRealm error when the realm was dropped
+    pub fn loop_realm_in<
+        R: Send + 'static,
+        C: FnOnce(&QuickJsRuntimeAdapter, &QuickJsRealmAdapter) -> Result<R, JsError> + Send + 'static,
+    >(
+        &self,
+        handle: &RealmHandle,
+        consumer: C,
+    ) -> Pin<Box<dyn Future<Output = Result<R, JsError>>>> {
+        let realm = RealmRef::Handle(handle.clone());
+        Box::pin(self.add_task_to_event_loop(|| loop_realm_ref(realm, consumer)))
+    }
+
+    /// Evaluate a script asynchronously in the realm of a handle, see [eval](Self::eval)
+    #[allow(clippy::type_complexity)]
+    pub fn eval_in(
+        &self,
+        handle: &RealmHandle,
+        script: Script,
+    ) -> Pin<Box<dyn Future<Output = Result<JsValueFacade, JsError>>>> {
+        self.eval_linked(RealmRef::Handle(handle.clone()), "eval", |rt, realm| {
+            taskscope::run_with_defaults(rt, || realm.eval(script))
+        })
+    }
+
+    /// Evaluate a script in the realm of a handle and return the result synchronously
+    pub fn eval_in_sync(
+        &self,
+        handle: &RealmHandle,
+        script: Script,
+    ) -> Result<JsValueFacade, JsError> {
+        let realm = RealmRef::Handle(handle.clone());
+        self.exe_task_in_event_loop(|| {
+            loop_realm_ref(realm, |rt, realm| {
+                taskscope::run_with_defaults(rt, || {
+                    let res = realm.eval(script)?;
+                    realm.to_js_value_facade(&res)
+                })
+            })
+        })
+    }
+
+    /// Evaluate a module asynchronously in the realm of a handle, see [eval_module](Self::eval_module)
+    #[allow(clippy::type_complexity)]
+    pub fn eval_module_in(
+        &self,
+        handle: &RealmHandle,
+        script: Script,
+    ) -> Pin<Box<dyn Future<Output = Result<JsValueFacade, JsError>>>> {
+        self.eval_linked(
+            RealmRef::Handle(handle.clone()),
+            "eval_module",
+            |rt, realm| taskscope::run_with_defaults(rt, || realm.eval_module(script)),
+        )
+    }
+
+    /// invoke a function asynchronously in the realm of a handle, see [invoke_function](Self::invoke_function)
+    #[allow(clippy::type_complexity)]
+    pub fn invoke_function_in(
+        &self,
+        handle: &RealmHandle,
+        namespace: &[&str],
+        method_name: &str,
+        args: Vec<JsValueFacade>,
+    ) -> Pin<Box<dyn Future<Output = Result<JsValueFacade, JsError>>>> {
+        let movable_namespace: Vec<String> = namespace.iter().map(|s| s.to_string()).collect();
+        let movable_method_name = method_name.to_string();
+
+        self.loop_realm_in(handle, move |rt, realm| {
+            let args_adapters = args
+                .into_iter()
+                .map(|jsvf| realm.from_js_value_facade(jsvf))
+                .collect::<Result<Vec<QuickJsValueAdapter>, JsError>>()?;
+
+            let namespace = movable_namespace
+                .iter()
+                .map(|s| s.as_str())
+                .collect::<Vec<&str>>();
+
+            let res = taskscope::run_with_defaults(rt, || {
+                realm.invoke_function_by_name(
+                    namespace.as_slice(),
+                    movable_method_name.as_str(),
+                    args_adapters.as_slice(),
+                )
+            })?;
+            realm.to_js_value_facade(&res)
+        })
+    }
+
+    // run an eval job which is tied to the returned future, the job is skipped when the future was dropped before it ran
+    // and its result is not converted when the future was dropped while it ran, see QuickJsRuntimeBuilder::detach_dropped_futures
+    #[allow(clippy::type_complexity)]
+    fn eval_linked<C>(
+        &self,
+        realm: RealmRef,
+        source: &'static str,
+        job: C,
+    ) -> Pin<Box<dyn Future<Output = Result<JsValueFacade, JsError>>>>
+    where
+        C: FnOnce(
+                &QuickJsRuntimeAdapter,
+                &QuickJsRealmAdapter,
+            ) -> Result<QuickJsValueAdapter, JsError>
+            + Send
+            + 'static,
+    {
+        let dropped = Arc::new(AtomicBool::new(false));
+        let job_dropped = dropped.clone();
+        // a void task with a channel instead of add_task_to_event_loop, the future of that is cancelled when it is dropped
+        // which would also skip the jobs of detached futures
+        let (tx, rx) = futures::channel::oneshot::channel();
+        self.add_task_to_event_loop_void(move || {
+            let res = loop_realm_ref(realm, move |rt, realm| {
+                let linked = !rt.detach_dropped_futures;
+                if linked && job_dropped.load(Ordering::SeqCst) {
+                    log::debug!(
+                        "[{}] skipped {}, the future was dropped",
+                        realm.get_realm_id(),
+                        source
+                    );
+                    return Err(dropped_future_error());
+                }
+                let started = Instant::now();
+                let res = job(rt, realm);
+                if linked && job_dropped.load(Ordering::SeqCst) {
+                    if let Err(e) = &res {
+                        uncaught::report_uncaught_q(realm, source, e);
+                    }
+                    return Err(dropped_future_error());
+                }
+                // the result is converted after the timeout of the job ended, the conversion gives up at the same deadline
+                let deadline = rt.default_eval_options.timeout.map(|t| started + t);
+                res.and_then(|value| {
+                    conversion::with_caller_deadline(
+                        deadline,
+                        linked.then_some(job_dropped),
+                        || realm.to_js_value_facade(&value),
+                    )
+                })
+            });
+            let _ = tx.send(res);
+        });
+        let future = async move { rx.await.unwrap_or_else(|_| Err(dropped_future_error())) };
+        Box::pin(LinkedFuture {
+            future: Box::pin(future),
+            dropped,
+            done: false,
+        })
+    }
+
     /// Evaluate a script asynchronously
+    ///
+    /// the script is skipped when the future is dropped before it ran, see [detach_dropped_futures](QuickJsRuntimeBuilder::detach_dropped_futures)
     /// # Example
     /// ```rust
     /// use futures::executor::block_on;
@@ -680,13 +1655,11 @@ impl QuickJsRuntimeFacade {
         realm_name: Option<&str>,
         script: Script,
     ) -> Pin<Box<dyn Future<Output = Result<JsValueFacade, JsError>>>> {
-        self.loop_realm(realm_name, |_rt, realm| {
-            let res = realm.eval(script);
-            match res {
-                Ok(jsvr) => realm.to_js_value_facade(&jsvr),
-                Err(e) => Err(e),
-            }
-        })
+        self.eval_linked(
+            RealmRef::Id(realm_name.map(|s| s.to_string())),
+            "eval",
+            |rt, realm| taskscope::run_with_defaults(rt, || realm.eval(script)),
+        )
     }
 
     /// Evaluate a script and return the result synchronously
@@ -704,9 +1677,40 @@ impl QuickJsRuntimeFacade {
         &self,
         realm_name: Option<&str>,
         script: Script,
+    ) -> Result<JsValueFacade, JsError> {
+        self.loop_realm_sync(realm_name, |rt, realm| {
+            taskscope::run_with_defaults(rt, || {
+                let res = realm.eval(script);
+                match res {
+                    Ok(jsvr) => realm.to_js_value_facade(&jsvr),
+                    Err(e) => Err(e),
+                }
+            })
+        })
+    }
+
+    /// Evaluate a template script asynchronously, see [Script::template]
+    #[allow(clippy::type_complexity)]
+    pub fn eval_template(
+        &self,
+        realm_name: Option<&str>,
+        template: ScriptTemplate,
+    ) -> Pin<Box<dyn Future<Output = Result<JsValueFacade, JsError>>>> {
+        self.eval_linked(
+            RealmRef::Id(realm_name.map(|s| s.to_string())),
+            "eval_template",
+            |_rt, realm| realm.eval_template(template),
+        )
+    }
+
+    /// Evaluate a template script and return the result synchronously, see [Script::template]
+    pub fn eval_template_sync(
+        &self,
+        realm_name: Option<&str>,
+        template: ScriptTemplate,
     ) -> Result<JsValueFacade, JsError> {
         self.loop_realm_sync(realm_name, |_rt, realm| {
-            let res = realm.eval(script);
+            let res = realm.eval_template(template);
             match res {
                 Ok(jsvr) => realm.to_js_value_facade(&jsvr),
                 Err(e) => Err(e),
@@ -753,10 +1757,11 @@ impl QuickJsRuntimeFacade {
         realm_name: Option<&str>,
         script: Script,
     ) -> Pin<Box<dyn Future<Output = Result<JsValueFacade, JsError>>>> {
-        self.loop_realm(realm_name, |_rt, realm| {
-            let res = realm.eval_module(script)?;
-            realm.to_js_value_facade(&res)
-        })
+        self.eval_linked(
+            RealmRef::Id(realm_name.map(|s| s.to_string())),
+            "eval_module",
+            |rt, realm| taskscope::run_with_defaults(rt, || realm.eval_module(script)),
+        )
     }
 
     /// evaluate a module synchronously, you need this if you want to compile a script that contains static imports
@@ -793,154 +1798,666 @@ impl QuickJsRuntimeFacade {
     /// ```
     pub fn eval_module_sync(
         &self,
-        realm_name: Option<&str>,
+        realm_name: Option<&str>,
+        script: Script,
+    ) -> Result<JsValueFacade, JsError> {
+        self.loop_realm_sync(realm_name, |rt, realm| {
+            taskscope::run_with_defaults(rt, || {
+                let res = realm.eval_module(script)?;
+                realm.to_js_value_facade(&res)
+            })
+        })
+    }
+
+    /// compile a script to bytecode which can be evaluated with [eval_compiled](Self::eval_compiled), see [compile_q](crate::quickjs_utils::compile::compile_q)
+    #[allow(clippy::type_complexity)]
+    pub fn compile(
+        &self,
+        realm_name: Option<&str>,
+        script: Script,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<u8>, JsError>>>> {
+        self.loop_realm(realm_name, |_rt, realm| realm.compile(script))
+    }
+
+    /// compile a module to bytecode which can be evaluated with [eval_compiled](Self::eval_compiled), see [compile_module_q](crate::quickjs_utils::compile::compile_module_q)
+    #[allow(clippy::type_complexity)]
+    pub fn compile_module(
+        &self,
+        realm_name: Option<&str>,
+        script: Script,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<u8>, JsError>>>> {
+        self.loop_realm(realm_name, |_rt, realm| realm.compile_module(script))
+    }
+
+    /// evaluate bytecode of [compile](Self::compile) or [compile_module](Self::compile_module) asynchronously,
+    /// bytecode which is corrupt or of another version fails with an error
+    /// # example
+    /// ```rust
+    /// use futures::executor::block_on;
+    /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+    /// use quickjs_runtime::jsutils::Script;
+    /// let rt = QuickJsRuntimeBuilder::new().build();
+    /// let bytes = block_on(rt.compile(None, Script::new("my_file.js", "(9 * 3);"))).expect("compile failed");
+    /// let res = block_on(rt.eval_compiled(None, bytes)).expect("script failed");
+    /// assert_eq!(res.get_i32(), 27);
+    /// ```
+    #[allow(clippy::type_complexity)]
+    pub fn eval_compiled(
+        &self,
+        realm_name: Option<&str>,
+        bytes: Vec<u8>,
+    ) -> Pin<Box<dyn Future<Output = Result<JsValueFacade, JsError>>>> {
+        self.eval_linked(
+            RealmRef::Id(realm_name.map(|s| s.to_string())),
+            "eval_compiled",
+            move |rt, realm| taskscope::run_with_defaults(rt, || realm.eval_compiled(&bytes)),
+        )
+    }
+
+    /// invoke a function in the engine and get the result synchronously
+    /// # example
+    /// ```rust
+    /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+    /// use quickjs_runtime::jsutils::Script;
+    /// use quickjs_runtime::values::JsValueConvertable;
+    /// let rt = QuickJsRuntimeBuilder::new().build();
+    /// let script = Script::new("my_file.es", "this.com = {my: {methodA: function(a, b, someStr, someBool){return a*b;}}};");
+    /// rt.eval_sync(None, script).ok().expect("script failed");
+    /// let res = rt.invoke_function_sync(None, &["com", "my"], "methodA", vec![7i32.to_js_value_facade(), 5i32.to_js_value_facade(), "abc".to_js_value_facade(), true.to_js_value_facade()]).ok().expect("func failed");
+    /// assert_eq!(res.get_i32(), 35);
+    /// ```
+    #[warn(clippy::type_complexity)]
+    pub fn invoke_function_sync(
+        &self,
+        realm_name: Option<&str>,
+        namespace: &[&str],
+        method_name: &str,
+        args: Vec<JsValueFacade>,
+    ) -> Result<JsValueFacade, JsError> {
+        let movable_namespace: Vec<String> = namespace.iter().map(|s| s.to_string()).collect();
+        let movable_method_name = method_name.to_string();
+
+        self.loop_realm_sync(realm_name, move |rt, realm| {
+            let args_adapters: Vec<QuickJsValueAdapter> = args
+                .into_iter()
+                .map(|jsvf| realm.from_js_value_facade(jsvf))
+                .collect::<Result<_, _>>()?;
+
+            let namespace = movable_namespace
+                .iter()
+                .map(|s| s.as_str())
+                .collect::<Vec<&str>>();
+
+            let res = taskscope::run_with_defaults(rt, || {
+                realm.invoke_function_by_name(
+                    namespace.as_slice(),
+                    movable_method_name.as_str(),
+                    args_adapters.as_slice(),
+                )
+            });
+
+            match res {
+                Ok(jsvr) => realm.to_js_value_facade(&jsvr),
+                Err(e) => Err(e),
+            }
+        })
+    }
+
+    /// invoke a function in the engine asynchronously
+    /// N.B. func_name is not a &str because of <https://github.com/rust-lang/rust/issues/56238> (i think)
+    /// # example
+    /// ```rust
+    /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+    /// use quickjs_runtime::jsutils::Script;
+    /// use quickjs_runtime::values::JsValueConvertable;
+    /// let rt = QuickJsRuntimeBuilder::new().build();
+    /// let script = Script::new("my_file.es", "this.com = {my: {methodA: function(a, b){return a*b;}}};");
+    /// rt.eval_sync(None, script).ok().expect("script failed");
+    /// rt.invoke_function(None, &["com", "my"], "methodA", vec![7.to_js_value_facade(), 5.to_js_value_facade()]);
+    /// ```
+    #[allow(clippy::type_complexity)]
+    pub fn invoke_function(
+        &self,
+        realm_name: Option<&str>,
+        namespace: &[&str],
+        method_name: &str,
+        args: Vec<JsValueFacade>,
+    ) -> Pin<Box<dyn Future<Output = Result<JsValueFacade, JsError>>>> {
+        let movable_namespace: Vec<String> = namespace.iter().map(|s| s.to_string()).collect();
+        let movable_method_name = method_name.to_string();
+
+        self.loop_realm(realm_name, move |rt, realm| {
+            let args_adapters: Vec<QuickJsValueAdapter> = args
+                .into_iter()
+                .map(|jsvf| realm.from_js_value_facade(jsvf))
+                .collect::<Result<_, _>>()?;
+
+            let namespace = movable_namespace
+                .iter()
+                .map(|s| s.as_str())
+                .collect::<Vec<&str>>();
+
+            let res = taskscope::run_with_defaults(rt, || {
+                realm.invoke_function_by_name(
+                    namespace.as_slice(),
+                    movable_method_name.as_str(),
+                    args_adapters.as_slice(),
+                )
+            });
+
+            match res {
+                Ok(jsvr) => realm.to_js_value_facade(&jsvr),
+                Err(e) => Err(e),
+            }
+        })
+    }
+
+    pub fn invoke_function_void(
+        &self,
+        realm_name: Option<&str>,
+        namespace: &[&str],
+        method_name: &str,
+        args: Vec<JsValueFacade>,
+    ) {
+        let movable_namespace: Vec<String> = namespace.iter().map(|s| s.to_string()).collect();
+        let movable_method_name = method_name.to_string();
+
+        self.loop_realm_void(realm_name, move |rt, realm| {
+            let args_adapters: Vec<QuickJsValueAdapter> = match args
+                .into_iter()
+                .map(|jsvf| realm.from_js_value_facade(jsvf))
+                .collect()
+            {
+                Ok(args_adapters) => args_adapters,
+                Err(err) => {
+                    log::error!(
+                        "js_function_invoke_void could not convert the args of {}: {}",
+                        movable_method_name.as_str(),
+                        err
+                    );
+                    return;
+                }
+            };
+
+            let namespace = movable_namespace
+                .iter()
+                .map(|s| s.as_str())
+                .collect::<Vec<&str>>();
+
+            let res = taskscope::run_with_defaults(rt, || {
+                realm.invoke_function_by_name(
+                    namespace.as_slice(),
+                    movable_method_name.as_str(),
+                    args_adapters.as_slice(),
+                )
+            })
+            .map(|jsvr| realm.to_js_value_facade(&jsvr));
+
+            match res {
+                Ok(_) => {
+                    log::trace!(
+                        "js_function_invoke_void succeeded: {}",
+                        movable_method_name.as_str()
+                    );
+                }
+                Err(err) => {
+                    log::trace!(
+                        "js_function_invoke_void failed: {}: {}",
+                        movable_method_name.as_str(),
+                        err
+                    );
+                }
+            }
+        })
+    }
+
+    /// invoke a function with a receiver or with the elements of an array as its arguments, see [CallOptions]
+    ///
+    /// the error message tells whether the lookup of the receiver, the lookup of the function or the invocation failed
+    /// # example
+    /// ```rust
+    /// use futures::executor::block_on;
+    /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+    /// use quickjs_runtime::facades::CallOptions;
+    /// use quickjs_runtime::jsutils::Script;
+    /// use quickjs_runtime::values::JsValueFacade;
+    /// let rt = QuickJsRuntimeBuilder::new().build();
+    /// rt.eval_sync(None, Script::new("my_file.js", "this.sum = function(...nums){return nums.reduce((a, b) => a + b, 0);};")).expect("script failed");
+    /// let nums = JsValueFacade::Array { val: vec![JsValueFacade::new_i32(1), JsValueFacade::new_i32(2), JsValueFacade::new_i32(3)] };
+    /// let opts = CallOptions { this: None, spread_last_array: true };
+    /// let res = block_on(rt.invoke_function_with_opts(None, &[], "sum", vec![nums], opts)).expect("call failed");
+    /// assert_eq!(res.get_i32(), 6);
+    /// ```
+    #[allow(clippy::type_complexity)]
+    pub fn invoke_function_with_opts(
+        &self,
+        realm_name: Option<&str>,
+        namespace: &[&str],
+        method_name: &str,
+        args: Vec<JsValueFacade>,
+        opts: CallOptions,
+    ) -> Pin<Box<dyn Future<Output = Result<JsValueFacade, JsError>>>> {
+        let movable_namespace: Vec<String> = namespace.iter().map(|s| s.to_string()).collect();
+        let movable_method_name = method_name.to_string();
+
+        self.loop_realm(realm_name, move |rt, realm| {
+            let namespace = movable_namespace
+                .iter()
+                .map(|s| s.as_str())
+                .collect::<Vec<&str>>();
+
+            let res = taskscope::run_with_defaults(rt, || {
+                invoke_function_with_opts_q(
+                    realm,
+                    namespace.as_slice(),
+                    movable_method_name.as_str(),
+                    args,
+                    opts,
+                )
+            })?;
+            realm.to_js_value_facade(&res)
+        })
+    }
+
+    /// replace a function with a memoized version of that function, see [memoize_function_q](crate::jsutils::memoize::memoize_function_q)
+    pub fn memoize_function(
+        &self,
+        realm_name: Option<&str>,
+        namespace: &[&str],
+        name: &str,
+        config: MemoConfig,
+    ) -> Result<(), JsError> {
+        let movable_namespace: Vec<String> = namespace.iter().map(|s| s.to_string()).collect();
+        let movable_name = name.to_string();
+
+        self.loop_realm_sync(realm_name, move |_rt, realm| {
+            let namespace = movable_namespace
+                .iter()
+                .map(|s| s.as_str())
+                .collect::<Vec<&str>>();
+            memoize_function_q(realm, namespace.as_slice(), movable_name.as_str(), config)
+        })
+    }
+
+    /// add a revocable function to the global scope of a realm, see [capabilities](crate::jsutils::capabilities)
+    pub fn create_capability<F>(
+        &self,
+        realm_name: Option<&str>,
+        name: &str,
+        handler: F,
+    ) -> Result<CapabilityHandle, JsError>
+    where
+        F: Fn(&QuickJsRealmAdapter, Vec<JsValueFacade>) -> Result<JsValueFacade, JsError>
+            + Send
+            + 'static,
+    {
+        let name = name.to_string();
+        self.loop_realm_sync(realm_name, move |_rt, realm| {
+            let (func, handle) = capabilities::create_capability_q(realm, name.as_str(), handler)?;
+            let global = realm.get_global()?;
+            objects::set_property_q(realm, &global, name.as_str(), &func)?;
+            Ok(handle)
+        })
+    }
+
+    /// add a live binding of a rust struct to the global scope of a realm, see [binding](crate::jsutils::binding)
+    pub fn bind_object<T>(
+        &self,
+        realm_name: Option<&str>,
+        name: &str,
+        value: Arc<RwLock<T>>,
+    ) -> Result<BoundObjectHandle, JsError>
+    where
+        T: Serialize + DeserializeOwned + Send + Sync + 'static,
+    {
+        let realm_id = match realm_name {
+            Some(realm_name) => realm_name.to_string(),
+            None => self.exe_rt_task_in_event_loop(|q_js_rt| q_js_rt.get_main_realm().id.clone()),
+        };
+        binding::bind_object(&self.inner, realm_id.as_str(), name, value)
+    }
+
+    /// remove all cached results of a memoized function
+    pub fn invalidate_memo(
+        &self,
+        realm_name: Option<&str>,
+        namespace: &[&str],
+        name: &str,
+    ) -> Result<(), JsError> {
+        let movable_namespace: Vec<String> = namespace.iter().map(|s| s.to_string()).collect();
+        let movable_name = name.to_string();
+
+        self.loop_realm_sync(realm_name, move |_rt, realm| {
+            let namespace = movable_namespace
+                .iter()
+                .map(|s| s.as_str())
+                .collect::<Vec<&str>>();
+            invalidate_memo_q(realm, namespace.as_slice(), movable_name.as_str())
+        })
+    }
+
+    /// evaluate a script in a new realm which is dropped afterwards, see [isolation](crate::jsutils::isolation)
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+    /// use quickjs_runtime::jsutils::isolation::IsolationOptions;
+    /// use quickjs_runtime::jsutils::Script;
+    /// use futures::executor::block_on;
+    /// use std::time::Duration;
+    /// let rt = QuickJsRuntimeBuilder::new().build();
+    /// let options = IsolationOptions {
+    ///     timeout: Some(Duration::from_secs(1)),
+    ///     ..Default::default()
+    /// };
+    /// let res = block_on(rt.eval_isolated(Script::new("snippet.js", "7 * 6"), options)).expect("eval failed");
+    /// assert_eq!(res.get_i32(), 42);
+    /// ```
+    pub async fn eval_isolated(
+        &self,
+        script: Script,
+        options: IsolationOptions,
+    ) -> Result<JsValueFacade, JsError> {
+        isolation::eval_isolated(self.inner.clone(), script, options).await
+    }
+
+    /// evaluate a script and track the timers and internal promises it starts, see [taskscope](crate::jsutils::taskscope)
+    ///
+    /// the [BackgroundPolicy](crate::jsutils::taskscope::BackgroundPolicy) of the options decides if the eval waits for that work, cancels it or leaves it running,
+    /// the options are merged with the [default_eval_options](crate::builder::QuickJsRuntimeBuilder::default_eval_options) of the runtime
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+    /// use quickjs_runtime::jsutils::taskscope::{BackgroundPolicy, EvalOptions};
+    /// use quickjs_runtime::jsutils::Script;
+    /// use futures::executor::block_on;
+    /// let rt = QuickJsRuntimeBuilder::new().build();
+    /// let options = EvalOptions::new().background_policy(BackgroundPolicy::Drain);
+    /// block_on(rt.eval_with_options(None, Script::new("drain.js", "globalThis.a = 1; setTimeout(() => a++, 10);"), options)).expect("eval failed");
+    /// assert_eq!(rt.eval_sync(None, Script::new("drain.js", "a")).expect("script failed").get_i32(), 2);
+    /// ```
+    pub async fn eval_with_options(
+        &self,
+        realm_name: Option<&str>,
+        script: Script,
+        options: EvalOptions,
+    ) -> Result<JsValueFacade, JsError> {
+        let (res, drain, options) = self
+            .loop_realm(realm_name, move |rt, realm| {
+                let options = rt.default_eval_options.merge(&options);
+                let (res, drain) = taskscope::eval_in_scope(rt, realm, script, &options);
+                (res, drain, options)
+            })
+            .await;
+        taskscope::await_drain(drain, &options).await?;
+        res
+    }
+
+    /// evaluate a script and roll back its changes to the globals when it throws or its result is rejected, see [transaction](crate::jsutils::transaction)
+    pub async fn eval_transactional(
+        &self,
+        realm_name: Option<&str>,
+        script: Script,
+        options: TransactionOptions,
+    ) -> Result<JsValueFacade, JsError> {
+        self.loop_realm(realm_name, move |rt, realm| {
+            transaction::eval_transactional_q(rt, realm, script, options)
+        })
+        .await
+    }
+
+    /// evaluate a script with a [JobContext] which is readable from native functions with [QuickJsRuntimeAdapter::current_job_context]
+    ///
+    /// the context is propagated to the timers, internal promises and promise reactions started by the script, see [jobcontext](crate::jsutils::jobcontext)
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+    /// use quickjs_runtime::jsutils::jobcontext::JobContext;
+    /// use quickjs_runtime::jsutils::Script;
+    /// use quickjs_runtime::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+    /// use quickjs_runtime::values::JsValueFacade;
+    /// use futures::executor::block_on;
+    /// #[derive(Clone)]
+    /// struct RequestId(String);
+    /// let rt = QuickJsRuntimeBuilder::new().build();
+    /// rt.set_function(&[], "requestId", |_realm, _args| {
+    ///     let id = QuickJsRuntimeAdapter::current_job_context::<RequestId>().map(|id| id.0);
+    ///     Ok(JsValueFacade::new_string(id.unwrap_or_default()))
+    /// }).expect("could not set function");
+    /// let context = JobContext::new().with(RequestId("req-1".to_string()));
+    /// let res = block_on(rt.eval_with_context(None, Script::new("context.js", "requestId()"), context)).expect("eval failed");
+    /// assert_eq!(res.get_str(), "req-1");
+    /// ```
+    pub async fn eval_with_context(
+        &self,
+        realm_name: Option<&str>,
+        script: Script,
+        context: JobContext,
+    ) -> Result<JsValueFacade, JsError> {
+        let context = Arc::new(std::sync::Mutex::new(context));
+        self.loop_realm(realm_name, move |_rt, realm| {
+            let _context_guard = JobContextGuard::enter(Some(context));
+            realm
+                .eval(script)
+                .and_then(|value| realm.to_js_value_facade(&value))
+        })
+        .await
+    }
+
+    /// the number of timers and internal promises started by [eval_with_options](Self::eval_with_options) in a realm which are still pending
+    pub fn pending_background_tasks(&self, realm_id: &str) -> usize {
+        let realm_id = realm_id.to_string();
+        self.exe_task_in_event_loop(move || taskscope::pending_tasks(realm_id.as_str()))
+    }
+
+    /// dispatch an event on a [Proxy](crate::reflection::Proxy) class in every realm in which that class is installed with [static_event_target](crate::reflection::Proxy::static_event_target)
+    ///
+    /// the payload is converted to a value in every realm and passed to the listeners which were added with `MyClass.addEventListener()`,
+    /// a listener which throws only stops the delivery in its own realm, the errors are returned by realm id
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+    /// use quickjs_runtime::jsutils::Script;
+    /// use quickjs_runtime::reflection::Proxy;
+    /// use futures::executor::block_on;
+    /// let rt = QuickJsRuntimeBuilder::new().build();
+    /// rt.loop_realm_sync(None, |_rt, realm| {
+    ///     Proxy::new().name("Config").static_event_target().install(realm, true).map(|_| ())
+    /// }).expect("install failed");
+    /// rt.eval_sync(None, Script::new("config.js", "Config.addEventListener('changed', (evt) => globalThis.level = evt.level);")).expect("script failed");
+    /// let errors = block_on(rt.dispatch_proxy_static_event("Config", "changed", serde_json::json!({"level": 3})));
+    /// assert!(errors.is_empty());
+    /// assert_eq!(rt.eval_sync(None, Script::new("config.js", "level")).expect("script failed").get_i32(), 3);
+    /// ```
+    pub async fn dispatch_proxy_static_event(
+        &self,
+        class_name: &str,
+        event_id: &str,
+        payload: serde_json::Value,
+    ) -> HashMap<String, JsError> {
+        let class_name = class_name.to_string();
+        let event_id = event_id.to_string();
+        self.add_rt_task_to_event_loop(move |q_js_rt| {
+            eventtarget::dispatch_static_event_in_all_realms(
+                q_js_rt,
+                class_name.as_str(),
+                event_id.as_str(),
+                &payload,
+            )
+        })
+        .await
+    }
+
+    /// the number of static listeners of a [Proxy](crate::reflection::Proxy) class in all realms, for a single event or for all events
+    pub fn proxy_static_listener_count(&self, class_name: &str, event_id: Option<&str>) -> usize {
+        let class_name = class_name.to_string();
+        let event_id = event_id.map(|e| e.to_string());
+        self.exe_rt_task_in_event_loop(move |q_js_rt| {
+            q_js_rt
+                .contexts
+                .values()
+                .map(|realm| {
+                    eventtarget::static_listener_count(
+                        realm,
+                        class_name.as_str(),
+                        event_id.as_deref(),
+                    )
+                })
+                .sum()
+        })
+    }
+
+    /// run the tests of a suite module in a new realm, see [testing](crate::features::testing)
+    pub async fn run_tests(
+        &self,
         script: Script,
-    ) -> Result<JsValueFacade, JsError> {
-        self.loop_realm_sync(realm_name, |_rt, realm| {
-            let res = realm.eval_module(script)?;
-            realm.to_js_value_facade(&res)
-        })
+        options: TestOptions,
+    ) -> Result<TestReport, JsError> {
+        testing::run_tests(self, script, options).await
     }
 
-    /// invoke a function in the engine and get the result synchronously
-    /// # example
-    /// ```rust
-    /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
-    /// use quickjs_runtime::jsutils::Script;
-    /// use quickjs_runtime::values::JsValueConvertable;
-    /// let rt = QuickJsRuntimeBuilder::new().build();
-    /// let script = Script::new("my_file.es", "this.com = {my: {methodA: function(a, b, someStr, someBool){return a*b;}}};");
-    /// rt.eval_sync(None, script).ok().expect("script failed");
-    /// let res = rt.invoke_function_sync(None, &["com", "my"], "methodA", vec![7i32.to_js_value_facade(), 5i32.to_js_value_facade(), "abc".to_js_value_facade(), true.to_js_value_facade()]).ok().expect("func failed");
-    /// assert_eq!(res.get_i32(), 35);
-    /// ```
-    #[warn(clippy::type_complexity)]
-    pub fn invoke_function_sync(
+    /// collect a diagnostic bundle of a realm, see [debugdump](crate::jsutils::debugdump)
+    pub async fn debug_dump(
         &self,
-        realm_name: Option<&str>,
-        namespace: &[&str],
-        method_name: &str,
-        args: Vec<JsValueFacade>,
-    ) -> Result<JsValueFacade, JsError> {
-        let movable_namespace: Vec<String> = namespace.iter().map(|s| s.to_string()).collect();
-        let movable_method_name = method_name.to_string();
+        realm_id: &str,
+        options: DumpOptions,
+    ) -> Result<DebugBundle, JsError> {
+        let realm_id = realm_id.to_string();
+        self.add_rt_task_to_event_loop(move |q_js_rt| match q_js_rt.get_realm(realm_id.as_str()) {
+            Some(realm) => Ok(debugdump::dump_q(q_js_rt, realm, &options)),
+            None => Err(JsError::new_string(format!("no such realm: {realm_id}"))),
+        })
+        .await
+    }
 
-        self.loop_realm_sync(realm_name, move |_rt, realm| {
-            let args_adapters: Vec<QuickJsValueAdapter> = args
-                .into_iter()
-                .map(|jsvf| realm.from_js_value_facade(jsvf).expect("conversion failed"))
-                .collect();
+    /// get the imports which were resolved in a realm, see [modulegraph](crate::jsutils::modulegraph)
+    pub async fn module_graph(&self, realm_id: &str) -> Result<ModuleGraph, JsError> {
+        let realm_id = realm_id.to_string();
+        self.add_rt_task_to_event_loop(move |q_js_rt| match q_js_rt.get_realm(realm_id.as_str()) {
+            Some(realm) => Ok(modulegraph::module_graph_q(realm)),
+            None => Err(JsError::new_string(format!("no such realm: {realm_id}"))),
+        })
+        .await
+    }
 
-            let namespace = movable_namespace
-                .iter()
-                .map(|s| s.as_str())
-                .collect::<Vec<&str>>();
+    /// get the timers which were started by scripts in a realm and did not fire or were not cleared yet, see [timers](crate::jsutils::timers)
+    pub async fn pending_timers(&self, realm_id: &str) -> Result<Vec<TimerInfo>, JsError> {
+        let realm_id = realm_id.to_string();
+        self.add_rt_task_to_event_loop(move |q_js_rt| match q_js_rt.get_realm(realm_id.as_str()) {
+            Some(realm) => Ok(timers::pending_timers_q(realm)),
+            None => Err(JsError::new_string(format!("no such realm: {realm_id}"))),
+        })
+        .await
+    }
 
-            let res = realm.invoke_function_by_name(
-                namespace.as_slice(),
-                movable_method_name.as_str(),
-                args_adapters.as_slice(),
-            );
+    /// clear the timers which were started by scripts in a realm, returns the number of cleared timers
+    pub async fn clear_all_timers(&self, realm_id: &str) -> Result<usize, JsError> {
+        let realm_id = realm_id.to_string();
+        self.add_rt_task_to_event_loop(move |q_js_rt| match q_js_rt.get_realm(realm_id.as_str()) {
+            Some(realm) => Ok(timers::clear_all_timers_q(realm)),
+            None => Err(JsError::new_string(format!("no such realm: {realm_id}"))),
+        })
+        .await
+    }
 
-            match res {
-                Ok(jsvr) => realm.to_js_value_facade(&jsvr),
-                Err(e) => Err(e),
-            }
+    /// start collecting the line coverage of the scripts which are evaluated in a realm, see [coverage](crate::features::coverage)
+    pub async fn start_coverage(&self, realm_id: &str) -> Result<(), JsError> {
+        let realm_id = realm_id.to_string();
+        self.add_rt_task_to_event_loop(move |q_js_rt| match q_js_rt.get_realm(realm_id.as_str()) {
+            Some(realm) => coverage::start_coverage_q(realm),
+            None => Err(JsError::new_string(format!("no such realm: {realm_id}"))),
         })
+        .await
     }
 
-    /// invoke a function in the engine asynchronously
-    /// N.B. func_name is not a &str because of <https://github.com/rust-lang/rust/issues/56238> (i think)
+    /// stop collecting coverage in a realm and get the hit counts of the lines which were instrumented
+    pub async fn stop_coverage(&self, realm_id: &str) -> Result<CoverageReport, JsError> {
+        let realm_id = realm_id.to_string();
+        self.add_rt_task_to_event_loop(move |q_js_rt| match q_js_rt.get_realm(realm_id.as_str()) {
+            Some(realm) => coverage::stop_coverage_q(realm),
+            None => Err(JsError::new_string(format!("no such realm: {realm_id}"))),
+        })
+        .await
+    }
+
+    /// check a script for syntax errors without running it, see [validation](crate::jsutils::validation)
+    pub async fn validate_script(&self, script: Script) -> Result<(), Vec<SyntaxErrorInfo>> {
+        self.add_task_to_event_loop(move || validation::validate(script, false))
+            .await
+    }
+
+    /// check a module for syntax errors and unresolvable static imports without running it or loading its imports
+    pub async fn validate_module(&self, script: Script) -> Result<(), Vec<SyntaxErrorInfo>> {
+        self.add_task_to_event_loop(move || validation::validate(script, true))
+            .await
+    }
+
+    /// capture the state of a realm so it can be resumed on another runtime, see [suspend](crate::jsutils::suspend)
+    pub async fn suspend_realm(&self, realm_id: &str) -> Result<SuspendedRealm, JsError> {
+        let realm_id = realm_id.to_string();
+        self.add_task_to_event_loop(move || suspend::suspend_realm(realm_id.as_str()))
+            .await
+    }
+
+    /// create a realm from a [SuspendedRealm], fails when a realm with the same id exists
+    pub async fn resume_realm(&self, suspended: SuspendedRealm) -> Result<(), JsError> {
+        self.add_task_to_event_loop(move || suspend::resume_realm(suspended))
+            .await
+    }
+
+    /// cache an object in a realm and get a handle which keeps it alive until the handle is dropped or consumed
+    ///
+    /// objects which are the result of an eval are already cached, a [JsValueFacade::JsObject] gets a second handle to the same object
+    /// and an object created in rust is converted first
     /// # example
     /// ```rust
+    /// use futures::executor::block_on;
     /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
-    /// use quickjs_runtime::jsutils::Script;
-    /// use quickjs_runtime::values::JsValueConvertable;
+    /// use quickjs_runtime::values::JsValueFacade;
+    /// use std::collections::HashMap;
     /// let rt = QuickJsRuntimeBuilder::new().build();
-    /// let script = Script::new("my_file.es", "this.com = {my: {methodA: function(a, b){return a*b;}}};");
-    /// rt.eval_sync(None, script).ok().expect("script failed");
-    /// rt.invoke_function(None, &["com", "my"], "methodA", vec![7.to_js_value_facade(), 5.to_js_value_facade()]);
+    /// let mut props = HashMap::new();
+    /// props.insert("name".to_string(), JsValueFacade::new_str("test"));
+    /// let handle = block_on(rt.cache_object(None, JsValueFacade::Object { val: props })).expect("cache failed");
+    /// let res = handle.invoke_method_sync("hasOwnProperty", vec![JsValueFacade::new_str("name")]).expect("call failed");
+    /// assert!(res.get_bool());
+    /// handle.consume().expect("consume failed");
     /// ```
     #[allow(clippy::type_complexity)]
-    pub fn invoke_function(
+    pub fn cache_object(
         &self,
         realm_name: Option<&str>,
-        namespace: &[&str],
-        method_name: &str,
-        args: Vec<JsValueFacade>,
-    ) -> Pin<Box<dyn Future<Output = Result<JsValueFacade, JsError>>>> {
-        let movable_namespace: Vec<String> = namespace.iter().map(|s| s.to_string()).collect();
-        let movable_method_name = method_name.to_string();
-
+        value: JsValueFacade,
+    ) -> Pin<Box<dyn Future<Output = Result<CachedJsObjectRef, JsError>>>> {
         self.loop_realm(realm_name, move |_rt, realm| {
-            let args_adapters: Vec<QuickJsValueAdapter> = args
-                .into_iter()
-                .map(|jsvf| realm.from_js_value_facade(jsvf).expect("conversion failed"))
-                .collect();
-
-            let namespace = movable_namespace
-                .iter()
-                .map(|s| s.as_str())
-                .collect::<Vec<&str>>();
-
-            let res = realm.invoke_function_by_name(
-                namespace.as_slice(),
-                movable_method_name.as_str(),
-                args_adapters.as_slice(),
-            );
-
-            match res {
-                Ok(jsvr) => realm.to_js_value_facade(&jsvr),
-                Err(e) => Err(e),
+            let obj = realm.from_js_value_facade(value)?;
+            if !obj.is_object() {
+                return Err(JsError::new_string(format!(
+                    "can not cache a {}, only objects can be cached",
+                    obj.get_js_type()
+                )));
             }
+            Ok(CachedJsObjectRef::new(realm, obj))
         })
     }
 
-    pub fn invoke_function_void(
+    /// watch a property path of a cached object, see [CachedJsObjectRef::watch]
+    pub async fn watch(
         &self,
-        realm_name: Option<&str>,
-        namespace: &[&str],
-        method_name: &str,
-        args: Vec<JsValueFacade>,
-    ) {
-        let movable_namespace: Vec<String> = namespace.iter().map(|s| s.to_string()).collect();
-        let movable_method_name = method_name.to_string();
-
-        self.loop_realm_void(realm_name, move |_rt, realm| {
-            let args_adapters: Vec<QuickJsValueAdapter> = args
-                .into_iter()
-                .map(|jsvf| realm.from_js_value_facade(jsvf).expect("conversion failed"))
-                .collect();
-
-            let namespace = movable_namespace
-                .iter()
-                .map(|s| s.as_str())
-                .collect::<Vec<&str>>();
-
-            let res = realm
-                .invoke_function_by_name(
-                    namespace.as_slice(),
-                    movable_method_name.as_str(),
-                    args_adapters.as_slice(),
-                )
-                .map(|jsvr| realm.to_js_value_facade(&jsvr));
+        object: &CachedJsObjectRef,
+        path: &[&str],
+        options: WatchOptions,
+    ) -> Result<JsWatch, JsError> {
+        object.watch(path, options).await
+    }
 
-            match res {
-                Ok(_) => {
-                    log::trace!(
-                        "js_function_invoke_void succeeded: {}",
-                        movable_method_name.as_str()
-                    );
-                }
-                Err(err) => {
-                    log::trace!(
-                        "js_function_invoke_void failed: {}: {}",
-                        movable_method_name.as_str(),
-                        err
-                    );
-                }
-            }
-        })
+    /// read the named properties of every element of a cached array into typed columns, see [CachedJsArrayRef::extract_columns]
+    pub async fn extract_columns(
+        &self,
+        array: &CachedJsArrayRef,
+        columns: &[(&str, ColumnType)],
+        mismatch_policy: TypeMismatchPolicy,
+    ) -> Result<Columns, JsError> {
+        array.extract_columns(columns, mismatch_policy).await
     }
 }
 
@@ -952,7 +2469,8 @@ lazy_static! {
 #[cfg(test)]
 pub mod tests {
 
-    use crate::facades::QuickJsRuntimeFacade;
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::facades::{CallOptions, QuickJsRuntimeFacade};
     use crate::jsutils::modules::{NativeModuleLoader, ScriptModuleLoader};
     use crate::jsutils::JsError;
     use crate::jsutils::Script;
@@ -964,6 +2482,8 @@ pub mod tests {
     use futures::executor::block_on;
     use log::debug;
     use std::panic;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
     use std::time::Duration;
 
     struct TestNativeModuleLoader {}
@@ -1134,6 +2654,54 @@ pub mod tests {
         }
     }
 
+    #[test]
+    fn test_async_func() {
+        let rt = init_test_rt();
+        rt.set_async_function(&["nl", "my", "utils"], "queryB", |_q_ctx, args| {
+            let a = args.first().map(|a| a.get_i32());
+            async move {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                match a {
+                    Some(a) if a >= 0 => Ok((a * 2).to_js_value_facade()),
+                    _ => Err(JsError::new_str("queryB needs a positive number")),
+                }
+            }
+        })
+        .expect("set_async_function failed");
+
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "test_async_func.es",
+                    r#"
+                    (async () => {
+                        let a = await nl.my.utils.queryB(21);
+                        let b;
+                        try {
+                            await nl.my.utils.queryB(-1);
+                        } catch(e) {
+                            b = (e instanceof Error) + ':' + e.message;
+                        }
+                        return a + '|' + b;
+                    })();
+                    "#,
+                ),
+            )
+            .expect("script failed");
+
+        match res {
+            JsValueFacade::JsPromise { cached_promise } => {
+                let p_res = cached_promise
+                    .get_promise_result_sync()
+                    .expect("promise timed out")
+                    .expect("promise rejected");
+                assert_eq!(p_res.get_str(), "42|true:queryB needs a positive number");
+            }
+            _ => panic!("not a promise"),
+        }
+    }
+
     #[test]
     fn test_eval_sync() {
         let rt = init_test_rt();
@@ -1296,6 +2864,443 @@ pub mod tests {
         let res = block_on(fut);
         assert_eq!(res, 123);
     }
+
+    #[test]
+    fn test_dropped_eval_future() {
+        let errors = Arc::new(Mutex::new(vec![]));
+        let errors2 = errors.clone();
+        let rt = QuickJsRuntimeBuilder::new()
+            .uncaught_error_hook(move |_realm_id, source, err| {
+                errors2
+                    .lock()
+                    .unwrap()
+                    .push(format!("{source}: {}", err.get_message()));
+            })
+            .build();
+        let block_loop = |rt: &QuickJsRuntimeFacade| {
+            rt.add_task_to_event_loop_void(|| std::thread::sleep(Duration::from_millis(200)))
+        };
+
+        // dropped before the job started, the script does not run
+        block_loop(&rt);
+        drop(rt.eval(None, Script::new("dropped.js", "globalThis.ran = true;")));
+        let res = rt
+            .eval_sync(None, Script::new("check.js", "typeof globalThis.ran"))
+            .expect("script failed");
+        assert_eq!(res.get_str(), "undefined");
+
+        // dropped while the job runs, the error goes to the hook
+        let fut = rt.eval(
+            None,
+            Script::new(
+                "dropped.js",
+                "const start = Date.now(); while (Date.now() - start < 300) {} throw Error('lost');",
+            ),
+        );
+        std::thread::sleep(Duration::from_millis(100));
+        drop(fut);
+        // eval_sync is not tied to a future and still returns its error
+        let err = rt
+            .eval_sync(None, Script::new("sync.js", "throw Error('sync');"))
+            .expect_err("script should fail");
+        assert_eq!(err.get_message(), "sync");
+        assert_eq!(*errors.lock().unwrap(), vec!["eval: lost".to_string()]);
+
+        // an awaited future is not affected
+        let res = block_on(rt.eval(None, Script::new("awaited.js", "1 + 2"))).expect("failed");
+        assert_eq!(res.get_i32(), 3);
+
+        // the old behavior for fire-and-forget evals
+        let rt = QuickJsRuntimeBuilder::new()
+            .detach_dropped_futures()
+            .build();
+        block_loop(&rt);
+        drop(rt.eval(None, Script::new("detached.js", "globalThis.ran = true;")));
+        let res = rt
+            .eval_sync(None, Script::new("check.js", "typeof globalThis.ran"))
+            .expect("script failed");
+        assert_eq!(res.get_str(), "boolean");
+    }
+
+    #[test]
+    fn test_invoke_function_with_opts() {
+        let rt = QuickJsRuntimeBuilder::new().build();
+        rt.eval_sync(
+            None,
+            Script::new(
+                "test_invoke_function_with_opts.js",
+                r#"
+                this.counters = {
+                    add: function(...nums) { return this.base + nums.reduce((a, b) => a + b, 0); },
+                    count: function() { return arguments.length; }
+                };
+                this.notAFunction = 1;
+            "#,
+            ),
+        )
+        .expect("script failed");
+        let receiver = match rt
+            .eval_sync(None, Script::new("receiver.js", "({base: 100});"))
+            .expect("script failed")
+        {
+            JsValueFacade::JsObject { cached_object } => cached_object,
+            _ => panic!("not an object"),
+        };
+        let nums = || JsValueFacade::Array {
+            val: vec![1.to_js_value_facade(), 2.to_js_value_facade()],
+        };
+
+        let opts = CallOptions {
+            this: Some(receiver),
+            spread_last_array: true,
+        };
+        let res = block_on(rt.invoke_function_with_opts(
+            None,
+            &["counters"],
+            "add",
+            vec![3.to_js_value_facade(), nums()],
+            opts,
+        ))
+        .expect("call failed");
+        assert_eq!(res.get_i32(), 106);
+
+        // without spreading the array is a single argument
+        let res = block_on(rt.invoke_function_with_opts(
+            None,
+            &["counters"],
+            "count",
+            vec![nums()],
+            CallOptions::default(),
+        ))
+        .expect("call failed");
+        assert_eq!(res.get_i32(), 1);
+
+        let err = block_on(rt.invoke_function_with_opts(
+            None,
+            &["counters"],
+            "missing",
+            vec![],
+            CallOptions::default(),
+        ))
+        .expect_err("missing function was called");
+        assert!(err
+            .get_message()
+            .starts_with("could not look up function counters.missing"));
+
+        let err = block_on(rt.invoke_function_with_opts(
+            None,
+            &[],
+            "notAFunction",
+            vec![],
+            CallOptions::default(),
+        ))
+        .expect_err("number was called");
+        assert!(err
+            .get_message()
+            .starts_with("could not look up function notAFunction"));
+
+        let err = block_on(rt.invoke_function_with_opts(
+            None,
+            &["counters"],
+            "count",
+            vec![1.to_js_value_facade()],
+            CallOptions {
+                this: None,
+                spread_last_array: true,
+            },
+        ))
+        .expect_err("number was spread");
+        assert!(err
+            .get_message()
+            .starts_with("invocation of counters.count failed"));
+
+        // the receiver is an object of another realm
+        let other = match rt
+            .eval_sync(
+                Some("other_realm"),
+                Script::new("receiver.js", "({base: 1});"),
+            )
+            .expect("script failed")
+        {
+            JsValueFacade::JsObject { cached_object } => cached_object,
+            _ => panic!("not an object"),
+        };
+        let err = block_on(rt.invoke_function_with_opts(
+            None,
+            &["counters"],
+            "add",
+            vec![],
+            CallOptions {
+                this: Some(other),
+                spread_last_array: false,
+            },
+        ))
+        .expect_err("receiver of another realm was used");
+        assert!(err
+            .get_message()
+            .starts_with("could not look up the receiver of counters.add"));
+
+        rt.eval_sync(
+            None,
+            Script::new(
+                "thrower.js",
+                "this.thrower = function() { throw new TypeError('nope'); };",
+            ),
+        )
+        .expect("script failed");
+        let err = block_on(rt.invoke_function_with_opts(
+            None,
+            &[],
+            "thrower",
+            vec![],
+            CallOptions::default(),
+        ))
+        .expect_err("thrower did not throw");
+        assert_eq!(err.get_name(), "TypeError");
+        assert_eq!(err.get_message(), "invocation of thrower failed: nope");
+    }
+
+    #[test]
+    fn test_context_routing() {
+        let rt = QuickJsRuntimeBuilder::new().build();
+        rt.create_context("ctx_a").expect("could not create ctx_a");
+        rt.create_context("ctx_b").expect("could not create ctx_b");
+        assert!(rt.create_context("ctx_a").is_err());
+        assert!(rt.has_context("ctx_a"));
+
+        let script = || {
+            Script::new(
+                "routing.js",
+                "globalThis.count = (globalThis.count || 0) + 1; this.inc = (n) => count + n; count;",
+            )
+        };
+        for _ in 0..2 {
+            block_on(rt.eval_ctx("ctx_a", script())).expect("script failed");
+        }
+        let res = block_on(rt.eval_ctx("ctx_b", script())).expect("script failed");
+        // the globals of ctx_a are not visible in ctx_b
+        assert_eq!(res.get_i32(), 1);
+        let res =
+            block_on(rt.invoke_function_ctx("ctx_a", &[], "inc", vec![10.to_js_value_facade()]))
+                .expect("invoke failed");
+        assert_eq!(res.get_i32(), 12);
+
+        block_on(rt.eval_ctx(
+            "ctx_b",
+            Script::new("timer.js", "setTimeout(() => {}, 60000);"),
+        ))
+        .expect("script failed");
+        rt.drop_context("ctx_b").expect("could not drop ctx_b");
+        assert!(!rt.has_context("ctx_b"));
+        // the context is not created again
+        let err = block_on(rt.eval_ctx("ctx_b", script())).expect_err("eval should fail");
+        assert_eq!(err.get_message(), "no such realm: ctx_b");
+        assert!(!rt.has_context("ctx_b"));
+        rt.drop_context("ctx_b").expect("dropping twice failed");
+        assert!(rt.drop_context("__main__").is_err());
+
+        let res = block_on(rt.eval_module_ctx(
+            "ctx_a",
+            Script::new("routing.mjs", "export const x = 1; globalThis.count += 1;"),
+        ));
+        assert!(res.is_ok());
+        let res = block_on(rt.eval_ctx("ctx_a", Script::new("count.js", "count;")))
+            .expect("script failed");
+        assert_eq!(res.get_i32(), 3);
+    }
+
+    #[test]
+    fn test_max_queue_size() {
+        let rt = Arc::new(QuickJsRuntimeBuilder::new().max_queue_size(4).build());
+        let counter = Arc::new(AtomicUsize::new(0));
+        let max_pending = Arc::new(AtomicUsize::new(0));
+        let producers: Vec<_> = (0..8)
+            .map(|_| {
+                let rt = rt.clone();
+                let counter = counter.clone();
+                let max_pending = max_pending.clone();
+                std::thread::spawn(move || {
+                    for i in 0..50 {
+                        let counter = counter.clone();
+                        let job = move || {
+                            counter.fetch_add(1, Ordering::SeqCst);
+                        };
+                        if i % 2 == 0 {
+                            rt.exe_task_in_event_loop(job);
+                        } else {
+                            block_on(rt.add_task_to_event_loop(job));
+                        }
+                        max_pending.fetch_max(rt.pending_jobs(), Ordering::SeqCst);
+                    }
+                })
+            })
+            .collect();
+        for producer in producers {
+            producer.join().expect("producer failed");
+        }
+        assert_eq!(counter.load(Ordering::SeqCst), 400);
+        assert!(max_pending.load(Ordering::SeqCst) <= 4);
+        assert_eq!(rt.pending_jobs(), 0);
+
+        // jobs which are added from the worker thread do not wait for the bound
+        let pending = rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let rti = q_js_rt.get_rti_ref().expect("runtime was dropped");
+            for _ in 0..16 {
+                rti.add_task_to_event_loop_void(|| {});
+            }
+            rti.pending_jobs()
+        });
+        assert!(pending > 4);
+        rt.exe_task_in_event_loop(|| {});
+
+        let facade = rt
+            .eval_sync(None, Script::new("test_max_queue_size.js", "({})"))
+            .expect("script failed");
+        assert!(facade.is_js_object());
+
+        // the queue is full while the blocking jobs are pending
+        let (tx, rx) = std::sync::mpsc::channel::<()>();
+        let rx = Arc::new(Mutex::new(rx));
+        for _ in 0..4 {
+            let rx = rx.clone();
+            rt.add_task_to_event_loop_void(move || {
+                let _ = rx.lock().unwrap().recv();
+            });
+        }
+        let err = rt
+            .try_add_task_to_event_loop(|| {})
+            .err()
+            .expect("queue should be full");
+        assert!(err.is_queue_full());
+        // releasing the value of a dropped facade does not wait for space
+        drop(facade);
+        for _ in 0..4 {
+            tx.send(()).expect("could not send");
+        }
+        rt.exe_task_in_event_loop(|| {});
+        assert_eq!(
+            rt.loop_realm_sync(None, |_rt, realm| realm.cached_object_count()),
+            0
+        );
+        let res = rt
+            .try_add_task_to_event_loop(|| 6 * 7)
+            .ok()
+            .expect("queue should have space");
+        assert_eq!(block_on(res), 42);
+    }
+
+    #[test]
+    fn test_memory_usage_and_gc() {
+        let rt = QuickJsRuntimeBuilder::new().build();
+        rt.eval_sync(
+            None,
+            Script::new(
+                "test_memory_usage.js",
+                "globalThis.big = []; for (let i = 0; i < 100000; i++) { big.push({i, s: 'item' + i}); }",
+            ),
+        )
+        .expect("script failed");
+        rt.gc_sync();
+        let with_array = block_on(rt.memory_usage());
+        assert!(with_array.obj_count > 100000);
+
+        rt.eval_sync(None, Script::new("test_memory_usage.js", "big = null;"))
+            .expect("script failed");
+        block_on(rt.gc());
+        let without_array = block_on(rt.memory_usage());
+        assert!(without_array.memory_used_size < with_array.memory_used_size);
+        assert!(without_array.obj_count < with_array.obj_count - 100000);
+    }
+
+    #[test]
+    fn test_cached_object_handle() {
+        let rt = QuickJsRuntimeBuilder::new().build();
+        let handle = match rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "test_cached_object_handle.js",
+                    "globalThis.big = {items: [1, 2, 3], n: 1, total: function() {return this.items.length + this.n;}}; big;",
+                ),
+            )
+            .expect("script failed")
+        {
+            JsValueFacade::JsObject { cached_object } => cached_object,
+            _ => panic!("not an object"),
+        };
+        let ref_count = |rt: &QuickJsRuntimeFacade| {
+            rt.loop_realm_sync(None, |_rt, realm| {
+                let global = realm.get_global().expect("no global");
+                let big = realm.get_object_property(&global, "big").expect("no big");
+                big.get_ref_count()
+            })
+        };
+        let rc_one_handle = ref_count(&rt);
+
+        rt.eval_sync(None, Script::new("mutate.js", "big.n = 2;"))
+            .expect("script failed");
+        let n = handle
+            .with_obj_sync(|realm, obj| realm.get_object_property(obj, "n").map(|n| n.to_i32()))
+            .expect("with_obj failed")
+            .expect("could not get n");
+        assert_eq!(n, 2);
+        let res = handle
+            .invoke_method_sync("total", vec![])
+            .expect("invoke failed");
+        assert_eq!(res.get_i32(), 5);
+
+        let second = block_on(
+            rt.cache_object(
+                None,
+                rt.eval_sync(None, Script::new("get.js", "big;"))
+                    .expect("script failed"),
+            ),
+        )
+        .expect("cache failed");
+        assert_eq!(ref_count(&rt), rc_one_handle + 1);
+        drop(second);
+        // the release of the dropped handle runs in a job which was added before this one
+        assert_eq!(ref_count(&rt), rc_one_handle);
+        handle.consume().expect("consume failed");
+        assert_eq!(ref_count(&rt), rc_one_handle - 1);
+        assert_eq!(
+            rt.loop_realm_sync(None, |_rt, realm| realm.cached_object_count()),
+            0
+        );
+
+        assert!(block_on(rt.cache_object(None, 1.to_js_value_facade())).is_err());
+
+        // the realm of the handle was dropped
+        let handle = match rt
+            .eval_sync(Some("short_lived"), Script::new("obj.js", "({a: 1});"))
+            .expect("script failed")
+        {
+            JsValueFacade::JsObject { cached_object } => cached_object,
+            _ => panic!("not an object"),
+        };
+        rt.destroy_realm("short_lived").expect("destroy failed");
+        handle
+            .with_obj_sync(|_realm, _obj| ())
+            .expect_err("realm was dropped");
+        handle
+            .invoke_method_sync("toString", vec![])
+            .expect_err("realm was dropped");
+        handle.consume().expect_err("realm was dropped");
+
+        // the runtime of the handle was dropped
+        let handle = match rt
+            .eval_sync(None, Script::new("obj.js", "({a: 1});"))
+            .expect("script failed")
+        {
+            JsValueFacade::JsObject { cached_object } => cached_object,
+            _ => panic!("not an object"),
+        };
+        drop(rt);
+        handle
+            .with_obj_sync(|_realm, _obj| ())
+            .expect_err("runtime was dropped");
+        drop(handle);
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,300 @@
+//! the `quickjs:buffer` module, provides native helpers for binary data
+//!
+//! * `concat(buffers)` concatenates an array of ArrayBuffers and TypedArrays into a new Uint8Array
+//! * `compare(a, b)` compares the bytes of two buffers, returns -1, 0 or 1
+//! * `indexOf(haystack, needle, fromIndex = 0)` finds the byte offset of a buffer or (utf8) string in a buffer, returns -1 when not found
+//!
+//! the helpers work on the bytes in the view of a TypedArray (byteOffset / byteLength), the result of concat always has a fresh buffer,
+//! also when a single buffer is passed, see also [concat_q](crate::quickjs_utils::typedarrays::concat_q)
+//!
+//! the module is only available when it was enabled with [buffer_module](crate::builder::QuickJsRuntimeBuilder::buffer_module)
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::Script;
+//! let rt = QuickJsRuntimeBuilder::new().buffer_module().build();
+//! rt.eval_module_sync(None, Script::new("test_buffer.mes", "import {concat, indexOf} from 'quickjs:buffer';\nconst joined = concat([new Uint8Array([1, 2]), new Uint8Array([3])]);\nif (indexOf(joined, new Uint8Array([2, 3])) !== 1) {throw Error('unexpected index');}")).expect("script failed");
+//! ```
+
+use crate::jsutils::modules::NativeModuleLoader;
+use crate::jsutils::JsError;
+use crate::quickjs_utils::errors::type_error;
+use crate::quickjs_utils::{arrays, functions, primitives, typedarrays};
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsvalueadapter::QuickJsValueAdapter;
+use std::cmp::Ordering;
+
+pub const MODULE_NAME: &str = "quickjs:buffer";
+
+/// the NativeModuleLoader which provides the `quickjs:buffer` module
+pub struct BufferModuleLoader {}
+
+impl NativeModuleLoader for BufferModuleLoader {
+    fn has_module(&self, _realm: &QuickJsRealmAdapter, module_name: &str) -> bool {
+        module_name.eq(MODULE_NAME)
+    }
+
+    fn get_module_export_names(
+        &self,
+        _realm: &QuickJsRealmAdapter,
+        _module_name: &str,
+    ) -> Vec<&str> {
+        vec!["concat", "compare", "indexOf"]
+    }
+
+    fn get_module_exports(
+        &self,
+        realm: &QuickJsRealmAdapter,
+        _module_name: &str,
+    ) -> Vec<(&str, QuickJsValueAdapter)> {
+        vec![
+            ("concat", new_buffer_function(realm, "concat", js_concat, 1)),
+            (
+                "compare",
+                new_buffer_function(realm, "compare", js_compare, 2),
+            ),
+            (
+                "indexOf",
+                new_buffer_function(realm, "indexOf", js_index_of, 2),
+            ),
+        ]
+    }
+}
+
+type BufferFunction =
+    fn(&QuickJsRealmAdapter, &[QuickJsValueAdapter]) -> Result<QuickJsValueAdapter, JsError>;
+
+fn new_buffer_function(
+    realm: &QuickJsRealmAdapter,
+    name: &str,
+    func: BufferFunction,
+    arg_count: u32,
+) -> QuickJsValueAdapter {
+    functions::new_function_q(
+        realm,
+        name,
+        move |realm, _this, args| func(realm, args),
+        arg_count,
+    )
+    .expect("could not create buffer function")
+}
+
+fn is_buffer(realm: &QuickJsRealmAdapter, value: &QuickJsValueAdapter) -> bool {
+    typedarrays::is_array_buffer_q(realm, value) || typedarrays::is_typed_array_q(realm, value)
+}
+
+fn get_buffer_arg<'a>(
+    realm: &QuickJsRealmAdapter,
+    func_name: &str,
+    args: &'a [QuickJsValueAdapter],
+    index: usize,
+) -> Result<&'a QuickJsValueAdapter, JsError> {
+    match args.get(index) {
+        Some(arg) if is_buffer(realm, arg) => Ok(arg),
+        _ => Err(type_error(format!(
+            "argument {index} of {func_name} must be an ArrayBuffer or TypedArray"
+        ))),
+    }
+}
+
+/// find the first occurrence of needle in haystack, an empty needle is found at 0
+pub fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    let (first, rest) = match needle.split_first() {
+        Some(split) => split,
+        None => return Some(0),
+    };
+    if needle.len() > haystack.len() {
+        return None;
+    }
+    let last_start = haystack.len() - needle.len();
+    let mut pos = 0;
+    while pos <= last_start {
+        // skip to the next candidate with a fast scan for the first byte
+        match haystack[pos..=last_start].iter().position(|b| b == first) {
+            Some(offset) => pos += offset,
+            None => return None,
+        }
+        if haystack[pos + 1..pos + needle.len()] == *rest {
+            return Some(pos);
+        }
+        pos += 1;
+    }
+    None
+}
+
+fn js_concat(
+    realm: &QuickJsRealmAdapter,
+    args: &[QuickJsValueAdapter],
+) -> Result<QuickJsValueAdapter, JsError> {
+    let list = match args.first() {
+        Some(arg) if arg.is_array() => arg,
+        _ => {
+            return Err(type_error(
+                "concat requires an array of ArrayBuffers or TypedArrays".to_string(),
+            ))
+        }
+    };
+    let len = arrays::get_length_q(realm, list)?;
+    let mut buffers = Vec::with_capacity(len as usize);
+    for index in 0..len {
+        let element = arrays::get_element_q(realm, list, index)?;
+        if !is_buffer(realm, &element) {
+            return Err(type_error(format!(
+                "element {index} of concat is not an ArrayBuffer or TypedArray"
+            )));
+        }
+        buffers.push(element);
+    }
+    typedarrays::concat_q(realm, &buffers.iter().collect::<Vec<_>>())
+}
+
+fn js_compare(
+    realm: &QuickJsRealmAdapter,
+    args: &[QuickJsValueAdapter],
+) -> Result<QuickJsValueAdapter, JsError> {
+    let a = get_buffer_arg(realm, "compare", args, 0)?;
+    let b = get_buffer_arg(realm, "compare", args, 1)?;
+    // no script runs while a is borrowed so the nested borrow of b is safe
+    let ordering = typedarrays::with_buffer_bytes_q(realm, a, |a_bytes| {
+        typedarrays::with_buffer_bytes_q(realm, b, |b_bytes| a_bytes.cmp(b_bytes))
+    })??;
+    Ok(primitives::from_i32(match ordering {
+        Ordering::Less => -1,
+        Ordering::Equal => 0,
+        Ordering::Greater => 1,
+    }))
+}
+
+fn js_index_of(
+    realm: &QuickJsRealmAdapter,
+    args: &[QuickJsValueAdapter],
+) -> Result<QuickJsValueAdapter, JsError> {
+    let haystack = get_buffer_arg(realm, "indexOf", args, 0)?;
+    let from_index = match args.get(2) {
+        Some(arg) if arg.is_i32() => arg.to_i32().max(0) as usize,
+        Some(arg) if arg.is_f64() => arg.to_f64().max(0.0) as usize,
+        Some(arg) if arg.is_null_or_undefined() => 0,
+        None => 0,
+        Some(_) => return Err(type_error("fromIndex must be a number".to_string())),
+    };
+    let search = |needle: &[u8]| {
+        typedarrays::with_buffer_bytes_q(realm, haystack, |bytes| {
+            if from_index > bytes.len() {
+                return None;
+            }
+            find_bytes(&bytes[from_index..], needle).map(|pos| pos + from_index)
+        })
+    };
+    let found = match args.get(1) {
+        Some(arg) if arg.is_string() => search(primitives::to_string_q(realm, arg)?.as_bytes())?,
+        Some(arg) if is_buffer(realm, arg) => {
+            typedarrays::with_buffer_bytes_q(realm, arg, search)??
+        }
+        _ => {
+            return Err(type_error(
+                "the needle of indexOf must be a string, ArrayBuffer or TypedArray".to_string(),
+            ))
+        }
+    };
+    Ok(primitives::from_i32(match found {
+        Some(pos) => pos as i32,
+        None => -1,
+    }))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::features::buffer::find_bytes;
+    use crate::jsutils::Script;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_find_bytes() {
+        assert_eq!(find_bytes(b"hello", b""), Some(0));
+        assert_eq!(find_bytes(b"hello", b"llo"), Some(2));
+        assert_eq!(find_bytes(b"hello", b"lo!"), None);
+        assert_eq!(find_bytes(b"aaab", b"aab"), Some(1));
+        assert_eq!(find_bytes(b"ab", b"abc"), None);
+        assert_eq!(find_bytes(b"", b"a"), None);
+    }
+
+    #[test]
+    fn test_buffer_module() {
+        let rt = QuickJsRuntimeBuilder::new().buffer_module().build();
+        rt.eval_module_sync(
+            None,
+            Script::new(
+                "test_buffer.mes",
+                r#"
+                import {concat, compare, indexOf} from 'quickjs:buffer';
+                globalThis.bufferResults = [];
+                const backing = new Uint8Array([9, 1, 2, 3, 9, 9]).buffer;
+                const view = new Uint8Array(backing, 1, 3);
+                const joined = concat([view, new Uint8Array([4]).buffer, new Uint16Array([0x0605])]);
+                bufferResults.push(Array.from(joined).join(','));
+                bufferResults.push(joined.buffer !== backing && joined.byteOffset === 0);
+                bufferResults.push(compare(view, new Uint8Array([1, 2, 3])));
+                bufferResults.push(compare(view, new Uint8Array([1, 2])));
+                bufferResults.push(compare(new Uint8Array([1, 2]), view));
+                bufferResults.push(indexOf(joined, new Uint8Array([3, 4])));
+                bufferResults.push(indexOf(view, new Uint8Array([9])));
+                bufferResults.push(indexOf(new Uint8Array([104, 105, 104, 105]), 'hi', 1));
+                bufferResults.push(indexOf(joined, new Uint8Array([1]), 10));
+                try { concat([view, 'nope']); } catch (e) { bufferResults.push(e.name); }
+                "#,
+            ),
+        )
+        .expect("script failed");
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new("test_buffer.js", "bufferResults.join('|')"),
+            )
+            .expect("script failed");
+        assert_eq!(res.get_str(), "1,2,3,4,5,6|true|0|1|-1|2|-1|2|-1|TypeError");
+    }
+
+    /// compares indexOf with a search loop in script
+    /// run with cargo test --release bench_index_of -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_index_of() {
+        let rt = QuickJsRuntimeBuilder::new().buffer_module().build();
+        rt.eval_module_sync(
+            None,
+            Script::new(
+                "bench_buffer.mes",
+                r#"
+                import {indexOf} from 'quickjs:buffer';
+                const haystack = new Uint8Array(4 * 1024 * 1024).map((_, i) => i % 251);
+                haystack.set([1, 2, 3, 4, 5, 6, 7, 8], haystack.length - 8);
+                const needle = new Uint8Array([1, 2, 3, 4, 5, 6, 7, 8]);
+                globalThis.nativeSearch = () => indexOf(haystack, needle);
+                globalThis.scriptSearch = () => {
+                    outer: for (let i = 0; i <= haystack.length - needle.length; i++) {
+                        for (let j = 0; j < needle.length; j++) {
+                            if (haystack[i + j] !== needle[j]) continue outer;
+                        }
+                        return i;
+                    }
+                    return -1;
+                };
+                "#,
+            ),
+        )
+        .expect("script failed");
+        let time = |code: &str| -> (i32, Duration) {
+            let start = Instant::now();
+            let res = rt
+                .eval_sync(None, Script::new("bench_buffer.js", code))
+                .expect("script failed");
+            (res.get_i32(), start.elapsed())
+        };
+        let (native_pos, native_duration) = time("nativeSearch()");
+        let (script_pos, script_duration) = time("scriptSearch()");
+        assert_eq!(native_pos, script_pos);
+        println!("indexOf: {native_duration:?}, script loop: {script_duration:?}");
+        assert!(native_duration * 10 < script_duration);
+    }
+}
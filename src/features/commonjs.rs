@@ -0,0 +1,273 @@
+//! a CommonJS style `require()` for scripts, see [enable_commonjs](crate::builder::QuickJsRuntimeBuilder::enable_commonjs)
+//!
+//! the global `require(specifier)` resolves the specifier against the path of the calling script with a [ScriptModuleLoader] and runs the code of the module
+//! in a function scope with `exports`, `require`, `module`, `__filename` and `__dirname`, the `require` of a module resolves against the path of that module
+//!
+//! the `module.exports` of a module is cached per realm by absolute path, so a module runs once per realm no matter how often it is required
+//! a module which is required while it is still running (a circular require) returns the exports it filled so far
+//!
+//! requiring a module which the loader can not resolve throws an Error with code `MODULE_NOT_FOUND`, a module which throws while running is removed from the cache
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::modules::ScriptModuleLoader;
+//! use quickjs_runtime::jsutils::Script;
+//! use quickjs_runtime::quickjsrealmadapter::QuickJsRealmAdapter;
+//!
+//! struct UtilsLoader {}
+//!
+//! impl ScriptModuleLoader for UtilsLoader {
+//!     fn normalize_path(&self, _realm: &QuickJsRealmAdapter, _ref_path: &str, path: &str) -> Option<String> {
+//!         (path == "./left-pad.js").then(|| "utils/left-pad.js".to_string())
+//!     }
+//!
+//!     fn load_module(&self, _realm: &QuickJsRealmAdapter, _absolute_path: &str) -> String {
+//!         "module.exports = (s, len) => s.padStart(len);".to_string()
+//!     }
+//! }
+//!
+//! let rt = QuickJsRuntimeBuilder::new().enable_commonjs(UtilsLoader {}).build();
+//! let res = rt.eval_sync(None, Script::new("main.js", "require('./left-pad.js')('a', 3)")).expect("script failed");
+//! assert_eq!(res.get_str(), "  a");
+//! ```
+
+use crate::features::WRITABLE_GLOBAL;
+use crate::jsutils::modules::ScriptModuleLoader;
+use crate::jsutils::{JsError, Script};
+use crate::quickjs_utils::{errors, functions, get_global_q, objects, primitives};
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use crate::quickjsvalueadapter::QuickJsValueAdapter;
+use std::rc::Rc;
+
+/// the code of `MODULE_NOT_FOUND` errors
+pub const MODULE_NOT_FOUND: &str = "MODULE_NOT_FOUND";
+
+/// install the global `require` function in every realm
+pub(crate) fn init<L: ScriptModuleLoader + 'static>(
+    q_js_rt: &QuickJsRuntimeAdapter,
+    loader: L,
+) -> Result<(), JsError> {
+    let loader: Rc<dyn ScriptModuleLoader> = Rc::new(loader);
+    q_js_rt.add_context_init_hook(move |_q_js_rt, realm| {
+        let loader = loader.clone();
+        let require_func = functions::new_function_q(
+            realm,
+            "require",
+            move |realm, _this, args| {
+                // the global require resolves against the script which calls it
+                let base = realm.get_script_or_module_name()?;
+                require(realm, &loader, base.as_str(), args)
+            },
+            1,
+        )?;
+        let global = get_global_q(realm);
+        objects::set_property2_q(realm, &global, "require", &require_func, WRITABLE_GLOBAL)
+    })
+}
+
+fn require(
+    realm: &QuickJsRealmAdapter,
+    loader: &Rc<dyn ScriptModuleLoader>,
+    base: &str,
+    args: &[QuickJsValueAdapter],
+) -> Result<QuickJsValueAdapter, JsError> {
+    if args.is_empty() || !args[0].is_string() {
+        return Err(JsError::new_str("require expects a specifier string"));
+    }
+    let specifier = primitives::to_string_q(realm, &args[0])?;
+    let Some(path) = loader.normalize_path(realm, base, specifier.as_str()) else {
+        return throw_module_not_found(realm, specifier.as_str());
+    };
+    let cached = realm
+        .commonjs_modules
+        .borrow()
+        .get(&path)
+        .map(QuickJsValueAdapter::dup);
+    let module = match cached {
+        Some(module) => module,
+        None => load(realm, loader, path.as_str())?,
+    };
+    realm.get_object_property(&module, "exports")
+}
+
+// run the code of a module and cache its module object
+fn load(
+    realm: &QuickJsRealmAdapter,
+    loader: &Rc<dyn ScriptModuleLoader>,
+    path: &str,
+) -> Result<QuickJsValueAdapter, JsError> {
+    let code = loader.try_load_module(realm, path)?;
+
+    let module = realm.create_object()?;
+    let exports = realm.create_object()?;
+    realm.set_object_property(&module, "exports", &exports)?;
+    realm.set_object_property(&module, "id", &realm.create_string(path)?)?;
+    realm.set_object_property(&module, "loaded", &realm.create_boolean(false)?)?;
+    // cached before it runs so a circular require gets the exports which were filled so far
+    realm
+        .commonjs_modules
+        .borrow_mut()
+        .insert(path.to_string(), module.dup());
+
+    let res = run_module(realm, loader, path, code.as_str(), &module, &exports);
+    if let Err(err) = res {
+        realm.commonjs_modules.borrow_mut().remove(path);
+        return Err(err);
+    }
+    realm.set_object_property(&module, "loaded", &realm.create_boolean(true)?)?;
+    Ok(module)
+}
+
+fn run_module(
+    realm: &QuickJsRealmAdapter,
+    loader: &Rc<dyn ScriptModuleLoader>,
+    path: &str,
+    code: &str,
+    module: &QuickJsValueAdapter,
+    exports: &QuickJsValueAdapter,
+) -> Result<(), JsError> {
+    // the code starts on the first line of the wrapper so the line numbers of errors match the file
+    let wrapped =
+        format!("(function (exports, require, module, __filename, __dirname) {{{code}\n}})");
+    let module_func = realm.eval(Script::new(path, wrapped.as_str()))?;
+
+    let module_loader = loader.clone();
+    let module_path = path.to_string();
+    let module_require = functions::new_function_q(
+        realm,
+        "require",
+        move |realm, _this, args| require(realm, &module_loader, module_path.as_str(), args),
+        1,
+    )?;
+    let dirname = path.rfind('/').map(|index| &path[..index]).unwrap_or("");
+    functions::call_function_q(
+        realm,
+        &module_func,
+        &[
+            exports.dup(),
+            module_require,
+            module.dup(),
+            realm.create_string(path)?,
+            realm.create_string(dirname)?,
+        ],
+        Some(exports),
+    )?;
+    Ok(())
+}
+
+// throw an Error with a code, a JsError returned by a native function would lose that code
+fn throw_module_not_found(
+    realm: &QuickJsRealmAdapter,
+    specifier: &str,
+) -> Result<QuickJsValueAdapter, JsError> {
+    let message = format!("Cannot find module '{specifier}'");
+    let err = unsafe { errors::new_error(realm.context, "Error", message.as_str(), "")? };
+    realm.set_object_property(&err, "code", &realm.create_string(MODULE_NOT_FOUND)?)?;
+    let thrown = unsafe { errors::throw(realm.context, err) };
+    Ok(QuickJsValueAdapter::new(
+        realm.context,
+        thrown,
+        false,
+        false,
+        "require MODULE_NOT_FOUND",
+    ))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::modules::ScriptModuleLoader;
+    use crate::jsutils::Script;
+    use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+
+    struct CjsLoader {}
+
+    impl ScriptModuleLoader for CjsLoader {
+        fn normalize_path(
+            &self,
+            _realm: &QuickJsRealmAdapter,
+            ref_path: &str,
+            path: &str,
+        ) -> Option<String> {
+            let absolute_path = match path.strip_prefix("./") {
+                Some(relative) => match ref_path.rfind('/') {
+                    Some(index) => format!("{}/{relative}", &ref_path[..index]),
+                    None => relative.to_string(),
+                },
+                None => path.to_string(),
+            };
+            match absolute_path.as_str() {
+                "lib/a.js" | "lib/b.js" | "lib/counter.js" => Some(absolute_path),
+                _ => None,
+            }
+        }
+
+        fn load_module(&self, _realm: &QuickJsRealmAdapter, absolute_path: &str) -> String {
+            match absolute_path {
+                "lib/a.js" => {
+                    "exports.name = 'a';\nconst b = require('./b.js');\nexports.fromB = b.name + ':' + b.aNameSeen;"
+                }
+                "lib/b.js" => {
+                    "const a = require('./a.js');\nexports.name = 'b';\nexports.aNameSeen = a.name;\nexports.aDone = a.fromB !== undefined;"
+                }
+                _ => {
+                    "globalThis.counterRuns = (globalThis.counterRuns || 0) + 1;\nmodule.exports = {runs: globalThis.counterRuns, file: __filename, dir: __dirname};"
+                }
+            }
+            .to_string()
+        }
+    }
+
+    #[test]
+    fn test_require_cycle() {
+        let rt = QuickJsRuntimeBuilder::new()
+            .enable_commonjs(CjsLoader {})
+            .build();
+
+        // b requires a while a is still running and sees the exports a filled so far
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "lib/main.js",
+                    "const a = require('./a.js'); const b = require('./b.js'); [a.fromB, b.aDone].join(',')",
+                ),
+            )
+            .expect("script failed");
+        assert_eq!(res.get_str(), "b:a,false");
+    }
+
+    #[test]
+    fn test_require_cache() {
+        let rt = QuickJsRuntimeBuilder::new()
+            .enable_commonjs(CjsLoader {})
+            .build();
+
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "lib/main.js",
+                    "const c1 = require('./counter.js'); const c2 = require('./counter.js'); [c1 === c2, c1.runs, globalThis.counterRuns, c1.file, c1.dir].join(',')",
+                ),
+            )
+            .expect("script failed");
+        assert_eq!(res.get_str(), "true,1,1,lib/counter.js,lib");
+
+        // a missing module throws a catchable error with a code
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "lib/main.js",
+                    "let r; try {require('./missing.js');} catch(e) {r = [e instanceof Error, e.code, e.message].join(',');} r",
+                ),
+            )
+            .expect("script failed");
+        assert_eq!(
+            res.get_str(),
+            "true,MODULE_NOT_FOUND,Cannot find module './missing.js'"
+        );
+    }
+}
@@ -6,10 +6,19 @@
 //! * console.error()
 //! * console.warning()
 //! * console.trace()
+//! * console.debug()
+//! * console.assert()
+//! * console.time() and console.timeEnd(), which log the elapsed time in ms
+//! * console.count()
+//! * console.group() and console.groupEnd(), which indent the output in between
+//! * console.table(), which logs the rows of an array or object as a text table
 //!
 //! The methods use rust's log crate to output messages. e.g. console.info() uses the log::info!() macro
 //! so the console messages should appear in the log you initialized from rust
 //!
+//! a [console_printer](crate::builder::QuickJsRuntimeBuilder::console_printer) replaces the log crate, it gets the args as
+//! [JsValueFacade](crate::values::JsValueFacade)s instead of a formatted line
+//!
 //! All methods accept a single message string and optional substitution values
 //!
 //! e.g.
@@ -41,18 +50,37 @@
 //! which will result in a log entry like
 //! ```[00:00:00.012] (7f44e7d24700) INFO   the quick brown fox jumped over 32 fences with a accuracy of 0.51```
 
+use crate::features::testing;
+use crate::jsutils::asyncstacks;
+use crate::jsutils::debugdump;
+use crate::jsutils::redaction;
 use crate::jsutils::{JsError, JsValueType};
 use crate::quickjs_utils;
 use crate::quickjs_utils::functions::call_to_string;
 use crate::quickjs_utils::json::stringify;
-use crate::quickjs_utils::{functions, json, parse_args, primitives};
+use crate::quickjs_utils::{errors, functions, json, objects, parse_args, primitives};
 use crate::quickjsrealmadapter::QuickJsRealmAdapter;
 use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
 use crate::quickjsvalueadapter::QuickJsValueAdapter;
 use crate::reflection::Proxy;
+use crate::values::JsValueFacade;
 use libquickjs_sys as q;
-use log::LevelFilter;
+use log::Level;
+use std::collections::HashMap;
 use std::str::FromStr;
+use std::time::Instant;
+
+pub use crate::builder::ConsolePrinter;
+
+const GROUP_INDENT: &str = "  ";
+
+/// the state of console in a realm
+#[derive(Default)]
+pub(crate) struct ConsoleState {
+    timers: HashMap<String, Instant>,
+    counts: HashMap<String, u64>,
+    group_depth: usize,
+}
 
 pub fn init(q_js_rt: &QuickJsRuntimeAdapter) -> Result<(), JsError> {
     q_js_rt.add_context_init_hook(|_q_js_rt, q_ctx| init_ctx(q_ctx))
@@ -66,8 +94,14 @@ pub(crate) fn init_ctx(q_ctx: &QuickJsRealmAdapter) -> Result<(), JsError> {
         .static_native_method("info", Some(console_info))
         .static_native_method("warn", Some(console_warn))
         .static_native_method("error", Some(console_error))
-        //.static_native_method("assert", Some(console_assert)) // todo
+        .static_native_method("assert", Some(console_assert))
         .static_native_method("debug", Some(console_debug))
+        .static_native_method("time", Some(console_time))
+        .static_native_method("timeEnd", Some(console_time_end))
+        .static_native_method("count", Some(console_count))
+        .static_native_method("group", Some(console_group))
+        .static_native_method("groupEnd", Some(console_group_end))
+        .static_native_method("table", Some(console_table))
         .install(q_ctx, true)
         .map(|_| {})
 }
@@ -177,26 +211,53 @@ unsafe fn stringify_log_obj(ctx: *mut q::JSContext, arg: &QuickJsValueAdapter) -
     }
 }
 
+fn indent_lines(text: &str, indent: &str) -> String {
+    if indent.is_empty() {
+        text.to_string()
+    } else {
+        format!(
+            "{indent}{}",
+            text.replace('\n', format!("\n{indent}").as_str())
+        )
+    }
+}
+
+// the realm and script of a console line
+fn line_prefix(realm: &QuickJsRealmAdapter) -> String {
+    let mut prefix = String::new();
+    prefix.push_str("JS_REALM:[");
+    prefix.push_str(realm.id.as_str());
+    prefix.push_str("][");
+    if let Ok(script_or_module_name) = quickjs_utils::get_script_or_module_name_q(realm) {
+        prefix.push_str(script_or_module_name.as_str());
+    }
+    prefix.push_str("]: ");
+    prefix
+}
+
+unsafe fn parse_line(
+    ctx: *mut q::JSContext,
+    args: Vec<QuickJsValueAdapter>,
+    indent: &str,
+) -> String {
+    let prefix = QuickJsRealmAdapter::with_context(ctx, line_prefix);
+    let message = format_message(ctx, args);
+    format!("{prefix}{}", indent_lines(message.as_str(), indent))
+}
+
 #[allow(clippy::or_fun_call)]
-unsafe fn parse_line(ctx: *mut q::JSContext, args: Vec<QuickJsValueAdapter>) -> String {
+unsafe fn format_message(ctx: *mut q::JSContext, args: Vec<QuickJsValueAdapter>) -> String {
     let mut output = String::new();
 
-    output.push_str("JS_REALM:");
-    QuickJsRealmAdapter::with_context(ctx, |realm| {
-        output.push('[');
-        output.push_str(realm.id.as_str());
-        output.push_str("][");
-        if let Ok(script_or_module_name) = quickjs_utils::get_script_or_module_name_q(realm) {
-            output.push_str(script_or_module_name.as_str());
-        }
-        output.push_str("]: ");
-    });
-
     if args.is_empty() {
-        return output;
+        return redaction::redact_string(output);
     }
 
     let message = match &args[0].get_js_type() {
+        // with async stack traces the stack of an error is logged as well
+        JsValueType::Error if asyncstacks::is_enabled() => {
+            format!("{}", errors::error_to_js_error(ctx, &args[0]))
+        }
         JsValueType::Object => stringify_log_obj(ctx, &args[0]),
         JsValueType::Function => stringify_log_obj(ctx, &args[0]),
         JsValueType::Array => stringify_log_obj(ctx, &args[0]),
@@ -242,6 +303,9 @@ unsafe fn parse_line(ctx: *mut q::JSContext, args: Vec<QuickJsValueAdapter>) ->
         // add args which we're not filled in str
         output.push(' ');
         let tail_arg = match arg.get_js_type() {
+            JsValueType::Error if asyncstacks::is_enabled() => {
+                format!("{}", errors::error_to_js_error(ctx, arg))
+            }
             JsValueType::Object => stringify_log_obj(ctx, arg),
             JsValueType::Function => stringify_log_obj(ctx, arg),
             JsValueType::Array => stringify_log_obj(ctx, arg),
@@ -250,7 +314,88 @@ unsafe fn parse_line(ctx: *mut q::JSContext, args: Vec<QuickJsValueAdapter>) ->
         output.push_str(tail_arg.as_str());
     }
 
-    output
+    redaction::redact_string(output)
+}
+
+/// log a console line, the line is only formatted when its level is enabled or when it is recorded for a debug dump
+///
+/// when a [console_printer](crate::builder::QuickJsRuntimeBuilder::console_printer) is set the args are passed to it instead of the log crate
+unsafe fn log_line(
+    ctx: *mut q::JSContext,
+    argc: ::std::os::raw::c_int,
+    argv: *mut q::JSValue,
+    level: Level,
+) {
+    log_args(ctx, parse_args(ctx, argc, argv), level)
+}
+
+unsafe fn log_args(ctx: *mut q::JSContext, args: Vec<QuickJsValueAdapter>, level: Level) {
+    let printer = QuickJsRuntimeAdapter::do_with(|q_js_rt| q_js_rt.console_printer.clone());
+    let recording = debugdump::is_recording();
+    let capturing = testing::is_capturing(QuickJsRealmAdapter::get_id(ctx));
+    let indent = QuickJsRealmAdapter::with_context(ctx, |realm| {
+        GROUP_INDENT.repeat(realm.console_state.borrow().group_depth)
+    });
+    if let Some(printer) = printer {
+        QuickJsRealmAdapter::with_context(ctx, |realm| {
+            if recording || capturing {
+                let line = parse_line(
+                    ctx,
+                    args.iter().map(QuickJsValueAdapter::dup).collect(),
+                    indent.as_str(),
+                );
+                if recording {
+                    debugdump::record_console(realm, level, line.as_str());
+                }
+                if capturing {
+                    testing::capture_console(realm.id.as_str(), line.as_str());
+                }
+            }
+            let mut facades = args
+                .into_iter()
+                .map(|arg| to_printer_facade(realm, arg))
+                .collect::<Vec<_>>();
+            if !indent.is_empty() {
+                match facades.first_mut() {
+                    Some(first @ JsValueFacade::String { .. }) => {
+                        *first = JsValueFacade::new_string(indent_lines(
+                            first.get_str(),
+                            indent.as_str(),
+                        ));
+                    }
+                    _ => facades.insert(0, JsValueFacade::new_string(indent.clone())),
+                }
+            }
+            printer(level, realm.id.as_str(), facades.as_slice());
+        });
+    } else if recording || capturing || log::max_level() >= level {
+        let line = parse_line(ctx, args, indent.as_str());
+        if recording || capturing {
+            QuickJsRealmAdapter::with_context(ctx, |realm| {
+                if recording {
+                    debugdump::record_console(realm, level, line.as_str());
+                }
+                if capturing {
+                    testing::capture_console(realm.id.as_str(), line.as_str());
+                }
+            });
+        }
+        log::log!(level, "{}", line);
+    }
+}
+
+// strings are redacted, values which can not be converted are passed as their string representation
+fn to_printer_facade(realm: &QuickJsRealmAdapter, arg: QuickJsValueAdapter) -> JsValueFacade {
+    if arg.is_string() {
+        let s = primitives::to_string_q(realm, &arg).unwrap_or_default();
+        return JsValueFacade::new_string(redaction::redact_string(s));
+    }
+    match realm.to_js_value_facade(&arg) {
+        Ok(facade) => facade,
+        Err(_) => JsValueFacade::new_string(redaction::redact_string(
+            functions::call_to_string_q(realm, &arg).unwrap_or_default(),
+        )),
+    }
 }
 
 unsafe extern "C" fn console_log(
@@ -259,10 +404,7 @@ unsafe extern "C" fn console_log(
     argc: ::std::os::raw::c_int,
     argv: *mut q::JSValue,
 ) -> q::JSValue {
-    if log::max_level() >= LevelFilter::Info {
-        let args = parse_args(ctx, argc, argv);
-        log::info!("{}", parse_line(ctx, args));
-    }
+    log_line(ctx, argc, argv, Level::Info);
     quickjs_utils::new_null()
 }
 
@@ -272,10 +414,7 @@ unsafe extern "C" fn console_trace(
     argc: ::std::os::raw::c_int,
     argv: *mut q::JSValue,
 ) -> q::JSValue {
-    if log::max_level() >= LevelFilter::Trace {
-        let args = parse_args(ctx, argc, argv);
-        log::trace!("{}", parse_line(ctx, args));
-    }
+    log_line(ctx, argc, argv, Level::Trace);
     quickjs_utils::new_null()
 }
 
@@ -285,10 +424,7 @@ unsafe extern "C" fn console_debug(
     argc: ::std::os::raw::c_int,
     argv: *mut q::JSValue,
 ) -> q::JSValue {
-    if log::max_level() >= LevelFilter::Debug {
-        let args = parse_args(ctx, argc, argv);
-        log::debug!("{}", parse_line(ctx, args));
-    }
+    log_line(ctx, argc, argv, Level::Debug);
     quickjs_utils::new_null()
 }
 
@@ -298,10 +434,7 @@ unsafe extern "C" fn console_info(
     argc: ::std::os::raw::c_int,
     argv: *mut q::JSValue,
 ) -> q::JSValue {
-    if log::max_level() >= LevelFilter::Info {
-        let args = parse_args(ctx, argc, argv);
-        log::info!("{}", parse_line(ctx, args));
-    }
+    log_line(ctx, argc, argv, Level::Info);
     quickjs_utils::new_null()
 }
 
@@ -311,10 +444,7 @@ unsafe extern "C" fn console_warn(
     argc: ::std::os::raw::c_int,
     argv: *mut q::JSValue,
 ) -> q::JSValue {
-    if log::max_level() >= LevelFilter::Warn {
-        let args = parse_args(ctx, argc, argv);
-        log::warn!("{}", parse_line(ctx, args));
-    }
+    log_line(ctx, argc, argv, Level::Warn);
     quickjs_utils::new_null()
 }
 
@@ -324,17 +454,262 @@ unsafe extern "C" fn console_error(
     argc: ::std::os::raw::c_int,
     argv: *mut q::JSValue,
 ) -> q::JSValue {
-    if log::max_level() >= LevelFilter::Error {
-        let args = parse_args(ctx, argc, argv);
-        log::error!("{}", parse_line(ctx, args));
+    log_line(ctx, argc, argv, Level::Error);
+    quickjs_utils::new_null()
+}
+
+unsafe extern "C" fn console_assert(
+    ctx: *mut q::JSContext,
+    _this_val: q::JSValue,
+    argc: ::std::os::raw::c_int,
+    argv: *mut q::JSValue,
+) -> q::JSValue {
+    let mut args = parse_args(ctx, argc, argv);
+    let holds = !args.is_empty() && q::JS_ToBool(ctx, *args[0].borrow_value()) != 0;
+    if !holds {
+        // replace the condition with a message, like browsers do
+        let message = if args.len() > 1 {
+            "Assertion failed:"
+        } else {
+            "Assertion failed"
+        };
+        if args.is_empty() {
+            args.push(primitives::from_string(ctx, message).expect("could not create string"));
+        } else {
+            args[0] = primitives::from_string(ctx, message).expect("could not create string");
+        }
+        log_args(ctx, args, Level::Error);
     }
     quickjs_utils::new_null()
 }
 
+// the label of console.time and console.count, "default" when no label was passed
+unsafe fn get_label(ctx: *mut q::JSContext, args: &[QuickJsValueAdapter]) -> String {
+    match args.first() {
+        Some(arg) if !arg.is_undefined() => call_to_string(ctx, arg).unwrap_or_default(),
+        _ => "default".to_string(),
+    }
+}
+
+/// log a message which was created in rust, e.g. the elapsed time of console.timeEnd
+unsafe fn log_message(ctx: *mut q::JSContext, message: &str, level: Level) {
+    match primitives::from_string(ctx, message) {
+        Ok(value) => log_args(ctx, vec![value], level),
+        Err(e) => log::error!("could not log console message: {}", e),
+    }
+}
+
+unsafe extern "C" fn console_time(
+    ctx: *mut q::JSContext,
+    _this_val: q::JSValue,
+    argc: ::std::os::raw::c_int,
+    argv: *mut q::JSValue,
+) -> q::JSValue {
+    let label = get_label(ctx, &parse_args(ctx, argc, argv));
+    let exists = QuickJsRealmAdapter::with_context(ctx, |realm| {
+        let mut state = realm.console_state.borrow_mut();
+        if state.timers.contains_key(&label) {
+            true
+        } else {
+            state.timers.insert(label.clone(), Instant::now());
+            false
+        }
+    });
+    if exists {
+        log_message(
+            ctx,
+            format!("Timer '{label}' already exists").as_str(),
+            Level::Warn,
+        );
+    }
+    quickjs_utils::new_null()
+}
+
+unsafe extern "C" fn console_time_end(
+    ctx: *mut q::JSContext,
+    _this_val: q::JSValue,
+    argc: ::std::os::raw::c_int,
+    argv: *mut q::JSValue,
+) -> q::JSValue {
+    let label = get_label(ctx, &parse_args(ctx, argc, argv));
+    let started = QuickJsRealmAdapter::with_context(ctx, |realm| {
+        realm.console_state.borrow_mut().timers.remove(&label)
+    });
+    match started {
+        Some(started) => {
+            let elapsed = started.elapsed().as_secs_f64() * 1000.0;
+            log_message(
+                ctx,
+                format!("{label}: {elapsed:.3}ms").as_str(),
+                Level::Info,
+            );
+        }
+        None => log_message(
+            ctx,
+            format!("Timer '{label}' does not exist").as_str(),
+            Level::Warn,
+        ),
+    }
+    quickjs_utils::new_null()
+}
+
+unsafe extern "C" fn console_count(
+    ctx: *mut q::JSContext,
+    _this_val: q::JSValue,
+    argc: ::std::os::raw::c_int,
+    argv: *mut q::JSValue,
+) -> q::JSValue {
+    let label = get_label(ctx, &parse_args(ctx, argc, argv));
+    let count = QuickJsRealmAdapter::with_context(ctx, |realm| {
+        let mut state = realm.console_state.borrow_mut();
+        let count = state.counts.entry(label.clone()).or_insert(0);
+        *count += 1;
+        *count
+    });
+    log_message(ctx, format!("{label}: {count}").as_str(), Level::Info);
+    quickjs_utils::new_null()
+}
+
+unsafe extern "C" fn console_group(
+    ctx: *mut q::JSContext,
+    _this_val: q::JSValue,
+    argc: ::std::os::raw::c_int,
+    argv: *mut q::JSValue,
+) -> q::JSValue {
+    // the label is logged at the current depth
+    if argc > 0 {
+        log_line(ctx, argc, argv, Level::Info);
+    }
+    QuickJsRealmAdapter::with_context(ctx, |realm| {
+        realm.console_state.borrow_mut().group_depth += 1;
+    });
+    quickjs_utils::new_null()
+}
+
+unsafe extern "C" fn console_group_end(
+    ctx: *mut q::JSContext,
+    _this_val: q::JSValue,
+    _argc: ::std::os::raw::c_int,
+    _argv: *mut q::JSValue,
+) -> q::JSValue {
+    QuickJsRealmAdapter::with_context(ctx, |realm| {
+        let mut state = realm.console_state.borrow_mut();
+        state.group_depth = state.group_depth.saturating_sub(1);
+    });
+    quickjs_utils::new_null()
+}
+
+unsafe extern "C" fn console_table(
+    ctx: *mut q::JSContext,
+    _this_val: q::JSValue,
+    argc: ::std::os::raw::c_int,
+    argv: *mut q::JSValue,
+) -> q::JSValue {
+    let args = parse_args(ctx, argc, argv);
+    let table = match args.first() {
+        Some(data) if data.is_object() && !functions::is_function(ctx, data) => {
+            QuickJsRealmAdapter::with_context(ctx, |realm| render_table(realm, data))
+        }
+        _ => None,
+    };
+    match table {
+        Some(table) => log_message(ctx, table.as_str(), Level::Info),
+        // like browsers log data which is not tabular as is
+        None => log_args(ctx, args, Level::Info),
+    }
+    quickjs_utils::new_null()
+}
+
+// a cell of console.table
+unsafe fn table_cell(ctx: *mut q::JSContext, value: &QuickJsValueAdapter) -> String {
+    match value.get_js_type() {
+        JsValueType::Object | JsValueType::Array => stringify_log_obj(ctx, value),
+        JsValueType::String => format!("'{}'", call_to_string(ctx, value).unwrap_or_default()),
+        _ => call_to_string(ctx, value).unwrap_or_default(),
+    }
+}
+
+/// render the rows of an array or object as a text table, the columns are the union of the keys of the rows
+unsafe fn render_table(realm: &QuickJsRealmAdapter, data: &QuickJsValueAdapter) -> Option<String> {
+    const INDEX: &str = "(index)";
+    const VALUES: &str = "Values";
+    let ctx = realm.context;
+    let row_names = objects::get_property_names_q(realm, data).ok()?;
+
+    let mut columns: Vec<String> = vec![];
+    let mut has_values = false;
+    let mut rows: Vec<(String, HashMap<String, String>)> = vec![];
+    for row_name in row_names {
+        let row = objects::get_property_q(realm, data, row_name.as_str()).ok()?;
+        let mut cells = HashMap::new();
+        if row.is_object() && !functions::is_function(ctx, &row) {
+            for column in objects::get_property_names_q(realm, &row).ok()? {
+                let value = objects::get_property_q(realm, &row, column.as_str()).ok()?;
+                cells.insert(column.clone(), table_cell(ctx, &value));
+                if !columns.contains(&column) {
+                    columns.push(column);
+                }
+            }
+        } else {
+            has_values = true;
+            cells.insert(VALUES.to_string(), table_cell(ctx, &row));
+        }
+        rows.push((row_name, cells));
+    }
+    if has_values {
+        columns.push(VALUES.to_string());
+    }
+
+    let mut widths = vec![INDEX.len()];
+    widths.extend(columns.iter().map(|c| c.chars().count()));
+    for (row_name, cells) in &rows {
+        widths[0] = widths[0].max(row_name.chars().count());
+        for (idx, column) in columns.iter().enumerate() {
+            let width = cells.get(column).map(|c| c.chars().count()).unwrap_or(0);
+            widths[idx + 1] = widths[idx + 1].max(width);
+        }
+    }
+    let render_row = |cells: Vec<&str>| -> String {
+        cells
+            .iter()
+            .zip(widths.iter())
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect::<Vec<_>>()
+            .join(" | ")
+            .trim_end()
+            .to_string()
+    };
+
+    let mut lines = vec![];
+    let mut header = vec![INDEX];
+    header.extend(columns.iter().map(|c| c.as_str()));
+    lines.push(render_row(header));
+    lines.push(
+        widths
+            .iter()
+            .map(|width| "-".repeat(*width))
+            .collect::<Vec<_>>()
+            .join("-+-"),
+    );
+    for (row_name, cells) in &rows {
+        let mut row = vec![row_name.as_str()];
+        row.extend(
+            columns
+                .iter()
+                .map(|column| cells.get(column).map(|c| c.as_str()).unwrap_or("")),
+        );
+        lines.push(render_row(row));
+    }
+    Some(lines.join("\n"))
+}
+
 #[cfg(test)]
 pub mod tests {
     use crate::builder::QuickJsRuntimeBuilder;
     use crate::jsutils::Script;
+    use crate::values::JsValueFacade;
+    use log::Level;
+    use std::sync::{Arc, Mutex};
     use std::thread;
     use std::time::Duration;
 
@@ -395,4 +770,115 @@ pub mod tests {
 
         thread::sleep(Duration::from_secs(1));
     }
+
+    #[test]
+    fn test_console_printer() {
+        // the facades only live for the call so the printer collects what it needs
+        type Printed = Vec<(Level, String, Vec<String>)>;
+        let printed: Arc<Mutex<Printed>> = Arc::new(Mutex::new(vec![]));
+        let printed2 = printed.clone();
+        let rt = QuickJsRuntimeBuilder::new()
+            .console_printer(move |level, realm_id, args| {
+                let args = args
+                    .iter()
+                    .map(|arg| match arg {
+                        JsValueFacade::JsObject { .. } => "object".to_string(),
+                        _ => arg.get_str().to_string(),
+                    })
+                    .collect();
+                printed2
+                    .lock()
+                    .unwrap()
+                    .push((level, realm_id.to_string(), args));
+            })
+            .build();
+        rt.eval_sync(
+            None,
+            Script::new(
+                "test_console_printer.es",
+                "console.log('a', {b: 1}); console.assert(1 === 1, 'fine'); console.assert(false, 'c');",
+            ),
+        )
+        .expect("script failed");
+
+        let printed = printed.lock().unwrap();
+        assert_eq!(
+            *printed,
+            vec![
+                (
+                    Level::Info,
+                    "__main__".to_string(),
+                    vec!["a".to_string(), "object".to_string()]
+                ),
+                (
+                    Level::Error,
+                    "__main__".to_string(),
+                    vec!["Assertion failed:".to_string(), "c".to_string()]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_console_time_count_group_table() {
+        let printed: Arc<Mutex<Vec<(Level, String)>>> = Arc::new(Mutex::new(vec![]));
+        let printed2 = printed.clone();
+        let rt = QuickJsRuntimeBuilder::new()
+            .console_printer(move |level, _realm_id, args| {
+                let line = args
+                    .iter()
+                    .map(|arg| arg.get_str().to_string())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                printed2.lock().unwrap().push((level, line));
+            })
+            .build();
+        rt.eval_sync(
+            None,
+            Script::new(
+                "test_console_time.es",
+                "console.time('load');\
+                 let t = Date.now(); while (Date.now() - t < 5) {}\
+                 console.timeEnd('load');\
+                 console.timeEnd('nope');\
+                 console.count(); console.count('x'); console.count();\
+                 console.group('outer');\
+                 console.log('inner');\
+                 console.groupEnd();\
+                 console.groupEnd();\
+                 console.log('after');\
+                 console.table([{a: 1, b: 'x'}, {a: 22, c: true}]);",
+            ),
+        )
+        .expect("script failed");
+
+        let printed = printed.lock().unwrap();
+        let (level, timer) = &printed[0];
+        assert_eq!(*level, Level::Info);
+        let elapsed = timer
+            .strip_prefix("load: ")
+            .and_then(|t| t.strip_suffix("ms"))
+            .expect("no label");
+        assert!(elapsed.parse::<f64>().expect("not a number") > 0.0);
+        assert_eq!(
+            printed[1],
+            (Level::Warn, "Timer 'nope' does not exist".to_string())
+        );
+        let lines: Vec<&str> = printed[2..].iter().map(|(_, l)| l.as_str()).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "default: 1",
+                "x: 1",
+                "default: 2",
+                "outer",
+                "  inner",
+                "after",
+                "(index) | a  | b   | c\n\
+                 --------+----+-----+-----\n\
+                 0       | 1  | 'x' |\n\
+                 1       | 22 |     | true",
+            ]
+        );
+    }
 }
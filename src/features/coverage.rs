@@ -0,0 +1,627 @@
+//! line coverage of the scripts which are evaluated in a realm, see [start_coverage](crate::facades::QuickJsRuntimeFacade::start_coverage)
+//!
+//! while coverage is collected in a realm every script and module which is compiled in that realm is instrumented,
+//! a counter call is added before the first statement which starts on a line, the report has a hit count for every instrumented line
+//! keyed by the path of the [Script](crate::jsutils::Script), lines which never ran have a count of 0
+//!
+//! * evaluating the same script again adds to the counts of its lines
+//! * the lines of a script with a source map (e.g. transpiled typescript) are mapped to the original source when the typescript feature is enabled
+//! * scripts which are evaluated while no coverage is collected are not changed, functions which were compiled while collecting
+//!   keep their counter calls, those are ignored when collection was stopped
+//! * modules are compiled once per realm, a module which was loaded before collection started is not instrumented
+//!
+//! the report can be serialized to the lcov format with [CoverageReport::to_lcov]
+//!
+//! # Limitations
+//! statements are found with a tokenizer and not a full parser, a statement which follows a line break without a semicolon
+//! is only counted when it starts with a word (e.g. `foo()` or `let x`), statements which start with a string literal are never counted
+//! so directives like `'use strict'` keep working
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::Script;
+//! use futures::executor::block_on;
+//! let rt = QuickJsRuntimeBuilder::new().build();
+//! block_on(rt.start_coverage("__main__")).expect("could not start coverage");
+//! rt.eval_sync(None, Script::new("rule.js", "function rule(a) {\n  if (a > 1) {\n    return 'high';\n  }\n  return 'low';\n}\nrule(0);")).expect("script failed");
+//! let report = block_on(rt.stop_coverage("__main__")).expect("could not stop coverage");
+//! assert_eq!(report.hit_count("rule.js", 3), Some(0));
+//! assert_eq!(report.hit_count("rule.js", 5), Some(1));
+//! assert!(report.to_lcov().contains("DA:3,0"));
+//! ```
+
+use crate::jsutils::{JsError, Script};
+use crate::quickjs_utils::{functions, get_global_q, objects};
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use libquickjs_sys as q;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+const HIT_FUNCTION_NAME: &str = "__qjsrt_coverage_hit";
+
+/// the hit counts of the lines of a script
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct FileCoverage {
+    pub path: String,
+    /// the hit count by line number (1-based), every instrumented line is present
+    pub lines: BTreeMap<u32, u64>,
+}
+
+/// the line coverage which was collected in a realm
+#[derive(Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CoverageReport {
+    /// the scripts in the order in which they were first instrumented
+    pub files: Vec<FileCoverage>,
+}
+
+impl CoverageReport {
+    /// the hit count of a line, None when the line was not instrumented
+    pub fn hit_count(&self, path: &str, line: u32) -> Option<u64> {
+        self.files
+            .iter()
+            .find(|f| f.path == path)
+            .and_then(|f| f.lines.get(&line).copied())
+    }
+
+    /// render the report in the lcov tracefile format
+    pub fn to_lcov(&self) -> String {
+        let mut lcov = String::new();
+        for file in &self.files {
+            let _ = writeln!(lcov, "TN:");
+            let _ = writeln!(lcov, "SF:{}", file.path);
+            for (line, count) in &file.lines {
+                let _ = writeln!(lcov, "DA:{line},{count}");
+            }
+            let _ = writeln!(lcov, "LF:{}", file.lines.len());
+            let _ = writeln!(
+                lcov,
+                "LH:{}",
+                file.lines.values().filter(|count| **count > 0).count()
+            );
+            let _ = writeln!(lcov, "end_of_record");
+        }
+        lcov
+    }
+}
+
+/// start collecting coverage in a realm
+pub fn start_coverage_q(realm: &QuickJsRealmAdapter) -> Result<(), JsError> {
+    if realm.coverage.borrow().is_some() {
+        return Err(JsError::new_string(format!(
+            "coverage is already collected in realm {}",
+            realm.id
+        )));
+    }
+    let global = get_global_q(realm);
+    // the function stays installed, functions which were instrumented can still run after collection stopped
+    if objects::get_property_q(realm, &global, HIT_FUNCTION_NAME)?.is_undefined() {
+        let hit_func = functions::new_function_q(
+            realm,
+            HIT_FUNCTION_NAME,
+            |realm, _this, args| {
+                if let (Some(file), Some(line)) = (args.first(), args.get(1)) {
+                    if let Some(report) = &mut *realm.coverage.borrow_mut() {
+                        if let Some(file) = report.files.get_mut(file.to_i32() as usize) {
+                            *file.lines.entry(line.to_i32() as u32).or_insert(0) += 1;
+                        }
+                    }
+                }
+                realm.create_undefined()
+            },
+            2,
+        )?;
+        objects::set_property2_q(realm, &global, HIT_FUNCTION_NAME, &hit_func, 0)?;
+    }
+    realm.coverage.replace(Some(CoverageReport::default()));
+    Ok(())
+}
+
+/// stop collecting coverage in a realm and get the report
+pub fn stop_coverage_q(realm: &QuickJsRealmAdapter) -> Result<CoverageReport, JsError> {
+    realm.coverage.take().ok_or_else(|| {
+        JsError::new_string(format!("coverage is not collected in realm {}", realm.id))
+    })
+}
+
+/// instrument a script which is compiled in a realm, when coverage is collected in that realm
+/// # Safety
+/// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
+pub(crate) unsafe fn instrument(context: *mut q::JSContext, script: &mut Script) {
+    QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+        instrument_q(q_js_rt.get_quickjs_context(context), script)
+    })
+}
+
+pub(crate) fn instrument_q(realm: &QuickJsRealmAdapter, script: &mut Script) {
+    let coverage = &mut *realm.coverage.borrow_mut();
+    let report = match coverage {
+        Some(report) => report,
+        None => return,
+    };
+    let code = script.get_runnable_code();
+    let starts = statement_starts(code);
+    if starts.is_empty() {
+        return;
+    }
+    let file_index = match report
+        .files
+        .iter()
+        .position(|f| f.path == script.get_path())
+    {
+        Some(index) => index,
+        None => {
+            report.files.push(FileCoverage {
+                path: script.get_path().to_string(),
+                lines: BTreeMap::new(),
+            });
+            report.files.len() - 1
+        }
+    };
+    let lines = original_lines(script.get_map(), &starts);
+
+    let mut new_code = String::with_capacity(code.len() + starts.len() * 32);
+    let mut starts_iter = starts.iter().zip(lines.iter()).peekable();
+    for (index, c) in code.chars().enumerate() {
+        if let Some(((start, _, _), line)) = starts_iter.peek() {
+            if *start == index {
+                let _ = write!(new_code, "{HIT_FUNCTION_NAME}({file_index},{line});");
+                report.files[file_index].lines.entry(**line).or_insert(0);
+                starts_iter.next();
+            }
+        }
+        new_code.push(c);
+    }
+
+    if script.get_runnable_code() == script.get_code() {
+        script.set_code(new_code);
+    } else {
+        let map = script.get_map().map(|m| m.to_string());
+        script.set_transpiled_code(new_code, map);
+    }
+}
+
+// the 1-based original lines of the statement starts
+#[cfg(feature = "typescript")]
+fn original_lines(map: Option<&str>, starts: &[(usize, u32, u32)]) -> Vec<u32> {
+    let source_map =
+        map.and_then(|map| swc::sourcemap::SourceMap::from_reader(std::io::Cursor::new(map)).ok());
+    starts
+        .iter()
+        .map(|(_, line, col)| {
+            let original = source_map
+                .as_ref()
+                .and_then(|source_map| source_map.lookup_token(*line, *col))
+                .map(|token| token.get_src_line())
+                .unwrap_or(*line);
+            original + 1
+        })
+        .collect()
+}
+
+#[cfg(not(feature = "typescript"))]
+fn original_lines(_map: Option<&str>, starts: &[(usize, u32, u32)]) -> Vec<u32> {
+    starts.iter().map(|(_, line, _)| line + 1).collect()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Start,
+    Word(String),
+    Punct(char),
+    Arrow,
+    Literal,
+}
+
+impl Token {
+    fn is_word(&self, word: &str) -> bool {
+        matches!(self, Token::Word(w) if w == word)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum BraceKind {
+    // a block, a function body or the top level
+    Statements,
+    Switch,
+    // an object literal, a class body or a destructuring pattern
+    Other,
+}
+
+struct Brace {
+    kind: BraceKind,
+    // the depth of parens and brackets when the brace was opened
+    parens: usize,
+    // a statement can follow the closing brace
+    closes_statement: bool,
+    is_do: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ParenKind {
+    // the condition of if, for, while, with or the param of catch
+    Control,
+    Switch,
+    Other,
+}
+
+// where a function or class declaration is, its body brace ends the statement
+#[derive(Clone, Copy, PartialEq)]
+enum Decl {
+    None,
+    Prefix(usize, usize),
+    Keyword(usize, usize),
+}
+
+const CONTROL_WORDS: [&str; 5] = ["if", "for", "while", "with", "catch"];
+const BLOCK_WORDS: [&str; 5] = ["else", "try", "finally", "do", "catch"];
+// words which continue a statement
+const CONTINUING_WORDS: [&str; 6] = ["else", "catch", "finally", "in", "of", "instanceof"];
+// words after which a line break does not end a statement
+const OPEN_WORDS: [&str; 24] = [
+    "return",
+    "typeof",
+    "new",
+    "delete",
+    "void",
+    "throw",
+    "await",
+    "yield",
+    "else",
+    "do",
+    "in",
+    "of",
+    "instanceof",
+    "case",
+    "let",
+    "const",
+    "var",
+    "async",
+    "function",
+    "class",
+    "extends",
+    "export",
+    "import",
+    "default",
+];
+// words after which a / starts a regex
+const REGEX_PRECEDING_WORDS: [&str; 11] = [
+    "return", "typeof", "case", "do", "else", "in", "of", "new", "delete", "void", "throw",
+];
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$' || !c.is_ascii()
+}
+
+fn ends_expression(token: &Token, last_closed_paren: ParenKind) -> bool {
+    match token {
+        Token::Literal => true,
+        Token::Word(w) => !OPEN_WORDS.contains(&w.as_str()),
+        Token::Punct(')') => last_closed_paren == ParenKind::Other,
+        Token::Punct(p) => matches!(p, ']' | '}'),
+        _ => false,
+    }
+}
+
+/// find where statements start, the char index, the 0-based line and column of the first statement on every line
+fn statement_starts(code: &str) -> Vec<(usize, u32, u32)> {
+    let chars: Vec<char> = code.chars().collect();
+    let mut starts = vec![];
+
+    let mut braces: Vec<Brace> = vec![];
+    let mut parens: Vec<ParenKind> = vec![];
+    let mut last_closed_paren = ParenKind::Other;
+    let mut prev = Token::Start;
+    let mut prev2 = Token::Start;
+    // a statement can start at the next token
+    let mut at_start = true;
+    // the previous token closed the block of a do-while
+    let mut after_do = false;
+    let mut newline = false;
+    let mut decl = Decl::None;
+    // Some(open ternaries) while in the expression of a case, with the depth of the case
+    let mut case_header: Option<(usize, usize, usize)> = None;
+
+    let mut line: u32 = 0;
+    let mut line_start = 0;
+    let mut last_line: Option<u32> = None;
+
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c.is_whitespace() {
+            if c == '\n' {
+                newline = true;
+                line += 1;
+                line_start = i + 1;
+            }
+            i += 1;
+            continue;
+        }
+        if c == '/' && chars.get(i + 1) == Some(&'/') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            continue;
+        }
+        if c == '/' && chars.get(i + 1) == Some(&'*') {
+            i += 2;
+            while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                if chars[i] == '\n' {
+                    newline = true;
+                    line += 1;
+                    line_start = i + 1;
+                }
+                i += 1;
+            }
+            i = (i + 2).min(chars.len());
+            continue;
+        }
+
+        // a token starts here
+        let (kind, paren_base) = match braces.last() {
+            Some(brace) => (brace.kind, brace.parens),
+            None => (BraceKind::Statements, 0),
+        };
+        let in_statements = kind != BraceKind::Other && parens.len() == paren_base;
+        let word: Option<String> = if is_word_char(c) {
+            let mut end = i;
+            while end < chars.len() && is_word_char(chars[end]) {
+                end += 1;
+            }
+            Some(chars[i..end].iter().collect())
+        } else {
+            None
+        };
+        let asi = newline
+            && in_statements
+            && case_header.is_none()
+            && word.is_some()
+            && ends_expression(&prev, last_closed_paren);
+        let statement_start = in_statements && (at_start || asi);
+        if statement_start {
+            let w = word.as_deref().unwrap_or("");
+            let skip = matches!(c, ';' | '}' | '\'' | '"')
+                || CONTINUING_WORDS.contains(&w)
+                || (after_do && w == "while")
+                || (kind == BraceKind::Switch && (w == "case" || w == "default"));
+            if !skip && last_line != Some(line) {
+                starts.push((i, line, (i - line_start) as u32));
+                last_line = Some(line);
+            }
+            if kind == BraceKind::Switch && (w == "case" || w == "default") {
+                case_header = Some((0, braces.len(), parens.len()));
+            }
+            decl = match w {
+                "function" | "class" => Decl::Keyword(braces.len(), parens.len()),
+                "export" | "async" | "default" => Decl::Prefix(braces.len(), parens.len()),
+                _ => Decl::None,
+            };
+        } else if let Decl::Prefix(b, p) = decl {
+            decl = match word.as_deref() {
+                Some("function") | Some("class") => Decl::Keyword(b, p),
+                Some("export") | Some("async") | Some("default") => Decl::Prefix(b, p),
+                _ => Decl::None,
+            };
+        }
+        at_start = false;
+        after_do = false;
+        newline = false;
+
+        let starts_regex = c == '/'
+            && match &prev {
+                Token::Punct(p) => !matches!(p, ')' | ']' | '}'),
+                Token::Word(w) => REGEX_PRECEDING_WORDS.contains(&w.as_str()),
+                Token::Arrow | Token::Start => true,
+                Token::Literal => false,
+            };
+        if c == '\'' || c == '"' || c == '`' || starts_regex {
+            let mut in_class = false;
+            i += 1;
+            while i < chars.len() {
+                let sc = chars[i];
+                i += 1;
+                if sc == '\\' {
+                    if chars.get(i) == Some(&'\n') {
+                        line += 1;
+                        line_start = i + 1;
+                    }
+                    i += 1;
+                } else if sc == '\n' {
+                    // template literals span lines
+                    line += 1;
+                    line_start = i;
+                } else if starts_regex && sc == '[' {
+                    in_class = true;
+                } else if starts_regex && sc == ']' {
+                    in_class = false;
+                } else if sc == c && !in_class {
+                    break;
+                }
+            }
+            prev2 = std::mem::replace(&mut prev, Token::Literal);
+            continue;
+        }
+
+        if let Some(word) = word {
+            i += word.chars().count();
+            prev2 = std::mem::replace(&mut prev, Token::Word(word));
+            continue;
+        }
+
+        if c == '=' && chars.get(i + 1) == Some(&'>') {
+            i += 2;
+            prev2 = std::mem::replace(&mut prev, Token::Arrow);
+            continue;
+        }
+
+        i += 1;
+        match c {
+            '(' | '[' => {
+                let kind = match &prev {
+                    _ if c == '[' => ParenKind::Other,
+                    Token::Word(w) if CONTROL_WORDS.contains(&w.as_str()) => ParenKind::Control,
+                    Token::Word(w) if w == "await" && prev2.is_word("for") => ParenKind::Control,
+                    Token::Word(w) if w == "switch" => ParenKind::Switch,
+                    _ => ParenKind::Other,
+                };
+                parens.push(kind);
+            }
+            ')' | ']' => {
+                last_closed_paren = parens.pop().unwrap_or(ParenKind::Other);
+            }
+            '{' => {
+                let function_body = prev == Token::Arrow
+                    || (prev == Token::Punct(')') && last_closed_paren == ParenKind::Other);
+                let kind = if statement_start || function_body {
+                    BraceKind::Statements
+                } else {
+                    match &prev {
+                        Token::Punct(')') if last_closed_paren == ParenKind::Switch => {
+                            BraceKind::Switch
+                        }
+                        Token::Punct(')') => BraceKind::Statements,
+                        Token::Word(w) if BLOCK_WORDS.contains(&w.as_str()) => {
+                            BraceKind::Statements
+                        }
+                        _ => BraceKind::Other,
+                    }
+                };
+                let is_decl_body = decl == Decl::Keyword(braces.len(), parens.len());
+                if is_decl_body {
+                    decl = Decl::None;
+                }
+                let closes_statement = if function_body || kind == BraceKind::Other {
+                    is_decl_body
+                } else {
+                    true
+                };
+                braces.push(Brace {
+                    kind,
+                    parens: parens.len(),
+                    closes_statement,
+                    is_do: prev.is_word("do"),
+                });
+                at_start = kind != BraceKind::Other;
+            }
+            '}' => {
+                if let Some(brace) = braces.pop() {
+                    if brace.closes_statement {
+                        at_start = true;
+                        after_do = brace.is_do;
+                    }
+                }
+            }
+            ';' if in_statements => {
+                at_start = true;
+            }
+            '?' => {
+                let next = chars.get(i).copied();
+                let next2 = chars.get(i + 1).copied();
+                if next == Some('.') && !next2.map(|n| n.is_ascii_digit()).unwrap_or(false) {
+                    // optional chaining
+                    i += 1;
+                } else if next == Some('?') {
+                    i += 1;
+                } else if let Some((open, b, p)) = case_header {
+                    case_header = Some((open + 1, b, p));
+                }
+            }
+            ':' => {
+                if let Some((open, b, p)) = case_header {
+                    if b == braces.len() && p == parens.len() {
+                        if open > 0 {
+                            case_header = Some((open - 1, b, p));
+                        } else {
+                            case_header = None;
+                            at_start = true;
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        prev2 = std::mem::replace(&mut prev, Token::Punct(c));
+    }
+    starts
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::features::coverage::statement_starts;
+    use crate::jsutils::Script;
+    use futures::executor::block_on;
+
+    fn lines(code: &str) -> Vec<u32> {
+        statement_starts(code)
+            .iter()
+            .map(|(_, line, _)| line + 1)
+            .collect()
+    }
+
+    #[test]
+    fn test_statement_starts() {
+        assert_eq!(
+            lines("'use strict';\nlet a = 1;\nif (a) {\n  a++;\n} else {\n  a--;\n}\n"),
+            vec![2, 3, 4, 6]
+        );
+        // no statements in object literals, class bodies or single statement bodies
+        assert_eq!(
+            lines("const o = {\n  a: 1,\n  b: 2\n};\nclass C {\n  m() {\n    return 1;\n  }\n}\nif (o)\n  o.a++;\nfoo()\nbar()"),
+            vec![1, 5, 7, 10, 12, 13]
+        );
+        // switch cases, do-while and statements after a function declaration
+        assert_eq!(
+            lines("switch (x) {\n  case a ? 1 : 2:\n    y();\n    break;\n  default:\n    z();\n}\ndo {\n  w();\n} while (v);\nfunction f() {\n  return 1;\n}\nf();"),
+            vec![1, 3, 4, 6, 8, 9, 11, 12, 14]
+        );
+        // strings, regexes and comments are skipped
+        assert_eq!(
+            lines("const s = '{ a; b; }'; // c; d\nconst r = /[;{]/;\n/* e;\n f; */ g();"),
+            vec![1, 2, 4]
+        );
+    }
+
+    #[test]
+    fn test_coverage() {
+        let rt = QuickJsRuntimeBuilder::new().build();
+        let code = "function rule(a) {\n  if (a > 1) {\n    return 'high';\n  }\n  switch (a) {\n    case 0:\n      return 'zero';\n    default:\n      return 'low';\n  }\n}\nrule(0);";
+
+        // not instrumented before collection starts
+        rt.eval_sync(None, Script::new("rule.js", code))
+            .expect("script failed");
+        block_on(rt.start_coverage("__main__")).expect("could not start");
+        assert!(block_on(rt.start_coverage("__main__")).is_err());
+        rt.eval_sync(None, Script::new("rule.js", code))
+            .expect("script failed");
+        rt.eval_sync(None, Script::new("rule.js", code))
+            .expect("script failed");
+        rt.eval_sync(None, Script::new("more.js", "rule(5);"))
+            .expect("script failed");
+        let report = block_on(rt.stop_coverage("__main__")).expect("could not stop");
+        assert!(block_on(rt.stop_coverage("__main__")).is_err());
+
+        // evaluating the script twice accumulates
+        assert_eq!(report.hit_count("rule.js", 12), Some(2));
+        assert_eq!(report.hit_count("rule.js", 2), Some(3));
+        assert_eq!(report.hit_count("rule.js", 3), Some(1));
+        assert_eq!(report.hit_count("rule.js", 7), Some(2));
+        assert_eq!(report.hit_count("rule.js", 9), Some(0));
+        assert_eq!(report.hit_count("more.js", 1), Some(1));
+
+        let lcov = report.to_lcov();
+        assert!(lcov.starts_with("TN:\nSF:rule.js\n"));
+        assert!(lcov.contains("DA:9,0\n"));
+        assert!(lcov.contains("SF:more.js\nDA:1,1\nLF:1\nLH:1\nend_of_record\n"));
+        serde_json::to_string(&report).expect("could not serialize");
+
+        // the functions which were instrumented still run after collection stopped
+        let res = rt
+            .eval_sync(None, Script::new("after.js", "rule(1)"))
+            .expect("script failed");
+        assert_eq!(res.get_str(), "low");
+    }
+}
@@ -0,0 +1,157 @@
+//! provides the crypto basics of the web platform, crypto.getRandomValues and crypto.randomUUID
+//!
+//! the random bytes come from rand::thread_rng which is a CSPRNG that is seeded by the os, a seeded Math.random
+//! (see [random_seed](crate::quickjsrealmadapter::RealmOptions::random_seed)) does not affect them
+
+use crate::features::WRITABLE_GLOBAL;
+use crate::jsutils::JsError;
+use crate::quickjs_utils::errors::type_error;
+use crate::quickjs_utils::{functions, get_global_q, objects, typedarrays};
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use crate::quickjsvalueadapter::QuickJsValueAdapter;
+use rand::{thread_rng, Rng, RngCore};
+
+// the max byteLength of a TypedArray which is passed to getRandomValues
+const MAX_RANDOM_VALUES_BYTES: usize = 65536;
+
+/// provides the crypto global with getRandomValues and randomUUID for the runtime
+/// # Example
+/// ```rust
+/// use quickjs_runtime::builder::{QuickJsRuntimeBuilder, WebDefaults};
+/// use quickjs_runtime::jsutils::Script;
+/// let rt = QuickJsRuntimeBuilder::new().web_platform_defaults(WebDefaults::Standard).build();
+/// let res = rt.eval_sync(None, Script::new("test_crypto.es", "crypto.getRandomValues(new Uint8Array(16)).length + crypto.randomUUID().length;")).expect("script failed");
+/// assert_eq!(res.get_i32(), 52);
+/// ```
+pub fn init(q_js_rt: &QuickJsRuntimeAdapter) -> Result<(), JsError> {
+    log::trace!("crypto::init");
+
+    q_js_rt.add_context_init_hook(|_q_js_rt, q_ctx| {
+        let crypto = objects::create_object_q(q_ctx)?;
+
+        let get_random_values_func = functions::new_function_q(
+            q_ctx,
+            "getRandomValues",
+            |q_ctx, _this, args| get_random_values(q_ctx, args),
+            1,
+        )?;
+        objects::set_property_q(q_ctx, &crypto, "getRandomValues", &get_random_values_func)?;
+
+        let random_uuid_func = functions::new_function_q(
+            q_ctx,
+            "randomUUID",
+            |q_ctx, _this, _args| q_ctx.create_string(random_uuid().as_str()),
+            0,
+        )?;
+        objects::set_property_q(q_ctx, &crypto, "randomUUID", &random_uuid_func)?;
+
+        let global = get_global_q(q_ctx);
+        objects::set_property2_q(q_ctx, &global, "crypto", &crypto, WRITABLE_GLOBAL)?;
+        Ok(())
+    })?;
+    Ok(())
+}
+
+// fill an integer TypedArray in place and return it
+fn get_random_values(
+    q_ctx: &QuickJsRealmAdapter,
+    args: &[QuickJsValueAdapter],
+) -> Result<QuickJsValueAdapter, JsError> {
+    let array = match args.first() {
+        Some(array) if is_integer_array(q_ctx, array)? => array,
+        _ => {
+            return Err(type_error(
+                "crypto.getRandomValues requires an integer TypedArray".to_string(),
+            ))
+        }
+    };
+    typedarrays::with_buffer_bytes_mut_q(q_ctx, array, |bytes| {
+        if bytes.len() > MAX_RANDOM_VALUES_BYTES {
+            return Err(JsError::new(
+                "QuotaExceededError".to_string(),
+                format!(
+                    "crypto.getRandomValues can fill at most {MAX_RANDOM_VALUES_BYTES} bytes, got {}",
+                    bytes.len()
+                ),
+                "".to_string(),
+            ));
+        }
+        thread_rng().fill_bytes(bytes);
+        Ok(())
+    })??;
+    Ok(array.dup())
+}
+
+fn is_integer_array(
+    q_ctx: &QuickJsRealmAdapter,
+    array: &QuickJsValueAdapter,
+) -> Result<bool, JsError> {
+    if !typedarrays::is_typed_array_q(q_ctx, array) {
+        return Ok(false);
+    }
+    for name in ["Float32Array", "Float64Array"] {
+        if objects::is_instance_of_by_name_q(q_ctx, array, name)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+// a version 4 UUID in its lowercase hyphenated form
+fn random_uuid() -> String {
+    let mut bytes: [u8; 16] = thread_rng().gen();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::{QuickJsRuntimeBuilder, WebDefaults};
+    use crate::jsutils::Script;
+
+    #[test]
+    fn test_crypto() {
+        let rt = QuickJsRuntimeBuilder::new()
+            .web_platform_defaults(WebDefaults::Standard)
+            .build();
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "test_crypto.js",
+                    r#"
+                    const buf = new Uint8Array(64);
+                    const view = new Uint32Array(buf.buffer, 16, 4);
+                    const same = crypto.getRandomValues(view) === view;
+                    const untouched = buf.subarray(0, 16).every(b => b === 0) && buf.subarray(32).every(b => b === 0);
+                    const errors = [new Float64Array(1), new ArrayBuffer(1), new Uint8Array(65537)].map(arg => {
+                        try {
+                            crypto.getRandomValues(arg);
+                            return 'none';
+                        } catch (e) {
+                            return e.name;
+                        }
+                    });
+                    const uuids = [crypto.randomUUID(), crypto.randomUUID()];
+                    const valid = uuids.every(u => /^[0-9a-f]{8}-[0-9a-f]{4}-4[0-9a-f]{3}-[89ab][0-9a-f]{3}-[0-9a-f]{12}$/.test(u));
+                    `${same}:${untouched}:${errors.join(',')}:${valid}:${uuids[0] !== uuids[1]}`
+                    "#,
+                ),
+            )
+            .expect("script failed");
+        assert_eq!(
+            res.get_str(),
+            "true:true:TypeError,TypeError,QuotaExceededError:true:true"
+        );
+    }
+}
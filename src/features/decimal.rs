@@ -0,0 +1,356 @@
+//! the `quickjs:decimal` module, provides a Decimal class for exact decimal arithmetic backed by [rust_decimal]
+//!
+//! a Decimal is created from a string, a number or another Decimal, numbers are converted by their shortest string representation (so `new Decimal(0.1)` is exactly 0.1)
+//! add, sub, mul and div return a new Decimal, an overflow or a division by zero throws a RangeError
+//!
+//! Decimals are passed to rust as objects, use [decimal_from_js_value_facade] to get the exact value, use [decimal_to_js_value_facade] to pass a Decimal to script as a string which can be passed to the constructor or methods without loss of precision
+//!
+//! the module is only available when it was enabled with [decimal_module](crate::builder::QuickJsRuntimeBuilder::decimal_module)
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::Script;
+//! let rt = QuickJsRuntimeBuilder::new().decimal_module().build();
+//! rt.eval_module_sync(None, Script::new("test_decimal.mes", "import {Decimal} from 'quickjs:decimal';\nif (new Decimal('0.1').add(0.2).toString() !== '0.3') {throw Error('unexpected sum');}")).expect("script failed");
+//! ```
+
+use crate::jsutils::modules::NativeModuleLoader;
+use crate::jsutils::JsError;
+use crate::quickjs_utils::errors::{range_error, type_error};
+use crate::quickjs_utils::primitives;
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsvalueadapter::QuickJsValueAdapter;
+use crate::reflection::{
+    get_proxy, get_proxy_instance_proxy_and_instance_id_q, new_instance2, Proxy,
+};
+use crate::values::JsValueFacade;
+use rust_decimal::{Decimal, RoundingStrategy};
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+pub const MODULE_NAME: &str = "quickjs:decimal";
+const CLASS_NAME: &str = "Decimal";
+
+thread_local! {
+    static DECIMALS: RefCell<HashMap<usize, Decimal>> = RefCell::new(HashMap::new());
+}
+
+/// the NativeModuleLoader which provides the `quickjs:decimal` module
+pub struct DecimalModuleLoader {}
+
+impl NativeModuleLoader for DecimalModuleLoader {
+    fn has_module(&self, _realm: &QuickJsRealmAdapter, module_name: &str) -> bool {
+        module_name.eq(MODULE_NAME)
+    }
+
+    fn get_module_export_names(
+        &self,
+        _realm: &QuickJsRealmAdapter,
+        _module_name: &str,
+    ) -> Vec<&str> {
+        vec![CLASS_NAME]
+    }
+
+    fn get_module_exports(
+        &self,
+        realm: &QuickJsRealmAdapter,
+        module_name: &str,
+    ) -> Vec<(&str, QuickJsValueAdapter)> {
+        self.try_get_module_exports(realm, module_name)
+            .unwrap_or_else(|err| {
+                log::error!("could not create the exports of {}: {}", module_name, err);
+                vec![]
+            })
+    }
+
+    fn try_get_module_exports(
+        &self,
+        realm: &QuickJsRealmAdapter,
+        _module_name: &str,
+    ) -> Result<Vec<(&str, QuickJsValueAdapter)>, JsError> {
+        Ok(vec![(CLASS_NAME, install_q(realm)?)])
+    }
+}
+
+/// parse a decimal string, the string must be representable without rounding
+pub fn parse_decimal(input: &str) -> Result<Decimal, JsError> {
+    Decimal::from_str_exact(input.trim()).map_err(|e| match e {
+        rust_decimal::Error::ErrorString(_) => type_error(format!("invalid decimal: {input}")),
+        _ => range_error(format!("decimal out of range: {input}")),
+    })
+}
+
+/// install the Decimal class in a realm (without adding it to the global scope), returns the constructor
+pub fn install_q(realm: &QuickJsRealmAdapter) -> Result<QuickJsValueAdapter, JsError> {
+    if let Some(constructor) = realm.proxy_constructor_refs.borrow().get(CLASS_NAME) {
+        return Ok(constructor.dup());
+    }
+    Proxy::new()
+        .name(CLASS_NAME)
+        .constructor(|_rt, realm, id, args| {
+            let value = match args.first() {
+                Some(arg) => to_decimal_q(realm, arg)?,
+                None => Decimal::ZERO,
+            };
+            DECIMALS.with(|rc| rc.borrow_mut().insert(id, value));
+            Ok(())
+        })
+        .finalizer(|_rt, _realm, id| {
+            DECIMALS.with(|rc| rc.borrow_mut().remove(&id));
+        })
+        .method("add", |_rt, realm, id, args| {
+            arithmetic(realm, *id, args, "add", |a, b| a.checked_add(b))
+        })
+        .method("sub", |_rt, realm, id, args| {
+            arithmetic(realm, *id, args, "sub", |a, b| a.checked_sub(b))
+        })
+        .method("mul", |_rt, realm, id, args| {
+            arithmetic(realm, *id, args, "mul", |a, b| a.checked_mul(b))
+        })
+        .method("div", |_rt, realm, id, args| {
+            if get_arg(realm, args, "div")?.is_zero() {
+                return Err(range_error("division by zero".to_string()));
+            }
+            arithmetic(realm, *id, args, "div", |a, b| a.checked_div(b))
+        })
+        .method("round", |_rt, realm, id, args| {
+            // rounds half away from zero
+            let dp = match args.first() {
+                None => 0,
+                Some(arg) if arg.is_i32() && arg.to_i32() >= 0 => arg.to_i32() as u32,
+                Some(_) => {
+                    return Err(range_error(
+                        "round requires a non-negative integer".to_string(),
+                    ))
+                }
+            };
+            let value =
+                get_value(*id)?.round_dp_with_strategy(dp, RoundingStrategy::MidpointAwayFromZero);
+            new_decimal_q(realm, value)
+        })
+        .method("cmp", |_rt, realm, id, args| {
+            let other = get_arg(realm, args, "cmp")?;
+            let res = match get_value(*id)?.cmp(&other) {
+                Ordering::Less => -1,
+                Ordering::Equal => 0,
+                Ordering::Greater => 1,
+            };
+            Ok(primitives::from_i32(res))
+        })
+        .method("toString", |_rt, realm, id, _args| {
+            realm.create_string(get_value(*id)?.to_string().as_str())
+        })
+        .method("toJSON", |_rt, realm, id, _args| {
+            realm.create_string(get_value(*id)?.to_string().as_str())
+        })
+        .method("toNumber", |_rt, realm, id, _args| {
+            let value = get_value(*id)?;
+            realm.create_f64(value.to_string().parse::<f64>().unwrap_or(f64::NAN))
+        })
+        .install(realm, false)
+}
+
+fn get_value(id: usize) -> Result<Decimal, JsError> {
+    DECIMALS
+        .with(|rc| rc.borrow().get(&id).cloned())
+        .ok_or_else(|| JsError::new_str("no such Decimal instance"))
+}
+
+fn get_arg(
+    realm: &QuickJsRealmAdapter,
+    args: &[QuickJsValueAdapter],
+    method_name: &str,
+) -> Result<Decimal, JsError> {
+    match args.first() {
+        Some(arg) => to_decimal_q(realm, arg),
+        None => Err(type_error(format!("{method_name} requires an argument"))),
+    }
+}
+
+fn arithmetic<F>(
+    realm: &QuickJsRealmAdapter,
+    id: usize,
+    args: &[QuickJsValueAdapter],
+    method_name: &str,
+    op: F,
+) -> Result<QuickJsValueAdapter, JsError>
+where
+    F: Fn(Decimal, Decimal) -> Option<Decimal>,
+{
+    let other = get_arg(realm, args, method_name)?;
+    match op(get_value(id)?, other) {
+        Some(res) => new_decimal_q(realm, res),
+        None => Err(range_error(format!("decimal overflow in {method_name}"))),
+    }
+}
+
+/// create a Decimal instance
+pub fn new_decimal_q(
+    realm: &QuickJsRealmAdapter,
+    value: Decimal,
+) -> Result<QuickJsValueAdapter, JsError> {
+    install_q(realm)?;
+    let proxy = get_proxy(realm, CLASS_NAME)
+        .ok_or_else(|| JsError::new_str("Decimal was not installed"))?;
+    let (id, instance) = new_instance2(&proxy, realm)?;
+    DECIMALS.with(|rc| rc.borrow_mut().insert(id, value));
+    Ok(instance)
+}
+
+/// convert a Decimal instance, a string or a number to a Decimal
+pub fn to_decimal_q(
+    realm: &QuickJsRealmAdapter,
+    value: &QuickJsValueAdapter,
+) -> Result<Decimal, JsError> {
+    if value.is_i32() {
+        Ok(Decimal::from(value.to_i32()))
+    } else if value.is_f64() {
+        let val = value.to_f64();
+        if !val.is_finite() {
+            return Err(range_error(format!("can not convert {val} to a decimal")));
+        }
+        // the shortest representation, the same as String(val) in script
+        Decimal::from_str(val.to_string().as_str())
+            .map_err(|_| range_error(format!("decimal out of range: {val}")))
+    } else if value.is_string() {
+        parse_decimal(primitives::to_string_q(realm, value)?.as_str())
+    } else {
+        match get_proxy_instance_proxy_and_instance_id_q(realm, value) {
+            Some((proxy, id)) if proxy.get_class_name() == CLASS_NAME => get_value(id),
+            _ => Err(type_error(format!(
+                "can not convert a {} to a decimal",
+                value.get_js_type()
+            ))),
+        }
+    }
+}
+
+/// pass a Decimal to script, as a string
+pub fn decimal_to_js_value_facade(value: &Decimal) -> JsValueFacade {
+    JsValueFacade::new_string(value.to_string())
+}
+
+/// get a Decimal from a value which was returned by script, a Decimal instance, a string or a number
+pub fn decimal_from_js_value_facade(value: &JsValueFacade) -> Result<Decimal, JsError> {
+    match value {
+        JsValueFacade::I32 { val } => Ok(Decimal::from(*val)),
+        JsValueFacade::F64 { val } => Decimal::from_str(val.to_string().as_str())
+            .map_err(|_| range_error(format!("can not convert {val} to a decimal"))),
+        JsValueFacade::String { val } => parse_decimal(val),
+        JsValueFacade::JsObject { cached_object } => cached_object.with_obj_sync(to_decimal_q)?,
+        _ => Err(type_error(format!(
+            "can not convert a {} to a decimal",
+            value.get_value_type()
+        ))),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::features::decimal::{decimal_from_js_value_facade, decimal_to_js_value_facade};
+    use crate::jsutils::Script;
+    use crate::values::JsValueFacade;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use rust_decimal::{Decimal, RoundingStrategy};
+    use std::str::FromStr;
+
+    #[test]
+    fn test_decimal() {
+        let rt = QuickJsRuntimeBuilder::new().decimal_module().build();
+        rt.eval_module_sync(
+            None,
+            Script::new(
+                "test_decimal.mes",
+                "import {Decimal} from 'quickjs:decimal';\nglobalThis.Decimal = Decimal;",
+            ),
+        )
+        .expect("script failed");
+        let eval = |code: &str| rt.eval_sync(None, Script::new("test_decimal.js", code));
+
+        let res = eval("[new Decimal('0.1').add(0.2), new Decimal(1).div(3).round(4), new Decimal('2.5').round(), new Decimal('-1.005').round(2), new Decimal('1.50').cmp('1.5'), JSON.stringify({a: new Decimal('12.30')})].join('|')").expect("script failed");
+        assert_eq!(res.get_str(), "0.3|0.3333|3|-1.01|0|{\"a\":\"12.30\"}");
+
+        for (code, name) in [
+            ("new Decimal(1).div(0)", "RangeError"),
+            (
+                "new Decimal('79228162514264337593543950335').mul(2)",
+                "RangeError",
+            ),
+            ("new Decimal('abc')", "TypeError"),
+            ("new Decimal(Infinity)", "RangeError"),
+            ("new Decimal({})", "TypeError"),
+        ] {
+            let err = eval(format!("let n; try {{{code};}} catch(e) {{n = e.name;}} n;").as_str())
+                .expect("script failed");
+            assert_eq!(err.get_str(), name, "{code}");
+        }
+
+        // round trip to rust without precision loss
+        let res = eval("new Decimal('12345678901234567.89').mul('10')").expect("script failed");
+        assert_eq!(
+            decimal_from_js_value_facade(&res).expect("not a decimal"),
+            Decimal::from_str("123456789012345678.90").unwrap()
+        );
+        let func = eval("(d) => new Decimal(d).add('0.000000001')").expect("script failed");
+        let res = match func {
+            JsValueFacade::JsFunction { cached_function } => cached_function
+                .invoke_function_sync(vec![decimal_to_js_value_facade(
+                    &Decimal::from_str("0.999999999").unwrap(),
+                )])
+                .expect("call failed"),
+            _ => panic!("not a function"),
+        };
+        assert_eq!(
+            decimal_from_js_value_facade(&res).expect("not a decimal"),
+            Decimal::ONE
+        );
+    }
+
+    #[test]
+    fn test_decimal_matches_rust_decimal() {
+        let rt = QuickJsRuntimeBuilder::new().decimal_module().build();
+        rt.eval_module_sync(
+            None,
+            Script::new(
+                "test_decimal.mes",
+                "import {Decimal} from 'quickjs:decimal';\nglobalThis.calc = (a, b, dp) => [a.add(b), a.sub(b), a.mul(b), a.div(b).round(dp), a.cmp(b)].join('|');\nglobalThis.Decimal = Decimal;",
+            ),
+        )
+        .expect("script failed");
+
+        let mut rng = StdRng::seed_from_u64(223);
+        for _ in 0..200 {
+            let a = Decimal::new(
+                rng.gen_range(-1_000_000_000..1_000_000_000),
+                rng.gen_range(0..10),
+            );
+            let mut b = Decimal::new(rng.gen_range(-1_000_000..1_000_000), rng.gen_range(0..6));
+            if b.is_zero() {
+                b = Decimal::ONE;
+            }
+            let dp = rng.gen_range(0..12);
+            let expected = format!(
+                "{}|{}|{}|{}|{}",
+                a + b,
+                a - b,
+                a * b,
+                (a / b).round_dp_with_strategy(dp, RoundingStrategy::MidpointAwayFromZero),
+                a.cmp(&b) as i32
+            );
+            let res = rt
+                .eval_sync(
+                    None,
+                    Script::new(
+                        "test_decimal.js",
+                        format!("calc(new Decimal('{a}'), new Decimal('{b}'), {dp})").as_str(),
+                    ),
+                )
+                .expect("script failed");
+            assert_eq!(res.get_str(), expected, "a={a} b={b} dp={dp}");
+        }
+    }
+}
@@ -0,0 +1,413 @@
+//! the `quickjs:encoding` module, provides native base64, hex and utf8 codecs
+//!
+//! the module is only available when it was enabled with [encoding_module](crate::builder::QuickJsRuntimeBuilder::encoding_module)
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::Script;
+//! let rt = QuickJsRuntimeBuilder::new().encoding_module().build();
+//! rt.eval_module_sync(None, Script::new("test_encoding.mes", "import {encodeBase64} from 'quickjs:encoding';\nif (encodeBase64('hello') !== 'aGVsbG8=') {throw Error('unexpected base64');}")).expect("script failed");
+//! ```
+
+use crate::jsutils::modules::NativeModuleLoader;
+use crate::jsutils::JsError;
+use crate::quickjs_utils::errors::type_error;
+use crate::quickjs_utils::{functions, primitives, typedarrays};
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsvalueadapter::QuickJsValueAdapter;
+
+pub const MODULE_NAME: &str = "quickjs:encoding";
+
+const BASE64_CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const HEX_CHARS: &[u8; 16] = b"0123456789abcdef";
+
+/// the NativeModuleLoader which provides the `quickjs:encoding` module
+pub struct EncodingModuleLoader {}
+
+impl NativeModuleLoader for EncodingModuleLoader {
+    fn has_module(&self, _realm: &QuickJsRealmAdapter, module_name: &str) -> bool {
+        module_name.eq(MODULE_NAME)
+    }
+
+    fn get_module_export_names(
+        &self,
+        _realm: &QuickJsRealmAdapter,
+        _module_name: &str,
+    ) -> Vec<&str> {
+        vec![
+            "encodeBase64",
+            "decodeBase64",
+            "encodeHex",
+            "decodeHex",
+            "utf8Encode",
+            "utf8Decode",
+        ]
+    }
+
+    fn get_module_exports(
+        &self,
+        realm: &QuickJsRealmAdapter,
+        module_name: &str,
+    ) -> Vec<(&str, QuickJsValueAdapter)> {
+        self.try_get_module_exports(realm, module_name)
+            .unwrap_or_else(|err| {
+                log::error!("could not create the exports of {}: {}", module_name, err);
+                vec![]
+            })
+    }
+
+    fn try_get_module_exports(
+        &self,
+        realm: &QuickJsRealmAdapter,
+        _module_name: &str,
+    ) -> Result<Vec<(&str, QuickJsValueAdapter)>, JsError> {
+        Ok(vec![
+            (
+                "encodeBase64",
+                new_encoding_function(realm, "encodeBase64", js_encode_base64)?,
+            ),
+            (
+                "decodeBase64",
+                new_encoding_function(realm, "decodeBase64", js_decode_base64)?,
+            ),
+            (
+                "encodeHex",
+                new_encoding_function(realm, "encodeHex", js_encode_hex)?,
+            ),
+            (
+                "decodeHex",
+                new_encoding_function(realm, "decodeHex", js_decode_hex)?,
+            ),
+            (
+                "utf8Encode",
+                new_encoding_function(realm, "utf8Encode", js_utf8_encode)?,
+            ),
+            (
+                "utf8Decode",
+                new_encoding_function(realm, "utf8Decode", js_utf8_decode)?,
+            ),
+        ])
+    }
+}
+
+type EncodingFunction =
+    fn(&QuickJsRealmAdapter, &[QuickJsValueAdapter]) -> Result<QuickJsValueAdapter, JsError>;
+
+fn new_encoding_function(
+    realm: &QuickJsRealmAdapter,
+    name: &str,
+    func: EncodingFunction,
+) -> Result<QuickJsValueAdapter, JsError> {
+    functions::new_function_q(realm, name, move |realm, _this, args| func(realm, args), 1)
+}
+
+/// get the bytes of a string (utf8) or an ArrayBuffer / TypedArray and pass them to a consumer
+fn with_input_bytes<C, R>(
+    realm: &QuickJsRealmAdapter,
+    func_name: &str,
+    args: &[QuickJsValueAdapter],
+    consumer: C,
+) -> Result<R, JsError>
+where
+    C: FnOnce(&[u8]) -> R,
+{
+    match args.first() {
+        Some(arg) if arg.is_string() => {
+            let s = primitives::to_string_q(realm, arg)?;
+            Ok(consumer(s.as_bytes()))
+        }
+        Some(arg)
+            if typedarrays::is_array_buffer_q(realm, arg)
+                || typedarrays::is_typed_array_q(realm, arg) =>
+        {
+            typedarrays::with_buffer_bytes_q(realm, arg, consumer)
+        }
+        _ => Err(type_error(format!(
+            "{func_name} requires a string, ArrayBuffer or TypedArray"
+        ))),
+    }
+}
+
+fn get_string_arg(
+    realm: &QuickJsRealmAdapter,
+    func_name: &str,
+    args: &[QuickJsValueAdapter],
+) -> Result<String, JsError> {
+    match args.first() {
+        Some(arg) if arg.is_string() => primitives::to_string_q(realm, arg),
+        _ => Err(type_error(format!("{func_name} requires a string"))),
+    }
+}
+
+/// encode bytes as base64 (with padding)
+pub fn encode_base64(bytes: &[u8]) -> String {
+    let mut res = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                res.push(BASE64_CHARS[(n >> (18 - i * 6)) as usize & 0x3f] as char);
+            } else {
+                res.push('=');
+            }
+        }
+    }
+    res
+}
+
+/// decode a base64 string, padding is optional but when present it should complete the last group of 4 chars
+/// the error contains the offset of the first invalid char
+pub fn decode_base64(input: &str) -> Result<Vec<u8>, JsError> {
+    let data = input.as_bytes();
+    let padding = data
+        .iter()
+        .rev()
+        .take(2)
+        .take_while(|c| **c == b'=')
+        .count();
+    let unpadded_len = data.len() - padding;
+    if unpadded_len % 4 == 1 {
+        return Err(type_error(format!(
+            "invalid base64 length at offset {unpadded_len}"
+        )));
+    }
+    if padding > 0 && (4 - unpadded_len % 4) % 4 != padding {
+        return Err(type_error(format!(
+            "invalid base64 padding at offset {unpadded_len}"
+        )));
+    }
+    let mut res = Vec::with_capacity(unpadded_len * 3 / 4);
+    let mut acc: u32 = 0;
+    let mut bits = 0;
+    for (offset, c) in data[..unpadded_len].iter().enumerate() {
+        let val = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => {
+                return Err(type_error(format!(
+                    "invalid base64 character at offset {offset}"
+                )))
+            }
+        };
+        acc = (acc << 6) | val as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            res.push((acc >> bits) as u8);
+        }
+    }
+    // the bits of the last char which are not part of a byte are zero in a canonical encoding
+    if acc & ((1 << bits) - 1) != 0 {
+        return Err(type_error(format!(
+            "invalid base64 trailing bits at offset {}",
+            unpadded_len - 1
+        )));
+    }
+    Ok(res)
+}
+
+/// encode bytes as lowercase hex
+pub fn encode_hex(bytes: &[u8]) -> String {
+    let mut res = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        res.push(HEX_CHARS[(b >> 4) as usize] as char);
+        res.push(HEX_CHARS[(b & 0xf) as usize] as char);
+    }
+    res
+}
+
+/// decode a hex string (upper or lowercase)
+/// the error contains the offset of the first invalid char
+pub fn decode_hex(input: &str) -> Result<Vec<u8>, JsError> {
+    let data = input.as_bytes();
+    let nibble = |offset: usize| -> Result<u8, JsError> {
+        match data[offset] {
+            c @ b'0'..=b'9' => Ok(c - b'0'),
+            c @ b'a'..=b'f' => Ok(c - b'a' + 10),
+            c @ b'A'..=b'F' => Ok(c - b'A' + 10),
+            _ => Err(type_error(format!(
+                "invalid hex character at offset {offset}"
+            ))),
+        }
+    };
+    let mut res = Vec::with_capacity(data.len() / 2);
+    for offset in (0..data.len()).step_by(2) {
+        let high = nibble(offset)?;
+        if offset + 1 >= data.len() {
+            return Err(type_error(format!(
+                "invalid hex length at offset {}",
+                data.len()
+            )));
+        }
+        let low = nibble(offset + 1)?;
+        res.push((high << 4) | low);
+    }
+    Ok(res)
+}
+
+fn js_encode_base64(
+    realm: &QuickJsRealmAdapter,
+    args: &[QuickJsValueAdapter],
+) -> Result<QuickJsValueAdapter, JsError> {
+    let encoded = with_input_bytes(realm, "encodeBase64", args, encode_base64)?;
+    realm.create_string(encoded.as_str())
+}
+
+fn js_decode_base64(
+    realm: &QuickJsRealmAdapter,
+    args: &[QuickJsValueAdapter],
+) -> Result<QuickJsValueAdapter, JsError> {
+    let input = get_string_arg(realm, "decodeBase64", args)?;
+    realm.create_typed_array_uint8(decode_base64(input.as_str())?)
+}
+
+fn js_encode_hex(
+    realm: &QuickJsRealmAdapter,
+    args: &[QuickJsValueAdapter],
+) -> Result<QuickJsValueAdapter, JsError> {
+    let encoded = with_input_bytes(realm, "encodeHex", args, encode_hex)?;
+    realm.create_string(encoded.as_str())
+}
+
+fn js_decode_hex(
+    realm: &QuickJsRealmAdapter,
+    args: &[QuickJsValueAdapter],
+) -> Result<QuickJsValueAdapter, JsError> {
+    let input = get_string_arg(realm, "decodeHex", args)?;
+    realm.create_typed_array_uint8(decode_hex(input.as_str())?)
+}
+
+fn js_utf8_encode(
+    realm: &QuickJsRealmAdapter,
+    args: &[QuickJsValueAdapter],
+) -> Result<QuickJsValueAdapter, JsError> {
+    let input = get_string_arg(realm, "utf8Encode", args)?;
+    realm.create_typed_array_uint8(input.into_bytes())
+}
+
+fn js_utf8_decode(
+    realm: &QuickJsRealmAdapter,
+    args: &[QuickJsValueAdapter],
+) -> Result<QuickJsValueAdapter, JsError> {
+    let decoded = with_input_bytes(realm, "utf8Decode", args, |bytes| {
+        std::str::from_utf8(bytes)
+            .map(|s| s.to_string())
+            .map_err(|e| {
+                type_error(format!(
+                    "invalid utf8 sequence at offset {}",
+                    e.valid_up_to()
+                ))
+            })
+    })??;
+    realm.create_string(decoded.as_str())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::features::encoding::{decode_base64, decode_hex, encode_base64, encode_hex};
+    use crate::jsutils::Script;
+
+    #[test]
+    fn test_codecs() {
+        for input in ["", "f", "fo", "foo", "foob", "fooba", "foobar"] {
+            let encoded = encode_base64(input.as_bytes());
+            assert_eq!(
+                decode_base64(encoded.as_str()).expect("decode failed"),
+                input.as_bytes()
+            );
+            let encoded = encode_hex(input.as_bytes());
+            assert_eq!(
+                decode_hex(encoded.as_str()).expect("decode failed"),
+                input.as_bytes()
+            );
+        }
+        assert_eq!(encode_base64(b"foobar"), "Zm9vYmFy");
+        assert_eq!(encode_base64(b"fo"), "Zm8=");
+        assert_eq!(decode_base64("Zm8").expect("decode failed"), b"fo");
+        assert!(decode_base64("Zm9v!mFy")
+            .expect_err("decode should fail")
+            .get_message()
+            .contains("offset 4"));
+        assert!(decode_base64("Zm8==")
+            .expect_err("decode should fail")
+            .get_message()
+            .contains("padding at offset 3"));
+        assert!(decode_base64("Zm9v=")
+            .expect_err("decode should fail")
+            .get_message()
+            .contains("padding at offset 4"));
+        assert!(decode_base64("Zm9=")
+            .expect_err("decode should fail")
+            .get_message()
+            .contains("trailing bits at offset 2"));
+        assert_eq!(encode_hex(&[0, 15, 255]), "000fff");
+        assert_eq!(decode_hex("0A0b").expect("decode failed"), vec![10, 11]);
+        assert!(decode_hex("0a0g")
+            .expect_err("decode should fail")
+            .get_message()
+            .contains("offset 3"));
+        assert!(decode_hex("0a0")
+            .expect_err("decode should fail")
+            .get_message()
+            .contains("offset 3"));
+    }
+
+    #[test]
+    fn test_encoding_module() {
+        let rt = QuickJsRuntimeBuilder::new().encoding_module().build();
+        rt.eval_module_sync(
+            None,
+            Script::new(
+                "test_encoding.mes",
+                r#"
+                import {encodeBase64, decodeBase64, encodeHex, decodeHex, utf8Encode, utf8Decode} from 'quickjs:encoding';
+                globalThis.encodingResults = [];
+                const bytes = utf8Encode('héllo');
+                encodingResults.push(bytes.length);
+                encodingResults.push(utf8Decode(decodeBase64(encodeBase64(bytes))));
+                encodingResults.push(encodeHex(bytes.subarray(1, 3)));
+                encodingResults.push(utf8Decode(decodeHex(encodeHex('héllo'))));
+                try {
+                    utf8Decode(new Uint8Array([104, 105, 255]));
+                } catch(e) {
+                    encodingResults.push(e.name + ': ' + e.message);
+                }
+                "#,
+            ),
+        )
+        .expect("module failed");
+
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new("test_encoding.js", "encodingResults.join(',');"),
+            )
+            .expect("script failed");
+        assert_eq!(
+            res.get_str(),
+            "6,héllo,c3a9,héllo,TypeError: invalid utf8 sequence at offset 2"
+        );
+    }
+
+    #[test]
+    fn test_encoding_module_disabled() {
+        let rt = QuickJsRuntimeBuilder::new().build();
+        let res = rt.eval_module_sync(
+            None,
+            Script::new(
+                "test_encoding_disabled.mes",
+                "import {encodeBase64} from 'quickjs:encoding';",
+            ),
+        );
+        assert!(res.is_err());
+    }
+}
@@ -0,0 +1,244 @@
+//! the `encoding` feature, provides the atob, btoa, TextEncoder and TextDecoder globals
+//!
+//! only utf-8 is supported by TextEncoder and TextDecoder, the codecs are shared with the [quickjs:encoding](crate::features::encoding) module
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::{QuickJsRuntimeBuilder, WebDefaults};
+//! use quickjs_runtime::jsutils::Script;
+//! let rt = QuickJsRuntimeBuilder::new().web_platform_defaults(WebDefaults::Standard).build();
+//! let res = rt.eval_sync(None, Script::new("test_encoding.es", "new TextDecoder().decode(new TextEncoder().encode(atob(btoa('hi'))));")).expect("script failed");
+//! assert_eq!(res.get_str(), "hi");
+//! ```
+
+use crate::features::encoding::{decode_base64, encode_base64};
+use crate::features::WRITABLE_GLOBAL;
+use crate::jsutils::JsError;
+use crate::quickjs_utils::errors::type_error;
+use crate::quickjs_utils::{functions, get_global_q, objects, primitives, typedarrays};
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use crate::quickjsvalueadapter::QuickJsValueAdapter;
+use crate::reflection::Proxy;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+thread_local! {
+    // the fatal flag per TextDecoder instance
+    static FATAL_DECODERS: RefCell<HashMap<usize, bool>> = RefCell::new(HashMap::new());
+}
+
+const UTF8_LABELS: &[&str] = &["utf-8", "utf8", "unicode-1-1-utf-8"];
+
+pub fn init(q_js_rt: &QuickJsRuntimeAdapter) -> Result<(), JsError> {
+    log::trace!("encoding_globals::init");
+
+    q_js_rt.add_context_init_hook(|_q_js_rt, q_ctx| {
+        let global = get_global_q(q_ctx);
+
+        let atob_func =
+            functions::new_function_q(q_ctx, "atob", |q_ctx, _this, args| atob(q_ctx, args), 1)?;
+        objects::set_property2_q(q_ctx, &global, "atob", &atob_func, WRITABLE_GLOBAL)?;
+
+        let btoa_func =
+            functions::new_function_q(q_ctx, "btoa", |q_ctx, _this, args| btoa(q_ctx, args), 1)?;
+        objects::set_property2_q(q_ctx, &global, "btoa", &btoa_func, WRITABLE_GLOBAL)?;
+
+        let text_encoder = install_text_encoder(q_ctx)?;
+        objects::set_property2_q(
+            q_ctx,
+            &global,
+            "TextEncoder",
+            &text_encoder,
+            WRITABLE_GLOBAL,
+        )?;
+
+        let text_decoder = install_text_decoder(q_ctx)?;
+        objects::set_property2_q(
+            q_ctx,
+            &global,
+            "TextDecoder",
+            &text_decoder,
+            WRITABLE_GLOBAL,
+        )?;
+        Ok(())
+    })?;
+    Ok(())
+}
+
+fn invalid_character_error(message: &str) -> JsError {
+    JsError::new(
+        "InvalidCharacterError".to_string(),
+        message.to_string(),
+        "".to_string(),
+    )
+}
+
+fn get_string_arg(
+    q_ctx: &QuickJsRealmAdapter,
+    args: &[QuickJsValueAdapter],
+) -> Result<String, JsError> {
+    match args.first() {
+        Some(arg) if arg.is_string() => primitives::to_string_q(q_ctx, arg),
+        Some(arg) if !arg.is_undefined() => functions::call_to_string_q(q_ctx, arg),
+        _ => Ok(String::new()),
+    }
+}
+
+/// decode base64 to a string with a char per byte, whitespace is ignored
+fn atob(
+    q_ctx: &QuickJsRealmAdapter,
+    args: &[QuickJsValueAdapter],
+) -> Result<QuickJsValueAdapter, JsError> {
+    let input = get_string_arg(q_ctx, args)?;
+    let input: String = input
+        .chars()
+        .filter(|c| !matches!(c, '\t' | '\n' | '\x0c' | '\r' | ' '))
+        .collect();
+    let bytes = decode_base64(input.as_str())
+        .map_err(|_| invalid_character_error("the string to be decoded is not valid base64"))?;
+    let binary: String = bytes.into_iter().map(char::from).collect();
+    q_ctx.create_string(binary.as_str())
+}
+
+/// encode a string with a char per byte as base64
+fn btoa(
+    q_ctx: &QuickJsRealmAdapter,
+    args: &[QuickJsValueAdapter],
+) -> Result<QuickJsValueAdapter, JsError> {
+    let input = get_string_arg(q_ctx, args)?;
+    let bytes = input
+        .chars()
+        .map(u8::try_from)
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|_| {
+            invalid_character_error(
+                "the string to be encoded contains characters outside of the Latin1 range",
+            )
+        })?;
+    q_ctx.create_string(encode_base64(&bytes).as_str())
+}
+
+fn install_text_encoder(q_ctx: &QuickJsRealmAdapter) -> Result<QuickJsValueAdapter, JsError> {
+    Proxy::new()
+        .name("TextEncoder")
+        .constructor(|_rt, _realm, _id, _args| Ok(()))
+        .getter("encoding", |_rt, realm, _id| realm.create_string("utf-8"))
+        .method("encode", |_rt, realm, _id, args| {
+            let input = get_string_arg(realm, args)?;
+            realm.create_typed_array_uint8(input.into_bytes())
+        })
+        .install(q_ctx, false)
+}
+
+fn install_text_decoder(q_ctx: &QuickJsRealmAdapter) -> Result<QuickJsValueAdapter, JsError> {
+    Proxy::new()
+        .name("TextDecoder")
+        .constructor(|_rt, realm, id, args| {
+            if let Some(label) = args.first().filter(|label| !label.is_undefined()) {
+                let label = functions::call_to_string_q(realm, label)?;
+                let label = label.trim().to_ascii_lowercase();
+                if !UTF8_LABELS.contains(&label.as_str()) {
+                    return Err(JsError::new(
+                        "RangeError".to_string(),
+                        format!("the encoding {label} is not supported"),
+                        "".to_string(),
+                    ));
+                }
+            }
+            let fatal = match args.get(1) {
+                Some(options) if options.is_object() => {
+                    objects::get_property_q(realm, options, "fatal")?.to_bool()
+                }
+                _ => false,
+            };
+            FATAL_DECODERS.with(|rc| rc.borrow_mut().insert(id, fatal));
+            Ok(())
+        })
+        .finalizer(|_rt, _realm, id| {
+            FATAL_DECODERS.with(|rc| rc.borrow_mut().remove(&id));
+        })
+        .getter("encoding", |_rt, realm, _id| realm.create_string("utf-8"))
+        .getter("fatal", |_rt, _realm, id| {
+            Ok(primitives::from_bool(is_fatal(*id)))
+        })
+        .method("decode", |_rt, realm, id, args| {
+            let decoded = match args.first() {
+                None => String::new(),
+                Some(arg) if arg.is_undefined() => String::new(),
+                Some(arg)
+                    if typedarrays::is_array_buffer_q(realm, arg)
+                        || typedarrays::is_typed_array_q(realm, arg) =>
+                {
+                    let fatal = is_fatal(*id);
+                    typedarrays::with_buffer_bytes_q(realm, arg, |bytes| {
+                        // the byte order mark is not part of the decoded string
+                        let bytes = bytes.strip_prefix(b"\xef\xbb\xbf").unwrap_or(bytes);
+                        if fatal {
+                            std::str::from_utf8(bytes)
+                                .map(|s| s.to_string())
+                                .map_err(|e| {
+                                    type_error(format!(
+                                        "invalid utf8 sequence at offset {}",
+                                        e.valid_up_to()
+                                    ))
+                                })
+                        } else {
+                            Ok(String::from_utf8_lossy(bytes).into_owned())
+                        }
+                    })??
+                }
+                Some(_) => {
+                    return Err(type_error(
+                        "decode requires an ArrayBuffer or TypedArray".to_string(),
+                    ))
+                }
+            };
+            realm.create_string(decoded.as_str())
+        })
+        .install(q_ctx, false)
+}
+
+fn is_fatal(id: usize) -> bool {
+    FATAL_DECODERS.with(|rc| rc.borrow().get(&id).copied().unwrap_or(false))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::{QuickJsRuntimeBuilder, WebDefaults};
+    use crate::jsutils::Script;
+
+    #[test]
+    fn test_encoding_globals() {
+        let rt = QuickJsRuntimeBuilder::new()
+            .web_platform_defaults(WebDefaults::Standard)
+            .build();
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "test_encoding_globals.js",
+                    r#"
+                    let bytes = new TextEncoder().encode('héllo');
+                    let failed = [];
+                    try { btoa('Ā'); } catch (e) { failed.push(e.name); }
+                    try { atob('Zm9v!'); } catch (e) { failed.push(e.name); }
+                    try { new TextDecoder('latin1'); } catch (e) { failed.push(e.name); }
+                    try { new TextDecoder('utf-8', {fatal: true}).decode(new Uint8Array([0xff])); } catch (e) { failed.push(e.name); }
+                    [
+                        bytes.length,
+                        new TextDecoder().decode(bytes),
+                        new TextDecoder().decode(new Uint8Array([0xef, 0xbb, 0xbf, 0x61])),
+                        btoa('foob'),
+                        atob(' Zm9v\nYg== '),
+                        failed.join(','),
+                    ].join(':');
+                    "#,
+                ),
+            )
+            .expect("script failed");
+        assert_eq!(
+            res.get_str(),
+            "6:h\u{e9}llo:a:Zm9vYg==:foob:InvalidCharacterError,InvalidCharacterError,RangeError,TypeError"
+        );
+    }
+}
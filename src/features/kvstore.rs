@@ -0,0 +1,385 @@
+//! the `quickjs:store` module, a simple key/value store for scripts which is backed by storage of the embedder
+//!
+//! values are serialized with the [structured clone](crate::quickjs_utils::structuredclone) algorithm so e.g. Dates, Maps, Sets, NaN and undefined are kept,
+//! all functions return a Promise, keys are namespaced per realm (or per tenant when a tenant key was set in the [KvStoreOptions])
+//!
+//! the module is only available when a [KvStoreProvider] was set with [kv_store](crate::builder::QuickJsRuntimeBuilder::kv_store)
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::features::kvstore::{KvStoreOptions, MemoryKvStoreProvider};
+//! use quickjs_runtime::jsutils::Script;
+//! let rt = QuickJsRuntimeBuilder::new().kv_store(MemoryKvStoreProvider::new(), KvStoreOptions::default()).build();
+//! rt.eval_module_sync(None, Script::new("test_store.mes", "import * as store from 'quickjs:store';\nstore.set('k', {a: 1}).then(() => store.get('k')).then((v) => console.log(v.a));")).expect("script failed");
+//! ```
+
+use crate::jsutils::modules::NativeModuleLoader;
+use crate::jsutils::JsError;
+use crate::quickjs_utils::structuredclone::{deserialize_q, serialize_q};
+use crate::quickjs_utils::{arrays, functions, primitives};
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsvalueadapter::QuickJsValueAdapter;
+use futures::Future;
+use std::collections::BTreeMap;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+pub const MODULE_NAME: &str = "quickjs:store";
+
+/// the future which is returned by the methods of a [KvStoreProvider]
+pub type KvStoreFuture<T> = Pin<Box<dyn Future<Output = Result<T, JsError>> + Send + 'static>>;
+
+/// the storage backend of the `quickjs:store` module
+/// keys which are passed to the provider are already prefixed with the namespace of the realm
+pub trait KvStoreProvider: Send + Sync {
+    fn get(&self, key: String) -> KvStoreFuture<Option<Vec<u8>>>;
+    fn set(&self, key: String, value: Vec<u8>) -> KvStoreFuture<()>;
+    /// delete a key, resolves to true if the key existed
+    fn delete(&self, key: String) -> KvStoreFuture<bool>;
+    /// list all keys which start with a prefix
+    fn list(&self, prefix: String) -> KvStoreFuture<Vec<String>>;
+}
+
+/// options for the `quickjs:store` module
+#[derive(Clone, Debug)]
+pub struct KvStoreOptions {
+    /// the namespace for all keys, when None the keys are namespaced by the id of the realm
+    pub tenant_key: Option<String>,
+    /// the max size of a serialized value in bytes
+    pub max_value_size: usize,
+}
+
+impl Default for KvStoreOptions {
+    fn default() -> Self {
+        Self {
+            tenant_key: None,
+            max_value_size: 64 * 1024,
+        }
+    }
+}
+
+/// a KvStoreProvider which keeps all values in memory, mainly useful for testing
+#[derive(Default)]
+pub struct MemoryKvStoreProvider {
+    entries: Arc<Mutex<BTreeMap<String, Vec<u8>>>>,
+}
+
+impl MemoryKvStoreProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl KvStoreProvider for MemoryKvStoreProvider {
+    fn get(&self, key: String) -> KvStoreFuture<Option<Vec<u8>>> {
+        let res = self.entries.lock().unwrap().get(&key).cloned();
+        Box::pin(async move { Ok(res) })
+    }
+
+    fn set(&self, key: String, value: Vec<u8>) -> KvStoreFuture<()> {
+        self.entries.lock().unwrap().insert(key, value);
+        Box::pin(async move { Ok(()) })
+    }
+
+    fn delete(&self, key: String) -> KvStoreFuture<bool> {
+        let existed = self.entries.lock().unwrap().remove(&key).is_some();
+        Box::pin(async move { Ok(existed) })
+    }
+
+    fn list(&self, prefix: String) -> KvStoreFuture<Vec<String>> {
+        let keys = self
+            .entries
+            .lock()
+            .unwrap()
+            .keys()
+            .filter(|k| k.starts_with(prefix.as_str()))
+            .cloned()
+            .collect();
+        Box::pin(async move { Ok(keys) })
+    }
+}
+
+/// the NativeModuleLoader which provides the `quickjs:store` module
+pub struct KvStoreModuleLoader {
+    provider: Arc<dyn KvStoreProvider>,
+    options: KvStoreOptions,
+}
+
+impl KvStoreModuleLoader {
+    pub fn new<P: KvStoreProvider + 'static>(provider: P, options: KvStoreOptions) -> Self {
+        Self {
+            provider: Arc::new(provider),
+            options,
+        }
+    }
+}
+
+impl NativeModuleLoader for KvStoreModuleLoader {
+    fn has_module(&self, _realm: &QuickJsRealmAdapter, module_name: &str) -> bool {
+        module_name.eq(MODULE_NAME)
+    }
+
+    fn get_module_export_names(
+        &self,
+        _realm: &QuickJsRealmAdapter,
+        _module_name: &str,
+    ) -> Vec<&str> {
+        vec!["get", "set", "delete", "list"]
+    }
+
+    fn get_module_exports(
+        &self,
+        realm: &QuickJsRealmAdapter,
+        _module_name: &str,
+    ) -> Vec<(&str, QuickJsValueAdapter)> {
+        let namespace = namespace(
+            self.options
+                .tenant_key
+                .as_deref()
+                .unwrap_or(realm.get_realm_id()),
+        );
+        let store = Arc::new(RealmStore {
+            provider: self.provider.clone(),
+            namespace,
+            max_value_size: self.options.max_value_size,
+        });
+
+        vec![
+            ("get", new_store_function(realm, "get", &store, js_get)),
+            ("set", new_store_function(realm, "set", &store, js_set)),
+            (
+                "delete",
+                new_store_function(realm, "delete", &store, js_delete),
+            ),
+            ("list", new_store_function(realm, "list", &store, js_list)),
+        ]
+    }
+}
+
+/// the prefix of the keys of a realm or tenant, the length of the id is part of it so no namespace is a prefix of another
+/// (e.g. the realm `a` and its child realm `a/1`)
+fn namespace(id: &str) -> String {
+    format!("{}:{}/", id.len(), id)
+}
+
+struct RealmStore {
+    provider: Arc<dyn KvStoreProvider>,
+    namespace: String,
+    max_value_size: usize,
+}
+
+impl RealmStore {
+    fn get_key(
+        &self,
+        realm: &QuickJsRealmAdapter,
+        func_name: &str,
+        args: &[QuickJsValueAdapter],
+    ) -> Result<String, JsError> {
+        match args.first() {
+            Some(arg) if arg.is_string() => Ok(format!(
+                "{}{}",
+                self.namespace,
+                primitives::to_string_q(realm, arg)?
+            )),
+            _ => Err(JsError::new(
+                "TypeError".to_string(),
+                format!("{func_name} requires a string key"),
+                "".to_string(),
+            )),
+        }
+    }
+}
+
+type StoreFunction = fn(
+    &QuickJsRealmAdapter,
+    &Arc<RealmStore>,
+    &[QuickJsValueAdapter],
+) -> Result<QuickJsValueAdapter, JsError>;
+
+fn new_store_function(
+    realm: &QuickJsRealmAdapter,
+    name: &str,
+    store: &Arc<RealmStore>,
+    func: StoreFunction,
+) -> QuickJsValueAdapter {
+    let store = store.clone();
+    functions::new_function_q(
+        realm,
+        name,
+        move |realm, _this, args| func(realm, &store, args),
+        1,
+    )
+    .expect("could not create store function")
+}
+
+fn js_get(
+    realm: &QuickJsRealmAdapter,
+    store: &Arc<RealmStore>,
+    args: &[QuickJsValueAdapter],
+) -> Result<QuickJsValueAdapter, JsError> {
+    let key = store.get_key(realm, "get", args)?;
+    realm.create_resolving_promise_async(store.provider.get(key), |realm, value| match value {
+        None => realm.create_undefined(),
+        Some(bytes) => deserialize_q(realm, bytes.as_slice()),
+    })
+}
+
+fn js_set(
+    realm: &QuickJsRealmAdapter,
+    store: &Arc<RealmStore>,
+    args: &[QuickJsValueAdapter],
+) -> Result<QuickJsValueAdapter, JsError> {
+    let key = store.get_key(realm, "set", args)?;
+    let value = match args.get(1) {
+        Some(value) => value,
+        None => {
+            return Err(JsError::new(
+                "TypeError".to_string(),
+                "set requires a value".to_string(),
+                "".to_string(),
+            ))
+        }
+    };
+    let bytes = serialize_q(realm, value)?;
+    if bytes.len() > store.max_value_size {
+        return Err(JsError::new(
+            "RangeError".to_string(),
+            format!(
+                "value of {} bytes exceeds the max size of {} bytes",
+                bytes.len(),
+                store.max_value_size
+            ),
+            "".to_string(),
+        ));
+    }
+    realm.create_resolving_promise_async(store.provider.set(key, bytes), |realm, _| {
+        realm.create_undefined()
+    })
+}
+
+fn js_delete(
+    realm: &QuickJsRealmAdapter,
+    store: &Arc<RealmStore>,
+    args: &[QuickJsValueAdapter],
+) -> Result<QuickJsValueAdapter, JsError> {
+    let key = store.get_key(realm, "delete", args)?;
+    realm.create_resolving_promise_async(store.provider.delete(key), |realm, existed| {
+        realm.create_boolean(existed)
+    })
+}
+
+fn js_list(
+    realm: &QuickJsRealmAdapter,
+    store: &Arc<RealmStore>,
+    args: &[QuickJsValueAdapter],
+) -> Result<QuickJsValueAdapter, JsError> {
+    // the prefix is optional, list() lists all keys of the realm
+    let prefix = if args.is_empty() || args[0].is_undefined() {
+        store.namespace.clone()
+    } else {
+        store.get_key(realm, "list", args)?
+    };
+    let namespace_len = store.namespace.len();
+    realm.create_resolving_promise_async(store.provider.list(prefix), move |realm, keys| {
+        let arr = arrays::create_array_q(realm)?;
+        for (index, key) in (0_u32..).zip(keys.iter()) {
+            let key = realm.create_string(&key[namespace_len..])?;
+            arrays::set_element_q(realm, &arr, index, &key)?;
+        }
+        Ok(arr)
+    })
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::features::kvstore::{KvStoreOptions, MemoryKvStoreProvider};
+    use crate::jsutils::Script;
+    use crate::values::JsValueFacade;
+
+    #[test]
+    fn test_kv_store() {
+        let rt = QuickJsRuntimeBuilder::new()
+            .kv_store(
+                MemoryKvStoreProvider::new(),
+                KvStoreOptions {
+                    tenant_key: None,
+                    max_value_size: 256,
+                },
+            )
+            .build();
+        rt.create_context("other_realm")
+            .expect("could not create realm");
+
+        let run = |realm: Option<&str>, code: &str| -> String {
+            let res = rt
+                .eval_sync(
+                    realm,
+                    Script::new(
+                        "test_kv_store.js",
+                        format!("import('quickjs:store').then(async (store) => {{{code}}});")
+                            .as_str(),
+                    ),
+                )
+                .expect("script failed");
+            match res {
+                JsValueFacade::JsPromise { cached_promise } => cached_promise
+                    .get_promise_result_sync()
+                    .expect("promise timed out")
+                    .expect("promise failed")
+                    .get_str()
+                    .to_string(),
+                _ => panic!("not a promise"),
+            }
+        };
+
+        assert_eq!(
+            run(
+                None,
+                "await store.set('a', {n: 1}); await store.set('b', [1, 2]); let a = await store.get('a'); return `${a.n}:${(await store.list()).join('|')}`;"
+            ),
+            "1:a|b"
+        );
+        // keys are namespaced per realm
+        assert_eq!(
+            run(
+                Some("other_realm"),
+                "let a = await store.get('a'); return `${a}:${(await store.list()).length}`;"
+            ),
+            "undefined:0"
+        );
+        assert_eq!(
+            run(
+                None,
+                "let deleted = await store.delete('a'); try {await store.set('c', 'x'.repeat(512));} catch(e) {return `${deleted}:${e.name}:${await store.get('a')}`;} return 'no error';"
+            ),
+            "true:RangeError:undefined"
+        );
+        // values are structured clones
+        assert_eq!(
+            run(
+                None,
+                "await store.set('v', {when: new Date(5), tags: new Set(['t']), nan: NaN, u: undefined, m: new Map([[1, -0]])}); let v = await store.get('v'); return [v.when.getTime(), v.tags.has('t'), Number.isNaN(v.nan), 'u' in v, Object.is(v.m.get(1), -0)].join(':');"
+            ),
+            "5:true:true:true:true"
+        );
+        // the namespace of a realm is not a prefix of the namespace of a child realm
+        rt.create_context("tenant").expect("could not create realm");
+        rt.create_context("tenant/1")
+            .expect("could not create realm");
+        assert_eq!(
+            run(
+                Some("tenant/1"),
+                "await store.set('x', 1); return `${(await store.list()).join('|')}`;"
+            ),
+            "x"
+        );
+        assert_eq!(
+            run(
+                Some("tenant"),
+                "return `${(await store.list()).length}:${await store.get('1/x')}`;"
+            ),
+            "0:undefined"
+        );
+    }
+}
@@ -0,0 +1,215 @@
+//! the `__limits` global which lets scripts read their limits and usage, see [limits_global](crate::builder::QuickJsRuntimeBuilder::limits_global)
+//!
+//! `__limits` is a frozen object with read-only getters
+//! * memoryLimit: the memory limit of the runtime in bytes, null when there is no limit
+//! * memoryUsage: the estimated number of bytes allocated by the runtime
+//! * memoryRemaining: the number of bytes left before the limit is reached, null when there is no limit
+//! * cpuRemaining: the number of milliseconds before the running script is interrupted (e.g. by the timeout of an eval), null when there is no deadline
+//! * timers: the number of pending timers in the realm
+//! * backgroundPolicy: the [BackgroundPolicy](crate::jsutils::taskscope::BackgroundPolicy) of the default eval options (detach, drain or cancel)
+//!
+//! computing the memory usage walks the heap, so the memory values are cached for [MEMORY_VALIDITY] and can be read in hot loops
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::Script;
+//! let rt = QuickJsRuntimeBuilder::new().memory_limit(64 * 1024 * 1024).limits_global().build();
+//! let res = rt.eval_sync(None, Script::new("limits.js", "__limits.memoryRemaining > 0 && __limits.memoryLimit === 64 * 1024 * 1024")).expect("script failed");
+//! assert!(res.get_bool());
+//! ```
+
+use crate::jsutils::taskscope::BackgroundPolicy;
+use crate::jsutils::JsError;
+use crate::quickjs_utils::{functions, get_global_q, objects};
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use crate::quickjsvalueadapter::QuickJsValueAdapter;
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+/// how long a computed memory usage is reused
+pub const MEMORY_VALIDITY: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Copy)]
+struct MemorySnapshot {
+    taken: Instant,
+    size: i64,
+    // None when there is no limit
+    limit: Option<i64>,
+}
+
+thread_local! {
+    static MEMORY_SNAPSHOT: Cell<Option<MemorySnapshot>> = Cell::new(None);
+}
+
+fn memory_snapshot() -> MemorySnapshot {
+    let now = Instant::now();
+    match MEMORY_SNAPSHOT.with(|s| s.get()) {
+        Some(snapshot) if now.duration_since(snapshot.taken) < MEMORY_VALIDITY => snapshot,
+        _ => {
+            let usage = QuickJsRuntimeAdapter::do_with(|q_js_rt| q_js_rt.memory_usage());
+            // a runtime without a limit reports the max size_t
+            let snapshot = MemorySnapshot {
+                taken: now,
+                size: usage.malloc_size,
+                limit: Some(usage.malloc_limit).filter(|limit| *limit > 0),
+            };
+            MEMORY_SNAPSHOT.with(|s| s.set(Some(snapshot)));
+            snapshot
+        }
+    }
+}
+
+fn create_optional_number(
+    realm: &QuickJsRealmAdapter,
+    value: Option<i64>,
+) -> Result<QuickJsValueAdapter, JsError> {
+    match value {
+        Some(value) => realm.create_f64(value as f64),
+        None => realm.create_null(),
+    }
+}
+
+fn limit_value(realm: &QuickJsRealmAdapter, name: &str) -> Result<QuickJsValueAdapter, JsError> {
+    match name {
+        "memoryLimit" => create_optional_number(realm, memory_snapshot().limit),
+        "memoryUsage" => realm.create_f64(memory_snapshot().size as f64),
+        "memoryRemaining" => {
+            let snapshot = memory_snapshot();
+            create_optional_number(
+                realm,
+                snapshot.limit.map(|limit| (limit - snapshot.size).max(0)),
+            )
+        }
+        "cpuRemaining" => {
+            let deadline =
+                QuickJsRuntimeAdapter::do_with(|q_js_rt| q_js_rt.interrupt_deadline.get());
+            create_optional_number(
+                realm,
+                deadline.map(|deadline| {
+                    deadline
+                        .saturating_duration_since(Instant::now())
+                        .as_millis() as i64
+                }),
+            )
+        }
+        "timers" => realm.create_i32(
+            (realm.timeout_ids.borrow().len() + realm.interval_ids.borrow().len()) as i32,
+        ),
+        "backgroundPolicy" => {
+            let policy = QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+                q_js_rt
+                    .default_eval_options
+                    .background_policy
+                    .unwrap_or_default()
+            });
+            realm.create_string(match policy {
+                BackgroundPolicy::Detach => "detach",
+                BackgroundPolicy::Drain => "drain",
+                BackgroundPolicy::Cancel => "cancel",
+            })
+        }
+        _ => realm.create_undefined(),
+    }
+}
+
+const NAMES: [&str; 6] = [
+    "memoryLimit",
+    "memoryUsage",
+    "memoryRemaining",
+    "cpuRemaining",
+    "timers",
+    "backgroundPolicy",
+];
+
+/// install `__limits` in every realm
+pub(crate) fn init(q_js_rt: &QuickJsRuntimeAdapter) -> Result<(), JsError> {
+    q_js_rt.add_context_init_hook(|_q_js_rt, realm| init_ctx(realm))
+}
+
+fn init_ctx(realm: &QuickJsRealmAdapter) -> Result<(), JsError> {
+    let limits = realm.create_object()?;
+    let setter = functions::new_function_q(
+        realm,
+        "set",
+        |_realm, _this, _args| {
+            Err(JsError::new(
+                "TypeError".to_string(),
+                "__limits is read-only".to_string(),
+                "".to_string(),
+            ))
+        },
+        1,
+    )?;
+    for name in NAMES {
+        let getter = functions::new_function_q(
+            realm,
+            name,
+            move |realm, _this, _args| limit_value(realm, name),
+            0,
+        )?;
+        objects::define_getter_setter_q(realm, &limits, name, &getter, &setter)?;
+    }
+    let global = get_global_q(realm);
+    let object = objects::get_property_q(realm, &global, "Object")?;
+    functions::invoke_member_function_q(realm, &object, "freeze", &[limits.dup()])?;
+    // not writable, not enumerable and not configurable
+    objects::set_property2_q(realm, &global, "__limits", &limits, 0)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::taskscope::{BackgroundPolicy, EvalOptions};
+    use crate::jsutils::Script;
+    use std::time::Duration;
+
+    #[test]
+    fn test_limits_global() {
+        let rt = QuickJsRuntimeBuilder::new()
+            .memory_limit(64 * 1024 * 1024)
+            .default_eval_options(
+                EvalOptions::new()
+                    .background_policy(BackgroundPolicy::Drain)
+                    .timeout(Duration::from_secs(10)),
+            )
+            .limits_global()
+            .build();
+        let eval = |code: &str| {
+            rt.eval_sync(None, Script::new("test_limits_global.js", code))
+                .expect("script failed")
+        };
+
+        assert!(eval("__limits.memoryLimit === 64 * 1024 * 1024").get_bool());
+        assert!(eval("__limits.memoryUsage > 0 && __limits.memoryRemaining > 0").get_bool());
+        assert!(eval("__limits.cpuRemaining > 0 && __limits.cpuRemaining <= 10000").get_bool());
+        assert_eq!(eval("__limits.backgroundPolicy").get_str(), "drain");
+        assert_eq!(
+            eval("setTimeout(() => {}, 1000); setInterval(() => {}, 1000); __limits.timers")
+                .get_i32(),
+            2
+        );
+
+        // read-only and frozen
+        assert!(eval(
+            "'use strict'; let threw = false; try { __limits.memoryLimit = 1; } catch (e) { threw = e.name === 'TypeError'; } threw"
+        )
+        .get_bool());
+        assert!(eval("Object.isFrozen(__limits)").get_bool());
+        assert!(eval("delete globalThis.__limits; typeof __limits === 'object'").get_bool());
+        assert!(eval("globalThis.__limits = null; __limits !== null").get_bool());
+
+        // reading in a hot loop is cheap
+        assert!(eval("let total = 0; for (let i = 0; i < 100000; i++) { total += __limits.memoryUsage > 0 ? 1 : 0; } total === 100000").get_bool());
+
+        // absent unless enabled
+        let rt = QuickJsRuntimeBuilder::new().build();
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new("test_limits_global.js", "typeof __limits"),
+            )
+            .expect("script failed");
+        assert_eq!(res.get_str(), "undefined");
+    }
+}
@@ -0,0 +1,512 @@
+//! reacting to memory pressure, see [memory_pressure](crate::builder::QuickJsRuntimeBuilder::memory_pressure)
+//!
+//! the memory usage of the runtime is compared with its [memory_limit](crate::builder::QuickJsRuntimeBuilder::memory_limit) every check interval,
+//! a runtime without a memory limit is never under pressure
+//!
+//! * the pressure is moderate from the `moderate` fraction of the limit and critical from the `critical` fraction
+//! * the hook of the options is called with a [MemoryPressureEvent] in the worker thread of the runtime
+//! * with [script_api](MemoryPressureOptions::script_api) `__runtime.onMemoryPressure(listener)` adds a listener to a realm and returns a function which removes it,
+//!   the listeners are called with an object with used, limit and level (moderate or critical)
+//! * with [allow_gc](MemoryPressureOptions::allow_gc) scripts can run the garbage collector with `__runtime.gc()`
+//! * while the pressure lasts the hook and the listeners of a realm are called at most once per min_interval, unless the level rises,
+//!   so a runtime which is just around a threshold does not call them on every check
+//! * errors thrown by listeners are passed to the [uncaught_error_hook](crate::builder::QuickJsRuntimeBuilder::uncaught_error_hook)
+//!
+//! when an allocation would exceed the limit the [memory_limit_exceeded_handler](crate::builder::QuickJsRuntimeBuilder::memory_limit_exceeded_handler)
+//! is called in the worker thread before the allocation fails, it gets a [MemoryLimitExceeded] and returns a [MemoryLimitAction]:
+//!
+//! * [Fail](MemoryLimitAction::Fail) keeps the limit, the allocation fails and the script gets an `InternalError: out of memory`,
+//!   when that error reaches the embedder its [kind](JsError::kind) is [OutOfMemory](crate::jsutils::JsErrorKind::OutOfMemory)
+//! * [Raise](MemoryLimitAction::Raise) sets a new limit, when the allocation fits in it the allocation succeeds and the script goes on
+//!
+//! the handler is called for every allocation which hits the limit, also when the script catches the error. It is not called
+//! while a lower limit of an [isolated eval](crate::facades::QuickJsRuntimeFacade::eval_isolated) is active or for the allocations
+//! which are done by the handler itself. The handler runs in the middle of an allocation of QuickJS so it may not use the runtime.
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::features::memorypressure::MemoryPressureOptions;
+//! use quickjs_runtime::jsutils::Script;
+//! use std::time::Duration;
+//! let rt = QuickJsRuntimeBuilder::new()
+//!     .memory_limit(64 * 1024 * 1024)
+//!     .memory_pressure(MemoryPressureOptions::new().moderate(0.0).check_interval(Duration::from_millis(10)).script_api())
+//!     .build();
+//! rt.eval_sync(None, Script::new("pressure.js", "globalThis.levels = []; __runtime.onMemoryPressure(e => levels.push(e.level));")).expect("script failed");
+//! std::thread::sleep(Duration::from_millis(100));
+//! let res = rt.eval_sync(None, Script::new("pressure.js", "levels[0]")).expect("script failed");
+//! assert_eq!(res.get_str(), "moderate");
+//! ```
+
+use crate::jsutils::{uncaught, JsError};
+use crate::quickjs_utils::{functions, objects};
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use crate::quickjsvalueadapter::QuickJsValueAdapter;
+use hirofa_utils::eventloop::EventLoop;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// a hook which is called when the runtime is under memory pressure
+pub type MemoryPressureHook = Arc<dyn Fn(&MemoryPressureEvent) + Send + Sync>;
+
+/// a handler which is called when an allocation would exceed the memory limit, see [memorypressure](self)
+pub type MemoryLimitExceededHandler =
+    Arc<dyn Fn(&MemoryLimitExceeded) -> MemoryLimitAction + Send + Sync>;
+
+/// what to do with an allocation which would exceed the memory limit
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryLimitAction {
+    /// keep the limit, the allocation fails
+    Fail,
+    /// set a new limit in bytes, the allocation succeeds when it fits in the new limit
+    Raise(u64),
+}
+
+/// the usage of the runtime when an allocation would exceed the memory limit, the sizes are in bytes
+#[derive(Clone, Debug)]
+pub struct MemoryLimitExceeded {
+    /// the number of bytes allocated by the runtime
+    pub used: u64,
+    /// the size of the allocation which would exceed the limit
+    pub requested: u64,
+    /// the memory limit of the runtime
+    pub limit: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MemoryPressureLevel {
+    Moderate,
+    Critical,
+}
+
+impl MemoryPressureLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MemoryPressureLevel::Moderate => "moderate",
+            MemoryPressureLevel::Critical => "critical",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MemoryPressureEvent {
+    /// the number of bytes allocated by the runtime
+    pub used: i64,
+    /// the memory limit of the runtime in bytes
+    pub limit: i64,
+    pub level: MemoryPressureLevel,
+}
+
+/// the thresholds and the subscribers of memory pressure events
+#[derive(Clone)]
+pub struct MemoryPressureOptions {
+    moderate: f64,
+    critical: f64,
+    check_interval: Duration,
+    min_interval: Duration,
+    script_api: bool,
+    allow_gc: bool,
+    hook: Option<MemoryPressureHook>,
+}
+
+impl Default for MemoryPressureOptions {
+    fn default() -> Self {
+        Self {
+            moderate: 0.7,
+            critical: 0.9,
+            check_interval: Duration::from_secs(1),
+            min_interval: Duration::from_secs(10),
+            script_api: false,
+            allow_gc: false,
+            hook: None,
+        }
+    }
+}
+
+impl MemoryPressureOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// the fraction of the memory limit from which the pressure is moderate, 0.7 by default
+    pub fn moderate(mut self, fraction: f64) -> Self {
+        self.moderate = fraction;
+        self
+    }
+    /// the fraction of the memory limit from which the pressure is critical, 0.9 by default
+    pub fn critical(mut self, fraction: f64) -> Self {
+        self.critical = fraction;
+        self
+    }
+    /// how often the memory usage is computed, this walks the heap so it should not be too often, 1 second by default
+    pub fn check_interval(mut self, interval: Duration) -> Self {
+        self.check_interval = interval;
+        self
+    }
+    /// the min time between two events with the same level, 10 seconds by default
+    pub fn min_interval(mut self, interval: Duration) -> Self {
+        self.min_interval = interval;
+        self
+    }
+    /// add `__runtime.onMemoryPressure` to every realm
+    pub fn script_api(mut self) -> Self {
+        self.script_api = true;
+        self
+    }
+    /// add `__runtime.gc` to every realm
+    pub fn allow_gc(mut self) -> Self {
+        self.allow_gc = true;
+        self
+    }
+    /// call a hook for memory pressure events
+    pub fn on_pressure<H>(mut self, hook: H) -> Self
+    where
+        H: Fn(&MemoryPressureEvent) + Send + Sync + 'static,
+    {
+        self.hook = Some(Arc::new(hook));
+        self
+    }
+
+    fn level_of(&self, used: i64, limit: i64) -> Option<MemoryPressureLevel> {
+        let fraction = used as f64 / limit as f64;
+        if fraction >= self.critical {
+            Some(MemoryPressureLevel::Critical)
+        } else if fraction >= self.moderate {
+            Some(MemoryPressureLevel::Moderate)
+        } else {
+            None
+        }
+    }
+}
+
+// when an event was passed on and its level
+type LastEvent = Option<(Instant, MemoryPressureLevel)>;
+
+/// the listeners of a realm
+#[derive(Default)]
+pub(crate) struct RealmMemoryPressure {
+    listeners: Vec<(u32, QuickJsValueAdapter)>,
+    next_id: u32,
+    last_event: LastEvent,
+}
+
+struct Monitor {
+    options: MemoryPressureOptions,
+    last_event: Cell<LastEvent>,
+}
+
+thread_local! {
+    static MONITOR: RefCell<Option<Rc<Monitor>>> = RefCell::new(None);
+}
+
+fn should_fire(
+    last_event: LastEvent,
+    level: MemoryPressureLevel,
+    now: Instant,
+    min: Duration,
+) -> bool {
+    match last_event {
+        Some((at, last_level)) => level > last_level || now.duration_since(at) >= min,
+        None => true,
+    }
+}
+
+/// start checking the memory usage and install the script api
+pub(crate) fn init(
+    q_js_rt: &QuickJsRuntimeAdapter,
+    options: MemoryPressureOptions,
+) -> Result<(), JsError> {
+    let check_interval = options.check_interval;
+    let script_api = options.script_api;
+    let allow_gc = options.allow_gc;
+    MONITOR.with(|m| {
+        m.replace(Some(Rc::new(Monitor {
+            options,
+            last_event: Cell::new(None),
+        })))
+    });
+    EventLoop::add_interval(
+        || QuickJsRuntimeAdapter::do_with(check_q),
+        check_interval,
+        check_interval,
+    );
+    if script_api || allow_gc {
+        q_js_rt
+            .add_context_init_hook(move |_q_js_rt, realm| init_ctx(realm, script_api, allow_gc))?;
+    }
+    Ok(())
+}
+
+fn init_ctx(realm: &QuickJsRealmAdapter, script_api: bool, allow_gc: bool) -> Result<(), JsError> {
+    let runtime_ns = objects::get_namespace_q(realm, &["__runtime"], true)?;
+    if script_api {
+        let on_func = functions::new_function_q(
+            realm,
+            "onMemoryPressure",
+            |realm, _this, args| add_listener(realm, args),
+            1,
+        )?;
+        objects::set_property2_q(realm, &runtime_ns, "onMemoryPressure", &on_func, 0)?;
+    }
+    if allow_gc {
+        let gc_func = functions::new_function_q(
+            realm,
+            "gc",
+            |realm, _this, _args| {
+                QuickJsRuntimeAdapter::do_with(|q_js_rt| q_js_rt.gc());
+                realm.create_undefined()
+            },
+            0,
+        )?;
+        objects::set_property2_q(realm, &runtime_ns, "gc", &gc_func, 0)?;
+    }
+    Ok(())
+}
+
+fn add_listener(
+    realm: &QuickJsRealmAdapter,
+    args: &[QuickJsValueAdapter],
+) -> Result<QuickJsValueAdapter, JsError> {
+    let listener = match args.first() {
+        Some(listener) if listener.is_function() => listener.dup(),
+        _ => {
+            return Err(JsError::new(
+                "TypeError".to_string(),
+                "onMemoryPressure requires a function".to_string(),
+                "".to_string(),
+            ))
+        }
+    };
+    let id = {
+        let state = &mut *realm.memory_pressure.borrow_mut();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.listeners.push((id, listener));
+        id
+    };
+    functions::new_function_q(
+        realm,
+        "removeMemoryPressureListener",
+        move |realm, _this, _args| {
+            // take the listener out of the realm first, dropping it may run finalizers
+            let removed = {
+                let listeners = &mut realm.memory_pressure.borrow_mut().listeners;
+                listeners
+                    .iter()
+                    .position(|(listener_id, _)| *listener_id == id)
+                    .map(|index| listeners.remove(index))
+            };
+            drop(removed);
+            realm.create_undefined()
+        },
+        0,
+    )
+}
+
+fn create_event(
+    realm: &QuickJsRealmAdapter,
+    event: &MemoryPressureEvent,
+) -> Result<QuickJsValueAdapter, JsError> {
+    let obj = realm.create_object()?;
+    realm.set_object_property(&obj, "used", &realm.create_f64(event.used as f64)?)?;
+    realm.set_object_property(&obj, "limit", &realm.create_f64(event.limit as f64)?)?;
+    realm.set_object_property(&obj, "level", &realm.create_string(event.level.as_str())?)?;
+    Ok(obj)
+}
+
+/// compute the memory usage and pass an event to the hook and the listeners when the runtime is under pressure
+pub(crate) fn check_q(q_js_rt: &QuickJsRuntimeAdapter) {
+    let monitor = match MONITOR.with(|m| m.borrow().clone()) {
+        Some(monitor) => monitor,
+        None => return,
+    };
+    let limit = match q_js_rt.allocator.memory_limit() {
+        Some(limit) if limit > 0 => limit as i64,
+        _ => return,
+    };
+    let used = q_js_rt.memory_usage().malloc_size;
+    let level = match monitor.options.level_of(used, limit) {
+        Some(level) => level,
+        None => return,
+    };
+    let event = MemoryPressureEvent { used, limit, level };
+    let now = Instant::now();
+    let min_interval = monitor.options.min_interval;
+
+    if should_fire(monitor.last_event.get(), level, now, min_interval) {
+        monitor.last_event.set(Some((now, level)));
+        if let Some(hook) = &monitor.options.hook {
+            hook(&event);
+        }
+    }
+
+    if !monitor.options.script_api {
+        return;
+    }
+    for realm in q_js_rt.contexts.values() {
+        let listeners: Vec<QuickJsValueAdapter> = {
+            let state = &mut *realm.memory_pressure.borrow_mut();
+            if state.listeners.is_empty()
+                || !should_fire(state.last_event, level, now, min_interval)
+            {
+                continue;
+            }
+            state.last_event = Some((now, level));
+            state.listeners.iter().map(|(_, l)| l.dup()).collect()
+        };
+        let res = create_event(realm, &event).map(|event_obj| {
+            for listener in &listeners {
+                if let Err(e) =
+                    functions::call_function_q(realm, listener, &[event_obj.dup()], None)
+                {
+                    uncaught::report_uncaught_q(realm, "onMemoryPressure", &e);
+                }
+            }
+        });
+        if let Err(e) = res {
+            uncaught::report_uncaught_q(realm, "onMemoryPressure", &e);
+        }
+    }
+    q_js_rt.run_pending_jobs_if_any();
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::features::memorypressure::{
+        check_q, MemoryLimitAction, MemoryPressureLevel, MemoryPressureOptions,
+    };
+    use crate::jsutils::{JsErrorKind, Script};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    const ALLOCATE_SCRIPT: &str =
+        "(() => { let a = []; while (true) { a.push('x'.repeat(1024) + a.length); } })()";
+
+    #[test]
+    fn test_memory_limit_exceeded_handler() {
+        let limits = Arc::new(Mutex::new(vec![]));
+        let limits2 = limits.clone();
+        let rt = QuickJsRuntimeBuilder::new()
+            .memory_limit(2 * 1024 * 1024)
+            .memory_limit_exceeded_handler(move |exceeded| {
+                limits2.lock().unwrap().push(exceeded.limit);
+                MemoryLimitAction::Fail
+            })
+            .build();
+        let err = rt
+            .eval_sync(None, Script::new("test_memory_limit.js", ALLOCATE_SCRIPT))
+            .expect_err("script should run out of memory");
+        assert_eq!(err.kind(), JsErrorKind::OutOfMemory, "{err}");
+        assert!(!limits.lock().unwrap().is_empty());
+        assert!(limits
+            .lock()
+            .unwrap()
+            .iter()
+            .all(|limit| *limit == 2 * 1024 * 1024));
+
+        // the handler is also called when the script catches the error
+        limits.lock().unwrap().clear();
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "test_memory_limit.js",
+                    "try { 'x'.repeat(4 * 1024 * 1024).length } catch (e) { 'caught' }",
+                ),
+            )
+            .expect("script failed");
+        assert_eq!(res.get_str(), "caught");
+        assert!(!limits.lock().unwrap().is_empty());
+
+        // the allocation which hit the limit succeeds with the raised limit
+        let rt = QuickJsRuntimeBuilder::new()
+            .memory_limit(2 * 1024 * 1024)
+            .memory_limit_exceeded_handler(|_exceeded| MemoryLimitAction::Raise(64 * 1024 * 1024))
+            .build();
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new("test_memory_limit.js", "'x'.repeat(4 * 1024 * 1024).length"),
+            )
+            .expect("script failed");
+        assert_eq!(res.get_i32(), 4 * 1024 * 1024);
+        let usage = futures::executor::block_on(rt.memory_usage());
+        assert_eq!(usage.malloc_limit, 64 * 1024 * 1024);
+    }
+
+    #[test]
+    fn test_memory_pressure() {
+        let events = Arc::new(Mutex::new(vec![]));
+        let events2 = events.clone();
+        let errors = Arc::new(Mutex::new(vec![]));
+        let errors2 = errors.clone();
+        let rt = QuickJsRuntimeBuilder::new()
+            .memory_limit(64 * 1024 * 1024)
+            .uncaught_error_hook(move |realm_id, source, err| {
+                errors2
+                    .lock()
+                    .unwrap()
+                    .push(format!("{realm_id} {source}: {}", err.get_message()));
+            })
+            .memory_pressure(
+                MemoryPressureOptions::new()
+                    .moderate(0.0)
+                    .critical(2.0)
+                    // only the explicit checks of the test run
+                    .check_interval(Duration::from_secs(3600))
+                    .min_interval(Duration::from_secs(3600))
+                    .script_api()
+                    .allow_gc()
+                    .on_pressure(move |event| events2.lock().unwrap().push(event.level)),
+            )
+            .build();
+        rt.create_realm("other").expect("could not create realm");
+
+        rt.eval_sync(
+            None,
+            Script::new(
+                "test_memory_pressure.js",
+                "globalThis.events = []; \
+                 __runtime.onMemoryPressure(e => events.push(`${e.level}:${e.used > 0}:${e.limit}`)); \
+                 __runtime.onMemoryPressure(() => { throw Error('listener failed'); }); \
+                 __runtime.gc();",
+            ),
+        )
+        .expect("script failed");
+        rt.eval_sync(
+            Some("other"),
+            Script::new(
+                "test_memory_pressure.js",
+                "globalThis.events = []; let off = __runtime.onMemoryPressure(e => events.push(e.level)); off();",
+            ),
+        )
+        .expect("script failed");
+
+        // the second check is rate-limited
+        rt.exe_rt_task_in_event_loop(check_q);
+        rt.exe_rt_task_in_event_loop(check_q);
+
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new("test_memory_pressure.js", "events.join(',')"),
+            )
+            .expect("script failed");
+        assert_eq!(res.get_str(), "moderate:true:67108864");
+        let res = rt
+            .eval_sync(
+                Some("other"),
+                Script::new("test_memory_pressure.js", "events.length"),
+            )
+            .expect("script failed");
+        assert_eq!(res.get_i32(), 0);
+        assert_eq!(*events.lock().unwrap(), vec![MemoryPressureLevel::Moderate]);
+        assert_eq!(
+            *errors.lock().unwrap(),
+            vec!["__main__ onMemoryPressure: listener failed".to_string()]
+        );
+    }
+}
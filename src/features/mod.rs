@@ -2,12 +2,58 @@
 
 use crate::facades::QuickJsRuntimeFacade;
 use crate::jsutils::JsError;
+use crate::quickjs_utils::objects;
+use libquickjs_sys as q;
+pub mod buffer;
+pub mod commonjs;
 #[cfg(feature = "console")]
 pub mod console;
+pub mod coverage;
+pub mod crypto;
+#[cfg(feature = "decimal")]
+pub mod decimal;
+pub mod encoding;
+pub mod encoding_globals;
+pub mod kvstore;
+pub mod limits;
+pub mod memorypressure;
+pub mod queue_microtask;
+pub mod random;
+pub mod realms;
 #[cfg(any(feature = "settimeout", feature = "setinterval"))]
 pub mod set_timeout;
 #[cfg(feature = "setimmediate")]
 pub mod setimmediate;
+pub mod structured_clone;
+pub mod structuredlog;
+pub mod testing;
+pub mod timeslice;
+
+/// the features which are installed when no bundle was selected with [web_platform_defaults](crate::builder::QuickJsRuntimeBuilder::web_platform_defaults)
+pub(crate) const DEFAULT_FEATURES: &[&str] = &[
+    "console",
+    "setTimeout",
+    "setInterval",
+    "setImmediate",
+    "structuredClone",
+];
+
+/// the flags of the globals which features install, they are writable and configurable so a realm may still override them
+pub(crate) const WRITABLE_GLOBAL: i32 = (q::JS_PROP_WRITABLE | q::JS_PROP_CONFIGURABLE) as i32;
+
+/// check if a feature was compiled into this build
+pub fn is_feature_available(name: &str) -> bool {
+    match name {
+        "console" => cfg!(feature = "console"),
+        "setTimeout" => cfg!(feature = "settimeout"),
+        "setInterval" => cfg!(feature = "setinterval"),
+        "setImmediate" => cfg!(feature = "setimmediate"),
+        name => matches!(
+            name,
+            "queueMicrotask" | "encoding" | "structuredClone" | "crypto"
+        ),
+    }
+}
 
 #[cfg(any(
     feature = "settimeout",
@@ -16,16 +62,70 @@ pub mod setimmediate;
     feature = "setimmediate"
 ))]
 pub fn init(es_rt: &QuickJsRuntimeFacade) -> Result<(), JsError> {
+    init_features(
+        es_rt,
+        DEFAULT_FEATURES.iter().map(|f| f.to_string()).collect(),
+        false,
+    )
+}
+
+/// install a set of features, when expose_features is true the names of the installed features are added to every realm as `__runtime.features`
+pub(crate) fn init_features(
+    es_rt: &QuickJsRuntimeFacade,
+    feature_names: Vec<String>,
+    expose_features: bool,
+) -> Result<(), JsError> {
     log::trace!("features::init");
 
+    let installed: Vec<String> = feature_names
+        .into_iter()
+        .filter(|name| is_feature_available(name))
+        .collect();
+
     es_rt.exe_rt_task_in_event_loop(move |q_js_rt| {
+        let has = |name: &str| installed.iter().any(|f| f.eq(name));
+
         #[cfg(feature = "console")]
-        console::init(q_js_rt)?;
+        if has("console") {
+            console::init(q_js_rt)?;
+        }
         #[cfg(feature = "setimmediate")]
-        setimmediate::init(q_js_rt)?;
+        if has("setImmediate") {
+            setimmediate::init(q_js_rt)?;
+        }
 
         #[cfg(any(feature = "settimeout", feature = "setinterval"))]
-        set_timeout::init(q_js_rt)?;
+        if has("setTimeout") || has("setInterval") {
+            set_timeout::init_timers(q_js_rt, has("setTimeout"), has("setInterval"))?;
+        }
+
+        if has("queueMicrotask") {
+            queue_microtask::init(q_js_rt)?;
+        }
+
+        if has("encoding") {
+            encoding_globals::init(q_js_rt)?;
+        }
+
+        if has("structuredClone") {
+            structured_clone::init(q_js_rt)?;
+        }
+
+        if has("crypto") {
+            crypto::init(q_js_rt)?;
+        }
+
+        if expose_features {
+            q_js_rt.add_context_init_hook(move |_q_js_rt, q_ctx| {
+                let runtime_ns = objects::get_namespace_q(q_ctx, &["__runtime"], true)?;
+                let features_arr = q_ctx.create_array()?;
+                for (index, name) in (0_u32..).zip(installed.iter()) {
+                    q_ctx.set_array_element(&features_arr, index, &q_ctx.create_string(name)?)?;
+                }
+                objects::set_property2_q(q_ctx, &runtime_ns, "features", &features_arr, 0)?;
+                Ok(())
+            })?;
+        }
         Ok(())
     })
 }
@@ -0,0 +1,112 @@
+use crate::features::WRITABLE_GLOBAL;
+use crate::jsutils::JsError;
+use crate::quickjs_utils;
+use crate::quickjs_utils::{functions, get_global_q, objects, parse_args};
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use libquickjs_sys as q;
+
+/// provides the queueMicrotask method for the runtime
+/// # Example
+/// ```rust
+/// use quickjs_runtime::builder::{QuickJsRuntimeBuilder, WebDefaults};
+/// use quickjs_runtime::jsutils::Script;
+/// let rt = QuickJsRuntimeBuilder::new().web_platform_defaults(WebDefaults::Minimal).build();
+/// rt.eval_sync(None, Script::new("test_microtask.es", "queueMicrotask(() => {console.log('microtask logging')});")).expect("script failed");
+/// ```
+pub fn init(q_js_rt: &QuickJsRuntimeAdapter) -> Result<(), JsError> {
+    log::trace!("queue_microtask::init");
+
+    q_js_rt.add_context_init_hook(|_q_js_rt, q_ctx| {
+        let queue_microtask_func = functions::new_native_function_q(
+            q_ctx,
+            "queueMicrotask",
+            Some(queue_microtask),
+            1,
+            false,
+        )?;
+
+        let global = get_global_q(q_ctx);
+
+        objects::set_property2_q(
+            q_ctx,
+            &global,
+            "queueMicrotask",
+            &queue_microtask_func,
+            WRITABLE_GLOBAL,
+        )?;
+        Ok(())
+    })?;
+    Ok(())
+}
+
+unsafe extern "C" fn queue_microtask(
+    context: *mut q::JSContext,
+    _this_val: q::JSValue,
+    argc: ::std::os::raw::c_int,
+    argv: *mut q::JSValue,
+) -> q::JSValue {
+    log::trace!("> queue_microtask");
+
+    let args = parse_args(context, argc, argv);
+
+    QuickJsRuntimeAdapter::do_with(move |q_js_rt| {
+        let q_ctx = q_js_rt.get_quickjs_context(context);
+        if args.is_empty() {
+            return q_ctx.report_ex("queueMicrotask requires one argument");
+        }
+        if !functions::is_function(context, &args[0]) {
+            return q_ctx.report_ex("queueMicrotask requires a function as first arg");
+        }
+
+        // the job queue dups the args so we can just pass the raw value
+        let mut job_args = [*args[0].borrow_value()];
+        let res = q::JS_EnqueueJob(context, Some(run_microtask), 1, job_args.as_mut_ptr());
+        if res < 0 {
+            return q_ctx.report_ex("could not enqueue microtask");
+        }
+
+        quickjs_utils::new_undefined()
+    })
+}
+
+unsafe extern "C" fn run_microtask(
+    context: *mut q::JSContext,
+    argc: ::std::os::raw::c_int,
+    argv: *mut q::JSValue,
+) -> q::JSValue {
+    let args = parse_args(context, argc, argv);
+    if let Err(e) = functions::call_function(context, &args[0], &[], None) {
+        log::error!("queueMicrotask callback failed: {}", e);
+    }
+    quickjs_utils::new_undefined()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::{QuickJsRuntimeBuilder, WebDefaults};
+    use crate::jsutils::Script;
+
+    #[test]
+    fn test_queue_microtask() {
+        let rt = QuickJsRuntimeBuilder::new()
+            .web_platform_defaults(WebDefaults::Minimal)
+            .build();
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "test_queue_microtask.es",
+                    "globalThis.order = []; queueMicrotask(() => {order.push('micro');}); order.push('sync'); 1;",
+                ),
+            )
+            .expect("script failed");
+        assert_eq!(res.get_i32(), 1);
+        let order = rt
+            .eval_sync(
+                None,
+                Script::new("test_queue_microtask2.es", "order.join(',');"),
+            )
+            .expect("script failed");
+        assert_eq!(order.get_str(), "sync,micro");
+    }
+}
@@ -0,0 +1,142 @@
+//! a seedable Math.random for reproducible runs, see [RealmOptions::random_seed](crate::quickjsrealmadapter::RealmOptions::random_seed)
+//!
+//! a seeded realm gets a native Math.random which is backed by a xoshiro256** generator, the state of that generator is kept per realm
+//! and can be read and restored with [get_random_state](crate::facades::QuickJsRuntimeFacade::get_random_state) and [set_random_state](crate::facades::QuickJsRuntimeFacade::set_random_state)
+//! so a run can be checkpointed and replayed
+//!
+//! the generator is not cryptographically secure, it only replaces Math.random, [crypto](crate::features::crypto) is not affected and remains a CSPRNG
+
+use crate::jsutils::JsError;
+use crate::quickjs_utils::{functions, get_global_q, objects};
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+
+/// the state of the Math.random generator of a seeded realm
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RandomState {
+    seed: u64,
+    words: [u64; 4],
+}
+
+impl RandomState {
+    /// create the initial state for a seed, the words are derived from the seed with splitmix64
+    pub fn from_seed(seed: u64) -> Self {
+        let mut x = seed;
+        let mut next = || {
+            x = x.wrapping_add(0x9E3779B97F4A7C15);
+            let mut z = x;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+            z ^ (z >> 31)
+        };
+        Self {
+            seed,
+            words: [next(), next(), next(), next()],
+        }
+    }
+    /// the seed which the realm was created with
+    pub fn get_seed(&self) -> u64 {
+        self.seed
+    }
+    /// the current state of the generator
+    pub fn get_words(&self) -> [u64; 4] {
+        self.words
+    }
+    /// restore a state which was read with [get_words](Self::get_words)
+    pub fn from_words(seed: u64, words: [u64; 4]) -> Self {
+        Self { seed, words }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let s = &mut self.words;
+        let res = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+        res
+    }
+
+    /// the next number in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+    }
+}
+
+/// replace Math.random of a realm with the seeded generator
+pub(crate) fn install_q(realm: &QuickJsRealmAdapter, state: RandomState) -> Result<(), JsError> {
+    let replaced = realm.random_state.replace(Some(state)).is_some();
+    if replaced {
+        return Ok(());
+    }
+    let random_func = functions::new_function_q(
+        realm,
+        "random",
+        |realm, _this, _args| {
+            let mut state = realm
+                .random_state
+                .get()
+                .ok_or_else(|| JsError::new_str("Math.random is not seeded in this realm"))?;
+            let res = state.next_f64();
+            realm.random_state.set(Some(state));
+            realm.create_f64(res)
+        },
+        0,
+    )?;
+    let math = objects::get_property_q(realm, &get_global_q(realm), "Math")?;
+    objects::set_property_q(realm, &math, "random", &random_func)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::features::random::RandomState;
+    use crate::jsutils::Script;
+    use crate::quickjsrealmadapter::RealmOptions;
+
+    #[test]
+    fn test_seeded_random() {
+        let rt = QuickJsRuntimeBuilder::new().build();
+        let script = "Array.from({length: 5}, () => Math.random()).join(',')";
+        let run = |realm: &str| {
+            rt.eval_sync(Some(realm), Script::new("test_random.js", script))
+                .expect("script failed")
+                .get_str()
+                .to_string()
+        };
+
+        for realm in ["seeded_a", "seeded_b"] {
+            rt.create_realm_with_options(realm, RealmOptions::new().random_seed(42))
+                .expect("could not create realm");
+        }
+        rt.create_realm_with_options("seeded_c", RealmOptions::new().random_seed(43))
+            .expect("could not create realm");
+
+        let checkpoint = rt
+            .get_random_state("seeded_a")
+            .expect("no realm")
+            .expect("not seeded");
+        assert_eq!(checkpoint.get_seed(), 42);
+        let a = run("seeded_a");
+        assert_eq!(a, run("seeded_b"));
+        assert_ne!(a, run("seeded_c"));
+        assert!(a.split(',').all(|n| {
+            let n: f64 = n.parse().expect("not a number");
+            (0.0..1.0).contains(&n)
+        }));
+
+        // replay from the checkpoint
+        rt.set_random_state("seeded_c", checkpoint)
+            .expect("could not set state");
+        assert_eq!(run("seeded_c"), a);
+        assert_eq!(run("seeded_a"), run("seeded_c"));
+
+        assert!(rt.get_random_state("__main__").expect("no realm").is_none());
+        assert_eq!(
+            RandomState::from_words(1, RandomState::from_seed(1).get_words()),
+            RandomState::from_seed(1)
+        );
+    }
+}
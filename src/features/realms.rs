@@ -0,0 +1,649 @@
+//! the `quickjs:realms` module which lets trusted scripts create and drop child realms, see [realms_module](crate::builder::QuickJsRuntimeBuilder::realms_module)
+//!
+//! * `createRealm(options)` returns a Promise for a sandbox object, options may contain `limits: {memory}` and `randomSeed`
+//! * `sandbox.eval(code)` evaluates a script in the child realm and returns a Promise for its (awaited) result, the result is copied with the structured clone algorithm
+//! * `sandbox.drop()` drops the child realm and all realms it created
+//!
+//! the module is meant for orchestration code, the caps of the [RealmsModuleOptions] keep it safe when it is exposed by accident
+//! * a realm can have at most `max_child_realms` live child realms
+//! * the child realms of a realm together reserve at most `max_memory_share` of the memory budget of that realm,
+//!   the budget of a child realm is the memory it reserved so nested realms get less and less memory
+//! * the budget of a realm which was not created by the module is the memory limit of the runtime, or [DEFAULT_MEMORY_BUDGET] when the runtime has no limit
+//!
+//! QuickJS accounts memory per runtime and not per realm, so the memory of a child realm is only capped while `sandbox.eval` runs,
+//! the runtime may then allocate at most the reservation of the child on top of what it already uses and an eval which allocates more
+//! is rejected with an out of memory error. Promise jobs and timers of a child realm which run after its eval returned are not capped
+//! by the reservation, only by the memory limit of the runtime
+//!
+//! child realms are dropped when their parent realm is dropped
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::features::realms::RealmsModuleOptions;
+//! use quickjs_runtime::jsutils::Script;
+//! use quickjs_runtime::values::JsValueFacade;
+//! let rt = QuickJsRuntimeBuilder::new().realms_module(RealmsModuleOptions::default()).build();
+//! let res = rt.eval_sync(None, Script::new("orchestrate.js", "import('quickjs:realms').then(async (realms) => {const sandbox = await realms.createRealm({limits: {memory: 4 * 1024 * 1024}}); const res = await sandbox.eval('6 * 7'); sandbox.drop(); return res;});")).expect("script failed");
+//! let JsValueFacade::JsPromise { cached_promise } = res else { panic!("not a promise") };
+//! let res = cached_promise.get_promise_result_sync().expect("promise timed out").expect("promise failed");
+//! assert_eq!(res.get_i32(), 42);
+//! ```
+
+use crate::facades::QuickjsRuntimeFacadeInner;
+use crate::jsutils::modules::NativeModuleLoader;
+use crate::jsutils::{JsError, Script};
+use crate::quickjs_utils::errors::range_error;
+use crate::quickjs_utils::structuredclone::structured_clone_q;
+use crate::quickjs_utils::{errors, functions, promises};
+use crate::quickjsrealmadapter::{QuickJsRealmAdapter, RealmOptions};
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use crate::quickjsvalueadapter::QuickJsValueAdapter;
+use hirofa_utils::eventloop::EventLoop;
+use std::collections::HashMap;
+
+pub const MODULE_NAME: &str = "quickjs:realms";
+
+/// the memory budget of the realms which were not created by the module when the runtime has no memory limit
+pub const DEFAULT_MEMORY_BUDGET: usize = 256 * 1024 * 1024;
+
+/// the least memory a child realm can reserve
+pub const MIN_MEMORY_RESERVATION: usize = 1024 * 1024;
+
+// a higher share would let a single child reserve all memory of its parent and nest without end
+const MAX_MEMORY_SHARE: f64 = 0.9;
+
+/// options for the `quickjs:realms` module
+#[derive(Clone, Debug)]
+pub struct RealmsModuleOptions {
+    /// the max number of live child realms of a realm
+    pub max_child_realms: usize,
+    /// the share of the memory budget of a realm which its child realms may reserve together, a share above 0.9 is used as 0.9
+    pub max_memory_share: f64,
+}
+
+impl Default for RealmsModuleOptions {
+    fn default() -> Self {
+        Self {
+            max_child_realms: 4,
+            max_memory_share: 0.5,
+        }
+    }
+}
+
+/// the parent and child realms of a realm which were created by the `quickjs:realms` module
+#[derive(Default)]
+pub(crate) struct RealmFamily {
+    // the realm which created this realm
+    parent: Option<String>,
+    // the memory which this realm reserved in its parent, None when it was not created by the module
+    budget: Option<usize>,
+    // the live child realms by id with the memory they reserved
+    children: HashMap<String, usize>,
+    // the number of child realms which were created, used for the ids of the children
+    created: usize,
+}
+
+/// detach a realm which is about to be dropped from its parent and get the ids of its child realms which should be dropped with it
+pub(crate) fn detach_q(realm: &QuickJsRealmAdapter) -> Vec<String> {
+    let family = realm.realm_family.take();
+    if let Some(parent_id) = family.parent {
+        QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+            if let Some(parent) = q_js_rt.get_realm(parent_id.as_str()) {
+                parent
+                    .realm_family
+                    .borrow_mut()
+                    .children
+                    .remove(realm.get_realm_id());
+            }
+        });
+    }
+    family.children.into_keys().collect()
+}
+
+/// the NativeModuleLoader which provides the `quickjs:realms` module
+pub struct RealmsModuleLoader {
+    options: RealmsModuleOptions,
+}
+
+impl RealmsModuleLoader {
+    pub fn new(options: RealmsModuleOptions) -> Self {
+        Self { options }
+    }
+}
+
+impl NativeModuleLoader for RealmsModuleLoader {
+    fn has_module(&self, _realm: &QuickJsRealmAdapter, module_name: &str) -> bool {
+        module_name.eq(MODULE_NAME)
+    }
+
+    fn get_module_export_names(
+        &self,
+        _realm: &QuickJsRealmAdapter,
+        _module_name: &str,
+    ) -> Vec<&str> {
+        vec!["createRealm"]
+    }
+
+    fn get_module_exports(
+        &self,
+        realm: &QuickJsRealmAdapter,
+        _module_name: &str,
+    ) -> Vec<(&str, QuickJsValueAdapter)> {
+        let options = self.options.clone();
+        let create_realm = functions::new_function_q(
+            realm,
+            "createRealm",
+            move |realm, _this, args| js_create_realm(realm, &options, args),
+            1,
+        )
+        .expect("could not create createRealm function");
+        vec![("createRealm", create_realm)]
+    }
+}
+
+fn get_number(value: &QuickJsValueAdapter) -> Option<f64> {
+    if value.is_i32() {
+        Some(value.to_i32() as f64)
+    } else if value.is_f64() {
+        Some(value.to_f64())
+    } else {
+        None
+    }
+}
+
+fn root_budget() -> usize {
+    let limit = QuickJsRuntimeAdapter::do_with(|q_js_rt| q_js_rt.memory_usage().malloc_limit);
+    // a runtime without a limit reports the max size_t
+    if limit > 0 {
+        limit as usize
+    } else {
+        DEFAULT_MEMORY_BUDGET
+    }
+}
+
+// reserve a slot and memory for a child realm in the family of the parent and get the id of the child
+fn reserve_child(
+    parent: &QuickJsRealmAdapter,
+    options: &RealmsModuleOptions,
+    memory: Option<usize>,
+) -> Result<(String, usize), JsError> {
+    let family = &mut *parent.realm_family.borrow_mut();
+    if family.children.len() >= options.max_child_realms {
+        return Err(range_error(format!(
+            "realm {} already has the max of {} child realms",
+            parent.get_realm_id(),
+            options.max_child_realms
+        )));
+    }
+    let share = options.max_memory_share.clamp(0.0, MAX_MEMORY_SHARE);
+    let shared = (family.budget.unwrap_or_else(root_budget) as f64 * share) as usize;
+    let reserved: usize = family.children.values().sum();
+    // by default the shared memory is divided evenly over the max number of children
+    let memory = memory.unwrap_or(shared / options.max_child_realms.max(1));
+    if memory < MIN_MEMORY_RESERVATION {
+        return Err(range_error(format!(
+            "a child realm needs at least {MIN_MEMORY_RESERVATION} bytes of memory but got {memory}"
+        )));
+    }
+    if reserved + memory > shared {
+        return Err(range_error(format!(
+            "a child realm with {memory} bytes of memory exceeds the {} bytes which are left for the child realms of realm {}",
+            shared.saturating_sub(reserved),
+            parent.get_realm_id()
+        )));
+    }
+    family.created += 1;
+    let id = format!("{}/{}", parent.get_realm_id(), family.created);
+    family.children.insert(id.clone(), memory);
+    Ok((id, memory))
+}
+
+fn js_create_realm(
+    realm: &QuickJsRealmAdapter,
+    options: &RealmsModuleOptions,
+    args: &[QuickJsValueAdapter],
+) -> Result<QuickJsValueAdapter, JsError> {
+    let mut memory = None;
+    let mut realm_options = RealmOptions::new();
+    if let Some(arg) = args.first().filter(|arg| arg.is_object()) {
+        let limits = realm.get_object_property(arg, "limits")?;
+        if limits.is_object() {
+            let value = realm.get_object_property(&limits, "memory")?;
+            if !value.is_null_or_undefined() {
+                match get_number(&value) {
+                    Some(bytes) if bytes >= 0.0 => memory = Some(bytes as usize),
+                    _ => {
+                        return Err(JsError::new(
+                            "TypeError".to_string(),
+                            "limits.memory should be a number of bytes".to_string(),
+                            "".to_string(),
+                        ))
+                    }
+                }
+            }
+        }
+        let seed = realm.get_object_property(arg, "randomSeed")?;
+        if let Some(seed) = get_number(&seed) {
+            realm_options = realm_options.random_seed(seed as u64);
+        }
+    }
+    let (child_id, budget) = reserve_child(realm, options, memory)?;
+
+    let promise = realm.create_promise()?;
+    let return_ref = promise.js_promise_get_value(realm);
+    let promise_id = realm.cache_promise(promise);
+    let parent_id = realm.get_realm_id().to_string();
+
+    // realms can not be created while a script runs, so the child is created by a next task of the event loop
+    EventLoop::add_local_void(move || {
+        // the parent may have been dropped before this task ran
+        let reserved = QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+            q_js_rt.get_realm(parent_id.as_str()).is_some_and(|parent| {
+                parent
+                    .realm_family
+                    .borrow()
+                    .children
+                    .contains_key(child_id.as_str())
+            })
+        });
+        if !reserved {
+            return;
+        }
+        let created = QuickJsRuntimeAdapter::create_context(child_id.as_str()).and_then(|_| {
+            QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+                let child = q_js_rt.get_context(child_id.as_str());
+                {
+                    let family = &mut *child.realm_family.borrow_mut();
+                    family.parent = Some(parent_id.clone());
+                    family.budget = Some(budget);
+                }
+                realm_options.apply(child)
+            })
+        });
+        // a realm whose init hooks failed is not handed out
+        if created.is_err()
+            && QuickJsRuntimeAdapter::do_with(|q_js_rt| q_js_rt.has_context(child_id.as_str()))
+        {
+            QuickJsRuntimeAdapter::remove_context(child_id.as_str());
+        }
+        QuickjsRuntimeFacadeInner::add_local_task_to_event_loop(move |q_js_rt| {
+            let Some(parent) = q_js_rt.get_realm(parent_id.as_str()) else {
+                return;
+            };
+            let Some(promise) = parent.consume_cached_promise(promise_id) else {
+                return;
+            };
+            // the child may have been dropped from rust before its sandbox was handed out
+            let still_reserved = parent
+                .realm_family
+                .borrow()
+                .children
+                .contains_key(child_id.as_str());
+            let res = match created {
+                Ok(()) if still_reserved => create_sandbox(parent, child_id.as_str()),
+                Ok(()) => Err(JsError::new_str("the child realm was dropped")),
+                Err(err) => {
+                    parent
+                        .realm_family
+                        .borrow_mut()
+                        .children
+                        .remove(child_id.as_str());
+                    Err(err)
+                }
+            };
+            let settled = match res {
+                Ok(sandbox) => promise.js_promise_resolve(parent, &sandbox),
+                Err(err) => parent
+                    .create_error(
+                        err.get_name(),
+                        err.get_script_message(),
+                        err.get_script_stack(),
+                    )
+                    .and_then(|err_ref| promise.js_promise_reject(parent, &err_ref)),
+            };
+            if let Err(e) = settled {
+                log::error!("[{parent_id}] could not settle createRealm promise: {e}");
+            }
+        });
+    });
+
+    Ok(return_ref)
+}
+
+// the object with which the parent realm uses a child realm
+fn create_sandbox(
+    parent: &QuickJsRealmAdapter,
+    child_id: &str,
+) -> Result<QuickJsValueAdapter, JsError> {
+    let sandbox = parent.create_object()?;
+    parent.set_object_property(&sandbox, "id", &parent.create_string(child_id)?)?;
+
+    let eval_child_id = child_id.to_string();
+    let eval_func = functions::new_function_q(
+        parent,
+        "eval",
+        move |realm, _this, args| js_eval(realm, eval_child_id.as_str(), args),
+        1,
+    )?;
+    parent.set_object_property(&sandbox, "eval", &eval_func)?;
+
+    let drop_child_id = child_id.to_string();
+    let drop_func = functions::new_function_q(
+        parent,
+        "drop",
+        move |realm, _this, _args| {
+            js_drop(realm, drop_child_id.as_str());
+            realm.create_undefined()
+        },
+        0,
+    )?;
+    parent.set_object_property(&sandbox, "drop", &drop_func)?;
+    Ok(sandbox)
+}
+
+// QuickJS accounts memory per runtime, so while an eval of a child realm runs the runtime may allocate at most the budget
+// of the child on top of what it already uses, a lower limit which is already active (e.g. that of the parent) is kept
+fn with_budget_limit<R, C: FnOnce() -> R>(
+    q_js_rt: &QuickJsRuntimeAdapter,
+    budget: Option<usize>,
+    eval: C,
+) -> R {
+    let Some(budget) = budget else {
+        return eval();
+    };
+    let usage = q_js_rt.memory_usage();
+    // a runtime without a limit reports the max size_t
+    let limit = if usage.malloc_limit > 0 {
+        usage.malloc_limit as usize
+    } else {
+        usize::MAX
+    };
+    let capped = (usage.malloc_size.max(0) as usize).saturating_add(budget);
+    if capped >= limit {
+        return eval();
+    }
+    q_js_rt.allocator.set_malloc_limit(capped);
+    let res = eval();
+    q_js_rt.allocator.set_malloc_limit(limit);
+    res
+}
+
+fn js_eval(
+    parent: &QuickJsRealmAdapter,
+    child_id: &str,
+    args: &[QuickJsValueAdapter],
+) -> Result<QuickJsValueAdapter, JsError> {
+    if args.is_empty() || !args[0].is_string() {
+        return Err(JsError::new(
+            "TypeError".to_string(),
+            "eval requires a string of code".to_string(),
+            "".to_string(),
+        ));
+    }
+    if !parent.realm_family.borrow().children.contains_key(child_id) {
+        return Err(JsError::new_str("the child realm was dropped"));
+    }
+    let code = args[0].to_string()?;
+
+    let promise = parent.create_promise()?;
+    let return_ref = promise.js_promise_get_value(parent);
+    let promise_id = parent.cache_promise(promise);
+    let parent_id = parent.get_realm_id().to_string();
+
+    QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+        let Some(child) = q_js_rt.get_realm(child_id) else {
+            settle(
+                q_js_rt,
+                None,
+                &parent_id,
+                promise_id,
+                Err(JsError::new_str("the child realm was dropped")),
+            );
+            return Ok(());
+        };
+        let budget = child.realm_family.borrow().budget;
+        let res = with_budget_limit(q_js_rt, budget, || {
+            child.eval(Script::new(
+                format!("{child_id}.js").as_str(),
+                code.as_str(),
+            ))
+        });
+        match res {
+            Ok(value) if value.is_promise() => {
+                // the result of an async eval is copied when it is settled
+                let then_parent_id = parent_id.clone();
+                let then_func = functions::new_function_q(
+                    child,
+                    "then",
+                    move |child, _this, args| {
+                        let value = args
+                            .first()
+                            .map(QuickJsValueAdapter::dup)
+                            .unwrap_or_else(crate::quickjs_utils::new_undefined_ref);
+                        QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+                            settle(q_js_rt, Some(child), &then_parent_id, promise_id, Ok(value))
+                        });
+                        child.create_undefined()
+                    },
+                    1,
+                )?;
+                let catch_parent_id = parent_id.clone();
+                let catch_func = functions::new_function_q(
+                    child,
+                    "catch",
+                    move |child, _this, args| {
+                        let err = match args.first() {
+                            Some(reason) if reason.is_error() => unsafe {
+                                errors::error_to_js_error(child.context, reason)
+                            },
+                            Some(reason) => {
+                                JsError::new_string(functions::call_to_string_q(child, reason)?)
+                            }
+                            None => JsError::new_str("the eval was rejected"),
+                        };
+                        QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+                            settle(q_js_rt, Some(child), &catch_parent_id, promise_id, Err(err))
+                        });
+                        child.create_undefined()
+                    },
+                    1,
+                )?;
+                promises::add_promise_reactions_q(
+                    child,
+                    &value,
+                    Some(then_func),
+                    Some(catch_func),
+                    None,
+                )
+            }
+            res => {
+                settle(q_js_rt, Some(child), &parent_id, promise_id, res);
+                Ok(())
+            }
+        }
+    })?;
+
+    Ok(return_ref)
+}
+
+// settle the promise of an eval in the parent realm with the outcome in the child realm
+fn settle(
+    q_js_rt: &QuickJsRuntimeAdapter,
+    child: Option<&QuickJsRealmAdapter>,
+    parent_id: &str,
+    promise_id: usize,
+    outcome: Result<QuickJsValueAdapter, JsError>,
+) {
+    let Some(parent) = q_js_rt.get_realm(parent_id) else {
+        return;
+    };
+    let Some(promise) = parent.consume_cached_promise(promise_id) else {
+        return;
+    };
+    let res = match (child, outcome) {
+        (Some(child), Ok(value)) => structured_clone_q(child, &value, parent),
+        (None, Ok(_)) => Err(JsError::new_str("the child realm was dropped")),
+        (_, Err(err)) => Err(err),
+    };
+    let settled = match res {
+        Ok(value) => promise.js_promise_resolve(parent, &value),
+        Err(err) => parent
+            .create_error(
+                err.get_name(),
+                err.get_script_message(),
+                err.get_script_stack(),
+            )
+            .and_then(|err_ref| promise.js_promise_reject(parent, &err_ref)),
+    };
+    if let Err(e) = settled {
+        log::error!("[{parent_id}] could not settle eval promise: {e}");
+    }
+}
+
+fn js_drop(parent: &QuickJsRealmAdapter, child_id: &str) {
+    // the reservation is released right away, the realm is dropped when the script is done
+    if parent
+        .realm_family
+        .borrow_mut()
+        .children
+        .remove(child_id)
+        .is_none()
+    {
+        return;
+    }
+    let child_id = child_id.to_string();
+    EventLoop::add_local_void(move || {
+        if QuickJsRuntimeAdapter::do_with(|q_js_rt| q_js_rt.has_context(child_id.as_str())) {
+            QuickJsRuntimeAdapter::remove_context(child_id.as_str());
+        }
+    });
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::facades::QuickJsRuntimeFacade;
+    use crate::features::realms::RealmsModuleOptions;
+    use crate::jsutils::Script;
+    use crate::values::JsValueFacade;
+
+    fn run(rt: &QuickJsRuntimeFacade, code: &str) -> String {
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "test_realms.js",
+                    format!("import('quickjs:realms').then(async (realms) => {{{code}}});")
+                        .as_str(),
+                ),
+            )
+            .expect("script failed");
+        match res {
+            JsValueFacade::JsPromise { cached_promise } => cached_promise
+                .get_promise_result_sync()
+                .expect("promise timed out")
+                .expect("promise failed")
+                .get_str()
+                .to_string(),
+            _ => panic!("not a promise"),
+        }
+    }
+
+    #[test]
+    fn test_nested_realms() {
+        let rt = QuickJsRuntimeBuilder::new()
+            .realms_module(RealmsModuleOptions::default())
+            .build();
+
+        // a child realm creates a realm of its own, values cross the realms as structured clones
+        assert_eq!(
+            run(
+                &rt,
+                "globalThis.sandbox = await realms.createRealm({limits: {memory: 16 * 1024 * 1024}});\
+                 const nested = await sandbox.eval(`import('quickjs:realms').then(async (realms) => {\
+                     const inner = await realms.createRealm();\
+                     const res = await inner.eval('({answer: 6 * 7, when: new Date(0)})');\
+                     return [inner.id, res.answer, res.when instanceof Date].join(',');\
+                 })`);\
+                 return [sandbox.id, nested].join('|');"
+            ),
+            "__main__/1|__main__/1/1,42,true"
+        );
+        assert!(rt.has_realm("__main__/1").unwrap());
+        assert!(rt.has_realm("__main__/1/1").unwrap());
+
+        // errors of a child realm reject the eval
+        assert_eq!(
+            run(
+                &rt,
+                "try {await sandbox.eval('throw new TypeError(\"oops\")');} catch(e) {return `${e.name}:${e.message}`;} return 'no error';"
+            ),
+            "TypeError:oops"
+        );
+
+        // an eval can not allocate more than the memory of the child realm, the limit is lifted afterwards
+        assert_eq!(
+            run(
+                &rt,
+                "let res; try {await sandbox.eval(`'x'.repeat(32 * 1024 * 1024).length`);} catch(e) {res = e.message;}\
+                 return [res, await sandbox.eval('6 * 7'), 'x'.repeat(32 * 1024 * 1024).length].join(',');"
+            ),
+            "out of memory,42,33554432"
+        );
+
+        // dropping a realm drops the realms it created
+        assert_eq!(
+            run(
+                &rt,
+                "sandbox.drop(); try {await sandbox.eval('1');} catch(e) {return e.message;} return 'no error';"
+            ),
+            "the child realm was dropped"
+        );
+        assert!(!rt.has_realm("__main__/1").unwrap());
+        assert!(!rt.has_realm("__main__/1/1").unwrap());
+
+        // dropping a parent from rust drops its children too
+        run(
+            &rt,
+            "globalThis.sandbox = await realms.createRealm(); await sandbox.eval(`import('quickjs:realms').then((realms) => realms.createRealm()).then(() => 'created')`); return '';",
+        );
+        assert!(rt.has_realm("__main__/2/1").unwrap());
+        rt.destroy_realm("__main__/2")
+            .expect("could not destroy realm");
+        assert!(!rt.has_realm("__main__/2/1").unwrap());
+        assert_eq!(
+            run(&rt, "return String((await realms.createRealm()).id);"),
+            "__main__/3"
+        );
+    }
+
+    #[test]
+    fn test_realm_caps() {
+        let rt = QuickJsRuntimeBuilder::new()
+            .memory_limit(64 * 1024 * 1024)
+            .realms_module(RealmsModuleOptions {
+                max_child_realms: 2,
+                max_memory_share: 0.5,
+            })
+            .build();
+
+        // the children of the main realm share half of the memory limit of the runtime
+        assert_eq!(
+            run(
+                &rt,
+                "try {await realms.createRealm({limits: {memory: 40 * 1024 * 1024}});} catch(e) {return e.name;} return 'no error';"
+            ),
+            "RangeError"
+        );
+        assert_eq!(
+            run(
+                &rt,
+                "const a = await realms.createRealm(); const b = await realms.createRealm();\
+                 let res; try {await realms.createRealm();} catch(e) {res = e.name;}\
+                 a.drop(); const c = await realms.createRealm();\
+                 return [res, c.id].join(',');"
+            ),
+            "RangeError,__main__/3"
+        );
+    }
+}
@@ -1,9 +1,19 @@
+use crate::features::WRITABLE_GLOBAL;
+use crate::jsutils::asyncstacks;
+use crate::jsutils::jobcontext::{self, JobContextGuard};
+use crate::jsutils::suspend;
+use crate::jsutils::taskscope::{self, ScopedTask};
+use crate::jsutils::timers;
+use crate::jsutils::uncaught;
 use crate::jsutils::JsError;
 use crate::quickjs_utils;
+use crate::quickjs_utils::interrupthandler::JobTimer;
+use crate::quickjs_utils::pinned::PinnedRef;
 use crate::quickjs_utils::{functions, get_global, objects, parse_args, primitives};
 use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
 use hirofa_utils::eventloop::EventLoop;
 use libquickjs_sys as q;
+use std::rc::Rc;
 use std::time::Duration;
 
 /// provides the setImmediate methods for the runtime
@@ -17,12 +27,22 @@ use std::time::Duration;
 /// std::thread::sleep(Duration::from_secs(2));
 /// ```
 pub fn init(q_js_rt: &QuickJsRuntimeAdapter) -> Result<(), JsError> {
+    init_timers(q_js_rt, true, true)
+}
+
+/// install only the setTimeout and/or setInterval methods
+#[allow(unused_variables)]
+pub(crate) fn init_timers(
+    q_js_rt: &QuickJsRuntimeAdapter,
+    timeouts: bool,
+    intervals: bool,
+) -> Result<(), JsError> {
     log::trace!("set_timeout::init");
 
-    q_js_rt.add_context_init_hook(|_q_js_rt, q_ctx| {
+    q_js_rt.add_context_init_hook(move |_q_js_rt, q_ctx| {
         let global = unsafe { get_global(q_ctx.context) };
         #[cfg(feature = "settimeout")]
-        {
+        if timeouts {
             let set_timeout_func =
                 functions::new_native_function_q(q_ctx, "setTimeout", Some(set_timeout), 2, false)?;
             let clear_timeout_func = functions::new_native_function_q(
@@ -32,11 +52,23 @@ pub fn init(q_js_rt: &QuickJsRuntimeAdapter) -> Result<(), JsError> {
                 1,
                 false,
             )?;
-            objects::set_property2_q(q_ctx, &global, "setTimeout", &set_timeout_func, 0)?;
-            objects::set_property2_q(q_ctx, &global, "clearTimeout", &clear_timeout_func, 0)?;
+            objects::set_property2_q(
+                q_ctx,
+                &global,
+                "setTimeout",
+                &set_timeout_func,
+                WRITABLE_GLOBAL,
+            )?;
+            objects::set_property2_q(
+                q_ctx,
+                &global,
+                "clearTimeout",
+                &clear_timeout_func,
+                WRITABLE_GLOBAL,
+            )?;
         }
         #[cfg(feature = "setinterval")]
-        {
+        if intervals {
             let set_interval_func = functions::new_native_function_q(
                 q_ctx,
                 "setInterval",
@@ -52,8 +84,20 @@ pub fn init(q_js_rt: &QuickJsRuntimeAdapter) -> Result<(), JsError> {
                 false,
             )?;
 
-            objects::set_property2_q(q_ctx, &global, "setInterval", &set_interval_func, 0)?;
-            objects::set_property2_q(q_ctx, &global, "clearInterval", &clear_interval_func, 0)?;
+            objects::set_property2_q(
+                q_ctx,
+                &global,
+                "setInterval",
+                &set_interval_func,
+                WRITABLE_GLOBAL,
+            )?;
+            objects::set_property2_q(
+                q_ctx,
+                &global,
+                "clearInterval",
+                &clear_interval_func,
+                WRITABLE_GLOBAL,
+            )?;
         }
         Ok(())
     })?;
@@ -96,15 +140,40 @@ unsafe extern "C" fn set_timeout(
         };
 
         let q_ctx_id = q_ctx.id.clone();
+        // the id is only known after adding the timeout
+        let timeout_id = Rc::new(std::cell::Cell::new(0));
+        let timeout_id2 = timeout_id.clone();
+        let scope_id = taskscope::current_scope();
+        let job_context = jobcontext::current_context();
+        let creation_stack = asyncstacks::capture_q(q_ctx);
+        // the realm keeps the function and its args alive until the timer ran or was cleared
+        let pinned_args: Rc<Vec<PinnedRef>> =
+            Rc::new(args.iter().map(|arg| PinnedRef::new(q_ctx, arg)).collect());
+        let scheduled_args = pinned_args.clone();
+        let scheduled_stack = creation_stack.clone();
 
         let id = EventLoop::add_timeout(
             move || {
                 QuickJsRuntimeAdapter::do_with(|q_js_rt| {
-                    let func = &args[0];
+                    let _job_timer = JobTimer::start();
+                    let _scope_guard = taskscope::ScopeGuard::enter(
+                        scope_id,
+                        Some(ScopedTask::Timeout(timeout_id2.get())),
+                    );
+                    let _context_guard = JobContextGuard::enter(job_context);
                     if let Some(q_ctx) = q_js_rt.opt_context(q_ctx_id.as_str()) {
-                        match functions::call_function_q(q_ctx, func, &args[2..], None) {
+                        if !q_ctx.timeout_ids.borrow_mut().remove(&timeout_id2.get()) {
+                            // the timeout was cleared after it was due
+                            return;
+                        }
+                        suspend::untrack_timer(q_ctx, ScopedTask::Timeout(timeout_id2.get()));
+                        match PinnedRef::get_all(&pinned_args).and_then(|args| {
+                            functions::call_function_q(q_ctx, &args[0], &args[2..], None)
+                        }) {
                             Ok(_) => {}
                             Err(e) => {
+                                let e = asyncstacks::stitch_error(e, creation_stack.as_deref());
+                                uncaught::report_uncaught_q(q_ctx, "setTimeout", &e);
                                 log::error!("setTimeout func failed: {}", e);
                             }
                         };
@@ -116,8 +185,19 @@ unsafe extern "C" fn set_timeout(
             },
             Duration::from_millis(delay_ms),
         );
+        timeout_id.set(id);
+        q_ctx.timeout_ids.borrow_mut().insert(id);
+        suspend::track_timer(
+            q_ctx,
+            ScopedTask::Timeout(id),
+            Duration::from_millis(delay_ms),
+            None,
+            scheduled_args,
+            scheduled_stack,
+        );
+        taskscope::track(ScopedTask::Timeout(id));
         log::trace!("set_timeout: {}", id);
-        primitives::from_i32(id).clone_value_incr_rc()
+        primitives::from_i32(id).into_raw()
     })
 }
 
@@ -157,16 +237,37 @@ unsafe extern "C" fn set_interval(
         };
 
         let q_ctx_id = q_ctx.id.clone();
+        // the id is only known after adding the interval
+        let interval_id = Rc::new(std::cell::Cell::new(0));
+        let interval_id2 = interval_id.clone();
+        let scope_id = taskscope::current_scope();
+        let job_context = jobcontext::current_context();
+        let creation_stack = asyncstacks::capture_q(q_ctx);
+        // the realm keeps the function and its args alive until the timer ran or was cleared
+        let pinned_args: Rc<Vec<PinnedRef>> =
+            Rc::new(args.iter().map(|arg| PinnedRef::new(q_ctx, arg)).collect());
+        let scheduled_args = pinned_args.clone();
+        let scheduled_stack = creation_stack.clone();
 
         let id = EventLoop::add_interval(
             move || {
                 QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+                    let _job_timer = JobTimer::start();
+                    let _scope_guard = taskscope::ScopeGuard::enter(scope_id, None);
+                    let _context_guard = JobContextGuard::enter(job_context.clone());
                     if let Some(q_ctx) = q_js_rt.opt_context(q_ctx_id.as_str()) {
-                        let func = &args[0];
-
-                        match functions::call_function_q(q_ctx, func, &args[2..], None) {
+                        if !q_ctx.interval_ids.borrow().contains(&interval_id2.get()) {
+                            // the interval was cleared after it was due
+                            return;
+                        }
+                        suspend::interval_fired(q_ctx, ScopedTask::Interval(interval_id2.get()));
+                        match PinnedRef::get_all(&pinned_args).and_then(|args| {
+                            functions::call_function_q(q_ctx, &args[0], &args[2..], None)
+                        }) {
                             Ok(_) => {}
                             Err(e) => {
+                                let e = asyncstacks::stitch_error(e, creation_stack.as_deref());
+                                uncaught::report_uncaught_q(q_ctx, "setInterval", &e);
                                 log::error!("setInterval func failed: {}", e);
                             }
                         };
@@ -179,8 +280,19 @@ unsafe extern "C" fn set_interval(
             Duration::from_millis(delay_ms),
             Duration::from_millis(delay_ms),
         );
+        interval_id.set(id);
+        q_ctx.interval_ids.borrow_mut().insert(id);
+        suspend::track_timer(
+            q_ctx,
+            ScopedTask::Interval(id),
+            Duration::from_millis(delay_ms),
+            Some(Duration::from_millis(delay_ms)),
+            scheduled_args,
+            scheduled_stack,
+        );
+        taskscope::track(ScopedTask::Interval(id));
         log::trace!("set_interval: {}", id);
-        primitives::from_i32(id).clone_value_incr_rc()
+        primitives::from_i32(id).into_raw()
     })
 }
 
@@ -204,7 +316,7 @@ unsafe extern "C" fn clear_interval(
         }
         let id = primitives::to_i32(&args[0]).ok().unwrap();
         log::trace!("clear_interval: {}", id);
-        EventLoop::clear_interval(id);
+        timers::clear_timer_q(q_ctx, ScopedTask::Interval(id));
         quickjs_utils::new_null()
     })
 }
@@ -231,7 +343,7 @@ unsafe extern "C" fn clear_timeout(
         let id = primitives::to_i32(&args[0]).ok().unwrap();
         log::trace!("clear_timeout: {}", id);
 
-        EventLoop::clear_timeout(id);
+        timers::clear_timer_q(q_ctx, ScopedTask::Timeout(id));
 
         quickjs_utils::new_null()
     })
@@ -319,6 +431,49 @@ pub mod tests {
         log::info!("done");
     }
 
+    #[test]
+    fn test_clear_timers() {
+        let rt = init_test_rt();
+        rt.eval_sync(
+            None,
+            Script::new(
+                "test_clear_timers.es",
+                "globalThis.fired = []; \
+                let t1 = setTimeout((a, b) => {fired.push('t1:' + a + b);}, 10, 'x', 'y'); \
+                let t2 = setTimeout(() => {fired.push('t2');}, 20); \
+                let t3 = setTimeout(() => {fired.push('t3');}, 30); \
+                clearTimeout(t2); \
+                let ticks = 0; let i1 = setInterval(() => {ticks++; if (ticks === 3) {clearInterval(i1);}}, 5); \
+                clearTimeout(123456); clearInterval(t2); \
+                globalThis.ids = [t1, t2, t3, i1].every((id) => typeof id === 'number');",
+            ),
+        )
+        .expect("script failed");
+
+        // an id of another realm does not clear the timer
+        rt.create_realm("test_clear_timers").expect("no realm");
+        rt.eval_sync(
+            Some("test_clear_timers"),
+            Script::new(
+                "test_clear_timers.es",
+                "for (let i = 0; i < 1000; i++) {clearTimeout(i);}",
+            ),
+        )
+        .expect("script failed");
+
+        std::thread::sleep(Duration::from_millis(150));
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "test_clear_timers.es",
+                    "clearTimeout(t1); ids + ':' + fired.join(',') + ':' + ticks;",
+                ),
+            )
+            .expect("script failed");
+        assert_eq!(res.get_str(), "true:t1:xy,t3:3");
+    }
+
     #[test]
     fn test_set_timeout() {
         let rt = init_test_rt();
@@ -1,4 +1,5 @@
 use crate::facades::QuickJsRuntimeFacade;
+use crate::features::WRITABLE_GLOBAL;
 use crate::jsutils::JsError;
 use crate::quickjs_utils;
 use crate::quickjs_utils::{functions, get_global_q, objects, parse_args};
@@ -25,7 +26,13 @@ pub fn init(q_js_rt: &QuickJsRuntimeAdapter) -> Result<(), JsError> {
 
         let global = get_global_q(q_ctx);
 
-        objects::set_property2_q(q_ctx, &global, "setImmediate", &set_immediate_func, 0)?;
+        objects::set_property2_q(
+            q_ctx,
+            &global,
+            "setImmediate",
+            &set_immediate_func,
+            WRITABLE_GLOBAL,
+        )?;
         Ok(())
     })?;
     Ok(())
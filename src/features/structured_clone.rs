@@ -0,0 +1,43 @@
+use crate::features::WRITABLE_GLOBAL;
+use crate::jsutils::JsError;
+use crate::quickjs_utils::structuredclone::structured_clone_q;
+use crate::quickjs_utils::{functions, get_global_q, new_undefined_ref, objects};
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+
+/// provides the structuredClone method for the runtime, values are cloned within the realm of the caller
+/// see [structuredclone](crate::quickjs_utils::structuredclone) for the values which can be cloned
+/// # Example
+/// ```rust
+/// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+/// use quickjs_runtime::jsutils::Script;
+/// let rt = QuickJsRuntimeBuilder::new().build();
+/// let res = rt.eval_sync(None, Script::new("test_clone.es", "let d = new Date(5); let c = structuredClone({d}); c.d !== d && c.d.getTime();")).expect("script failed");
+/// assert_eq!(res.get_i32(), 5);
+/// ```
+pub fn init(q_js_rt: &QuickJsRuntimeAdapter) -> Result<(), JsError> {
+    log::trace!("structured_clone::init");
+
+    q_js_rt.add_context_init_hook(|_q_js_rt, q_ctx| {
+        let structured_clone_func = functions::new_function_q(
+            q_ctx,
+            "structuredClone",
+            |q_ctx, _this, args| match args.first() {
+                Some(value) => structured_clone_q(q_ctx, value, q_ctx),
+                None => Ok(new_undefined_ref()),
+            },
+            1,
+        )?;
+
+        let global = get_global_q(q_ctx);
+
+        objects::set_property2_q(
+            q_ctx,
+            &global,
+            "structuredClone",
+            &structured_clone_func,
+            WRITABLE_GLOBAL,
+        )?;
+        Ok(())
+    })?;
+    Ok(())
+}
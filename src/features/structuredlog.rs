@@ -0,0 +1,388 @@
+//! the `quickjs:log` module, structured log records for scripts which are passed to a handler of the embedder
+//!
+//! unlike console the fields of a record are not formatted to a line, they are copied to [JsValueFacade]s so a handler
+//! can forward them to a log pipeline as they are
+//!
+//! * the module exports `trace`, `debug`, `info`, `warn` and `error`, which all take a message and an optional object with fields
+//! * calls below the min_level of the [StructuredLogOptions] return right away, their args are not converted
+//! * the fields are copied up to max_depth levels and at most max_values values per record, when a record was cut off it is marked as truncated
+//! * values which can not be copied (e.g. functions, objects nested deeper than max_depth or getters which throw) are replaced
+//!   with placeholders like `[Function]`, the log call never fails because of its fields
+//! * strings are redacted with the [redaction_hook](crate::builder::QuickJsRuntimeBuilder::redaction_hook)
+//! * the values of a [LogContext] in the [JobContext](crate::jsutils::jobcontext::JobContext) of the eval are added to every record
+//!
+//! the module is only available when a handler was set with [structured_log_handler](crate::builder::QuickJsRuntimeBuilder::structured_log_handler)
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::features::structuredlog::StructuredLogOptions;
+//! use quickjs_runtime::jsutils::Script;
+//! let rt = QuickJsRuntimeBuilder::new()
+//!     .structured_log_handler(StructuredLogOptions::default(), |record| {
+//!         println!("{} {} {:?}", record.level, record.message, record.fields.keys());
+//!     })
+//!     .build();
+//! rt.eval_module_sync(None, Script::new("test_log.mes", "import * as log from 'quickjs:log';\nlog.info('order placed', {orderId: 12, items: ['a', 'b']});")).expect("script failed");
+//! ```
+
+use crate::jsutils::jobcontext;
+use crate::jsutils::modules::NativeModuleLoader;
+use crate::jsutils::redaction;
+use crate::jsutils::{JsError, JsValueType};
+use crate::quickjs_utils;
+use crate::quickjs_utils::{arrays, dates, functions, objects, primitives};
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsvalueadapter::QuickJsValueAdapter;
+use crate::values::JsValueFacade;
+use log::Level;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub const MODULE_NAME: &str = "quickjs:log";
+
+/// receives the records of the `quickjs:log` module, see [structured_log_handler](crate::builder::QuickJsRuntimeBuilder::structured_log_handler)
+pub type StructuredLogHandler = Arc<dyn Fn(LogRecord) + Send + Sync>;
+
+/// options for the `quickjs:log` module
+#[derive(Clone, Debug)]
+pub struct StructuredLogOptions {
+    /// calls with a level below this level are ignored
+    pub min_level: Level,
+    /// the max nesting of objects and arrays in the fields
+    pub max_depth: usize,
+    /// the max number of values which are copied per record
+    pub max_values: usize,
+}
+
+impl Default for StructuredLogOptions {
+    fn default() -> Self {
+        Self {
+            min_level: Level::Info,
+            max_depth: 8,
+            max_values: 256,
+        }
+    }
+}
+
+/// values which are added to every record which is logged during a job, put this in the [JobContext](crate::jsutils::jobcontext::JobContext) of an eval
+#[derive(Clone, Debug, Default)]
+pub struct LogContext {
+    pub values: HashMap<String, String>,
+}
+
+/// a record which was logged by a script
+pub struct LogRecord {
+    pub level: Level,
+    pub message: String,
+    pub fields: HashMap<String, JsValueFacade>,
+    pub realm_id: String,
+    /// the name of the script or module which logged the record
+    pub script_name: String,
+    /// the values of the [LogContext] of the job, if any
+    pub context: HashMap<String, String>,
+    /// some fields or values were left out because of the max_values limit
+    pub truncated: bool,
+}
+
+/// the NativeModuleLoader which provides the `quickjs:log` module
+pub struct StructuredLogModuleLoader {
+    handler: StructuredLogHandler,
+    options: StructuredLogOptions,
+}
+
+impl StructuredLogModuleLoader {
+    pub fn new<H>(options: StructuredLogOptions, handler: H) -> Self
+    where
+        H: Fn(LogRecord) + Send + Sync + 'static,
+    {
+        Self {
+            handler: Arc::new(handler),
+            options,
+        }
+    }
+}
+
+const LEVELS: &[(&str, Level)] = &[
+    ("trace", Level::Trace),
+    ("debug", Level::Debug),
+    ("info", Level::Info),
+    ("warn", Level::Warn),
+    ("error", Level::Error),
+];
+
+impl NativeModuleLoader for StructuredLogModuleLoader {
+    fn has_module(&self, _realm: &QuickJsRealmAdapter, module_name: &str) -> bool {
+        module_name.eq(MODULE_NAME)
+    }
+
+    fn get_module_export_names(
+        &self,
+        _realm: &QuickJsRealmAdapter,
+        _module_name: &str,
+    ) -> Vec<&str> {
+        LEVELS.iter().map(|(name, _)| *name).collect()
+    }
+
+    fn get_module_exports(
+        &self,
+        realm: &QuickJsRealmAdapter,
+        _module_name: &str,
+    ) -> Vec<(&str, QuickJsValueAdapter)> {
+        LEVELS
+            .iter()
+            .map(|(name, level)| (*name, self.new_log_function(realm, name, *level)))
+            .collect()
+    }
+}
+
+impl StructuredLogModuleLoader {
+    fn new_log_function(
+        &self,
+        realm: &QuickJsRealmAdapter,
+        name: &str,
+        level: Level,
+    ) -> QuickJsValueAdapter {
+        let handler = self.handler.clone();
+        let options = self.options.clone();
+        functions::new_function_q(
+            realm,
+            name,
+            move |realm, _this, args| {
+                if level > options.min_level {
+                    return realm.create_undefined();
+                }
+                let record = new_record(realm, level, args, &options)?;
+                handler(record);
+                realm.create_undefined()
+            },
+            2,
+        )
+        .expect("could not create log function")
+    }
+}
+
+fn new_record(
+    realm: &QuickJsRealmAdapter,
+    level: Level,
+    args: &[QuickJsValueAdapter],
+    options: &StructuredLogOptions,
+) -> Result<LogRecord, JsError> {
+    let message = match args.first() {
+        Some(arg) if arg.is_string() => primitives::to_string_q(realm, arg)?,
+        Some(arg) => functions::call_to_string_q(realm, arg).unwrap_or_default(),
+        None => String::new(),
+    };
+    let mut copier = FieldCopier {
+        realm,
+        options,
+        remaining: options.max_values,
+        truncated: false,
+    };
+    let mut fields = HashMap::new();
+    match args.get(1) {
+        None => {}
+        Some(arg) if arg.is_null_or_undefined() => {}
+        Some(arg) if matches!(arg.get_js_type(), JsValueType::Object) => {
+            if let JsValueFacade::Object { val } = copier.copy(arg, 0) {
+                fields = val;
+            }
+        }
+        // fields which are not an object are logged as a single field
+        Some(arg) => {
+            fields.insert("value".to_string(), copier.copy(arg, 0));
+        }
+    }
+    Ok(LogRecord {
+        level,
+        message: redaction::redact_string(message),
+        fields,
+        realm_id: realm.get_realm_id().to_string(),
+        script_name: quickjs_utils::get_script_or_module_name_q(realm).unwrap_or_default(),
+        context: jobcontext::current_value::<LogContext>()
+            .map(|context| context.values)
+            .unwrap_or_default(),
+        truncated: copier.truncated,
+    })
+}
+
+fn placeholder(name: &str) -> JsValueFacade {
+    JsValueFacade::new_string(format!("[{name}]"))
+}
+
+struct FieldCopier<'a> {
+    realm: &'a QuickJsRealmAdapter,
+    options: &'a StructuredLogOptions,
+    remaining: usize,
+    truncated: bool,
+}
+
+impl FieldCopier<'_> {
+    // take one value from the budget, returns false when the record is full
+    fn take(&mut self) -> bool {
+        if self.remaining == 0 {
+            self.truncated = true;
+            false
+        } else {
+            self.remaining -= 1;
+            true
+        }
+    }
+
+    fn copy(&mut self, value: &QuickJsValueAdapter, depth: usize) -> JsValueFacade {
+        let realm = self.realm;
+        match value.get_js_type() {
+            JsValueType::I32 => JsValueFacade::new_i32(value.to_i32()),
+            JsValueType::F64 => JsValueFacade::new_f64(value.to_f64()),
+            JsValueType::Boolean => JsValueFacade::new_bool(value.to_bool()),
+            JsValueType::Null => JsValueFacade::Null,
+            JsValueType::Undefined => JsValueFacade::Undefined,
+            JsValueType::String => match primitives::to_string_q(realm, value) {
+                Ok(s) => JsValueFacade::new_string(redaction::redact_string(s)),
+                Err(_) => placeholder("String"),
+            },
+            JsValueType::Function => placeholder("Function"),
+            JsValueType::Promise => placeholder("Promise"),
+            JsValueType::Error => {
+                let s = functions::call_to_string_q(realm, value).unwrap_or_default();
+                JsValueFacade::new_string(redaction::redact_string(s))
+            }
+            _ if value.is_object() && dates::is_date_q(realm, value) => {
+                match dates::get_time_q(realm, value) {
+                    Ok(millis) => JsValueFacade::new_date(millis),
+                    Err(_) => placeholder("Date"),
+                }
+            }
+            JsValueType::Array | JsValueType::Object if depth >= self.options.max_depth => {
+                placeholder("MaxDepth")
+            }
+            JsValueType::Array => self.copy_array(value, depth),
+            JsValueType::Object => self.copy_object(value, depth),
+            _ => placeholder(value.type_of()),
+        }
+    }
+
+    fn copy_array(&mut self, value: &QuickJsValueAdapter, depth: usize) -> JsValueFacade {
+        let realm = self.realm;
+        let len = arrays::get_length_q(realm, value).unwrap_or(0);
+        let mut val = vec![];
+        for index in 0..len {
+            if !self.take() {
+                break;
+            }
+            let element = match arrays::get_element_q(realm, value, index) {
+                Ok(element) => self.copy(&element, depth + 1),
+                Err(_) => placeholder("Error"),
+            };
+            val.push(element);
+        }
+        JsValueFacade::Array { val }
+    }
+
+    fn copy_object(&mut self, value: &QuickJsValueAdapter, depth: usize) -> JsValueFacade {
+        let realm = self.realm;
+        let names = objects::get_property_names_q(realm, value).unwrap_or_default();
+        let mut val = HashMap::new();
+        for name in names {
+            if !self.take() {
+                break;
+            }
+            let prop = match objects::get_property_q(realm, value, name.as_str()) {
+                Ok(prop) => self.copy(&prop, depth + 1),
+                Err(_) => placeholder("Error"),
+            };
+            val.insert(name, prop);
+        }
+        JsValueFacade::Object { val }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::features::structuredlog::{LogContext, LogRecord, StructuredLogOptions};
+    use crate::jsutils::jobcontext::JobContext;
+    use crate::jsutils::Script;
+    use crate::values::JsValueFacade;
+    use futures::executor::block_on;
+    use log::Level;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_structured_log() {
+        let records: Arc<Mutex<Vec<LogRecord>>> = Arc::new(Mutex::new(vec![]));
+        let records2 = records.clone();
+        let rt = QuickJsRuntimeBuilder::new()
+            .structured_log_handler(
+                StructuredLogOptions {
+                    min_level: Level::Info,
+                    max_depth: 2,
+                    max_values: 16,
+                },
+                move |record| records2.lock().unwrap().push(record),
+            )
+            .build();
+
+        rt.eval_module_sync(
+            None,
+            Script::new(
+                "test_log.mes",
+                "import * as log from 'quickjs:log'; globalThis.log = log;",
+            ),
+        )
+        .expect("module failed");
+        let context = JobContext::new().with(LogContext {
+            values: HashMap::from([("requestId".to_string(), "r1".to_string())]),
+        });
+        block_on(rt.eval_with_context(
+            None,
+            Script::new(
+                "test_log.js",
+                "log.debug('skipped', {a: 1});\
+                 const cyclic = {name: 'c'}; cyclic.self = cyclic;\
+                 log.warn('order', {id: 12, ok: true, tags: ['a', 'b'], cb: () => 1, cyclic});\
+                 log.info('big', {list: Array.from({length: 20}, (_, i) => i)});",
+            ),
+            context,
+        ))
+        .expect("script failed");
+
+        let records = records.lock().unwrap();
+        assert_eq!(records.len(), 2);
+
+        let order = &records[0];
+        assert_eq!(order.level, Level::Warn);
+        assert_eq!(order.message, "order");
+        assert_eq!(order.realm_id, "__main__");
+        assert!(order.script_name.ends_with("test_log.js"));
+        assert_eq!(
+            order.context.get("requestId").map(|s| s.as_str()),
+            Some("r1")
+        );
+        assert!(!order.truncated);
+        assert_eq!(order.fields.get("id").unwrap().get_i32(), 12);
+        assert!(order.fields.get("ok").unwrap().get_bool());
+        assert_eq!(order.fields.get("cb").unwrap().get_str(), "[Function]");
+        match order.fields.get("tags").unwrap() {
+            JsValueFacade::Array { val } => {
+                assert_eq!(val.len(), 2);
+                assert_eq!(val[1].get_str(), "b");
+            }
+            _ => panic!("tags is not an array"),
+        }
+        match order.fields.get("cyclic").unwrap() {
+            JsValueFacade::Object { val } => {
+                assert_eq!(val.get("name").unwrap().get_str(), "c");
+                assert_eq!(val.get("self").unwrap().get_str(), "[MaxDepth]");
+            }
+            _ => panic!("cyclic is not an object"),
+        }
+
+        let big = &records[1];
+        assert!(big.truncated);
+        match big.fields.get("list").unwrap() {
+            JsValueFacade::Array { val } => assert_eq!(val.len(), 15),
+            _ => panic!("list is not an array"),
+        }
+    }
+}
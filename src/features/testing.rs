@@ -0,0 +1,682 @@
+//! running JS unit tests inside the runtime, see [testing](crate::builder::QuickJsRuntimeBuilder::testing) and [run_tests](crate::facades::QuickJsRuntimeFacade::run_tests)
+//!
+//! when enabled, scripts can import the `quickjs:assert` module which exports
+//! * assert(condition, message?)
+//! * assertEquals(actual, expected, message?), compares with [deep_equals_q](crate::quickjs_utils::objects::deep_equals_q)
+//! * assertThrows(func, errorName?), errorName checks the name of the thrown error
+//! * assertRejects(promiseOrFunc, errorName?), returns a promise which rejects when the promise resolves
+//!
+//! failed assertions throw an AssertionError
+//!
+//! a test suite is a module, its exported functions with a name which starts with `test` are run one at a time in the order of
+//! the module namespace (alphabetical), a test passes when it returns or when the promise it returns resolves
+//!
+//! every suite runs in a new realm which is dropped afterwards, a test which does not finish within the timeout of the [TestOptions] fails
+//! with a TimeoutError, console lines are captured per test
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::features::testing::TestOptions;
+//! use quickjs_runtime::jsutils::Script;
+//! use futures::executor::block_on;
+//! let rt = QuickJsRuntimeBuilder::new().testing().build();
+//! let suite = Script::new("math.test.mes", "import {assertEquals} from 'quickjs:assert';\n\
+//!     export function testAdd() { assertEquals({sum: 1 + 2}, {sum: 3}); }\n\
+//!     export async function testSub() { assertEquals(await Promise.resolve(3 - 2), 2); }");
+//! let report = block_on(rt.run_tests(suite, TestOptions::default())).expect("suite failed to load");
+//! assert_eq!(report.passed(), 1);
+//! assert_eq!(report.failed(), 1);
+//! assert_eq!(report.results[1].error.as_ref().map(|e| e.name.as_str()), Some("AssertionError"));
+//! ```
+
+use crate::facades::QuickJsRuntimeFacade;
+use crate::jsutils::modules::{NativeModuleLoader, ScriptModuleLoader};
+use crate::jsutils::{JsError, Script};
+use crate::quickjs_utils::errors::timeout_error;
+use crate::quickjs_utils::{
+    errors, functions, get_global_q, interrupthandler, json, objects, promises,
+};
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use crate::quickjsvalueadapter::QuickJsValueAdapter;
+use hirofa_utils::eventloop::EventLoop;
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+pub const ASSERT_MODULE_NAME: &str = "quickjs:assert";
+const SUITE_PREFIX: &str = "quickjs:test-suite/";
+const SUITE_GLOBAL: &str = "__quickjs_test_suite";
+
+static SUITE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    // the sources of the suites which are being loaded
+    static SUITES: RefCell<HashMap<String, String>> = RefCell::new(HashMap::new());
+    // the realm of the running test and its console lines
+    static CONSOLE_CAPTURE: RefCell<Option<(String, Vec<String>)>> = RefCell::new(None);
+}
+
+/// the options of a test run
+#[derive(Clone, Debug)]
+pub struct TestOptions {
+    /// the max duration of a single test, including waiting for a returned promise
+    pub timeout: Duration,
+}
+
+impl Default for TestOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// the error of a failed test
+#[derive(Serialize, Clone, Debug)]
+pub struct TestError {
+    pub name: String,
+    pub message: String,
+    pub stack: String,
+}
+
+impl From<JsError> for TestError {
+    fn from(err: JsError) -> Self {
+        Self {
+            name: err.get_name().to_string(),
+            message: err.get_message().to_string(),
+            stack: err.get_stack().to_string(),
+        }
+    }
+}
+
+/// the result of a single test
+#[derive(Serialize, Clone, Debug)]
+pub struct TestResult {
+    pub name: String,
+    pub passed: bool,
+    pub error: Option<TestError>,
+    pub duration: Duration,
+    /// the console lines which were logged while the test ran
+    pub console: Vec<String>,
+}
+
+/// the results of a test suite
+#[derive(Serialize, Clone, Debug)]
+pub struct TestReport {
+    pub suite: String,
+    pub results: Vec<TestResult>,
+    pub duration: Duration,
+}
+
+impl TestReport {
+    pub fn passed(&self) -> usize {
+        self.results.iter().filter(|r| r.passed).count()
+    }
+    pub fn failed(&self) -> usize {
+        self.results.len() - self.passed()
+    }
+    pub fn all_passed(&self) -> bool {
+        self.results.iter().all(|r| r.passed)
+    }
+}
+
+/// check if console lines of a realm are captured for a test
+pub(crate) fn is_capturing(realm_id: &str) -> bool {
+    CONSOLE_CAPTURE.with(|rc| {
+        rc.borrow()
+            .as_ref()
+            .map(|(id, _)| id.eq(realm_id))
+            .unwrap_or(false)
+    })
+}
+
+/// keep a console line for the running test
+pub(crate) fn capture_console(realm_id: &str, line: &str) {
+    CONSOLE_CAPTURE.with(|rc| {
+        if let Some((id, lines)) = rc.borrow_mut().as_mut() {
+            if id.as_str().eq(realm_id) {
+                lines.push(line.to_string());
+            }
+        }
+    })
+}
+
+fn assertion_error(message: String) -> JsError {
+    JsError::new("AssertionError".to_string(), message, "".to_string())
+}
+
+// a readable representation of a value for assertion messages
+fn describe(realm: &QuickJsRealmAdapter, value: &QuickJsValueAdapter) -> String {
+    if !value.is_function() {
+        if let Ok(json) = json::stringify_q(realm, value, None) {
+            if let Ok(s) = json.to_string() {
+                if json.is_string() {
+                    return s;
+                }
+            }
+        }
+    }
+    functions::call_to_string_q(realm, value).unwrap_or_else(|_| "[unprintable]".to_string())
+}
+
+fn optional_message(
+    realm: &QuickJsRealmAdapter,
+    args: &[QuickJsValueAdapter],
+    index: usize,
+    default: String,
+) -> String {
+    match args.get(index) {
+        Some(arg) if arg.is_string() => functions::call_to_string_q(realm, arg).unwrap_or(default),
+        _ => default,
+    }
+}
+
+fn js_assert(
+    realm: &QuickJsRealmAdapter,
+    args: &[QuickJsValueAdapter],
+) -> Result<QuickJsValueAdapter, JsError> {
+    let ok = match args.first() {
+        Some(arg) => {
+            let global = get_global_q(realm);
+            let boolean = objects::get_property_q(realm, &global, "Boolean")?;
+            functions::call_function_q(realm, &boolean, &[arg.dup()], None)?.to_bool()
+        }
+        None => false,
+    };
+    if ok {
+        realm.create_undefined()
+    } else {
+        Err(assertion_error(optional_message(
+            realm,
+            args,
+            1,
+            "assertion failed".to_string(),
+        )))
+    }
+}
+
+fn js_assert_equals(
+    realm: &QuickJsRealmAdapter,
+    args: &[QuickJsValueAdapter],
+) -> Result<QuickJsValueAdapter, JsError> {
+    if args.len() < 2 {
+        return Err(JsError::new(
+            "TypeError".to_string(),
+            "assertEquals requires an actual and an expected value".to_string(),
+            "".to_string(),
+        ));
+    }
+    if objects::deep_equals_q(realm, &args[0], &args[1])? {
+        realm.create_undefined()
+    } else {
+        let default = format!(
+            "expected {} but got {}",
+            describe(realm, &args[1]),
+            describe(realm, &args[0])
+        );
+        Err(assertion_error(optional_message(realm, args, 2, default)))
+    }
+}
+
+// check the name of an error against an optional expected name
+fn check_error_name(
+    realm: &QuickJsRealmAdapter,
+    expected_name: Option<&QuickJsValueAdapter>,
+    err: &JsError,
+) -> Result<(), JsError> {
+    match expected_name {
+        Some(arg) if arg.is_string() => {
+            let expected = functions::call_to_string_q(realm, arg)?;
+            if err.get_name().eq(expected.as_str()) {
+                Ok(())
+            } else {
+                Err(assertion_error(format!(
+                    "expected a {} but got {}",
+                    expected, err
+                )))
+            }
+        }
+        _ => Ok(()),
+    }
+}
+
+fn js_assert_throws(
+    realm: &QuickJsRealmAdapter,
+    args: &[QuickJsValueAdapter],
+) -> Result<QuickJsValueAdapter, JsError> {
+    match args.first() {
+        Some(func) if func.is_function() => {
+            match functions::call_function_q(realm, func, &[], None) {
+                Ok(_) => Err(assertion_error(
+                    "expected the function to throw".to_string(),
+                )),
+                Err(err) => {
+                    check_error_name(realm, args.get(1), &err)?;
+                    realm.create_undefined()
+                }
+            }
+        }
+        _ => Err(JsError::new(
+            "TypeError".to_string(),
+            "assertThrows requires a function".to_string(),
+            "".to_string(),
+        )),
+    }
+}
+
+fn js_assert_rejects(
+    realm: &QuickJsRealmAdapter,
+    args: &[QuickJsValueAdapter],
+) -> Result<QuickJsValueAdapter, JsError> {
+    let res = promises::new_promise_q(realm)?;
+    let subject = match args.first() {
+        Some(func) if func.is_function() => {
+            match functions::call_function_q(realm, func, &[], None) {
+                Ok(value) => value,
+                Err(err) => {
+                    // a function which throws before returning a promise also rejects
+                    match check_error_name(realm, args.get(1), &err) {
+                        Ok(()) => res.resolve_q(realm, realm.create_undefined()?)?,
+                        Err(e) => res.reject_q(
+                            realm,
+                            realm.create_error(e.get_name(), e.get_message(), "")?,
+                        )?,
+                    }
+                    return Ok(res.get_promise_obj_ref());
+                }
+            }
+        }
+        Some(value) => value.dup(),
+        None => {
+            return Err(JsError::new(
+                "TypeError".to_string(),
+                "assertRejects requires a promise or a function".to_string(),
+                "".to_string(),
+            ))
+        }
+    };
+    if !subject.is_promise() {
+        return Err(assertion_error("expected a promise".to_string()));
+    }
+
+    let expected_name = args.get(1).map(QuickJsValueAdapter::dup);
+    let then_res = res.clone();
+    let then_func = functions::new_function_q(
+        realm,
+        "then",
+        move |realm, _this, _args| {
+            then_res.reject_q(
+                realm,
+                realm.create_error("AssertionError", "expected the promise to reject", "")?,
+            )?;
+            realm.create_undefined()
+        },
+        1,
+    )?;
+    let catch_res = res.clone();
+    let catch_func = functions::new_function_q(
+        realm,
+        "catch",
+        move |realm, _this, reasons| {
+            let err = match reasons.first() {
+                Some(reason) if reason.is_object() => unsafe {
+                    errors::error_to_js_error(realm.context, reason)
+                },
+                _ => JsError::new_str("rejected"),
+            };
+            match check_error_name(realm, expected_name.as_ref(), &err) {
+                Ok(()) => catch_res.resolve_q(realm, realm.create_undefined()?)?,
+                Err(e) => catch_res.reject_q(
+                    realm,
+                    realm.create_error(e.get_name(), e.get_message(), "")?,
+                )?,
+            }
+            realm.create_undefined()
+        },
+        1,
+    )?;
+    promises::add_promise_reactions_q(realm, &subject, Some(then_func), Some(catch_func), None)?;
+    Ok(res.get_promise_obj_ref())
+}
+
+type AssertFunction =
+    fn(&QuickJsRealmAdapter, &[QuickJsValueAdapter]) -> Result<QuickJsValueAdapter, JsError>;
+
+fn new_assert_function(
+    realm: &QuickJsRealmAdapter,
+    name: &str,
+    func: AssertFunction,
+) -> QuickJsValueAdapter {
+    functions::new_function_q(realm, name, move |realm, _this, args| func(realm, args), 1)
+        .expect("could not create assert function")
+}
+
+/// the NativeModuleLoader which provides the `quickjs:assert` module
+pub struct AssertModuleLoader {}
+
+impl NativeModuleLoader for AssertModuleLoader {
+    fn has_module(&self, _realm: &QuickJsRealmAdapter, module_name: &str) -> bool {
+        module_name.eq(ASSERT_MODULE_NAME)
+    }
+
+    fn get_module_export_names(
+        &self,
+        _realm: &QuickJsRealmAdapter,
+        _module_name: &str,
+    ) -> Vec<&str> {
+        vec!["assert", "assertEquals", "assertThrows", "assertRejects"]
+    }
+
+    fn get_module_exports(
+        &self,
+        realm: &QuickJsRealmAdapter,
+        _module_name: &str,
+    ) -> Vec<(&str, QuickJsValueAdapter)> {
+        vec![
+            ("assert", new_assert_function(realm, "assert", js_assert)),
+            (
+                "assertEquals",
+                new_assert_function(realm, "assertEquals", js_assert_equals),
+            ),
+            (
+                "assertThrows",
+                new_assert_function(realm, "assertThrows", js_assert_throws),
+            ),
+            (
+                "assertRejects",
+                new_assert_function(realm, "assertRejects", js_assert_rejects),
+            ),
+        ]
+    }
+}
+
+/// the ScriptModuleLoader which provides the suites of [run_tests] while they are loaded
+pub struct TestSuiteLoader {}
+
+impl ScriptModuleLoader for TestSuiteLoader {
+    fn normalize_path(
+        &self,
+        _realm: &QuickJsRealmAdapter,
+        _ref_path: &str,
+        path: &str,
+    ) -> Option<String> {
+        if path.starts_with(SUITE_PREFIX) && SUITES.with(|rc| rc.borrow().contains_key(path)) {
+            Some(path.to_string())
+        } else {
+            None
+        }
+    }
+
+    fn load_module(&self, _realm: &QuickJsRealmAdapter, absolute_path: &str) -> String {
+        SUITES.with(|rc| rc.borrow().get(absolute_path).cloned().unwrap_or_default())
+    }
+}
+
+// load the suite and return the names of its tests
+fn load_suite_q(realm: &QuickJsRealmAdapter, script: Script) -> Result<Vec<String>, JsError> {
+    let suite_path = format!(
+        "{}{}/{}",
+        SUITE_PREFIX,
+        SUITE_COUNTER.fetch_add(1, Ordering::SeqCst),
+        script.get_path()
+    );
+    SUITES.with(|rc| {
+        rc.borrow_mut()
+            .insert(suite_path.clone(), script.get_code().to_string())
+    });
+    let res = realm.eval_module(Script::new(
+        format!("{suite_path}.runner.mes").as_str(),
+        format!("import * as suite from '{suite_path}';\nglobalThis.{SUITE_GLOBAL} = suite;")
+            .as_str(),
+    ));
+    SUITES.with(|rc| rc.borrow_mut().remove(&suite_path));
+    res?;
+
+    let global = get_global_q(realm);
+    let suite = objects::get_property_q(realm, &global, SUITE_GLOBAL)?;
+    if !suite.is_object() {
+        return Err(JsError::new_string(format!(
+            "test suite {} did not load",
+            script.get_path()
+        )));
+    }
+    let mut names = vec![];
+    let property_names = objects::get_own_property_names_q(realm, &suite)?;
+    for index in 0..property_names.len() {
+        let name = property_names.get_name(index)?;
+        if name.starts_with("test")
+            && objects::get_property_q(realm, &suite, name.as_str())?.is_function()
+        {
+            names.push(name);
+        }
+    }
+    Ok(names)
+}
+
+// start a test and send its result, a promise result is sent when it settles
+fn start_test_q(
+    q_js_rt: &QuickJsRuntimeAdapter,
+    realm: &QuickJsRealmAdapter,
+    name: &str,
+    timeout: Duration,
+    tx: flume::Sender<Result<(), JsError>>,
+) -> Result<(), JsError> {
+    CONSOLE_CAPTURE.with(|rc| rc.replace(Some((realm.id.clone(), vec![]))));
+
+    let global = get_global_q(realm);
+    let suite = objects::get_property_q(realm, &global, SUITE_GLOBAL)?;
+    let func = objects::get_property_q(realm, &suite, name)?;
+
+    let deadline = Instant::now() + timeout;
+    let previous_deadline = q_js_rt.interrupt_deadline.get();
+    q_js_rt.interrupt_deadline.set(Some(deadline));
+    interrupthandler::init(q_js_rt);
+    let res = functions::call_function_q(realm, &func, &[], None);
+    q_js_rt.interrupt_deadline.set(previous_deadline);
+    let res = res.map_err(|err| {
+        if Instant::now() >= deadline {
+            timeout_error("test", timeout)
+        } else {
+            err
+        }
+    })?;
+
+    if res.is_promise() {
+        // the timer is cleared when the realm is dropped
+        let timeout_tx = tx.clone();
+        let id = EventLoop::add_timeout(
+            move || {
+                let _ = timeout_tx.try_send(Err(timeout_error("test", timeout)));
+            },
+            timeout,
+        );
+        realm.timeout_ids.borrow_mut().insert(id);
+
+        let then_tx = tx.clone();
+        let then_func = functions::new_function_q(
+            realm,
+            "then",
+            move |realm, _this, _args| {
+                let _ = then_tx.try_send(Ok(()));
+                realm.create_undefined()
+            },
+            1,
+        )?;
+        let catch_func = functions::new_function_q(
+            realm,
+            "catch",
+            move |realm, _this, args| {
+                let err = match args.first() {
+                    Some(reason) => unsafe { errors::thrown_to_js_error(realm.context, reason) },
+                    None => JsError::new_str("rejected"),
+                };
+                let _ = tx.try_send(Err(err));
+                realm.create_undefined()
+            },
+            1,
+        )?;
+        promises::add_promise_reactions_q(realm, &res, Some(then_func), Some(catch_func), None)?;
+    } else {
+        let _ = tx.try_send(Ok(()));
+    }
+    Ok(())
+}
+
+fn take_console() -> Vec<String> {
+    CONSOLE_CAPTURE.with(|rc| rc.take().map(|(_, lines)| lines).unwrap_or_default())
+}
+
+async fn run_suite(
+    rt: &QuickJsRuntimeFacade,
+    realm_id: &str,
+    script: Script,
+    options: TestOptions,
+) -> Result<Vec<TestResult>, JsError> {
+    let names = rt
+        .loop_realm(Some(realm_id), move |_rt, realm| {
+            load_suite_q(realm, script)
+        })
+        .await?;
+
+    let mut results = vec![];
+    for name in names {
+        let start = Instant::now();
+        // one result is sent, later sends (e.g. the timeout after a result) fail
+        let (tx, rx) = flume::bounded(1);
+        let test_name = name.clone();
+        let timeout = options.timeout;
+        rt.loop_realm(Some(realm_id), move |q_js_rt, realm| {
+            if let Err(err) = start_test_q(q_js_rt, realm, test_name.as_str(), timeout, tx.clone())
+            {
+                let _ = tx.try_send(Err(err));
+            }
+        })
+        .await;
+        let res = rx
+            .recv_async()
+            .await
+            .map_err(|_| JsError::new_str("test realm was dropped before the test completed"))?;
+        let duration = start.elapsed();
+        let console = rt
+            .loop_realm(Some(realm_id), |_rt, _realm| take_console())
+            .await;
+        results.push(TestResult {
+            name,
+            passed: res.is_ok(),
+            error: res.err().map(TestError::from),
+            duration,
+            console,
+        });
+    }
+    Ok(results)
+}
+
+/// run the tests of a suite in a new realm, fails when the suite can not be loaded
+pub(crate) async fn run_tests(
+    rt: &QuickJsRuntimeFacade,
+    script: Script,
+    options: TestOptions,
+) -> Result<TestReport, JsError> {
+    let start = Instant::now();
+    let suite = script.get_path().to_string();
+    let realm_id = format!("__tests_{}", SUITE_COUNTER.fetch_add(1, Ordering::SeqCst));
+    let realm = rt.create_realm(realm_id.as_str())?;
+    let results = run_suite(rt, realm_id.as_str(), script, options).await;
+    let _ = rt.loop_sync(|_rt| take_console());
+    rt.drop_realm(&realm)?;
+    Ok(TestReport {
+        suite,
+        results: results?,
+        duration: start.elapsed(),
+    })
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::features::testing::TestOptions;
+    use crate::jsutils::Script;
+    use futures::executor::block_on;
+    use std::time::Duration;
+
+    #[test]
+    fn test_run_tests() {
+        let rt = QuickJsRuntimeBuilder::new().testing().build();
+        let suite = Script::new(
+            "test_run_tests.mes",
+            "import {assert, assertEquals, assertThrows, assertRejects} from 'quickjs:assert';\n\
+             export function testAsserts() {\n\
+                 console.log('checking asserts');\n\
+                 assert(1 + 1 === 2);\n\
+                 assertEquals({a: [1, {b: NaN}]}, {a: [1, {b: NaN}]});\n\
+                 assertThrows(() => { throw new TypeError('nope'); }, 'TypeError');\n\
+             }\n\
+             export async function testAsync() {\n\
+                 await assertRejects(Promise.reject(new RangeError('far')), 'RangeError');\n\
+                 await assertRejects(async () => { throw Error('boom'); });\n\
+             }\n\
+             export function testFailing() { assertEquals([1, 2], [1, 3]); }\n\
+             export function testLoops() { while (true) {} }\n\
+             export function testNeverSettles() { return new Promise(() => {}); }\n\
+             export async function testWrongRejection() { await assertRejects(Promise.resolve(1)); }\n\
+             export function helper() { throw Error('not a test'); }\n",
+        );
+        let report = block_on(rt.run_tests(
+            suite,
+            TestOptions {
+                timeout: Duration::from_millis(200),
+            },
+        ))
+        .expect("suite failed to load");
+
+        let names: Vec<&str> = report.results.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "testAsserts",
+                "testAsync",
+                "testFailing",
+                "testLoops",
+                "testNeverSettles",
+                "testWrongRejection"
+            ]
+        );
+        assert_eq!(report.passed(), 2);
+        assert!(!report.all_passed());
+
+        let result = |name: &str| {
+            report
+                .results
+                .iter()
+                .find(|r| r.name == name)
+                .expect("no result")
+        };
+        assert_eq!(result("testAsserts").console.len(), 1);
+        assert!(result("testAsserts").console[0].contains("checking asserts"));
+        let error = |name: &str| result(name).error.clone().expect("no error");
+        assert_eq!(error("testFailing").name, "AssertionError");
+        assert_eq!(error("testFailing").message, "expected [1,3] but got [1,2]");
+        assert_eq!(error("testLoops").name, "TimeoutError");
+        assert_eq!(error("testNeverSettles").name, "TimeoutError");
+        assert_eq!(error("testWrongRejection").name, "AssertionError");
+        assert!(result("testNeverSettles").duration >= Duration::from_millis(200));
+
+        serde_json::to_string(&report).expect("could not serialize");
+
+        // the suite realm is dropped
+        assert_eq!(
+            rt.exe_rt_task_in_event_loop(|q_js_rt| q_js_rt.memory_usage().realm_ct),
+            1
+        );
+
+        // a suite which does not load fails
+        assert!(block_on(rt.run_tests(
+            Script::new("broken.mes", "export function testA() {"),
+            TestOptions::default()
+        ))
+        .is_err());
+    }
+}
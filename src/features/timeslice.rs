@@ -0,0 +1,421 @@
+//! cooperative time slicing for long running scripts, see [time_slice](crate::builder::QuickJsRuntimeBuilder::time_slice)
+//!
+//! a script can not be suspended by the runtime, instead a long running script yields to the event loop by awaiting `yieldToHost()`,
+//! the returned promise is resolved on a next turn of the event loop so timers, other tasks and other realms can run in between
+//! `yieldToHost.due()` returns true when the current slice is used up, so a hot loop only yields when needed:
+//! ```javascript
+//! for (const item of items) {
+//!     if (yieldToHost.due()) await yieldToHost();
+//!     process(item);
+//! }
+//! ```
+//!
+//! the [CooperativePreProcessor] adds these yields to the loops of scripts which start with the `'use cooperative';` directive,
+//! such a script is run as an async function so the eval returns a Promise (which resolves to undefined)
+//!
+//! # Limitations
+//! yields are only added to loops with a block body which are directly in an async function (or in the top level of the script),
+//! loops in non-async functions are not changed because they can not await
+//! the directive should not be used in modules, wrapping a module in a function breaks its imports and exports
+
+use crate::jsutils::{JsError, Script, ScriptPreProcessor};
+use crate::quickjs_utils::{functions, get_global_q, objects};
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use crate::quickjsvalueadapter::QuickJsValueAdapter;
+use hirofa_utils::eventloop::EventLoop;
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+const DIRECTIVES: [&str; 2] = ["'use cooperative'", "\"use cooperative\""];
+const YIELD_CODE: &str = " if (yieldToHost.due()) await yieldToHost();";
+
+thread_local! {
+    static SLICE: Cell<Duration> = Cell::new(Duration::from_millis(10));
+    // when the current slice started, reset when a yield resumes
+    static SLICE_START: Cell<Option<Instant>> = Cell::new(None);
+}
+
+/// install `yieldToHost` in every realm
+pub fn init(q_js_rt: &QuickJsRuntimeAdapter, slice: Duration) -> Result<(), JsError> {
+    SLICE.with(|s| s.set(slice));
+    q_js_rt.add_context_init_hook(|_q_js_rt, realm| {
+        let yield_func = functions::new_function_q(
+            realm,
+            "yieldToHost",
+            |realm, _this, _args| yield_to_host(realm),
+            0,
+        )?;
+        let due_func = functions::new_function_q(
+            realm,
+            "due",
+            |realm, _this, _args| realm.create_boolean(is_due()),
+            0,
+        )?;
+        objects::set_property_q(realm, &yield_func, "due", &due_func)?;
+        objects::set_property_q(realm, &get_global_q(realm), "yieldToHost", &yield_func)
+    })
+}
+
+fn is_due() -> bool {
+    let now = Instant::now();
+    match SLICE_START.with(|s| s.get()) {
+        Some(start) => now.duration_since(start) >= SLICE.with(|s| s.get()),
+        None => {
+            SLICE_START.with(|s| s.set(Some(now)));
+            false
+        }
+    }
+}
+
+fn yield_to_host(realm: &QuickJsRealmAdapter) -> Result<QuickJsValueAdapter, JsError> {
+    let promise = realm.create_promise()?;
+    let res = promise.js_promise_get_value(realm);
+    let id = realm.cache_promise(promise);
+    let realm_id = realm.get_realm_id().to_string();
+    // a timer and not a task so due timers of other scripts run first
+    EventLoop::add_timeout(
+        move || {
+            SLICE_START.with(|s| s.set(Some(Instant::now())));
+            QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+                if let Some(realm) = q_js_rt.get_realm(realm_id.as_str()) {
+                    if let Some(promise) = realm.consume_cached_promise(id) {
+                        let res = realm
+                            .create_undefined()
+                            .and_then(|undefined| promise.js_promise_resolve(realm, &undefined));
+                        if let Err(e) = res {
+                            log::error!("[{}] could not resume: {}", realm.get_realm_id(), e);
+                        }
+                    }
+                }
+                q_js_rt.run_pending_jobs_if_any();
+            })
+        },
+        Duration::ZERO,
+    );
+    Ok(res)
+}
+
+/// a ScriptPreProcessor which adds yields to the loops of scripts which start with the `'use cooperative';` directive
+#[derive(Default)]
+pub struct CooperativePreProcessor {}
+
+impl CooperativePreProcessor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ScriptPreProcessor for CooperativePreProcessor {
+    fn process(&self, script: &mut Script) -> Result<(), JsError> {
+        let code = script.get_runnable_code();
+        let trimmed = code.trim_start();
+        if !DIRECTIVES.iter().any(|d| trimmed.starts_with(d)) {
+            return Ok(());
+        }
+        let new_code = format!("(async () => {{\n{}\n}})()", inject_yields(code));
+        if script.get_runnable_code() == script.get_code() {
+            script.set_code(new_code);
+        } else {
+            let map = script.get_map().map(|m| m.to_string());
+            script.set_transpiled_code(new_code, map);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Punct(char),
+    Arrow,
+    Literal,
+}
+
+impl Token {
+    fn is_word(&self, word: &str) -> bool {
+        matches!(self, Token::Word(w) if w == word)
+    }
+}
+
+// what a ( belongs to
+#[derive(Clone, Copy, PartialEq)]
+enum ParenKind {
+    Loop,
+    // the params of a function, a method or an async arrow function (or a call, which is never followed by a {)
+    Params { is_async: bool },
+    Other,
+}
+
+const KEYWORDS: [&str; 9] = [
+    "if", "for", "while", "switch", "catch", "with", "return", "typeof", "function",
+];
+// words after which a / starts a regex
+const REGEX_PRECEDING_WORDS: [&str; 11] = [
+    "return", "typeof", "case", "do", "else", "in", "of", "new", "delete", "void", "throw",
+];
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$' || !c.is_ascii()
+}
+
+/// add a yield check at the start of every loop body in an async scope, the top level is considered async
+pub fn inject_yields(code: &str) -> String {
+    let chars: Vec<char> = code.chars().collect();
+    let mut res = String::with_capacity(code.len() + 256);
+
+    // for every open { whether it is in an async scope
+    let mut braces: Vec<bool> = vec![];
+    let mut parens: Vec<ParenKind> = vec![];
+    let mut last_closed_paren = ParenKind::Other;
+    let mut prev = Token::Literal;
+    let mut prev2 = Token::Literal;
+    // Some(is_async) after the function keyword until its params start
+    let mut function_header: Option<bool> = None;
+    // Some(is_async) after an arrow, until the next token
+    let mut arrow_body: Option<bool> = None;
+
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let in_async = braces.last().copied().unwrap_or(true);
+
+        // skip whitespace, comments, strings and regexes
+        if c.is_whitespace() {
+            res.push(c);
+            i += 1;
+            continue;
+        }
+        if c == '/' && chars.get(i + 1) == Some(&'/') {
+            while i < chars.len() && chars[i] != '\n' {
+                res.push(chars[i]);
+                i += 1;
+            }
+            continue;
+        }
+        if c == '/' && chars.get(i + 1) == Some(&'*') {
+            res.push_str("/*");
+            i += 2;
+            while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                res.push(chars[i]);
+                i += 1;
+            }
+            if i < chars.len() {
+                res.push_str("*/");
+                i += 2;
+            }
+            continue;
+        }
+        let starts_regex = c == '/'
+            && match &prev {
+                Token::Punct(p) => !matches!(p, ')' | ']' | '}'),
+                Token::Word(w) => REGEX_PRECEDING_WORDS.contains(&w.as_str()),
+                Token::Arrow => true,
+                Token::Literal => res.trim().is_empty(),
+            };
+        if c == '\'' || c == '"' || c == '`' || starts_regex {
+            let mut in_class = false;
+            res.push(c);
+            i += 1;
+            while i < chars.len() {
+                let sc = chars[i];
+                res.push(sc);
+                i += 1;
+                if sc == '\\' {
+                    if let Some(escaped) = chars.get(i) {
+                        res.push(*escaped);
+                        i += 1;
+                    }
+                } else if starts_regex && sc == '[' {
+                    in_class = true;
+                } else if starts_regex && sc == ']' {
+                    in_class = false;
+                } else if sc == c && !in_class {
+                    break;
+                }
+            }
+            prev2 = std::mem::replace(&mut prev, Token::Literal);
+            arrow_body = None;
+            continue;
+        }
+
+        if is_word_char(c) {
+            let start = i;
+            while i < chars.len() && is_word_char(chars[i]) {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            res.push_str(word.as_str());
+            if word == "function" {
+                function_header = Some(prev.is_word("async"));
+            }
+            arrow_body = None;
+            prev2 = std::mem::replace(&mut prev, Token::Word(word));
+            continue;
+        }
+
+        if c == '=' && chars.get(i + 1) == Some(&'>') {
+            res.push_str("=>");
+            i += 2;
+            let is_async = match &prev {
+                Token::Punct(')') => last_closed_paren == ParenKind::Params { is_async: true },
+                Token::Word(_) => prev2.is_word("async"),
+                _ => false,
+            };
+            arrow_body = Some(is_async);
+            prev2 = std::mem::replace(&mut prev, Token::Arrow);
+            continue;
+        }
+
+        res.push(c);
+        i += 1;
+        match c {
+            '(' => {
+                let kind = if function_header.is_some() {
+                    ParenKind::Params {
+                        is_async: function_header.take().unwrap_or(false),
+                    }
+                } else {
+                    match &prev {
+                        Token::Word(w) if w == "for" || w == "while" => ParenKind::Loop,
+                        Token::Word(w) if w == "await" && prev2.is_word("for") => ParenKind::Loop,
+                        Token::Word(w) if w == "async" => ParenKind::Params { is_async: true },
+                        Token::Word(w) if !KEYWORDS.contains(&w.as_str()) => ParenKind::Params {
+                            is_async: prev2.is_word("async"),
+                        },
+                        _ => ParenKind::Other,
+                    }
+                };
+                parens.push(kind);
+            }
+            ')' => {
+                last_closed_paren = parens.pop().unwrap_or(ParenKind::Other);
+            }
+            '{' => {
+                let (is_async, is_loop) = if let Some(is_async) = arrow_body {
+                    (is_async, false)
+                } else if prev == Token::Punct(')') {
+                    match last_closed_paren {
+                        ParenKind::Loop => (in_async, true),
+                        ParenKind::Params { is_async } => (is_async, false),
+                        ParenKind::Other => (in_async, false),
+                    }
+                } else {
+                    (in_async, prev.is_word("do"))
+                };
+                if is_loop && is_async {
+                    res.push_str(YIELD_CODE);
+                }
+                braces.push(is_async);
+            }
+            '}' => {
+                braces.pop();
+            }
+            _ => {}
+        }
+        arrow_body = None;
+        prev2 = std::mem::replace(&mut prev, Token::Punct(c));
+    }
+    res
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::features::timeslice::inject_yields;
+    use crate::jsutils::Script;
+    use crate::values::JsValueFacade;
+    use std::time::Duration;
+
+    #[test]
+    fn test_inject_yields() {
+        let y = " if (yieldToHost.due()) await yieldToHost();";
+        assert_eq!(
+            inject_yields("for (let i = 0; i < 3; i++) { a(); }"),
+            format!("for (let i = 0; i < 3; i++) {{{y} a(); }}")
+        );
+        assert_eq!(
+            inject_yields("while (x) { do { y(); } while (z); }"),
+            format!("while (x) {{{y} do {{{y} y(); }} while (z); }}")
+        );
+        assert_eq!(
+            inject_yields("async function f({a}) { for (const x of a) { g(x); } }"),
+            format!("async function f({{a}}) {{ for (const x of a) {{{y} g(x); }} }}")
+        );
+        // not in sync functions, strings, comments or regexes
+        let sync_code = "function f() { for (;;) { break; } } const g = (a) => { while (a) { a--; } }; class C { m() { while (1) { break; } } }";
+        assert_eq!(inject_yields(sync_code), sync_code);
+        let literal_code = "'for (;;) {' + \"while (1) {\" + `do {` /* for (;;) { */ + /for (;;) {/.source; // while (1) {";
+        assert_eq!(inject_yields(literal_code), literal_code);
+        assert_eq!(
+            inject_yields("const h = async (a) => { for (;;) { break; } }; const i = async a => { while (a) { a--; } };"),
+            format!("const h = async (a) => {{ for (;;) {{{y} break; }} }}; const i = async a => {{ while (a) {{{y} a--; }} }};")
+        );
+    }
+
+    #[test]
+    fn test_time_slice() {
+        let rt = QuickJsRuntimeBuilder::new()
+            .time_slice(Duration::from_millis(5))
+            .build();
+
+        // two realms each compute for a while, a timer in the main realm keeps firing in between
+        rt.eval_sync(
+            None,
+            Script::new(
+                "test_time_slice.js",
+                "globalThis.latencies = []; let last = Date.now(); globalThis.ticker = setInterval(() => { const now = Date.now(); latencies.push(now - last); last = now; }, 10);",
+            ),
+        )
+        .expect("script failed");
+        let compute = "'use cooperative';\nglobalThis.startedAt = Date.now();\nlet sum = 0;\nfor (let i = 0; i < 1000000; i++) {\n    sum += Math.sqrt(i);\n}\nglobalThis.doneAt = Date.now();";
+        for realm in ["slice_a", "slice_b"] {
+            let res = rt
+                .eval_sync(Some(realm), Script::new("test_time_slice.js", compute))
+                .expect("script failed");
+            assert!(res.is_js_promise());
+        }
+
+        let read = |realm: &str, code: &str| {
+            rt.eval_sync(Some(realm), Script::new("test_time_slice.js", code))
+                .expect("script failed")
+        };
+        let mut waited = 0;
+        while read("slice_a", "typeof doneAt").get_str() == "undefined"
+            || read("slice_b", "typeof doneAt").get_str() == "undefined"
+        {
+            std::thread::sleep(Duration::from_millis(10));
+            waited += 1;
+            assert!(waited < 1000, "computations did not finish");
+        }
+
+        let range = |realm: &str| {
+            let res = read(realm, "`${startedAt},${doneAt}`");
+            let (start, done) = res.get_str().split_once(',').expect("no range");
+            (
+                start.parse::<f64>().expect("not a number"),
+                done.parse::<f64>().expect("not a number"),
+            )
+        };
+        let (a_start, a_done) = range("slice_a");
+        let (b_start, b_done) = range("slice_b");
+        // the computations overlapped
+        assert!(a_start < b_done && b_start < a_done);
+
+        let max_latency = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "test_time_slice.js",
+                    "clearInterval(ticker); Math.max(...latencies)",
+                ),
+            )
+            .expect("script failed");
+        let max_latency = match max_latency {
+            JsValueFacade::I32 { val } => val as f64,
+            JsValueFacade::F64 { val } => val,
+            _ => panic!("not a number"),
+        };
+        assert!(max_latency < 100.0, "timer latency was {max_latency}ms");
+    }
+}
@@ -0,0 +1,757 @@
+//! serving http requests with script handlers, requires the `http` feature
+//!
+//! [serve] accepts http/1.1 connections and dispatches every request to the `handleRequest` function which is exported by a module,
+//! the `route_fn` decides which module handles a request, in which realm it runs and with which [JobContext]
+//!
+//! the handler is called with a frozen request object with `method`, `url`, `headers` (lowercase names, repeated headers are joined with `, `),
+//! `body` (a Uint8Array) and `text()` / `json()` helpers, it may return (a promise of)
+//! * a string or Uint8Array, which is sent with status 200
+//! * an object with an optional `status`, `headers` and `body`, a body which is not a string, Uint8Array or iterator is sent as json
+//! * an object with an (async) iterator or generator as body, the chunks (strings or Uint8Arrays) are streamed as they are produced
+//!
+//! * a handler which throws or rejects before it responded results in a 500 response with a json body with the error name and message,
+//!   the stack is only included when [expose_stacks](ServeOptions::expose_stacks) is set, message and stack are redacted
+//! * a handler which does not respond within the timeout results in a 504 response, a stream which is not done within the timeout is aborted
+//! * requests for which `route_fn` returns None get a 404 response, bodies which are larger than the max body size a 413 response
+//!
+//! requests are handled concurrently on the worker thread of the runtime, a handler should not block it with long running sync code
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::integrations::http::{serve, Route, ServeOptions};
+//! use quickjs_runtime::jsutils::modules::ScriptModuleLoader;
+//! use quickjs_runtime::quickjsrealmadapter::QuickJsRealmAdapter;
+//! struct HandlerLoader {}
+//! impl ScriptModuleLoader for HandlerLoader {
+//!     fn normalize_path(&self, _realm: &QuickJsRealmAdapter, _ref_path: &str, path: &str) -> Option<String> {
+//!         (path == "hello.mes").then(|| path.to_string())
+//!     }
+//!     fn load_module(&self, _realm: &QuickJsRealmAdapter, _absolute_path: &str) -> String {
+//!         "export function handleRequest(req) { return {status: 200, body: {hello: req.url}}; }".to_string()
+//!     }
+//! }
+//! # tokio::runtime::Runtime::new().unwrap().block_on(async {
+//! let rt = QuickJsRuntimeBuilder::new().script_module_loader(HandlerLoader {}).build();
+//! let server = serve(&rt, "127.0.0.1:0".parse().unwrap(), |_parts| Some(Route::new("hello.mes")), ServeOptions::default())
+//!     .await
+//!     .expect("could not bind");
+//! println!("listening on {}", server.local_addr());
+//! server.shutdown();
+//! # });
+//! ```
+
+use crate::facades::{QuickJsRuntimeFacade, QuickjsRuntimeFacadeInner};
+use crate::jsutils::jobcontext::{JobContext, JobContextGuard};
+use crate::jsutils::{JsError, Script};
+use crate::quickjs_utils::errors::{timeout_error, type_error};
+use crate::quickjs_utils::{errors, functions, interrupthandler, objects, promises, typedarrays};
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use crate::quickjsvalueadapter::QuickJsValueAdapter;
+use bytes::Bytes;
+use http_body_util::combinators::UnsyncBoxBody;
+use http_body_util::{BodyExt, Full, Limited, StreamBody};
+use hyper::body::{Frame, Incoming};
+use hyper::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use hyper::http::request::Parts;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use serde_json::Value;
+use std::cell::RefCell;
+use std::net::SocketAddr;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+
+static HTTP_REALM_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+const DRIVER: &str = r#"
+(async function (module, raw, respond, send) {
+    const handleRequest = (await import(module)).handleRequest;
+    if (typeof handleRequest !== 'function') {
+        throw new TypeError(`${module} does not export a handleRequest function`);
+    }
+    const request = Object.freeze({
+        method: raw.method,
+        url: raw.url,
+        headers: Object.freeze(raw.headers),
+        body: raw.body,
+        text: async () => raw.text,
+        json: async () => JSON.parse(raw.text),
+    });
+    let res = await handleRequest(request);
+    if (res === undefined || res === null) {
+        res = {status: 204};
+    } else if (typeof res === 'string' || res instanceof Uint8Array) {
+        res = {body: res};
+    }
+    const headers = Object.assign({}, res.headers);
+    let body = res.body;
+    const isChunk = typeof body === 'string' || body instanceof Uint8Array;
+    const isStream = !isChunk && body !== null && typeof body === 'object' && !Array.isArray(body)
+        && (typeof body[Symbol.asyncIterator] === 'function' || typeof body[Symbol.iterator] === 'function');
+    if (body !== undefined && body !== null && !isChunk && !isStream) {
+        body = JSON.stringify(body);
+        if (!Object.keys(headers).some((name) => name.toLowerCase() === 'content-type')) {
+            headers['content-type'] = 'application/json';
+        }
+    }
+    respond(res.status ?? 200, headers);
+    if (isStream) {
+        for await (const chunk of body) {
+            send(chunk);
+        }
+    } else if (body !== undefined && body !== null) {
+        send(body);
+    }
+})
+"#;
+
+type HttpBody = UnsyncBoxBody<Bytes, JsError>;
+type HeadResult = Result<(StatusCode, HeaderMap), JsError>;
+type Chunk = Result<Bytes, JsError>;
+
+/// where and how a request is handled
+pub struct Route {
+    /// the name of the module which exports `handleRequest`
+    pub module: String,
+    /// the realm to handle the request in, None for the main realm, ignored when isolated is set
+    pub realm_id: Option<String>,
+    /// handle the request in a new realm which is dropped when the response is done
+    pub isolated: bool,
+    /// the context which is readable with [current_job_context](crate::quickjsruntimeadapter::QuickJsRuntimeAdapter::current_job_context) while the handler runs
+    pub context: JobContext,
+}
+
+impl Route {
+    pub fn new(module: &str) -> Self {
+        Self {
+            module: module.to_string(),
+            realm_id: None,
+            isolated: false,
+            context: JobContext::new(),
+        }
+    }
+    pub fn realm(mut self, realm_id: &str) -> Self {
+        self.realm_id = Some(realm_id.to_string());
+        self
+    }
+    pub fn isolated(mut self) -> Self {
+        self.isolated = true;
+        self
+    }
+    pub fn context(mut self, context: JobContext) -> Self {
+        self.context = context;
+        self
+    }
+}
+
+/// the limits of a server
+pub struct ServeOptions {
+    /// the maximum duration of a request, from reading the request body until the last chunk of the response was produced
+    pub timeout: Duration,
+    /// the maximum size of a request body in bytes
+    pub max_body_size: usize,
+    /// include the (redacted) stack of an error in a 500 response
+    pub expose_stacks: bool,
+}
+
+impl Default for ServeOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+            max_body_size: 1024 * 1024,
+            expose_stacks: false,
+        }
+    }
+}
+
+/// a running server, the server stops accepting connections when it is shut down or dropped
+pub struct HttpServer {
+    local_addr: SocketAddr,
+    shutdown_tx: Option<oneshot::Sender<()>>,
+}
+
+impl HttpServer {
+    /// the address the server listens on, useful when binding to port 0
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+    /// stop accepting connections, requests which are being handled are completed
+    pub fn shutdown(mut self) {
+        self.stop();
+    }
+    fn stop(&mut self) {
+        if let Some(tx) = self.shutdown_tx.take() {
+            let _ = tx.send(());
+        }
+    }
+}
+
+impl Drop for HttpServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+type RouteFn = Box<dyn Fn(&Parts) -> Option<Route> + Send + Sync>;
+
+struct Server {
+    rti: Weak<QuickjsRuntimeFacadeInner>,
+    route_fn: RouteFn,
+    options: ServeOptions,
+}
+
+/// start serving http requests, the server runs on the current tokio runtime
+///
+/// the server does not keep the runtime alive, requests which arrive after the runtime was dropped get a 503 response
+pub async fn serve<R>(
+    rt: &QuickJsRuntimeFacade,
+    addr: SocketAddr,
+    route_fn: R,
+    options: ServeOptions,
+) -> Result<HttpServer, JsError>
+where
+    R: Fn(&Parts) -> Option<Route> + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind(addr)
+        .await
+        .map_err(|e| JsError::new_string(format!("could not bind to {addr}: {e}")))?;
+    let local_addr = listener
+        .local_addr()
+        .map_err(|e| JsError::new_string(format!("{e}")))?;
+    let server = Arc::new(Server {
+        rti: Arc::downgrade(rt.get_inner()),
+        route_fn: Box::new(route_fn),
+        options,
+    });
+    let (shutdown_tx, mut shutdown_rx) = oneshot::channel::<()>();
+    tokio::spawn(async move {
+        loop {
+            let stream = tokio::select! {
+                _ = &mut shutdown_rx => break,
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        log::error!("http: could not accept connection: {}", e);
+                        continue;
+                    }
+                },
+            };
+            let server = server.clone();
+            tokio::spawn(async move {
+                let service = service_fn(move |req| {
+                    let server = server.clone();
+                    async move { Ok::<_, JsError>(handle_request(server, req).await) }
+                });
+                if let Err(e) = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await
+                {
+                    log::debug!("http: connection failed: {}", e);
+                }
+            });
+        }
+    });
+    Ok(HttpServer {
+        local_addr,
+        shutdown_tx: Some(shutdown_tx),
+    })
+}
+
+fn full_response(status: StatusCode, content_type: &str, body: String) -> Response<HttpBody> {
+    let mut res = Response::new(
+        Full::new(Bytes::from(body))
+            .map_err(|never| match never {})
+            .boxed_unsync(),
+    );
+    *res.status_mut() = status;
+    if let Ok(value) = HeaderValue::from_str(content_type) {
+        res.headers_mut().insert(CONTENT_TYPE, value);
+    }
+    res
+}
+
+fn status_response(status: StatusCode) -> Response<HttpBody> {
+    full_response(
+        status,
+        "text/plain",
+        status.canonical_reason().unwrap_or("").to_string(),
+    )
+}
+
+fn error_response(err: &JsError, expose_stacks: bool) -> Response<HttpBody> {
+    let mut body = serde_json::json!({
+        "error": err.get_name(),
+        "message": err.get_message(),
+    });
+    if expose_stacks {
+        body["stack"] = Value::String(err.get_stack().to_string());
+    }
+    full_response(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "application/json",
+        body.to_string(),
+    )
+}
+
+// drops the realm of an isolated request when the response is done or the connection was closed
+struct RequestRealmGuard {
+    rti: Weak<QuickjsRuntimeFacadeInner>,
+    realm_id: Option<String>,
+}
+
+impl Drop for RequestRealmGuard {
+    fn drop(&mut self) {
+        if let (Some(rti), Some(realm_id)) = (self.rti.upgrade(), self.realm_id.take()) {
+            rti.add_task_to_event_loop_void(move || {
+                if QuickJsRuntimeAdapter::do_with(|q_js_rt| q_js_rt.has_context(realm_id.as_str()))
+                {
+                    QuickJsRuntimeAdapter::remove_context(realm_id.as_str());
+                }
+            });
+        }
+    }
+}
+
+async fn handle_request(server: Arc<Server>, req: Request<Incoming>) -> Response<HttpBody> {
+    let (parts, body) = req.into_parts();
+    let route = match (server.route_fn)(&parts) {
+        Some(route) => route,
+        None => return status_response(StatusCode::NOT_FOUND),
+    };
+    let options = &server.options;
+    let deadline = tokio::time::Instant::now() + options.timeout;
+    let body = match Limited::new(body, options.max_body_size).collect().await {
+        Ok(collected) => collected.to_bytes(),
+        Err(e) if e.is::<http_body_util::LengthLimitError>() => {
+            return status_response(StatusCode::PAYLOAD_TOO_LARGE)
+        }
+        Err(e) => {
+            log::debug!("http: could not read request body: {}", e);
+            return status_response(StatusCode::BAD_REQUEST);
+        }
+    };
+    let rti = match server.rti.upgrade() {
+        Some(rti) => rti,
+        None => return status_response(StatusCode::SERVICE_UNAVAILABLE),
+    };
+
+    let guard = RequestRealmGuard {
+        rti: server.rti.clone(),
+        realm_id: route.isolated.then(|| {
+            format!(
+                "__http_{}",
+                HTTP_REALM_COUNTER.fetch_add(1, Ordering::SeqCst)
+            )
+        }),
+    };
+    let (head_tx, head_rx) = flume::bounded::<HeadResult>(1);
+    let (chunk_tx, chunk_rx) = flume::unbounded::<Chunk>();
+    let realm_id = guard.realm_id.clone().or(route.realm_id.clone());
+    let isolated = route.isolated;
+    let timeout = options.timeout;
+    let started = rti
+        .add_task_to_event_loop(move || {
+            if isolated {
+                if let Some(realm_id) = realm_id.as_deref() {
+                    QuickJsRuntimeAdapter::create_context(realm_id)?;
+                }
+            }
+            QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+                let realm = match realm_id.as_deref() {
+                    Some(realm_id) => q_js_rt
+                        .get_realm(realm_id)
+                        .ok_or_else(|| JsError::new_string(format!("no such realm: {realm_id}")))?,
+                    None => q_js_rt.get_main_realm(),
+                };
+                start_request_q(
+                    q_js_rt,
+                    realm,
+                    route.module.as_str(),
+                    route.context,
+                    parts_to_raw(&parts),
+                    body,
+                    timeout,
+                    head_tx,
+                    chunk_tx,
+                )
+            })
+        })
+        .await;
+    drop(rti);
+    if let Err(err) = started {
+        // the sync part of the handler was interrupted
+        if tokio::time::Instant::now() >= deadline {
+            return status_response(StatusCode::GATEWAY_TIMEOUT);
+        }
+        return error_response(&err, options.expose_stacks);
+    }
+
+    let (status, headers) = match tokio::time::timeout_at(deadline, head_rx.recv_async()).await {
+        Ok(Ok(Ok(head))) => head,
+        Ok(Ok(Err(err))) => return error_response(&err, options.expose_stacks),
+        Ok(Err(_)) => {
+            return error_response(
+                &JsError::new_str("handler finished without a response"),
+                options.expose_stacks,
+            )
+        }
+        Err(_) => return status_response(StatusCode::GATEWAY_TIMEOUT),
+    };
+
+    // the guard is moved into the body so an isolated realm lives until the last chunk was produced
+    let chunks = futures::stream::unfold(Some((chunk_rx, guard)), move |state| async move {
+        let (chunk_rx, guard) = state?;
+        match tokio::time::timeout_at(deadline, chunk_rx.recv_async()).await {
+            Ok(Ok(Ok(bytes))) => Some((Ok(Frame::data(bytes)), Some((chunk_rx, guard)))),
+            Ok(Ok(Err(err))) => Some((Err(err), None)),
+            Ok(Err(_)) => None,
+            Err(_) => Some((Err(timeout_error("request", timeout)), None)),
+        }
+    });
+    let mut res = Response::new(StreamBody::new(chunks).boxed_unsync());
+    *res.status_mut() = status;
+    *res.headers_mut() = headers;
+    res
+}
+
+fn parts_to_raw(parts: &Parts) -> Value {
+    let mut headers = serde_json::Map::new();
+    for (name, value) in parts.headers.iter() {
+        let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+        match headers.get_mut(name.as_str()) {
+            Some(Value::String(existing)) => {
+                existing.push_str(", ");
+                existing.push_str(value.as_str());
+            }
+            _ => {
+                headers.insert(name.as_str().to_string(), Value::String(value));
+            }
+        }
+    }
+    serde_json::json!({
+        "method": parts.method.as_str(),
+        "url": parts.uri.to_string(),
+        "headers": headers,
+    })
+}
+
+fn reason_to_js_error(realm: &QuickJsRealmAdapter, reason: &QuickJsValueAdapter) -> JsError {
+    unsafe { errors::thrown_to_js_error(realm.context, reason) }
+}
+
+fn to_head(
+    realm: &QuickJsRealmAdapter,
+    args: &[QuickJsValueAdapter],
+) -> Result<(StatusCode, HeaderMap), JsError> {
+    let status = match args.first() {
+        Some(status) => realm.value_adapter_to_serde_value(status)?,
+        None => Value::Null,
+    };
+    let status = status
+        .as_u64()
+        .and_then(|status| u16::try_from(status).ok())
+        .and_then(|status| StatusCode::from_u16(status).ok())
+        .ok_or_else(|| type_error(format!("invalid status: {status}")))?;
+    let mut headers = HeaderMap::new();
+    if let Some(arg) = args.get(1) {
+        if let Value::Object(map) = realm.value_adapter_to_serde_value(arg)? {
+            for (name, value) in map {
+                let header_name = HeaderName::from_bytes(name.as_bytes())
+                    .map_err(|_| type_error(format!("invalid header name: {name}")))?;
+                let values = match value {
+                    Value::Array(values) => values,
+                    value => vec![value],
+                };
+                for value in values {
+                    let value = match value {
+                        Value::String(s) => s,
+                        other => other.to_string(),
+                    };
+                    let header_value = HeaderValue::from_str(value.as_str())
+                        .map_err(|_| type_error(format!("invalid value for header {name}")))?;
+                    headers.append(header_name.clone(), header_value);
+                }
+            }
+        }
+    }
+    Ok((status, headers))
+}
+
+fn to_chunk(realm: &QuickJsRealmAdapter, chunk: &QuickJsValueAdapter) -> Result<Bytes, JsError> {
+    if chunk.is_string() {
+        Ok(Bytes::from(chunk.to_string()?))
+    } else if chunk.is_typed_array() {
+        typedarrays::with_buffer_bytes_q(realm, chunk, Bytes::copy_from_slice)
+    } else {
+        Err(type_error(
+            "a body chunk must be a string or a Uint8Array".to_string(),
+        ))
+    }
+}
+
+// call the driver, the head and the chunks of the response are sent by the native respond and send functions
+#[allow(clippy::too_many_arguments)]
+fn start_request_q(
+    q_js_rt: &QuickJsRuntimeAdapter,
+    realm: &QuickJsRealmAdapter,
+    module: &str,
+    context: JobContext,
+    raw: Value,
+    body: Bytes,
+    timeout: Duration,
+    head_tx: flume::Sender<HeadResult>,
+    chunk_tx: flume::Sender<Chunk>,
+) -> Result<(), JsError> {
+    let _context_guard = JobContextGuard::enter(Some(Arc::new(Mutex::new(context))));
+
+    let raw_ref = realm.serde_value_to_value_adapter(raw)?;
+    let text = realm.create_string(String::from_utf8_lossy(&body).as_ref())?;
+    objects::set_property_q(realm, &raw_ref, "text", &text)?;
+    let body_ref = typedarrays::new_uint8_array_copy_q(realm, &body)?;
+    objects::set_property_q(realm, &raw_ref, "body", &body_ref)?;
+
+    let head_tx = Rc::new(RefCell::new(Some(head_tx)));
+    let chunk_tx = Rc::new(RefCell::new(Some(chunk_tx)));
+
+    let respond_head_tx = head_tx.clone();
+    let respond = functions::new_function_q(
+        realm,
+        "respond",
+        move |realm, _this, args| {
+            let head = to_head(realm, args)?;
+            match respond_head_tx.borrow_mut().take() {
+                Some(tx) => {
+                    let _ = tx.send(Ok(head));
+                    realm.create_undefined()
+                }
+                None => Err(JsError::new_str("already responded")),
+            }
+        },
+        2,
+    )?;
+    let send_chunk_tx = chunk_tx.clone();
+    let send = functions::new_function_q(
+        realm,
+        "send",
+        move |realm, _this, args| {
+            let chunk = match args.first() {
+                Some(chunk) => to_chunk(realm, chunk)?,
+                None => Bytes::new(),
+            };
+            if let Some(tx) = &*send_chunk_tx.borrow() {
+                // the receiver is gone when the client disconnected, the handler is not interrupted for that
+                let _ = tx.send(Ok(chunk));
+            }
+            realm.create_undefined()
+        },
+        1,
+    )?;
+
+    let done_chunk_tx = chunk_tx.clone();
+    let then = functions::new_function_q(
+        realm,
+        "then",
+        move |realm, _this, _args| {
+            done_chunk_tx.borrow_mut().take();
+            realm.create_undefined()
+        },
+        1,
+    )?;
+    let catch = functions::new_function_q(
+        realm,
+        "catch",
+        move |realm, _this, args| {
+            let err = match args.first() {
+                Some(reason) => reason_to_js_error(realm, reason),
+                None => JsError::new_str("handler failed"),
+            }
+            .redact();
+            match (head_tx.borrow_mut().take(), chunk_tx.borrow_mut().take()) {
+                (Some(head_tx), _) => {
+                    let _ = head_tx.send(Err(err));
+                }
+                (None, Some(chunk_tx)) => {
+                    log::error!("http: handler failed while streaming: {}", err);
+                    let _ = chunk_tx.send(Err(err));
+                }
+                (None, None) => {}
+            }
+            realm.create_undefined()
+        },
+        1,
+    )?;
+
+    let driver = realm.eval(Script::new("quickjs_http_driver.js", DRIVER))?;
+    let module_ref = realm.create_string(module)?;
+
+    let deadline = std::time::Instant::now() + timeout;
+    let previous_deadline = q_js_rt.interrupt_deadline.get();
+    q_js_rt.interrupt_deadline.set(Some(deadline));
+    interrupthandler::init(q_js_rt);
+    let res =
+        functions::call_function_q(realm, &driver, &[module_ref, raw_ref, respond, send], None);
+    q_js_rt.interrupt_deadline.set(previous_deadline);
+    let promise = res?;
+    promises::add_promise_reactions_q(realm, &promise, Some(then), Some(catch), None)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::integrations::http::{serve, Route, ServeOptions};
+    use crate::jsutils::jobcontext::JobContext;
+    use crate::jsutils::modules::ScriptModuleLoader;
+    use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+    use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+    use crate::values::JsValueFacade;
+    use std::net::SocketAddr;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    #[derive(Clone)]
+    struct RequestPath(String);
+
+    struct HandlerLoader {}
+
+    impl ScriptModuleLoader for HandlerLoader {
+        fn normalize_path(
+            &self,
+            _realm: &QuickJsRealmAdapter,
+            _ref_path: &str,
+            path: &str,
+        ) -> Option<String> {
+            (path == "handler.mes").then(|| path.to_string())
+        }
+
+        fn load_module(&self, _realm: &QuickJsRealmAdapter, _absolute_path: &str) -> String {
+            r#"
+            export async function handleRequest(req) {
+                switch (req.url) {
+                    case '/plain':
+                        return {status: 201, headers: {'x-method': req.method}, body: {echo: await req.json()}};
+                    case '/stream':
+                        return {body: (async function* () { yield 'a'; await null; yield new Uint8Array([98]); })()};
+                    case '/throw':
+                        throw new Error('secret failure');
+                    case '/slow':
+                        return new Promise(() => {});
+                    case '/context':
+                        await null;
+                        return requestPath();
+                    case '/count':
+                    case '/isolated':
+                        globalThis.hits = (globalThis.hits || 0) + 1;
+                        return String(globalThis.hits);
+                }
+            }
+            "#
+            .to_string()
+        }
+    }
+
+    async fn request(addr: SocketAddr, method: &str, path: &str, body: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.expect("could not connect");
+        let req = format!(
+            "{method} {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        stream
+            .write_all(req.as_bytes())
+            .await
+            .expect("could not write");
+        let mut res = String::new();
+        let _ = stream.read_to_string(&mut res).await;
+        res
+    }
+
+    #[tokio::test]
+    async fn test_serve() {
+        let rt = QuickJsRuntimeBuilder::new()
+            .script_module_loader(HandlerLoader {})
+            .build();
+        rt.set_function(&[], "requestPath", |_realm, _args| {
+            Ok(
+                match QuickJsRuntimeAdapter::current_job_context::<RequestPath>() {
+                    Some(path) => JsValueFacade::new_string(path.0),
+                    None => JsValueFacade::Null,
+                },
+            )
+        })
+        .expect("could not set function");
+        let server = serve(
+            &rt,
+            "127.0.0.1:0".parse().unwrap(),
+            |parts| {
+                let path = parts.uri.path().to_string();
+                match path.as_str() {
+                    "/missing" => None,
+                    "/isolated" => Some(Route::new("handler.mes").isolated()),
+                    _ => Some(
+                        Route::new("handler.mes")
+                            .context(JobContext::new().with(RequestPath(path))),
+                    ),
+                }
+            },
+            ServeOptions {
+                timeout: Duration::from_millis(500),
+                max_body_size: 64,
+                expose_stacks: false,
+            },
+        )
+        .await
+        .expect("could not bind");
+        let addr = server.local_addr();
+
+        let res = request(addr, "POST", "/plain", "{\"a\":1}").await;
+        assert!(res.starts_with("HTTP/1.1 201"), "{res}");
+        assert!(res.contains("x-method: POST"), "{res}");
+        assert!(res.contains("content-type: application/json"), "{res}");
+        assert!(res.ends_with("{\"echo\":{\"a\":1}}"), "{res}");
+
+        let res = request(addr, "GET", "/stream", "").await;
+        assert!(res.starts_with("HTTP/1.1 200"), "{res}");
+        assert!(res.contains("transfer-encoding: chunked"), "{res}");
+        assert!(res.contains("\r\n1\r\na\r\n1\r\nb\r\n0\r\n"), "{res}");
+
+        let res = request(addr, "GET", "/throw", "").await;
+        assert!(res.starts_with("HTTP/1.1 500"), "{res}");
+        assert!(res.contains("secret failure"), "{res}");
+        assert!(!res.contains("stack"), "{res}");
+
+        let res = request(addr, "GET", "/slow", "").await;
+        assert!(res.starts_with("HTTP/1.1 504"), "{res}");
+
+        let res = request(addr, "GET", "/context", "").await;
+        assert!(res.ends_with("/context"), "{res}");
+
+        let res = request(addr, "GET", "/missing", "").await;
+        assert!(res.starts_with("HTTP/1.1 404"), "{res}");
+
+        let res = request(addr, "POST", "/plain", &"x".repeat(100)).await;
+        assert!(res.starts_with("HTTP/1.1 413"), "{res}");
+
+        // the main realm is shared between requests, every isolated request gets a new realm
+        assert!(request(addr, "GET", "/count", "")
+            .await
+            .ends_with("\r\n\r\n1"));
+        assert!(request(addr, "GET", "/count", "")
+            .await
+            .ends_with("\r\n\r\n2"));
+        for _ in 0..2 {
+            let res = request(addr, "GET", "/isolated", "").await;
+            assert!(res.ends_with("\r\n\r\n1"), "{res}");
+        }
+
+        server.shutdown();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(TcpStream::connect(addr).await.is_err());
+    }
+}
@@ -0,0 +1,4 @@
+//! bridges between the runtime and other libraries, these are behind feature flags
+
+#[cfg(feature = "http")]
+pub mod http;
@@ -0,0 +1,207 @@
+//! async stack traces, see [async_stack_traces](crate::builder::QuickJsRuntimeBuilder::async_stack_traces)
+//!
+//! the stack of an error which rejects a promise only shows the frames of the job which rejected it, which is not where the script awaited it
+//!
+//! when async stack traces are enabled the stack of the script which created a promise through one of the bridges of the runtime (the async native functions
+//! which use [new_resolving_promise](crate::jsutils::promises::new_resolving_promise)) or which started a timer is recorded,
+//! when that promise is rejected or the timer callback throws the recorded stack is added under the stack of the error after an [AWAITED_AT] line,
+//! console.error also prints the stack of the errors it logs
+//!
+//! the recorded stack is limited to max_depth frames, when disabled (the default) nothing is recorded
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::{JsError, Script};
+//! use quickjs_runtime::values::JsValueFacade;
+//! use futures::executor::block_on;
+//! let rt = QuickJsRuntimeBuilder::new().async_stack_traces(true, 16).build();
+//! rt.set_function(&[], "loadUser", |realm, _args| {
+//!     let promise = realm.create_resolving_promise(
+//!         || Err::<(), JsError>(JsError::new_str("user not found")),
+//!         |realm, _| realm.create_undefined(),
+//!     )?;
+//!     realm.to_js_value_facade(&promise)
+//! }).expect("could not set function");
+//! let res = rt.eval_sync(None, Script::new("users.js", "async function showUser() { await loadUser(); }\nshowUser();")).expect("script failed");
+//! let cached_promise = match res {
+//!     JsValueFacade::JsPromise { cached_promise } => cached_promise,
+//!     _ => panic!("not a promise"),
+//! };
+//! let err = block_on(cached_promise.get_promise_result()).expect("promise failed").expect_err("promise resolved");
+//! let err = err.stringify();
+//! assert!(err.contains("--- awaited at ---"), "{err}");
+//! assert!(err.contains("users.js"), "{err}");
+//! ```
+
+use crate::jsutils::JsError;
+use crate::quickjs_utils::errors;
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use std::borrow::Cow;
+
+/// the line which separates the stack of an error from the stack where its promise was created
+pub const AWAITED_AT: &str = "--- awaited at ---";
+
+pub(crate) fn init(
+    q_js_rt: &QuickJsRuntimeAdapter,
+    enabled: bool,
+    max_depth: usize,
+) -> Result<(), JsError> {
+    q_js_rt
+        .async_stack_depth
+        .set(if enabled { Some(max_depth) } else { None });
+    Ok(())
+}
+
+/// check if async stack traces are enabled for the runtime of the current thread
+pub(crate) fn is_enabled() -> bool {
+    QuickJsRuntimeAdapter::do_with(|q_js_rt| q_js_rt.async_stack_depth.get().is_some())
+}
+
+/// record the current stack of a realm, returns None when async stack traces are disabled
+pub(crate) fn capture_q(realm: &QuickJsRealmAdapter) -> Option<String> {
+    let max_depth = QuickJsRuntimeAdapter::do_with(|q_js_rt| q_js_rt.async_stack_depth.get())?;
+    let stack = errors::get_stack(realm).ok()?;
+    if !stack.is_string() {
+        return None;
+    }
+    let stack = stack.to_string().ok()?;
+    let frames: Vec<&str> = stack
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.contains("(native)"))
+        .take(max_depth)
+        .collect();
+    if frames.is_empty() {
+        None
+    } else {
+        Some(frames.join("\n"))
+    }
+}
+
+/// add a recorded stack under the stack of an error
+pub(crate) fn stitch<'a>(stack: &'a str, creation_stack: Option<&str>) -> Cow<'a, str> {
+    match creation_stack {
+        None => Cow::Borrowed(stack),
+        Some(creation_stack) if stack.trim().is_empty() => {
+            Cow::Owned(format!("{AWAITED_AT}\n{creation_stack}"))
+        }
+        Some(creation_stack) => Cow::Owned(format!(
+            "{}\n{AWAITED_AT}\n{creation_stack}",
+            stack.trim_end()
+        )),
+    }
+}
+
+/// add a recorded stack under the stack of a JsError
+pub(crate) fn stitch_error(err: JsError, creation_stack: Option<&str>) -> JsError {
+    match creation_stack {
+        None => err,
+        Some(_) => {
+            let stack = stitch(err.get_stack(), creation_stack).into_owned();
+            JsError::new(
+                err.get_name().to_string(),
+                err.get_message().to_string(),
+                stack,
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::facades::QuickJsRuntimeFacade;
+    use crate::jsutils::asyncstacks::{stitch, AWAITED_AT};
+    use crate::jsutils::{JsError, Script};
+    use crate::values::JsValueFacade;
+    use futures::executor::block_on;
+    use std::time::Instant;
+
+    fn build(enabled: bool, max_depth: usize) -> QuickJsRuntimeFacade {
+        let rt = QuickJsRuntimeBuilder::new()
+            .async_stack_traces(enabled, max_depth)
+            .build();
+        rt.set_function(&[], "failLater", |realm, _args| {
+            let promise = realm.create_resolving_promise(
+                || Err::<(), JsError>(JsError::new_str("failed later")),
+                |realm, _| realm.create_undefined(),
+            )?;
+            realm.to_js_value_facade(&promise)
+        })
+        .expect("could not set function");
+        rt
+    }
+
+    fn rejection_stack(rt: &QuickJsRuntimeFacade) -> String {
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "test_async_stacks.js",
+                    "function a() { return b(); }\nfunction b() { return c(); }\nasync function c() { await failLater(); }\na();",
+                ),
+            )
+            .expect("script failed");
+        let cached_promise = match res {
+            JsValueFacade::JsPromise { cached_promise } => cached_promise,
+            _ => panic!("not a promise"),
+        };
+        let reason = block_on(cached_promise.get_promise_result())
+            .expect("promise failed")
+            .expect_err("promise resolved");
+        match reason {
+            JsValueFacade::JsError { val } => val.get_stack().to_string(),
+            other => other.stringify(),
+        }
+    }
+
+    #[test]
+    fn test_async_stack_traces() {
+        let stack = rejection_stack(&build(true, 16));
+        let (_, awaited) = stack.split_once(AWAITED_AT).expect("no awaited at");
+        for func in ["at c", "at b", "at a"] {
+            assert!(awaited.contains(func), "{stack}");
+        }
+
+        // the depth is bounded
+        let stack = rejection_stack(&build(true, 1));
+        let (_, awaited) = stack.split_once(AWAITED_AT).expect("no awaited at");
+        assert_eq!(awaited.trim().lines().count(), 1, "{stack}");
+
+        let stack = rejection_stack(&build(false, 16));
+        assert!(!stack.contains(AWAITED_AT), "{stack}");
+
+        assert_eq!(stitch("at x", None), "at x");
+        assert_eq!(stitch("", Some("at y")), format!("{AWAITED_AT}\nat y"));
+        assert_eq!(
+            stitch("at x\n", Some("at y")),
+            format!("at x\n{AWAITED_AT}\nat y")
+        );
+    }
+
+    /// the overhead of recording stacks for promises
+    /// run with cargo test --release bench_async_stack_traces -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_async_stack_traces() {
+        let code = "(async () => { for (let i = 0; i < 10000; i++) { try { await failLater(); } catch (e) {} } })();";
+        let mut durations = vec![];
+        for enabled in [false, true] {
+            let rt = build(enabled, 16);
+            let start = Instant::now();
+            let res = rt
+                .eval_sync(None, Script::new("bench_async_stacks.js", code))
+                .expect("script failed");
+            if let JsValueFacade::JsPromise { cached_promise } = res {
+                block_on(cached_promise.get_promise_result())
+                    .expect("promise failed")
+                    .expect("promise rejected");
+            }
+            durations.push(start.elapsed());
+        }
+        println!(
+            "10000 rejected promises, disabled: {:?}, enabled: {:?}",
+            durations[0], durations[1]
+        );
+    }
+}
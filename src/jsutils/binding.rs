@@ -0,0 +1,288 @@
+//! live bindings of rust structs, see [bind_object](crate::facades::QuickJsRuntimeFacade::bind_object)
+//!
+//! a bound object is a sealed JS object with a getter and a setter for every top-level property of the serialized struct,
+//! the getters and setters read and write through the `RwLock` on every access so changes on the rust side are visible to
+//! scripts and changes by scripts are visible to rust
+//!
+//! * only top-level properties are live, a nested value is copied when it is read so `bound.nested.a = 1` does not change the struct,
+//!   assign the whole nested value instead (`bound.nested = {...bound.nested, a: 1}`)
+//! * a set takes the write lock, merges the new value into the serialized struct and deserializes the struct, a value which does
+//!   not deserialize throws a TypeError and leaves the struct unchanged
+//! * the object is sealed, adding or deleting properties fails (throws in strict mode), properties which appear or disappear on the
+//!   rust side (e.g. skipped Options or map keys) are picked up by [BoundObjectHandle::refresh] which replaces the global with a new
+//!   bound object, objects which were obtained earlier keep working for the properties they had
+//! * a script which accesses the object while rust holds the write lock blocks the worker thread of the runtime until the lock is released,
+//!   rust code should not hold the lock for long and should never wait for the runtime while holding it
+//! * every get and set is atomic on its own, a read-modify-write in a script (e.g. `bound.count++`) is two accesses and may overwrite
+//!   a concurrent write from rust
+//!
+//! every access locks and serializes the whole struct, this is fine for configs but not for values which are read in hot loops,
+//! copy the object once (`{...bound}`) when a script needs to read it often
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::Script;
+//! use serde::{Deserialize, Serialize};
+//! use std::sync::{Arc, RwLock};
+//! #[derive(Serialize, Deserialize)]
+//! struct Config {
+//!     retries: i32,
+//! }
+//! let rt = QuickJsRuntimeBuilder::new().build();
+//! let config = Arc::new(RwLock::new(Config { retries: 3 }));
+//! let _handle = rt.bind_object(None, "config", config.clone()).expect("bind failed");
+//! config.write().unwrap().retries = 5;
+//! let res = rt.eval_sync(None, Script::new("bind.js", "config.retries++; config.retries")).expect("script failed");
+//! assert_eq!(res.get_i32(), 6);
+//! assert_eq!(config.read().unwrap().retries, 6);
+//! ```
+
+use crate::facades::QuickjsRuntimeFacadeInner;
+use crate::jsutils::JsError;
+use crate::quickjs_utils::errors::type_error;
+use crate::quickjs_utils::{functions, get_global_q, objects};
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use crate::quickjsvalueadapter::QuickJsValueAdapter;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::{Arc, RwLock, Weak};
+
+type Installer = dyn Fn(&QuickJsRealmAdapter) -> Result<(), JsError> + Send + Sync;
+
+fn to_object_value<T: Serialize>(value: &T) -> Result<serde_json::Map<String, Value>, JsError> {
+    match serde_json::to_value(value) {
+        Ok(Value::Object(map)) => Ok(map),
+        Ok(_) => Err(type_error(
+            "a bound struct must serialize to an object".to_string(),
+        )),
+        Err(e) => Err(JsError::new_string(format!("{e}"))),
+    }
+}
+
+fn read_property<T: Serialize>(lock: &RwLock<T>, name: &str) -> Result<Value, JsError> {
+    let guard = lock
+        .read()
+        .map_err(|_| JsError::new_str("bound object lock was poisoned"))?;
+    Ok(to_object_value(&*guard)?
+        .remove(name)
+        .unwrap_or(Value::Null))
+}
+
+fn write_property<T: Serialize + DeserializeOwned>(
+    lock: &RwLock<T>,
+    name: &str,
+    value: Value,
+) -> Result<(), JsError> {
+    let mut guard = lock
+        .write()
+        .map_err(|_| JsError::new_str("bound object lock was poisoned"))?;
+    let mut map = to_object_value(&*guard)?;
+    map.insert(name.to_string(), value);
+    *guard = serde_json::from_value(Value::Object(map))
+        .map_err(|e| type_error(format!("invalid value for {name}: {e}")))?;
+    Ok(())
+}
+
+/// create a bound object for a struct, the object is not added to the global scope
+pub fn create_bound_object_q<T>(
+    realm: &QuickJsRealmAdapter,
+    value: &Arc<RwLock<T>>,
+) -> Result<QuickJsValueAdapter, JsError>
+where
+    T: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    let names: Vec<String> = {
+        let guard = value
+            .read()
+            .map_err(|_| JsError::new_str("bound object lock was poisoned"))?;
+        to_object_value(&*guard)?.keys().cloned().collect()
+    };
+    let obj = realm.create_object()?;
+    for name in names {
+        let get_lock = value.clone();
+        let get_name = name.clone();
+        let getter = functions::new_function_q(
+            realm,
+            "get",
+            move |realm, _this, _args| {
+                realm.serde_value_to_value_adapter(read_property(&get_lock, get_name.as_str())?)
+            },
+            0,
+        )?;
+        let set_lock = value.clone();
+        let set_name = name.clone();
+        let setter = functions::new_function_q(
+            realm,
+            "set",
+            move |realm, _this, args| {
+                let new_value = match args.first() {
+                    Some(arg) => realm.value_adapter_to_serde_value(arg)?,
+                    None => Value::Null,
+                };
+                write_property(&set_lock, set_name.as_str(), new_value)?;
+                realm.create_undefined()
+            },
+            1,
+        )?;
+        objects::define_getter_setter_q(realm, &obj, name.as_str(), &getter, &setter)?;
+    }
+    let global = get_global_q(realm);
+    let object = objects::get_property_q(realm, &global, "Object")?;
+    functions::invoke_member_function_q(realm, &object, "seal", &[obj.dup()])?;
+    Ok(obj)
+}
+
+/// the handle of a bound object, see [binding](crate::jsutils::binding)
+///
+/// dropping the handle does not remove the binding
+pub struct BoundObjectHandle {
+    rti: Weak<QuickjsRuntimeFacadeInner>,
+    realm_id: String,
+    name: String,
+    installer: Arc<Installer>,
+}
+
+impl BoundObjectHandle {
+    /// replace the global with a new bound object which has the current top-level properties of the struct
+    pub fn refresh(&self) -> Result<(), JsError> {
+        let rti = self
+            .rti
+            .upgrade()
+            .ok_or_else(|| JsError::new_str("runtime was dropped"))?;
+        let realm_id = self.realm_id.clone();
+        let installer = self.installer.clone();
+        rti.exe_task_in_event_loop(move || {
+            QuickJsRuntimeAdapter::do_with(|q_js_rt| match q_js_rt.get_realm(realm_id.as_str()) {
+                Some(realm) => installer(realm),
+                None => Err(JsError::new_string(format!("no such realm: {realm_id}"))),
+            })
+        })
+    }
+    pub fn get_name(&self) -> &str {
+        self.name.as_str()
+    }
+    pub fn get_realm_id(&self) -> &str {
+        self.realm_id.as_str()
+    }
+}
+
+pub(crate) fn bind_object<T>(
+    rti: &Arc<QuickjsRuntimeFacadeInner>,
+    realm_id: &str,
+    name: &str,
+    value: Arc<RwLock<T>>,
+) -> Result<BoundObjectHandle, JsError>
+where
+    T: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    let global_name = name.to_string();
+    let installer: Arc<Installer> = Arc::new(move |realm| {
+        let obj = create_bound_object_q(realm, &value)?;
+        let global = get_global_q(realm);
+        objects::set_property_q(realm, &global, global_name.as_str(), &obj)
+    });
+    let handle = BoundObjectHandle {
+        rti: Arc::downgrade(rti),
+        realm_id: realm_id.to_string(),
+        name: name.to_string(),
+        installer,
+    };
+    handle.refresh()?;
+    Ok(handle)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::Script;
+    use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+    use std::sync::{Arc, RwLock};
+
+    #[derive(Serialize, Deserialize, Clone)]
+    struct Settings {
+        name: String,
+        retries: i32,
+        limits: HashMap<String, i32>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        proxy: Option<String>,
+    }
+
+    #[test]
+    fn test_bind_object() {
+        let rt = QuickJsRuntimeBuilder::new().build();
+        let eval = |code: &str| {
+            rt.eval_sync(None, Script::new("test_bind_object.js", code))
+                .expect("script failed")
+        };
+        let settings = Arc::new(RwLock::new(Settings {
+            name: "svc".to_string(),
+            retries: 3,
+            limits: HashMap::from([("cpu".to_string(), 2)]),
+            proxy: None,
+        }));
+        let handle = rt
+            .bind_object(None, "settings", settings.clone())
+            .expect("bind failed");
+
+        // rust changes are visible to scripts
+        settings.write().unwrap().retries = 4;
+        assert_eq!(eval("settings.retries").get_i32(), 4);
+
+        // script changes are visible to rust
+        eval("settings.retries = 7; settings.name = 'renamed';");
+        assert_eq!(settings.read().unwrap().retries, 7);
+        assert_eq!(settings.read().unwrap().name, "renamed");
+
+        // nested values are copied on read
+        eval("settings.limits.cpu = 8;");
+        assert_eq!(settings.read().unwrap().limits["cpu"], 2);
+        eval("settings.limits = {...settings.limits, cpu: 8};");
+        assert_eq!(settings.read().unwrap().limits["cpu"], 8);
+
+        // invalid values throw and leave the struct unchanged
+        assert_eq!(
+            eval("try { settings.retries = 'many'; 'set' } catch (e) { e.name }").get_str(),
+            "TypeError"
+        );
+        assert_eq!(settings.read().unwrap().retries, 7);
+
+        // properties can not be added or deleted
+        assert_eq!(
+            eval("'use strict'; try { settings.extra = 1; 'added' } catch (e) { e.name }")
+                .get_str(),
+            "TypeError"
+        );
+        assert!(!eval("delete settings.name").get_bool());
+        assert!(!eval("'proxy' in settings").get_bool());
+
+        // refresh picks up properties which appeared on the rust side
+        settings.write().unwrap().proxy = Some("localhost:3128".to_string());
+        handle.refresh().expect("refresh failed");
+        assert_eq!(eval("settings.proxy").get_str(), "localhost:3128");
+
+        // writers on other threads
+        let writers: Vec<_> = (0..4)
+            .map(|_| {
+                let settings = settings.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..100 {
+                        settings.write().unwrap().retries += 1;
+                    }
+                })
+            })
+            .collect();
+        // reads are consistent while rust writes, a script which does a read and a write (++) may overwrite a concurrent rust write
+        assert!(eval(
+            "let last = 0; let ok = true; for (let i = 0; i < 1000; i++) { const v = settings.retries; ok = ok && v >= last; last = v; } ok"
+        )
+        .get_bool());
+        for writer in writers {
+            writer.join().expect("writer failed");
+        }
+        assert_eq!(settings.read().unwrap().retries, 7 + 400);
+        assert_eq!(eval("settings.retries").get_i32(), 7 + 400);
+    }
+}
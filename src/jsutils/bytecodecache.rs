@@ -0,0 +1,602 @@
+//! a persistent cache of compiled script modules and startup scripts, see [bytecode_cache_dir](crate::builder::QuickJsRuntimeBuilder::bytecode_cache_dir)
+//!
+//! * entries are keyed by the path and the code of the script (after pre-processing) and by the version of this crate and the engine,
+//!   an entry of another version is never used
+//! * the files in the directory are read into memory by the executor of the runtime when the runtime is built, the worker thread
+//!   never waits for the disk, a module which is loaded before its entry was read is compiled as if it was not cached,
+//!   building a runtime with startup scripts waits until the entries were read
+//! * new entries are written by the executor, when the directory grows beyond [max_bytes](CachePolicy::max_bytes) the least recently
+//!   used entries are removed, since all entries are kept in memory this also bounds the memory which is used by the cache
+//! * an entry which is corrupt, was written by another version or can not be read by the engine is removed and the script is
+//!   compiled and cached again
+//! * the entries of modules which were pinned with [pin_module](crate::facades::QuickJsRuntimeFacade::pin_module) are never evicted
+//! * the hits, misses and evictions are counted in the [BytecodeCacheStats], see
+//!   [bytecode_cache_stats](crate::facades::QuickJsRuntimeFacade::bytecode_cache_stats)
+//!
+//! script modules which are loaded by a [ScriptModuleLoader](crate::jsutils::modules::ScriptModuleLoader) and
+//! [startup scripts](crate::builder::QuickJsRuntimeBuilder::startup_script) are cached, other evals are not
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::bytecodecache::CachePolicy;
+//! let dir = std::env::temp_dir().join("quickjs_runtime_doc_cache");
+//! let rt = QuickJsRuntimeBuilder::new()
+//!     .bytecode_cache_dir(&dir, CachePolicy::default())
+//!     .build();
+//! let stats = rt.bytecode_cache_stats().expect("no cache");
+//! assert_eq!(stats.hits, 0);
+//! ```
+
+use crate::jsutils::executor::JsExecutor;
+use crate::jsutils::{JsError, Script};
+use crate::quickjs_utils::compile;
+use crate::quickjsvalueadapter::QuickJsValueAdapter;
+use libquickjs_sys as q;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, SystemTime};
+
+const MAGIC: &[u8; 8] = b"QJSRTBC1";
+const EXTENSION: &str = "qjsbc";
+
+#[cfg(feature = "quickjs-ng")]
+const ENGINE: &str = "quickjs-ng";
+#[cfg(not(feature = "quickjs-ng"))]
+const ENGINE: &str = "bellard";
+
+/// the limits of a [bytecode cache](crate::jsutils::bytecodecache)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CachePolicy {
+    /// the max size of all entries together, the least recently used entries are removed when the cache grows beyond this
+    pub max_bytes: u64,
+    /// verify a checksum of every entry before it is used, an entry which does not match is removed
+    pub verify_hash: bool,
+}
+
+impl Default for CachePolicy {
+    fn default() -> Self {
+        Self {
+            max_bytes: 64 * 1024 * 1024,
+            verify_hash: true,
+        }
+    }
+}
+
+/// the use of a bytecode cache since the runtime was built
+#[derive(Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct BytecodeCacheStats {
+    /// scripts which were read from the cache
+    pub hits: u64,
+    /// scripts which were compiled because they were not cached (yet)
+    pub misses: u64,
+    /// entries which were removed because the cache grew beyond its max_bytes
+    pub evictions: u64,
+    /// entries which were removed because they were corrupt or written by another version
+    pub invalidated: u64,
+    /// the number of entries in the cache
+    pub entries: usize,
+    /// the size of all entries
+    pub bytes: u64,
+}
+
+/// what kind of code an entry holds, a module and a script with the same path and code have different bytecode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum CachedKind {
+    Script,
+    Module,
+}
+
+struct Entry {
+    bytecode: Arc<Vec<u8>>,
+    last_used: u64,
+    // the entry of a pinned module, see pin
+    pinned: bool,
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<u64, Entry>,
+    // incremented for every use, orders the entries from least to most recently used
+    clock: u64,
+    stats: BytecodeCacheStats,
+    // the paths of the modules whose entries are not evicted
+    pinned_paths: HashSet<String>,
+}
+
+impl CacheState {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+    fn update_size(&mut self) {
+        self.stats.entries = self.entries.len();
+        self.stats.bytes = self.entries.values().map(|e| e.bytecode.len() as u64).sum();
+    }
+}
+
+pub(crate) struct BytecodeCache {
+    dir: PathBuf,
+    policy: CachePolicy,
+    executor: Arc<dyn JsExecutor>,
+    state: Mutex<CacheState>,
+    // the entries in the directory were read
+    loaded: (Mutex<bool>, Condvar),
+}
+
+// FNV-1a, stable between builds unlike the hasher of std
+pub(crate) fn fnv1a(parts: &[&[u8]]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for part in parts {
+        for byte in part.iter().chain((part.len() as u64).to_le_bytes().iter()) {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
+pub(crate) fn cache_version() -> String {
+    format!("{}/{}/{}", env!("CARGO_PKG_VERSION"), ENGINE, usize::BITS)
+}
+
+fn encode(key: u64, bytecode: &[u8]) -> Vec<u8> {
+    let version = cache_version();
+    let mut file = Vec::with_capacity(bytecode.len() + 64);
+    file.extend_from_slice(MAGIC);
+    file.extend_from_slice(&(version.len() as u32).to_le_bytes());
+    file.extend_from_slice(version.as_bytes());
+    file.extend_from_slice(&key.to_le_bytes());
+    file.extend_from_slice(&fnv1a(&[bytecode]).to_le_bytes());
+    file.extend_from_slice(bytecode);
+    file
+}
+
+// the key and bytecode of a file, None when it is corrupt or of another version
+fn decode(file: &[u8], verify_hash: bool) -> Option<(u64, Vec<u8>)> {
+    fn take<'a>(file: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+        if file.len() < len {
+            return None;
+        }
+        let (head, tail) = file.split_at(len);
+        *file = tail;
+        Some(head)
+    }
+    fn take_u64(file: &mut &[u8]) -> Option<u64> {
+        take(file, 8).map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+    }
+    let mut rest = file;
+    if take(&mut rest, 8)? != MAGIC {
+        return None;
+    }
+    let version_len = u32::from_le_bytes(take(&mut rest, 4)?.try_into().unwrap()) as usize;
+    if take(&mut rest, version_len)? != cache_version().as_bytes() {
+        return None;
+    }
+    let key = take_u64(&mut rest)?;
+    let checksum = take_u64(&mut rest)?;
+    if rest.is_empty() || (verify_hash && fnv1a(&[rest]) != checksum) {
+        return None;
+    }
+    Some((key, rest.to_vec()))
+}
+
+impl BytecodeCache {
+    /// create the cache and read the entries in the directory with the executor
+    pub(crate) fn open(
+        dir: PathBuf,
+        policy: CachePolicy,
+        executor: Arc<dyn JsExecutor>,
+    ) -> Arc<Self> {
+        let cache = Arc::new(Self {
+            dir,
+            policy,
+            executor: executor.clone(),
+            state: Mutex::new(CacheState::default()),
+            loaded: (Mutex::new(false), Condvar::new()),
+        });
+        let loading = cache.clone();
+        executor.spawn_blocking(Box::new(move || {
+            loading.load_entries();
+            let (lock, condvar) = &loading.loaded;
+            *lock.lock().unwrap() = true;
+            condvar.notify_all();
+        }));
+        cache
+    }
+
+    /// wait until the entries in the directory were read, this should never be called from the worker thread
+    pub(crate) fn wait_until_loaded(&self, timeout: Duration) {
+        let (lock, condvar) = &self.loaded;
+        let guard = lock.lock().unwrap();
+        let _ = condvar.wait_timeout_while(guard, timeout, |loaded| !*loaded);
+    }
+
+    pub(crate) fn get_stats(&self) -> BytecodeCacheStats {
+        self.state.lock().unwrap().stats.clone()
+    }
+
+    fn file_of(&self, key: u64) -> PathBuf {
+        self.dir.join(format!("{key:016x}.{EXTENSION}"))
+    }
+
+    fn load_entries(&self) {
+        if let Err(e) = std::fs::create_dir_all(&self.dir) {
+            log::error!(
+                "could not create bytecode cache dir {}: {}",
+                self.dir.display(),
+                e
+            );
+            return;
+        }
+        let read_dir = match std::fs::read_dir(&self.dir) {
+            Ok(read_dir) => read_dir,
+            Err(e) => {
+                log::error!(
+                    "could not read bytecode cache dir {}: {}",
+                    self.dir.display(),
+                    e
+                );
+                return;
+            }
+        };
+        let mut files: Vec<(SystemTime, PathBuf)> = read_dir
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().map(|e| e == EXTENSION).unwrap_or(false))
+            .map(|path| {
+                let modified = std::fs::metadata(&path)
+                    .and_then(|m| m.modified())
+                    .unwrap_or(SystemTime::UNIX_EPOCH);
+                (modified, path)
+            })
+            .collect();
+        // the least recently used files are inserted first so they get the lowest clock
+        files.sort();
+
+        for (_modified, path) in files {
+            let decoded = std::fs::read(&path)
+                .ok()
+                .and_then(|file| decode(&file, self.policy.verify_hash))
+                .filter(|(key, _)| self.file_of(*key) == path);
+            let mut state = self.state.lock().unwrap();
+            match decoded {
+                Some((key, bytecode)) => {
+                    if !state.entries.contains_key(&key) {
+                        let last_used = state.tick();
+                        state.entries.insert(
+                            key,
+                            Entry {
+                                bytecode: Arc::new(bytecode),
+                                last_used,
+                                pinned: false,
+                            },
+                        );
+                    }
+                }
+                None => {
+                    log::debug!("removing invalid bytecode cache entry {}", path.display());
+                    state.stats.invalidated += 1;
+                    let _ = std::fs::remove_file(&path);
+                }
+            }
+        }
+        let evicted = {
+            let mut state = self.state.lock().unwrap();
+            let evicted = self.evict(&mut state);
+            state.update_size();
+            evicted
+        };
+        for key in evicted {
+            let _ = std::fs::remove_file(self.file_of(key));
+        }
+    }
+
+    // remove the least recently used entries until the cache fits in max_bytes, returns the keys of the removed entries
+    fn evict(&self, state: &mut CacheState) -> Vec<u64> {
+        let mut total: u64 = state
+            .entries
+            .values()
+            .map(|e| e.bytecode.len() as u64)
+            .sum();
+        let mut evicted = vec![];
+        while total > self.policy.max_bytes {
+            let oldest = state
+                .entries
+                .iter()
+                .filter(|(_, e)| !e.pinned)
+                .min_by_key(|(_, e)| e.last_used)
+                .map(|(key, _)| *key);
+            match oldest {
+                Some(key) => {
+                    if let Some(entry) = state.entries.remove(&key) {
+                        total -= entry.bytecode.len() as u64;
+                    }
+                    state.stats.evictions += 1;
+                    evicted.push(key);
+                }
+                None => break,
+            }
+        }
+        evicted
+    }
+
+    fn key_of(kind: CachedKind, script: &Script) -> u64 {
+        let kind = match kind {
+            CachedKind::Script => "script",
+            CachedKind::Module => "module",
+        };
+        fnv1a(&[
+            cache_version().as_bytes(),
+            kind.as_bytes(),
+            script.get_path().as_bytes(),
+            script.get_runnable_code().as_bytes(),
+        ])
+    }
+
+    fn lookup(&self, key: u64, pinned: bool) -> Option<Arc<Vec<u8>>> {
+        let mut state = self.state.lock().unwrap();
+        let last_used = state.tick();
+        match state.entries.get_mut(&key) {
+            Some(entry) => {
+                entry.last_used = last_used;
+                entry.pinned |= pinned;
+                Some(entry.bytecode.clone())
+            }
+            None => None,
+        }
+    }
+
+    fn invalidate(&self, key: u64) {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.entries.remove(&key);
+            state.stats.invalidated += 1;
+            state.update_size();
+        }
+        let file = self.file_of(key);
+        self.executor.spawn_blocking(Box::new(move || {
+            let _ = std::fs::remove_file(file);
+        }));
+    }
+
+    fn store(&self, key: u64, bytecode: Vec<u8>, pinned: bool) {
+        let file_content = encode(key, &bytecode);
+        let evicted = {
+            let mut state = self.state.lock().unwrap();
+            let last_used = state.tick();
+            state.entries.insert(
+                key,
+                Entry {
+                    bytecode: Arc::new(bytecode),
+                    last_used,
+                    pinned,
+                },
+            );
+            let evicted = self.evict(&mut state);
+            state.update_size();
+            evicted
+        };
+        let written = if evicted.contains(&key) {
+            None
+        } else {
+            Some(self.file_of(key))
+        };
+        let evicted: Vec<PathBuf> = evicted.into_iter().map(|k| self.file_of(k)).collect();
+        let dir = self.dir.clone();
+        self.executor.spawn_blocking(Box::new(move || {
+            if let Some(file) = written {
+                if let Err(e) = write_file(&dir, &file, &file_content) {
+                    log::error!(
+                        "could not write bytecode cache entry {}: {}",
+                        file.display(),
+                        e
+                    );
+                }
+            }
+            for file in evicted {
+                let _ = std::fs::remove_file(file);
+            }
+        }));
+    }
+
+    /// keep the entries of a module, the path is the normalized path of the module
+    pub(crate) fn pin(&self, path: &str) {
+        self.state
+            .lock()
+            .unwrap()
+            .pinned_paths
+            .insert(path.to_string());
+    }
+
+    fn touch(&self, key: u64) {
+        let file = self.file_of(key);
+        self.executor.spawn_blocking(Box::new(move || {
+            if let Ok(f) = std::fs::File::options().write(true).open(file) {
+                let _ = f.set_modified(SystemTime::now());
+            }
+        }));
+    }
+
+    /// get the compiled script from the cache or compile it with the compiler and cache the result
+    /// # Safety
+    /// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
+    pub(crate) unsafe fn compile_cached<C>(
+        &self,
+        context: *mut q::JSContext,
+        kind: CachedKind,
+        script: &Script,
+        compiler: C,
+    ) -> Result<QuickJsValueAdapter, JsError>
+    where
+        C: FnOnce() -> Result<QuickJsValueAdapter, JsError>,
+    {
+        let key = Self::key_of(kind, script);
+        let pinned = self
+            .state
+            .lock()
+            .unwrap()
+            .pinned_paths
+            .contains(script.get_path());
+        if let Some(bytecode) = self.lookup(key, pinned) {
+            match compile::from_bytecode(context, &bytecode) {
+                Ok(compiled) => {
+                    self.state.lock().unwrap().stats.hits += 1;
+                    self.touch(key);
+                    return Ok(compiled);
+                }
+                Err(e) => {
+                    log::debug!(
+                        "could not read cached bytecode of {}, compiling it again: {}",
+                        script.get_path(),
+                        e
+                    );
+                    self.invalidate(key);
+                }
+            }
+        }
+        self.state.lock().unwrap().stats.misses += 1;
+        let compiled = compiler()?;
+        self.store(key, compile::to_bytecode(context, &compiled), pinned);
+        Ok(compiled)
+    }
+}
+
+// write to a temporary file first so a crash never leaves a partial entry
+fn write_file(dir: &Path, file: &Path, content: &[u8]) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let tmp = file.with_extension("tmp");
+    std::fs::write(&tmp, content)?;
+    std::fs::rename(tmp, file)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::bytecodecache::{decode, encode, BytecodeCacheStats, CachePolicy};
+    use crate::jsutils::modules::ScriptModuleLoader;
+    use crate::jsutils::Script;
+    use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    struct CacheTestLoader {}
+
+    impl ScriptModuleLoader for CacheTestLoader {
+        fn normalize_path(
+            &self,
+            _realm: &QuickJsRealmAdapter,
+            _ref_path: &str,
+            path: &str,
+        ) -> Option<String> {
+            path.starts_with("cached_").then(|| path.to_string())
+        }
+        fn load_module(&self, _realm: &QuickJsRealmAdapter, absolute_path: &str) -> String {
+            format!(
+                "export const name = '{absolute_path}'; export const big = '{}';",
+                "x".repeat(1000)
+            )
+        }
+    }
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("quickjs_runtime_{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn run(dir: &PathBuf, policy: CachePolicy, modules: &[&str]) -> BytecodeCacheStats {
+        let rt = QuickJsRuntimeBuilder::new()
+            .script_module_loader(CacheTestLoader {})
+            .bytecode_cache_dir(dir, policy)
+            .build();
+        // the entries are read by the executor
+        std::thread::sleep(Duration::from_millis(200));
+        for module in modules {
+            rt.eval_module_sync(
+                None,
+                Script::new(
+                    "cache_test.mes",
+                    format!("import {{name}} from '{module}'; if (name !== '{module}') {{throw Error('wrong module');}}").as_str(),
+                ),
+            )
+            .expect("module failed");
+        }
+        let stats = rt.bytecode_cache_stats().expect("no cache");
+        drop(rt);
+        // the entries are written by the executor
+        std::thread::sleep(Duration::from_millis(200));
+        stats
+    }
+
+    #[test]
+    fn test_bytecode_cache() {
+        let dir = test_dir("bytecode_cache");
+        let policy = CachePolicy::default();
+
+        let stats = run(&dir, policy, &["cached_a.mes", "cached_b.mes"]);
+        assert_eq!((stats.hits, stats.misses), (0, 2));
+
+        let stats = run(&dir, policy, &["cached_a.mes", "cached_b.mes"]);
+        assert_eq!((stats.hits, stats.misses), (2, 0));
+
+        // corrupt an entry, it is removed and compiled again
+        let file = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .next()
+            .unwrap();
+        let mut content = std::fs::read(&file).unwrap();
+        let last = content.len() - 1;
+        content[last] ^= 0xff;
+        std::fs::write(&file, content).unwrap();
+
+        let stats = run(&dir, policy, &["cached_a.mes", "cached_b.mes"]);
+        assert_eq!(stats.invalidated, 1);
+        assert_eq!((stats.hits, stats.misses), (1, 1));
+        assert_eq!(stats.entries, 2);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_bytecode_cache_eviction() {
+        let dir = test_dir("bytecode_cache_eviction");
+        let first = run(&dir, CachePolicy::default(), &["cached_a.mes"]);
+        let policy = CachePolicy {
+            max_bytes: first.bytes * 2 + first.bytes / 2,
+            verify_hash: true,
+        };
+
+        let stats = run(
+            &dir,
+            policy,
+            &[
+                "cached_b.mes",
+                "cached_c.mes",
+                "cached_a.mes",
+                "cached_d.mes",
+            ],
+        );
+        assert!(stats.evictions >= 2);
+        assert!(stats.bytes <= policy.max_bytes);
+
+        // the most recently used entries were kept
+        let stats = run(&dir, policy, &["cached_a.mes", "cached_d.mes"]);
+        assert_eq!((stats.hits, stats.misses), (2, 0));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let file = encode(42, &[1, 2, 3]);
+        assert_eq!(decode(&file, true), Some((42, vec![1, 2, 3])));
+        assert_eq!(decode(&file[..file.len() - 1], true), None);
+        let mut other_version = file.clone();
+        other_version[12] ^= 0xff;
+        assert_eq!(decode(&other_version, false), None);
+    }
+}
@@ -0,0 +1,223 @@
+//! revocable native functions
+//!
+//! a capability is a JS function backed by a rust handler which works until its [CapabilityHandle] is revoked,
+//! after that every call throws a RevokedError, no matter where the script stored the function
+//!
+//! the handle can be revoked from any thread, calls which started before the revoke complete normally when the handle is revoked with
+//! [revoke](CapabilityHandle::revoke) and throw a RevokedError instead of returning their result when it is revoked with
+//! [revoke_and_abort](CapabilityHandle::revoke_and_abort), dropping the handle revokes the capability
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::Script;
+//! use quickjs_runtime::values::JsValueFacade;
+//! let rt = QuickJsRuntimeBuilder::new().build();
+//! let handle = rt.create_capability(None, "approvePayment", |_realm, _args| {
+//!     Ok(JsValueFacade::new_bool(true))
+//! }).expect("could not create capability");
+//! let res = rt.eval_sync(None, Script::new("pay.js", "approvePayment()")).expect("script failed");
+//! assert!(res.get_bool());
+//! handle.revoke();
+//! let res = rt.eval_sync(None, Script::new("pay.js", "try { approvePayment(); } catch (e) { e.name }")).expect("script failed");
+//! assert_eq!(res.get_str(), "RevokedError");
+//! ```
+
+use crate::jsutils::JsError;
+use crate::quickjs_utils::functions;
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsvalueadapter::QuickJsValueAdapter;
+use crate::values::JsValueFacade;
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+const ACTIVE: u8 = 0;
+const REVOKED: u8 = 1;
+const ABORTED: u8 = 2;
+
+struct CapabilityState {
+    name: String,
+    state: AtomicU8,
+    in_flight: AtomicUsize,
+}
+
+impl CapabilityState {
+    fn revoked_error(&self) -> JsError {
+        JsError::new(
+            "RevokedError".to_string(),
+            format!("capability {} was revoked", self.name),
+            "".to_string(),
+        )
+    }
+}
+
+// decrements the number of calls in flight when a call returns or fails
+struct InFlightGuard<'a> {
+    state: &'a CapabilityState,
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.state.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// the handle of a capability, the capability is revoked when the handle is dropped
+pub struct CapabilityHandle {
+    state: Arc<CapabilityState>,
+}
+
+impl CapabilityHandle {
+    /// revoke the capability, calls which are in flight complete normally
+    pub fn revoke(&self) {
+        let _ =
+            self.state
+                .state
+                .compare_exchange(ACTIVE, REVOKED, Ordering::SeqCst, Ordering::SeqCst);
+    }
+    /// revoke the capability, calls which are in flight throw a RevokedError when they return
+    pub fn revoke_and_abort(&self) {
+        self.state.state.store(ABORTED, Ordering::SeqCst);
+    }
+    pub fn is_revoked(&self) -> bool {
+        self.state.state.load(Ordering::SeqCst) != ACTIVE
+    }
+    /// the number of calls which are running
+    pub fn in_flight(&self) -> usize {
+        self.state.in_flight.load(Ordering::SeqCst)
+    }
+    pub fn get_name(&self) -> &str {
+        self.state.name.as_str()
+    }
+}
+
+impl Drop for CapabilityHandle {
+    fn drop(&mut self) {
+        self.revoke();
+    }
+}
+
+/// create a capability function in a realm, the function is not added to the global scope
+pub fn create_capability_q<F>(
+    realm: &QuickJsRealmAdapter,
+    name: &str,
+    handler: F,
+) -> Result<(QuickJsValueAdapter, CapabilityHandle), JsError>
+where
+    F: Fn(&QuickJsRealmAdapter, Vec<JsValueFacade>) -> Result<JsValueFacade, JsError> + 'static,
+{
+    let state = Arc::new(CapabilityState {
+        name: name.to_string(),
+        state: AtomicU8::new(ACTIVE),
+        in_flight: AtomicUsize::new(0),
+    });
+    let call_state = state.clone();
+    let func = functions::new_function_q(
+        realm,
+        name,
+        move |realm, _this, args| {
+            // count the call before checking the state so a revoke which sees no calls in flight also stops this call
+            call_state.in_flight.fetch_add(1, Ordering::SeqCst);
+            let _guard = InFlightGuard { state: &call_state };
+            if call_state.state.load(Ordering::SeqCst) != ACTIVE {
+                return Err(call_state.revoked_error());
+            }
+            let mut args_facades = vec![];
+            for arg in args {
+                args_facades.push(realm.to_js_value_facade(arg)?);
+            }
+            let res = handler(realm, args_facades);
+            if call_state.state.load(Ordering::SeqCst) == ABORTED {
+                return Err(call_state.revoked_error());
+            }
+            realm.from_js_value_facade(res?)
+        },
+        1,
+    )?;
+    Ok((func, CapabilityHandle { state }))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::Script;
+    use crate::values::JsValueFacade;
+    use std::sync::mpsc::channel;
+    use std::time::Duration;
+
+    #[test]
+    fn test_capability() {
+        let rt = QuickJsRuntimeBuilder::new().build();
+        let eval = |code: &str| {
+            rt.eval_sync(None, Script::new("test_capability.js", code))
+                .expect("script failed")
+        };
+
+        let handle = rt
+            .create_capability(None, "approve", |_realm, args| {
+                Ok(JsValueFacade::new_i32(args[0].get_i32() * 2))
+            })
+            .expect("could not create capability");
+        assert_eq!(
+            eval("globalThis.stored = {fn: approve}; approve(21)").get_i32(),
+            42
+        );
+        handle.revoke();
+        assert!(handle.is_revoked());
+        let res = eval(
+            "[approve, stored.fn].map(f => { try { f(1); return 'called'; } catch (e) { return e.name + ': ' + e.message; } }).join(',')",
+        );
+        assert_eq!(
+            res.get_str(),
+            "RevokedError: capability approve was revoked,RevokedError: capability approve was revoked"
+        );
+
+        // dropping the handle revokes
+        let handle = rt
+            .create_capability(None, "once", |_realm, _args| {
+                Ok(JsValueFacade::new_bool(true))
+            })
+            .expect("could not create capability");
+        assert!(eval("once()").get_bool());
+        drop(handle);
+        assert_eq!(
+            eval("try { once(); 'called' } catch (e) { e.name }").get_str(),
+            "RevokedError"
+        );
+
+        // calls in flight complete after revoke and throw after revoke_and_abort
+        for abort in [false, true] {
+            let (started_tx, started_rx) = channel::<()>();
+            let (revoked_tx, revoked_rx) = channel::<()>();
+            let started_tx = std::sync::Mutex::new(started_tx);
+            let revoked_rx = std::sync::Mutex::new(revoked_rx);
+            let handle = rt
+                .create_capability(None, "slow", move |_realm, _args| {
+                    started_tx.lock().unwrap().send(()).expect("send failed");
+                    revoked_rx
+                        .lock()
+                        .unwrap()
+                        .recv_timeout(Duration::from_secs(5))
+                        .expect("not revoked");
+                    Ok(JsValueFacade::new_str("done"))
+                })
+                .expect("could not create capability");
+            let res = rt.eval(
+                None,
+                Script::new("test_capability.js", "try { slow() } catch (e) { e.name }"),
+            );
+            started_rx
+                .recv_timeout(Duration::from_secs(5))
+                .expect("not started");
+            assert_eq!(handle.in_flight(), 1);
+            if abort {
+                handle.revoke_and_abort();
+            } else {
+                handle.revoke();
+            }
+            revoked_tx.send(()).expect("send failed");
+            let res = futures::executor::block_on(res).expect("script failed");
+            assert_eq!(res.get_str(), if abort { "RevokedError" } else { "done" });
+            assert_eq!(handle.in_flight(), 0);
+        }
+    }
+}
@@ -0,0 +1,367 @@
+//! an audit trail of the code which a runtime compiles
+//!
+//! when enabled with [compile_audit](crate::builder::QuickJsRuntimeBuilder::compile_audit) or
+//! [compile_audit_hook](crate::builder::QuickJsRuntimeBuilder::compile_audit_hook) a [CompileEvent] is recorded for:
+//!
+//! * scripts and modules which are evaluated or compiled by the embedder
+//! * modules which are loaded by a [ScriptModuleLoader](crate::jsutils::modules::ScriptModuleLoader) for a static or dynamic import
+//! * code which scripts compile with `eval()` or the `Function` constructors, when [script_eval](CompileAuditOptions::script_eval) is set
+//!
+//! the events are passed to the hook or, when no hook was set, kept in a ring buffer which can be read with
+//! [compile_audit_log](crate::facades::QuickJsRuntimeFacade::compile_audit_log)
+//!
+//! the hash and the byte length are those of the code after the [ScriptPreProcessor](crate::jsutils::ScriptPreProcessor)s ran,
+//! the snippet is redacted by the [redaction_hook](crate::builder::QuickJsRuntimeBuilder::redaction_hook)
+//!
+//! quickjs has no hook for the code which scripts compile so `eval` and the `Function`, `AsyncFunction`, `GeneratorFunction`
+//! and `AsyncGeneratorFunction` constructors are replaced in every realm before any script runs, as a consequence a direct eval
+//! is evaluated as an indirect eval in the global scope
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::compileaudit::{CompileAuditOptions, CompileOrigin};
+//! use quickjs_runtime::jsutils::Script;
+//! let rt = QuickJsRuntimeBuilder::new()
+//!     .compile_audit(CompileAuditOptions {
+//!         script_eval: true,
+//!         ..Default::default()
+//!     })
+//!     .build();
+//! rt.eval_sync(None, Script::new("audit.js", "new Function('a', 'return a * 2;')(21);")).expect("script failed");
+//! let log = rt.compile_audit_log();
+//! assert!(log.iter().any(|e| e.origin == CompileOrigin::FunctionConstructor && e.path == "audit.js"));
+//! ```
+
+use crate::jsutils::{redaction, JsError, Script};
+use crate::quickjs_utils::atoms::JSAtomRef;
+use crate::quickjs_utils::objects::StableHasher;
+use crate::quickjs_utils::{atoms, functions, new_undefined_ref, primitives};
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use libquickjs_sys as q;
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// a hook which is called with every [CompileEvent], it runs in the worker thread of the runtime and should not block
+pub type CompileAuditHook = Arc<dyn Fn(&CompileEvent) + Send + Sync>;
+
+const INSTRUMENT_PATH: &str = "compile_audit.js";
+
+const INSTRUMENT_SCRIPT: &str = r#"
+(function (record) {
+    'use strict';
+    const originalEval = globalThis.eval;
+    // a method is not a constructor, like the original eval
+    const evalWrapper = {
+        eval(code) {
+            if (typeof code === 'string') {
+                record('script_eval', code);
+            }
+            return originalEval(code);
+        }
+    }.eval;
+    const wrapConstructor = (original, prefix) => {
+        const wrapper = function (...args) {
+            // the args are converted once so a toString with side effects can not change the code which was recorded
+            const strings = args.map(String);
+            const params = strings.slice(0, -1).join(',');
+            const body = strings.length ? strings[strings.length - 1] : '';
+            record('function_constructor', `${prefix} anonymous(${params}\n) {\n${body}\n}`);
+            return Reflect.construct(original, strings, new.target || original);
+        };
+        Object.defineProperty(wrapper, 'prototype', {value: original.prototype});
+        Object.defineProperty(wrapper, 'name', {value: original.name});
+        Object.defineProperty(wrapper, 'length', {value: original.length});
+        Object.setPrototypeOf(wrapper, Object.getPrototypeOf(original));
+        Object.defineProperty(original.prototype, 'constructor', {value: wrapper});
+        return wrapper;
+    };
+    const AsyncFunction = Object.getPrototypeOf(async function () {}).constructor;
+    const GeneratorFunction = Object.getPrototypeOf(function* () {}).constructor;
+    const AsyncGeneratorFunction = Object.getPrototypeOf(async function* () {}).constructor;
+    Object.defineProperty(globalThis, 'eval', {value: evalWrapper, writable: true, configurable: true});
+    Object.defineProperty(globalThis, 'Function', {value: wrapConstructor(Function, 'function'), writable: true, configurable: true});
+    wrapConstructor(AsyncFunction, 'async function');
+    wrapConstructor(GeneratorFunction, 'function*');
+    wrapConstructor(AsyncGeneratorFunction, 'async function*');
+})
+"#;
+
+/// where the compiled code came from
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CompileOrigin {
+    /// a script which was evaluated or compiled by the embedder
+    Eval,
+    /// a module which was evaluated or compiled by the embedder
+    Module,
+    /// a module which was loaded for a static import
+    StaticImport,
+    /// a module which was loaded for a dynamic import()
+    DynamicImport,
+    /// the body of a function created with one of the Function constructors
+    FunctionConstructor,
+    /// code which a script passed to eval()
+    ScriptEval,
+}
+
+/// the options of a compile audit
+#[derive(Clone, Debug)]
+pub struct CompileAuditOptions {
+    /// the max number of chars of the source which are included as snippet, 0 for no snippet
+    pub snippet_length: usize,
+    /// the max number of events which are kept when no hook was set
+    pub capacity: usize,
+    /// record the code which scripts compile with eval() and the Function constructors
+    pub script_eval: bool,
+}
+
+impl Default for CompileAuditOptions {
+    fn default() -> Self {
+        Self {
+            snippet_length: 64,
+            capacity: 1024,
+            script_eval: false,
+        }
+    }
+}
+
+/// a piece of code which was compiled
+#[derive(Serialize, Clone, Debug)]
+pub struct CompileEvent {
+    pub origin: CompileOrigin,
+    pub realm_id: String,
+    /// the path of the script or module, for code compiled by a script the path of that script
+    pub path: String,
+    /// the stable hash of the source as hex
+    pub hash: String,
+    pub byte_length: usize,
+    /// the redacted start of the source
+    pub snippet: String,
+    /// millis since the epoch
+    pub timestamp: u64,
+}
+
+pub(crate) struct CompileAudit {
+    options: CompileAuditOptions,
+    hook: Option<CompileAuditHook>,
+    log: RefCell<VecDeque<CompileEvent>>,
+}
+
+impl CompileAudit {
+    pub(crate) fn new(options: CompileAuditOptions, hook: Option<CompileAuditHook>) -> Self {
+        Self {
+            options,
+            hook,
+            log: RefCell::new(VecDeque::new()),
+        }
+    }
+
+    pub(crate) fn get_log(&self) -> Vec<CompileEvent> {
+        self.log.borrow().iter().cloned().collect()
+    }
+}
+
+/// replace eval and the Function constructors in every realm, see [script_eval](CompileAuditOptions::script_eval)
+pub(crate) fn init(q_js_rt: &QuickJsRuntimeAdapter) -> Result<(), JsError> {
+    q_js_rt.add_context_init_hook(|_q_js_rt, realm| instrument(realm))
+}
+
+fn instrument(realm: &QuickJsRealmAdapter) -> Result<(), JsError> {
+    let record_func = functions::new_function_q(
+        realm,
+        "record",
+        |realm, _this, args| {
+            let origin = match primitives::to_string_q(realm, &args[0])?.as_str() {
+                "script_eval" => CompileOrigin::ScriptEval,
+                _ => CompileOrigin::FunctionConstructor,
+            };
+            let code = primitives::to_string_q(realm, &args[1])?;
+            let path = caller_path(realm)?;
+            QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+                record(
+                    q_js_rt,
+                    realm.get_realm_id(),
+                    origin,
+                    path.as_str(),
+                    code.as_str(),
+                )
+            });
+            Ok(new_undefined_ref())
+        },
+        2,
+    )?;
+    let install_func = realm.eval(Script::new(INSTRUMENT_PATH, INSTRUMENT_SCRIPT))?;
+    functions::call_function_q(realm, &install_func, &[record_func], None)?;
+    Ok(())
+}
+
+// the path of the script which called eval or a Function constructor, the frames of the wrappers are skipped
+fn caller_path(realm: &QuickJsRealmAdapter) -> Result<String, JsError> {
+    for level in 0..100 {
+        let path = unsafe {
+            let atom = q::JS_GetScriptOrModuleName(realm.context, level);
+            let atom_ref = JSAtomRef::new(realm.context, atom);
+            atoms::to_string(realm.context, &atom_ref)?
+        };
+        if !path.is_empty() && path != INSTRUMENT_PATH {
+            return Ok(path);
+        }
+    }
+    Ok("".to_string())
+}
+
+/// record the compilation of a piece of code when the audit is enabled
+pub(crate) fn record(
+    q_js_rt: &QuickJsRuntimeAdapter,
+    realm_id: &str,
+    origin: CompileOrigin,
+    path: &str,
+    source: &str,
+) {
+    let Some(audit) = q_js_rt.compile_audit.as_ref() else {
+        return;
+    };
+    let mut hasher = StableHasher::new();
+    hasher.write(source.as_bytes());
+    // the whole source is redacted first so a secret which is cut off by the snippet can not leak partially
+    let snippet = if audit.options.snippet_length > 0 {
+        redaction::redact(source)
+            .chars()
+            .take(audit.options.snippet_length)
+            .collect()
+    } else {
+        String::new()
+    };
+    let event = CompileEvent {
+        origin,
+        realm_id: realm_id.to_string(),
+        path: path.to_string(),
+        hash: format!("{:016x}", hasher.finish()),
+        byte_length: source.len(),
+        snippet,
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0),
+    };
+    match &audit.hook {
+        Some(hook) => hook(&event),
+        None => {
+            let log = &mut *audit.log.borrow_mut();
+            while log.len() >= audit.options.capacity.max(1) {
+                log.pop_front();
+            }
+            log.push_back(event);
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::compileaudit::{CompileAuditOptions, CompileOrigin};
+    use crate::jsutils::modules::ScriptModuleLoader;
+    use crate::jsutils::Script;
+    use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+    use std::borrow::Cow;
+    use std::sync::{Arc, Mutex};
+
+    struct TestModuleLoader {}
+
+    impl ScriptModuleLoader for TestModuleLoader {
+        fn normalize_path(
+            &self,
+            _realm: &QuickJsRealmAdapter,
+            _ref_path: &str,
+            path: &str,
+        ) -> Option<String> {
+            Some(path.to_string())
+        }
+
+        fn load_module(&self, _realm: &QuickJsRealmAdapter, _absolute_path: &str) -> String {
+            "export const a = 1;".to_string()
+        }
+    }
+
+    #[test]
+    fn test_compile_audit_log() {
+        let rt = QuickJsRuntimeBuilder::new()
+            .compile_audit(CompileAuditOptions {
+                snippet_length: 24,
+                capacity: 16,
+                script_eval: true,
+            })
+            .redaction_hook(|s| {
+                if s.contains("hunter2") {
+                    Cow::Owned(s.replace("hunter2", "[redacted]"))
+                } else {
+                    Cow::Borrowed(s)
+                }
+            })
+            .script_module_loader(TestModuleLoader {})
+            .build();
+
+        rt.eval_sync(
+            None,
+            Script::new(
+                "audit.js",
+                "const pw = 'hunter2'; eval('1 + 1'); new Function('a', 'return a;');",
+            ),
+        )
+        .expect("script failed");
+        rt.eval_module_sync(None, Script::new("audit.mjs", "import {a} from 'a.mjs';"))
+            .expect("module failed");
+
+        let log = rt.compile_audit_log();
+        let find = |origin: CompileOrigin| {
+            log.iter()
+                .find(|e| e.origin == origin)
+                .expect("no event for origin")
+        };
+        let eval = log
+            .iter()
+            .find(|e| e.origin == CompileOrigin::Eval && e.path == "audit.js")
+            .expect("no eval event");
+        assert_eq!(eval.snippet, "const pw = '[redacted]';");
+        assert_eq!(eval.realm_id, "__main__");
+        assert_eq!(find(CompileOrigin::ScriptEval).snippet, "1 + 1");
+        assert_eq!(find(CompileOrigin::ScriptEval).path, "audit.js");
+        assert_eq!(find(CompileOrigin::ScriptEval).byte_length, 5);
+        assert!(find(CompileOrigin::FunctionConstructor)
+            .snippet
+            .starts_with("function anonymous(a"));
+        assert_eq!(find(CompileOrigin::Module).path, "audit.mjs");
+        assert_eq!(find(CompileOrigin::StaticImport).path, "a.mjs");
+    }
+
+    #[test]
+    fn test_compile_audit_hook() {
+        let events = Arc::new(Mutex::new(vec![]));
+        let events2 = events.clone();
+        let rt = QuickJsRuntimeBuilder::new()
+            .compile_audit_hook(CompileAuditOptions::default(), move |event| {
+                events2.lock().unwrap().push(event.clone());
+            })
+            .build();
+        rt.eval_sync(None, Script::new("hook.js", "1 + 1;"))
+            .expect("script failed");
+        rt.eval_sync(None, Script::new("hook2.js", "1 + 1;"))
+            .expect("script failed");
+
+        // the log is empty when a hook was set
+        assert!(rt.compile_audit_log().is_empty());
+        let events = events.lock().unwrap();
+        let hook_events: Vec<_> = events
+            .iter()
+            .filter(|e| e.path.starts_with("hook"))
+            .collect();
+        assert_eq!(hook_events.len(), 2);
+        // the same source has the same hash
+        assert_eq!(hook_events[0].hash, hook_events[1].hash);
+        assert_eq!(hook_events[0].byte_length, 6);
+    }
+}
@@ -0,0 +1,538 @@
+//! a diagnostic bundle of a realm for support tickets, see [debug_dump](crate::facades::QuickJsRuntimeFacade::debug_dump)
+//!
+//! the bundle contains the enumerable global properties of a realm, the modules which were loaded with a hash of their source,
+//! the installed proxy classes with their instance counts, the pending timers and promises, the memory usage of the runtime and the
+//! last console records and errors of the realm, all of it can be serialized (e.g. to json) and attached to a ticket
+//!
+//! console records and errors are only kept when [debug_records](crate::builder::QuickJsRuntimeBuilder::debug_records) is set on the builder,
+//! every realm then keeps the last `capacity` records in a ring buffer
+//!
+//! collecting the bundle is bounded by [DumpOptions], the global property tree is cut off at a depth, a number of properties per object and
+//! a total number of nodes, and scripts (e.g. getters) which run while the tree is collected are interrupted after the timeout,
+//! strings pass the [redaction_hook](crate::builder::QuickJsRuntimeBuilder::redaction_hook)
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::debugdump::DumpOptions;
+//! use quickjs_runtime::jsutils::Script;
+//! use futures::executor::block_on;
+//! let rt = QuickJsRuntimeBuilder::new().debug_records(16).build();
+//! rt.eval_sync(None, Script::new("dump.js", "globalThis.settings = {mode: 'fast'}; console.log('started');")).expect("script failed");
+//! let bundle = block_on(rt.debug_dump("__main__", DumpOptions::default())).expect("dump failed");
+//! assert_eq!(bundle.globals["settings"]["mode"], "fast");
+//! let json = serde_json::to_string(&bundle).expect("could not serialize");
+//! assert!(json.contains("started"));
+//! ```
+
+use crate::builder::BuilderSummary;
+use crate::jsutils::{redaction, JsError, JsValueType};
+use crate::quickjs_utils::objects::StableHasher;
+use crate::quickjs_utils::{arrays, errors, get_global_q, interrupthandler, objects};
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsruntimeadapter::{MemoryUsage, QuickJsRuntimeAdapter};
+use crate::quickjsvalueadapter::QuickJsValueAdapter;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// the limits for collecting a [DebugBundle]
+#[derive(Clone, Debug)]
+pub struct DumpOptions {
+    /// objects nested deeper than this are not expanded
+    pub max_depth: usize,
+    /// the max number of properties or elements per object or array
+    pub max_properties: usize,
+    /// the max number of values in the global property tree
+    pub max_nodes: usize,
+    /// longer strings are cut off
+    pub max_string_length: usize,
+    /// the max number of console records and errors
+    pub max_records: usize,
+    /// the max time spent on collecting the global property tree
+    pub timeout: Duration,
+}
+
+impl Default for DumpOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: 4,
+            max_properties: 100,
+            max_nodes: 5000,
+            max_string_length: 1024,
+            max_records: 32,
+            timeout: Duration::from_millis(100),
+        }
+    }
+}
+
+/// a module which was loaded in a realm
+#[derive(Serialize, Clone, Debug)]
+pub struct ModuleRecord {
+    pub path: String,
+    /// script, compiled or native for modules from a loader, eval for modules which were evaluated with eval_module
+    pub kind: String,
+    /// a hex FNV-1a hash of the source or bytecode, None for native modules
+    pub hash: Option<String>,
+}
+
+/// a [Proxy](crate::reflection::Proxy) class which is installed in a realm
+#[derive(Serialize, Clone, Debug)]
+pub struct ProxyClassRecord {
+    pub name: String,
+    pub instances: usize,
+}
+
+/// the work which is still pending in a realm
+#[derive(Serialize, Clone, Debug)]
+pub struct PendingRecord {
+    pub timeouts: usize,
+    pub intervals: usize,
+    /// promises which are cached to be resolved from rust
+    pub promises: usize,
+    /// true when the runtime has pending promise jobs
+    pub jobs: bool,
+}
+
+/// a line which was logged with console
+#[derive(Serialize, Clone, Debug)]
+pub struct ConsoleRecord {
+    pub level: String,
+    pub message: String,
+    /// milliseconds since the unix epoch
+    pub timestamp: u64,
+}
+
+/// an error which was not handled by the script
+#[derive(Serialize, Clone, Debug)]
+pub struct ErrorRecord {
+    /// where the error was caught, e.g. setTimeout or unhandled rejection
+    pub source: String,
+    pub message: String,
+    /// milliseconds since the unix epoch
+    pub timestamp: u64,
+}
+
+/// a diagnostic bundle of a realm, see [debugdump](crate::jsutils::debugdump)
+#[derive(Serialize)]
+pub struct DebugBundle {
+    pub realm_id: String,
+    /// the enumerable properties of the global object
+    pub globals: Value,
+    /// true when the global property tree was cut off by one of the limits of the [DumpOptions]
+    pub truncated: bool,
+    pub modules: Vec<ModuleRecord>,
+    pub proxy_classes: Vec<ProxyClassRecord>,
+    pub pending: PendingRecord,
+    pub console: Vec<ConsoleRecord>,
+    pub errors: Vec<ErrorRecord>,
+    pub memory: MemoryUsage,
+    /// the configuration the runtime was built with, see [summary](crate::builder::QuickJsRuntimeBuilder::summary)
+    pub builder: Option<BuilderSummary>,
+}
+
+/// the records which are kept per realm for a dump
+#[derive(Default)]
+pub(crate) struct DebugRecords {
+    modules: Vec<ModuleRecord>,
+    console: VecDeque<ConsoleRecord>,
+    errors: VecDeque<ErrorRecord>,
+}
+
+pub(crate) fn init(q_js_rt: &QuickJsRuntimeAdapter, capacity: usize) -> Result<(), JsError> {
+    q_js_rt.debug_record_capacity.set(capacity);
+    Ok(())
+}
+
+fn capacity() -> usize {
+    QuickJsRuntimeAdapter::do_with(|q_js_rt| q_js_rt.debug_record_capacity.get())
+}
+
+/// check if console records and errors are kept, console lines need to be formatted to be recorded
+pub(crate) fn is_recording() -> bool {
+    capacity() > 0
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn push_bounded<T>(buffer: &mut VecDeque<T>, item: T, capacity: usize) {
+    while buffer.len() >= capacity {
+        buffer.pop_front();
+    }
+    buffer.push_back(item);
+}
+
+/// keep a console line, the line should already be redacted
+pub(crate) fn record_console(realm: &QuickJsRealmAdapter, level: log::Level, message: &str) {
+    let capacity = capacity();
+    if capacity > 0 {
+        let record = ConsoleRecord {
+            level: level.to_string(),
+            message: message.to_string(),
+            timestamp: now(),
+        };
+        push_bounded(
+            &mut realm.debug_records.borrow_mut().console,
+            record,
+            capacity,
+        );
+    }
+}
+
+/// keep an error which was not handled by the script
+pub(crate) fn record_error(realm: &QuickJsRealmAdapter, source: &str, message: &str) {
+    let capacity = capacity();
+    if capacity > 0 {
+        let record = ErrorRecord {
+            source: source.to_string(),
+            message: redaction::redact(message).into_owned(),
+            timestamp: now(),
+        };
+        push_bounded(
+            &mut realm.debug_records.borrow_mut().errors,
+            record,
+            capacity,
+        );
+    }
+}
+
+/// keep a module which was loaded, a module which is loaded again replaces the previous record
+pub(crate) fn record_module(
+    realm: &QuickJsRealmAdapter,
+    path: &str,
+    kind: &str,
+    source: Option<&[u8]>,
+) {
+    let hash = source.map(|source| {
+        let mut hasher = StableHasher::new();
+        hasher.write(source);
+        format!("{:016x}", hasher.finish())
+    });
+    let record = ModuleRecord {
+        path: path.to_string(),
+        kind: kind.to_string(),
+        hash,
+    };
+    let modules = &mut realm.debug_records.borrow_mut().modules;
+    match modules.iter_mut().find(|m| m.path == record.path) {
+        Some(existing) => *existing = record,
+        None => modules.push(record),
+    }
+}
+
+/// drop the records of modules which will be loaded again
+pub(crate) fn forget_modules(realm: &QuickJsRealmAdapter, paths: &[String]) {
+    realm
+        .debug_records
+        .borrow_mut()
+        .modules
+        .retain(|m| !paths.contains(&m.path));
+}
+
+/// the modules which were loaded in a realm, in load order
+pub(crate) fn loaded_modules(realm: &QuickJsRealmAdapter) -> Vec<ModuleRecord> {
+    realm.debug_records.borrow().modules.clone()
+}
+
+struct Budget<'a> {
+    options: &'a DumpOptions,
+    nodes: usize,
+    deadline: Instant,
+    truncated: bool,
+}
+
+impl Budget<'_> {
+    fn take(&mut self) -> bool {
+        if self.nodes >= self.options.max_nodes || Instant::now() >= self.deadline {
+            self.truncated = true;
+            false
+        } else {
+            self.nodes += 1;
+            true
+        }
+    }
+}
+
+fn string_node(s: &str, budget: &mut Budget) -> Value {
+    let max = budget.options.max_string_length;
+    let s = redaction::redact(s);
+    match s.char_indices().nth(max) {
+        Some((idx, _)) => {
+            budget.truncated = true;
+            Value::String(format!("{}...", &s[..idx]))
+        }
+        None => Value::String(s.into_owned()),
+    }
+}
+
+fn value_node(
+    realm: &QuickJsRealmAdapter,
+    value: &QuickJsValueAdapter,
+    depth: usize,
+    budget: &mut Budget,
+) -> Value {
+    if !budget.take() {
+        return Value::String("[truncated]".to_string());
+    }
+    match value.get_js_type() {
+        JsValueType::I32 => Value::from(value.to_i32()),
+        JsValueType::F64 => Value::from(value.to_f64()),
+        JsValueType::Boolean => Value::from(value.to_bool()),
+        JsValueType::String => match value.to_string() {
+            Ok(s) => string_node(s.as_str(), budget),
+            Err(_) => Value::String("[unreadable]".to_string()),
+        },
+        JsValueType::Null | JsValueType::Undefined => Value::Null,
+        JsValueType::Function => Value::String("[Function]".to_string()),
+        JsValueType::BigInt => Value::String("[BigInt]".to_string()),
+        JsValueType::Promise => Value::String("[Promise]".to_string()),
+        JsValueType::Date => Value::String("[Date]".to_string()),
+        JsValueType::Error => {
+            let err = unsafe { errors::error_to_js_error(realm.context, value) };
+            string_node(
+                format!("[{}: {}]", err.get_name(), err.get_message()).as_str(),
+                budget,
+            )
+        }
+        JsValueType::Array => {
+            let len = arrays::get_length_q(realm, value).unwrap_or(0);
+            if depth >= budget.options.max_depth {
+                budget.truncated = true;
+                return Value::String(format!("[Array({len})]"));
+            }
+            let mut elements = vec![];
+            for index in 0..len.min(budget.options.max_properties as u32) {
+                elements.push(match arrays::get_element_q(realm, value, index) {
+                    Ok(element) => value_node(realm, &element, depth + 1, budget),
+                    Err(_) => Value::String("[unreadable]".to_string()),
+                });
+            }
+            if len as usize > budget.options.max_properties {
+                budget.truncated = true;
+            }
+            Value::Array(elements)
+        }
+        JsValueType::Object => {
+            if depth >= budget.options.max_depth {
+                budget.truncated = true;
+                return Value::String("[Object]".to_string());
+            }
+            object_node(realm, value, depth, budget)
+        }
+    }
+}
+
+fn object_node(
+    realm: &QuickJsRealmAdapter,
+    object: &QuickJsValueAdapter,
+    depth: usize,
+    budget: &mut Budget,
+) -> Value {
+    let names = match objects::get_property_names_q(realm, object) {
+        Ok(names) => names,
+        Err(_) => return Value::String("[unreadable]".to_string()),
+    };
+    if names.len() > budget.options.max_properties {
+        budget.truncated = true;
+    }
+    let mut map = serde_json::Map::new();
+    for name in names.into_iter().take(budget.options.max_properties) {
+        // getters may run scripts, those are interrupted at the deadline
+        let node = match objects::get_property_q(realm, object, name.as_str()) {
+            Ok(prop) => value_node(realm, &prop, depth + 1, budget),
+            Err(_) => Value::String("[unreadable]".to_string()),
+        };
+        map.insert(name, node);
+    }
+    Value::Object(map)
+}
+
+/// collect the global property tree of a realm within the limits of the options, returns the tree and true when it was truncated
+pub fn globals_q(
+    q_js_rt: &QuickJsRuntimeAdapter,
+    realm: &QuickJsRealmAdapter,
+    options: &DumpOptions,
+) -> (Value, bool) {
+    let deadline = Instant::now() + options.timeout;
+    let previous_deadline = q_js_rt.interrupt_deadline.get();
+    q_js_rt
+        .interrupt_deadline
+        .set(Some(match previous_deadline {
+            Some(previous) => previous.min(deadline),
+            None => deadline,
+        }));
+    interrupthandler::init(q_js_rt);
+
+    let mut budget = Budget {
+        options,
+        nodes: 0,
+        deadline,
+        truncated: false,
+    };
+    let globals = object_node(realm, &get_global_q(realm), 0, &mut budget);
+
+    q_js_rt.interrupt_deadline.set(previous_deadline);
+    (globals, budget.truncated)
+}
+
+/// collect a [DebugBundle] for a realm
+pub fn dump_q(
+    q_js_rt: &QuickJsRuntimeAdapter,
+    realm: &QuickJsRealmAdapter,
+    options: &DumpOptions,
+) -> DebugBundle {
+    let (globals, truncated) = globals_q(q_js_rt, realm, options);
+
+    let mut proxy_classes: Vec<ProxyClassRecord> = realm
+        .proxy_registry
+        .borrow()
+        .iter()
+        .map(|(name, proxy)| ProxyClassRecord {
+            name: name.clone(),
+            instances: proxy.proxy_instance_id_mappings.borrow().len(),
+        })
+        .collect();
+    proxy_classes.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let pending = PendingRecord {
+        timeouts: realm.timeout_ids.borrow().len(),
+        intervals: realm.interval_ids.borrow().len(),
+        promises: realm.cached_promise_count(),
+        jobs: q_js_rt.has_pending_jobs(),
+    };
+
+    let records = realm.debug_records.borrow();
+    let last = |len: usize| len.saturating_sub(options.max_records);
+    DebugBundle {
+        realm_id: realm.id.clone(),
+        globals,
+        truncated,
+        modules: records.modules.clone(),
+        proxy_classes,
+        pending,
+        console: records
+            .console
+            .iter()
+            .skip(last(records.console.len()))
+            .cloned()
+            .collect(),
+        errors: records
+            .errors
+            .iter()
+            .skip(last(records.errors.len()))
+            .cloned()
+            .collect(),
+        memory: q_js_rt.memory_usage(),
+        builder: q_js_rt.builder_summary.clone(),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::debugdump::DumpOptions;
+    use crate::jsutils::Script;
+    use crate::reflection::Proxy;
+    use futures::executor::block_on;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_debug_dump() {
+        let rt = QuickJsRuntimeBuilder::new().debug_records(4).build();
+        rt.loop_realm_sync(None, |_rt, realm| {
+            Proxy::new()
+                .name("Widget")
+                .constructor(|_rt, _realm, _id, _args| Ok(()))
+                .install(realm, true)
+                .map(|_| ())
+        })
+        .expect("install failed");
+        rt.eval_sync(
+            None,
+            Script::new(
+                "test_debug_dump.js",
+                "globalThis.config = {name: 'x'.repeat(50), nested: {a: {b: {c: 1}}}, list: Array.from({length: 200}, (_, i) => i)}; \
+                 globalThis.widgets = [new Widget(), new Widget()]; \
+                 for (let i = 0; i < 10; i++) { console.log('line ' + i); } \
+                 setTimeout(() => {}, 10000); \
+                 Promise.reject(new Error('nobody listens'));",
+            ),
+        )
+        .expect("script failed");
+        rt.eval_module_sync(
+            None,
+            Script::new("test_debug_dump.mes", "export const a = 1;"),
+        )
+        .expect("module failed");
+
+        let options = DumpOptions {
+            max_depth: 3,
+            max_string_length: 10,
+            max_records: 2,
+            ..Default::default()
+        };
+        let bundle = block_on(rt.debug_dump("__main__", options)).expect("dump failed");
+        assert!(bundle.truncated);
+        assert_eq!(bundle.globals["config"]["name"], "xxxxxxxxxx...");
+        assert_eq!(bundle.globals["config"]["nested"]["a"], "[Object]");
+        assert_eq!(
+            bundle.globals["config"]["list"]
+                .as_array()
+                .expect("not an array")
+                .len(),
+            100
+        );
+
+        let widget = bundle
+            .proxy_classes
+            .iter()
+            .find(|p| p.name == "Widget")
+            .expect("no Widget");
+        assert_eq!(widget.instances, 2);
+        assert_eq!(bundle.pending.timeouts, 1);
+
+        let module = bundle
+            .modules
+            .iter()
+            .find(|m| m.path == "test_debug_dump.mes")
+            .expect("no module");
+        assert_eq!(module.hash.as_ref().map(|h| h.len()), Some(16));
+
+        // the ring buffer keeps 4 records, the dump returns the last 2
+        let lines: Vec<&str> = bundle.console.iter().map(|r| r.message.as_str()).collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("line 8"), "{lines:?}");
+        assert!(lines[1].ends_with("line 9"), "{lines:?}");
+        assert!(bundle.errors[0].message.contains("nobody listens"));
+
+        serde_json::to_string(&bundle).expect("could not serialize");
+
+        // a getter which never returns is interrupted
+        rt.eval_sync(
+            None,
+            Script::new(
+                "test_debug_dump.js",
+                "Object.defineProperty(globalThis, 'stuck', {enumerable: true, get: () => { while (true) {} }});",
+            ),
+        )
+        .expect("script failed");
+        let start = Instant::now();
+        let bundle = block_on(rt.debug_dump(
+            "__main__",
+            DumpOptions {
+                timeout: Duration::from_millis(50),
+                ..Default::default()
+            },
+        ))
+        .expect("dump failed");
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(bundle.globals["stuck"], "[unreadable]");
+
+        assert!(block_on(rt.debug_dump("no_such_realm", DumpOptions::default())).is_err());
+    }
+}
@@ -0,0 +1,186 @@
+//! executors which run the async parts of the runtime (e.g. the producers of resolving promises)
+//!
+//! by default the built-in helper thread pool is used, an alternative may be selected with [executor](crate::builder::QuickJsRuntimeBuilder::executor)
+
+use crate::jsutils::helper_tasks::{add_helper_task, add_helper_task_async};
+use futures::Future;
+use std::pin::Pin;
+
+/// a future as it is passed to [JsExecutor::spawn]
+pub type JsExecutorFuture = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/// a blocking task as it is passed to [JsExecutor::spawn_blocking]
+pub type JsExecutorTask = Box<dyn FnOnce() + Send + 'static>;
+
+/// the JsExecutor is used to run futures and blocking tasks outside of the EventLoop thread of a runtime
+pub trait JsExecutor: Send + Sync {
+    /// run a future to completion, the result is not awaited by the caller
+    fn spawn(&self, future: JsExecutorFuture);
+    /// run a task which may block the current thread
+    fn spawn_blocking(&self, task: JsExecutorTask);
+}
+
+/// the default executor, runs everything in the built-in helper thread pool
+pub struct HelperTaskExecutor {}
+
+impl JsExecutor for HelperTaskExecutor {
+    fn spawn(&self, future: JsExecutorFuture) {
+        let _ignore_result = add_helper_task_async(future);
+    }
+
+    fn spawn_blocking(&self, task: JsExecutorTask) {
+        add_helper_task(task);
+    }
+}
+
+/// an executor which runs everything in an existing tokio runtime
+pub struct TokioExecutor {
+    handle: tokio::runtime::Handle,
+}
+
+impl TokioExecutor {
+    pub fn new(handle: tokio::runtime::Handle) -> Self {
+        Self { handle }
+    }
+    /// create an executor for the tokio runtime of the current thread
+    /// # Panics
+    /// when not called from a tokio runtime
+    pub fn current() -> Self {
+        Self::new(tokio::runtime::Handle::current())
+    }
+}
+
+impl JsExecutor for TokioExecutor {
+    fn spawn(&self, future: JsExecutorFuture) {
+        let _join_handle = self.handle.spawn(future);
+    }
+
+    fn spawn_blocking(&self, task: JsExecutorTask) {
+        let _join_handle = self.handle.spawn_blocking(task);
+    }
+}
+
+/// an executor which runs everything in the global async-std executor
+#[cfg(feature = "async-std")]
+pub struct AsyncStdExecutor {}
+
+#[cfg(feature = "async-std")]
+impl JsExecutor for AsyncStdExecutor {
+    fn spawn(&self, future: JsExecutorFuture) {
+        let _join_handle = async_std::task::spawn(future);
+    }
+
+    fn spawn_blocking(&self, task: JsExecutorTask) {
+        let _join_handle = async_std::task::spawn_blocking(task);
+    }
+}
+
+/// an executor which runs everything in the global smol executor
+#[cfg(feature = "smol")]
+pub struct SmolExecutor {}
+
+#[cfg(feature = "smol")]
+impl JsExecutor for SmolExecutor {
+    fn spawn(&self, future: JsExecutorFuture) {
+        smol::spawn(future).detach();
+    }
+
+    fn spawn_blocking(&self, task: JsExecutorTask) {
+        smol::unblock(task).detach();
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::facades::QuickJsRuntimeFacade;
+    use crate::jsutils::executor::{HelperTaskExecutor, JsExecutor, TokioExecutor};
+    use crate::jsutils::{JsError, Script};
+    use crate::quickjs_utils::{functions, get_global_q, objects, primitives};
+    use crate::values::JsValueFacade;
+
+    fn test_async_bridges(rt: QuickJsRuntimeFacade) {
+        rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let realm = q_js_rt.get_main_realm();
+            let blocking_func = functions::new_function_q(
+                realm,
+                "blocking",
+                |realm, _this, args| {
+                    let a = primitives::to_i32(&args[0])?;
+                    realm.create_resolving_promise(
+                        move || Ok(a * 2),
+                        |realm, res| realm.create_i32(res),
+                    )
+                },
+                1,
+            )
+            .expect("could not create func");
+            let async_func = functions::new_function_q(
+                realm,
+                "nonBlocking",
+                |realm, _this, args| {
+                    let a = primitives::to_i32(&args[0])?;
+                    realm.create_resolving_promise_async(
+                        async move { Ok::<i32, JsError>(a * 3) },
+                        |realm, res| realm.create_i32(res),
+                    )
+                },
+                1,
+            )
+            .expect("could not create func");
+            let global = get_global_q(realm);
+            objects::set_property_q(realm, &global, "blocking", &blocking_func)
+                .expect("could not set prop");
+            objects::set_property_q(realm, &global, "nonBlocking", &async_func)
+                .expect("could not set prop");
+        });
+
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "test_executor.js",
+                    "(async () => {return (await blocking(2)) + (await nonBlocking(2));})()",
+                ),
+            )
+            .expect("script failed");
+        let res = match res {
+            JsValueFacade::JsPromise { cached_promise } => cached_promise
+                .get_promise_result_sync()
+                .expect("promise timed out")
+                .expect("promise failed"),
+            _ => panic!("not a promise"),
+        };
+        assert_eq!(res.get_i32(), 10);
+    }
+
+    fn build_rt<E: JsExecutor + 'static>(executor: E) -> QuickJsRuntimeFacade {
+        QuickJsRuntimeBuilder::new().executor(executor).build()
+    }
+
+    #[test]
+    fn test_helper_task_executor() {
+        test_async_bridges(build_rt(HelperTaskExecutor {}));
+    }
+
+    #[test]
+    fn test_tokio_executor() {
+        let tokio_rt = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .build()
+            .expect("could not build tokio runtime");
+        test_async_bridges(build_rt(TokioExecutor::new(tokio_rt.handle().clone())));
+    }
+
+    #[cfg(feature = "async-std")]
+    #[test]
+    fn test_async_std_executor() {
+        test_async_bridges(build_rt(crate::jsutils::executor::AsyncStdExecutor {}));
+    }
+
+    #[cfg(feature = "smol")]
+    #[test]
+    fn test_smol_executor() {
+        test_async_bridges(build_rt(crate::jsutils::executor::SmolExecutor {}));
+    }
+}
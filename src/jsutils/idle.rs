@@ -0,0 +1,185 @@
+//! background maintenance while the runtime is idle, see [idle_callback](crate::builder::QuickJsRuntimeBuilder::idle_callback)
+//!
+//! the callback runs in the worker thread when no jobs were added and no timers or pending jobs ran for `min_idle`
+//!
+//! * it runs as a job of the worker thread, so it never runs at the same time as another job
+//! * while the runtime stays idle it runs again every `min_idle`
+//! * it should return within its [budget](crate::builder::QuickJsRuntimeBuilder::idle_callback_budget), the time it takes is measured
+//!   and a warning is logged when it took longer
+//! * it stops firing when the runtime is dropped
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use std::sync::atomic::{AtomicUsize, Ordering};
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//! let fired = Arc::new(AtomicUsize::new(0));
+//! let counter = fired.clone();
+//! let rt = QuickJsRuntimeBuilder::new()
+//!     .idle_callback(Duration::from_millis(10), move |q_js_rt| {
+//!         q_js_rt.gc();
+//!         counter.fetch_add(1, Ordering::SeqCst);
+//!     })
+//!     .build();
+//! std::thread::sleep(Duration::from_millis(100));
+//! assert!(fired.load(Ordering::SeqCst) > 0);
+//! ```
+
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// the callback of [idle_callback](crate::builder::QuickJsRuntimeBuilder::idle_callback)
+pub type IdleCallback = Box<dyn Fn(&QuickJsRuntimeAdapter) + Send>;
+
+/// the budget of an idle callback when none was set
+pub const DEFAULT_IDLE_BUDGET: Duration = Duration::from_millis(10);
+
+/// tracks the activity of the worker thread, shared by the facade, the worker and the thread which polls for idleness
+pub(crate) struct IdleTracker {
+    min_idle: Duration,
+    budget: Duration,
+    // jobs which were added and did not finish yet
+    pending: AtomicUsize,
+    // the end of the last job, or of the last idle callback
+    last_activity: Mutex<Instant>,
+    // an idle callback was added to the worker and did not run yet
+    queued: AtomicBool,
+    stopped: AtomicBool,
+}
+
+impl IdleTracker {
+    pub(crate) fn new(min_idle: Duration, budget: Duration, now: Instant) -> Self {
+        Self {
+            min_idle,
+            budget,
+            pending: AtomicUsize::new(0),
+            last_activity: Mutex::new(now),
+            queued: AtomicBool::new(false),
+            stopped: AtomicBool::new(false),
+        }
+    }
+
+    pub(crate) fn job_added(&self) {
+        self.pending.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub(crate) fn job_done(&self, now: Instant) {
+        self.touch(now);
+        self.pending.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// record activity which was not added as a job, e.g. a timer
+    pub(crate) fn touch(&self, now: Instant) {
+        *self.last_activity.lock().unwrap() = now;
+    }
+
+    /// check if the runtime was idle for min_idle
+    pub(crate) fn is_due(&self, now: Instant) -> bool {
+        !self.stopped.load(Ordering::SeqCst)
+            && self.pending.load(Ordering::SeqCst) == 0
+            && now.saturating_duration_since(*self.last_activity.lock().unwrap()) >= self.min_idle
+    }
+
+    /// check if the callback should be added to the worker, at most one callback is queued at a time
+    pub(crate) fn should_queue(&self, now: Instant) -> bool {
+        self.is_due(now) && !self.queued.swap(true, Ordering::SeqCst)
+    }
+
+    pub(crate) fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+
+    pub(crate) fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
+
+    /// how often the polling thread checks for idleness
+    pub(crate) fn poll_interval(&self) -> Duration {
+        (self.min_idle / 4).max(Duration::from_millis(1))
+    }
+}
+
+/// run the idle callback in the worker thread, a job which was added after the callback was queued skips it
+pub(crate) fn run_idle_callback_q(q_js_rt: &QuickJsRuntimeAdapter, tracker: &IdleTracker) {
+    tracker.queued.store(false, Ordering::SeqCst);
+    if !tracker.is_due(Instant::now()) {
+        return;
+    }
+    if let Some(callback) = &q_js_rt.idle_callback {
+        let start = Instant::now();
+        callback(q_js_rt);
+        q_js_rt.run_pending_jobs_if_any();
+        let elapsed = start.elapsed();
+        if elapsed > tracker.budget {
+            log::warn!(
+                "idle callback took {:?}, its budget is {:?}",
+                elapsed,
+                tracker.budget
+            );
+        }
+    }
+    tracker.touch(Instant::now());
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::idle::IdleTracker;
+    use crate::jsutils::Script;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_idle_tracker() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let tracker = IdleTracker::new(Duration::from_millis(100), Duration::from_millis(5), start);
+        assert!(!tracker.is_due(at(99)));
+        assert!(tracker.is_due(at(100)));
+
+        // a running job is never idle, its end restarts the idle time
+        tracker.job_added();
+        assert!(!tracker.is_due(at(500)));
+        tracker.job_done(at(500));
+        assert!(!tracker.is_due(at(550)));
+        assert!(tracker.should_queue(at(600)));
+        // only one callback is queued
+        assert!(!tracker.should_queue(at(700)));
+
+        tracker.stop();
+        assert!(!tracker.is_due(at(10_000)));
+    }
+
+    #[test]
+    fn test_idle_callback() {
+        let fired = Arc::new(AtomicUsize::new(0));
+        let counter = fired.clone();
+        let rt = QuickJsRuntimeBuilder::new()
+            .idle_callback(Duration::from_millis(20), move |q_js_rt| {
+                // the callback runs in the worker, so no script is running
+                assert!(q_js_rt
+                    .get_main_realm()
+                    .eval(Script::new("idle.js", "1;"))
+                    .is_ok());
+                counter.fetch_add(1, Ordering::SeqCst);
+            })
+            .build();
+        // keep the runtime busy, the callback does not fire while jobs keep arriving
+        for _ in 0..10 {
+            rt.eval_sync(None, Script::new("busy.js", "1;"))
+                .expect("script failed");
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        assert_eq!(fired.load(Ordering::SeqCst), 0);
+        std::thread::sleep(Duration::from_millis(150));
+        assert!(fired.load(Ordering::SeqCst) > 0);
+
+        drop(rt);
+        let after_drop = fired.load(Ordering::SeqCst);
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(fired.load(Ordering::SeqCst), after_drop);
+    }
+}
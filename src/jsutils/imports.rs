@@ -0,0 +1,225 @@
+//! module bindings for scripts, see [EvalOptions::module_imports](crate::jsutils::taskscope::EvalOptions::module_imports)
+//!
+//! a script (which can not use import statements) is evaluated with the named exports of modules in scope, as if it was preceded by
+//! `import {helper} from "lib";`
+//!
+//! * the modules are loaded through the module loaders of the runtime, relative specifiers are resolved against the path of the script
+//! * the namespace of a module is kept by the realm, later evals with the same module do not load it again
+//! * an export which does not exist fails the eval before the script runs
+//! * the script runs as the body of a function, the value it returns is the result, see [eval_with_scope](crate::quickjsrealmadapter::QuickJsRealmAdapter::eval_with_scope)
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::modules::ScriptModuleLoader;
+//! use quickjs_runtime::jsutils::taskscope::EvalOptions;
+//! use quickjs_runtime::jsutils::Script;
+//! use quickjs_runtime::quickjsrealmadapter::QuickJsRealmAdapter;
+//! use futures::executor::block_on;
+//! struct LibLoader {}
+//! impl ScriptModuleLoader for LibLoader {
+//!     fn normalize_path(&self, _realm: &QuickJsRealmAdapter, _ref_path: &str, path: &str) -> Option<String> {
+//!         (path == "lib").then(|| path.to_string())
+//!     }
+//!     fn load_module(&self, _realm: &QuickJsRealmAdapter, _absolute_path: &str) -> String {
+//!         "export function helper(a) { return a * 2; }".to_string()
+//!     }
+//! }
+//! let rt = QuickJsRuntimeBuilder::new().script_module_loader(LibLoader {}).build();
+//! let options = EvalOptions::new().module_imports(vec![("lib", vec!["helper"])]);
+//! let res = block_on(rt.eval_with_options(None, Script::new("fragment.js", "return helper(21);"), options)).expect("eval failed");
+//! assert_eq!(res.get_i32(), 42);
+//! ```
+
+use crate::jsutils::{JsError, Script};
+use crate::quickjs_utils::compile::{compile, run_compiled_function};
+use crate::quickjs_utils::{errors, functions, objects, promises};
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use crate::quickjsvalueadapter::QuickJsValueAdapter;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+type ImportResult = Rc<RefCell<Option<Result<QuickJsValueAdapter, JsError>>>>;
+
+fn reason_to_js_error(realm: &QuickJsRealmAdapter, reason: &QuickJsValueAdapter) -> JsError {
+    unsafe { errors::thrown_to_js_error(realm.context, reason) }
+}
+
+/// load a module with a dynamic import and get its namespace, fails when the import does not settle while the pending jobs run
+fn import_namespace_q(
+    q_js_rt: &QuickJsRuntimeAdapter,
+    realm: &QuickJsRealmAdapter,
+    referrer: &str,
+    specifier: &str,
+) -> Result<QuickJsValueAdapter, JsError> {
+    let code = format!(
+        "import({});",
+        serde_json::to_string(specifier).map_err(|e| JsError::new_string(format!("{e}")))?
+    );
+    // compiled directly so the import is resolved against the path of the script
+    let promise = unsafe {
+        let compiled = compile(realm.context, Script::new(referrer, code.as_str()))?;
+        run_compiled_function(realm.context, &compiled)?
+    };
+
+    let result: ImportResult = Rc::new(RefCell::new(None));
+    let then_result = result.clone();
+    let then_func = functions::new_function_q(
+        realm,
+        "then",
+        move |realm, _this, args| {
+            then_result.replace(Some(Ok(args[0].dup())));
+            realm.create_undefined()
+        },
+        1,
+    )?;
+    let catch_result = result.clone();
+    let catch_func = functions::new_function_q(
+        realm,
+        "catch",
+        move |realm, _this, args| {
+            catch_result.replace(Some(Err(reason_to_js_error(realm, &args[0]))));
+            realm.create_undefined()
+        },
+        1,
+    )?;
+    promises::add_promise_reactions_q(realm, &promise, Some(then_func), Some(catch_func), None)?;
+    q_js_rt.run_pending_jobs_if_any();
+
+    let settled = result.borrow_mut().take();
+    match settled {
+        Some(res) => res,
+        None => Err(JsError::new_string(format!(
+            "module {specifier} did not finish loading, modules with a pending top level await can not be imported for a script"
+        ))),
+    }
+}
+
+/// load a module and keep its namespace in the realm, a module which was loaded before is not imported again unless the module cache is disabled
+pub(crate) fn get_namespace_q(
+    q_js_rt: &QuickJsRuntimeAdapter,
+    realm: &QuickJsRealmAdapter,
+    referrer: &str,
+    specifier: &str,
+) -> Result<QuickJsValueAdapter, JsError> {
+    if !q_js_rt.module_cache {
+        return import_namespace_q(q_js_rt, realm, referrer, specifier);
+    }
+    let (normalized, _) = q_js_rt.normalize_module_path(realm, referrer, specifier)?;
+    let cached = realm
+        .module_namespaces
+        .borrow()
+        .get(&normalized)
+        .map(QuickJsValueAdapter::dup);
+    match cached {
+        Some(namespace) => Ok(namespace),
+        None => {
+            let namespace = import_namespace_q(q_js_rt, realm, referrer, specifier)?;
+            realm
+                .module_namespaces
+                .borrow_mut()
+                .insert(normalized, namespace.dup());
+            Ok(namespace)
+        }
+    }
+}
+
+/// get the bindings for the imports of a script, the namespaces are cached by the normalized name of the module
+pub(crate) fn import_bindings_q(
+    q_js_rt: &QuickJsRuntimeAdapter,
+    realm: &QuickJsRealmAdapter,
+    referrer: &str,
+    imports: &[(String, Vec<String>)],
+) -> Result<Vec<(String, QuickJsValueAdapter)>, JsError> {
+    let mut bindings = vec![];
+    for (specifier, names) in imports {
+        let namespace = get_namespace_q(q_js_rt, realm, referrer, specifier)?;
+
+        let exports = objects::get_property_names_q(realm, &namespace)?;
+        for name in names {
+            if !exports.contains(name) {
+                return Err(JsError::new_string(format!(
+                    "module {specifier} does not export {name}"
+                )));
+            }
+            bindings.push((
+                name.clone(),
+                objects::get_property_q(realm, &namespace, name)?,
+            ));
+        }
+    }
+    Ok(bindings)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::modules::ScriptModuleLoader;
+    use crate::jsutils::taskscope::EvalOptions;
+    use crate::jsutils::Script;
+    use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+    use futures::executor::block_on;
+
+    struct TestLoader {}
+
+    impl ScriptModuleLoader for TestLoader {
+        fn normalize_path(
+            &self,
+            _realm: &QuickJsRealmAdapter,
+            _ref_path: &str,
+            path: &str,
+        ) -> Option<String> {
+            match path {
+                "lib" => Some("imports/lib.mes".to_string()),
+                _ => None,
+            }
+        }
+
+        fn load_module(&self, _realm: &QuickJsRealmAdapter, _absolute_path: &str) -> String {
+            "globalThis.libLoads = (globalThis.libLoads || 0) + 1;\nexport function helper(a) { return a + 1; }\nexport const base = 10;"
+                .to_string()
+        }
+    }
+
+    #[test]
+    fn test_module_imports() {
+        let rt = QuickJsRuntimeBuilder::new()
+            .script_module_loader(TestLoader {})
+            .build();
+        let options = EvalOptions::new().module_imports(vec![("lib", vec!["helper", "base"])]);
+        for _ in 0..2 {
+            let res = block_on(rt.eval_with_options(
+                None,
+                Script::new("imports/fragment.js", "return helper(base);"),
+                options.clone(),
+            ))
+            .expect("eval failed");
+            assert_eq!(res.get_i32(), 11);
+        }
+        let loads = rt
+            .eval_sync(None, Script::new("imports/check.js", "libLoads;"))
+            .expect("script failed");
+        assert_eq!(loads.get_i32(), 1);
+
+        let err = block_on(rt.eval_with_options(
+            None,
+            Script::new("imports/missing.js", "globalThis.ran = true;"),
+            EvalOptions::new().module_imports(vec![("lib", vec!["nope"])]),
+        ))
+        .expect_err("eval succeeded");
+        assert!(err.get_message().contains("lib"));
+        assert!(err.get_message().contains("nope"));
+        let ran = rt
+            .eval_sync(None, Script::new("imports/check.js", "typeof ran;"))
+            .expect("script failed");
+        assert_eq!(ran.get_str(), "undefined");
+
+        let err = block_on(rt.eval_with_options(
+            None,
+            Script::new("imports/unknown.js", "return 1;"),
+            EvalOptions::new().module_imports(vec![("other", vec!["x"])]),
+        ))
+        .expect_err("eval succeeded");
+        assert!(err.get_message().contains("other"));
+    }
+}
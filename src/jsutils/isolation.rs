@@ -0,0 +1,306 @@
+//! evaluating a script in a throwaway realm, see [eval_isolated](crate::facades::QuickJsRuntimeFacade::eval_isolated)
+//!
+//! every isolated eval gets a new realm which is dropped when the eval is done, fails, times out or when the future is dropped,
+//! dropping the realm also releases the objects and clears the timers created by the script
+//!
+//! isolated evals are queued and run one at a time, in the order in which they were started
+//!
+//! the result is converted to a value which does not refer to the realm, objects and arrays are returned as a [JsValueFacade::SerdeValue],
+//! functions can not be returned and a returned promise is awaited (within the timeout)
+
+use crate::facades::QuickjsRuntimeFacadeInner;
+use crate::jsutils::{JsError, Script};
+use crate::quickjs_utils::errors::timeout_error;
+use crate::quickjs_utils::{errors, functions, get_global_q, interrupthandler, objects, promises};
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use crate::quickjsvalueadapter::QuickJsValueAdapter;
+use crate::values::JsValueFacade;
+use hirofa_utils::eventloop::EventLoop;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+
+static ISOLATED_REALM_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// the limits and globals of an isolated eval
+#[derive(Default)]
+pub struct IsolationOptions {
+    /// the maximum duration of the eval, including waiting for a returned promise
+    pub timeout: Option<Duration>,
+    /// the number of bytes the script may allocate on top of what the runtime already uses
+    pub memory_share: Option<u64>,
+    /// values which are set as global variables before the script is evaluated
+    pub globals: HashMap<String, JsValueFacade>,
+}
+
+type IsolatedResult = Result<JsValueFacade, JsError>;
+
+// drops the realm and resets the limits when the eval is done or the future was dropped
+struct IsolatedRealmGuard {
+    rti: Weak<QuickjsRuntimeFacadeInner>,
+    realm_id: String,
+}
+
+impl Drop for IsolatedRealmGuard {
+    fn drop(&mut self) {
+        if let Some(rti) = self.rti.upgrade() {
+            let realm_id = std::mem::take(&mut self.realm_id);
+            rti.add_task_to_event_loop_void(move || {
+                let has_realm = QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+                    q_js_rt.interrupt_deadline.set(None);
+                    q_js_rt
+                        .allocator
+                        .set_memory_limit(q_js_rt.allocator.memory_limit());
+                    q_js_rt.has_context(realm_id.as_str())
+                });
+                if has_realm {
+                    QuickJsRuntimeAdapter::remove_context(realm_id.as_str());
+                }
+            });
+        }
+    }
+}
+
+/// convert a value to a facade which does not refer to the realm
+fn to_detached_facade(
+    realm: &QuickJsRealmAdapter,
+    value: &QuickJsValueAdapter,
+) -> Result<JsValueFacade, JsError> {
+    if value.is_function() || value.is_promise() {
+        Err(JsError::new_string(format!(
+            "an isolated eval can not return a {}",
+            value.get_js_type()
+        )))
+    } else if value.is_array()
+        || (value.is_object() && !value.is_error() && !value.is_typed_array())
+    {
+        Ok(JsValueFacade::SerdeValue {
+            value: realm.value_adapter_to_serde_value(value)?,
+        })
+    } else {
+        realm.to_js_value_facade(value)
+    }
+}
+
+fn reason_to_js_error(realm: &QuickJsRealmAdapter, reason: &QuickJsValueAdapter) -> JsError {
+    unsafe { errors::thrown_to_js_error(realm.context, reason) }
+}
+
+// eval the script and send the result, a promise result is sent when it settles
+fn start_eval(
+    q_js_rt: &QuickJsRuntimeAdapter,
+    realm: &QuickJsRealmAdapter,
+    script: Script,
+    options: IsolationOptions,
+    tx: flume::Sender<IsolatedResult>,
+) -> Result<(), JsError> {
+    if let Some(timeout) = options.timeout {
+        q_js_rt
+            .interrupt_deadline
+            .set(Some(Instant::now() + timeout));
+        interrupthandler::init(q_js_rt);
+        // also times out while waiting for a promise, the timer is cleared when the realm is dropped
+        let timeout_tx = tx.clone();
+        let id = EventLoop::add_timeout(
+            move || {
+                let _ = timeout_tx.try_send(Err(timeout_error("isolated eval", timeout)));
+            },
+            timeout,
+        );
+        realm.timeout_ids.borrow_mut().insert(id);
+    }
+    if let Some(memory_share) = options.memory_share {
+        let used = q_js_rt.memory_usage().malloc_size.max(0) as u64;
+        q_js_rt
+            .allocator
+            .set_malloc_limit((used + memory_share) as usize);
+    }
+
+    let global = get_global_q(realm);
+    for (name, value) in options.globals {
+        let value = realm.from_js_value_facade(value)?;
+        objects::set_property_q(realm, &global, name.as_str(), &value)?;
+    }
+
+    let res = realm.eval(script).map_err(|err| {
+        let timed_out = q_js_rt
+            .interrupt_deadline
+            .get()
+            .map(|deadline| Instant::now() >= deadline)
+            .unwrap_or(false);
+        match options.timeout {
+            Some(timeout) if timed_out => timeout_error("isolated eval", timeout),
+            _ => err,
+        }
+    })?;
+
+    if res.is_promise() {
+        let then_tx = tx.clone();
+        let then_func = functions::new_function_q(
+            realm,
+            "then",
+            move |realm, _this, args| {
+                let _ = then_tx.try_send(to_detached_facade(realm, &args[0]));
+                realm.create_undefined()
+            },
+            1,
+        )?;
+        let catch_func = functions::new_function_q(
+            realm,
+            "catch",
+            move |realm, _this, args| {
+                let _ = tx.try_send(Err(reason_to_js_error(realm, &args[0])));
+                realm.create_undefined()
+            },
+            1,
+        )?;
+        promises::add_promise_reactions_q(realm, &res, Some(then_func), Some(catch_func), None)?;
+    } else {
+        let _ = tx.try_send(to_detached_facade(realm, &res));
+    }
+    Ok(())
+}
+
+pub(crate) async fn eval_isolated(
+    rti: Arc<QuickjsRuntimeFacadeInner>,
+    script: Script,
+    options: IsolationOptions,
+) -> Result<JsValueFacade, JsError> {
+    // the permit is dropped after the guard so the next isolated eval starts after the teardown was queued
+    let _permit = rti
+        .isolation_queue
+        .acquire()
+        .await
+        .map_err(|e| JsError::new_string(format!("{e}")))?;
+
+    let realm_id = format!(
+        "__isolated_{}",
+        ISOLATED_REALM_COUNTER.fetch_add(1, Ordering::SeqCst)
+    );
+    let _guard = IsolatedRealmGuard {
+        rti: Arc::downgrade(&rti),
+        realm_id: realm_id.clone(),
+    };
+
+    // one result is sent, the capacity of 1 makes later sends (e.g. the timeout after a result) fail
+    let (tx, rx) = flume::bounded(1);
+    rti.add_task_to_event_loop_void(move || {
+        if let Err(err) = QuickJsRuntimeAdapter::create_context(realm_id.as_str()) {
+            let _ = tx.try_send(Err(err));
+            return;
+        }
+        QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+            let realm = q_js_rt.get_context(realm_id.as_str());
+            if let Err(err) = start_eval(q_js_rt, realm, script, options, tx.clone()) {
+                let _ = tx.try_send(Err(err));
+            }
+        });
+    });
+
+    rx.recv_async()
+        .await
+        .map_err(|_| JsError::new_str("isolated realm was dropped before the eval completed"))?
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::isolation::IsolationOptions;
+    use crate::jsutils::Script;
+    use crate::values::JsValueFacade;
+    use futures::executor::block_on;
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    #[test]
+    fn test_eval_isolated() {
+        let rt = QuickJsRuntimeBuilder::new().build();
+
+        let mut globals = HashMap::new();
+        globals.insert("input".to_string(), JsValueFacade::new_i32(21));
+        let res = block_on(rt.eval_isolated(
+            Script::new(
+                "test_isolated.js",
+                "globalThis.leaked = true; setInterval(() => {}, 10); ({doubled: input * 2});",
+            ),
+            IsolationOptions {
+                globals,
+                ..Default::default()
+            },
+        ))
+        .expect("eval failed");
+        match res {
+            JsValueFacade::SerdeValue { value } => assert_eq!(value["doubled"], 42),
+            _ => panic!("not a serde value"),
+        }
+
+        // the realm is gone
+        let realm_ct = rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            q_js_rt
+                .get_main_realm()
+                .eval(Script::new("test_isolated.js", "typeof leaked"))
+                .expect("script failed")
+                .to_string()
+                .expect("not a string")
+                + ":"
+                + q_js_rt.memory_usage().realm_ct.to_string().as_str()
+        });
+        assert_eq!(realm_ct, "undefined:1");
+
+        // promises are awaited
+        let res = block_on(rt.eval_isolated(
+            Script::new(
+                "test_isolated.js",
+                "new Promise((resolve) => setTimeout(() => resolve('later'), 10));",
+            ),
+            IsolationOptions::default(),
+        ))
+        .expect("eval failed");
+        assert_eq!(res.get_str(), "later");
+
+        let err = block_on(rt.eval_isolated(
+            Script::new("test_isolated.js", "Promise.reject(new TypeError('nope'));"),
+            IsolationOptions::default(),
+        ))
+        .expect_err("eval should fail");
+        assert_eq!(err.get_name(), "TypeError");
+
+        // endless loops and promises which never settle time out
+        for code in ["while (true) {}", "new Promise(() => {});"] {
+            let err = block_on(rt.eval_isolated(
+                Script::new("test_isolated.js", code),
+                IsolationOptions {
+                    timeout: Some(Duration::from_millis(50)),
+                    ..Default::default()
+                },
+            ))
+            .expect_err("eval should time out");
+            assert_eq!(err.get_name(), "TimeoutError", "{err}");
+        }
+
+        block_on(rt.eval_isolated(
+            Script::new(
+                "test_isolated.js",
+                "let a = []; while (true) { a.push('x'.repeat(1024)); }",
+            ),
+            IsolationOptions {
+                memory_share: Some(1024 * 1024),
+                ..Default::default()
+            },
+        ))
+        .expect_err("eval should run out of memory");
+
+        // the limits are reset afterwards
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new("test_isolated.js", "'x'.repeat(4 * 1024 * 1024).length"),
+            )
+            .expect("script failed");
+        assert_eq!(res.get_i32(), 4 * 1024 * 1024);
+        let realm_ct = rt.exe_rt_task_in_event_loop(|q_js_rt| q_js_rt.memory_usage().realm_ct);
+        assert_eq!(realm_ct, 1);
+    }
+}
@@ -0,0 +1,158 @@
+//! ambient values for the native functions which are called by a script, see [eval_with_context](crate::facades::QuickJsRuntimeFacade::eval_with_context)
+//!
+//! an eval with a [JobContext] makes the values of that context readable with [current_job_context](crate::quickjsruntimeadapter::QuickJsRuntimeAdapter::current_job_context)
+//! while the script runs, this can be used to pass e.g. the id of the request which triggered the eval to native functions for tracing or authorization
+//!
+//! the context is propagated like async local storage, timer callbacks and the resolutions of internal promises run in the context of the job which started them,
+//! as do the promise reactions which are run by those jobs, outside of a job with a context [current_job_context](crate::quickjsruntimeadapter::QuickJsRuntimeAdapter::current_job_context) returns None
+//!
+//! # Limitations
+//! like [taskscope](crate::jsutils::taskscope) a promise which is resolved from rust in another way (e.g. by a cached function which is invoked later) runs outside of the context
+
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// a map of values by type which is readable from native functions during a job
+/// # Example
+/// ```rust
+/// use quickjs_runtime::jsutils::jobcontext::JobContext;
+/// #[derive(Clone)]
+/// struct RequestId(String);
+/// let context = JobContext::new().with(RequestId("abc".to_string()));
+/// assert_eq!(context.get::<RequestId>().map(|id| id.0.as_str()), Some("abc"));
+/// ```
+#[derive(Default)]
+pub struct JobContext {
+    values: HashMap<TypeId, Box<dyn Any + Send>>,
+}
+
+impl JobContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// add a value, a value of the same type is replaced
+    pub fn with<T: Send + 'static>(mut self, value: T) -> Self {
+        self.insert(value);
+        self
+    }
+    /// add a value, returns the value of the same type which was replaced
+    pub fn insert<T: Send + 'static>(&mut self, value: T) -> Option<T> {
+        self.values
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|old| old.downcast::<T>().ok())
+            .map(|old| *old)
+    }
+    /// get a value by its type
+    pub fn get<T: 'static>(&self) -> Option<&T> {
+        self.values
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref::<T>())
+    }
+}
+
+// shared between the jobs of a context, those may be resolved from helper threads so the context needs to be Send
+pub(crate) type SharedJobContext = Arc<Mutex<JobContext>>;
+
+thread_local! {
+    static CURRENT_CONTEXT: RefCell<Option<SharedJobContext>> = RefCell::new(None);
+}
+
+/// the context of the job which is currently running
+pub(crate) fn current_context() -> Option<SharedJobContext> {
+    CURRENT_CONTEXT.with(|rc| rc.borrow().clone())
+}
+
+/// see [current_job_context](crate::quickjsruntimeadapter::QuickJsRuntimeAdapter::current_job_context)
+pub(crate) fn current_value<T: Clone + 'static>() -> Option<T> {
+    let context = current_context()?;
+    let context = context.lock().unwrap_or_else(|e| e.into_inner());
+    context.get::<T>().cloned()
+}
+
+/// runs a job in a context, when dropped the pending jobs are run (still in the context) and the previous context is restored
+pub(crate) struct JobContextGuard {
+    entered: bool,
+    previous: Option<SharedJobContext>,
+}
+
+impl JobContextGuard {
+    pub(crate) fn enter(context: Option<SharedJobContext>) -> Self {
+        let entered = context.is_some();
+        let previous = CURRENT_CONTEXT.with(|rc| rc.replace(context));
+        Self { entered, previous }
+    }
+}
+
+impl Drop for JobContextGuard {
+    fn drop(&mut self) {
+        if self.entered {
+            QuickJsRuntimeAdapter::do_with(|q_js_rt| q_js_rt.run_pending_jobs_if_any());
+        }
+        let previous = self.previous.take();
+        CURRENT_CONTEXT.with(|rc| rc.replace(previous));
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::jobcontext::JobContext;
+    use crate::jsutils::Script;
+    use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+    use crate::values::JsValueFacade;
+    use futures::executor::block_on;
+    use std::time::Duration;
+
+    #[derive(Clone)]
+    struct RequestId(String);
+
+    #[test]
+    fn test_job_context() {
+        let rt = QuickJsRuntimeBuilder::new().build();
+        rt.set_function(&[], "requestId", |_realm, _args| {
+            Ok(
+                match QuickJsRuntimeAdapter::current_job_context::<RequestId>() {
+                    Some(id) => JsValueFacade::new_string(id.0),
+                    None => JsValueFacade::Null,
+                },
+            )
+        })
+        .expect("could not set function");
+
+        let res = block_on(rt.eval_with_context(
+            None,
+            Script::new(
+                "test_job_context.js",
+                "globalThis.seen = [requestId()]; \
+                 Promise.resolve().then(() => seen.push(requestId())); \
+                 setTimeout(() => { seen.push(requestId()); Promise.resolve().then(() => seen.push(requestId())); }, 50); \
+                 requestId();",
+            ),
+            JobContext::new().with(RequestId("req-1".to_string())),
+        ))
+        .expect("eval failed");
+        assert_eq!(res.get_str(), "req-1");
+
+        // a timer started outside of the job has no context
+        rt.eval_sync(
+            None,
+            Script::new(
+                "test_job_context.js",
+                "setTimeout(() => seen.push(requestId()), 80); seen.push(requestId());",
+            ),
+        )
+        .expect("script failed");
+
+        std::thread::sleep(Duration::from_millis(200));
+        let seen = rt
+            .eval_sync(
+                None,
+                Script::new("test_job_context.js", "seen.map(String).join(',')"),
+            )
+            .expect("script failed");
+        assert_eq!(seen.get_str(), "req-1,req-1,null,req-1,req-1,null");
+    }
+}
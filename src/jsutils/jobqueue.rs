@@ -0,0 +1,146 @@
+//! the number of jobs which were added to the event queue of a runtime and an optional bound on it
+//!
+//! when a [max_queue_size](crate::builder::QuickJsRuntimeBuilder::max_queue_size) is set a job is only added when the number
+//! of pending jobs is below it:
+//!
+//! * the futures of async adds (e.g. [eval](crate::facades::QuickJsRuntimeFacade::eval)) wait for space before the job is added
+//! * sync adds (e.g. [eval_sync](crate::facades::QuickJsRuntimeFacade::eval_sync)) and void adds block the calling thread
+//! * [try_add_task_to_event_loop](crate::facades::QuickJsRuntimeFacade::try_add_task_to_event_loop) fails with a `QueueFull` error
+//!
+//! jobs which are added from the worker thread of the runtime itself are counted but never wait, the worker would otherwise wait for itself,
+//! neither do the jobs which release the values of dropped facades, a facade may be dropped on a thread which should not block
+//! (e.g. a thread of an async executor)
+//!
+//! a job counts as pending until it is done, see [pending_jobs](crate::facades::QuickJsRuntimeFacade::pending_jobs),
+//! the time from adding a job until it is done is recorded in the job latency histogram of the [metrics](crate::jsutils::metrics)
+
+use crate::jsutils::metrics::LatencyHistogram;
+use crate::jsutils::JsError;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::thread::ThreadId;
+use std::time::Instant;
+use tokio::sync::Semaphore;
+
+pub(crate) struct JobQueue {
+    pending: AtomicUsize,
+    // the max number of pending jobs and the permits for them
+    bound: Option<(usize, Semaphore)>,
+    worker: OnceLock<ThreadId>,
+    pub(crate) latency: LatencyHistogram,
+}
+
+/// a place in the queue which is released when it is dropped, it is moved into the job
+pub(crate) struct JobSlot {
+    queue: Arc<JobQueue>,
+    permit: bool,
+    added: Instant,
+}
+
+impl Drop for JobSlot {
+    fn drop(&mut self) {
+        self.queue.latency.record(self.added.elapsed());
+        self.queue.pending.fetch_sub(1, Ordering::SeqCst);
+        if self.permit {
+            if let Some((_, semaphore)) = &self.queue.bound {
+                semaphore.add_permits(1);
+            }
+        }
+    }
+}
+
+impl JobQueue {
+    pub(crate) fn new(max_size: Option<usize>) -> Self {
+        Self {
+            pending: AtomicUsize::new(0),
+            bound: max_size.map(|max_size| {
+                let max_size = max_size.max(1);
+                (max_size, Semaphore::new(max_size))
+            }),
+            worker: OnceLock::new(),
+            latency: LatencyHistogram::default(),
+        }
+    }
+
+    /// remember the worker thread, its adds are never bounded
+    pub(crate) fn set_worker(&self, worker: ThreadId) {
+        let _ = self.worker.set(worker);
+    }
+
+    pub(crate) fn pending(&self) -> usize {
+        self.pending.load(Ordering::SeqCst)
+    }
+
+    // the semaphore to wait for, None when the queue is unbounded or the caller is the worker thread
+    fn semaphore(&self) -> Option<&Semaphore> {
+        let (_, semaphore) = self.bound.as_ref()?;
+        if self.worker.get() == Some(&std::thread::current().id()) {
+            None
+        } else {
+            Some(semaphore)
+        }
+    }
+
+    fn slot(queue: &Arc<JobQueue>, permit: bool) -> JobSlot {
+        queue.pending.fetch_add(1, Ordering::SeqCst);
+        JobSlot {
+            queue: queue.clone(),
+            permit,
+            added: Instant::now(),
+        }
+    }
+
+    /// get a slot without waiting, fails with a `QueueFull` error when the queue is full
+    pub(crate) fn try_reserve(queue: &Arc<JobQueue>) -> Result<JobSlot, JsError> {
+        match queue.semaphore() {
+            None => Ok(Self::slot(queue, false)),
+            Some(semaphore) => match semaphore.try_acquire() {
+                Ok(permit) => {
+                    permit.forget();
+                    Ok(Self::slot(queue, true))
+                }
+                Err(_) => Err(JsError::new_queue_full(queue.max_size())),
+            },
+        }
+    }
+
+    /// get a slot without waiting for space, for the jobs of the runtime itself
+    pub(crate) fn reserve_unbounded(queue: &Arc<JobQueue>) -> JobSlot {
+        Self::slot(queue, false)
+    }
+
+    /// get a slot, blocks the current thread until there is space
+    pub(crate) fn reserve_sync(queue: &Arc<JobQueue>) -> JobSlot {
+        match queue.semaphore() {
+            None => Self::slot(queue, false),
+            Some(semaphore) => {
+                // the semaphore is never closed
+                let permit =
+                    futures::executor::block_on(semaphore.acquire()).expect("invalid state");
+                permit.forget();
+                Self::slot(queue, true)
+            }
+        }
+    }
+
+    /// get a slot, waits until there is space
+    pub(crate) async fn reserve(queue: Arc<JobQueue>) -> JobSlot {
+        match queue.semaphore() {
+            None => Self::slot(&queue, false),
+            Some(semaphore) => {
+                let permit = semaphore.acquire().await.expect("invalid state");
+                permit.forget();
+                Self::slot(&queue, true)
+            }
+        }
+    }
+
+    /// the max number of pending jobs, None when the queue is unbounded
+    pub(crate) fn bound(&self) -> Option<usize> {
+        self.bound.as_ref().map(|(max_size, _)| *max_size)
+    }
+
+    fn max_size(&self) -> usize {
+        self.bound().unwrap_or(0)
+    }
+}
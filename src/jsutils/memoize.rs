@@ -0,0 +1,813 @@
+//! memoization of JS functions
+//!
+//! a memoized function is replaced by a native wrapper which caches the results of the original function keyed by its arguments
+//! cache hits are served without calling into script
+//!
+//! the arguments of a call are copied when it is stored, only primitives, arrays and plain objects (with Object.prototype or null
+//! as prototype) are copied and only their own enumerable data properties are read, so copying never calls into script
+//! (e.g. for getters or Proxy traps), calls with other arguments (accessors, Proxies, functions, symbols, Dates, Maps, class instances)
+//! are passed to the original function without caching
+//!
+//! a Proxy is recognized without calling its traps because the Proxy constructor of every realm is replaced by a Proxy of the
+//! original constructor which records the proxies it creates (also those of Proxy.revocable) in a WeakSet
+//!
+//! a cached result is only returned when the copied arguments are equal to those of the stored call (numbers are compared
+//! with SameValueZero semantics, property order is ignored) and `this` is the same object (or primitive),
+//! so a hash collision never returns the result of another call and an argument which is mutated after the call no longer matches
+
+use crate::features::WRITABLE_GLOBAL;
+use crate::jsutils::JsError;
+use crate::quickjs_utils::objects::StableHasher;
+use crate::quickjs_utils::{arrays, bigints, errors, functions, get_global_q, objects, promises};
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use crate::quickjsvalueadapter::{
+    QuickJsValueAdapter, TAG_BIG_INT, TAG_BOOL, TAG_FLOAT64, TAG_INT, TAG_NULL, TAG_OBJECT,
+    TAG_STRING, TAG_SYMBOL, TAG_UNDEFINED,
+};
+use libquickjs_sys as q;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::mem::MaybeUninit;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// configuration for a memoized function
+#[derive(Clone, Debug)]
+pub struct MemoConfig {
+    /// the max number of cached results, the oldest result is evicted when the cache is full
+    pub max_entries: usize,
+    /// the time after which a cached result expires, None means results never expire
+    pub ttl: Option<Duration>,
+    /// how deep the arguments are inspected when calculating the cache key, values nested deeper do not contribute to the key
+    /// but are still compared before a cached result is returned
+    pub key_depth: usize,
+}
+
+impl Default for MemoConfig {
+    fn default() -> Self {
+        Self {
+            max_entries: 1000,
+            ttl: None,
+            key_depth: 8,
+        }
+    }
+}
+
+/// a copy of an argument which was made without calling into script
+#[derive(Clone, Debug, PartialEq)]
+enum ArgSnapshot {
+    Undefined,
+    Null,
+    Boolean(bool),
+    // the bits of the number, normalized so equal bits means SameValueZero
+    Number(u64),
+    String(String),
+    BigInt(String),
+    // the length and the own enumerable properties sorted by name
+    Array(u32, Vec<(String, ArgSnapshot)>),
+    Object(Vec<(String, ArgSnapshot)>),
+    // a reference to an enclosing array or object, by its depth
+    Cycle(usize),
+}
+
+impl ArgSnapshot {
+    /// hash the snapshot, arrays and objects nested deeper than max_depth do not contribute to the hash
+    fn hash(&self, depth: usize, max_depth: usize, hasher: &mut StableHasher) {
+        let write_str = |hasher: &mut StableHasher, s: &str| {
+            hasher.write(&(s.len() as u64).to_le_bytes());
+            hasher.write(s.as_bytes());
+        };
+        let write_props = |hasher: &mut StableHasher, props: &[(String, ArgSnapshot)], tag: u8| {
+            hasher.write(&[tag]);
+            if depth >= max_depth {
+                return;
+            }
+            hasher.write(&(props.len() as u64).to_le_bytes());
+            for (name, prop) in props {
+                write_str(hasher, name);
+                prop.hash(depth + 1, max_depth, hasher);
+            }
+        };
+        match self {
+            ArgSnapshot::Undefined => hasher.write(&[0]),
+            ArgSnapshot::Null => hasher.write(&[1]),
+            ArgSnapshot::Boolean(b) => hasher.write(&[2, *b as u8]),
+            ArgSnapshot::Number(bits) => {
+                hasher.write(&[3]);
+                hasher.write(&bits.to_le_bytes());
+            }
+            ArgSnapshot::String(s) => {
+                hasher.write(&[4]);
+                write_str(hasher, s);
+            }
+            ArgSnapshot::BigInt(s) => {
+                hasher.write(&[5]);
+                write_str(hasher, s);
+            }
+            ArgSnapshot::Array(_, props) => write_props(hasher, props, 6),
+            ArgSnapshot::Object(props) => write_props(hasher, props, 7),
+            ArgSnapshot::Cycle(pos) => {
+                hasher.write(&[8]);
+                hasher.write(&(*pos as u64).to_le_bytes());
+            }
+        }
+    }
+}
+
+fn number_bits(n: f64) -> u64 {
+    if n.is_nan() {
+        f64::NAN.to_bits()
+    } else if n == 0.0 {
+        0.0_f64.to_bits()
+    } else {
+        n.to_bits()
+    }
+}
+
+/// copy the arguments of a call, None when an argument can not be copied without calling into script
+fn snapshot_args(
+    realm: &QuickJsRealmAdapter,
+    args: &[QuickJsValueAdapter],
+) -> Result<Option<Vec<ArgSnapshot>>, JsError> {
+    let object_proto = objects::get_prototype_of_q(realm, &objects::create_object_q(realm)?)?;
+    let mut stack = vec![];
+    let mut snapshots = Vec::with_capacity(args.len());
+    for arg in args {
+        match snapshot(realm, arg, &object_proto, &mut stack)? {
+            Some(snapshot) => snapshots.push(snapshot),
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(snapshots))
+}
+
+fn snapshot(
+    realm: &QuickJsRealmAdapter,
+    value: &QuickJsValueAdapter,
+    object_proto: &QuickJsValueAdapter,
+    stack: &mut Vec<QuickJsValueAdapter>,
+) -> Result<Option<ArgSnapshot>, JsError> {
+    let snapshot = match value.get_tag() {
+        TAG_UNDEFINED => ArgSnapshot::Undefined,
+        TAG_NULL => ArgSnapshot::Null,
+        TAG_BOOL => ArgSnapshot::Boolean(value.to_bool()),
+        TAG_INT => ArgSnapshot::Number(number_bits(value.to_i32() as f64)),
+        TAG_FLOAT64 => ArgSnapshot::Number(number_bits(value.to_f64())),
+        TAG_STRING => ArgSnapshot::String(value.to_string()?),
+        TAG_BIG_INT => ArgSnapshot::BigInt(bigints::to_string_q(realm, value)?),
+        TAG_OBJECT => {
+            // a Proxy is checked first, the other checks would call its traps
+            if is_proxy(value)? || functions::is_function_q(realm, value) {
+                return Ok(None);
+            }
+            let is_array = arrays::is_array_q(realm, value);
+            if !is_array {
+                let proto = objects::get_prototype_of_q(realm, value)?;
+                if !proto.is_null() && proto != *object_proto {
+                    return Ok(None);
+                }
+            }
+            if let Some(pos) = stack.iter().position(|v| v == value) {
+                return Ok(Some(ArgSnapshot::Cycle(pos)));
+            }
+            stack.push(value.dup());
+            let props = snapshot_properties(realm, value, object_proto, stack);
+            stack.pop();
+            let props = match props? {
+                Some(props) => props,
+                None => return Ok(None),
+            };
+            if is_array {
+                ArgSnapshot::Array(arrays::get_length_q(realm, value)?, props)
+            } else {
+                ArgSnapshot::Object(props)
+            }
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(snapshot))
+}
+
+// copy the own enumerable data properties of an array or plain object, None when it has an accessor or a symbol key
+fn snapshot_properties(
+    realm: &QuickJsRealmAdapter,
+    obj: &QuickJsValueAdapter,
+    object_proto: &QuickJsValueAdapter,
+    stack: &mut Vec<QuickJsValueAdapter>,
+) -> Result<Option<Vec<(String, ArgSnapshot)>>, JsError> {
+    let names = objects::get_own_property_names_q(realm, obj)?;
+    let mut props = Vec::with_capacity(names.len() as usize);
+    for index in 0..names.len() {
+        let atom = names.get_atom(index);
+        let (prop, is_accessor) = unsafe {
+            let key = QuickJsValueAdapter::new(
+                realm.context,
+                q::JS_AtomToValue(realm.context, atom.get_atom()),
+                false,
+                true,
+                "memoize::snapshot_properties key",
+            );
+            if key.get_tag() == TAG_SYMBOL {
+                return Ok(None);
+            }
+            let mut desc = MaybeUninit::<q::JSPropertyDescriptor>::uninit();
+            let res = q::JS_GetOwnProperty(
+                realm.context,
+                desc.as_mut_ptr(),
+                *obj.borrow_value(),
+                atom.get_atom(),
+            );
+            if res < 0 {
+                return Err(errors::get_exception_or(
+                    realm.context,
+                    "could not get own property",
+                ));
+            }
+            if res == 0 {
+                continue;
+            }
+            let desc = desc.assume_init();
+            let label = "memoize::snapshot_properties desc";
+            // all three are owned by the descriptor, the adapters free them
+            let prop = QuickJsValueAdapter::new(realm.context, desc.value, false, true, label);
+            let _getter = QuickJsValueAdapter::new(realm.context, desc.getter, false, true, label);
+            let _setter = QuickJsValueAdapter::new(realm.context, desc.setter, false, true, label);
+            (prop, desc.flags & q::JS_PROP_GETSET as i32 != 0)
+        };
+        if is_accessor {
+            return Ok(None);
+        }
+        match snapshot(realm, &prop, object_proto, stack)? {
+            Some(snapshot) => props.push((names.get_name(index)?, snapshot)),
+            None => return Ok(None),
+        }
+    }
+    props.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(Some(props))
+}
+
+/// the proxies which were created in a realm and the original functions which are needed to create and recognize them
+pub(crate) struct ProxyTracking {
+    // a WeakSet of the proxies
+    proxies: QuickJsValueAdapter,
+    weak_set_add: QuickJsValueAdapter,
+    weak_set_has: QuickJsValueAdapter,
+    reflect_construct: QuickJsValueAdapter,
+    proxy_constructor: QuickJsValueAdapter,
+    proxy_revocable: QuickJsValueAdapter,
+}
+
+/// replace the Proxy constructor in every realm so memoized functions can recognize proxies without calling their traps
+pub(crate) fn init(q_js_rt: &QuickJsRuntimeAdapter) -> Result<(), JsError> {
+    q_js_rt.add_context_init_hook(|_q_js_rt, realm| init_proxy_tracking(realm))
+}
+
+fn init_proxy_tracking(realm: &QuickJsRealmAdapter) -> Result<(), JsError> {
+    let global = get_global_q(realm);
+    let proxy_constructor = objects::get_property_q(realm, &global, "Proxy")?;
+    let weak_set_constructor = objects::get_property_q(realm, &global, "WeakSet")?;
+    let weak_set_proto = objects::get_property_q(realm, &weak_set_constructor, "prototype")?;
+    let reflect = objects::get_property_q(realm, &global, "Reflect")?;
+    let tracking = ProxyTracking {
+        proxies: unsafe { objects::construct_object(realm.context, &weak_set_constructor, &[])? },
+        weak_set_add: objects::get_property_q(realm, &weak_set_proto, "add")?,
+        weak_set_has: objects::get_property_q(realm, &weak_set_proto, "has")?,
+        reflect_construct: objects::get_property_q(realm, &reflect, "construct")?,
+        proxy_revocable: objects::get_property_q(realm, &proxy_constructor, "revocable")?,
+        proxy_constructor: proxy_constructor.dup(),
+    };
+    realm.proxy_tracking.replace(Some(tracking));
+
+    // the functions look up the originals in the realm so they hold no references to JS values
+    let construct_trap = functions::new_function_q(
+        realm,
+        "construct",
+        |realm, _this, args| {
+            let reflect_construct = with_proxy_tracking(realm, |t| t.reflect_construct.dup())?;
+            let proxy = functions::call_function_q(realm, &reflect_construct, args, None)?;
+            track_proxy(realm, &proxy)?;
+            Ok(proxy)
+        },
+        3,
+    )?;
+    let revocable = functions::new_function_q(
+        realm,
+        "revocable",
+        |realm, _this, args| {
+            let (proxy_constructor, proxy_revocable) = with_proxy_tracking(realm, |t| {
+                (t.proxy_constructor.dup(), t.proxy_revocable.dup())
+            })?;
+            let res = functions::call_function_q(
+                realm,
+                &proxy_revocable,
+                args,
+                Some(&proxy_constructor),
+            )?;
+            track_proxy(realm, &objects::get_property_q(realm, &res, "proxy")?)?;
+            Ok(res)
+        },
+        2,
+    )?;
+    let handler = objects::create_object_q(realm)?;
+    objects::set_property_q(realm, &handler, "construct", &construct_trap)?;
+    // reads of the tracking Proxy are forwarded to the original constructor
+    objects::set_property2_q(
+        realm,
+        &proxy_constructor,
+        "revocable",
+        &revocable,
+        WRITABLE_GLOBAL,
+    )?;
+    let tracking_constructor = unsafe {
+        objects::construct_object(
+            realm.context,
+            &proxy_constructor,
+            &[&proxy_constructor, &handler],
+        )?
+    };
+    objects::set_property2_q(
+        realm,
+        &global,
+        "Proxy",
+        &tracking_constructor,
+        WRITABLE_GLOBAL,
+    )
+}
+
+fn with_proxy_tracking<R, C: FnOnce(&ProxyTracking) -> R>(
+    realm: &QuickJsRealmAdapter,
+    consumer: C,
+) -> Result<R, JsError> {
+    match &*realm.proxy_tracking.borrow() {
+        Some(tracking) => Ok(consumer(tracking)),
+        None => Err(JsError::new_string(format!(
+            "proxies are not tracked in realm {}",
+            realm.get_realm_id()
+        ))),
+    }
+}
+
+fn track_proxy(realm: &QuickJsRealmAdapter, proxy: &QuickJsValueAdapter) -> Result<(), JsError> {
+    let (proxies, weak_set_add) =
+        with_proxy_tracking(realm, |t| (t.proxies.dup(), t.weak_set_add.dup()))?;
+    functions::call_function_q(realm, &weak_set_add, &[proxy.dup()], Some(&proxies))?;
+    Ok(())
+}
+
+/// check if an object is a Proxy which was created in any realm of the runtime, this never calls into script
+fn is_proxy(value: &QuickJsValueAdapter) -> Result<bool, JsError> {
+    QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+        for realm in q_js_rt.contexts.values() {
+            // a realm whose init hooks did not run yet has no tracking (and no scripts which created proxies)
+            let tracking = realm
+                .proxy_tracking
+                .borrow()
+                .as_ref()
+                .map(|t| (t.proxies.dup(), t.weak_set_has.dup()));
+            if let Some((proxies, weak_set_has)) = tracking {
+                let res = functions::call_function_q(
+                    realm,
+                    &weak_set_has,
+                    &[value.dup()],
+                    Some(&proxies),
+                )?;
+                if res.to_bool() {
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    })
+}
+
+fn hash_args(args: &[ArgSnapshot], key_depth: usize) -> u64 {
+    let mut hasher = StableHasher::new();
+    hasher.write(&(args.len() as u64).to_le_bytes());
+    for arg in args {
+        arg.hash(0, key_depth, &mut hasher);
+    }
+    hasher.finish()
+}
+
+struct MemoEntry {
+    id: u64,
+    created: Instant,
+    this: QuickJsValueAdapter,
+    args: Vec<ArgSnapshot>,
+    value: QuickJsValueAdapter,
+    is_promise: bool,
+}
+
+pub(crate) struct MemoCache {
+    config: MemoConfig,
+    // the entries per hash of their arguments, more than one when hashes collide
+    entries: HashMap<u64, Vec<MemoEntry>>,
+    insertion_order: VecDeque<(u64, u64)>,
+    next_id: u64,
+}
+
+impl MemoCache {
+    fn new(config: MemoConfig) -> Self {
+        Self {
+            config,
+            entries: HashMap::new(),
+            insertion_order: VecDeque::new(),
+            next_id: 0,
+        }
+    }
+
+    /// get the cached result of a call with the same this and equal arguments, and whether it is the result of a Promise
+    fn get(
+        &mut self,
+        key: u64,
+        this: &QuickJsValueAdapter,
+        args: &[ArgSnapshot],
+    ) -> Option<(QuickJsValueAdapter, bool)> {
+        let expired: Vec<u64> = match (self.entries.get(&key), self.config.ttl) {
+            (Some(entries), Some(ttl)) => entries
+                .iter()
+                .filter(|entry| entry.created.elapsed() > ttl)
+                .map(|entry| entry.id)
+                .collect(),
+            _ => vec![],
+        };
+        for id in expired {
+            self.remove(key, id);
+        }
+        self.entries.get(&key).and_then(|entries| {
+            entries
+                .iter()
+                .find(|entry| entry.this == *this && entry.args == args)
+                .map(|entry| (entry.value.dup(), entry.is_promise))
+        })
+    }
+
+    fn insert(
+        &mut self,
+        key: u64,
+        this: QuickJsValueAdapter,
+        args: Vec<ArgSnapshot>,
+        value: QuickJsValueAdapter,
+        is_promise: bool,
+    ) {
+        if self.config.max_entries == 0 {
+            return;
+        }
+        while self.insertion_order.len() >= self.config.max_entries {
+            match self.insertion_order.front().cloned() {
+                Some((oldest_key, oldest_id)) => self.remove(oldest_key, oldest_id),
+                None => break,
+            }
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.entries.entry(key).or_default().push(MemoEntry {
+            id,
+            created: Instant::now(),
+            this,
+            args,
+            value,
+            is_promise,
+        });
+        self.insertion_order.push_back((key, id));
+    }
+
+    fn remove(&mut self, key: u64, id: u64) {
+        if let Some(entries) = self.entries.get_mut(&key) {
+            entries.retain(|entry| entry.id != id);
+            if entries.is_empty() {
+                self.entries.remove(&key);
+            }
+        }
+        self.insertion_order
+            .retain(|(k, entry_id)| *k != key || *entry_id != id);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+        self.insertion_order.clear();
+    }
+}
+
+fn get_memo_key(namespace: &[&str], name: &str) -> String {
+    let mut parts = namespace.to_vec();
+    parts.push(name);
+    parts.join(".")
+}
+
+/// replace a function with a memoized version of that function
+///
+/// functions which return a Promise are cached only after the Promise was fulfilled, rejections are not cached
+/// calls with arguments which can not be copied without calling into script (see the [module docs](self)) are passed to the original function without caching
+/// # Example
+/// ```rust
+/// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+/// use quickjs_runtime::jsutils::Script;
+/// use quickjs_runtime::jsutils::memoize::{memoize_function_q, MemoConfig};
+/// let rt = QuickJsRuntimeBuilder::new().build();
+/// rt.exe_rt_task_in_event_loop(|q_js_rt| {
+///     let realm = q_js_rt.get_main_realm();
+///     realm.eval(Script::new("memo.js", "globalThis.calls = 0; globalThis.price = function(a) {calls++; return a * 2;};")).ok().unwrap();
+///     memoize_function_q(realm, &[], "price", MemoConfig::default()).ok().unwrap();
+///     let res = realm.eval(Script::new("memo.js", "price(2) + price(2) + calls;")).ok().unwrap();
+///     assert_eq!(res.to_i32(), 9);
+/// });
+/// ```
+pub fn memoize_function_q(
+    realm: &QuickJsRealmAdapter,
+    namespace: &[&str],
+    name: &str,
+    config: MemoConfig,
+) -> Result<(), JsError> {
+    let ns_obj = objects::get_namespace_q(realm, namespace, false)?;
+    let original = objects::get_property_q(realm, &ns_obj, name)?;
+    if !functions::is_function_q(realm, &original) {
+        return Err(JsError::new_string(format!(
+            "{} is not a function",
+            get_memo_key(namespace, name)
+        )));
+    }
+
+    let key_depth = config.key_depth;
+    let cache = Rc::new(RefCell::new(MemoCache::new(config)));
+    let wrapper_cache = cache.clone();
+
+    let wrapper = functions::new_function_q(
+        realm,
+        name,
+        move |realm, this, args| {
+            let snapshots = match snapshot_args(realm, args)? {
+                Some(snapshots) => snapshots,
+                None => {
+                    log::trace!("memoize: arguments can not be copied, not caching");
+                    return functions::call_function_q(realm, &original, args, Some(this));
+                }
+            };
+            let key = hash_args(&snapshots, key_depth);
+
+            let cached = wrapper_cache.borrow_mut().get(key, this, &snapshots);
+            if let Some((value, is_promise)) = cached {
+                return if is_promise {
+                    let promise = realm.create_promise()?;
+                    promise.resolve_q(realm, value)?;
+                    Ok(promise.get_promise_obj_ref())
+                } else {
+                    Ok(value)
+                };
+            }
+
+            let res = functions::call_function_q(realm, &original, args, Some(this))?;
+            if promises::is_promise_q(realm, &res) {
+                let then_cache = wrapper_cache.clone();
+                let then_this = this.dup();
+                let then = functions::new_function_q(
+                    realm,
+                    "memoize_then",
+                    move |realm, _this, args| {
+                        if let Some(value) = args.first() {
+                            then_cache.borrow_mut().insert(
+                                key,
+                                then_this.dup(),
+                                snapshots.clone(),
+                                value.dup(),
+                                true,
+                            );
+                        }
+                        realm.create_undefined()
+                    },
+                    1,
+                )?;
+                // rejections are not cached, the catch func only prevents an unhandled rejection of the derived promise
+                let catch = functions::new_function_q(
+                    realm,
+                    "memoize_catch",
+                    |realm, _this, _args| realm.create_undefined(),
+                    1,
+                )?;
+                functions::invoke_member_function_q(realm, &res, "then", &[then, catch])?;
+            } else {
+                wrapper_cache
+                    .borrow_mut()
+                    .insert(key, this.dup(), snapshots, res.dup(), false);
+            }
+            Ok(res)
+        },
+        1,
+    )?;
+
+    objects::set_property_q(realm, &ns_obj, name, &wrapper)?;
+
+    let memo_caches = &mut *realm.memo_caches.borrow_mut();
+    memo_caches.insert(get_memo_key(namespace, name), cache);
+    Ok(())
+}
+
+/// remove all cached results of a memoized function
+pub fn invalidate_memo_q(
+    realm: &QuickJsRealmAdapter,
+    namespace: &[&str],
+    name: &str,
+) -> Result<(), JsError> {
+    let key = get_memo_key(namespace, name);
+    let cache = {
+        let memo_caches = &*realm.memo_caches.borrow();
+        memo_caches.get(&key).cloned()
+    };
+    match cache {
+        Some(cache) => {
+            cache.borrow_mut().clear();
+            Ok(())
+        }
+        None => Err(JsError::new_string(format!("{key} is not memoized"))),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::facades::tests::init_test_rt;
+    use crate::jsutils::memoize::MemoConfig;
+    use crate::jsutils::Script;
+    use std::time::Duration;
+
+    #[test]
+    fn test_memoize() {
+        let rt = init_test_rt();
+        rt.eval_sync(
+            None,
+            Script::new(
+                "test_memoize.js",
+                "globalThis.com = {pricing: {calls: 0, price: function(a, b) {this.calls++; return {total: a.amount * b};}}};",
+            ),
+        )
+        .expect("script failed");
+
+        rt.memoize_function(
+            None,
+            &["com", "pricing"],
+            "price",
+            MemoConfig {
+                max_entries: 2,
+                ttl: None,
+                key_depth: 4,
+            },
+        )
+        .expect("memoize failed");
+
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "test_memoize.js",
+                    "let p = com.pricing; p.price({amount: 2}, 3); p.price({amount: 2}, 3); p.price({amount: 3}, 3); `${p.price({amount: 2}, 3).total}:${p.calls}`",
+                ),
+            )
+            .expect("script failed");
+        assert_eq!(res.get_str(), "6:2");
+
+        rt.invalidate_memo(None, &["com", "pricing"], "price")
+            .expect("invalidate failed");
+
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "test_memoize.js",
+                    "com.pricing.price({amount: 2}, 3); com.pricing.calls",
+                ),
+            )
+            .expect("script failed");
+        assert_eq!(res.get_i32(), 3);
+    }
+
+    #[test]
+    fn test_memoize_equal_hashes() {
+        let rt = init_test_rt();
+        rt.eval_sync(
+            None,
+            Script::new(
+                "test_memoize_equal_hashes.js",
+                "globalThis.calls = 0; globalThis.quote = function(a) {calls++; return (this.rate || 1) * a.item.price;}; globalThis.vip = {rate: 2};",
+            ),
+        )
+        .expect("script failed");
+
+        // with a key_depth of 1 the items do not contribute to the hash, so these calls have equal hashes
+        rt.memoize_function(
+            None,
+            &[],
+            "quote",
+            MemoConfig {
+                max_entries: 10,
+                ttl: None,
+                key_depth: 1,
+            },
+        )
+        .expect("memoize failed");
+
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "test_memoize_equal_hashes.js",
+                    "let q = [quote({item: {price: 1}}), quote({item: {price: 2}}), quote({item: {price: 1}}), quote.call(vip, {item: {price: 1}})]; `${q.join(',')}:${calls}`",
+                ),
+            )
+            .expect("script failed");
+        assert_eq!(res.get_str(), "1,2,1,2:3");
+    }
+
+    #[test]
+    fn test_memoize_promise() {
+        let rt = init_test_rt();
+        rt.eval_sync(
+            None,
+            Script::new(
+                "test_memoize_promise.js",
+                "globalThis.calls = 0; globalThis.fetchPrice = async function(a) {calls++; if (a < 0) {throw Error('negative');} return a * 2;};",
+            ),
+        )
+        .expect("script failed");
+
+        rt.memoize_function(
+            None,
+            &[],
+            "fetchPrice",
+            MemoConfig {
+                max_entries: 10,
+                ttl: Some(Duration::from_secs(60)),
+                key_depth: 1,
+            },
+        )
+        .expect("memoize failed");
+
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "test_memoize_promise.js",
+                    "(async () => {let a = await fetchPrice(2); let b = await fetchPrice(2); try {await fetchPrice(-1);} catch(e) {} try {await fetchPrice(-1);} catch(e) {} return `${a + b}:${calls}`;})()",
+                ),
+            )
+            .expect("script failed");
+        let res = match res {
+            crate::values::JsValueFacade::JsPromise { cached_promise } => cached_promise
+                .get_promise_result_sync()
+                .expect("promise timed out")
+                .expect("promise failed"),
+            _ => panic!("not a promise"),
+        };
+        assert_eq!(res.get_str(), "8:3");
+    }
+
+    #[test]
+    fn test_memoize_no_script_on_lookup() {
+        let rt = init_test_rt();
+        rt.eval_sync(
+            None,
+            Script::new(
+                "test_memoize_no_script_on_lookup.js",
+                "globalThis.calls = 0; globalThis.reads = 0; globalThis.size = function(a) {calls++; return Object.keys(a).length;};",
+            ),
+        )
+        .expect("script failed");
+
+        rt.memoize_function(None, &[], "size", MemoConfig::default())
+            .expect("memoize failed");
+
+        // arguments with a getter or a Proxy are not cached, the memoize wrapper itself never reads them
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "test_memoize_no_script_on_lookup.js",
+                    r#"
+                    const withGetter = {get amount() {reads++; return 2;}};
+                    const nested = {item: withGetter};
+                    const proxy = new Proxy({amount: 2}, {
+                        ownKeys(target) {reads++; return Reflect.ownKeys(target);},
+                        getOwnPropertyDescriptor(target, key) {reads++; return Reflect.getOwnPropertyDescriptor(target, key);},
+                    });
+                    const {proxy: revocable} = Proxy.revocable({amount: 2}, {
+                        getPrototypeOf(target) {reads++; return Reflect.getPrototypeOf(target);},
+                    });
+                    const plain = {amount: 2};
+                    for (const arg of [withGetter, withGetter, nested, nested, plain, plain]) {
+                        size(arg);
+                    }
+                    const readsBefore = reads;
+                    size(proxy);
+                    size(proxy);
+                    size(revocable);
+                    size(revocable);
+                    plain.extra = 1;
+                    const sameProxy = Proxy.name === 'Proxy' && Proxy.length === 2 && Array.isArray(new Proxy([], {}));
+                    `${readsBefore}:${reads - readsBefore}:${size(plain)}:${calls}:${sameProxy}`
+                    "#,
+                ),
+            )
+            .expect("script failed");
+        // the proxy traps are only called by Object.keys in the original function, twice per call
+        assert_eq!(res.get_str(), "0:4:2:10:true");
+    }
+}
@@ -0,0 +1,434 @@
+//! a snapshot of the metrics of a runtime, see [metrics_snapshot](crate::facades::QuickJsRuntimeFacade::metrics_snapshot)
+//!
+//! the snapshot holds plain numbers with stable names so it can be serialized or exported as is:
+//!
+//! * the depth of the event queue and the latency of its jobs (from adding a job until it is done) in a histogram with the
+//!   fixed buckets of [JOB_LATENCY_BUCKETS]
+//! * the [MemoryUsage] of the runtime and the number and the total duration of the garbage collections
+//! * the timers, cached promises, cached objects and proxy instances of every realm
+//! * the hits and misses of the [bytecode cache](crate::builder::QuickJsRuntimeBuilder::bytecode_cache_dir)
+//!
+//! the counters only grow, rates such as the cache hit rate are derived by the monitoring system,
+//! computing the memory usage walks the heap so a snapshot should not be taken more often than every second or so
+//!
+//! with the `prometheus` feature [to_prometheus](MetricsSnapshot::to_prometheus) renders a snapshot in the Prometheus text exposition format
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::Script;
+//! let rt = QuickJsRuntimeBuilder::new().build();
+//! rt.eval_sync(None, Script::new("metrics.js", "setTimeout(() => {}, 10000);")).expect("script failed");
+//! let snapshot = rt.metrics_snapshot();
+//! assert!(snapshot.jobs_completed > 0);
+//! assert_eq!(snapshot.realms[0].timeouts, 1);
+//! println!("{}", serde_json::to_string(&snapshot).expect("could not serialize"));
+//! ```
+
+use crate::jsutils::bytecodecache::BytecodeCacheStats;
+use crate::quickjsruntimeadapter::{MemoryUsage, QuickJsRuntimeAdapter};
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// the upper bounds of the buckets of the job latency histogram in seconds
+pub const JOB_LATENCY_BUCKETS: [f64; 10] =
+    [0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0];
+
+/// a histogram which is recorded from any thread without locking
+#[derive(Default)]
+pub(crate) struct LatencyHistogram {
+    // the number of durations per bucket, the last one for the durations above all bounds
+    buckets: [AtomicU64; JOB_LATENCY_BUCKETS.len() + 1],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl LatencyHistogram {
+    pub(crate) fn record(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let index = JOB_LATENCY_BUCKETS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(JOB_LATENCY_BUCKETS.len());
+        self.buckets[index].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> HistogramSnapshot {
+        let mut cumulative = 0;
+        let buckets = JOB_LATENCY_BUCKETS
+            .iter()
+            .zip(&self.buckets)
+            .map(|(bound, count)| {
+                cumulative += count.load(Ordering::Relaxed);
+                HistogramBucket {
+                    le: *bound,
+                    count: cumulative,
+                }
+            })
+            .collect();
+        HistogramSnapshot {
+            buckets,
+            count: self.count.load(Ordering::Relaxed),
+            sum_seconds: self.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0,
+        }
+    }
+}
+
+/// the number of observations which are less than or equal to an upper bound
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct HistogramBucket {
+    /// the upper bound in seconds
+    pub le: f64,
+    pub count: u64,
+}
+
+/// a histogram with cumulative buckets, the observations above the last bound are only included in the count
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct HistogramSnapshot {
+    pub buckets: Vec<HistogramBucket>,
+    pub count: u64,
+    pub sum_seconds: f64,
+}
+
+/// the counts of a realm
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct RealmMetrics {
+    pub realm_id: String,
+    pub timeouts: usize,
+    pub intervals: usize,
+    /// promises which are cached to be resolved from rust
+    pub cached_promises: usize,
+    pub cached_objects: usize,
+    pub proxy_instances: usize,
+}
+
+/// the metrics of a runtime, see [metrics](crate::jsutils::metrics)
+#[derive(Serialize, Clone, Debug)]
+pub struct MetricsSnapshot {
+    pub runtime_id: String,
+    /// the jobs which were added and are not done yet
+    pub pending_jobs: usize,
+    /// the [max_queue_size](crate::builder::QuickJsRuntimeBuilder::max_queue_size), None when the queue is unbounded
+    pub max_queue_size: Option<usize>,
+    pub jobs_completed: u64,
+    pub job_latency: HistogramSnapshot,
+    pub memory: MemoryUsage,
+    pub gc_runs: u64,
+    pub gc_seconds_total: f64,
+    /// the realms ordered by id
+    pub realms: Vec<RealmMetrics>,
+    /// None when no bytecode cache was set
+    pub bytecode_cache: Option<BytecodeCacheStats>,
+}
+
+/// collect the metrics of the worker thread, the queue metrics are read by the caller before the collecting job is added
+pub(crate) fn snapshot_q(
+    q_js_rt: &QuickJsRuntimeAdapter,
+    pending_jobs: usize,
+    max_queue_size: Option<usize>,
+    job_latency: HistogramSnapshot,
+) -> MetricsSnapshot {
+    let mut realms: Vec<RealmMetrics> = q_js_rt
+        .contexts
+        .values()
+        .map(|realm| RealmMetrics {
+            realm_id: realm.id.clone(),
+            timeouts: realm.timeout_ids.borrow().len(),
+            intervals: realm.interval_ids.borrow().len(),
+            cached_promises: realm.cached_promise_count(),
+            cached_objects: realm.cached_object_count(),
+            proxy_instances: realm
+                .proxy_registry
+                .borrow()
+                .values()
+                .map(|proxy| proxy.proxy_instance_id_mappings.borrow().len())
+                .sum(),
+        })
+        .collect();
+    realms.sort_by(|a, b| a.realm_id.cmp(&b.realm_id));
+    MetricsSnapshot {
+        runtime_id: q_js_rt.get_id().to_string(),
+        pending_jobs,
+        max_queue_size,
+        jobs_completed: job_latency.count,
+        job_latency,
+        memory: q_js_rt.memory_usage(),
+        gc_runs: q_js_rt.gc_runs.get(),
+        gc_seconds_total: q_js_rt.gc_time.get().as_secs_f64(),
+        realms,
+        bytecode_cache: q_js_rt
+            .bytecode_cache
+            .as_ref()
+            .map(|cache| cache.get_stats()),
+    }
+}
+
+// the name, the help and the value of a gauge of every realm
+#[cfg(feature = "prometheus")]
+type RealmGauge = (&'static str, &'static str, fn(&RealmMetrics) -> usize);
+
+#[cfg(feature = "prometheus")]
+impl MetricsSnapshot {
+    /// render the snapshot in the Prometheus text exposition format, every sample has a `runtime` label with the given value
+    pub fn to_prometheus(&self, runtime: &str) -> String {
+        use std::fmt::Write;
+
+        let runtime = escape_label(runtime);
+        let mut out = String::new();
+        let plain = |value: f64| vec![(String::new(), value)];
+        let per_realm = |value: fn(&RealmMetrics) -> usize| -> Vec<(String, f64)> {
+            self.realms
+                .iter()
+                .map(|r| {
+                    (
+                        format!(",realm=\"{}\"", escape_label(r.realm_id.as_str())),
+                        value(r) as f64,
+                    )
+                })
+                .collect()
+        };
+
+        write_metric(
+            &mut out,
+            &runtime,
+            "pending_jobs",
+            "gauge",
+            "jobs which were added to the event queue and are not done yet",
+            plain(self.pending_jobs as f64),
+        );
+        if let Some(max_queue_size) = self.max_queue_size {
+            write_metric(
+                &mut out,
+                &runtime,
+                "max_queue_size",
+                "gauge",
+                "the max number of pending jobs",
+                plain(max_queue_size as f64),
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP quickjs_job_latency_seconds the time from adding a job until it is done"
+        );
+        let _ = writeln!(out, "# TYPE quickjs_job_latency_seconds histogram");
+        for bucket in &self.job_latency.buckets {
+            let _ = writeln!(
+                out,
+                "quickjs_job_latency_seconds_bucket{{runtime=\"{runtime}\",le=\"{}\"}} {}",
+                bucket.le, bucket.count
+            );
+        }
+        let _ = writeln!(
+            out,
+            "quickjs_job_latency_seconds_bucket{{runtime=\"{runtime}\",le=\"+Inf\"}} {}",
+            self.job_latency.count
+        );
+        let _ = writeln!(
+            out,
+            "quickjs_job_latency_seconds_sum{{runtime=\"{runtime}\"}} {}",
+            self.job_latency.sum_seconds
+        );
+        let _ = writeln!(
+            out,
+            "quickjs_job_latency_seconds_count{{runtime=\"{runtime}\"}} {}",
+            self.job_latency.count
+        );
+
+        let memory = &self.memory;
+        let gauges = [
+            (
+                "memory_malloc_bytes",
+                "the bytes allocated by the runtime",
+                memory.malloc_size,
+            ),
+            (
+                "memory_limit_bytes",
+                "the memory limit of the runtime",
+                memory.malloc_limit,
+            ),
+            (
+                "memory_used_bytes",
+                "the bytes used by the runtime",
+                memory.memory_used_size,
+            ),
+            ("objects", "the objects of the runtime", memory.obj_count),
+            ("strings", "the strings of the runtime", memory.str_count),
+            (
+                "js_functions",
+                "the bytecode functions of the runtime",
+                memory.js_func_count,
+            ),
+        ];
+        for (name, help, value) in gauges {
+            write_metric(&mut out, &runtime, name, "gauge", help, plain(value as f64));
+        }
+        write_metric(
+            &mut out,
+            &runtime,
+            "gc_runs_total",
+            "counter",
+            "the garbage collections",
+            plain(self.gc_runs as f64),
+        );
+        write_metric(
+            &mut out,
+            &runtime,
+            "gc_seconds_total",
+            "counter",
+            "the time spent in garbage collections",
+            plain(self.gc_seconds_total),
+        );
+
+        let realm_gauges: [RealmGauge; 5] = [
+            ("realm_timeouts", "the pending timeouts of a realm", |r| {
+                r.timeouts
+            }),
+            ("realm_intervals", "the intervals of a realm", |r| {
+                r.intervals
+            }),
+            (
+                "realm_cached_promises",
+                "the promises of a realm which are resolved from rust",
+                |r| r.cached_promises,
+            ),
+            (
+                "realm_cached_objects",
+                "the objects of a realm which are cached for rust",
+                |r| r.cached_objects,
+            ),
+            (
+                "realm_proxy_instances",
+                "the proxy instances of a realm",
+                |r| r.proxy_instances,
+            ),
+        ];
+        for (name, help, value) in realm_gauges {
+            write_metric(&mut out, &runtime, name, "gauge", help, per_realm(value));
+        }
+
+        if let Some(cache) = &self.bytecode_cache {
+            write_metric(
+                &mut out,
+                &runtime,
+                "bytecode_cache_hits_total",
+                "counter",
+                "the scripts which were read from the bytecode cache",
+                plain(cache.hits as f64),
+            );
+            write_metric(
+                &mut out,
+                &runtime,
+                "bytecode_cache_misses_total",
+                "counter",
+                "the scripts which were compiled because they were not cached",
+                plain(cache.misses as f64),
+            );
+        }
+        out
+    }
+}
+
+// write the help, the type and the samples of a metric, the labels of a sample start with a comma
+#[cfg(feature = "prometheus")]
+fn write_metric(
+    out: &mut String,
+    runtime: &str,
+    name: &str,
+    kind: &str,
+    help: &str,
+    samples: Vec<(String, f64)>,
+) {
+    use std::fmt::Write;
+
+    let _ = writeln!(out, "# HELP quickjs_{name} {help}");
+    let _ = writeln!(out, "# TYPE quickjs_{name} {kind}");
+    for (labels, value) in samples {
+        let _ = writeln!(
+            out,
+            "quickjs_{name}{{runtime=\"{runtime}\"{labels}}} {value}"
+        );
+    }
+}
+
+#[cfg(feature = "prometheus")]
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::metrics::{LatencyHistogram, JOB_LATENCY_BUCKETS};
+    use crate::jsutils::Script;
+    use std::time::Duration;
+
+    #[test]
+    fn test_latency_histogram() {
+        let histogram = LatencyHistogram::default();
+        histogram.record(Duration::from_micros(50));
+        histogram.record(Duration::from_millis(3));
+        histogram.record(Duration::from_secs(10));
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.buckets.len(), JOB_LATENCY_BUCKETS.len());
+        assert_eq!(snapshot.buckets[0].count, 1);
+        // the buckets are cumulative
+        assert_eq!(snapshot.buckets[3].count, 2);
+        assert_eq!(snapshot.buckets[JOB_LATENCY_BUCKETS.len() - 1].count, 2);
+        assert_eq!(snapshot.count, 3);
+        assert!(snapshot.sum_seconds > 10.0);
+    }
+
+    #[test]
+    fn test_metrics_snapshot() {
+        let rt = QuickJsRuntimeBuilder::new().max_queue_size(64).build();
+        rt.create_realm("other").expect("could not create realm");
+        rt.eval_sync(
+            Some("other"),
+            Script::new(
+                "test_metrics.js",
+                "setTimeout(() => {}, 10000); setInterval(() => {}, 10000);",
+            ),
+        )
+        .expect("script failed");
+        rt.gc_sync();
+
+        let snapshot = rt.metrics_snapshot();
+        assert_eq!(snapshot.max_queue_size, Some(64));
+        assert!(snapshot.jobs_completed >= 3);
+        assert_eq!(snapshot.job_latency.count, snapshot.jobs_completed);
+        assert!(snapshot.gc_runs >= 1);
+        assert!(snapshot.memory.malloc_size > 0);
+        assert!(snapshot.bytecode_cache.is_none());
+        let ids: Vec<&str> = snapshot
+            .realms
+            .iter()
+            .map(|r| r.realm_id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["__main__", "other"]);
+        assert_eq!(snapshot.realms[1].timeouts, 1);
+        assert_eq!(snapshot.realms[1].intervals, 1);
+        assert_eq!(snapshot.realms[0].timeouts, 0);
+
+        let json = serde_json::to_value(&snapshot).expect("could not serialize");
+        assert!(json["job_latency"]["buckets"].is_array());
+
+        #[cfg(feature = "prometheus")]
+        {
+            let text = snapshot.to_prometheus("tenant \"a\"");
+            assert!(text.contains("# TYPE quickjs_job_latency_seconds histogram"));
+            assert!(text.contains(
+                "quickjs_realm_timeouts{runtime=\"tenant \\\"a\\\"\",realm=\"other\"} 1"
+            ));
+            assert!(text.contains(
+                "quickjs_job_latency_seconds_bucket{runtime=\"tenant \\\"a\\\"\",le=\"+Inf\"}"
+            ));
+        }
+    }
+}
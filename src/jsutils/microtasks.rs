@@ -0,0 +1,349 @@
+//! a budget for the promise jobs (microtasks) which run after a task, see [microtask_budget](crate::builder::QuickJsRuntimeBuilder::microtask_budget)
+//!
+//! after every task the worker runs the promise jobs until none are pending, each of those jobs may add new ones so a script like
+//! `function loop() { Promise.resolve().then(loop); }` keeps the worker busy forever without any single job running long enough
+//! to be interrupted by a [script_timeout](crate::builder::QuickJsRuntimeBuilder::script_timeout), timers and other tasks never run again
+//!
+//! with a budget a drain which ran more than [max_jobs](MicrotaskBudget::max_jobs) jobs or longer than [max_duration](MicrotaskBudget::max_duration)
+//! is stopped, what happens next depends on the [MicrotaskBudgetMode]:
+//!
+//! * [Fairness](MicrotaskBudgetMode::Fairness) continues the remaining jobs in a new task after the due timers and the tasks which were already added,
+//!   a drain which is long but finite (e.g. thousands of awaits in a loop) still completes
+//! * [Strict](MicrotaskBudgetMode::Strict) interrupts the remaining jobs, their promises are rejected, and passes a `MicrotaskBudgetExceeded` error
+//!   to the [uncaught_error_hook](crate::builder::QuickJsRuntimeBuilder::uncaught_error_hook)
+//!
+//! in both modes the [hook](MicrotaskBudget::hook) of the budget is called with a [MicrotaskBudgetEvent] and a warning is logged
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::microtasks::MicrotaskBudget;
+//! use quickjs_runtime::jsutils::Script;
+//! use std::time::Duration;
+//! let rt = QuickJsRuntimeBuilder::new()
+//!     .microtask_budget(MicrotaskBudget::new().max_jobs(1000))
+//!     .build();
+//! rt.eval_sync(None, Script::new("starve.js", r#"
+//!     globalThis.fired = false;
+//!     setTimeout(() => fired = true, 0);
+//!     function loop() { if (!fired) Promise.resolve().then(loop); }
+//!     loop();
+//! "#)).expect("script failed");
+//! std::thread::sleep(Duration::from_millis(100));
+//! assert!(rt.eval_sync(None, Script::new("starve.js", "fired")).expect("script failed").get_bool());
+//! ```
+
+use crate::jsutils::{uncaught, JsError};
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use hirofa_utils::eventloop::EventLoop;
+use std::cell::Cell;
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// a hook which is called when a drain exceeded its budget, it runs in the worker thread of the runtime and should not block
+pub type MicrotaskBudgetHook = Arc<dyn Fn(&MicrotaskBudgetEvent) + Send + Sync>;
+
+/// what happens with the remaining jobs of a drain which exceeded its budget
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MicrotaskBudgetMode {
+    /// continue the remaining jobs in a new task
+    Fairness,
+    /// interrupt the remaining jobs
+    Strict,
+}
+
+/// a drain which exceeded its budget
+#[derive(Clone, Debug)]
+pub struct MicrotaskBudgetEvent {
+    /// the number of jobs which ran in the drain
+    pub jobs: usize,
+    /// how long the drain ran
+    pub elapsed: Duration,
+    pub mode: MicrotaskBudgetMode,
+}
+
+/// the max number of jobs and the max duration of a drain of the promise jobs
+#[derive(Clone)]
+pub struct MicrotaskBudget {
+    max_jobs: Option<usize>,
+    max_duration: Option<Duration>,
+    mode: MicrotaskBudgetMode,
+    hook: Option<MicrotaskBudgetHook>,
+}
+
+impl Default for MicrotaskBudget {
+    fn default() -> Self {
+        Self {
+            max_jobs: Some(100_000),
+            max_duration: Some(Duration::from_secs(1)),
+            mode: MicrotaskBudgetMode::Fairness,
+            hook: None,
+        }
+    }
+}
+
+impl Debug for MicrotaskBudget {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MicrotaskBudget")
+            .field("max_jobs", &self.max_jobs)
+            .field("max_duration", &self.max_duration)
+            .field("mode", &self.mode)
+            .field("hook", &self.hook.is_some())
+            .finish()
+    }
+}
+
+impl MicrotaskBudget {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// the max number of jobs of a drain, 100000 by default, 0 for no max
+    pub fn max_jobs(mut self, max_jobs: usize) -> Self {
+        self.max_jobs = (max_jobs > 0).then_some(max_jobs);
+        self
+    }
+    /// the max duration of a drain, 1 second by default, zero for no max
+    pub fn max_duration(mut self, max_duration: Duration) -> Self {
+        self.max_duration = (!max_duration.is_zero()).then_some(max_duration);
+        self
+    }
+    /// interrupt the remaining jobs instead of continuing them in a new task
+    pub fn strict(mut self) -> Self {
+        self.mode = MicrotaskBudgetMode::Strict;
+        self
+    }
+    pub fn get_mode(&self) -> MicrotaskBudgetMode {
+        self.mode
+    }
+    /// call a hook when a drain exceeded the budget
+    pub fn hook<H>(mut self, hook: H) -> Self
+    where
+        H: Fn(&MicrotaskBudgetEvent) + Send + Sync + 'static,
+    {
+        self.hook = Some(Arc::new(hook));
+        self
+    }
+
+    fn exceeded(&self, jobs: usize, elapsed: Duration) -> bool {
+        self.max_jobs.map(|max| jobs > max).unwrap_or(false)
+            || self.max_duration.map(|max| elapsed >= max).unwrap_or(false)
+    }
+}
+
+pub(crate) struct MicrotaskLimiter {
+    budget: MicrotaskBudget,
+    // a task which continues the remaining jobs was added and did not run yet
+    resume_scheduled: Cell<bool>,
+    // the remaining jobs of a strict drain are being interrupted
+    aborting: Cell<bool>,
+}
+
+impl MicrotaskLimiter {
+    pub(crate) fn new(budget: MicrotaskBudget) -> Self {
+        Self {
+            budget,
+            resume_scheduled: Cell::new(false),
+            aborting: Cell::new(false),
+        }
+    }
+}
+
+/// true while the remaining jobs of a strict drain are interrupted, checked by the interrupt handler
+pub(crate) fn is_aborting(q_js_rt: &QuickJsRuntimeAdapter) -> bool {
+    q_js_rt
+        .microtask_limiter
+        .as_ref()
+        .map(|limiter| limiter.aborting.get())
+        .unwrap_or(false)
+}
+
+/// counts the jobs of a drain, see [QuickJsRuntimeAdapter::run_pending_jobs_if_any]
+pub(crate) struct MicrotaskDrain {
+    jobs: usize,
+    started: Instant,
+    aborting: bool,
+}
+
+impl MicrotaskDrain {
+    pub(crate) fn start() -> Self {
+        Self {
+            jobs: 0,
+            started: Instant::now(),
+            aborting: false,
+        }
+    }
+
+    /// count the job which is about to run, returns false when the drain should stop
+    pub(crate) fn next(&mut self, q_js_rt: &QuickJsRuntimeAdapter) -> bool {
+        let limiter = match q_js_rt.microtask_limiter.as_ref() {
+            Some(limiter) => limiter,
+            None => return true,
+        };
+        self.jobs += 1;
+        if self.aborting || !limiter.budget.exceeded(self.jobs, self.started.elapsed()) {
+            return true;
+        }
+        let event = MicrotaskBudgetEvent {
+            jobs: self.jobs - 1,
+            elapsed: self.started.elapsed(),
+            mode: limiter.budget.mode,
+        };
+        log::warn!(
+            "the promise jobs exceeded their budget after {} jobs in {:?}",
+            event.jobs,
+            event.elapsed
+        );
+        if let Some(hook) = &limiter.budget.hook {
+            hook(&event);
+        }
+        match limiter.budget.mode {
+            MicrotaskBudgetMode::Fairness => {
+                if !limiter.resume_scheduled.replace(true) {
+                    // a timer and not a task so the due timers run first
+                    EventLoop::add_timeout(
+                        || {
+                            QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+                                if let Some(limiter) = q_js_rt.microtask_limiter.as_ref() {
+                                    limiter.resume_scheduled.set(false);
+                                }
+                                q_js_rt.run_pending_jobs_if_any();
+                            })
+                        },
+                        Duration::ZERO,
+                    );
+                }
+                false
+            }
+            MicrotaskBudgetMode::Strict => {
+                // the remaining jobs still run but are interrupted at the first check of the interrupt handler,
+                // that rejects their promises so they do not add new jobs
+                self.aborting = true;
+                limiter.aborting.set(true);
+                let err = JsError::new_microtask_budget_exceeded(event.jobs, event.elapsed);
+                uncaught::report_uncaught_q(q_js_rt.get_main_realm(), "microtasks", &err);
+                true
+            }
+        }
+    }
+}
+
+impl Drop for MicrotaskDrain {
+    fn drop(&mut self) {
+        if self.aborting {
+            QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+                if let Some(limiter) = q_js_rt.microtask_limiter.as_ref() {
+                    limiter.aborting.set(false);
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::microtasks::{MicrotaskBudget, MicrotaskBudgetMode};
+    use crate::jsutils::{JsErrorKind, Script};
+    use crate::values::JsValueFacade;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    const STARVE_SCRIPT: &str = r#"
+        globalThis.fired = false;
+        globalThis.ticks = 0;
+        setTimeout(() => fired = true, 0);
+        function loop() { if (!fired) { ticks++; Promise.resolve().then(loop); } }
+        loop();
+    "#;
+
+    #[test]
+    fn test_microtask_fairness() {
+        let events = Arc::new(Mutex::new(vec![]));
+        let events2 = events.clone();
+        let rt = QuickJsRuntimeBuilder::new()
+            .microtask_budget(
+                MicrotaskBudget::new()
+                    .max_jobs(1000)
+                    .hook(move |event| events2.lock().unwrap().push(event.clone())),
+            )
+            .build();
+        rt.eval_sync(None, Script::new("fairness.js", STARVE_SCRIPT))
+            .expect("script failed");
+
+        // the timer runs between two drains and stops the loop
+        let started = Instant::now();
+        while !rt
+            .eval_sync(None, Script::new("fairness.js", "fired"))
+            .expect("script failed")
+            .get_bool()
+        {
+            assert!(started.elapsed() < Duration::from_secs(5), "timer starved");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        {
+            // the hook locks the events too, so the guard is released before the next drain
+            let events = events.lock().unwrap();
+            assert!(!events.is_empty());
+            assert_eq!(events[0].jobs, 1000);
+            assert_eq!(events[0].mode, MicrotaskBudgetMode::Fairness);
+        }
+
+        // a long but finite drain completes
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "fairness.js",
+                    "(async () => { let s = 0; for (let i = 0; i < 10000; i++) { await null; s++; } return s; })()",
+                ),
+            )
+            .expect("script failed");
+        match res {
+            JsValueFacade::JsPromise { cached_promise } => {
+                let p_res = cached_promise
+                    .get_promise_result_sync()
+                    .expect("promise timed out")
+                    .expect("promise rejected");
+                assert_eq!(p_res.get_i32(), 10000);
+            }
+            _ => panic!("not a promise"),
+        }
+    }
+
+    #[test]
+    fn test_microtask_strict() {
+        let errors = Arc::new(Mutex::new(vec![]));
+        let errors2 = errors.clone();
+        let rt = QuickJsRuntimeBuilder::new()
+            .microtask_budget(MicrotaskBudget::new().max_jobs(1000).strict())
+            .uncaught_error_hook(move |_realm_id, source, err| {
+                errors2
+                    .lock()
+                    .unwrap()
+                    .push((source.to_string(), err.kind()));
+            })
+            .build();
+        rt.eval_sync(None, Script::new("strict.js", STARVE_SCRIPT))
+            .expect("script failed");
+
+        // the loop was interrupted so it no longer runs
+        let ticks = rt
+            .eval_sync(None, Script::new("strict.js", "ticks"))
+            .expect("script failed")
+            .get_i32();
+        assert!(ticks >= 1000);
+        std::thread::sleep(Duration::from_millis(50));
+        let res = rt
+            .eval_sync(None, Script::new("strict.js", "ticks"))
+            .expect("script failed");
+        assert_eq!(res.get_i32(), ticks);
+
+        let errors = errors.lock().unwrap();
+        let (source, kind) = errors
+            .iter()
+            .find(|(source, _)| source == "microtasks")
+            .expect("no budget error");
+        assert_eq!(source, "microtasks");
+        assert_eq!(*kind, JsErrorKind::MicrotaskBudgetExceeded);
+    }
+}
@@ -1,14 +1,48 @@
 //! This contains abstract traits and structs for use with different javascript runtimes
 //! the Adapter traits are use in the worker thread (EventLoop) of the Runtime and thus are not Send, they should never leave the thread
 //! The facade classes are for use outside the worker thread, they are Send
+//! the commonly used parts are re-exported in the [prelude](crate::prelude), this path may change in a minor version
 //!
 
+use crate::values::JsValueFacade;
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::fmt::{Debug, Display, Error, Formatter};
+use std::time::Duration;
 
+pub mod asyncstacks;
+pub mod binding;
+pub mod bytecodecache;
+pub mod capabilities;
+pub mod compileaudit;
+pub mod debugdump;
+pub mod executor;
 pub mod helper_tasks;
+pub mod idle;
+pub mod imports;
+pub mod isolation;
+pub mod jobcontext;
+pub mod jobqueue;
 pub mod jsproxies;
+pub mod memoize;
+pub mod metrics;
+pub mod microtasks;
+pub mod modulecache;
+pub mod modulegraph;
 pub mod modules;
+pub mod preload;
 pub mod promises;
+pub mod realmhandle;
+pub mod redaction;
+pub mod remoteref;
+pub mod startup;
+pub mod suspend;
+pub mod syncbridge;
+pub mod taskscope;
+pub mod timers;
+pub mod transaction;
+pub mod uncaught;
+pub mod validation;
 
 pub trait ScriptPreProcessor {
     fn process(&self, script: &mut Script) -> Result<(), JsError>;
@@ -52,19 +86,46 @@ impl Display for JsValueType {
     }
 }
 
-#[derive(Debug)]
+/// the kind of a [JsError], the errors which are raised by the runtime itself have their own kind so callers can match on it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JsErrorKind {
+    /// an error which was thrown by script or returned by native code
+    Error,
+    /// a value or cached object was used after the context of its realm was destroyed
+    ContextDestroyed,
+    /// the promise jobs of a task exceeded a strict budget, see [microtasks]
+    MicrotaskBudgetExceeded,
+    /// an allocation failed because of the [memory_limit](crate::builder::QuickJsRuntimeBuilder::memory_limit), see [memorypressure](crate::features::memorypressure)
+    OutOfMemory,
+}
+
 pub struct JsError {
+    kind: JsErrorKind,
     name: String,
     message: String,
     stack: String,
+    // the original message and stack when they were changed by the redaction hook
+    unredacted: Option<Box<(String, String)>>,
+    // what script threw, when it was captured
+    thrown: Option<Box<ThrownDetails>>,
+}
+
+struct ThrownDetails {
+    // the thrown value when it was not an instance of Error
+    value: Option<JsValueFacade>,
+    // the custom (enumerable) props of a thrown Error, e.g. code
+    properties: serde_json::Map<String, serde_json::Value>,
 }
 
 impl JsError {
     pub fn new(name: String, message: String, stack: String) -> Self {
         Self {
+            kind: JsErrorKind::Error,
             name,
             message,
             stack,
+            unredacted: None,
+            thrown: None,
         }
     }
     pub fn new_str(err: &str) -> Self {
@@ -72,11 +133,128 @@ impl JsError {
     }
     pub fn new_string(err: String) -> Self {
         JsError {
+            kind: JsErrorKind::Error,
             name: "Error".to_string(),
             message: err,
             stack: "".to_string(),
+            unredacted: None,
+            thrown: None,
+        }
+    }
+    /// apply the redaction hook of the runtime to the message and stack, errors are redacted only once
+    pub(crate) fn redact(mut self) -> Self {
+        if self.unredacted.is_some() {
+            return self;
+        }
+        let message = match redaction::redact(self.message.as_str()) {
+            Cow::Owned(message) => Some(message),
+            Cow::Borrowed(_) => None,
+        };
+        let stack = match redaction::redact(self.stack.as_str()) {
+            Cow::Owned(stack) => Some(stack),
+            Cow::Borrowed(_) => None,
+        };
+        if message.is_none() && stack.is_none() {
+            return self;
+        }
+        let original_message = match message {
+            Some(message) => std::mem::replace(&mut self.message, message),
+            None => self.message.clone(),
+        };
+        let original_stack = match stack {
+            Some(stack) => std::mem::replace(&mut self.stack, stack),
+            None => self.stack.clone(),
+        };
+        self.unredacted = Some(Box::new((original_message, original_stack)));
+        self
+    }
+    /// check if the message or stack were changed by the redaction hook
+    pub fn is_redacted(&self) -> bool {
+        self.unredacted.is_some()
+    }
+    /// the message as it is seen by script, this is the message before redaction
+    pub(crate) fn get_script_message(&self) -> &str {
+        match &self.unredacted {
+            Some(unredacted) => unredacted.0.as_str(),
+            None => self.message.as_str(),
+        }
+    }
+    /// the stack as it is seen by script, this is the stack before redaction
+    pub(crate) fn get_script_stack(&self) -> &str {
+        match &self.unredacted {
+            Some(unredacted) => unredacted.1.as_str(),
+            None => self.stack.as_str(),
         }
     }
+    /// the error which is returned when a value or cached object is used after the context of its realm was destroyed
+    pub fn new_context_destroyed() -> Self {
+        Self::new(
+            "ContextDestroyed".to_string(),
+            "the context of this value was destroyed".to_string(),
+            "".to_string(),
+        )
+        .with_kind(JsErrorKind::ContextDestroyed)
+    }
+    pub fn is_context_destroyed(&self) -> bool {
+        self.kind == JsErrorKind::ContextDestroyed
+    }
+    /// the error which is returned when a [RealmHandle](crate::jsutils::realmhandle::RealmHandle) is used after its realm was dropped
+    pub fn new_dead_realm(realm_id: &str) -> Self {
+        Self::new(
+            "DeadRealm".to_string(),
+            format!("realm {realm_id} was dropped"),
+            "".to_string(),
+        )
+    }
+    pub fn is_dead_realm(&self) -> bool {
+        self.name.eq("DeadRealm")
+    }
+    /// the error which is returned when a job can not be added because the event queue is full, see [jobqueue]
+    pub fn new_queue_full(max_size: usize) -> Self {
+        Self::new(
+            "QueueFull".to_string(),
+            format!("the event queue is full, it holds at most {max_size} jobs"),
+            "".to_string(),
+        )
+    }
+    pub fn is_queue_full(&self) -> bool {
+        self.name.eq("QueueFull")
+    }
+    pub fn kind(&self) -> JsErrorKind {
+        self.kind
+    }
+    pub(crate) fn with_kind(mut self, kind: JsErrorKind) -> Self {
+        self.kind = kind;
+        self
+    }
+    /// true for the error which is thrown when an allocation failed because of the [memory_limit](crate::builder::QuickJsRuntimeBuilder::memory_limit),
+    /// see [memorypressure](crate::features::memorypressure)
+    pub fn is_out_of_memory(&self) -> bool {
+        self.kind == JsErrorKind::OutOfMemory
+    }
+    /// the error which is reported when the promise jobs of a task exceeded a strict budget, see [microtasks]
+    pub fn new_microtask_budget_exceeded(jobs: usize, elapsed: Duration) -> Self {
+        Self::new(
+            "MicrotaskBudgetExceeded".to_string(),
+            format!("the promise jobs were interrupted after {jobs} jobs in {elapsed:?}"),
+            "".to_string(),
+        )
+        .with_kind(JsErrorKind::MicrotaskBudgetExceeded)
+    }
+    pub fn is_microtask_budget_exceeded(&self) -> bool {
+        self.kind == JsErrorKind::MicrotaskBudgetExceeded
+    }
+    /// the error which is returned when a value is nested deeper than the [max_conversion_depth](crate::builder::QuickJsRuntimeBuilder::max_conversion_depth)
+    pub fn new_depth_exceeded(max_depth: usize, path: &str) -> Self {
+        Self::new(
+            "DepthExceeded".to_string(),
+            format!("value is nested deeper than {max_depth} levels at {path}"),
+            "".to_string(),
+        )
+    }
+    pub fn is_depth_exceeded(&self) -> bool {
+        self.name.eq("DepthExceeded")
+    }
     pub fn get_message(&self) -> &str {
         self.message.as_str()
     }
@@ -86,6 +264,53 @@ impl JsError {
     pub fn get_name(&self) -> &str {
         self.name.as_str()
     }
+    fn thrown_mut(&mut self) -> &mut ThrownDetails {
+        self.thrown.get_or_insert_with(|| {
+            Box::new(ThrownDetails {
+                value: None,
+                properties: serde_json::Map::new(),
+            })
+        })
+    }
+    pub(crate) fn with_thrown_value(mut self, value: JsValueFacade) -> Self {
+        self.thrown_mut().value = Some(value);
+        self
+    }
+    pub(crate) fn with_properties(
+        mut self,
+        properties: serde_json::Map<String, serde_json::Value>,
+    ) -> Self {
+        if !properties.is_empty() {
+            self.thrown_mut().properties = properties;
+        }
+        self
+    }
+    /// the value which was thrown by script when that was not an instance of Error, e.g. throw {code: 1};
+    pub fn get_thrown_value(&self) -> Option<&JsValueFacade> {
+        self.thrown.as_ref().and_then(|t| t.value.as_ref())
+    }
+    /// the custom props of a thrown Error, e.g. throw Object.assign(new Error('x'), {code: 7});
+    pub fn get_properties(&self) -> Option<&serde_json::Map<String, serde_json::Value>> {
+        match &self.thrown {
+            Some(thrown) if !thrown.properties.is_empty() => Some(&thrown.properties),
+            _ => None,
+        }
+    }
+    pub fn get_property(&self, name: &str) -> Option<&serde_json::Value> {
+        self.get_properties().and_then(|props| props.get(name))
+    }
+}
+
+impl Debug for JsError {
+    // the unredacted message and stack are never printed
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JsError")
+            .field("kind", &self.kind)
+            .field("name", &self.name)
+            .field("message", &self.message)
+            .field("stack", &self.stack)
+            .finish()
+    }
 }
 
 impl std::error::Error for JsError {
@@ -152,6 +377,56 @@ impl Script {
     pub fn get_map(&self) -> Option<&str> {
         self.map.as_deref()
     }
+    /// create a template script, for every param a variable named `$name` is bound in the scope of the script
+    /// the params are never textually substituted in the code so they need no quoting or escaping
+    /// referencing a `$name` for which no param was supplied results in a ReferenceError, unused params are allowed
+    /// the script runs as the body of a function, the value it returns is the result
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+    /// use quickjs_runtime::jsutils::Script;
+    /// use quickjs_runtime::values::JsValueConvertable;
+    /// use std::collections::HashMap;
+    /// let rt = QuickJsRuntimeBuilder::new().build();
+    /// let mut params = HashMap::new();
+    /// params.insert("name", "'); throw Error('injected'); ('".to_js_value_facade());
+    /// let template = Script::template("greet.js", "return `hello ${$name}`;", params);
+    /// let res = rt.eval_template_sync(None, template).expect("script failed");
+    /// assert_eq!(res.get_str(), "hello '); throw Error('injected'); ('");
+    /// ```
+    pub fn template(
+        absolute_path: &str,
+        template_code: &str,
+        params: HashMap<&str, JsValueFacade>,
+    ) -> ScriptTemplate {
+        let mut params: Vec<(String, JsValueFacade)> = params
+            .into_iter()
+            .map(|(name, value)| (name.trim_start_matches('$').to_string(), value))
+            .collect();
+        params.sort_by(|a, b| a.0.cmp(&b.0));
+        ScriptTemplate {
+            script: Script::new(absolute_path, template_code),
+            params,
+        }
+    }
+}
+
+/// a Script with params which are bound as `$name` variables when evaluated, see [Script::template]
+pub struct ScriptTemplate {
+    script: Script,
+    params: Vec<(String, JsValueFacade)>,
+}
+
+impl ScriptTemplate {
+    pub fn get_script(&self) -> &Script {
+        &self.script
+    }
+    pub fn get_param_names(&self) -> Vec<&str> {
+        self.params.iter().map(|p| p.0.as_str()).collect()
+    }
+    pub(crate) fn into_parts(self) -> (Script, Vec<(String, JsValueFacade)>) {
+        (self.script, self.params)
+    }
 }
 
 impl Clone for Script {
@@ -0,0 +1,299 @@
+//! loading modules again, see [invalidate_module](crate::quickjsrealmadapter::QuickJsRealmAdapter::invalidate_module),
+//! [clear_module_cache](crate::facades::QuickJsRuntimeFacade::clear_module_cache) and [module_cache](crate::builder::QuickJsRuntimeBuilder::module_cache)
+//!
+//! quickjs keeps every module which was loaded in a realm and never loads a module with the same name again, an invalidated module
+//! is therefore loaded under a new name, its path with a `#reload=N` suffix
+//!
+//! * the module loaders, the module resolver, the [module graph](crate::jsutils::modulegraph) and the debug records see the path without the suffix,
+//!   script sees the name with the suffix, e.g. in `import.meta.url` and in stack traces
+//! * the modules which import an invalidated module (directly or indirectly) are invalidated as well, so the next import of any of them sees the new version
+//! * modules which already imported the old version keep using it, e.g. a function which was imported before keeps working with the old module
+//! * modules of a compiled module loader are not loaded again, their name is part of their bytecode
+//! * when the module cache is disabled every import of a script loads the imported module and its imports again, the modules
+//!   of one such import are loaded once so cyclic imports work
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::modules::ScriptModuleLoader;
+//! use quickjs_runtime::jsutils::Script;
+//! use quickjs_runtime::quickjsrealmadapter::QuickJsRealmAdapter;
+//! use std::sync::atomic::{AtomicI32, Ordering};
+//! use std::sync::Arc;
+//! struct ConfigLoader {
+//!     value: Arc<AtomicI32>,
+//! }
+//! impl ScriptModuleLoader for ConfigLoader {
+//!     fn normalize_path(&self, _realm: &QuickJsRealmAdapter, _ref_path: &str, path: &str) -> Option<String> {
+//!         (path == "config").then(|| path.to_string())
+//!     }
+//!     fn load_module(&self, _realm: &QuickJsRealmAdapter, _absolute_path: &str) -> String {
+//!         format!("export const value = {};", self.value.load(Ordering::SeqCst))
+//!     }
+//! }
+//! let value = Arc::new(AtomicI32::new(1));
+//! let rt = QuickJsRuntimeBuilder::new().script_module_loader(ConfigLoader { value: value.clone() }).build();
+//! rt.eval_module_sync(None, Script::new("first.mes", "import {value} from 'config'; globalThis.first = value;")).expect("module failed");
+//! value.store(2, Ordering::SeqCst);
+//! rt.clear_module_cache();
+//! rt.eval_module_sync(None, Script::new("second.mes", "import {value} from 'config'; globalThis.second = value;")).expect("module failed");
+//! let res = rt.eval_sync(None, Script::new("res.js", "`${first}:${second}`")).expect("script failed");
+//! assert_eq!(res.get_str(), "1:2");
+//! ```
+
+use crate::jsutils::debugdump;
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use std::collections::{HashMap, HashSet};
+
+const GENERATION_SEPARATOR: &str = "#reload=";
+
+/// the generations of the modules of a realm
+#[derive(Default)]
+pub(crate) struct ModuleCacheState {
+    // the generation of every invalidated module, a module which is not in here is loaded under its own path
+    generations: HashMap<String, u64>,
+    // the paths of the modules which imported a module
+    importers: HashMap<String, HashSet<String>>,
+    // generations are unique in a realm so a module is never loaded under the name of an older version
+    last_generation: u64,
+}
+
+impl ModuleCacheState {
+    fn next_generation(&mut self) -> u64 {
+        self.last_generation += 1;
+        self.last_generation
+    }
+}
+
+fn split_generation(name: &str) -> (&str, Option<u64>) {
+    match name.rsplit_once(GENERATION_SEPARATOR) {
+        Some((path, generation)) => match generation.parse::<u64>() {
+            Ok(generation) => (path, Some(generation)),
+            Err(_) => (name, None),
+        },
+        None => (name, None),
+    }
+}
+
+/// the path of a module without its reload suffix
+pub(crate) fn strip_generation(name: &str) -> &str {
+    split_generation(name).0
+}
+
+/// the name a module is loaded under when an import of `importer` was resolved to `path`
+pub(crate) fn module_name_q(
+    q_js_rt: &QuickJsRuntimeAdapter,
+    realm: &QuickJsRealmAdapter,
+    importer: &str,
+    path: &str,
+    loader_kind: &str,
+) -> String {
+    let (importer_path, importer_generation) = split_generation(importer);
+    let state = &mut *realm.module_cache.borrow_mut();
+    state
+        .importers
+        .entry(path.to_string())
+        .or_default()
+        .insert(importer_path.to_string());
+    if loader_kind == "compiled" {
+        return path.to_string();
+    }
+    let generation = if q_js_rt.module_cache {
+        state.generations.get(path).copied()
+    } else {
+        // the imports of a module which was loaded again share its generation
+        Some(importer_generation.unwrap_or_else(|| state.next_generation()))
+    };
+    match generation {
+        Some(generation) => format!("{path}{GENERATION_SEPARATOR}{generation}"),
+        None => path.to_string(),
+    }
+}
+
+fn invalidate_paths(realm: &QuickJsRealmAdapter, paths: Vec<String>) -> Vec<String> {
+    let state = &mut *realm.module_cache.borrow_mut();
+    let mut invalidated = paths;
+    let mut index = 0;
+    while index < invalidated.len() {
+        if let Some(importers) = state.importers.get(&invalidated[index]) {
+            for importer in importers {
+                if !invalidated.contains(importer) {
+                    invalidated.push(importer.clone());
+                }
+            }
+        }
+        index += 1;
+    }
+    for path in &invalidated {
+        let generation = state.next_generation();
+        state.generations.insert(path.clone(), generation);
+    }
+
+    let mut namespaces = realm.module_namespaces.borrow_mut();
+    namespaces.retain(|path, _| !invalidated.contains(path));
+    drop(namespaces);
+    debugdump::forget_modules(realm, &invalidated);
+    invalidated
+}
+
+/// invalidate a module and the modules which import it, returns the paths of the invalidated modules
+pub(crate) fn invalidate_q(realm: &QuickJsRealmAdapter, path: &str) -> Vec<String> {
+    invalidate_paths(realm, vec![path.to_string()])
+}
+
+/// invalidate every module which was loaded in a realm
+pub(crate) fn clear_q(realm: &QuickJsRealmAdapter) {
+    let mut paths: Vec<String> = debugdump::loaded_modules(realm)
+        .into_iter()
+        .map(|m| m.path)
+        .collect();
+    {
+        let state = realm.module_cache.borrow();
+        for path in state.generations.keys().chain(state.importers.keys()) {
+            if !paths.contains(path) {
+                paths.push(path.clone());
+            }
+        }
+    }
+    invalidate_paths(realm, paths);
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::facades::QuickJsRuntimeFacade;
+    use crate::jsutils::modules::ScriptModuleLoader;
+    use crate::jsutils::Script;
+    use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+    use std::sync::atomic::{AtomicI32, Ordering};
+    use std::sync::Arc;
+
+    struct ReloadLoader {
+        value: Arc<AtomicI32>,
+        loads: Arc<AtomicI32>,
+    }
+
+    impl ScriptModuleLoader for ReloadLoader {
+        fn normalize_path(
+            &self,
+            _realm: &QuickJsRealmAdapter,
+            _ref_path: &str,
+            path: &str,
+        ) -> Option<String> {
+            path.starts_with("mc_")
+                .then(|| format!("modcache/{path}.mes"))
+        }
+        fn load_module(&self, _realm: &QuickJsRealmAdapter, absolute_path: &str) -> String {
+            match absolute_path {
+                "modcache/mc_value.mes" => {
+                    self.loads.fetch_add(1, Ordering::SeqCst);
+                    format!(
+                        "export const value = {};",
+                        self.value.load(Ordering::SeqCst)
+                    )
+                }
+                "modcache/mc_user.mes" => {
+                    "import {value} from 'mc_value'; export const doubled = value * 2;".to_string()
+                }
+                "modcache/mc_cycle_a.mes" => {
+                    "import {b} from 'mc_cycle_b'; export const a = 'a'; export function both() { return a + b; }"
+                        .to_string()
+                }
+                _ => "import {a} from 'mc_cycle_a'; export const b = 'b';".to_string(),
+            }
+        }
+    }
+
+    fn read_global(rt: &QuickJsRuntimeFacade, name: &str) -> i32 {
+        rt.eval_sync(None, Script::new("test_modulecache.js", name))
+            .expect("script failed")
+            .get_i32()
+    }
+
+    #[test]
+    fn test_invalidate_module() {
+        let value = Arc::new(AtomicI32::new(1));
+        let loads = Arc::new(AtomicI32::new(0));
+        let rt = QuickJsRuntimeBuilder::new()
+            .script_module_loader(ReloadLoader {
+                value: value.clone(),
+                loads: loads.clone(),
+            })
+            .build();
+        let import = |name: &str| {
+            rt.eval_module_sync(
+                None,
+                Script::new(
+                    format!("modcache/{name}.mes").as_str(),
+                    format!("import {{value}} from 'mc_value'; import {{doubled}} from 'mc_user'; globalThis.{name} = value; globalThis.{name}_doubled = doubled;").as_str(),
+                ),
+            )
+            .expect("module failed");
+        };
+
+        import("first");
+        assert_eq!(read_global(&rt, "first"), 1);
+
+        value.store(2, Ordering::SeqCst);
+        import("cached");
+        assert_eq!(read_global(&rt, "cached"), 1);
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+
+        let invalidated = rt.invalidate_module(None, "modcache/mc_value.mes");
+        assert_eq!(invalidated[0], "modcache/mc_value.mes");
+        // the dependents are invalidated as well
+        assert!(invalidated.contains(&"modcache/mc_user.mes".to_string()));
+
+        import("reloaded");
+        assert_eq!(read_global(&rt, "reloaded"), 2);
+        assert_eq!(read_global(&rt, "reloaded_doubled"), 4);
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+        // the module which imported the old version keeps it
+        assert_eq!(read_global(&rt, "first_doubled"), 2);
+
+        value.store(3, Ordering::SeqCst);
+        rt.clear_module_cache();
+        import("cleared");
+        assert_eq!(read_global(&rt, "cleared"), 3);
+        assert_eq!(loads.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_disabled_module_cache() {
+        let value = Arc::new(AtomicI32::new(1));
+        let loads = Arc::new(AtomicI32::new(0));
+        let rt = QuickJsRuntimeBuilder::new()
+            .script_module_loader(ReloadLoader {
+                value: value.clone(),
+                loads: loads.clone(),
+            })
+            .module_cache(false)
+            .build();
+        for expected in 1..4 {
+            value.store(expected, Ordering::SeqCst);
+            rt.eval_module_sync(
+                None,
+                Script::new(
+                    format!("modcache/uncached{expected}.mes").as_str(),
+                    "import {value} from 'mc_value'; globalThis.uncached = value;",
+                ),
+            )
+            .expect("module failed");
+            assert_eq!(read_global(&rt, "uncached"), expected);
+        }
+        assert_eq!(loads.load(Ordering::SeqCst), 3);
+
+        // cyclic imports are loaded once per import
+        let res = rt
+            .eval_module_sync(
+                None,
+                Script::new(
+                    "modcache/cycle.mes",
+                    "import {both} from 'mc_cycle_a'; globalThis.both = both();",
+                ),
+            )
+            .and_then(|_| rt.eval_sync(None, Script::new("test_modulecache.js", "both")))
+            .expect("cyclic import failed");
+        assert_eq!(res.get_str(), "ab");
+    }
+}
@@ -0,0 +1,302 @@
+//! the import graph which was resolved in a realm, see [module_graph](crate::facades::QuickJsRuntimeFacade::module_graph)
+//!
+//! when [module_graph](crate::builder::QuickJsRuntimeBuilder::module_graph) is set on the builder every realm records an edge for every import
+//! it resolves, the edge has the importing module, the specifier as written in the import, the resolved name and the loader which provides the module
+//!
+//! * an import which no loader could resolve, or a module which failed to load, is recorded as an edge to an error node
+//! * imports which are resolved while a dynamic `import()` is loaded are flagged as dynamic, this includes the static imports of a dynamically imported module
+//! * an import is recorded once per importer, specifier and kind, the recorded graph grows with the size of the import graph and is only freed with its realm
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::Script;
+//! use futures::executor::block_on;
+//! let rt = QuickJsRuntimeBuilder::new().encoding_module().module_graph().build();
+//! rt.eval_module_sync(None, Script::new("main.mes", "import {encodeHex} from 'quickjs:encoding';")).expect("module failed");
+//! let graph = block_on(rt.module_graph("__main__")).expect("no graph");
+//! assert_eq!(graph.edges[0].resolved.as_deref(), Some("quickjs:encoding"));
+//! assert!(graph.to_dot().contains("\"main.mes\" -> \"quickjs:encoding\""));
+//! ```
+
+use crate::jsutils::JsError;
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use serde::Serialize;
+use std::fmt::Write;
+
+/// a resolved (or failed) import
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct ModuleEdge {
+    /// the name of the importing module
+    pub importer: String,
+    /// the specifier as written in the import
+    pub specifier: String,
+    /// the normalized name of the imported module, None when the import could not be resolved
+    pub resolved: Option<String>,
+    /// the kind of loader which provided the module (compiled, native or script)
+    pub loader: Option<String>,
+    pub dynamic: bool,
+    /// why the import could not be resolved or loaded
+    pub error: Option<String>,
+}
+
+/// a module in a [ModuleGraph]
+#[derive(Serialize, Clone, Debug)]
+pub struct ModuleNode {
+    pub name: String,
+    /// the kind of loader which provided the module, None for modules which were evaluated directly and for error nodes
+    pub loader: Option<String>,
+    /// true for an import which could not be resolved or loaded
+    pub error: bool,
+}
+
+/// the import graph of a realm
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct ModuleGraph {
+    pub nodes: Vec<ModuleNode>,
+    pub edges: Vec<ModuleEdge>,
+}
+
+fn error_node_name(edge: &ModuleEdge) -> String {
+    format!("error: {}", edge.specifier)
+}
+
+fn target_name(edge: &ModuleEdge) -> String {
+    match (&edge.resolved, &edge.error) {
+        (Some(resolved), None) => resolved.clone(),
+        _ => error_node_name(edge),
+    }
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+impl ModuleGraph {
+    fn from_edges(edges: Vec<ModuleEdge>) -> Self {
+        let mut nodes: Vec<ModuleNode> = vec![];
+        let mut add_node = |name: String, loader: Option<String>, error: bool| match nodes
+            .iter_mut()
+            .find(|n| n.name == name)
+        {
+            Some(node) => {
+                if node.loader.is_none() {
+                    node.loader = loader;
+                }
+            }
+            None => nodes.push(ModuleNode {
+                name,
+                loader,
+                error,
+            }),
+        };
+        for edge in &edges {
+            add_node(edge.importer.clone(), None, false);
+            add_node(
+                target_name(edge),
+                edge.loader.clone(),
+                edge.error.is_some() || edge.resolved.is_none(),
+            );
+        }
+        Self { nodes, edges }
+    }
+
+    /// render the graph in the graphviz dot format, dynamic imports are dashed and error nodes are red
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph modules {\n");
+        for node in &self.nodes {
+            let _ = match (node.error, &node.loader) {
+                (true, _) => writeln!(dot, "    {} [shape=box, color=red];", quote(&node.name)),
+                (false, Some(loader)) => writeln!(
+                    dot,
+                    "    {} [tooltip={}];",
+                    quote(&node.name),
+                    quote(loader)
+                ),
+                (false, None) => writeln!(dot, "    {};", quote(&node.name)),
+            };
+        }
+        for edge in &self.edges {
+            let style = if edge.dynamic { ", style=dashed" } else { "" };
+            let _ = writeln!(
+                dot,
+                "    {} -> {} [label={}{}];",
+                quote(&edge.importer),
+                quote(&target_name(edge)),
+                quote(&edge.specifier),
+                style
+            );
+        }
+        dot.push('}');
+        dot.push('\n');
+        dot
+    }
+}
+
+pub(crate) fn init(q_js_rt: &QuickJsRuntimeAdapter) -> Result<(), JsError> {
+    q_js_rt.record_module_graph.set(true);
+    Ok(())
+}
+
+fn is_recording() -> bool {
+    QuickJsRuntimeAdapter::do_with(|q_js_rt| q_js_rt.record_module_graph.get())
+}
+
+/// record a resolved import, or a failed resolution when resolved is None
+pub(crate) fn record_import(
+    realm: &QuickJsRealmAdapter,
+    importer: &str,
+    specifier: &str,
+    resolved: Option<(&str, &str)>,
+    dynamic: bool,
+) {
+    if !is_recording() {
+        return;
+    }
+    let edge = ModuleEdge {
+        importer: importer.to_string(),
+        specifier: specifier.to_string(),
+        resolved: resolved.map(|(name, _)| name.to_string()),
+        loader: resolved.map(|(_, loader)| loader.to_string()),
+        dynamic,
+        error: match resolved {
+            Some(_) => None,
+            None => Some(format!("Module {specifier} was not found")),
+        },
+    };
+    let edges = &mut *realm.module_graph.borrow_mut();
+    if !edges.iter().any(|e| {
+        e.importer == edge.importer && e.specifier == edge.specifier && e.dynamic == edge.dynamic
+    }) {
+        edges.push(edge);
+    }
+}
+
+/// mark the imports of a module which failed to load as errors
+pub(crate) fn record_load_error(realm: &QuickJsRealmAdapter, resolved: &str, error: &str) {
+    if !is_recording() {
+        return;
+    }
+    for edge in realm.module_graph.borrow_mut().iter_mut() {
+        if edge.resolved.as_deref() == Some(resolved) {
+            edge.error = Some(error.to_string());
+        }
+    }
+}
+
+/// get the recorded import graph of a realm
+pub fn module_graph_q(realm: &QuickJsRealmAdapter) -> ModuleGraph {
+    ModuleGraph::from_edges(realm.module_graph.borrow().clone())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::modules::ScriptModuleLoader;
+    use crate::jsutils::Script;
+    use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+    use futures::executor::block_on;
+
+    struct TestLoader {}
+
+    impl ScriptModuleLoader for TestLoader {
+        fn normalize_path(
+            &self,
+            _realm: &QuickJsRealmAdapter,
+            _ref_path: &str,
+            path: &str,
+        ) -> Option<String> {
+            match path {
+                "./a.mes" | "./b.mes" | "./lazy.mes" | "./broken.mes" => {
+                    Some(format!("graph/{}", &path[2..]))
+                }
+                _ => None,
+            }
+        }
+
+        fn load_module(&self, _realm: &QuickJsRealmAdapter, absolute_path: &str) -> String {
+            match absolute_path {
+                "graph/a.mes" => "import {b} from './b.mes'; export const a = b;",
+                "graph/b.mes" => "export const b = 1;",
+                "graph/lazy.mes" => "export const lazy = true;",
+                _ => "export const = ;",
+            }
+            .to_string()
+        }
+    }
+
+    #[test]
+    fn test_module_graph() {
+        let rt = QuickJsRuntimeBuilder::new()
+            .script_module_loader(TestLoader {})
+            .module_graph()
+            .build();
+        rt.eval_module_sync(
+            None,
+            Script::new(
+                "graph/main.mes",
+                "import {a} from './a.mes';\nimport {b} from './b.mes';\nglobalThis.lazy = import('./lazy.mes');",
+            ),
+        )
+        .expect("module failed");
+        assert!(rt
+            .eval_module_sync(
+                None,
+                Script::new("graph/missing.mes", "import {x} from './nope.mes';")
+            )
+            .is_err());
+        assert!(rt
+            .eval_module_sync(
+                None,
+                Script::new("graph/bad.mes", "import {x} from './broken.mes';")
+            )
+            .is_err());
+
+        let graph = block_on(rt.module_graph("__main__")).expect("no graph");
+        let edge = |importer: &str, specifier: &str| {
+            graph
+                .edges
+                .iter()
+                .find(|e| e.importer == importer && e.specifier == specifier)
+                .unwrap_or_else(|| panic!("no edge {importer} -> {specifier}: {graph:?}"))
+        };
+        let main_a = edge("graph/main.mes", "./a.mes");
+        assert_eq!(main_a.resolved.as_deref(), Some("graph/a.mes"));
+        assert_eq!(main_a.loader.as_deref(), Some("script"));
+        assert!(!main_a.dynamic);
+        assert!(!edge("graph/a.mes", "./b.mes").dynamic);
+        assert!(edge("graph/main.mes", "./lazy.mes").dynamic);
+        assert!(edge("graph/missing.mes", "./nope.mes").error.is_some());
+        assert!(edge("graph/bad.mes", "./broken.mes").error.is_some());
+        // b is imported twice but is a single node
+        assert_eq!(
+            graph
+                .nodes
+                .iter()
+                .filter(|n| n.name == "graph/b.mes")
+                .count(),
+            1
+        );
+        assert!(graph.nodes.iter().any(|n| n.error));
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph modules {"));
+        assert!(dot.contains(
+            "\"graph/main.mes\" -> \"graph/lazy.mes\" [label=\"./lazy.mes\", style=dashed];"
+        ));
+        assert!(dot.contains("\"error: ./nope.mes\" [shape=box, color=red];"));
+        serde_json::to_string(&graph).expect("could not serialize");
+
+        // nothing is recorded unless enabled
+        let rt = QuickJsRuntimeBuilder::new()
+            .script_module_loader(TestLoader {})
+            .build();
+        rt.eval_module_sync(
+            None,
+            Script::new("graph/main.mes", "import {a} from './a.mes';"),
+        )
+        .expect("module failed");
+        let graph = block_on(rt.module_graph("__main__")).expect("no graph");
+        assert!(graph.edges.is_empty());
+    }
+}
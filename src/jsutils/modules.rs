@@ -1,8 +1,13 @@
+use crate::jsutils::JsError;
 use crate::quickjsrealmadapter::QuickJsRealmAdapter;
 use crate::quickjsvalueadapter::QuickJsValueAdapter;
 use std::sync::Arc;
+use std::time::Duration;
 
+/// loads the code of script modules, used for static imports and for dynamic `import()` alike
 pub trait ScriptModuleLoader {
+    /// resolve a path to an absolute path, ref_path is the (absolute) path of the importing module or script
+    /// return None when this loader can not load the module, a dynamic import of a module no loader can load rejects
     fn normalize_path(
         &self,
         realm: &QuickJsRealmAdapter,
@@ -10,8 +15,48 @@ pub trait ScriptModuleLoader {
         path: &str,
     ) -> Option<String>;
     fn load_module(&self, realm: &QuickJsRealmAdapter, absolute_path: &str) -> String;
+    /// load a module which may fail, a failed load is retried according to the [RetryPolicy] of the runtime
+    /// implement this instead of just load_module if your loader may fail transiently (e.g. when loading over the network)
+    fn try_load_module(
+        &self,
+        realm: &QuickJsRealmAdapter,
+        absolute_path: &str,
+    ) -> Result<String, JsError> {
+        Ok(self.load_module(realm, absolute_path))
+    }
+}
+
+/// the policy for retrying failed module loads, see [module_load_retry](crate::builder::QuickJsRuntimeBuilder::module_load_retry)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// the max number of times a module load is attempted
+    pub attempts: u32,
+    /// the delay after the first failed attempt, the delay is doubled after every next failed attempt
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// get the delay before the next attempt after a number of failed attempts
+    pub fn get_delay(&self, failed_attempts: u32) -> Duration {
+        self.backoff
+            .saturating_mul(2_u32.saturating_pow(failed_attempts.saturating_sub(1)))
+    }
 }
 
+/// the result of a [module_resolver](crate::builder::QuickJsRuntimeBuilder::module_resolver)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ResolvedSpecifier {
+    /// keep the specifier as it was imported
+    Unchanged,
+    /// replace the specifier, the module loaders get this name instead
+    Rewritten(String),
+    /// fail the import with a reason
+    Rejected(String),
+}
+
+/// rewrites the specifiers of imports before the module loaders see them, called with the path of the importing script and the specifier
+pub type ModuleResolver = Box<dyn Fn(&str, &str) -> ResolvedSpecifier + Send>;
+
 pub trait CompiledModuleLoader {
     fn normalize_path(
         &self,
@@ -30,4 +75,13 @@ pub trait NativeModuleLoader {
         realm: &QuickJsRealmAdapter,
         module_name: &str,
     ) -> Vec<(&str, QuickJsValueAdapter)>;
+    /// get the exports of a module which may fail, a failure fails the import of the module
+    /// implement this instead of just get_module_exports if creating the exports may fail
+    fn try_get_module_exports(
+        &self,
+        realm: &QuickJsRealmAdapter,
+        module_name: &str,
+    ) -> Result<Vec<(&str, QuickJsValueAdapter)>, JsError> {
+        Ok(self.get_module_exports(realm, module_name))
+    }
 }
@@ -0,0 +1,245 @@
+//! loading modules before they are imported, see [preload_modules](crate::facades::QuickJsRuntimeFacade::preload_modules)
+//!
+//! the first import of a module in a realm loads, compiles and evaluates it and all of its imports, preloading moves that work
+//! out of the first request which uses the module
+//!
+//! * modules are imported like a dynamic `import()` of a script named `preload.js`, so the [module_resolver](crate::builder::QuickJsRuntimeBuilder::module_resolver)
+//!   and the module loaders are used as for any other import
+//! * the namespace of a preloaded module is kept by the realm until the realm is dropped
+//! * a module which fails does not fail the other modules, its error is part of the [PreloadReport]
+//! * [pin_module](crate::facades::QuickJsRuntimeFacade::pin_module) keeps the entries of a module in the
+//!   [bytecode cache](crate::builder::QuickJsRuntimeBuilder::bytecode_cache_dir) when other entries are evicted
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::modules::ScriptModuleLoader;
+//! use quickjs_runtime::jsutils::preload::PreloadStatus;
+//! use quickjs_runtime::quickjsrealmadapter::QuickJsRealmAdapter;
+//! use futures::executor::block_on;
+//! struct LibLoader {}
+//! impl ScriptModuleLoader for LibLoader {
+//!     fn normalize_path(&self, _realm: &QuickJsRealmAdapter, _ref_path: &str, path: &str) -> Option<String> {
+//!         (path == "lib").then(|| path.to_string())
+//!     }
+//!     fn load_module(&self, _realm: &QuickJsRealmAdapter, _absolute_path: &str) -> String {
+//!         "export function helper(a) { return a * 2; }".to_string()
+//!     }
+//! }
+//! let rt = QuickJsRuntimeBuilder::new().script_module_loader(LibLoader {}).build();
+//! let report = block_on(rt.preload_modules(None, &["lib"]));
+//! assert!(matches!(report.modules[0].status, PreloadStatus::Loaded));
+//! ```
+
+use crate::jsutils::debugdump;
+use crate::jsutils::imports;
+use crate::jsutils::JsError;
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use std::time::{Duration, Instant};
+
+// the path which relative specifiers are resolved against
+const PRELOAD_REFERRER: &str = "preload.js";
+
+/// the outcome of preloading a module
+#[derive(Debug)]
+pub enum PreloadStatus {
+    /// the module was already loaded in the realm, e.g. by an import of a script
+    AlreadyLoaded,
+    /// the module was loaded and evaluated by the preload
+    Loaded,
+    /// the module could not be resolved, loaded or evaluated
+    Failed(JsError),
+}
+
+/// a module of a [PreloadReport]
+#[derive(Debug)]
+pub struct ModulePreload {
+    pub specifier: String,
+    /// the normalized path of the module, None when the specifier could not be resolved
+    pub path: Option<String>,
+    pub status: PreloadStatus,
+    /// the time it took to load and evaluate the module and its imports
+    pub duration: Duration,
+}
+
+/// the result of [preload_modules](crate::facades::QuickJsRuntimeFacade::preload_modules), in the order of the specifiers
+#[derive(Debug, Default)]
+pub struct PreloadReport {
+    pub modules: Vec<ModulePreload>,
+}
+
+impl PreloadReport {
+    /// the modules which could not be preloaded
+    pub fn failed(&self) -> Vec<&ModulePreload> {
+        self.modules
+            .iter()
+            .filter(|m| matches!(m.status, PreloadStatus::Failed(_)))
+            .collect()
+    }
+    /// the time it took to preload all modules
+    pub fn total_duration(&self) -> Duration {
+        self.modules.iter().map(|m| m.duration).sum()
+    }
+}
+
+/// the normalized path of a module, as it would be imported by a preload
+pub(crate) fn normalize_q(
+    q_js_rt: &QuickJsRuntimeAdapter,
+    realm: &QuickJsRealmAdapter,
+    specifier: &str,
+) -> Result<String, JsError> {
+    q_js_rt
+        .normalize_module_path(realm, PRELOAD_REFERRER, specifier)
+        .map(|(path, _)| path)
+}
+
+fn is_loaded(realm: &QuickJsRealmAdapter, path: &str) -> bool {
+    realm.module_namespaces.borrow().contains_key(path)
+        || debugdump::loaded_modules(realm)
+            .iter()
+            .any(|m| m.path == path)
+}
+
+/// load and evaluate modules in a realm, every module is preloaded even if a previous one failed
+pub(crate) fn preload_modules_q(
+    q_js_rt: &QuickJsRuntimeAdapter,
+    realm: &QuickJsRealmAdapter,
+    specifiers: &[String],
+) -> PreloadReport {
+    let mut report = PreloadReport::default();
+    for specifier in specifiers {
+        let start = Instant::now();
+        let (path, status) = match normalize_q(q_js_rt, realm, specifier) {
+            Err(e) => (None, PreloadStatus::Failed(e)),
+            Ok(path) => {
+                let already_loaded = is_loaded(realm, path.as_str());
+                let status =
+                    match imports::get_namespace_q(q_js_rt, realm, PRELOAD_REFERRER, specifier) {
+                        Ok(_) if already_loaded => PreloadStatus::AlreadyLoaded,
+                        Ok(_) => PreloadStatus::Loaded,
+                        Err(e) => PreloadStatus::Failed(e),
+                    };
+                (Some(path), status)
+            }
+        };
+        report.modules.push(ModulePreload {
+            specifier: specifier.clone(),
+            path,
+            status,
+            duration: start.elapsed(),
+        });
+    }
+    report
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::bytecodecache::CachePolicy;
+    use crate::jsutils::modules::{ResolvedSpecifier, ScriptModuleLoader};
+    use crate::jsutils::preload::PreloadStatus;
+    use crate::jsutils::Script;
+    use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+    use futures::executor::block_on;
+
+    struct PreloadLoader {}
+
+    impl ScriptModuleLoader for PreloadLoader {
+        fn normalize_path(
+            &self,
+            _realm: &QuickJsRealmAdapter,
+            _ref_path: &str,
+            path: &str,
+        ) -> Option<String> {
+            path.starts_with("pre_")
+                .then(|| format!("preload/{path}.mes"))
+        }
+        fn load_module(&self, _realm: &QuickJsRealmAdapter, absolute_path: &str) -> String {
+            match absolute_path {
+                "preload/pre_a.mes" => {
+                    "import {b} from 'pre_b'; globalThis.loads = (globalThis.loads || 0) + 1; export const a = b + 1;"
+                        .to_string()
+                }
+                "preload/pre_bad.mes" => "throw Error('bad module');".to_string(),
+                _ => format!("export const b = 1; export const path = '{absolute_path}';"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_preload_modules() {
+        let rt = QuickJsRuntimeBuilder::new()
+            .script_module_loader(PreloadLoader {})
+            .module_resolver(|_referrer, specifier| {
+                if specifier.eq("pre_blocked") {
+                    ResolvedSpecifier::Rejected("not allowed".to_string())
+                } else {
+                    ResolvedSpecifier::Unchanged
+                }
+            })
+            .build();
+
+        let report =
+            block_on(rt.preload_modules(None, &["pre_a", "missing", "pre_bad", "pre_blocked"]));
+        let statuses: Vec<&PreloadStatus> = report.modules.iter().map(|m| &m.status).collect();
+        assert!(matches!(statuses[0], PreloadStatus::Loaded));
+        assert_eq!(report.modules[0].path.as_deref(), Some("preload/pre_a.mes"));
+        assert!(matches!(statuses[1], PreloadStatus::Failed(_)));
+        assert!(report.modules[1].path.is_none());
+        match statuses[2] {
+            PreloadStatus::Failed(e) => assert!(e.get_message().contains("bad module")),
+            _ => panic!("bad module did not fail"),
+        }
+        match statuses[3] {
+            PreloadStatus::Failed(e) => assert!(e.get_message().contains("rejected")),
+            _ => panic!("blocked module was not rejected"),
+        }
+        assert_eq!(report.failed().len(), 3);
+
+        // pre_b was loaded as an import of pre_a
+        let report = block_on(rt.preload_modules(None, &["pre_a", "pre_b"]));
+        assert!(report
+            .modules
+            .iter()
+            .all(|m| matches!(m.status, PreloadStatus::AlreadyLoaded)));
+
+        // scripts use the preloaded module
+        rt.eval_module_sync(
+            None,
+            Script::new(
+                "preload/use.mes",
+                "import {a} from 'pre_a'; if (a !== 2 || loads !== 1) { throw Error('reloaded'); }",
+            ),
+        )
+        .expect("module failed");
+    }
+
+    #[test]
+    fn test_pin_module() {
+        let dir =
+            std::env::temp_dir().join(format!("quickjs_runtime_pin_module_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let rt = QuickJsRuntimeBuilder::new()
+            .script_module_loader(PreloadLoader {})
+            .bytecode_cache_dir(
+                &dir,
+                CachePolicy {
+                    max_bytes: 1,
+                    verify_hash: true,
+                },
+            )
+            .build();
+        rt.pin_module(None, "pre_c").expect("pin failed");
+        assert!(rt.pin_module(None, "missing").is_err());
+
+        let report = block_on(rt.preload_modules(None, &["pre_c", "pre_d", "pre_e"]));
+        assert!(report.failed().is_empty());
+        // every entry is larger than max_bytes, only the pinned one is kept
+        let stats = rt.bytecode_cache_stats().expect("no cache");
+        assert_eq!(stats.entries, 1);
+        assert_eq!(stats.evictions, 2);
+
+        drop(rt);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,4 +1,6 @@
-use crate::jsutils::helper_tasks::{add_helper_task, add_helper_task_async};
+use crate::jsutils::asyncstacks;
+use crate::jsutils::jobcontext::{self, JobContextGuard};
+use crate::jsutils::taskscope::{self, ScopedTask};
 use crate::jsutils::JsError;
 use crate::quickjs_utils::promises::QuickJsPromiseAdapter;
 use crate::quickjsrealmadapter::QuickJsRealmAdapter;
@@ -7,7 +9,7 @@ use futures::Future;
 
 #[allow(clippy::type_complexity)]
 /// create a new promise with a producer and a mapper
-/// the producer will run in a helper thread(in the tokio thread pool) and thus get a result asynchronously
+/// the producer will run in a helper thread (see [JsExecutor::spawn_blocking](crate::jsutils::executor::JsExecutor::spawn_blocking)) and thus get a result asynchronously
 /// the resulting value will then be mapped to a JSValueRef by the mapper in the EventQueue thread
 /// the promise which was returned is then resolved with the value which is returned by the mapper
 pub fn new_resolving_promise<P, R, M>(
@@ -29,13 +31,26 @@ where
 
     let rti_ref = realm.get_runtime_facade_inner();
 
+    let executor = match rti_ref.upgrade() {
+        Some(rti) => rti.get_executor().clone(),
+        None => return Err(JsError::new_str("runtime was dropped")),
+    };
+
     let realm_id = realm.get_realm_id().to_string();
+    let scope_id = taskscope::track(ScopedTask::Promise(id));
+    let job_context = jobcontext::current_context();
+    // the stack of a rejection shows where the promise was created
+    let creation_stack = asyncstacks::capture_q(realm);
     // go async
-    add_helper_task(move || {
+    executor.spawn_blocking(Box::new(move || {
         // in helper thread, produce result
         let produced_result = producer();
         if let Some(rti) = rti_ref.upgrade() {
             rti.add_rt_task_to_event_loop_void(move |rt| {
+                // reactions to the promise are run in the scope and the context of the task which created it
+                let _scope_guard =
+                    taskscope::ScopeGuard::enter(scope_id, Some(ScopedTask::Promise(id)));
+                let _context_guard = JobContextGuard::enter(job_context);
                 if let Some(realm) = rt.get_realm(realm_id.as_str()) {
                     // in q_js_rt worker thread, resolve promise
                     // retrieve promise
@@ -64,8 +79,11 @@ where
                                         let err_ref = realm
                                             .create_error(
                                                 err.get_name(),
-                                                err.get_message(),
-                                                err.get_stack(),
+                                                err.get_script_message(),
+                                                &asyncstacks::stitch(
+                                                    err.get_script_stack(),
+                                                    creation_stack.as_deref(),
+                                                ),
                                             )
                                             .expect("could not create error");
                                         if let Err(e) = prom_ref.js_promise_reject(realm, &err_ref)
@@ -84,8 +102,11 @@ where
                                 let err_ref = realm
                                     .create_error(
                                         err.get_name(),
-                                        err.get_message(),
-                                        err.get_stack(),
+                                        err.get_script_message(),
+                                        &asyncstacks::stitch(
+                                            err.get_script_stack(),
+                                            creation_stack.as_deref(),
+                                        ),
                                     )
                                     .expect("could not create error");
                                 if let Err(e) = prom_ref.js_promise_reject(realm, &err_ref) {
@@ -111,14 +132,14 @@ where
         } else {
             log::error!("async promise running for dropped runtime");
         }
-    });
+    }));
 
     Ok(return_ref)
 }
 
 #[allow(clippy::type_complexity)]
 /// create a new promise with an async producer and a mapper
-/// the producer will be awaited asynchronously and
+/// the producer will be awaited asynchronously by the executor of the runtime and
 /// the resulting value will then be mapped to a JSValueRef by the mapper in the EventQueue thread
 /// the promise which was returned is then resolved with the value which is returned by the mapper
 pub(crate) fn new_resolving_promise_async<P, R, M>(
@@ -140,13 +161,26 @@ where
 
     let rti_ref = realm.get_runtime_facade_inner();
 
+    let executor = match rti_ref.upgrade() {
+        Some(rti) => rti.get_executor().clone(),
+        None => return Err(JsError::new_str("runtime was dropped")),
+    };
+
     let realm_id = realm.get_realm_id().to_string();
+    let scope_id = taskscope::track(ScopedTask::Promise(id));
+    let job_context = jobcontext::current_context();
+    // the stack of a rejection shows where the promise was created
+    let creation_stack = asyncstacks::capture_q(realm);
     // go async
-    let _ignore_result = add_helper_task_async(async move {
+    executor.spawn(Box::pin(async move {
         // in helper thread, produce result
         let produced_result = producer.await;
         if let Some(rti) = rti_ref.upgrade() {
             rti.add_rt_task_to_event_loop_void(move |rt| {
+                // reactions to the promise are run in the scope and the context of the task which created it
+                let _scope_guard =
+                    taskscope::ScopeGuard::enter(scope_id, Some(ScopedTask::Promise(id)));
+                let _context_guard = JobContextGuard::enter(job_context);
                 if let Some(realm) = rt.get_realm(realm_id.as_str()) {
                     // in q_js_rt worker thread, resolve promise
                     // retrieve promise
@@ -175,8 +209,11 @@ where
                                         let err_ref = realm
                                             .create_error(
                                                 err.get_name(),
-                                                err.get_message(),
-                                                err.get_stack(),
+                                                err.get_script_message(),
+                                                &asyncstacks::stitch(
+                                                    err.get_script_stack(),
+                                                    creation_stack.as_deref(),
+                                                ),
                                             )
                                             .expect("could not create err");
                                         if let Err(e) = prom_ref.js_promise_reject(realm, &err_ref)
@@ -195,8 +232,11 @@ where
                                 let err_ref = realm
                                     .create_error(
                                         err.get_name(),
-                                        err.get_message(),
-                                        err.get_stack(),
+                                        err.get_script_message(),
+                                        &asyncstacks::stitch(
+                                            err.get_script_stack(),
+                                            creation_stack.as_deref(),
+                                        ),
                                     )
                                     .expect("could not create str");
                                 if let Err(e) = prom_ref.js_promise_reject(realm, &err_ref) {
@@ -222,6 +262,6 @@ where
         } else {
             log::error!("async promise running on dropped runtime");
         }
-    });
+    }));
     Ok(return_ref)
 }
@@ -0,0 +1,222 @@
+//! typed handles to realms, see [create_realm](crate::facades::QuickJsRuntimeFacade::create_realm)
+//!
+//! a [RealmHandle] refers to a single realm, it is cheap to clone and can be sent to other threads
+//!
+//! * when its realm is dropped a handle is invalid for good, also when a new realm with the same id is created later,
+//!   methods which target the realm of a dead handle fail with a [DeadRealm](crate::jsutils::JsError::is_dead_realm) error
+//! * all handles of a realm share their state, with [drop_realms_with_last_handle](crate::builder::QuickJsRuntimeBuilder::drop_realms_with_last_handle)
+//!   a realm is dropped when its last handle is dropped, realms which are only used by id and the main realm are never dropped this way
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::Script;
+//! let rt = QuickJsRuntimeBuilder::new().build();
+//! let realm = rt.create_realm("tenant_1").expect("could not create realm");
+//! let res = rt.eval_in_sync(&realm, Script::new("tenant.js", "1 + 2;")).expect("script failed");
+//! assert_eq!(res.get_i32(), 3);
+//! rt.drop_realm(&realm).expect("could not drop realm");
+//! assert!(!realm.is_alive());
+//! let err = rt.eval_in_sync(&realm, Script::new("tenant.js", "1 + 2;")).expect_err("realm was alive");
+//! assert!(err.is_dead_realm());
+//! ```
+
+use crate::facades::QuickjsRuntimeFacadeInner;
+use crate::jsutils::JsError;
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use serde::Serialize;
+use std::fmt::{Debug, Formatter};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
+
+/// the state which is shared by all handles of a realm
+pub(crate) struct RealmHandleState {
+    id: String,
+    // shared with the realm, set to false when the realm is freed
+    alive: Arc<AtomicBool>,
+    rti: Weak<QuickjsRuntimeFacadeInner>,
+    drop_with_last_handle: bool,
+}
+
+impl Drop for RealmHandleState {
+    fn drop(&mut self) {
+        if !self.drop_with_last_handle
+            || self.id == "__main__"
+            || !self.alive.load(Ordering::SeqCst)
+        {
+            return;
+        }
+        if let Some(rti) = self.rti.upgrade() {
+            let id = self.id.clone();
+            let alive = self.alive.clone();
+            rti.add_task_to_event_loop_void(move || {
+                // a new handle may have been created for the realm before this task ran
+                let orphaned =
+                    QuickJsRuntimeAdapter::do_with(|rt| match rt.get_realm(id.as_str()) {
+                        Some(realm) => {
+                            Arc::ptr_eq(&realm.alive, &alive)
+                                && realm.handle_state.borrow().strong_count() == 0
+                        }
+                        None => false,
+                    });
+                if orphaned {
+                    log::debug!("dropping realm {id}, its last handle was dropped");
+                    QuickJsRuntimeAdapter::remove_context(id.as_str());
+                }
+            });
+        }
+    }
+}
+
+/// a handle to a realm, see [realmhandle](crate::jsutils::realmhandle)
+#[derive(Clone)]
+pub struct RealmHandle {
+    state: Arc<RealmHandleState>,
+}
+
+impl Debug for RealmHandle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "RealmHandle({}, alive: {})", self.id(), self.is_alive())
+    }
+}
+
+/// the number of values a realm holds on to, see [RealmHandle::stats]
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct RealmStats {
+    /// objects which are referenced from rust, e.g. by a [CachedJsObjectRef](crate::values::CachedJsObjectRef)
+    pub cached_objects: usize,
+    /// promises which are waiting to be resolved from rust
+    pub cached_promises: usize,
+    pub pinned_values: usize,
+    pub timeouts: usize,
+    pub intervals: usize,
+    /// the instances of proxy classes which were created in the realm and not finalized
+    pub proxy_instances: usize,
+}
+
+impl RealmStats {
+    fn of(realm: &QuickJsRealmAdapter) -> Self {
+        Self {
+            cached_objects: realm.cached_object_count(),
+            cached_promises: realm.cached_promise_count(),
+            pinned_values: realm.pinned_values.borrow().len(),
+            timeouts: realm.timeout_ids.borrow().len(),
+            intervals: realm.interval_ids.borrow().len(),
+            proxy_instances: realm
+                .proxy_registry
+                .borrow()
+                .values()
+                .map(|proxy| proxy.proxy_instance_id_mappings.borrow().len())
+                .sum(),
+        }
+    }
+}
+
+impl RealmHandle {
+    /// the id of the realm
+    pub fn id(&self) -> &str {
+        self.state.id.as_str()
+    }
+
+    /// check if the realm was not dropped
+    pub fn is_alive(&self) -> bool {
+        self.state.alive.load(Ordering::SeqCst)
+    }
+
+    /// get the number of values the realm holds on to
+    pub async fn stats(&self) -> Result<RealmStats, JsError> {
+        let rti = match self.state.rti.upgrade() {
+            Some(rti) => rti,
+            None => return Err(JsError::new_dead_realm(self.id())),
+        };
+        let handle = self.clone();
+        rti.add_rt_task_to_event_loop(move |rt| handle.resolve_q(rt).map(RealmStats::of))
+            .await
+    }
+
+    /// get the realm of this handle, fails with a DeadRealm error when the realm was dropped
+    pub(crate) fn resolve_q<'a>(
+        &self,
+        rt: &'a QuickJsRuntimeAdapter,
+    ) -> Result<&'a QuickJsRealmAdapter, JsError> {
+        if self.is_alive() {
+            if let Some(realm) = rt.get_realm(self.id()) {
+                return Ok(realm);
+            }
+        }
+        Err(JsError::new_dead_realm(self.id()))
+    }
+}
+
+/// get a handle to a realm, all handles of a realm share their state
+pub(crate) fn handle_q(rt: &QuickJsRuntimeAdapter, realm: &QuickJsRealmAdapter) -> RealmHandle {
+    let existing = realm.handle_state.borrow().upgrade();
+    match existing {
+        Some(state) => RealmHandle { state },
+        None => {
+            let state = Arc::new(RealmHandleState {
+                id: realm.id.clone(),
+                alive: realm.alive.clone(),
+                rti: match rt.get_rti_ref() {
+                    Some(rti) => Arc::downgrade(&rti),
+                    None => Weak::new(),
+                },
+                drop_with_last_handle: rt.drop_realms_with_last_handle,
+            });
+            realm.handle_state.replace(Arc::downgrade(&state));
+            RealmHandle { state }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::Script;
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_realm_handle() {
+        let rt = QuickJsRuntimeBuilder::new().build();
+        let realm = rt.create_realm("handle_a").expect("could not create realm");
+        assert_eq!(realm.id(), "handle_a");
+        rt.eval_in_sync(
+            &realm,
+            Script::new("handle.js", "globalThis.t = setTimeout(() => {}, 10000);"),
+        )
+        .expect("script failed");
+        let stats = block_on(realm.stats()).expect("no stats");
+        assert_eq!(stats.timeouts, 1);
+
+        // handles from create_realm and realm_handle share their realm
+        let same = rt.realm_handle("handle_a").expect("no handle");
+        rt.drop_realm(&same).expect("could not drop realm");
+        assert!(!realm.is_alive());
+
+        // a new realm with the same id does not revive the handle
+        let new_realm = rt.create_realm("handle_a").expect("could not create realm");
+        let err = block_on(rt.eval_in(&realm, Script::new("handle.js", "1;")))
+            .expect_err("dead handle was resolved");
+        assert!(err.is_dead_realm());
+        assert!(block_on(rt.eval_in(&new_realm, Script::new("handle.js", "1;"))).is_ok());
+        assert!(rt.has_realm("handle_a").expect("has_realm failed"));
+
+        // the string id apis still work
+        assert!(rt
+            .eval_sync(Some("handle_a"), Script::new("handle.js", "1;"))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_drop_with_last_handle() {
+        let rt = QuickJsRuntimeBuilder::new()
+            .drop_realms_with_last_handle()
+            .build();
+        let realm = rt.create_realm("handle_b").expect("could not create realm");
+        let other = realm.clone();
+        drop(realm);
+        assert!(rt.has_realm("handle_b").expect("has_realm failed"));
+        drop(other);
+        assert!(!rt.has_realm("handle_b").expect("has_realm failed"));
+    }
+}
@@ -0,0 +1,114 @@
+//! redaction of strings which the runtime emits outward
+//!
+//! a hook set with [redaction_hook](crate::builder::QuickJsRuntimeBuilder::redaction_hook) is applied to console output, the messages and stacks of errors thrown by scripts and the reasons of unhandled promise rejections
+//! values which are returned to the embedder (e.g. a JsValueFacade) are never redacted
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::sync::Arc;
+
+/// a hook which scrubs secrets from a string, it should return a Cow::Borrowed when nothing was redacted
+pub type RedactionHook = Arc<dyn Fn(&str) -> Cow<str> + Send + Sync>;
+
+thread_local! {
+    // set in the EventLoop thread of the runtime
+    static REDACTION_HOOK: RefCell<Option<RedactionHook>> = RefCell::new(None);
+}
+
+pub(crate) fn set_redaction_hook(hook: Option<RedactionHook>) {
+    REDACTION_HOOK.with(|rc| *rc.borrow_mut() = hook);
+}
+
+/// apply the redaction hook of the runtime of the current thread, if any
+pub(crate) fn redact(s: &str) -> Cow<'_, str> {
+    // clone the hook so it does not run while the thread_local is borrowed
+    let hook = REDACTION_HOOK.with(|rc| rc.borrow().clone());
+    match hook {
+        Some(hook) => hook(s),
+        None => Cow::Borrowed(s),
+    }
+}
+
+/// apply the redaction hook to an owned string, the string is returned as is when nothing was redacted
+pub(crate) fn redact_string(s: String) -> String {
+    let redacted = match redact(s.as_str()) {
+        Cow::Owned(redacted) => Some(redacted),
+        Cow::Borrowed(_) => None,
+    };
+    redacted.unwrap_or(s)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::Script;
+    use crate::quickjs_utils::{functions, get_global_q, objects};
+    use std::borrow::Cow;
+
+    #[test]
+    fn test_redaction_hook() {
+        // this hook is not idempotent so redacting a string twice would be visible
+        let rt = QuickJsRuntimeBuilder::new()
+            .redaction_hook(|s| {
+                if s.contains("s3cr3t") {
+                    Cow::Owned(format!("[{}]", s.replace("s3cr3t", "***")))
+                } else {
+                    Cow::Borrowed(s)
+                }
+            })
+            .build();
+
+        let err = rt
+            .eval_sync(
+                None,
+                Script::new("test_redaction.js", "throw Error('token s3cr3t');"),
+            )
+            .expect_err("script should fail");
+        assert_eq!(err.get_message(), "[token ***]");
+        assert!(!err.get_stack().contains("s3cr3t"));
+
+        rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let realm = q_js_rt.get_main_realm();
+            let call_it = functions::new_function_q(
+                realm,
+                "callIt",
+                |realm, _this, args| functions::call_function_q(realm, &args[0], &[], None),
+                1,
+            )
+            .expect("could not create func");
+            let global = get_global_q(realm);
+            objects::set_property_q(realm, &global, "callIt", &call_it)
+                .expect("could not set prop");
+        });
+
+        // errors which pass through native code are only redacted once
+        let err = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "test_redaction.js",
+                    "callIt(() => {throw Error('token s3cr3t');});",
+                ),
+            )
+            .expect_err("script should fail");
+        assert_eq!(err.get_message(), "[token ***]");
+
+        // script sees the original message
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "test_redaction.js",
+                    "let m; try {callIt(() => {throw Error('token s3cr3t');});} catch(e) {m = e.message;} m",
+                ),
+            )
+            .expect("script failed");
+        assert_eq!(res.get_str(), "token s3cr3t");
+
+        // values are not redacted
+        let res = rt
+            .eval_sync(None, Script::new("test_redaction.js", "'token s3cr3t'"))
+            .expect("script failed");
+        assert_eq!(res.get_str(), "token s3cr3t");
+    }
+}
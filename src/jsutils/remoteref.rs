@@ -0,0 +1,168 @@
+//! releasing values of a realm which are referred to from outside the worker thread
+//!
+//! a [RemoteRefGuard] is held by the facades which keep a value of a realm alive (e.g. a
+//! [CachedJsObjectRef](crate::values::CachedJsObjectRef)), the value is released on the worker thread when the guard is dropped
+//!
+//! * the guard may be dropped in any thread, also when the runtime or the realm is already gone, the release is then skipped
+//! * the releases are queued in the runtime and run in a single job, dropping many guards at once adds one job to the EventLoop
+
+use crate::facades::QuickjsRuntimeFacadeInner;
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+struct PendingRelease {
+    realm_id: String,
+    realm_alive: Arc<AtomicBool>,
+    id: i32,
+}
+
+/// the releases of a runtime which did not run yet
+#[derive(Default)]
+pub(crate) struct ReleaseQueue {
+    pending: Mutex<Vec<PendingRelease>>,
+    scheduled: AtomicBool,
+    // the number of jobs which released values, used by the tests
+    jobs: AtomicU64,
+}
+
+impl ReleaseQueue {
+    #[cfg(test)]
+    pub(crate) fn job_count(&self) -> u64 {
+        self.jobs.load(Ordering::SeqCst)
+    }
+
+    fn release_pending(&self, q_js_rt: &QuickJsRuntimeAdapter) {
+        // clear the flag first so a release which is queued while this job runs schedules a new job
+        self.scheduled.store(false, Ordering::SeqCst);
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        self.jobs.fetch_add(1, Ordering::SeqCst);
+        for release in pending {
+            // a realm with the same id may have been created after the original one was dropped
+            if !release.realm_alive.load(Ordering::SeqCst) {
+                continue;
+            }
+            if let Some(realm) = q_js_rt.get_realm(release.realm_id.as_str()) {
+                realm.dispose_cached_object(release.id);
+            }
+        }
+    }
+}
+
+/// releases a cached object of a realm when it is dropped
+pub(crate) struct RemoteRefGuard {
+    rti: Weak<QuickjsRuntimeFacadeInner>,
+    release: Option<PendingRelease>,
+}
+
+impl RemoteRefGuard {
+    /// guard an object which was cached with [cache_object](QuickJsRealmAdapter::cache_object)
+    pub(crate) fn new(realm: &QuickJsRealmAdapter, id: i32) -> Self {
+        Self {
+            rti: realm.get_runtime_facade_inner(),
+            release: Some(PendingRelease {
+                realm_id: realm.get_realm_id().to_string(),
+                realm_alive: realm.alive.clone(),
+                id,
+            }),
+        }
+    }
+
+    /// do not release the object when this guard is dropped, the owner released it
+    pub(crate) fn disarm(&mut self) {
+        self.release = None;
+    }
+}
+
+impl Drop for RemoteRefGuard {
+    fn drop(&mut self) {
+        let release = match self.release.take() {
+            Some(release) => release,
+            None => return,
+        };
+        if !release.realm_alive.load(Ordering::SeqCst) {
+            return;
+        }
+        let rti = match self.rti.upgrade() {
+            Some(rti) => rti,
+            None => return,
+        };
+        let queue = &rti.released_refs;
+        queue.pending.lock().unwrap().push(release);
+        if !queue.scheduled.swap(true, Ordering::SeqCst) {
+            let job_rti = Arc::downgrade(&rti);
+            rti.add_internal_rt_task_to_event_loop_void(move |q_js_rt| {
+                if let Some(rti) = job_rti.upgrade() {
+                    rti.released_refs.release_pending(q_js_rt);
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::Script;
+    use crate::values::JsValueFacade;
+    use std::thread;
+
+    #[test]
+    fn test_drop_from_threads() {
+        let rt = QuickJsRuntimeBuilder::new().build();
+        let facades: Vec<JsValueFacade> = rt.loop_realm_sync(None, |_rt, realm| {
+            (0..100_000)
+                .map(|i| {
+                    let obj = realm
+                        .eval(Script::new("test_remoteref.js", &format!("({{i: {i}}})")))
+                        .expect("script failed");
+                    realm.to_js_value_facade(&obj).expect("conversion failed")
+                })
+                .collect()
+        });
+        assert_eq!(
+            rt.loop_realm_sync(None, |_rt, realm| realm.cached_object_count()),
+            100_000
+        );
+
+        let mut chunks = vec![];
+        let mut facades = facades;
+        while !facades.is_empty() {
+            let rest = facades.split_off(facades.len().saturating_sub(25_000));
+            chunks.push(rest);
+        }
+        let droppers = chunks
+            .into_iter()
+            .map(|chunk| thread::spawn(move || drop(chunk)))
+            .collect::<Vec<_>>();
+        // the worker is busy while the handles are dropped
+        rt.eval_sync(
+            None,
+            Script::new(
+                "test_remoteref.js",
+                "let t = Date.now(); while (Date.now() - t < 100) {} 1;",
+            ),
+        )
+        .expect("script failed");
+        for dropper in droppers {
+            dropper.join().expect("dropper failed");
+        }
+
+        assert_eq!(
+            rt.loop_realm_sync(None, |_rt, realm| realm.cached_object_count()),
+            0
+        );
+        assert!(rt.get_inner().released_refs.job_count() < 1_000);
+
+        // dropping after the runtime is gone is a no-op
+        let late = rt.loop_realm_sync(None, |_rt, realm| {
+            let obj = realm
+                .eval(Script::new("test_remoteref.js", "({})"))
+                .expect("script failed");
+            realm.to_js_value_facade(&obj).expect("conversion failed")
+        });
+        drop(rt);
+        drop(late);
+    }
+}
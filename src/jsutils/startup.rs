@@ -0,0 +1,230 @@
+//! scripts which are evaluated when a runtime is built, see [startup_script](crate::builder::QuickJsRuntimeBuilder::startup_script)
+//!
+//! startup scripts and modules are evaluated in the main realm in the order in which they were added, after the realm init hooks
+//! scripts which are flagged with [in_new_realms](StartupScript::in_new_realms) are also evaluated in every realm which is created later,
+//! those scripts are compiled once and the bytecode is reused for every new realm
+
+use crate::jsutils::bytecodecache::CachedKind;
+use crate::jsutils::{JsError, Script};
+use crate::quickjs_utils::compile;
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+
+/// what to do when a startup script fails
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StartupFailurePolicy {
+    /// building the runtime fails, [try_build](crate::builder::QuickJsRuntimeBuilder::try_build) returns the error
+    /// a realm which is created later fails to initialize
+    #[default]
+    FailBuild,
+    /// log the error and continue with the next script
+    LogAndContinue,
+}
+
+/// a script or module which is evaluated when a runtime is built
+#[derive(Clone)]
+pub struct StartupScript {
+    script: Script,
+    is_module: bool,
+    in_new_realms: bool,
+}
+
+impl StartupScript {
+    pub fn new(script: Script) -> Self {
+        Self {
+            script,
+            is_module: false,
+            in_new_realms: false,
+        }
+    }
+    /// also evaluate this script in every realm which is created after the runtime was built
+    pub fn in_new_realms(mut self) -> Self {
+        self.in_new_realms = true;
+        self
+    }
+    pub(crate) fn module(mut self) -> Self {
+        self.is_module = true;
+        self
+    }
+}
+
+impl From<Script> for StartupScript {
+    fn from(script: Script) -> Self {
+        Self::new(script)
+    }
+}
+
+// the code of a startup script as it is evaluated in new realms
+enum RealmStartupCode {
+    Bytecode { path: String, bytecode: Vec<u8> },
+    Module(Script),
+}
+
+impl RealmStartupCode {
+    fn get_path(&self) -> &str {
+        match self {
+            RealmStartupCode::Bytecode { path, .. } => path.as_str(),
+            RealmStartupCode::Module(script) => script.get_path(),
+        }
+    }
+    fn run(&self, realm: &QuickJsRealmAdapter) -> Result<(), JsError> {
+        match self {
+            RealmStartupCode::Bytecode { bytecode, .. } => unsafe {
+                let func = compile::from_bytecode(realm.context, bytecode)?;
+                compile::run_compiled_function(realm.context, &func).map(|_| ())
+            },
+            RealmStartupCode::Module(script) => realm.eval_module(script.clone()).map(|_| ()),
+        }
+    }
+}
+
+fn handle_failure(
+    policy: StartupFailurePolicy,
+    path: &str,
+    realm_id: &str,
+    err: JsError,
+) -> Result<(), JsError> {
+    match policy {
+        StartupFailurePolicy::FailBuild => Err(JsError::new(
+            err.get_name().to_string(),
+            format!("startup script {path} failed: {}", err.get_message()),
+            err.get_stack().to_string(),
+        )),
+        StartupFailurePolicy::LogAndContinue => {
+            log::error!("startup script {path} failed in realm {realm_id}: {err}");
+            Ok(())
+        }
+    }
+}
+
+// compile a script and run it, the bytecode is returned so it can be reused in new realms
+fn run_script(realm: &QuickJsRealmAdapter, script: Script) -> Result<Vec<u8>, JsError> {
+    let script = QuickJsRuntimeAdapter::pre_process(script)?;
+    let bytecode_cache = QuickJsRuntimeAdapter::do_with(|q_js_rt| q_js_rt.bytecode_cache.clone());
+    unsafe {
+        let func = match bytecode_cache {
+            Some(cache) => {
+                cache.compile_cached(realm.context, CachedKind::Script, &script, || {
+                    compile::compile(realm.context, script.clone())
+                })?
+            }
+            None => compile::compile(realm.context, script)?,
+        };
+        let bytecode = compile::to_bytecode(realm.context, &func);
+        compile::run_compiled_function(realm.context, &func)?;
+        Ok(bytecode)
+    }
+}
+
+pub(crate) fn run_startup_scripts(
+    q_js_rt: &QuickJsRuntimeAdapter,
+    scripts: Vec<StartupScript>,
+    policy: StartupFailurePolicy,
+) -> Result<(), JsError> {
+    let realm = q_js_rt.get_main_realm();
+    let mut realm_startup_code = vec![];
+    for startup_script in scripts {
+        let path = startup_script.script.get_path().to_string();
+        let res = if startup_script.is_module {
+            realm
+                .eval_module(startup_script.script.clone())
+                .map(|_| RealmStartupCode::Module(startup_script.script))
+        } else {
+            run_script(realm, startup_script.script).map(|bytecode| RealmStartupCode::Bytecode {
+                path: path.clone(),
+                bytecode,
+            })
+        };
+        match res {
+            Ok(code) => {
+                if startup_script.in_new_realms {
+                    realm_startup_code.push(code);
+                }
+            }
+            Err(err) => handle_failure(policy, path.as_str(), realm.get_realm_id(), err)?,
+        }
+    }
+
+    if !realm_startup_code.is_empty() {
+        // added directly instead of with add_context_init_hook so the hook does not run in the existing realms
+        q_js_rt
+            .context_init_hooks
+            .borrow_mut()
+            .push(Box::new(move |_rt, realm| {
+                for code in &realm_startup_code {
+                    if let Err(err) = code.run(realm) {
+                        handle_failure(policy, code.get_path(), realm.get_realm_id(), err)?;
+                    }
+                }
+                Ok(())
+            }));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::startup::{StartupFailurePolicy, StartupScript};
+    use crate::jsutils::Script;
+    use crate::quickjs_utils::get_global_q;
+    use crate::quickjs_utils::objects;
+
+    #[test]
+    fn test_startup_scripts() {
+        let rt = QuickJsRuntimeBuilder::new()
+            .realm_adapter_init_hook(|_rt, realm| {
+                let global = get_global_q(realm);
+                objects::set_property_q(realm, &global, "hooked", &realm.create_boolean(true)?)
+            })
+            .startup_script(Script::new(
+                "startup_a.js",
+                "globalThis.order = ['a:' + globalThis.hooked];",
+            ))
+            .startup_script(
+                StartupScript::new(Script::new(
+                    "startup_b.js",
+                    "globalThis.sum = (a, b) => a + b; globalThis.order?.push('b');",
+                ))
+                .in_new_realms(),
+            )
+            .startup_module(Script::new("startup_c.mjs", "globalThis.order.push('c');"))
+            .build();
+
+        let res = rt
+            .eval_sync(None, Script::new("test_startup.js", "order.join(',')"))
+            .expect("script failed");
+        assert_eq!(res.get_str(), "a:true,b,c");
+
+        let res = rt
+            .eval_sync(
+                Some("startup_realm"),
+                Script::new("test_startup.js", "`${typeof order}:${sum(1, 2)}`"),
+            )
+            .expect("script failed");
+        assert_eq!(res.get_str(), "undefined:3");
+    }
+
+    #[test]
+    fn test_startup_failure_policy() {
+        let err = QuickJsRuntimeBuilder::new()
+            .startup_script(Script::new("startup_ok.js", "globalThis.a = 1;"))
+            .startup_script(Script::new("startup_fail.js", "throw Error('boom');"))
+            .try_build()
+            .err()
+            .expect("build should fail");
+        assert!(err.get_message().contains("startup_fail.js"), "{err}");
+        assert!(err.get_message().contains("boom"), "{err}");
+
+        let rt = QuickJsRuntimeBuilder::new()
+            .startup_failure_policy(StartupFailurePolicy::LogAndContinue)
+            .startup_script(Script::new("startup_fail.js", "throw Error('boom');"))
+            .startup_script(Script::new("startup_ok.js", "globalThis.a = 1;"))
+            .try_build()
+            .expect("build failed");
+        let res = rt
+            .eval_sync(None, Script::new("test_startup.js", "a"))
+            .expect("script failed");
+        assert_eq!(res.get_i32(), 1);
+    }
+}
@@ -0,0 +1,548 @@
+//! suspend a realm and resume it on another runtime, see [suspend_realm](crate::facades::QuickJsRuntimeFacade::suspend_realm)
+//!
+//! suspending a realm runs the pending jobs and captures its state in a [SuspendedRealm] which can be serialized to a portable blob:
+//!
+//! * the enumerable properties of the global object which were added by scripts (e.g. `var` declarations and `globalThis.x = ...`), as json
+//! * the modules which were loaded from a module loader, in load order
+//! * the pending setTimeout and setInterval timers with their remaining delay, if their callback is a global function and their args can be serialized
+//!
+//! resuming creates the realm on the other runtime, imports the modules, restores the globals and reschedules the timers,
+//! the timers of the realm which was suspended are cleared so they don't fire in both realms, the realm itself is kept,
+//! destroy it with [destroy_realm](crate::facades::QuickJsRuntimeFacade::destroy_realm) once the resumed realm took over
+//!
+//! live state which can not be moved is listed in [SuspendedRealm::unsupported] so the caller can decide whether to proceed:
+//! global functions, promises and proxy instances, modules which were evaluated from source, timers with an anonymous callback,
+//! event listeners on proxy instances and promises which are still pending (e.g. a running fetch)
+//!
+//! top-level `let`, `const` and `class` declarations and the variables inside modules are not properties of the global object,
+//! they are not captured, modules are evaluated again when the realm is resumed and timer ids differ after resuming
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::suspend::SuspendedRealm;
+//! use quickjs_runtime::jsutils::Script;
+//! use futures::executor::block_on;
+//! let rt = QuickJsRuntimeBuilder::new().build();
+//! rt.create_realm("worker").expect("could not create realm");
+//! rt.eval_sync(Some("worker"), Script::new("state.js", "var visits = 3;")).expect("script failed");
+//! let suspended = block_on(rt.suspend_realm("worker")).expect("suspend failed");
+//! let blob = suspended.to_bytes().expect("could not serialize");
+//!
+//! let other = QuickJsRuntimeBuilder::new().build();
+//! block_on(other.resume_realm(SuspendedRealm::from_bytes(&blob).expect("invalid blob"))).expect("resume failed");
+//! let res = other.eval_sync(Some("worker"), Script::new("read.js", "visits")).expect("script failed");
+//! assert_eq!(res.get_i32(), 3);
+//! ```
+
+use crate::jsutils::taskscope::ScopedTask;
+use crate::jsutils::{debugdump, timers, JsError, Script};
+use crate::quickjs_utils::pinned::PinnedRef;
+use crate::quickjs_utils::{functions, get_global_q, objects};
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use crate::quickjsvalueadapter::QuickJsValueAdapter;
+use crate::reflection;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// the version of the blob format written by [SuspendedRealm::to_bytes]
+pub const FORMAT_VERSION: u32 = 1;
+
+/// the state of a suspended realm
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SuspendedRealm {
+    pub version: u32,
+    /// the id of the realm, the realm is resumed with this id
+    pub realm_id: String,
+    /// the global properties which were added by scripts
+    pub globals: Map<String, Value>,
+    /// the modules which are imported again when the realm is resumed
+    pub modules: Vec<String>,
+    pub timers: Vec<SuspendedTimer>,
+    /// the live state which was not captured
+    pub unsupported: Vec<UnsupportedState>,
+}
+
+/// a pending setTimeout or setInterval
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SuspendedTimer {
+    /// the name of the global function which is called
+    pub callback: String,
+    /// the time left until the timer fires
+    pub delay_ms: u64,
+    /// the interval of a setInterval timer
+    pub interval_ms: Option<u64>,
+    pub args: Vec<Value>,
+}
+
+/// live state which could not be suspended
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct UnsupportedState {
+    /// global, module, timer, event_listener or promise
+    pub kind: String,
+    pub name: String,
+    pub reason: String,
+}
+
+impl SuspendedRealm {
+    /// true when all live state of the realm was captured
+    pub fn is_complete(&self) -> bool {
+        self.unsupported.is_empty()
+    }
+
+    /// serialize the suspended realm to a portable blob
+    pub fn to_bytes(&self) -> Result<Vec<u8>, JsError> {
+        serde_json::to_vec(self).map_err(|e| JsError::new_string(format!("{e}")))
+    }
+
+    /// read a blob written by [to_bytes](Self::to_bytes)
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, JsError> {
+        let suspended: Self = serde_json::from_slice(bytes)
+            .map_err(|e| JsError::new_string(format!("invalid suspended realm: {e}")))?;
+        if suspended.version != FORMAT_VERSION {
+            return Err(JsError::new_string(format!(
+                "unsupported suspended realm version: {}",
+                suspended.version
+            )));
+        }
+        Ok(suspended)
+    }
+}
+
+fn unsupported(kind: &str, name: &str, reason: &str) -> UnsupportedState {
+    UnsupportedState {
+        kind: kind.to_string(),
+        name: name.to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+/// when a timer of a realm is due, kept so the remaining delay can be suspended
+pub(crate) struct TimerSchedule {
+    pub(crate) due: Instant,
+    pub(crate) interval: Option<Duration>,
+    // the function, the delay and the args of the timer
+    args: Rc<Vec<PinnedRef>>,
+    // only captured when async stack traces are enabled
+    pub(crate) creation_stack: Option<String>,
+}
+
+pub(crate) fn track_timer(
+    realm: &QuickJsRealmAdapter,
+    task: ScopedTask,
+    delay: Duration,
+    interval: Option<Duration>,
+    args: Rc<Vec<PinnedRef>>,
+    creation_stack: Option<String>,
+) {
+    realm.timer_schedules.borrow_mut().insert(
+        task,
+        TimerSchedule {
+            due: Instant::now() + delay,
+            interval,
+            args,
+            creation_stack,
+        },
+    );
+}
+
+pub(crate) fn untrack_timer(realm: &QuickJsRealmAdapter, task: ScopedTask) {
+    // drop the schedule outside the borrow, dropping its PinnedRefs borrows the realm
+    let removed = realm.timer_schedules.borrow_mut().remove(&task);
+    drop(removed);
+}
+
+/// an interval fired, it is due again after the interval
+pub(crate) fn interval_fired(realm: &QuickJsRealmAdapter, task: ScopedTask) {
+    if let Some(schedule) = realm.timer_schedules.borrow_mut().get_mut(&task) {
+        if let Some(interval) = schedule.interval {
+            schedule.due = Instant::now() + interval;
+        }
+    }
+}
+
+fn global_names(realm: &QuickJsRealmAdapter) -> Result<Vec<String>, JsError> {
+    objects::get_property_names_q(realm, &get_global_q(realm))
+}
+
+/// remember the globals of a new realm after its init hooks ran, these are installed by the runtime and are not suspended
+pub(crate) fn record_initial_globals(realm: &QuickJsRealmAdapter) -> Result<(), JsError> {
+    let names = global_names(realm)?;
+    realm.initial_global_names.borrow_mut().extend(names);
+    Ok(())
+}
+
+/// run an init hook which was added for a realm that already exists, the globals it adds are not suspended either
+pub(crate) fn run_init_hook<H>(realm: &QuickJsRealmAdapter, hook: H) -> Result<(), JsError>
+where
+    H: FnOnce() -> Result<(), JsError>,
+{
+    let before: HashSet<String> = global_names(realm)?.into_iter().collect();
+    hook()?;
+    let added: Vec<String> = global_names(realm)?
+        .into_iter()
+        .filter(|name| !before.contains(name))
+        .collect();
+    realm.initial_global_names.borrow_mut().extend(added);
+    Ok(())
+}
+
+// a timer with when it is due, its interval and its function, delay and args
+type ScheduledTimer = (ScopedTask, Instant, Option<Duration>, Rc<Vec<PinnedRef>>);
+
+fn capture_q(
+    realm: &QuickJsRealmAdapter,
+    baseline: &HashSet<String>,
+) -> Result<SuspendedRealm, JsError> {
+    let global = get_global_q(realm);
+    let mut globals = Map::new();
+    let mut functions_by_value: Vec<(QuickJsValueAdapter, String)> = vec![];
+    let mut unsupported_state = vec![];
+
+    for name in global_names(realm)? {
+        if baseline.contains(&name) {
+            continue;
+        }
+        let value = objects::get_property_q(realm, &global, name.as_str())?;
+        if value.is_undefined() {
+            continue;
+        }
+        if value.is_function() {
+            unsupported_state.push(unsupported(
+                "global",
+                &name,
+                "functions can not be serialized, define them in a module",
+            ));
+            functions_by_value.push((value, name));
+        } else if value.is_promise() {
+            unsupported_state.push(unsupported(
+                "global",
+                &name,
+                "promises can not be serialized",
+            ));
+        } else if reflection::is_proxy_instance_q(realm, &value) {
+            unsupported_state.push(unsupported(
+                "global",
+                &name,
+                "proxy instances are backed by native state",
+            ));
+        } else {
+            match realm.value_adapter_to_serde_value(&value) {
+                Ok(json) => {
+                    globals.insert(name, json);
+                }
+                Err(e) => unsupported_state.push(unsupported("global", &name, e.get_message())),
+            }
+        }
+    }
+
+    let mut modules = vec![];
+    for module in debugdump::loaded_modules(realm) {
+        if module.kind.eq("eval") {
+            unsupported_state.push(unsupported(
+                "module",
+                &module.path,
+                "evaluated from source, not available from a module loader",
+            ));
+        } else {
+            modules.push(module.path);
+        }
+    }
+
+    let now = Instant::now();
+    let mut timers = vec![];
+    let mut schedules: Vec<ScheduledTimer> = realm
+        .timer_schedules
+        .borrow()
+        .iter()
+        .map(|(task, s)| (*task, s.due, s.interval, s.args.clone()))
+        .collect();
+    schedules.sort_by_key(|(_, due, _, _)| *due);
+    for (task, due, interval, args) in schedules {
+        let timer_name = match task {
+            ScopedTask::Timeout(id) => format!("setTimeout {id}"),
+            ScopedTask::Interval(id) => format!("setInterval {id}"),
+            ScopedTask::Promise(id) => format!("promise {id}"),
+        };
+        let args = PinnedRef::get_all(&args)?;
+        let callback = match functions_by_value.iter().find(|(f, _)| f.eq(&args[0])) {
+            Some((_, name)) => name.clone(),
+            None => {
+                unsupported_state.push(unsupported(
+                    "timer",
+                    &timer_name,
+                    "the callback is not a global function",
+                ));
+                continue;
+            }
+        };
+        let timer_args: Result<Vec<Value>, JsError> = args
+            .iter()
+            .skip(2)
+            .map(|arg| realm.value_adapter_to_serde_value(arg))
+            .collect();
+        match timer_args {
+            Ok(timer_args) => timers.push(SuspendedTimer {
+                callback,
+                delay_ms: due.saturating_duration_since(now).as_millis() as u64,
+                interval_ms: interval.map(|i| i.as_millis() as u64),
+                args: timer_args,
+            }),
+            Err(e) => unsupported_state.push(unsupported("timer", &timer_name, e.get_message())),
+        }
+    }
+    // the functions were only kept to identify the timer callbacks
+    drop(functions_by_value);
+
+    for (class_name, instances) in realm.proxy_event_listeners.borrow().iter() {
+        for (instance_id, events) in instances {
+            for event_id in events.keys() {
+                unsupported_state.push(unsupported(
+                    "event_listener",
+                    &format!("{class_name}#{instance_id} {event_id}"),
+                    "listeners are registered on a proxy instance",
+                ));
+            }
+        }
+    }
+    let pending_promises = realm.cached_promise_count();
+    if pending_promises > 0 {
+        unsupported_state.push(unsupported(
+            "promise",
+            &format!("{pending_promises} pending"),
+            "promises which are resolved by native code can not be moved",
+        ));
+    }
+
+    Ok(SuspendedRealm {
+        version: FORMAT_VERSION,
+        realm_id: realm.id.clone(),
+        globals,
+        modules,
+        timers,
+        unsupported: unsupported_state,
+    })
+}
+
+/// suspend a realm, must be called from the worker thread outside of do_with
+pub(crate) fn suspend_realm(realm_id: &str) -> Result<SuspendedRealm, JsError> {
+    QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+        if q_js_rt.get_realm(realm_id).is_none() {
+            return Err(JsError::new_string(format!("no such realm: {realm_id}")));
+        }
+        q_js_rt.run_pending_jobs_if_any();
+        Ok(())
+    })?;
+
+    QuickJsRuntimeAdapter::do_with(|q_js_rt| match q_js_rt.get_realm(realm_id) {
+        Some(realm) => {
+            let suspended = capture_q(realm, &realm.initial_global_names.borrow())?;
+            // the timers continue in the resumed realm
+            timers::clear_all_timers_q(realm);
+            Ok(suspended)
+        }
+        None => Err(JsError::new_string(format!("no such realm: {realm_id}"))),
+    })
+}
+
+fn restore_q(realm: &QuickJsRealmAdapter, suspended: SuspendedRealm) -> Result<(), JsError> {
+    for (index, module) in suspended.modules.iter().enumerate() {
+        let code = format!("import {};", Value::String(module.clone()));
+        realm.eval_module(Script::new(&format!("__resume_{index}.mes"), &code))?;
+    }
+    QuickJsRuntimeAdapter::do_with(|q_js_rt| q_js_rt.run_pending_jobs_if_any());
+
+    let global = get_global_q(realm);
+    for (name, value) in suspended.globals {
+        let value = realm.serde_value_to_value_adapter(value)?;
+        objects::set_property_q(realm, &global, name.as_str(), &value)?;
+    }
+
+    if suspended.timers.is_empty() {
+        return Ok(());
+    }
+    // an interval fires after its remaining delay first and then keeps its interval
+    let schedule = realm.eval(Script::new(
+        "__resume_timers.js",
+        "((fn, delay, interval, args) => interval === null ? setTimeout(fn, delay, ...args) : setTimeout(() => { setInterval(fn, interval, ...args); fn(...args); }, delay))",
+    ))?;
+    for timer in suspended.timers {
+        let callback = objects::get_property_q(realm, &global, timer.callback.as_str())?;
+        if !functions::is_function_q(realm, &callback) {
+            return Err(JsError::new_string(format!(
+                "timer callback {} is not a function after resuming",
+                timer.callback
+            )));
+        }
+        let args = realm.serde_value_to_value_adapter(Value::Array(timer.args))?;
+        let interval = realm.serde_value_to_value_adapter(match timer.interval_ms {
+            Some(interval) => Value::from(interval),
+            None => Value::Null,
+        })?;
+        functions::call_function_q(
+            realm,
+            &schedule,
+            &[
+                callback,
+                realm.serde_value_to_value_adapter(Value::from(timer.delay_ms))?,
+                interval,
+                args,
+            ],
+            None,
+        )?;
+    }
+    Ok(())
+}
+
+/// resume a suspended realm, must be called from the worker thread outside of do_with
+pub(crate) fn resume_realm(suspended: SuspendedRealm) -> Result<(), JsError> {
+    let realm_id = suspended.realm_id.clone();
+    if QuickJsRuntimeAdapter::do_with(|q_js_rt| q_js_rt.has_context(realm_id.as_str())) {
+        return Err(JsError::new_string(format!(
+            "realm {realm_id} already exists"
+        )));
+    }
+    QuickJsRuntimeAdapter::create_context(realm_id.as_str())?;
+    let res = QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+        restore_q(q_js_rt.get_context(realm_id.as_str()), suspended)
+    });
+    if res.is_err() {
+        // don't leave a half restored realm behind
+        QuickJsRuntimeAdapter::remove_context(realm_id.as_str());
+    }
+    res
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::modules::ScriptModuleLoader;
+    use crate::jsutils::suspend::SuspendedRealm;
+    use crate::jsutils::Script;
+    use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+    use futures::executor::block_on;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    struct TestLoader {}
+
+    impl ScriptModuleLoader for TestLoader {
+        fn normalize_path(
+            &self,
+            _realm: &QuickJsRealmAdapter,
+            _ref_path: &str,
+            path: &str,
+        ) -> Option<String> {
+            match path {
+                "counter.mes" => Some(path.to_string()),
+                _ => None,
+            }
+        }
+
+        fn load_module(&self, _realm: &QuickJsRealmAdapter, _absolute_path: &str) -> String {
+            "globalThis.tick = (step) => { globalThis.count += step; };".to_string()
+        }
+    }
+
+    #[test]
+    fn test_suspend_resume() {
+        let hook_calls = Arc::new(AtomicUsize::new(0));
+        let hook_calls2 = hook_calls.clone();
+        let rt = QuickJsRuntimeBuilder::new()
+            .script_module_loader(TestLoader {})
+            .realm_adapter_init_hook(move |_rt, realm| {
+                hook_calls2.fetch_add(1, Ordering::SeqCst);
+                realm.set_object_property(&realm.get_global()?, "hookValue", &realm.create_i32(1)?)
+            })
+            .build();
+        rt.create_realm("migrating")
+            .expect("could not create realm");
+        rt.eval_sync(
+            Some("migrating"),
+            Script::new(
+                "setup.js",
+                r#"
+                globalThis.count = 0;
+                globalThis.settings = {name: 'worker', tags: ['a', 'b']};
+                globalThis.pending = new Promise(() => {});
+                setTimeout(() => {}, 10000);
+                "#,
+            ),
+        )
+        .expect("script failed");
+        rt.eval_module_sync(
+            Some("migrating"),
+            Script::new(
+                "main.mes",
+                "import 'counter.mes';\nsetTimeout(tick, 200, 5);\nsetInterval(tick, 100000, 1);",
+            ),
+        )
+        .expect("module failed");
+
+        let suspended = block_on(rt.suspend_realm("migrating")).expect("suspend failed");
+        assert_eq!(suspended.globals["count"], 0);
+        assert_eq!(suspended.globals["settings"]["tags"][1], "b");
+        assert_eq!(suspended.modules, vec!["counter.mes".to_string()]);
+        assert_eq!(suspended.timers.len(), 2);
+        assert_eq!(suspended.timers[0].callback, "tick");
+        assert_eq!(suspended.timers[0].args, vec![serde_json::json!(5)]);
+        assert!(suspended.timers[0].delay_ms <= 200);
+        assert_eq!(suspended.timers[1].interval_ms, Some(100000));
+        let unsupported = |kind: &str, name: &str| {
+            suspended
+                .unsupported
+                .iter()
+                .any(|u| u.kind == kind && u.name == name)
+        };
+        assert!(unsupported("global", "tick"));
+        assert!(unsupported("global", "pending"));
+        assert!(unsupported("module", "main.mes"));
+        assert!(suspended
+            .unsupported
+            .iter()
+            .any(|u| u.kind == "timer" && u.reason.contains("not a global function")));
+        assert!(!suspended.is_complete());
+        // the runtime's own globals and the globals of the init hooks are not suspended
+        assert!(!suspended.globals.contains_key("console"));
+        assert!(!suspended.globals.contains_key("hookValue"));
+        // the init hooks only ran for the main realm and the suspended realm
+        assert_eq!(hook_calls.load(Ordering::SeqCst), 2);
+        // the timers of the suspended realm were stopped
+        assert!(block_on(rt.pending_timers("migrating"))
+            .expect("could not list timers")
+            .is_empty());
+
+        let blob = suspended.to_bytes().expect("could not serialize");
+        let resumed = SuspendedRealm::from_bytes(&blob).expect("invalid blob");
+
+        let other = QuickJsRuntimeBuilder::new()
+            .script_module_loader(TestLoader {})
+            .build();
+        block_on(other.resume_realm(resumed.clone())).expect("resume failed");
+        assert!(block_on(other.resume_realm(resumed)).is_err());
+        let res = other
+            .eval_sync(
+                Some("migrating"),
+                Script::new("check.js", "settings.name + ':' + count"),
+            )
+            .expect("script failed");
+        assert_eq!(res.get_str(), "worker:0");
+
+        std::thread::sleep(Duration::from_millis(500));
+        let res = other
+            .eval_sync(Some("migrating"), Script::new("check.js", "count"))
+            .expect("script failed");
+        assert_eq!(res.get_i32(), 5);
+        let res = rt
+            .eval_sync(Some("migrating"), Script::new("check.js", "count"))
+            .expect("script failed");
+        assert_eq!(res.get_i32(), 0);
+
+        assert!(block_on(rt.suspend_realm("nope")).is_err());
+        assert!(SuspendedRealm::from_bytes(b"{}").is_err());
+    }
+}
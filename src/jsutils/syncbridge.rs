@@ -0,0 +1,288 @@
+//! a blocking bridge which lets synchronous script use a value of async rust, see [register_sync_bridge](crate::facades::QuickJsRuntimeFacade::register_sync_bridge)
+//!
+//! **a bridged call stalls the EventLoop**: while the future runs no other job, timer or promise reaction of the runtime runs,
+//! prefer [set_async_function](crate::facades::QuickJsRuntimeFacade::set_async_function) and only use the bridge for legacy scripts which can not await
+//!
+//! * the bridge needs to be enabled with [allow_sync_bridge](crate::builder::QuickJsRuntimeBuilder::allow_sync_bridge)
+//! * the future runs on the executor of the runtime, the call waits at most `max_wait` and then throws a TimeoutError
+//! * the interrupt handler, the script timeout and the timeouts of evals are checked while the call waits, the call throws
+//!   an `InternalError: interrupted` when one of them fires
+//! * a bridge which is called while another bridged call waits (e.g. from a conversion of its arguments) throws an Error
+//! * the calls and the time they waited are counted in the [SyncBridgeStats] of the runtime, see
+//!   [sync_bridge_stats](crate::facades::QuickJsRuntimeFacade::sync_bridge_stats)
+//! * the future must not wait for the runtime itself (e.g. by evaluating a script), it would time out
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::Script;
+//! use quickjs_runtime::values::JsValueFacade;
+//! use std::time::Duration;
+//! let rt = QuickJsRuntimeBuilder::new().allow_sync_bridge().build();
+//! rt.register_sync_bridge(&["config"], "lookup", |args| {
+//!     let key = args[0].get_str().to_string();
+//!     async move { Ok(JsValueFacade::new_string(format!("value of {key}"))) }
+//! }, Duration::from_secs(1)).expect("register failed");
+//! let res = rt.eval_sync(None, Script::new("bridge.js", "config.lookup('port');")).expect("script failed");
+//! assert_eq!(res.get_str(), "value of port");
+//! ```
+
+use crate::jsutils::JsError;
+use crate::quickjs_utils::errors::timeout_error;
+use crate::quickjs_utils::{functions, interrupthandler};
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use crate::quickjsvalueadapter::QuickJsValueAdapter;
+use crate::values::JsValueFacade;
+use futures::Future;
+use std::cell::Cell;
+use std::rc::Rc;
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+// how often the interrupt handler and the timeouts are checked while a call waits
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+thread_local! {
+    // a bridged call is waiting in this thread
+    static WAITING: Cell<bool> = Cell::new(false);
+}
+
+/// the calls of the sync bridges of a runtime
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SyncBridgeStats {
+    /// the number of calls which waited for their future
+    pub calls: u64,
+    /// the calls which did not get a value within their max_wait
+    pub timeouts: u64,
+    /// the calls which were interrupted while they waited
+    pub interrupted: u64,
+    /// the calls which were refused because another bridged call was waiting
+    pub refused: u64,
+    /// the time the EventLoop was stalled by the calls
+    pub total_wait: Duration,
+    /// the longest wait of a single call
+    pub longest_wait: Duration,
+}
+
+struct WaitGuard {}
+
+impl WaitGuard {
+    fn enter() -> Option<Self> {
+        WAITING.with(|waiting| {
+            if waiting.replace(true) {
+                None
+            } else {
+                Some(Self {})
+            }
+        })
+    }
+}
+
+impl Drop for WaitGuard {
+    fn drop(&mut self) {
+        WAITING.with(|waiting| waiting.set(false));
+    }
+}
+
+enum Waited {
+    Done(Result<JsValueFacade, JsError>),
+    TimedOut,
+    Interrupted,
+}
+
+/// create a Function which blocks until the future of the handler produced a value
+pub(crate) fn new_bridge_function_q<F, R>(
+    realm: &QuickJsRealmAdapter,
+    name: &str,
+    handler: Rc<F>,
+    max_wait: Duration,
+) -> Result<QuickJsValueAdapter, JsError>
+where
+    F: Fn(Vec<JsValueFacade>) -> R + 'static,
+    R: Future<Output = Result<JsValueFacade, JsError>> + Send + 'static,
+{
+    let bridge_name = name.to_string();
+    functions::new_function_q(
+        realm,
+        name,
+        move |realm, _this, args| {
+            let _guard = match WaitGuard::enter() {
+                Some(guard) => guard,
+                None => {
+                    QuickJsRuntimeAdapter::do_with(|rt| {
+                        rt.sync_bridge_stats.borrow_mut().refused += 1;
+                    });
+                    return Err(JsError::new_string(format!(
+                        "sync bridge {bridge_name} can not be called while another sync bridge call is waiting"
+                    )));
+                }
+            };
+            let mut args_facades = vec![];
+            for arg in args {
+                args_facades.push(realm.to_js_value_facade(arg)?);
+            }
+            let producer = handler(args_facades);
+            let res = QuickJsRuntimeAdapter::do_with(|rt| {
+                wait_for(rt, realm, bridge_name.as_str(), producer, max_wait)
+            })?;
+            realm.from_js_value_facade(res)
+        },
+        1,
+    )
+}
+
+fn wait_for<R>(
+    rt: &QuickJsRuntimeAdapter,
+    realm: &QuickJsRealmAdapter,
+    name: &str,
+    producer: R,
+    max_wait: Duration,
+) -> Result<JsValueFacade, JsError>
+where
+    R: Future<Output = Result<JsValueFacade, JsError>> + Send + 'static,
+{
+    let executor = match realm.get_runtime_facade_inner().upgrade() {
+        Some(rti) => rti.get_executor().clone(),
+        None => return Err(JsError::new_str("runtime was dropped")),
+    };
+    let (tx, rx) = channel();
+    executor.spawn(Box::pin(async move {
+        let _ = tx.send(producer.await);
+    }));
+
+    let start = Instant::now();
+    let waited = loop {
+        let remaining = max_wait.saturating_sub(start.elapsed());
+        if remaining.is_zero() {
+            break Waited::TimedOut;
+        }
+        match rx.recv_timeout(remaining.min(POLL_INTERVAL)) {
+            Ok(res) => break Waited::Done(res),
+            Err(RecvTimeoutError::Timeout) => {
+                if interrupthandler::should_interrupt(rt) {
+                    break Waited::Interrupted;
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                break Waited::Done(Err(JsError::new_string(format!(
+                    "the future of sync bridge {name} was dropped"
+                ))))
+            }
+        }
+    };
+    let wait = start.elapsed();
+
+    let mut stats = rt.sync_bridge_stats.borrow_mut();
+    stats.calls += 1;
+    stats.total_wait += wait;
+    stats.longest_wait = stats.longest_wait.max(wait);
+    if wait >= POLL_INTERVAL {
+        log::warn!("sync bridge {} stalled the EventLoop for {:?}", name, wait);
+    }
+
+    match waited {
+        Waited::Done(res) => res,
+        Waited::TimedOut => {
+            stats.timeouts += 1;
+            Err(timeout_error(
+                format!("sync bridge {name}").as_str(),
+                max_wait,
+            ))
+        }
+        Waited::Interrupted => {
+            stats.interrupted += 1;
+            Err(JsError::new(
+                "InternalError".to_string(),
+                "interrupted".to_string(),
+                "".to_string(),
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::Script;
+    use crate::values::JsValueFacade;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_sync_bridge() {
+        let rt = QuickJsRuntimeBuilder::new().allow_sync_bridge().build();
+        rt.register_sync_bridge(
+            &["config"],
+            "lookup",
+            |args| {
+                let key = args[0].get_str().to_string();
+                async move {
+                    if key.eq("slow") {
+                        tokio::time::sleep(Duration::from_secs(5)).await;
+                    } else {
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                    }
+                    Ok(JsValueFacade::new_string(format!("value of {key}")))
+                }
+            },
+            Duration::from_millis(200),
+        )
+        .expect("register failed");
+
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "test_sync_bridge.js",
+                    "let a = config.lookup('port'); let b; try { config.lookup('slow'); } catch(e) { b = e.name; } a + '|' + b;",
+                ),
+            )
+            .expect("script failed");
+        assert_eq!(res.get_str(), "value of port|TimeoutError");
+
+        let stats = rt.sync_bridge_stats();
+        assert_eq!(stats.calls, 2);
+        assert_eq!(stats.timeouts, 1);
+        assert!(stats.total_wait >= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn test_sync_bridge_interrupted() {
+        let rt = QuickJsRuntimeBuilder::new()
+            .allow_sync_bridge()
+            .script_timeout(Duration::from_millis(100))
+            .build();
+        rt.register_sync_bridge(
+            &["config"],
+            "lookup",
+            |_args| async move {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                Ok(JsValueFacade::Null)
+            },
+            Duration::from_secs(5),
+        )
+        .expect("register failed");
+
+        let start = Instant::now();
+        let err = rt
+            .eval_sync(
+                None,
+                Script::new("test_sync_bridge.js", "config.lookup('a');"),
+            )
+            .expect_err("call was not interrupted");
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(err.get_message().contains("interrupted"));
+        assert_eq!(rt.sync_bridge_stats().interrupted, 1);
+    }
+
+    #[test]
+    fn test_sync_bridge_not_allowed() {
+        let rt = QuickJsRuntimeBuilder::new().build();
+        let res = rt.register_sync_bridge(
+            &["config"],
+            "lookup",
+            |_args| async move { Ok(JsValueFacade::Null) },
+            Duration::from_secs(1),
+        );
+        assert!(res.is_err());
+    }
+}
@@ -0,0 +1,706 @@
+//! scoping of the background work which is started by a script, see [eval_with_options](crate::facades::QuickJsRuntimeFacade::eval_with_options)
+//!
+//! an eval with options opens a task scope, the timers (setTimeout/setInterval) and the internal promises (e.g. of async native functions)
+//! which are created while the script runs are tracked in that scope
+//! the scope is transitive, timer callbacks and promise resolutions run in the scope of the task which started them so work they start is tracked as well
+//!
+//! when the eval is done the [BackgroundPolicy] decides what happens with the work which is still pending
+//!
+//! defaults for all evals can be set with [default_eval_options](crate::builder::QuickJsRuntimeBuilder::default_eval_options),
+//! the options of a call only override the fields they set, see [EvalOptions::merge]
+//! the evals, module evals and function invocations without options use the [timeout](EvalOptions::timeout) of the defaults,
+//! without defaults they run exactly as before
+//!
+//! # Limitations
+//! promise reactions are only tracked when they are run by a timer callback or by the resolution of an internal promise (or directly by the eval),
+//! a promise which is resolved from rust in another way (e.g. by a cached function which is invoked later) runs outside of the scope
+
+use crate::jsutils::{imports, timers, JsError, Script};
+use crate::quickjs_utils::errors::timeout_error;
+use crate::quickjs_utils::interrupthandler;
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use crate::values::JsValueFacade;
+use hirofa_utils::eventloop::EventLoop;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+/// what happens with the background work of a script when the eval is done
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BackgroundPolicy {
+    /// the work continues after the eval returns
+    #[default]
+    Detach,
+    /// the eval returns when all pending work is done, intervals need to be cleared by the script
+    Drain,
+    /// pending timers are cleared and pending internal promises are rejected when the eval returns
+    Cancel,
+}
+
+/// options for [eval_with_options](crate::facades::QuickJsRuntimeFacade::eval_with_options)
+///
+/// a field which is None is not set, it is taken from the [defaults](crate::builder::QuickJsRuntimeBuilder::default_eval_options) of the runtime
+/// # Example
+/// ```rust
+/// use quickjs_runtime::jsutils::taskscope::{BackgroundPolicy, EvalOptions};
+/// use std::time::Duration;
+/// let defaults = EvalOptions::new().background_policy(BackgroundPolicy::Drain).timeout(Duration::from_secs(1));
+/// let merged = defaults.merge(&EvalOptions::new().timeout(Duration::from_secs(5)));
+/// assert_eq!(merged.background_policy, Some(BackgroundPolicy::Drain));
+/// assert_eq!(merged.timeout, Some(Duration::from_secs(5)));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EvalOptions {
+    /// what happens with the background work when the eval is done, [BackgroundPolicy::Detach] when not set
+    pub background_policy: Option<BackgroundPolicy>,
+    /// the maximum time to wait for the work to drain, the remaining work is cancelled when the timeout passes
+    pub drain_timeout: Option<Duration>,
+    /// the maximum time the script may run, it is interrupted with a TimeoutError when the timeout passes
+    ///
+    /// the jobs the script queued (e.g. dynamic imports and the code after a top-level await of a module) run within the same timeout
+    pub timeout: Option<Duration>,
+    /// the specifiers of modules and the names of their exports which are in scope of the script, see [imports](crate::jsutils::imports)
+    pub module_imports: Option<Vec<(String, Vec<String>)>>,
+}
+
+impl EvalOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn background_policy(mut self, background_policy: BackgroundPolicy) -> Self {
+        self.background_policy = Some(background_policy);
+        self
+    }
+    pub fn drain_timeout(mut self, drain_timeout: Duration) -> Self {
+        self.drain_timeout = Some(drain_timeout);
+        self
+    }
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+    pub fn module_imports<S: Into<String>>(mut self, module_imports: Vec<(S, Vec<S>)>) -> Self {
+        self.module_imports = Some(
+            module_imports
+                .into_iter()
+                .map(|(specifier, names)| {
+                    (
+                        specifier.into(),
+                        names.into_iter().map(|name| name.into()).collect(),
+                    )
+                })
+                .collect(),
+        );
+        self
+    }
+    /// combine these options with the options of a call, the fields which are set in overrides replace those of self
+    pub fn merge(&self, overrides: &EvalOptions) -> EvalOptions {
+        EvalOptions {
+            background_policy: overrides.background_policy.or(self.background_policy),
+            drain_timeout: overrides.drain_timeout.or(self.drain_timeout),
+            timeout: overrides.timeout.or(self.timeout),
+            module_imports: overrides
+                .module_imports
+                .clone()
+                .or_else(|| self.module_imports.clone()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum ScopedTask {
+    Timeout(i32),
+    Interval(i32),
+    Promise(usize),
+}
+
+/// how the work of a scope ended
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ScopeEnd {
+    Drained,
+    TimedOut,
+    Cancelled,
+    RealmDropped,
+}
+
+struct TaskScope {
+    realm_id: String,
+    tasks: HashSet<ScopedTask>,
+    // a scope is closed when the eval is done, a closed scope is removed when its last task is done
+    closed: bool,
+    drain_waiter: Option<flume::Sender<ScopeEnd>>,
+}
+
+thread_local! {
+    static SCOPES: RefCell<HashMap<usize, TaskScope>> = RefCell::new(HashMap::new());
+    static CURRENT_SCOPE: Cell<Option<usize>> = Cell::new(None);
+    static SCOPE_COUNTER: Cell<usize> = Cell::new(0);
+}
+
+fn remove_scope(scope_id: usize, end: ScopeEnd) -> Option<TaskScope> {
+    let scope = SCOPES.with(|rc| rc.borrow_mut().remove(&scope_id));
+    if let Some(waiter) = scope.as_ref().and_then(|s| s.drain_waiter.as_ref()) {
+        let _ = waiter.try_send(end);
+    }
+    scope
+}
+
+pub(crate) fn open_scope(realm_id: &str) -> usize {
+    let scope_id = SCOPE_COUNTER.with(|c| {
+        c.set(c.get() + 1);
+        c.get()
+    });
+    SCOPES.with(|rc| {
+        rc.borrow_mut().insert(
+            scope_id,
+            TaskScope {
+                realm_id: realm_id.to_string(),
+                tasks: HashSet::new(),
+                closed: false,
+                drain_waiter: None,
+            },
+        )
+    });
+    scope_id
+}
+
+/// the scope of the task which is currently running
+pub(crate) fn current_scope() -> Option<usize> {
+    CURRENT_SCOPE.with(|c| c.get())
+}
+
+/// add a task to the current scope, returns the scope so the task can be run in it later
+pub(crate) fn track(task: ScopedTask) -> Option<usize> {
+    let scope_id = current_scope()?;
+    SCOPES.with(|rc| {
+        rc.borrow_mut()
+            .get_mut(&scope_id)
+            .map(|scope| scope.tasks.insert(task))
+    })?;
+    Some(scope_id)
+}
+
+/// remove a task from its scope
+pub(crate) fn untrack(scope_id: usize, task: ScopedTask) {
+    let done = SCOPES.with(|rc| match rc.borrow_mut().get_mut(&scope_id) {
+        Some(scope) => {
+            scope.tasks.remove(&task);
+            scope.closed && scope.tasks.is_empty()
+        }
+        None => false,
+    });
+    if done {
+        remove_scope(scope_id, ScopeEnd::Drained);
+    }
+}
+
+/// remove a timer from the scope it was created in, e.g. when it is cleared by script
+pub(crate) fn untrack_timer(task: ScopedTask) {
+    let scope_id = SCOPES.with(|rc| {
+        rc.borrow()
+            .iter()
+            .find(|(_id, scope)| scope.tasks.contains(&task))
+            .map(|(id, _scope)| *id)
+    });
+    if let Some(scope_id) = scope_id {
+        untrack(scope_id, task);
+    }
+}
+
+/// runs a task in a scope, when dropped the pending jobs are run (still in the scope) and the finished task is removed from the scope
+pub(crate) struct ScopeGuard {
+    scope_id: Option<usize>,
+    previous: Option<usize>,
+    finished_task: Option<ScopedTask>,
+}
+
+impl ScopeGuard {
+    pub(crate) fn enter(scope_id: Option<usize>, finished_task: Option<ScopedTask>) -> Self {
+        let previous = CURRENT_SCOPE.with(|c| c.replace(scope_id));
+        Self {
+            scope_id,
+            previous,
+            finished_task,
+        }
+    }
+}
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        if let Some(scope_id) = self.scope_id {
+            QuickJsRuntimeAdapter::do_with(|q_js_rt| q_js_rt.run_pending_jobs_if_any());
+            if let Some(task) = self.finished_task {
+                untrack(scope_id, task);
+            }
+        }
+        CURRENT_SCOPE.with(|c| c.set(self.previous));
+    }
+}
+
+// the jobs which a script queued (e.g. a dynamic import or the rest of a module after a top-level await) run within the remaining time
+// of the script, the jobs which are left when the time is up run later without the deadline
+fn run_queued_jobs(q_js_rt: &QuickJsRuntimeAdapter, deadline: Instant) {
+    while Instant::now() < deadline && q_js_rt.has_pending_jobs() {
+        if let Err(e) = q_js_rt.run_pending_job() {
+            log::error!("run_pending_job failed: {}", e);
+        }
+    }
+}
+
+/// run a script with a timeout, the script is interrupted when the timeout passes, an enclosing timeout which passes earlier is kept
+///
+/// the jobs which the script queued, like the evaluation of dynamically imported modules, run within the same timeout
+pub(crate) fn run_with_timeout<R, F>(
+    q_js_rt: &QuickJsRuntimeAdapter,
+    timeout: Option<Duration>,
+    runner: F,
+) -> Result<R, JsError>
+where
+    F: FnOnce() -> Result<R, JsError>,
+{
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return runner(),
+    };
+    let deadline = Instant::now() + timeout;
+    let previous = q_js_rt.interrupt_deadline.get();
+    q_js_rt.interrupt_deadline.set(Some(match previous {
+        Some(previous) => previous.min(deadline),
+        None => deadline,
+    }));
+    interrupthandler::init(q_js_rt);
+    let res = runner();
+    if previous.is_none() {
+        run_queued_jobs(q_js_rt, deadline);
+    }
+    q_js_rt.interrupt_deadline.set(previous);
+    res.map_err(|err| {
+        if Instant::now() >= deadline {
+            timeout_error("script", timeout)
+        } else {
+            err
+        }
+    })
+}
+
+/// run a script without options with the timeout of the default options of the runtime
+pub(crate) fn run_with_defaults<R, F>(
+    q_js_rt: &QuickJsRuntimeAdapter,
+    runner: F,
+) -> Result<R, JsError>
+where
+    F: FnOnce() -> Result<R, JsError>,
+{
+    run_with_timeout(q_js_rt, q_js_rt.default_eval_options.timeout, runner)
+}
+
+fn drain_timeout_error(timeout: Duration) -> JsError {
+    JsError::new(
+        "TimeoutError".to_string(),
+        format!("background work did not drain within {timeout:?}"),
+        "".to_string(),
+    )
+}
+
+/// clear the timers and reject the internal promises of a scope
+fn cancel_scope(realm: &QuickJsRealmAdapter, scope_id: usize, reason: &JsError, end: ScopeEnd) {
+    let scope = match remove_scope(scope_id, end) {
+        Some(scope) => scope,
+        None => return,
+    };
+    for task in scope.tasks {
+        match task {
+            ScopedTask::Timeout(_) | ScopedTask::Interval(_) => {
+                timers::clear_timer_q(realm, task);
+            }
+            ScopedTask::Promise(id) => {
+                if let Some(prom_ref) = realm.consume_cached_promise(id) {
+                    let res = realm
+                        .create_error(reason.get_name(), reason.get_message(), reason.get_stack())
+                        .and_then(|err_ref| prom_ref.js_promise_reject(realm, &err_ref));
+                    if let Err(e) = res {
+                        log::error!(
+                            "[{}] could not reject cancelled promise: {}",
+                            realm.get_realm_id(),
+                            e
+                        );
+                    }
+                }
+            }
+        }
+    }
+    QuickJsRuntimeAdapter::do_with(|q_js_rt| q_js_rt.run_pending_jobs_if_any());
+}
+
+/// remove the scopes of a realm which is dropped, its timers are cleared by the realm
+pub(crate) fn remove_realm_scopes(realm_id: &str) {
+    let scope_ids: Vec<usize> = SCOPES.with(|rc| {
+        rc.borrow()
+            .iter()
+            .filter(|(_id, scope)| scope.realm_id == realm_id)
+            .map(|(id, _scope)| *id)
+            .collect()
+    });
+    for scope_id in scope_ids {
+        remove_scope(scope_id, ScopeEnd::RealmDropped);
+    }
+}
+
+/// close a scope which was opened for a single job, its pending work is cancelled with the reason or detached when there is none
+pub(crate) fn end_scope(
+    realm: &QuickJsRealmAdapter,
+    scope_id: usize,
+    cancel_reason: Option<&JsError>,
+) {
+    if let Some(reason) = cancel_reason {
+        cancel_scope(realm, scope_id, reason, ScopeEnd::Cancelled);
+        return;
+    }
+    let done = SCOPES.with(|rc| match rc.borrow_mut().get_mut(&scope_id) {
+        Some(scope) => {
+            scope.closed = true;
+            scope.tasks.is_empty()
+        }
+        None => false,
+    });
+    if done {
+        remove_scope(scope_id, ScopeEnd::Drained);
+    }
+}
+
+/// the number of pending timers and internal promises which were started by evals with options in a realm
+pub(crate) fn pending_tasks(realm_id: &str) -> usize {
+    SCOPES.with(|rc| {
+        rc.borrow()
+            .values()
+            .filter(|scope| scope.realm_id == realm_id)
+            .map(|scope| scope.tasks.len())
+            .sum()
+    })
+}
+
+/// eval a script in a new scope and apply the policy, when draining a receiver is returned which receives how the work ended
+pub(crate) fn eval_in_scope(
+    q_js_rt: &QuickJsRuntimeAdapter,
+    realm: &QuickJsRealmAdapter,
+    script: Script,
+    options: &EvalOptions,
+) -> (
+    Result<JsValueFacade, JsError>,
+    Option<flume::Receiver<ScopeEnd>>,
+) {
+    let scope_id = open_scope(realm.get_realm_id());
+    let res = {
+        let _guard = ScopeGuard::enter(Some(scope_id), None);
+        run_with_timeout(q_js_rt, options.timeout, || {
+            let value = match &options.module_imports {
+                Some(module_imports) => {
+                    let bindings = imports::import_bindings_q(
+                        q_js_rt,
+                        realm,
+                        script.get_path(),
+                        module_imports,
+                    )?;
+                    realm.eval_with_scope(script, bindings)?
+                }
+                None => realm.eval(script)?,
+            };
+            realm.to_js_value_facade(&value)
+        })
+    };
+
+    let background_policy = options.background_policy.unwrap_or_default();
+    match background_policy {
+        BackgroundPolicy::Cancel => {
+            let reason = JsError::new(
+                "CancelledError".to_string(),
+                "the eval which started this work is done".to_string(),
+                "".to_string(),
+            );
+            cancel_scope(realm, scope_id, &reason, ScopeEnd::Cancelled);
+            (res, None)
+        }
+        BackgroundPolicy::Detach | BackgroundPolicy::Drain => {
+            let (tx, rx) = flume::bounded(1);
+            let done = SCOPES.with(|rc| match rc.borrow_mut().get_mut(&scope_id) {
+                Some(scope) => {
+                    scope.closed = true;
+                    if background_policy == BackgroundPolicy::Drain {
+                        scope.drain_waiter = Some(tx);
+                    }
+                    scope.tasks.is_empty()
+                }
+                None => true,
+            });
+            if done {
+                remove_scope(scope_id, ScopeEnd::Drained);
+                return (res, None);
+            }
+            if background_policy == BackgroundPolicy::Detach {
+                return (res, None);
+            }
+            if let Some(timeout) = options.drain_timeout {
+                let realm_id = realm.get_realm_id().to_string();
+                // a no-op when the scope was already drained
+                EventLoop::add_timeout(
+                    move || {
+                        QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+                            if let Some(realm) = q_js_rt.get_realm(realm_id.as_str()) {
+                                cancel_scope(
+                                    realm,
+                                    scope_id,
+                                    &drain_timeout_error(timeout),
+                                    ScopeEnd::TimedOut,
+                                );
+                            }
+                        })
+                    },
+                    timeout,
+                );
+            }
+            (res, Some(rx))
+        }
+    }
+}
+
+/// see [eval_with_options](crate::facades::QuickJsRuntimeFacade::eval_with_options)
+pub(crate) async fn await_drain(
+    drain: Option<flume::Receiver<ScopeEnd>>,
+    options: &EvalOptions,
+) -> Result<(), JsError> {
+    let drain = match drain {
+        Some(drain) => drain,
+        None => return Ok(()),
+    };
+    match drain.recv_async().await {
+        Ok(ScopeEnd::Drained) => Ok(()),
+        Ok(ScopeEnd::TimedOut) => Err(drain_timeout_error(
+            options.drain_timeout.unwrap_or_default(),
+        )),
+        _ => Err(JsError::new_str(
+            "realm was dropped before the background work was drained",
+        )),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::taskscope::{BackgroundPolicy, EvalOptions};
+    use crate::jsutils::{JsError, Script};
+    use crate::values::JsValueFacade;
+    use futures::executor::block_on;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_background_policy() {
+        let rt = QuickJsRuntimeBuilder::new().build();
+        let eval = |code: &str, background_policy, drain_timeout| {
+            block_on(rt.eval_with_options(
+                None,
+                Script::new("test_background_policy.js", code),
+                EvalOptions {
+                    background_policy: Some(background_policy),
+                    drain_timeout,
+                    ..Default::default()
+                },
+            ))
+        };
+        let read = |code: &str| {
+            rt.eval_sync(None, Script::new("test_background_policy.js", code))
+                .expect("script failed")
+        };
+
+        // work started by timers is tracked transitively
+        eval(
+            "globalThis.log = []; setTimeout(() => { log.push('a'); Promise.resolve().then(() => setTimeout(() => log.push('b'), 10)); }, 10);",
+            BackgroundPolicy::Drain,
+            None,
+        )
+        .expect("eval failed");
+        assert_eq!(read("log.join(',')").get_str(), "a,b");
+        assert_eq!(rt.pending_background_tasks("__main__"), 0);
+
+        eval(
+            "setTimeout(() => log.push('c'), 10); setInterval(() => log.push('d'), 10);",
+            BackgroundPolicy::Cancel,
+            None,
+        )
+        .expect("eval failed");
+        assert_eq!(rt.pending_background_tasks("__main__"), 0);
+
+        eval(
+            "globalThis.detached = setTimeout(() => log.push('e'), 50);",
+            BackgroundPolicy::Detach,
+            None,
+        )
+        .expect("eval failed");
+        assert_eq!(rt.pending_background_tasks("__main__"), 1);
+        read("clearTimeout(detached);");
+        assert_eq!(rt.pending_background_tasks("__main__"), 0);
+
+        // an interval never drains
+        let err = eval(
+            "setInterval(() => log.push('f'), 10);",
+            BackgroundPolicy::Drain,
+            Some(Duration::from_millis(50)),
+        )
+        .expect_err("drain should time out");
+        assert_eq!(err.get_name(), "TimeoutError");
+        assert_eq!(rt.pending_background_tasks("__main__"), 0);
+
+        // the cancelled timers and the interval do not run after the eval
+        let len = read("log.length").get_i32();
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(read("log.length").get_i32(), len);
+        let log = read("log.join(',')");
+        assert!(log.get_str().starts_with("a,b,f"), "{}", log.get_str());
+        assert!(
+            !log.get_str().contains(['c', 'd', 'e']),
+            "{}",
+            log.get_str()
+        );
+    }
+
+    #[test]
+    fn test_merge_eval_options() {
+        let second = Duration::from_secs(1);
+        let unset = EvalOptions::new();
+        let set = EvalOptions::new()
+            .background_policy(BackgroundPolicy::Drain)
+            .drain_timeout(second)
+            .timeout(second);
+        let other = EvalOptions::new()
+            .background_policy(BackgroundPolicy::Cancel)
+            .drain_timeout(second * 2)
+            .timeout(second * 2);
+
+        // unset fields never override
+        assert_eq!(unset.merge(&unset), unset);
+        assert_eq!(set.merge(&unset), set);
+        assert_eq!(unset.merge(&set), set);
+        assert_eq!(set.merge(&other), other);
+        assert_eq!(other.merge(&set), set);
+
+        // fields are merged one by one
+        let partial = EvalOptions::new().timeout(second * 3);
+        let merged = set.merge(&partial);
+        assert_eq!(merged.background_policy, Some(BackgroundPolicy::Drain));
+        assert_eq!(merged.drain_timeout, Some(second));
+        assert_eq!(merged.timeout, Some(second * 3));
+    }
+
+    #[test]
+    fn test_default_eval_options() {
+        let endless = "while (true) {}";
+        let timed_out = |res: Result<JsValueFacade, JsError>| matches!(res, Err(err) if err.get_name() == "TimeoutError");
+
+        let rt = QuickJsRuntimeBuilder::new()
+            .default_eval_options(
+                EvalOptions::new()
+                    .background_policy(BackgroundPolicy::Drain)
+                    .timeout(Duration::from_millis(50)),
+            )
+            .build();
+        rt.eval_sync(
+            None,
+            Script::new(
+                "test_default_eval_options.js",
+                "function spin() { while (true) {} }",
+            ),
+        )
+        .expect("script failed");
+
+        // the evals, module evals and function invocations without options use the default timeout
+        let start = Instant::now();
+        assert!(timed_out(rt.eval_sync(
+            None,
+            Script::new("test_default_eval_options.js", endless)
+        )));
+        assert!(timed_out(block_on(rt.eval(
+            None,
+            Script::new("test_default_eval_options.js", endless)
+        ))));
+        // depending on the engine an interrupted module fails or returns a rejected promise, either way it returns
+        let _ = rt.eval_module_sync(None, Script::new("test_default_eval_options.mes", endless));
+        assert!(timed_out(rt.invoke_function_sync(
+            None,
+            &[],
+            "spin",
+            vec![]
+        )));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        // the deadline is reset after a timeout
+        assert_eq!(
+            rt.eval_sync(None, Script::new("test_default_eval_options.js", "1 + 1"))
+                .expect("script failed")
+                .get_i32(),
+            2
+        );
+
+        // the default policy drains
+        block_on(rt.eval_with_options(
+            None,
+            Script::new(
+                "test_default_eval_options.js",
+                "globalThis.a = 1; setTimeout(() => a++, 10);",
+            ),
+            EvalOptions::new(),
+        ))
+        .expect("eval failed");
+        assert_eq!(
+            rt.eval_sync(None, Script::new("test_default_eval_options.js", "a"))
+                .expect("script failed")
+                .get_i32(),
+            2
+        );
+
+        // the options of a call override the defaults
+        let res = block_on(rt.eval_with_options(
+            None,
+            Script::new(
+                "test_default_eval_options.js",
+                "let end = Date.now() + 100; while (Date.now() < end) {} setTimeout(() => a++, 10); a;",
+            ),
+            EvalOptions::new()
+                .timeout(Duration::from_secs(5))
+                .background_policy(BackgroundPolicy::Cancel),
+        ))
+        .expect("eval failed");
+        assert_eq!(res.get_i32(), 2);
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(
+            rt.eval_sync(None, Script::new("test_default_eval_options.js", "a"))
+                .expect("script failed")
+                .get_i32(),
+            2
+        );
+        assert!(timed_out(block_on(rt.eval_with_options(
+            None,
+            Script::new("test_default_eval_options.js", endless),
+            EvalOptions::new().background_policy(BackgroundPolicy::Detach),
+        ))));
+
+        // without defaults nothing is interrupted and evals with options detach
+        let rt = QuickJsRuntimeBuilder::new().build();
+        rt.eval_sync(
+            None,
+            Script::new(
+                "test_default_eval_options.js",
+                "let end = Date.now() + 100; while (Date.now() < end) {}",
+            ),
+        )
+        .expect("script failed");
+        block_on(rt.eval_with_options(
+            None,
+            Script::new(
+                "test_default_eval_options.js",
+                "globalThis.detached = setTimeout(() => {}, 50);",
+            ),
+            EvalOptions::new(),
+        ))
+        .expect("eval failed");
+        assert_eq!(rt.pending_background_tasks("__main__"), 1);
+    }
+}
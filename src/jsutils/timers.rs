@@ -0,0 +1,176 @@
+//! listing and clearing the timers of a realm, see [pending_timers](crate::facades::QuickJsRuntimeFacade::pending_timers)
+//!
+//! the timers which were started by scripts with setTimeout and setInterval are listed, e.g. to check that a test did not leak any
+//!
+//! * a cleared timer is never called, also when it was due at the moment it was cleared
+//! * dropping a realm clears its timers the same way as [clear_all_timers](crate::facades::QuickJsRuntimeFacade::clear_all_timers)
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::timers::TimerKind;
+//! use quickjs_runtime::jsutils::Script;
+//! use futures::executor::block_on;
+//! let rt = QuickJsRuntimeBuilder::new().build();
+//! rt.eval_sync(None, Script::new("leak.js", "setInterval(() => {}, 1000);")).expect("script failed");
+//! let timers = block_on(rt.pending_timers("__main__")).expect("no timers");
+//! assert_eq!(timers.len(), 1);
+//! assert_eq!(timers[0].kind, TimerKind::Interval);
+//! assert_eq!(block_on(rt.clear_all_timers("__main__")).expect("clear failed"), 1);
+//! ```
+
+use crate::jsutils::suspend;
+use crate::jsutils::taskscope::{self, ScopedTask};
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use hirofa_utils::eventloop::EventLoop;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimerKind {
+    Timeout,
+    Interval,
+}
+
+/// a timer of a realm which did not fire yet, or an interval which was not cleared
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct TimerInfo {
+    /// the id which was returned by setTimeout or setInterval
+    pub id: i32,
+    pub kind: TimerKind,
+    /// the time until the timer fires next, for an interval this is its next deadline
+    pub remaining: Duration,
+    pub interval: Option<Duration>,
+    /// where the timer was created, only when [async_stack_traces](crate::builder::QuickJsRuntimeBuilder::async_stack_traces) are enabled
+    pub creation_stack: Option<String>,
+}
+
+/// get the timers of a realm, the timer which fires first comes first
+pub(crate) fn pending_timers_q(realm: &QuickJsRealmAdapter) -> Vec<TimerInfo> {
+    let now = Instant::now();
+    let mut timers: Vec<TimerInfo> = realm
+        .timer_schedules
+        .borrow()
+        .iter()
+        .filter_map(|(task, schedule)| {
+            let (id, kind) = match task {
+                ScopedTask::Timeout(id) => (*id, TimerKind::Timeout),
+                ScopedTask::Interval(id) => (*id, TimerKind::Interval),
+                ScopedTask::Promise(_) => return None,
+            };
+            Some(TimerInfo {
+                id,
+                kind,
+                remaining: schedule.due.saturating_duration_since(now),
+                interval: schedule.interval,
+                creation_stack: schedule.creation_stack.clone(),
+            })
+        })
+        .collect();
+    timers.sort_by_key(|timer| (timer.remaining, timer.id));
+    timers
+}
+
+/// clear a timer of a realm, the callback of a cleared timer is skipped when it was already due
+///
+/// the ids are unique in the runtime, an id of another realm or of a timer which already fired is ignored
+pub(crate) fn clear_timer_q(realm: &QuickJsRealmAdapter, task: ScopedTask) {
+    match task {
+        ScopedTask::Timeout(id) => {
+            if !realm.timeout_ids.borrow_mut().remove(&id) {
+                return;
+            }
+            EventLoop::clear_timeout(id);
+        }
+        ScopedTask::Interval(id) => {
+            if !realm.interval_ids.borrow_mut().remove(&id) {
+                return;
+            }
+            EventLoop::clear_interval(id);
+        }
+        ScopedTask::Promise(_) => return,
+    }
+    suspend::untrack_timer(realm, task);
+    taskscope::untrack_timer(task);
+}
+
+/// clear all timers which were started by scripts in a realm, returns the number of cleared timers
+pub(crate) fn clear_all_timers_q(realm: &QuickJsRealmAdapter) -> usize {
+    let tasks: Vec<ScopedTask> = realm.timer_schedules.borrow().keys().copied().collect();
+    for task in &tasks {
+        clear_timer_q(realm, *task);
+    }
+    tasks.len()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::timers::{clear_all_timers_q, TimerKind};
+    use crate::jsutils::Script;
+    use futures::executor::block_on;
+    use std::time::Duration;
+
+    #[test]
+    fn test_pending_timers() {
+        let rt = QuickJsRuntimeBuilder::new()
+            .async_stack_traces(true, 8)
+            .build();
+        rt.eval_sync(
+            None,
+            Script::new(
+                "timers.js",
+                "globalThis.calls = 0; function start() { setInterval(() => {calls++;}, 50); setTimeout(() => {calls += 100;}, 5000); } start();",
+            ),
+        )
+        .expect("script failed");
+        let timers = block_on(rt.pending_timers("__main__")).expect("no timers");
+        assert_eq!(timers.len(), 2);
+        assert_eq!(timers[0].kind, TimerKind::Interval);
+        assert_eq!(timers[0].interval, Some(Duration::from_millis(50)));
+        assert!(timers[0].remaining <= Duration::from_millis(50));
+        assert_eq!(timers[1].kind, TimerKind::Timeout);
+        assert!(timers[1]
+            .creation_stack
+            .as_deref()
+            .unwrap_or_default()
+            .contains("start"));
+
+        // the deadline of an interval moves after it fired
+        std::thread::sleep(Duration::from_millis(80));
+        let timers = block_on(rt.pending_timers("__main__")).expect("no timers");
+        assert_eq!(timers[0].kind, TimerKind::Interval);
+        assert!(timers[0].remaining > Duration::ZERO);
+
+        // clearing while the interval is due skips its callback
+        let res = rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            std::thread::sleep(Duration::from_millis(60));
+            let realm = q_js_rt.get_main_realm();
+            let calls = realm
+                .eval(Script::new("calls.js", "calls;"))
+                .expect("script failed");
+            (calls.to_i32(), clear_all_timers_q(realm))
+        });
+        assert_eq!(res.1, 2);
+        std::thread::sleep(Duration::from_millis(100));
+        let calls = rt
+            .eval_sync(None, Script::new("calls.js", "calls;"))
+            .expect("script failed");
+        assert_eq!(calls.get_i32(), res.0);
+        assert!(block_on(rt.pending_timers("__main__"))
+            .expect("no timers")
+            .is_empty());
+
+        // dropping a realm clears its timers with the same path
+        let realm = rt.create_realm("timers_realm").expect("no realm");
+        rt.eval_in_sync(&realm, Script::new("t.js", "setTimeout(() => {}, 5000);"))
+            .expect("script failed");
+        assert_eq!(
+            block_on(rt.clear_all_timers("timers_realm")).expect("clear failed"),
+            1
+        );
+        rt.eval_in_sync(&realm, Script::new("t.js", "setTimeout(() => {}, 5000);"))
+            .expect("script failed");
+        rt.drop_realm(&realm).expect("drop failed");
+        assert!(block_on(rt.pending_timers("timers_realm")).is_err());
+    }
+}
@@ -0,0 +1,302 @@
+//! evaluating a script which is rolled back when it fails, see [eval_transactional](crate::facades::QuickJsRuntimeFacade::eval_transactional)
+//!
+//! before the script runs the own properties of the global object are recorded, when the script throws or when the
+//! [validate](TransactionOptions::validate) callback rejects the result the recorded properties are restored, globals which were added are
+//! removed and the timers and internal promises which were started by the script are cancelled
+//!
+//! by default only the global properties themselves are recorded, with [deep](TransactionOptions::deep) the objects, arrays, Maps, Sets and
+//! typed arrays which can be reached from the enumerable globals are recorded as well and restored in place, so references to them stay valid
+//!
+//! # Limitations
+//! some state can not be rolled back:
+//! * variables which are captured by closures
+//! * the native state of proxy instances and the state of modules
+//! * top level `let`, `const` and `class` declarations of the script, these are not properties of the global object
+//! * the result of the script is not awaited, the reactions of a returned promise which run after the eval are not part of the transaction
+//!
+//! properties which the rollback can not restore or remove (e.g. globals declared with `var` or `function`, which can not be deleted)
+//! are reported, the eval then fails with an `IncompleteRollback` error which lists them
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::transaction::TransactionOptions;
+//! use quickjs_runtime::jsutils::Script;
+//! use futures::executor::block_on;
+//! let rt = QuickJsRuntimeBuilder::new().build();
+//! rt.eval_sync(None, Script::new("state.js", "globalThis.balance = 10;")).expect("script failed");
+//! let res = block_on(rt.eval_transactional(None, Script::new("rule.js", "balance -= 25; if (balance < 0) throw Error('overdrawn');"), TransactionOptions::new()));
+//! assert!(res.is_err());
+//! let balance = rt.eval_sync(None, Script::new("check.js", "balance;")).expect("script failed");
+//! assert_eq!(balance.get_i32(), 10);
+//! ```
+
+use crate::jsutils::{taskscope, JsError, Script};
+use crate::quickjs_utils::compile::{compile, run_compiled_function};
+use crate::quickjs_utils::functions;
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use crate::quickjsvalueadapter::QuickJsValueAdapter;
+use crate::values::JsValueFacade;
+
+/// a callback which inspects the result of a transactional eval, the eval is rolled back when it returns an error
+pub type TransactionValidator =
+    Box<dyn FnOnce(&QuickJsRealmAdapter, &QuickJsValueAdapter) -> Result<(), JsError> + Send>;
+
+/// options for [eval_transactional](crate::facades::QuickJsRuntimeFacade::eval_transactional)
+#[derive(Default)]
+pub struct TransactionOptions {
+    /// record the objects which can be reached from the globals, not just the globals
+    pub deep: bool,
+    pub validate: Option<TransactionValidator>,
+}
+
+impl TransactionOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn deep(mut self) -> Self {
+        self.deep = true;
+        self
+    }
+    pub fn validate<F>(mut self, validate: F) -> Self
+    where
+        F: FnOnce(&QuickJsRealmAdapter, &QuickJsValueAdapter) -> Result<(), JsError>
+            + Send
+            + 'static,
+    {
+        self.validate = Some(Box::new(validate));
+        self
+    }
+}
+
+// returns a snapshot with a rollback function, rollback returns the properties it could not restore
+const SNAPSHOT_SCRIPT: &str = r#"(function (deep) {
+    const records = [];
+    const seen = new Set();
+    const describe = (path, key) => (path ? path + "." : "") + String(key);
+    const record = (obj, path, followAll) => {
+        if (seen.has(obj)) {
+            return;
+        }
+        seen.add(obj);
+        const entries = Reflect.ownKeys(obj).map((key) => [key, Reflect.getOwnPropertyDescriptor(obj, key)]);
+        const rec = {obj, path, entries};
+        if (obj instanceof Map) {
+            rec.map = Array.from(Map.prototype.entries.call(obj));
+        } else if (obj instanceof Set) {
+            rec.set = Array.from(Set.prototype.values.call(obj));
+        } else if (ArrayBuffer.isView(obj) && !(obj instanceof DataView)) {
+            rec.view = obj.slice();
+        }
+        records.push(rec);
+        if (!deep) {
+            return;
+        }
+        const follow = (value, childPath) => {
+            if (typeof value === "object" && value !== null) {
+                record(value, childPath, true);
+            }
+        };
+        for (const [key, desc] of entries) {
+            if ("value" in desc && (followAll || desc.enumerable)) {
+                follow(desc.value, describe(path, key));
+            }
+        }
+        if (rec.map) {
+            rec.map.forEach(([key, value]) => follow(value, describe(path, "get(" + String(key) + ")")));
+        }
+    };
+    const same = (a, b) => Object.is(a.value, b.value) && a.get === b.get && a.set === b.set
+        && a.writable === b.writable && a.enumerable === b.enumerable && a.configurable === b.configurable;
+    record(globalThis, "", false);
+    return {
+        rollback() {
+            const problems = [];
+            for (let i = records.length - 1; i >= 0; i--) {
+                const {obj, path, entries} = records[i];
+                const before = new Map(entries);
+                for (const key of Reflect.ownKeys(obj)) {
+                    if (!before.has(key) && !Reflect.deleteProperty(obj, key)) {
+                        problems.push(describe(path, key));
+                    }
+                }
+                for (const [key, desc] of entries) {
+                    const now = Reflect.getOwnPropertyDescriptor(obj, key);
+                    if (!(now && same(now, desc)) && !Reflect.defineProperty(obj, key, desc)) {
+                        problems.push(describe(path, key));
+                    }
+                }
+                const rec = records[i];
+                if (rec.map) {
+                    Map.prototype.clear.call(obj);
+                    rec.map.forEach(([key, value]) => Map.prototype.set.call(obj, key, value));
+                } else if (rec.set) {
+                    Set.prototype.clear.call(obj);
+                    rec.set.forEach((value) => Set.prototype.add.call(obj, value));
+                } else if (rec.view) {
+                    obj.set(rec.view);
+                }
+            }
+            return problems;
+        }
+    };
+})"#;
+
+fn snapshot_q(realm: &QuickJsRealmAdapter, deep: bool) -> Result<QuickJsValueAdapter, JsError> {
+    // compiled directly so the pre-processors and coverage do not touch the helper
+    let snapshot_fn = unsafe {
+        let compiled = compile(
+            realm.context,
+            Script::new("__transaction.js", SNAPSHOT_SCRIPT),
+        )?;
+        run_compiled_function(realm.context, &compiled)?
+    };
+    functions::call_function_q(realm, &snapshot_fn, &[realm.create_boolean(deep)?], None)
+}
+
+fn rollback_q(
+    realm: &QuickJsRealmAdapter,
+    snapshot: &QuickJsValueAdapter,
+) -> Result<Vec<String>, JsError> {
+    let problems = functions::invoke_member_function_q(realm, snapshot, "rollback", &[])?;
+    serde_json::from_value(realm.value_adapter_to_serde_value(&problems)?)
+        .map_err(|e| JsError::new_string(format!("could not read rollback problems: {e}")))
+}
+
+/// eval a script and roll back the realm when it fails
+pub(crate) fn eval_transactional_q(
+    q_js_rt: &QuickJsRuntimeAdapter,
+    realm: &QuickJsRealmAdapter,
+    script: Script,
+    options: TransactionOptions,
+) -> Result<JsValueFacade, JsError> {
+    let snapshot = snapshot_q(realm, options.deep)?;
+    let scope_id = taskscope::open_scope(realm.get_realm_id());
+    let res = {
+        let _guard = taskscope::ScopeGuard::enter(Some(scope_id), None);
+        taskscope::run_with_defaults(q_js_rt, || {
+            let value = realm.eval(script)?;
+            if let Some(validate) = options.validate {
+                validate(realm, &value)?;
+            }
+            realm.to_js_value_facade(&value)
+        })
+    };
+    let err = match res {
+        Ok(value) => {
+            taskscope::end_scope(realm, scope_id, None);
+            return Ok(value);
+        }
+        Err(err) => err,
+    };
+
+    let reason = JsError::new(
+        "CancelledError".to_string(),
+        "the transaction which started this work was rolled back".to_string(),
+        "".to_string(),
+    );
+    taskscope::end_scope(realm, scope_id, Some(&reason));
+    let problems = rollback_q(realm, &snapshot)?;
+    if problems.is_empty() {
+        return Err(err);
+    }
+    log::warn!(
+        "[{}] incomplete rollback, could not restore: {}",
+        realm.get_realm_id(),
+        problems.join(", ")
+    );
+    Err(JsError::new(
+        "IncompleteRollback".to_string(),
+        format!(
+            "{}: {} (not rolled back: {})",
+            err.get_name(),
+            err.get_message(),
+            problems.join(", ")
+        ),
+        err.get_stack().to_string(),
+    ))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::transaction::TransactionOptions;
+    use crate::jsutils::{JsError, Script};
+    use crate::quickjs_utils::primitives;
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_eval_transactional() {
+        let rt = QuickJsRuntimeBuilder::new().build();
+        rt.eval_sync(
+            None,
+            Script::new(
+                "tx_state.js",
+                "globalThis.state = {count: 1, items: [1, 2], tags: new Map([['a', 1]])}; globalThis.ref = state.items;",
+            ),
+        )
+        .expect("script failed");
+        let check = |code: &str| {
+            rt.eval_sync(None, Script::new("tx_check.js", code))
+                .expect("script failed")
+                .get_str()
+                .to_string()
+        };
+        let state_json =
+            "JSON.stringify([state, Array.from(state.tags), ref === state.items, typeof added, typeof t])";
+        let before = check(state_json);
+
+        // shallow, a replaced global is restored and an added global is removed
+        let res = block_on(rt.eval_transactional(
+            None,
+            Script::new(
+                "tx_shallow.js",
+                "globalThis.state = 0; globalThis.added = 1; throw Error('fail');",
+            ),
+            TransactionOptions::new(),
+        ));
+        assert_eq!(res.expect_err("eval succeeded").get_message(), "fail");
+        assert_eq!(check(state_json), before);
+
+        // deep, nested mutations are restored in place and timers are cancelled
+        let res = block_on(rt.eval_transactional(
+            None,
+            Script::new(
+                "tx_deep.js",
+                "state.count++; state.items.push(3); state.tags.set('b', 2); globalThis.t = setTimeout(() => {globalThis.late = 1;}, 1); state.count;",
+            ),
+            TransactionOptions::new()
+                .deep()
+                .validate(|_realm, value| match primitives::to_i32(value) {
+                    Ok(2) => Err(JsError::new_str("count may not be 2")),
+                    _ => Ok(()),
+                }),
+        ));
+        assert_eq!(
+            res.expect_err("validation passed").get_message(),
+            "count may not be 2"
+        );
+        assert_eq!(check(state_json), before);
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        assert_eq!(check("typeof late"), "undefined");
+
+        // a committed transaction keeps its changes
+        block_on(rt.eval_transactional(
+            None,
+            Script::new("tx_commit.js", "state.count = 5;"),
+            TransactionOptions::new().deep(),
+        ))
+        .expect("eval failed");
+        assert_eq!(check("String(state.count)"), "5");
+
+        // var declarations can not be removed
+        let err = block_on(rt.eval_transactional(
+            None,
+            Script::new("tx_var.js", "var declared = 1; throw Error('fail');"),
+            TransactionOptions::new(),
+        ))
+        .expect_err("eval succeeded");
+        assert_eq!(err.get_name(), "IncompleteRollback");
+        assert!(err.get_message().contains("declared"));
+    }
+}
@@ -0,0 +1,45 @@
+//! errors which can not be returned to a caller
+//!
+//! a hook set with [uncaught_error_hook](crate::builder::QuickJsRuntimeBuilder::uncaught_error_hook) is called with the id of the realm,
+//! the source of the error and the error for:
+//!
+//! * errors thrown by setTimeout and setInterval callbacks
+//! * unhandled promise rejections
+//! * errors thrown by listeners of [memory pressure](crate::features::memorypressure) events
+//! * errors of an eval whose future was dropped while the script ran, see [detach_dropped_futures](crate::builder::QuickJsRuntimeBuilder::detach_dropped_futures)
+//!
+//! the errors are also kept in the debug records of the realm, the hook runs in the worker thread of the runtime and should not block
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::Script;
+//! use std::sync::{Arc, Mutex};
+//! let errors = Arc::new(Mutex::new(vec![]));
+//! let errors2 = errors.clone();
+//! let rt = QuickJsRuntimeBuilder::new()
+//!     .uncaught_error_hook(move |realm_id, source, err| {
+//!         errors2.lock().unwrap().push(format!("{realm_id} {source}: {}", err.get_message()));
+//!     })
+//!     .build();
+//! rt.eval_sync(None, Script::new("uncaught.js", "setTimeout(() => {throw Error('late');}, 0);")).expect("script failed");
+//! std::thread::sleep(std::time::Duration::from_millis(100));
+//! assert_eq!(errors.lock().unwrap()[0], "__main__ setTimeout: late");
+//! ```
+
+use crate::jsutils::{debugdump, JsError};
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use std::sync::Arc;
+
+/// a hook which is called with the realm id, the source (e.g. setTimeout) and the error
+pub type UncaughtErrorHook = Arc<dyn Fn(&str, &str, &JsError) + Send + Sync>;
+
+/// record an error which has no caller and pass it to the uncaught error hook of the runtime
+pub(crate) fn report_uncaught_q(realm: &QuickJsRealmAdapter, source: &str, error: &JsError) {
+    debugdump::record_error(realm, source, &error.to_string());
+    // clone the hook so the runtime is not borrowed while it runs
+    let hook = QuickJsRuntimeAdapter::do_with(|q_js_rt| q_js_rt.uncaught_error_hook.clone());
+    if let Some(hook) = hook {
+        hook(realm.id.as_str(), source, error);
+    }
+}
@@ -0,0 +1,376 @@
+//! syntax checks which do not run a script, see [validate_script](crate::facades::QuickJsRuntimeFacade::validate_script)
+//!
+//! scripts are pre-processed and compiled in a throwaway realm which is removed afterwards, so a validation does not define globals or
+//! add modules to the module cache of a realm
+//!
+//! * QuickJS stops compiling at the first syntax error, so at most one syntax error is reported per script
+//! * [validate_module](crate::facades::QuickJsRuntimeFacade::validate_module) also resolves the static imports of a module through the
+//!   module loaders and reports every import which no loader can resolve, the imported modules are not loaded
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::Script;
+//! use futures::executor::block_on;
+//! let rt = QuickJsRuntimeBuilder::new().build();
+//! let errors = block_on(rt.validate_script(Script::new("saved.js", "let a = 1;\nlet b = ;"))).expect_err("script was valid");
+//! assert_eq!(errors[0].line, Some(2));
+//! assert!(block_on(rt.validate_script(Script::new("saved.js", "let a = 1;"))).is_ok());
+//! ```
+
+use crate::jsutils::{JsError, Script};
+use crate::quickjs_utils::compile::compile;
+use crate::quickjs_utils::modules::compile_module;
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use serde::Serialize;
+
+const PROBE_REALM_ID: &str = "__validate_probe";
+
+/// an error which was found while validating a script
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct SyntaxErrorInfo {
+    pub path: String,
+    /// the 1-based line of the error, if it is known
+    pub line: Option<u32>,
+    /// the 1-based column of the error, if it is known
+    pub column: Option<u32>,
+    pub message: String,
+    /// the import which could not be resolved, None for syntax errors
+    pub unresolved_import: Option<String>,
+}
+
+impl SyntaxErrorInfo {
+    fn from_error(path: &str, err: &JsError) -> Self {
+        let (line, column) = location(err.get_stack());
+        let message = if err.get_name() == "SyntaxError" {
+            err.get_message().to_string()
+        } else {
+            format!("{}: {}", err.get_name(), err.get_message())
+        };
+        Self {
+            path: path.to_string(),
+            line,
+            column,
+            message,
+            unresolved_import: None,
+        }
+    }
+}
+
+/// get the line and column of the first frame of a stack which has a line number
+fn location(stack: &str) -> (Option<u32>, Option<u32>) {
+    for frame in stack.lines() {
+        let frame = frame.trim().trim_start_matches("at ").trim_end_matches(')');
+        let frame = match frame.rfind('(') {
+            Some(idx) => &frame[idx + 1..],
+            None => frame,
+        };
+        let parts: Vec<Option<u32>> = frame.rsplitn(3, ':').map(|p| p.parse().ok()).collect();
+        match parts.as_slice() {
+            [Some(column), Some(line), _] => return (Some(*line), Some(*column)),
+            [Some(line), _] => return (Some(*line), None),
+            _ => {}
+        }
+    }
+    (None, None)
+}
+
+/// validate a script or module, this creates a realm and should not be called while the runtime is borrowed
+pub(crate) fn validate(script: Script, module: bool) -> Result<(), Vec<SyntaxErrorInfo>> {
+    let path = script.get_path().to_string();
+    let to_infos = |err: JsError| vec![SyntaxErrorInfo::from_error(path.as_str(), &err)];
+    let script = QuickJsRuntimeAdapter::pre_process(script).map_err(to_infos)?;
+
+    QuickJsRuntimeAdapter::create_context(PROBE_REALM_ID).map_err(to_infos)?;
+    let res = QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+        validate_q(q_js_rt, q_js_rt.get_context(PROBE_REALM_ID), script, module)
+    });
+    QuickJsRuntimeAdapter::remove_context(PROBE_REALM_ID);
+    res
+}
+
+fn validate_q(
+    q_js_rt: &QuickJsRuntimeAdapter,
+    realm: &QuickJsRealmAdapter,
+    script: Script,
+    module: bool,
+) -> Result<(), Vec<SyntaxErrorInfo>> {
+    let path = script.get_path().to_string();
+    if !module {
+        return match unsafe { compile(realm.context, script) } {
+            Ok(_) => Ok(()),
+            Err(err) => Err(vec![SyntaxErrorInfo::from_error(path.as_str(), &err)]),
+        };
+    }
+
+    // imports are checked first because compiling a module already normalizes its imports
+    let imports = static_imports(script.get_runnable_code());
+    let unresolved: Vec<SyntaxErrorInfo> = imports
+        .into_iter()
+        .filter_map(|(specifier, line)| {
+            q_js_rt
+                .normalize_module_path(realm, path.as_str(), specifier.as_str())
+                .err()
+                .map(|err| SyntaxErrorInfo {
+                    path: path.clone(),
+                    line: Some(line),
+                    column: None,
+                    message: err.get_message().to_string(),
+                    unresolved_import: Some(specifier),
+                })
+        })
+        .collect();
+    if !unresolved.is_empty() {
+        return Err(unresolved);
+    }
+    match unsafe { compile_module(realm.context, script) } {
+        Ok(_) => Ok(()),
+        Err(err) => Err(vec![SyntaxErrorInfo::from_error(path.as_str(), &err)]),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+    Punct(char),
+}
+
+// words after which a / starts a regex
+const REGEX_PRECEDING_WORDS: [&str; 9] = [
+    "return", "typeof", "case", "do", "else", "in", "of", "new", "throw",
+];
+
+/// tokenize the code, comments, template literals and regexes are skipped
+fn tokens(code: &str) -> Vec<(Token, u32)> {
+    let chars: Vec<char> = code.chars().collect();
+    let mut tokens: Vec<(Token, u32)> = vec![];
+    let mut line = 1;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        if c == '\n' {
+            line += 1;
+            i += 1;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if c == '/' && next == Some('/') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '/' && next == Some('*') {
+            i += 2;
+            while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                if chars[i] == '\n' {
+                    line += 1;
+                }
+                i += 1;
+            }
+            i += 2;
+        } else if c == '\'' || c == '"' {
+            let start_line = line;
+            let mut value = String::new();
+            i += 1;
+            while i < chars.len() && chars[i] != c && chars[i] != '\n' {
+                if chars[i] == '\\' {
+                    i += 1;
+                }
+                if let Some(ch) = chars.get(i) {
+                    value.push(*ch);
+                }
+                i += 1;
+            }
+            i += 1;
+            tokens.push((Token::Str(value), start_line));
+        } else if c == '`' {
+            // a template literal, the expressions in it are skipped as well
+            let mut depth = 0;
+            i += 1;
+            while i < chars.len() && !(chars[i] == '`' && depth == 0) {
+                match chars[i] {
+                    '\\' => i += 1,
+                    '\n' => line += 1,
+                    '$' if chars.get(i + 1) == Some(&'{') => {
+                        depth += 1;
+                        i += 1;
+                    }
+                    '}' if depth > 0 => depth -= 1,
+                    _ => {}
+                }
+                i += 1;
+            }
+            i += 1;
+            tokens.push((Token::Punct('`'), line));
+        } else if c == '/'
+            && match tokens.last() {
+                None => true,
+                Some((Token::Punct(p), _)) => !matches!(p, ')' | ']' | '}' | '`'),
+                Some((Token::Word(w), _)) => REGEX_PRECEDING_WORDS.contains(&w.as_str()),
+                Some((Token::Str(_), _)) => false,
+            }
+        {
+            let mut in_class = false;
+            i += 1;
+            while i < chars.len() && chars[i] != '\n' && (chars[i] != '/' || in_class) {
+                match chars[i] {
+                    '\\' => i += 1,
+                    '[' => in_class = true,
+                    ']' => in_class = false,
+                    _ => {}
+                }
+                i += 1;
+            }
+            i += 1;
+            tokens.push((Token::Punct('/'), line));
+        } else if c.is_alphanumeric() || c == '_' || c == '$' || !c.is_ascii() {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_alphanumeric()
+                    || chars[i] == '_'
+                    || chars[i] == '$'
+                    || !chars[i].is_ascii())
+            {
+                i += 1;
+            }
+            tokens.push((Token::Word(chars[start..i].iter().collect()), line));
+        } else {
+            tokens.push((Token::Punct(c), line));
+            i += 1;
+        }
+    }
+    tokens
+}
+
+/// find the specifiers of the static imports and re-exports of a module, with their 1-based line
+fn static_imports(code: &str) -> Vec<(String, u32)> {
+    let tokens = tokens(code);
+    let mut imports = vec![];
+    for (idx, (token, line)) in tokens.iter().enumerate() {
+        let is_property = idx > 0 && tokens[idx - 1].0 == Token::Punct('.');
+        let next = tokens.get(idx + 1).map(|(t, _)| t);
+        let is_import = match token {
+            Token::Word(w) if w == "import" && !is_property => match next {
+                // import('x') and import.meta
+                Some(Token::Punct('(')) | Some(Token::Punct('.')) => false,
+                Some(Token::Str(specifier)) => {
+                    imports.push((specifier.clone(), *line));
+                    false
+                }
+                _ => true,
+            },
+            Token::Word(w) if w == "export" && !is_property => {
+                matches!(next, Some(Token::Punct('*')) | Some(Token::Punct('{')))
+            }
+            _ => false,
+        };
+        if !is_import {
+            continue;
+        }
+        // the specifier is the first string of the statement, if it follows from
+        for pos in idx + 1..tokens.len() {
+            match &tokens[pos].0 {
+                Token::Str(specifier) => {
+                    if tokens[pos - 1].0 == Token::Word("from".to_string()) {
+                        imports.push((specifier.clone(), *line));
+                    }
+                    break;
+                }
+                Token::Punct(';') => break,
+                _ => {}
+            }
+        }
+    }
+    imports
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::static_imports;
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::modules::ScriptModuleLoader;
+    use crate::jsutils::Script;
+    use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+    use futures::executor::block_on;
+
+    struct TestLoader {}
+
+    impl ScriptModuleLoader for TestLoader {
+        fn normalize_path(
+            &self,
+            _realm: &QuickJsRealmAdapter,
+            _ref_path: &str,
+            path: &str,
+        ) -> Option<String> {
+            match path {
+                "./lib.mes" => Some("validate/lib.mes".to_string()),
+                _ => None,
+            }
+        }
+
+        fn load_module(&self, _realm: &QuickJsRealmAdapter, _absolute_path: &str) -> String {
+            "export const lib = 3;".to_string()
+        }
+    }
+
+    #[test]
+    fn test_static_imports() {
+        let code = "import a from './a.mes';\nimport {b as from} from \"./b.mes\"\nimport './c.mes';\n// import d from './d.mes';\nexport * from './e.mes';\nexport {f};\nconst re = /'/;\nimport('./g.mes'); let s = `import h from './h.mes'`;\nexport {i} from './i.mes';";
+        let imports = static_imports(code);
+        assert_eq!(
+            imports,
+            vec![
+                ("./a.mes".to_string(), 1),
+                ("./b.mes".to_string(), 2),
+                ("./c.mes".to_string(), 3),
+                ("./e.mes".to_string(), 5),
+                ("./i.mes".to_string(), 9),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let rt = QuickJsRuntimeBuilder::new()
+            .script_module_loader(TestLoader {})
+            .build();
+
+        let errors = block_on(rt.validate_script(Script::new(
+            "validate/broken.js",
+            "let a = 1;\nfunction f() {\n  return a +;\n}",
+        )))
+        .expect_err("script was valid");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, "validate/broken.js");
+        assert_eq!(errors[0].line, Some(3));
+        assert!(errors[0].unresolved_import.is_none());
+
+        let errors = block_on(rt.validate_module(Script::new(
+            "validate/missing.mes",
+            "import {lib} from './lib.mes';\nimport {x} from './nope.mes';\nexport const y = lib + x;",
+        )))
+        .expect_err("module was valid");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].unresolved_import.as_deref(), Some("./nope.mes"));
+        assert_eq!(errors[0].line, Some(2));
+
+        // validating does not run the script or cache the module
+        let code = "import {lib} from './lib.mes';\nglobalThis.validated = (globalThis.validated || 0) + lib;";
+        block_on(rt.validate_module(Script::new("validate/main.mes", code)))
+            .expect("module was invalid");
+        block_on(rt.validate_script(Script::new(
+            "validate/global.js",
+            "globalThis.validated = 100;",
+        )))
+        .expect("script was invalid");
+        rt.eval_module_sync(None, Script::new("validate/main.mes", code))
+            .expect("module failed");
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new("validate/check.js", "globalThis.validated;"),
+            )
+            .expect("script failed");
+        assert_eq!(res.get_i32(), 3);
+    }
+}
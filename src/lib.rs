@@ -70,21 +70,23 @@
 //! ```
 //!
 //! For more details and examples please explore the packages below
+//!
+//! ## Stability
+//!
+//! The [prelude] re-exports the stable, recommended surface, `use quickjs_runtime::prelude::*;` is all most code needs.
+//! The deeper module paths are public as well but may be reorganized in a minor version.
 
 #[macro_use]
 extern crate lazy_static;
 extern crate core;
 
+pub mod bench_util;
 pub mod builder;
 pub mod facades;
-#[cfg(any(
-    feature = "settimeout",
-    feature = "setinterval",
-    feature = "console",
-    feature = "setimmediate"
-))]
 pub mod features;
+pub mod integrations;
 pub mod jsutils;
+pub mod prelude;
 pub mod quickjs_utils;
 pub mod quickjsrealmadapter;
 pub mod quickjsruntimeadapter;
@@ -0,0 +1,156 @@
+//! the stable, recommended surface of this crate
+//!
+//! ```dontrun
+//! use quickjs_runtime::prelude::*;
+//! let rt = QuickJsRuntimeBuilder::new().build();
+//! let res = rt.eval_sync(None, Script::new("hello.js", "7 * 6;"))?;
+//! assert_eq!(res.get_i32(), 42);
+//! ```
+//!
+//! everything exported here keeps its name and meaning between minor versions, the paths it is re-exported from
+//! (e.g. [reflection](crate::reflection) or [quickjs_utils](crate::quickjs_utils)) may be reorganized, so prefer importing from here
+//!
+
+pub use crate::builder::QuickJsRuntimeBuilder;
+pub use crate::facades::QuickJsRuntimeFacade;
+pub use crate::jsutils::modules::{NativeModuleLoader, ScriptModuleLoader};
+pub use crate::jsutils::realmhandle::RealmHandle;
+pub use crate::jsutils::taskscope::EvalOptions;
+pub use crate::jsutils::{JsError, JsErrorKind, JsValueType, Script};
+pub use crate::quickjs_utils::arrays::{
+    create_array_q, get_element_q, get_length_q, set_element_q,
+};
+pub use crate::quickjs_utils::functions::{
+    call_function_q, invoke_member_function_q, is_function_q, new_function_q,
+};
+pub use crate::quickjs_utils::get_global_q;
+pub use crate::quickjs_utils::json::{parse_q, stringify_q};
+pub use crate::quickjs_utils::objects::{create_object_q, get_property_q, set_property_q};
+pub use crate::quickjs_utils::primitives::{from_string_q, to_string_q};
+pub use crate::quickjsrealmadapter::{QuickJsRealmAdapter, RealmOptions};
+pub use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+pub use crate::quickjsvalueadapter::QuickJsValueAdapter;
+pub use crate::reflection::{JsProxyInstanceId, Proxy};
+pub use crate::values::{
+    CachedJsArrayRef, CachedJsFunctionRef, CachedJsObjectRef, CachedJsPromiseRef,
+    JsValueConvertable, JsValueFacade,
+};
+
+#[cfg(test)]
+pub mod tests {
+    // these pin the contents of the prelude, removing or changing an export breaks the build
+
+    use crate::prelude::*;
+    use std::collections::HashMap;
+
+    struct TestNativeModuleLoader {}
+
+    impl NativeModuleLoader for TestNativeModuleLoader {
+        fn has_module(&self, _realm: &QuickJsRealmAdapter, module_name: &str) -> bool {
+            module_name.eq("prelude_mod")
+        }
+        fn get_module_export_names(
+            &self,
+            _realm: &QuickJsRealmAdapter,
+            _module_name: &str,
+        ) -> Vec<&str> {
+            vec!["answer"]
+        }
+        fn get_module_exports(
+            &self,
+            realm: &QuickJsRealmAdapter,
+            _module_name: &str,
+        ) -> Vec<(&str, QuickJsValueAdapter)> {
+            vec![("answer", realm.create_i32(42).unwrap())]
+        }
+    }
+
+    struct TestScriptModuleLoader {
+        modules: HashMap<&'static str, &'static str>,
+    }
+
+    impl ScriptModuleLoader for TestScriptModuleLoader {
+        fn normalize_path(
+            &self,
+            _realm: &QuickJsRealmAdapter,
+            _ref_path: &str,
+            path: &str,
+        ) -> Option<String> {
+            self.modules.get(path).map(|_| path.to_string())
+        }
+        fn load_module(&self, _realm: &QuickJsRealmAdapter, absolute_path: &str) -> String {
+            self.modules.get(absolute_path).unwrap().to_string()
+        }
+    }
+
+    #[test]
+    fn test_prelude() {
+        let rt: QuickJsRuntimeFacade = QuickJsRuntimeBuilder::new()
+            .native_module_loader(TestNativeModuleLoader {})
+            .script_module_loader(TestScriptModuleLoader {
+                modules: HashMap::from([("prelude_script.mes", "export const q = 1;")]),
+            })
+            .build();
+
+        let res: Result<JsValueFacade, JsError> =
+            rt.eval_sync(None, Script::new("prelude.js", "7 * 6;"));
+        assert_eq!(res.expect("script failed").get_i32(), 42);
+        assert!(matches!(
+            6.to_js_value_facade().get_value_type(),
+            JsValueType::I32
+        ));
+
+        let _options: Option<EvalOptions> = None;
+        let _realm_options: Option<RealmOptions> = None;
+        let _handle: Option<RealmHandle> = None;
+        let _refs: Option<(
+            CachedJsObjectRef,
+            CachedJsArrayRef,
+            CachedJsFunctionRef,
+            CachedJsPromiseRef,
+        )> = None;
+
+        let sum = rt.loop_realm_sync(
+            None,
+            |_rt: &QuickJsRuntimeAdapter, realm: &QuickJsRealmAdapter| {
+                let _id: Option<JsProxyInstanceId> = None;
+                Proxy::new()
+                    .name("PreludeProxy")
+                    .static_method("twice", |_rt, realm, args| {
+                        realm.create_i32(args[0].to_i32() * 2)
+                    })
+                    .install(realm, true)
+                    .expect("install failed");
+
+                let global = get_global_q(realm);
+                let obj = create_object_q(realm).expect("create failed");
+                let arr = create_array_q(realm).expect("create failed");
+                set_element_q(realm, &arr, 0, &realm.create_i32(3).unwrap()).expect("set failed");
+                assert_eq!(get_length_q(realm, &arr).unwrap(), 1);
+                let three = get_element_q(realm, &arr, 0).expect("get failed");
+                set_property_q(realm, &obj, "three", &three).expect("set failed");
+
+                let func = new_function_q(
+                    realm,
+                    "add",
+                    |realm, _this, args| realm.create_i32(args[0].to_i32() + args[1].to_i32()),
+                    2,
+                )
+                .expect("new_function failed");
+                assert!(is_function_q(realm, &func));
+                let proxy = get_property_q(realm, &global, "PreludeProxy").expect("get failed");
+                let six = invoke_member_function_q(realm, &proxy, "twice", &[three.dup()])
+                    .expect("twice failed");
+                let sum = call_function_q(realm, &func, &[three, six], None).expect("add failed");
+
+                let json = stringify_q(realm, &obj, None).expect("stringify failed");
+                let parsed = parse_q(realm, to_string_q(realm, &json).unwrap().as_str())
+                    .expect("parse failed");
+                assert!(parsed.is_object());
+                let _s = from_string_q(realm, "prelude").expect("from_string failed");
+                sum.to_i32()
+            },
+        );
+        assert_eq!(sum, 9);
+    }
+}
@@ -0,0 +1,347 @@
+//! the allocation functions of the runtime, they enforce the [memory_limit](crate::builder::QuickJsRuntimeBuilder::memory_limit)
+//! instead of QuickJS so the [memory_limit_exceeded_handler](crate::builder::QuickJsRuntimeBuilder::memory_limit_exceeded_handler)
+//! can be called before an allocation fails, when the handler raises the limit the allocation which hit it succeeds
+//!
+//! the limit of QuickJS itself is never set, JS_SetMemoryLimit would have no effect or fail allocations without calling the handler
+
+use crate::features::memorypressure::{
+    MemoryLimitAction, MemoryLimitExceeded, MemoryLimitExceededHandler,
+};
+use libquickjs_sys as q;
+use std::alloc::{self, Layout};
+use std::cell::{Cell, RefCell};
+use std::ffi::c_void;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+// every block starts with a header which holds its size, the header also keeps the blocks aligned like malloc does
+const HEADER_SIZE: usize = 16;
+// what QuickJS adds to the size of every block in its accounting
+const MALLOC_OVERHEAD: usize = 8;
+
+thread_local! {
+    // true when an allocation was refused because of the limit since the last call of take_limit_hit
+    static LIMIT_HIT: Cell<bool> = Cell::new(false);
+}
+
+/// the limit of a runtime, it is passed to the allocation functions as their opaque pointer so it lives as long as the runtime
+pub(crate) struct Allocator {
+    // the limit which is enforced, usize::MAX when there is none
+    malloc_limit: Cell<usize>,
+    // the limit which was set with the builder or raised by the handler, a lower limit may be active for a while (e.g. that of an isolated eval)
+    memory_limit: Cell<Option<u64>>,
+    handler: RefCell<Option<MemoryLimitExceededHandler>>,
+    // allocations which are done while the handler runs do not call it again
+    in_handler: Cell<bool>,
+    // quickjs-ng does not pass its accounting to the allocation functions so they keep their own
+    #[cfg(feature = "quickjs-ng")]
+    malloc_size: Cell<usize>,
+}
+
+impl Allocator {
+    pub(crate) fn new() -> Box<Self> {
+        Box::new(Self {
+            malloc_limit: Cell::new(usize::MAX),
+            memory_limit: Cell::new(None),
+            handler: RefCell::new(None),
+            in_handler: Cell::new(false),
+            #[cfg(feature = "quickjs-ng")]
+            malloc_size: Cell::new(0),
+        })
+    }
+    /// the limit which was set with the builder or raised by the handler
+    pub(crate) fn memory_limit(&self) -> Option<u64> {
+        self.memory_limit.get()
+    }
+    /// set the limit of the runtime, this also ends a lower limit which is active
+    pub(crate) fn set_memory_limit(&self, limit: Option<u64>) {
+        self.memory_limit.set(limit);
+        self.malloc_limit
+            .set(limit.map(|l| l as usize).unwrap_or(usize::MAX));
+    }
+    /// the limit which is enforced, usize::MAX when there is none
+    pub(crate) fn malloc_limit(&self) -> usize {
+        self.malloc_limit.get()
+    }
+    /// enforce a limit for a while, the handler is not called while it differs from the [memory_limit](Self::memory_limit)
+    pub(crate) fn set_malloc_limit(&self, limit: usize) {
+        self.malloc_limit.set(limit);
+    }
+    pub(crate) fn set_handler(&self, handler: Option<MemoryLimitExceededHandler>) {
+        *self.handler.borrow_mut() = handler;
+    }
+
+    // check if size bytes may be allocated on top of the used bytes, when the limit is hit the handler may raise it
+    fn allows(&self, used: usize, size: usize) -> bool {
+        let limit = self.malloc_limit.get();
+        if used.saturating_add(size) <= limit {
+            return true;
+        }
+        let refused = |limit: usize| {
+            LIMIT_HIT.with(|hit| hit.set(true));
+            log::warn!(
+                "an allocation of {} bytes exceeded the memory limit of {} bytes",
+                size,
+                limit
+            );
+            false
+        };
+        let configured_limit = self
+            .memory_limit
+            .get()
+            .map(|l| l as usize)
+            .unwrap_or(usize::MAX);
+        // a lower limit is active
+        if limit != configured_limit || self.in_handler.get() {
+            return refused(limit);
+        }
+        let handler = match self.handler.borrow().as_ref() {
+            Some(handler) => handler.clone(),
+            None => return refused(limit),
+        };
+        let exceeded = MemoryLimitExceeded {
+            used: used as u64,
+            requested: size as u64,
+            limit: limit as u64,
+        };
+        self.in_handler.set(true);
+        // a panic may not unwind into QuickJS
+        let action =
+            panic::catch_unwind(AssertUnwindSafe(|| handler(&exceeded))).unwrap_or_else(|_| {
+                log::error!("the memory_limit_exceeded_handler panicked");
+                MemoryLimitAction::Fail
+            });
+        self.in_handler.set(false);
+        match action {
+            MemoryLimitAction::Fail => refused(limit),
+            MemoryLimitAction::Raise(new_limit) => {
+                log::warn!(
+                    "the memory limit of {} bytes was exceeded, raising it to {} bytes",
+                    limit,
+                    new_limit
+                );
+                self.set_memory_limit(Some(new_limit));
+                if used.saturating_add(size) <= self.malloc_limit.get() {
+                    true
+                } else {
+                    refused(self.malloc_limit.get())
+                }
+            }
+        }
+    }
+}
+
+/// check if an allocation was refused because of the memory limit since the last call, this resets the check
+pub(crate) fn take_limit_hit() -> bool {
+    LIMIT_HIT.with(|hit| hit.replace(false))
+}
+
+/// create a runtime which allocates with the functions of this module
+/// # Safety
+/// the allocator must outlive the runtime
+pub(crate) unsafe fn new_runtime(allocator: &Allocator) -> *mut q::JSRuntime {
+    #[cfg(feature = "bellard")]
+    let mf = q::JSMallocFunctions {
+        js_malloc: Some(js_malloc),
+        js_free: Some(js_free),
+        js_realloc: Some(js_realloc),
+        js_malloc_usable_size: Some(js_malloc_usable_size),
+    };
+    #[cfg(feature = "quickjs-ng")]
+    let mf = q::JSMallocFunctions {
+        js_calloc: Some(js_calloc),
+        js_malloc: Some(js_malloc),
+        js_free: Some(js_free),
+        js_realloc: Some(js_realloc),
+        js_malloc_usable_size: Some(js_malloc_usable_size),
+    };
+    q::JS_NewRuntime2(&mf, allocator as *const Allocator as *mut c_void)
+}
+
+unsafe fn alloc_block(size: usize, zeroed: bool) -> *mut c_void {
+    let layout = match size
+        .checked_add(HEADER_SIZE)
+        .and_then(|total| Layout::from_size_align(total, HEADER_SIZE).ok())
+    {
+        Some(layout) => layout,
+        None => return ptr::null_mut(),
+    };
+    let base = if zeroed {
+        alloc::alloc_zeroed(layout)
+    } else {
+        alloc::alloc(layout)
+    };
+    if base.is_null() {
+        return ptr::null_mut();
+    }
+    (base as *mut usize).write(size);
+    base.add(HEADER_SIZE) as *mut c_void
+}
+
+unsafe fn block_size(block: *const c_void) -> usize {
+    if block.is_null() {
+        0
+    } else {
+        ((block as *const u8).sub(HEADER_SIZE) as *const usize).read()
+    }
+}
+
+unsafe fn free_block(block: *mut c_void) {
+    let base = (block as *mut u8).sub(HEADER_SIZE);
+    let size = (base as *const usize).read();
+    alloc::dealloc(
+        base,
+        Layout::from_size_align_unchecked(size + HEADER_SIZE, HEADER_SIZE),
+    );
+}
+
+// the old block is kept when this fails
+unsafe fn realloc_block(block: *mut c_void, size: usize) -> *mut c_void {
+    let base = (block as *mut u8).sub(HEADER_SIZE);
+    let old_size = (base as *const usize).read();
+    let total = match size.checked_add(HEADER_SIZE) {
+        Some(total) if Layout::from_size_align(total, HEADER_SIZE).is_ok() => total,
+        _ => return ptr::null_mut(),
+    };
+    let new_base = alloc::realloc(
+        base,
+        Layout::from_size_align_unchecked(old_size + HEADER_SIZE, HEADER_SIZE),
+        total,
+    );
+    if new_base.is_null() {
+        return ptr::null_mut();
+    }
+    (new_base as *mut usize).write(size);
+    new_base.add(HEADER_SIZE) as *mut c_void
+}
+
+unsafe extern "C" fn js_malloc_usable_size(block: *const c_void) -> usize {
+    block_size(block)
+}
+
+// bellard passes its accounting to the allocation functions and leaves the limit to them
+
+#[cfg(feature = "bellard")]
+unsafe extern "C" fn js_malloc(s: *mut q::JSMallocState, size: usize) -> *mut c_void {
+    let allocator = &*((*s).opaque as *const Allocator);
+    if !allocator.allows((*s).malloc_size, size) {
+        return ptr::null_mut();
+    }
+    let block = alloc_block(size, false);
+    if !block.is_null() {
+        (*s).malloc_count += 1;
+        (*s).malloc_size += block_size(block) + MALLOC_OVERHEAD;
+    }
+    block
+}
+
+#[cfg(feature = "bellard")]
+unsafe extern "C" fn js_free(s: *mut q::JSMallocState, block: *mut c_void) {
+    if block.is_null() {
+        return;
+    }
+    (*s).malloc_count -= 1;
+    (*s).malloc_size -= block_size(block) + MALLOC_OVERHEAD;
+    free_block(block);
+}
+
+#[cfg(feature = "bellard")]
+unsafe extern "C" fn js_realloc(
+    s: *mut q::JSMallocState,
+    block: *mut c_void,
+    size: usize,
+) -> *mut c_void {
+    if block.is_null() {
+        if size == 0 {
+            return ptr::null_mut();
+        }
+        return js_malloc(s, size);
+    }
+    if size == 0 {
+        js_free(s, block);
+        return ptr::null_mut();
+    }
+    let old_size = block_size(block);
+    let allocator = &*((*s).opaque as *const Allocator);
+    if size > old_size && !allocator.allows((*s).malloc_size.saturating_sub(old_size), size) {
+        return ptr::null_mut();
+    }
+    let block = realloc_block(block, size);
+    if !block.is_null() {
+        (*s).malloc_size = (*s).malloc_size - old_size + block_size(block);
+    }
+    block
+}
+
+// quickjs-ng does its own accounting and checks its own limit before it calls the allocation functions, that limit is never set
+
+#[cfg(feature = "quickjs-ng")]
+unsafe fn ng_alloc(opaque: *mut c_void, size: usize, zeroed: bool) -> *mut c_void {
+    let allocator = &*(opaque as *const Allocator);
+    if !allocator.allows(allocator.malloc_size.get(), size) {
+        return ptr::null_mut();
+    }
+    let block = alloc_block(size, zeroed);
+    if !block.is_null() {
+        allocator
+            .malloc_size
+            .set(allocator.malloc_size.get() + block_size(block) + MALLOC_OVERHEAD);
+    }
+    block
+}
+
+#[cfg(feature = "quickjs-ng")]
+unsafe extern "C" fn js_calloc(opaque: *mut c_void, count: usize, size: usize) -> *mut c_void {
+    match count.checked_mul(size) {
+        Some(size) => ng_alloc(opaque, size, true),
+        None => ptr::null_mut(),
+    }
+}
+
+#[cfg(feature = "quickjs-ng")]
+unsafe extern "C" fn js_malloc(opaque: *mut c_void, size: usize) -> *mut c_void {
+    ng_alloc(opaque, size, false)
+}
+
+#[cfg(feature = "quickjs-ng")]
+unsafe extern "C" fn js_free(opaque: *mut c_void, block: *mut c_void) {
+    if block.is_null() {
+        return;
+    }
+    let allocator = &*(opaque as *const Allocator);
+    allocator
+        .malloc_size
+        .set(allocator.malloc_size.get() - block_size(block) - MALLOC_OVERHEAD);
+    free_block(block);
+}
+
+#[cfg(feature = "quickjs-ng")]
+unsafe extern "C" fn js_realloc(
+    opaque: *mut c_void,
+    block: *mut c_void,
+    size: usize,
+) -> *mut c_void {
+    if block.is_null() {
+        if size == 0 {
+            return ptr::null_mut();
+        }
+        return js_malloc(opaque, size);
+    }
+    if size == 0 {
+        js_free(opaque, block);
+        return ptr::null_mut();
+    }
+    let allocator = &*(opaque as *const Allocator);
+    let old_size = block_size(block);
+    let used = allocator.malloc_size.get();
+    if size > old_size && !allocator.allows(used.saturating_sub(old_size), size) {
+        return ptr::null_mut();
+    }
+    let block = realloc_block(block, size);
+    if !block.is_null() {
+        allocator
+            .malloc_size
+            .set(allocator.malloc_size.get() - old_size + block_size(block));
+    }
+    block
+}
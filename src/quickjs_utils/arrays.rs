@@ -1,4 +1,6 @@
 use crate::jsutils::JsError;
+use crate::quickjs_utils::atoms::JSAtomRef;
+use crate::quickjs_utils::errors;
 use crate::quickjsrealmadapter::QuickJsRealmAdapter;
 use crate::quickjsvalueadapter::QuickJsValueAdapter;
 use libquickjs_sys as q;
@@ -103,7 +105,10 @@ pub unsafe fn create_array(context: *mut q::JSContext) -> Result<QuickJsValueAda
     let arr = q::JS_NewArray(context);
     let arr_ref = QuickJsValueAdapter::new(context, arr, false, true, "create_array");
     if arr_ref.is_exception() {
-        return Err(JsError::new_str("Could not create array in runtime"));
+        return Err(errors::get_exception_or(
+            context,
+            "Could not create array in runtime",
+        ));
     }
     Ok(arr_ref)
 }
@@ -154,7 +159,10 @@ pub unsafe fn set_element(
         q::JS_PROP_C_W_E as i32,
     );
     if ret < 0 {
-        return Err(JsError::new_str("Could not append element to array"));
+        return Err(errors::get_exception_or(
+            context,
+            "Could not append element to array",
+        ));
     }
     Ok(())
 }
@@ -203,11 +211,43 @@ pub unsafe fn get_element(
         format!("get_element[{index}]").as_str(),
     );
     if ret.is_exception() {
-        return Err(JsError::new_str("Could not build array"));
+        return Err(errors::get_exception_or(context, "Could not build array"));
     }
     Ok(ret)
 }
 
+/// check whether an array has an element at an index, this is false for the holes of a sparse array
+pub fn has_element_q(
+    q_ctx: &QuickJsRealmAdapter,
+    array_ref: &QuickJsValueAdapter,
+    index: u32,
+) -> Result<bool, JsError> {
+    unsafe { has_element(q_ctx.context, array_ref, index) }
+}
+
+/// # Safety
+/// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
+pub unsafe fn has_element(
+    context: *mut q::JSContext,
+    array_ref: &QuickJsValueAdapter,
+    index: u32,
+) -> Result<bool, JsError> {
+    let atom = JSAtomRef::new(context, q::JS_NewAtomUInt32(context, index));
+    let res = q::JS_GetOwnProperty(
+        context,
+        std::ptr::null_mut(),
+        *array_ref.borrow_value(),
+        atom.get_atom(),
+    );
+    if res < 0 {
+        return Err(errors::get_exception_or(
+            context,
+            "Could not check array element",
+        ));
+    }
+    Ok(res > 0)
+}
+
 #[cfg(test)]
 pub mod tests {
     use crate::facades::tests::init_test_rt;
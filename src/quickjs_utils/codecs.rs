@@ -0,0 +1,327 @@
+//! value codecs convert instances of user-defined classes to a [JsValueFacade] and back
+//!
+//! a codec is registered with [register_value_codec](crate::builder::QuickJsRuntimeBuilder::register_value_codec), it consists of
+//! * a [JsClassMatcher] which identifies the values by constructor name, by class id or with a predicate
+//! * an encoder which converts a matched value to a facade, e.g. a [JsValueFacade::Custom] with a tag and some bytes
+//! * a decoder which converts a [JsValueFacade::Custom] back to a value, it returns None for a tag of another codec
+//!
+//! the built-in conversions come first, codecs are only tried for objects which would otherwise become a [JsValueFacade::JsObject]
+//! the codecs are tried in the order they were registered, the first matching codec encodes the value
+//!
+//! an encoder and a decoder may convert nested values with [to_js_value_facade](crate::quickjsrealmadapter::QuickJsRealmAdapter::to_js_value_facade)
+//! and [from_js_value_facade](crate::quickjsrealmadapter::QuickJsRealmAdapter::from_js_value_facade), codecs apply to those values as well
+//! while a value is being encoded converting that same value skips the codecs, so an encoder can wrap the default conversion
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::Script;
+//! use quickjs_runtime::quickjs_utils::codecs::JsClassMatcher;
+//! use quickjs_runtime::quickjs_utils::objects;
+//! use quickjs_runtime::values::JsValueFacade;
+//! let rt = QuickJsRuntimeBuilder::new()
+//!     .register_value_codec(
+//!         JsClassMatcher::constructor_name("Money"),
+//!         |realm, value| {
+//!             let cents = objects::get_property_q(realm, value, "cents")?.to_i32();
+//!             Ok(JsValueFacade::new_custom("money", cents.to_le_bytes().to_vec()))
+//!         },
+//!         |realm, tag, data| {
+//!             if tag != "money" {
+//!                 return Ok(None);
+//!             }
+//!             let cents = i32::from_le_bytes(data.try_into().expect("4 bytes"));
+//!             let money = realm.eval(Script::new("money.js", "new Money(0);"))?;
+//!             objects::set_property_q(realm, &money, "cents", &realm.create_i32(cents)?)?;
+//!             Ok(Some(money))
+//!         },
+//!     )
+//!     .build();
+//! rt.eval_sync(None, Script::new("money.js", "class Money { constructor(cents) { this.cents = cents; } }; globalThis.Money = Money;"))
+//!     .expect("script failed");
+//! let res = rt.eval_sync(None, Script::new("money.js", "new Money(1250);")).expect("script failed");
+//! assert!(matches!(res, JsValueFacade::Custom { ref tag, .. } if tag == "money"));
+//! ```
+
+use crate::jsutils::JsError;
+use crate::quickjs_utils::objects;
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsvalueadapter::QuickJsValueAdapter;
+use crate::values::JsValueFacade;
+use libquickjs_sys as q;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// a predicate which tells if a value is handled by a codec, see [JsClassMatcher::predicate]
+pub type CodecPredicate =
+    Arc<dyn Fn(&QuickJsRealmAdapter, &QuickJsValueAdapter) -> Result<bool, JsError> + Send + Sync>;
+/// converts a matched value to a facade
+pub type ValueEncoder = Arc<
+    dyn Fn(&QuickJsRealmAdapter, &QuickJsValueAdapter) -> Result<JsValueFacade, JsError>
+        + Send
+        + Sync,
+>;
+/// converts the tag and data of a [JsValueFacade::Custom] to a value, None when the tag belongs to another codec
+pub type ValueDecoder = Arc<
+    dyn Fn(&QuickJsRealmAdapter, &str, &[u8]) -> Result<Option<QuickJsValueAdapter>, JsError>
+        + Send
+        + Sync,
+>;
+
+/// identifies the values which are converted by a codec
+#[derive(Clone)]
+pub enum JsClassMatcher {
+    /// values whose `constructor.name` is equal to this name
+    ConstructorName(String),
+    /// instances of a native class with this class id which have opaque data, e.g. a class created with
+    /// [new_class_id](crate::quickjsruntimeadapter::QuickJsRuntimeAdapter::new_class_id)
+    ClassId(u32),
+    /// values for which the predicate returns true
+    Predicate(CodecPredicate),
+}
+
+impl JsClassMatcher {
+    pub fn constructor_name(name: &str) -> Self {
+        Self::ConstructorName(name.to_string())
+    }
+    pub fn class_id(class_id: u32) -> Self {
+        Self::ClassId(class_id)
+    }
+    pub fn predicate<P>(predicate: P) -> Self
+    where
+        P: Fn(&QuickJsRealmAdapter, &QuickJsValueAdapter) -> Result<bool, JsError>
+            + Send
+            + Sync
+            + 'static,
+    {
+        Self::Predicate(Arc::new(predicate))
+    }
+
+    fn matches(
+        &self,
+        realm: &QuickJsRealmAdapter,
+        value: &QuickJsValueAdapter,
+        constructor_name: &mut Option<Option<String>>,
+    ) -> Result<bool, JsError> {
+        match self {
+            JsClassMatcher::ConstructorName(name) => {
+                // the name is looked up once for all codecs
+                if constructor_name.is_none() {
+                    *constructor_name = Some(get_constructor_name_q(realm, value)?);
+                }
+                Ok(constructor_name.as_ref().and_then(|n| n.as_deref()) == Some(name.as_str()))
+            }
+            JsClassMatcher::ClassId(class_id) => {
+                Ok(!unsafe { q::JS_GetOpaque(*value.borrow_value(), *class_id) }.is_null())
+            }
+            JsClassMatcher::Predicate(predicate) => predicate(realm, value),
+        }
+    }
+}
+
+/// a registered codec, see [register_value_codec](crate::builder::QuickJsRuntimeBuilder::register_value_codec)
+#[derive(Clone)]
+pub struct ValueCodec {
+    pub(crate) matcher: JsClassMatcher,
+    pub(crate) encoder: ValueEncoder,
+    pub(crate) decoder: ValueDecoder,
+}
+
+thread_local! {
+    // set in the EventLoop thread of the runtime
+    static VALUE_CODECS: RefCell<Rc<Vec<ValueCodec>>> = RefCell::new(Rc::new(vec![]));
+    // the objects which are being encoded, converting one of those again skips the codecs
+    static ENCODING: RefCell<Vec<usize>> = const { RefCell::new(vec![]) };
+}
+
+pub(crate) fn set_value_codecs(codecs: Vec<ValueCodec>) {
+    VALUE_CODECS.with(|rc| *rc.borrow_mut() = Rc::new(codecs));
+}
+
+fn get_constructor_name_q(
+    realm: &QuickJsRealmAdapter,
+    value: &QuickJsValueAdapter,
+) -> Result<Option<String>, JsError> {
+    let constructor = objects::get_property_q(realm, value, "constructor")?;
+    if !constructor.is_function() {
+        return Ok(None);
+    }
+    let name = objects::get_property_q(realm, &constructor, "name")?;
+    if name.is_string() {
+        Ok(Some(name.to_string()?))
+    } else {
+        Ok(None)
+    }
+}
+
+pub(crate) fn object_id(value: &QuickJsValueAdapter) -> usize {
+    unsafe { value.borrow_value().u.ptr as usize }
+}
+
+/// encode an object with the first matching codec, None when no codec matches
+pub(crate) fn encode_q(
+    realm: &QuickJsRealmAdapter,
+    value: &QuickJsValueAdapter,
+) -> Result<Option<JsValueFacade>, JsError> {
+    // clone the codecs so they do not run while the thread_local is borrowed
+    let codecs = VALUE_CODECS.with(|rc| rc.borrow().clone());
+    if codecs.is_empty() {
+        return Ok(None);
+    }
+    let id = object_id(value);
+    if ENCODING.with(|rc| rc.borrow().contains(&id)) {
+        return Ok(None);
+    }
+    let mut constructor_name = None;
+    for codec in codecs.iter() {
+        if codec.matcher.matches(realm, value, &mut constructor_name)? {
+            ENCODING.with(|rc| rc.borrow_mut().push(id));
+            let res = (codec.encoder)(realm, value);
+            ENCODING.with(|rc| rc.borrow_mut().pop());
+            return res.map(Some);
+        }
+    }
+    Ok(None)
+}
+
+/// decode the tag and data of a [JsValueFacade::Custom] with the first codec which accepts the tag
+pub(crate) fn decode_q(
+    realm: &QuickJsRealmAdapter,
+    tag: &str,
+    data: &[u8],
+) -> Result<QuickJsValueAdapter, JsError> {
+    let codecs = VALUE_CODECS.with(|rc| rc.borrow().clone());
+    for codec in codecs.iter() {
+        if let Some(value) = (codec.decoder)(realm, tag, data)? {
+            return Ok(value);
+        }
+    }
+    Err(JsError::new_string(format!(
+        "no value codec could decode a custom value with tag {tag}"
+    )))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::Script;
+    use crate::quickjs_utils::codecs::JsClassMatcher;
+    use crate::quickjs_utils::{arrays, functions, get_global_q, objects};
+    use crate::values::JsValueFacade;
+
+    #[test]
+    fn test_value_codecs() {
+        // a List is encoded as an Array of its elements, which are converted by the other codecs
+        let rt =
+            QuickJsRuntimeBuilder::new()
+                .register_value_codec(
+                    JsClassMatcher::constructor_name("Money"),
+                    |realm, value| {
+                        let cents = objects::get_property_q(realm, value, "cents")?.to_i32();
+                        Ok(JsValueFacade::new_custom(
+                            "money",
+                            cents.to_le_bytes().to_vec(),
+                        ))
+                    },
+                    |realm, tag, data| {
+                        if tag != "money" {
+                            return Ok(None);
+                        }
+                        let cents = i32::from_le_bytes(data.try_into().expect("invalid money"));
+                        let constructor =
+                            objects::get_property_q(realm, &get_global_q(realm), "Money")?;
+                        let cents = realm.create_i32(cents)?;
+                        let money = unsafe {
+                            objects::construct_object(realm.context, &constructor, &[&cents])
+                        }?;
+                        Ok(Some(money))
+                    },
+                )
+                .register_value_codec(
+                    JsClassMatcher::predicate(|realm, value| {
+                        let marker = objects::get_property_q(realm, value, "__list")?;
+                        Ok(marker.is_bool() && marker.to_bool())
+                    }),
+                    |realm, value| {
+                        let items = objects::get_property_q(realm, value, "items")?;
+                        let mut elements = vec![];
+                        for index in 0..arrays::get_length_q(realm, &items)? {
+                            elements.push(realm.to_js_value_facade(&arrays::get_element_q(
+                                realm, &items, index,
+                            )?)?);
+                        }
+                        Ok(JsValueFacade::Array { val: elements })
+                    },
+                    |_realm, _tag, _data| Ok(None),
+                )
+                .build();
+
+        rt.eval_sync(
+            None,
+            Script::new(
+                "test_value_codecs.js",
+                "class Money { constructor(cents) { this.cents = cents; } add(o) { return new Money(this.cents + o.cents); } };\
+                 globalThis.Money = Money;\
+                 globalThis.list = {__list: true, items: [new Money(1), new Money(2)]};",
+            ),
+        )
+        .expect("script failed");
+
+        let money = rt
+            .eval_sync(
+                None,
+                Script::new("test_value_codecs.js", "new Money(1250);"),
+            )
+            .expect("script failed");
+        assert!(
+            matches!(money, JsValueFacade::Custom { ref tag, ref data } if tag == "money" && data == &1250_i32.to_le_bytes())
+        );
+
+        // the elements of the List are encoded by the Money codec
+        let list = rt
+            .eval_sync(None, Script::new("test_value_codecs.js", "list;"))
+            .expect("script failed");
+        match list {
+            JsValueFacade::Array { val } => {
+                assert_eq!(val.len(), 2);
+                assert!(matches!(&val[1], JsValueFacade::Custom { tag, .. } if tag == "money"));
+            }
+            other => panic!("expected an Array but got {}", other.stringify()),
+        }
+
+        // a plain object is not touched by the codecs
+        let plain = rt
+            .eval_sync(None, Script::new("test_value_codecs.js", "({cents: 1});"))
+            .expect("script failed");
+        assert!(plain.is_js_object());
+
+        // decoding creates a real Money again
+        let res = rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let realm = q_js_rt.get_main_realm();
+            let func = realm
+                .eval(Script::new(
+                    "test_value_codecs.js",
+                    "(m) => m instanceof Money ? m.add(new Money(1)).cents : -1;",
+                ))
+                .expect("script failed");
+            let money = realm
+                .from_js_value_facade(JsValueFacade::new_custom(
+                    "money",
+                    41_i32.to_le_bytes().to_vec(),
+                ))
+                .expect("could not decode");
+            functions::call_function_q(realm, &func, &[money], None)
+                .expect("call failed")
+                .to_i32()
+        });
+        assert_eq!(res, 42);
+
+        let err = rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            q_js_rt
+                .get_main_realm()
+                .from_js_value_facade(JsValueFacade::new_custom("unknown", vec![]))
+                .is_err()
+        });
+        assert!(err);
+    }
+}
@@ -1,9 +1,29 @@
 //! Utils to compile script to bytecode and run script from bytecode
-
+//!
+//! [compile_q] and [compile_module_q] return the bytecode of a script in an envelope with the version of this crate and the engine
+//! and a checksum, [eval_compiled_q] checks the envelope before the bytecode is read so bytes which are corrupt or were compiled
+//! by another version fail with an error instead of being read by the engine
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::Script;
+//! let rt = QuickJsRuntimeBuilder::new().build();
+//! rt.exe_rt_task_in_event_loop(|q_js_rt| {
+//!     let realm = q_js_rt.get_main_realm();
+//!     let bytes = realm.compile(Script::new("bootstrap.js", "6 * 7;")).expect("compile failed");
+//!     let res = realm.eval_compiled(&bytes).expect("eval failed");
+//!     assert_eq!(res.to_i32(), 42);
+//! });
+//! ```
+
+use crate::jsutils::bytecodecache::{cache_version, fnv1a};
+use crate::jsutils::compileaudit::CompileOrigin;
 use crate::jsutils::JsError;
 use crate::jsutils::Script;
+use crate::quickjs_utils::interrupthandler::ExecutionDepthGuard;
+use crate::quickjs_utils::modules;
 use crate::quickjsrealmadapter::QuickJsRealmAdapter;
-use crate::quickjsruntimeadapter::make_cstring;
+use crate::quickjsruntimeadapter::{make_cstring, QuickJsRuntimeAdapter};
 use crate::quickjsvalueadapter::QuickJsValueAdapter;
 use libquickjs_sys as q;
 use std::os::raw::c_void;
@@ -179,6 +199,149 @@ pub unsafe fn from_bytecode(
     }
 }
 
+const ENVELOPE_MAGIC: &[u8; 8] = b"QJSRTCP1";
+
+/// the kind of script in the bytes of [compile_q] and [compile_module_q]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompiledKind {
+    Script,
+    Module,
+}
+
+fn seal(kind: CompiledKind, path: &str, bytecode: &[u8]) -> Vec<u8> {
+    let version = cache_version();
+    let mut bytes = Vec::with_capacity(bytecode.len() + path.len() + 64);
+    bytes.extend_from_slice(ENVELOPE_MAGIC);
+    bytes.extend_from_slice(&(version.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(version.as_bytes());
+    bytes.push(match kind {
+        CompiledKind::Script => 0,
+        CompiledKind::Module => 1,
+    });
+    bytes.extend_from_slice(&(path.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(path.as_bytes());
+    bytes.extend_from_slice(&fnv1a(&[bytecode]).to_le_bytes());
+    bytes.extend_from_slice(bytecode);
+    bytes
+}
+
+// the kind, the path and the bytecode in an envelope
+fn open(bytes: &[u8]) -> Result<(CompiledKind, String, &[u8]), JsError> {
+    fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8], JsError> {
+        if bytes.len() < len {
+            return Err(JsError::new_str("compiled script is truncated"));
+        }
+        let (head, tail) = bytes.split_at(len);
+        *bytes = tail;
+        Ok(head)
+    }
+    fn take_u32(bytes: &mut &[u8]) -> Result<usize, JsError> {
+        take(bytes, 4).map(|b| u32::from_le_bytes(b.try_into().unwrap()) as usize)
+    }
+    let mut rest = bytes;
+    if take(&mut rest, 8)? != ENVELOPE_MAGIC {
+        return Err(JsError::new_str("not a compiled script"));
+    }
+    let version_len = take_u32(&mut rest)?;
+    let version = take(&mut rest, version_len)?;
+    if version != cache_version().as_bytes() {
+        return Err(JsError::new_string(format!(
+            "compiled script is of version {} but this runtime is of version {}",
+            String::from_utf8_lossy(version),
+            cache_version()
+        )));
+    }
+    let kind = match take(&mut rest, 1)?[0] {
+        0 => CompiledKind::Script,
+        1 => CompiledKind::Module,
+        other => {
+            return Err(JsError::new_string(format!(
+                "compiled script has an unknown kind {other}"
+            )))
+        }
+    };
+    let path_len = take_u32(&mut rest)?;
+    let path = String::from_utf8(take(&mut rest, path_len)?.to_vec())
+        .map_err(|_| JsError::new_str("compiled script has an invalid path"))?;
+    let checksum = u64::from_le_bytes(take(&mut rest, 8)?.try_into().unwrap());
+    if rest.is_empty() || fnv1a(&[rest]) != checksum {
+        return Err(JsError::new_str("compiled script is corrupt"));
+    }
+    Ok((kind, path, rest))
+}
+
+/// compile a script to bytes which can be evaluated with [eval_compiled_q] in any realm of a runtime with the same version of this crate and the engine
+pub fn compile_q(realm: &QuickJsRealmAdapter, script: Script) -> Result<Vec<u8>, JsError> {
+    let script = QuickJsRuntimeAdapter::pre_process(script)?;
+    unsafe { QuickJsRealmAdapter::record_compile(realm.context, CompileOrigin::Eval, &script) };
+    let path = script.get_path().to_string();
+    let compiled = unsafe { compile(realm.context, script)? };
+    let bytecode = unsafe { to_bytecode(realm.context, &compiled) };
+    Ok(seal(CompiledKind::Script, path.as_str(), &bytecode))
+}
+
+/// compile a module to bytes which can be evaluated with [eval_compiled_q], module bytecode differs from script bytecode
+pub fn compile_module_q(realm: &QuickJsRealmAdapter, script: Script) -> Result<Vec<u8>, JsError> {
+    let script = QuickJsRuntimeAdapter::pre_process(script)?;
+    unsafe { QuickJsRealmAdapter::record_compile(realm.context, CompileOrigin::Module, &script) };
+    let path = script.get_path().to_string();
+    let compiled = unsafe { modules::compile_module(realm.context, script)? };
+    let bytecode = unsafe { to_bytecode(realm.context, &compiled) };
+    Ok(seal(CompiledKind::Module, path.as_str(), &bytecode))
+}
+
+/// evaluate the bytes of [compile_q] or [compile_module_q], a module resolves its imports with the module loaders of the runtime
+/// and evaluates to the same value as [eval_module](QuickJsRealmAdapter::eval_module)
+pub fn eval_compiled_q(
+    realm: &QuickJsRealmAdapter,
+    bytes: &[u8],
+) -> Result<QuickJsValueAdapter, JsError> {
+    let (kind, path, bytecode) = open(bytes)?;
+    let _depth = QuickJsRuntimeAdapter::do_with(ExecutionDepthGuard::enter)?;
+    let compiled = unsafe { from_bytecode(realm.context, bytecode)? };
+    match kind {
+        CompiledKind::Script => {
+            if !compiled.is_compiled_function() {
+                return Err(JsError::new_str("compiled script is not a script"));
+            }
+            unsafe { run_compiled_function(realm.context, &compiled) }
+        }
+        CompiledKind::Module => {
+            if !compiled.is_module() {
+                return Err(JsError::new_str("compiled script is not a module"));
+            }
+            unsafe {
+                modules::set_import_meta(realm, modules::get_module_def(&compiled), path.as_str())?;
+                // the engine frees the module when it fails to resolve or evaluate, so our reference is given up in both cases
+                let res = modules::with_static_imports(move || {
+                    if q::JS_ResolveModule(realm.context, *compiled.borrow_value()) < 0 {
+                        compiled.forget();
+                        return None;
+                    }
+                    Some(q::JS_EvalFunction(realm.context, compiled.into_raw()))
+                });
+                let ret = res.map(|raw| {
+                    QuickJsValueAdapter::new(
+                        realm.context,
+                        raw,
+                        false,
+                        true,
+                        format!("eval_compiled result of {path}").as_str(),
+                    )
+                });
+                match ret {
+                    Some(ret) if !ret.is_exception() => Ok(ret),
+                    _ => Err(
+                        QuickJsRealmAdapter::get_exception(realm.context).unwrap_or_else(|| {
+                            JsError::new_str("eval_compiled failed and could not get exception")
+                        }),
+                    ),
+                }
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 pub mod tests {
     use crate::builder::QuickJsRuntimeBuilder;
@@ -395,4 +558,69 @@ pub mod tests {
             panic!("did not get a prom");
         }
     }
+
+    #[test]
+    fn test_eval_compiled() {
+        let bytes = QuickJsRuntimeBuilder::new()
+            .build()
+            .loop_realm_sync(None, |_rt, realm| {
+                realm.compile(Script::new(
+                    "test_eval_compiled.js",
+                    "function tripled(x){return x * 3;}",
+                ))
+            })
+            .expect("compile failed");
+
+        let rt = QuickJsRuntimeBuilder::new().build();
+        block_on(rt.eval_compiled(Some("fresh_realm"), bytes.clone())).expect("eval failed");
+        let res = rt
+            .invoke_function_sync(
+                Some("fresh_realm"),
+                &[],
+                "tripled",
+                vec![JsValueFacade::new_i32(14)],
+            )
+            .expect("call failed");
+        assert_eq!(res.get_i32(), 42);
+
+        rt.loop_realm_sync(None, move |_rt, realm| {
+            let mut corrupt = bytes.clone();
+            let last = corrupt.len() - 1;
+            corrupt[last] ^= 0xff;
+            realm
+                .eval_compiled(&corrupt)
+                .expect_err("corrupt bytes evaluated");
+            realm
+                .eval_compiled(&bytes[..bytes.len() / 2])
+                .expect_err("truncated bytes evaluated");
+            realm.eval_compiled(&[]).expect_err("empty bytes evaluated");
+            let mut other_version = bytes.clone();
+            // the first char of the version after the magic and the length of the version
+            other_version[12] ^= 0xff;
+            let err = realm
+                .eval_compiled(&other_version)
+                .expect_err("bytes of another version evaluated");
+            assert!(err.get_message().contains("version"));
+        });
+    }
+
+    #[test]
+    fn test_eval_compiled_module() {
+        let rt = QuickJsRuntimeBuilder::new().build();
+        let bytes = block_on(rt.compile_module(
+            None,
+            Script::new(
+                "test_eval_compiled_module.js",
+                "export const a = 6; globalThis.fromModule = a * 7;",
+            ),
+        ))
+        .expect("compile failed");
+        rt.loop_realm_sync(Some("fresh_realm"), move |_rt, realm| {
+            realm.eval_compiled(&bytes).expect("module failed");
+            let res = realm
+                .eval(Script::new("check.js", "globalThis.fromModule;"))
+                .expect("script failed");
+            assert_eq!(res.to_i32(), 42);
+        });
+    }
 }
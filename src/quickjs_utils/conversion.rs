@@ -0,0 +1,1009 @@
+//! the rules for converting the elements of arrays and the properties of objects
+//!
+//! the conversion to a [JsValueFacade] (e.g. [CachedJsArrayRef::get_array](crate::values::CachedJsArrayRef::get_array)) and the conversion to a serde value
+//! (e.g. [value_adapter_to_serde_value](crate::quickjsrealmadapter::QuickJsRealmAdapter::value_adapter_to_serde_value)) both use the routines in this module
+//!
+//! with the default [UndefinedPolicy::Keep]
+//! * arrays stay dense, an element which is undefined is converted to [JsValueFacade::Undefined] (null in a serde value)
+//! * the holes of a sparse array are converted like undefined, [to_sparse_array_q] can be used to tell them apart, it returns None for a hole
+//! * object properties with an undefined value are kept (as null in a serde value)
+//!
+//! with [UndefinedPolicy::Prune] the conversion follows JSON.stringify, object properties which are undefined are dropped and
+//! array elements which are undefined or holes are converted to null
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::Script;
+//! use quickjs_runtime::quickjs_utils::conversion::{to_serde_value_q, UndefinedPolicy};
+//! let rt = QuickJsRuntimeBuilder::new().build();
+//! rt.exe_rt_task_in_event_loop(|q_js_rt| {
+//!     let realm = q_js_rt.get_main_realm();
+//!     let obj = realm.eval(Script::new("conversion.js", "({a: undefined, b: [1, undefined, 3]});")).expect("script failed");
+//!     let kept = to_serde_value_q(realm, &obj, UndefinedPolicy::Keep).expect("conversion failed");
+//!     assert_eq!(kept.to_string(), r#"{"a":null,"b":[1,null,3]}"#);
+//!     let pruned = to_serde_value_q(realm, &obj, UndefinedPolicy::Prune).expect("conversion failed");
+//!     assert_eq!(pruned.to_string(), r#"{"b":[1,null,3]}"#);
+//! });
+//! ```
+//!
+//! # Deadlines
+//! the walks check every [DEADLINE_CHECK_INTERVAL] values whether their caller still waits for the result, a conversion which runs
+//! within the timeout of an eval (e.g. the result of [eval_with_options](crate::facades::QuickJsRuntimeFacade::eval_with_options)) or
+//! for a future which was dropped fails with a TimeoutError instead of finishing a result nobody receives,
+//! the same check applies to [structured_clone_q](crate::quickjs_utils::structuredclone::structured_clone_q),
+//! [extract_columns_q](crate::quickjs_utils::columns::extract_columns_q) and [stringify_q](crate::quickjs_utils::json::stringify_q)
+
+use crate::jsutils::{JsError, JsValueType};
+use crate::quickjs_utils::{arrays, codecs, maps, objects, sets};
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use crate::quickjsvalueadapter::QuickJsValueAdapter;
+use crate::values::{CachedJsObjectRef, JsValueFacade};
+use serde_json::Value;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// the number of values which are converted between two checks of the deadline of the caller, see [Deadlines](self#deadlines)
+pub const DEADLINE_CHECK_INTERVAL: usize = 1024;
+
+// the deadline and the dropped flag of a caller
+type CallerLimits = (Option<Instant>, Option<Arc<AtomicBool>>);
+
+thread_local! {
+    // the caller whose result is being converted, see with_caller_deadline
+    static CALLER: RefCell<Option<CallerLimits>> = RefCell::new(None);
+    // the number of values which were converted by this thread
+    static TICKS: Cell<usize> = Cell::new(0);
+}
+
+/// run a conversion for a caller which gives up at the deadline or when the flag is set, e.g. when its future was dropped
+pub(crate) fn with_caller_deadline<R, F: FnOnce() -> R>(
+    deadline: Option<Instant>,
+    dropped: Option<Arc<AtomicBool>>,
+    conversion: F,
+) -> R {
+    let previous = CALLER.with(|rc| rc.replace(Some((deadline, dropped))));
+    let res = conversion();
+    CALLER.with(|rc| *rc.borrow_mut() = previous);
+    res
+}
+
+// the deadline of the caller and of the running eval, whichever passes first, and the dropped flag of the caller
+fn caller_limits() -> CallerLimits {
+    let (caller_deadline, dropped) = CALLER.with(|rc| rc.borrow().clone()).unwrap_or_default();
+    let eval_deadline = QuickJsRuntimeAdapter::do_with(|q_js_rt| q_js_rt.interrupt_deadline.get());
+    let deadline = match (caller_deadline, eval_deadline) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
+    (deadline, dropped)
+}
+
+/// true when a conversion which runs now may be aborted
+pub(crate) fn has_caller_deadline() -> bool {
+    let (deadline, dropped) = caller_limits();
+    deadline.is_some() || dropped.is_some()
+}
+
+/// fail with a TimeoutError when the caller of the conversion no longer waits for it
+pub(crate) fn check_caller_deadline() -> Result<(), JsError> {
+    let (deadline, dropped) = caller_limits();
+    let dropped = dropped
+        .map(|dropped| dropped.load(Ordering::SeqCst))
+        .unwrap_or(false);
+    let passed = deadline
+        .map(|deadline| Instant::now() >= deadline)
+        .unwrap_or(false);
+    if dropped || passed {
+        Err(JsError::new(
+            "TimeoutError".to_string(),
+            "the conversion was aborted, the caller no longer waits for it".to_string(),
+            "".to_string(),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// count a converted value, the deadline is checked every [DEADLINE_CHECK_INTERVAL] values
+pub(crate) fn tick() -> Result<(), JsError> {
+    let count = TICKS.with(|ticks| {
+        let count = ticks.get().wrapping_add(1);
+        ticks.set(count);
+        count
+    });
+    if count % DEADLINE_CHECK_INTERVAL == 0 {
+        check_caller_deadline()
+    } else {
+        Ok(())
+    }
+}
+
+/// how undefined array elements and object properties are converted
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UndefinedPolicy {
+    /// keep undefined array elements and object properties
+    #[default]
+    Keep,
+    /// drop undefined object properties and convert undefined array elements to null, like JSON.stringify
+    Prune,
+}
+
+/// an element of an array, see [traverse_elements_q]
+pub enum ArrayElement<'a> {
+    Value(&'a QuickJsValueAdapter),
+    /// an index of a sparse array which has no element
+    Hole,
+}
+
+/// visit every index of an array from 0 to its length, holes included
+pub fn traverse_elements_q<F>(
+    realm: &QuickJsRealmAdapter,
+    array: &QuickJsValueAdapter,
+    mut visitor: F,
+) -> Result<(), JsError>
+where
+    F: FnMut(u32, ArrayElement) -> Result<(), JsError>,
+{
+    for index in 0..arrays::get_length_q(realm, array)? {
+        tick()?;
+        if arrays::has_element_q(realm, array, index)? {
+            let element = arrays::get_element_q(realm, array, index)?;
+            visitor(index, ArrayElement::Value(&element))?;
+        } else {
+            visitor(index, ArrayElement::Hole)?;
+        }
+    }
+    Ok(())
+}
+
+/// visit the own enumerable properties of an object, undefined properties are skipped when pruning
+pub fn traverse_entries_q<F>(
+    realm: &QuickJsRealmAdapter,
+    object: &QuickJsValueAdapter,
+    policy: UndefinedPolicy,
+    mut visitor: F,
+) -> Result<(), JsError>
+where
+    F: FnMut(&str, &QuickJsValueAdapter) -> Result<(), JsError>,
+{
+    objects::traverse_properties_q_mut(realm, object, |name, value| {
+        tick()?;
+        if policy == UndefinedPolicy::Prune && value.is_undefined() {
+            Ok(())
+        } else {
+            visitor(name, value)
+        }
+    })
+}
+
+fn element_to_facade(
+    realm: &QuickJsRealmAdapter,
+    element: ArrayElement,
+    policy: UndefinedPolicy,
+) -> Result<JsValueFacade, JsError> {
+    match element {
+        ArrayElement::Value(value) if !value.is_undefined() => realm.to_js_value_facade(value),
+        _ => match policy {
+            UndefinedPolicy::Keep => Ok(JsValueFacade::Undefined),
+            UndefinedPolicy::Prune => Ok(JsValueFacade::Null),
+        },
+    }
+}
+
+/// convert the elements of an array to a dense Vec of facades
+pub fn to_array_q(
+    realm: &QuickJsRealmAdapter,
+    array: &QuickJsValueAdapter,
+    policy: UndefinedPolicy,
+) -> Result<Vec<JsValueFacade>, JsError> {
+    let mut vec = vec![];
+    traverse_elements_q(realm, array, |_index, element| {
+        vec.push(element_to_facade(realm, element, policy)?);
+        Ok(())
+    })?;
+    Ok(vec)
+}
+
+/// convert the elements of an array to facades, a hole is converted to None and an element which is undefined to [JsValueFacade::Undefined]
+pub fn to_sparse_array_q(
+    realm: &QuickJsRealmAdapter,
+    array: &QuickJsValueAdapter,
+) -> Result<Vec<Option<JsValueFacade>>, JsError> {
+    let mut vec = vec![];
+    traverse_elements_q(realm, array, |_index, element| {
+        vec.push(match element {
+            ArrayElement::Value(value) => Some(realm.to_js_value_facade(value)?),
+            ArrayElement::Hole => None,
+        });
+        Ok(())
+    })?;
+    Ok(vec)
+}
+
+/// convert the properties of an object to facades
+pub fn to_map_q(
+    realm: &QuickJsRealmAdapter,
+    object: &QuickJsValueAdapter,
+    policy: UndefinedPolicy,
+) -> Result<HashMap<String, JsValueFacade>, JsError> {
+    let mut map = HashMap::new();
+    traverse_entries_q(realm, object, policy, |name, value| {
+        map.insert(name.to_string(), realm.to_js_value_facade(value)?);
+        Ok(())
+    })?;
+    Ok(map)
+}
+
+/// how deep values may be nested when they are converted, see [max_conversion_depth](crate::builder::QuickJsRuntimeBuilder::max_conversion_depth)
+pub const DEFAULT_MAX_CONVERSION_DEPTH: usize = 1000;
+
+// only the start of the path of a value which is nested too deep is reported
+const MAX_PATH_SEGMENTS: usize = 16;
+
+fn describe_path<I: Iterator<Item = String>>(segments: I, truncated: bool) -> String {
+    let mut path = "$".to_string();
+    for segment in segments.take(MAX_PATH_SEGMENTS) {
+        path.push_str(segment.as_str());
+    }
+    if truncated {
+        path.push_str("...");
+    }
+    path
+}
+
+fn max_conversion_depth() -> usize {
+    QuickJsRuntimeAdapter::do_with(|q_js_rt| q_js_rt.max_conversion_depth)
+}
+
+#[derive(Clone)]
+enum SerdeKey {
+    Name(String),
+    Index(u32),
+}
+
+impl SerdeKey {
+    fn segment(&self) -> String {
+        match self {
+            SerdeKey::Name(name) => format!(".{name}"),
+            SerdeKey::Index(index) => format!("[{index}]"),
+        }
+    }
+}
+
+// an object or array which is being converted, its children are popped in order
+struct SerdeFrame {
+    key: SerdeKey,
+    value: Value,
+    children: Vec<(SerdeKey, Option<QuickJsValueAdapter>)>,
+}
+
+impl SerdeFrame {
+    fn insert(&mut self, key: SerdeKey, value: Value) {
+        match (&mut self.value, key) {
+            (Value::Object(map), SerdeKey::Name(name)) => {
+                map.insert(name, value);
+            }
+            (Value::Array(arr), _) => arr.push(value),
+            _ => {}
+        }
+    }
+}
+
+fn serde_frame_q(
+    realm: &QuickJsRealmAdapter,
+    key: SerdeKey,
+    value: &QuickJsValueAdapter,
+    policy: UndefinedPolicy,
+) -> Result<Option<SerdeFrame>, JsError> {
+    let mut children = vec![];
+    let value = match value.get_js_type() {
+        JsValueType::Object => {
+            traverse_entries_q(realm, value, policy, |name, value| {
+                children.push((SerdeKey::Name(name.to_string()), Some(value.dup())));
+                Ok(())
+            })?;
+            Value::Object(serde_json::Map::new())
+        }
+        JsValueType::Array => {
+            traverse_elements_q(realm, value, |index, element| {
+                children.push((
+                    SerdeKey::Index(index),
+                    match element {
+                        ArrayElement::Value(value) => Some(value.dup()),
+                        ArrayElement::Hole => None,
+                    },
+                ));
+                Ok(())
+            })?;
+            Value::Array(vec![])
+        }
+        _ => return Ok(None),
+    };
+    children.reverse();
+    Ok(Some(SerdeFrame {
+        key,
+        value,
+        children,
+    }))
+}
+
+fn scalar_to_serde_value(value: &QuickJsValueAdapter) -> Result<Value, JsError> {
+    match value.get_js_type() {
+        JsValueType::I32 => Ok(Value::from(value.to_i32())),
+        JsValueType::F64 => Ok(Value::from(value.to_f64())),
+        JsValueType::String => Ok(Value::from(value.to_string()?)),
+        JsValueType::Boolean => Ok(Value::from(value.to_bool())),
+        _ => Ok(Value::Null),
+    }
+}
+
+/// convert a value to a serde value, values which have no json representation (e.g. undefined or functions) are converted to null
+///
+/// nested objects and arrays are converted without recursion, a value which is nested deeper than the
+/// [max_conversion_depth](crate::builder::QuickJsRuntimeBuilder::max_conversion_depth) fails with a DepthExceeded error
+pub fn to_serde_value_q(
+    realm: &QuickJsRealmAdapter,
+    value: &QuickJsValueAdapter,
+    policy: UndefinedPolicy,
+) -> Result<Value, JsError> {
+    let root = match serde_frame_q(realm, SerdeKey::Index(0), value, policy)? {
+        Some(frame) => frame,
+        None => return scalar_to_serde_value(value),
+    };
+    let max_depth = max_conversion_depth();
+    let mut stack = vec![root];
+    loop {
+        let next = stack.last_mut().and_then(|frame| frame.children.pop());
+        match next {
+            Some((key, Some(child))) => match serde_frame_q(realm, key.clone(), &child, policy)? {
+                Some(frame) => {
+                    if stack.len() >= max_depth {
+                        let segments = stack[1..]
+                            .iter()
+                            .chain(std::iter::once(&frame))
+                            .map(|frame| frame.key.segment());
+                        return Err(JsError::new_depth_exceeded(
+                            max_depth,
+                            describe_path(segments, stack.len() > MAX_PATH_SEGMENTS).as_str(),
+                        ));
+                    }
+                    stack.push(frame);
+                }
+                None => {
+                    let value = scalar_to_serde_value(&child)?;
+                    if let Some(frame) = stack.last_mut() {
+                        frame.insert(key, value);
+                    }
+                }
+            },
+            Some((key, None)) => {
+                if let Some(frame) = stack.last_mut() {
+                    frame.insert(key, Value::Null);
+                }
+            }
+            None => {
+                let done = match stack.pop() {
+                    Some(done) => done,
+                    None => return Ok(Value::Null),
+                };
+                match stack.last_mut() {
+                    Some(parent) => parent.insert(done.key, done.value),
+                    None => return Ok(done.value),
+                }
+            }
+        }
+    }
+}
+
+// where a value of a Map or Set is found, only used to describe the path of a value which is nested too deep
+#[derive(Clone, Copy)]
+enum CollectionSlot {
+    MapKey,
+    MapValue,
+    SetValue,
+}
+
+impl CollectionSlot {
+    fn segment(&self) -> String {
+        match self {
+            CollectionSlot::MapKey => ".<map key>",
+            CollectionSlot::MapValue => ".<map value>",
+            CollectionSlot::SetValue => ".<set value>",
+        }
+        .to_string()
+    }
+}
+
+// a Map or Set which is being converted, its children are popped in order and the converted ones are collected in converted
+struct CollectionFrame {
+    slot: CollectionSlot,
+    is_map: bool,
+    children: Vec<(CollectionSlot, QuickJsValueAdapter)>,
+    converted: Vec<JsValueFacade>,
+}
+
+impl CollectionFrame {
+    fn into_facade(self) -> JsValueFacade {
+        if !self.is_map {
+            return JsValueFacade::Set {
+                values: self.converted,
+            };
+        }
+        // the keys and values of a Map were converted in turns
+        let mut converted = self.converted.into_iter();
+        let mut entries = vec![];
+        while let (Some(key), Some(value)) = (converted.next(), converted.next()) {
+            entries.push((key, value));
+        }
+        JsValueFacade::Map { entries }
+    }
+}
+
+fn collection_frame_q(
+    realm: &QuickJsRealmAdapter,
+    slot: CollectionSlot,
+    value: &QuickJsValueAdapter,
+) -> Result<Option<CollectionFrame>, JsError> {
+    let is_map = maps::is_map_q(realm, value)?;
+    let mut children = if is_map {
+        maps::entries_q(realm, value, |key, value| {
+            Ok([
+                (CollectionSlot::MapKey, key),
+                (CollectionSlot::MapValue, value),
+            ])
+        })?
+        .into_iter()
+        .flatten()
+        .collect()
+    } else if sets::is_set_q(realm, value)? {
+        sets::values_q(realm, value, |value| Ok((CollectionSlot::SetValue, value)))?
+    } else {
+        return Ok(None);
+    };
+    children.reverse();
+    Ok(Some(CollectionFrame {
+        slot,
+        is_map,
+        children,
+        converted: vec![],
+    }))
+}
+
+fn walk_collections_q(
+    realm: &QuickJsRealmAdapter,
+    stack: &mut Vec<CollectionFrame>,
+    visited: &mut HashSet<usize>,
+    max_depth: usize,
+) -> Result<JsValueFacade, JsError> {
+    loop {
+        let next = stack.last_mut().and_then(|frame| frame.children.pop());
+        match next {
+            Some((slot, child)) => {
+                tick()?;
+                let converted = if !child.is_object() {
+                    realm.to_js_value_facade(&child)?
+                } else if visited.contains(&codecs::object_id(&child)) {
+                    // a Map or Set which was copied already (e.g. one which contains itself) is passed as a reference
+                    JsValueFacade::JsObject {
+                        cached_object: CachedJsObjectRef::new(realm, child),
+                    }
+                } else {
+                    match collection_frame_q(realm, slot, &child)? {
+                        Some(frame) => {
+                            if stack.len() >= max_depth {
+                                let segments = stack[1..]
+                                    .iter()
+                                    .chain(std::iter::once(&frame))
+                                    .map(|frame| frame.slot.segment());
+                                return Err(JsError::new_depth_exceeded(
+                                    max_depth,
+                                    describe_path(segments, stack.len() > MAX_PATH_SEGMENTS)
+                                        .as_str(),
+                                ));
+                            }
+                            visited.insert(codecs::object_id(&child));
+                            stack.push(frame);
+                            continue;
+                        }
+                        None => realm.to_js_value_facade(&child)?,
+                    }
+                };
+                if let Some(frame) = stack.last_mut() {
+                    frame.converted.push(converted);
+                }
+            }
+            None => {
+                let done = match stack.pop() {
+                    Some(done) => done.into_facade(),
+                    None => return Ok(JsValueFacade::Undefined),
+                };
+                match stack.last_mut() {
+                    Some(parent) => parent.converted.push(done),
+                    None => return Ok(done),
+                }
+            }
+        }
+    }
+}
+
+/// copy a Map or Set to a facade, None when the value is not a Map or Set
+///
+/// nested Maps and Sets are copied without recursion, a Map or Set which was copied already (e.g. a Map which contains itself)
+/// is passed as a [JsObject](JsValueFacade::JsObject) and one which is nested deeper than the
+/// [max_conversion_depth](crate::builder::QuickJsRuntimeBuilder::max_conversion_depth) fails with a DepthExceeded error
+pub(crate) fn collection_to_js_value_facade_q(
+    realm: &QuickJsRealmAdapter,
+    value: &QuickJsValueAdapter,
+) -> Result<Option<JsValueFacade>, JsError> {
+    let root = match collection_frame_q(realm, CollectionSlot::SetValue, value)? {
+        Some(frame) => frame,
+        None => return Ok(None),
+    };
+    let mut visited = HashSet::from([codecs::object_id(value)]);
+    let mut stack = vec![root];
+    let res = walk_collections_q(realm, &mut stack, &mut visited, max_conversion_depth());
+    if res.is_err() {
+        dismantle(
+            stack
+                .into_iter()
+                .flat_map(|frame| frame.converted)
+                .collect(),
+        );
+    }
+    res.map(Some)
+}
+
+// where a converted value is added to its container
+enum Slot {
+    Property(String),
+    Element(u32),
+    MapValue(QuickJsValueAdapter),
+    SetValue,
+}
+
+impl Slot {
+    fn segment(&self) -> String {
+        match self {
+            Slot::Property(name) => format!(".{name}"),
+            Slot::Element(index) => format!("[{index}]"),
+            Slot::MapValue(_) => ".<map value>".to_string(),
+            Slot::SetValue => ".<set value>".to_string(),
+        }
+    }
+    fn assign(
+        self,
+        realm: &QuickJsRealmAdapter,
+        container: &QuickJsValueAdapter,
+        value: QuickJsValueAdapter,
+    ) -> Result<(), JsError> {
+        match self {
+            Slot::Property(name) => realm.set_object_property(container, name.as_str(), &value),
+            Slot::Element(index) => realm.set_array_element(container, index, &value),
+            Slot::MapValue(key) => maps::set_q(realm, container, key, value).map(|_| ()),
+            Slot::SetValue => sets::add_q(realm, container, value).map(|_| ()),
+        }
+    }
+}
+
+// a value which still has to be created and added to its container
+struct Pending {
+    container: QuickJsValueAdapter,
+    slot: Slot,
+    value: JsValueFacade,
+    depth: usize,
+}
+
+fn is_container(value: &JsValueFacade) -> bool {
+    match value {
+        JsValueFacade::Object { .. }
+        | JsValueFacade::Array { .. }
+        | JsValueFacade::Map { .. }
+        | JsValueFacade::Set { .. } => true,
+        JsValueFacade::SerdeValue { value } => value.is_object() || value.is_array(),
+        _ => false,
+    }
+}
+
+/// drop facades without recursion, a deeply nested value would overflow the stack when it is dropped
+fn dismantle(mut stack: Vec<JsValueFacade>) {
+    while let Some(value) = stack.pop() {
+        match value {
+            JsValueFacade::Object { val } => stack.extend(val.into_values()),
+            JsValueFacade::Array { val } => stack.extend(val),
+            JsValueFacade::Set { values } => stack.extend(values),
+            JsValueFacade::Map { entries } => {
+                for (key, value) in entries {
+                    stack.push(key);
+                    stack.push(value);
+                }
+            }
+            JsValueFacade::SerdeValue { value } => match value {
+                Value::Array(arr) => stack.extend(
+                    arr.into_iter()
+                        .map(|value| JsValueFacade::SerdeValue { value }),
+                ),
+                Value::Object(map) => stack.extend(
+                    map.into_iter()
+                        .map(|(_, value)| JsValueFacade::SerdeValue { value }),
+                ),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+}
+
+fn serde_scalar_q(
+    realm: &QuickJsRealmAdapter,
+    value: Value,
+) -> Result<QuickJsValueAdapter, JsError> {
+    match value {
+        Value::Bool(b) => realm.create_boolean(b),
+        Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                if i <= i32::MAX as i64 && i >= i32::MIN as i64 {
+                    return realm.create_i32(i as i32);
+                }
+            } else if let Some(i) = n.as_u64() {
+                if i <= i32::MAX as u64 {
+                    return realm.create_i32(i as i32);
+                }
+            }
+            realm.create_f64(n.as_f64().unwrap_or(f64::NAN))
+        }
+        Value::String(s) => realm.create_string(s.as_str()),
+        _ => realm.create_null(),
+    }
+}
+
+/// create a value, the contents of a container are added to the work stack in reverse so they are created in order
+fn create_q(
+    realm: &QuickJsRealmAdapter,
+    value: JsValueFacade,
+    depth: usize,
+    max_depth: usize,
+    path: &[String],
+    work: &mut Vec<Pending>,
+) -> Result<QuickJsValueAdapter, JsError> {
+    if is_container(&value) && depth >= max_depth {
+        dismantle(vec![value]);
+        return Err(JsError::new_depth_exceeded(
+            max_depth,
+            describe_path(path.iter().cloned(), depth > path.len()).as_str(),
+        ));
+    }
+    let start = work.len();
+    let mut pending = |container: &QuickJsValueAdapter, slot: Slot, value: JsValueFacade| {
+        work.push(Pending {
+            container: container.dup(),
+            slot,
+            value,
+            depth: depth + 1,
+        })
+    };
+    let created = match value {
+        JsValueFacade::Object { val } => {
+            let obj = realm.create_object()?;
+            for (name, value) in val {
+                pending(&obj, Slot::Property(name), value);
+            }
+            obj
+        }
+        JsValueFacade::Array { val } => {
+            let arr = realm.create_array()?;
+            for (index, value) in (0_u32..).zip(val) {
+                pending(&arr, Slot::Element(index), value);
+            }
+            arr
+        }
+        JsValueFacade::Map { entries } => {
+            let map = maps::new_map_q(realm)?;
+            for (key, value) in entries {
+                // keys are converted right away, a key which is itself a deeply nested value is rare
+                let key = convert_q(realm, key, depth + 1, max_depth)?;
+                pending(&map, Slot::MapValue(key), value);
+            }
+            map
+        }
+        JsValueFacade::Set { values } => {
+            let set = sets::new_set_q(realm)?;
+            for value in values {
+                pending(&set, Slot::SetValue, value);
+            }
+            set
+        }
+        JsValueFacade::SerdeValue { value } => match value {
+            Value::Object(map) => {
+                let obj = realm.create_object()?;
+                for (name, value) in map {
+                    pending(
+                        &obj,
+                        Slot::Property(name),
+                        JsValueFacade::SerdeValue { value },
+                    );
+                }
+                obj
+            }
+            Value::Array(arr) => {
+                let created = realm.create_array()?;
+                for (index, value) in (0_u32..).zip(arr) {
+                    pending(
+                        &created,
+                        Slot::Element(index),
+                        JsValueFacade::SerdeValue { value },
+                    );
+                }
+                created
+            }
+            scalar => serde_scalar_q(realm, scalar)?,
+        },
+        other => realm.leaf_from_js_value_facade(other)?,
+    };
+    work[start..].reverse();
+    Ok(created)
+}
+
+fn convert_q(
+    realm: &QuickJsRealmAdapter,
+    value: JsValueFacade,
+    depth: usize,
+    max_depth: usize,
+) -> Result<QuickJsValueAdapter, JsError> {
+    let mut work: Vec<Pending> = vec![];
+    let mut path: Vec<String> = vec![];
+    let run = || {
+        let root = create_q(realm, value, depth, max_depth, &path, &mut work)?;
+        while let Some(Pending {
+            container,
+            slot,
+            value,
+            depth: value_depth,
+        }) = work.pop()
+        {
+            let level = value_depth - depth;
+            path.truncate(level - 1);
+            if path.len() == level - 1 && level <= MAX_PATH_SEGMENTS {
+                path.push(slot.segment());
+            }
+            let created = create_q(realm, value, value_depth, max_depth, &path, &mut work)?;
+            slot.assign(realm, &container, created)?;
+        }
+        Ok(root)
+    };
+    let res = run();
+    if res.is_err() {
+        dismantle(work.into_iter().map(|pending| pending.value).collect());
+    }
+    res
+}
+
+/// convert a facade to a value, nested objects, arrays, Maps and Sets are created without recursion
+///
+/// a facade which is nested deeper than the [max_conversion_depth](crate::builder::QuickJsRuntimeBuilder::max_conversion_depth)
+/// fails with a DepthExceeded error which contains the start of the path of the value
+pub(crate) fn from_js_value_facade_q(
+    realm: &QuickJsRealmAdapter,
+    value: JsValueFacade,
+) -> Result<QuickJsValueAdapter, JsError> {
+    convert_q(realm, value, 0, max_conversion_depth())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::facades::tests::init_test_rt;
+    use crate::jsutils::taskscope::EvalOptions;
+    use crate::jsutils::Script;
+    use crate::quickjs_utils::conversion::{
+        to_array_q, to_map_q, to_serde_value_q, to_sparse_array_q, UndefinedPolicy,
+    };
+    use crate::values::JsValueFacade;
+    use futures::executor::block_on;
+    use std::collections::HashMap;
+    use std::time::{Duration, Instant};
+
+    fn types(values: &[JsValueFacade]) -> Vec<String> {
+        values
+            .iter()
+            .map(|v| v.get_value_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_undefined_conversion() {
+        let rt = init_test_rt();
+        rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let realm = q_js_rt.get_main_realm();
+            let eval = |code: &str| {
+                realm
+                    .eval(Script::new("test_undefined_conversion.js", code))
+                    .expect("script failed")
+            };
+
+            // explicit undefined, a hole, a trailing hole (length 5) and null
+            let arr = eval("let a = [1, undefined, , null]; a.length = 5; a;");
+            for policy in [UndefinedPolicy::Keep, UndefinedPolicy::Prune] {
+                assert_eq!(
+                    to_array_q(realm, &arr, policy)
+                        .expect("conversion failed")
+                        .len(),
+                    5
+                );
+            }
+            assert_eq!(
+                types(&to_array_q(realm, &arr, UndefinedPolicy::Keep).expect("conversion failed")),
+                ["I32", "Undefined", "Undefined", "Null", "Undefined"]
+            );
+            assert_eq!(
+                types(&to_array_q(realm, &arr, UndefinedPolicy::Prune).expect("conversion failed")),
+                ["I32", "Null", "Null", "Null", "Null"]
+            );
+            let sparse = to_sparse_array_q(realm, &arr).expect("conversion failed");
+            let holes: Vec<bool> = sparse.iter().map(|e| e.is_none()).collect();
+            assert_eq!(holes, [false, false, true, false, true]);
+            assert!(matches!(sparse[1], Some(JsValueFacade::Undefined)));
+
+            let obj = eval("({a: undefined, b: null, c: 1});");
+            let kept = to_map_q(realm, &obj, UndefinedPolicy::Keep).expect("conversion failed");
+            assert_eq!(kept.len(), 3);
+            assert!(matches!(kept.get("a"), Some(JsValueFacade::Undefined)));
+            let pruned = to_map_q(realm, &obj, UndefinedPolicy::Prune).expect("conversion failed");
+            assert_eq!(pruned.len(), 2);
+            assert!(!pruned.contains_key("a"));
+            assert!(matches!(pruned.get("b"), Some(JsValueFacade::Null)));
+
+            let nested = eval("({a: undefined, b: [undefined, , {c: undefined}], d: () => {}});");
+            assert_eq!(
+                to_serde_value_q(realm, &nested, UndefinedPolicy::Keep)
+                    .expect("conversion failed")
+                    .to_string(),
+                r#"{"a":null,"b":[null,null,{"c":null}],"d":null}"#
+            );
+            assert_eq!(
+                to_serde_value_q(realm, &nested, UndefinedPolicy::Prune)
+                    .expect("conversion failed")
+                    .to_string(),
+                r#"{"b":[null,null,{}],"d":null}"#
+            );
+            // the default conversion keeps undefined
+            assert_eq!(
+                realm
+                    .value_adapter_to_serde_value(&nested)
+                    .expect("conversion failed")
+                    .to_string(),
+                r#"{"a":null,"b":[null,null,{"c":null}],"d":null}"#
+            );
+        });
+
+        // the facades of cached arrays and objects use the same rules
+        let arr = rt
+            .eval_sync(
+                None,
+                Script::new("test_undefined_conversion.js", "[1, undefined, , 3];"),
+            )
+            .expect("script failed");
+        let cached_array = match arr {
+            JsValueFacade::JsArray { cached_array } => cached_array,
+            _ => panic!("not an array"),
+        };
+        let vec = block_on(cached_array.get_array()).expect("conversion failed");
+        assert_eq!(types(&vec), ["I32", "Undefined", "Undefined", "I32"]);
+        let sparse = block_on(cached_array.get_sparse_array()).expect("conversion failed");
+        assert!(sparse[2].is_none());
+
+        let obj = rt
+            .eval_sync(
+                None,
+                Script::new("test_undefined_conversion.js", "({a: undefined, b: 1});"),
+            )
+            .expect("script failed");
+        let cached_object = match obj {
+            JsValueFacade::JsObject { cached_object } => cached_object,
+            _ => panic!("not an object"),
+        };
+        assert_eq!(
+            cached_object
+                .get_object_sync()
+                .expect("conversion failed")
+                .len(),
+            2
+        );
+        let pruned = block_on(cached_object.get_object_with_policy(UndefinedPolicy::Prune))
+            .expect("conversion failed");
+        assert_eq!(pruned.len(), 1);
+    }
+
+    fn nested_facade(levels: usize) -> JsValueFacade {
+        let mut value = JsValueFacade::new_i32(1);
+        for _ in 0..levels {
+            let mut val = HashMap::new();
+            val.insert("a".to_string(), value);
+            value = JsValueFacade::Object { val };
+        }
+        value
+    }
+
+    #[test]
+    fn test_conversion_depth() {
+        let rt = QuickJsRuntimeBuilder::new().build();
+        rt.eval_sync(
+            None,
+            Script::new(
+                "depth.js",
+                "this.com = {depth: {check: (o) => o.a.a.a.a === 1 || typeof o}};",
+            ),
+        )
+        .expect("script failed");
+
+        // a deep facade fails without overflowing the stack of the worker
+        let err = rt
+            .invoke_function_sync(
+                None,
+                &["com", "depth"],
+                "check",
+                vec![nested_facade(100_000)],
+            )
+            .expect_err("conversion succeeded");
+        assert!(err.is_depth_exceeded());
+        assert!(err.get_message().contains("$.a.a.a"));
+        let res = rt
+            .invoke_function_sync(None, &["com", "depth"], "check", vec![nested_facade(4)])
+            .expect("func failed");
+        assert!(res.get_bool());
+
+        // the same for a deep script value which is converted to a serde value
+        let res = rt.loop_realm_sync(None, |_rt, realm| {
+            let deep = realm
+                .eval(Script::new(
+                    "depth.js",
+                    "let o = 1; for (let i = 0; i < 100000; i++) { o = {a: o}; } o;",
+                ))
+                .expect("script failed");
+            to_serde_value_q(realm, &deep, UndefinedPolicy::Keep).map(|_| ())
+        });
+        assert!(res.expect_err("conversion succeeded").is_depth_exceeded());
+
+        let rt = QuickJsRuntimeBuilder::new().max_conversion_depth(3).build();
+        let res = rt.loop_realm_sync(None, |_rt, realm| {
+            let fits = realm.from_js_value_facade(nested_facade(3)).is_ok();
+            let err = realm
+                .from_js_value_facade(nested_facade(4))
+                .expect_err("conversion succeeded");
+            (fits, err.get_message().to_string())
+        });
+        assert!(res.0);
+        assert!(res.1.ends_with("at $.a.a.a"), "{}", res.1);
+    }
+
+    #[test]
+    fn test_conversion_deadline() {
+        let rt = QuickJsRuntimeBuilder::new().build();
+        rt.eval_sync(
+            None,
+            Script::new(
+                "test_conversion_deadline.js",
+                "globalThis.big = new Map(); for (let i = 0; i < 1000000; i++) { big.set(i, {i}); }",
+            ),
+        )
+        .expect("script failed");
+
+        let started = Instant::now();
+        let res = block_on(rt.eval_with_options(
+            None,
+            Script::new("test_conversion_deadline.js", "big;"),
+            EvalOptions::new().timeout(Duration::from_millis(20)),
+        ));
+        let err = res.expect_err("conversion should time out");
+        assert_eq!(err.get_name(), "TimeoutError");
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        // the worker is free again
+        let started = Instant::now();
+        rt.exe_task_in_event_loop(|| ());
+        assert!(started.elapsed() < Duration::from_millis(100));
+    }
+}
@@ -84,8 +84,11 @@ pub unsafe fn get_time(
 pub mod tests {
 
     use crate::facades::tests::init_test_rt;
+    use crate::jsutils::Script;
     use crate::quickjs_utils::dates;
     use crate::quickjs_utils::dates::{get_time_q, is_date_q, set_time_q};
+    use crate::values::{JsValueConvertable, JsValueFacade};
+    use std::time::{Duration, UNIX_EPOCH};
 
     #[test]
     fn test_date() {
@@ -118,4 +121,55 @@ pub mod tests {
             }
         });
     }
+
+    #[test]
+    fn test_date_facade() {
+        let rt = init_test_rt();
+        rt.eval_sync(
+            None,
+            Script::new(
+                "dates.js",
+                "this.com = {dates: {echo: (d) => d, isDate: (d) => d instanceof Date, invalid: () => new Date('nope')}};",
+            ),
+        )
+        .expect("script failed");
+
+        // a date arg is a real Date and keeps its millis
+        let time = UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+        let is_date = rt
+            .invoke_function_sync(
+                None,
+                &["com", "dates"],
+                "isDate",
+                vec![time.to_js_value_facade()],
+            )
+            .expect("func failed");
+        assert!(is_date.get_bool());
+        let echoed = rt
+            .invoke_function_sync(
+                None,
+                &["com", "dates"],
+                "echo",
+                vec![time.to_js_value_facade()],
+            )
+            .expect("func failed");
+        assert!(echoed.is_date());
+        assert_eq!(echoed.get_date(), Some(1_700_000_000_123f64));
+
+        // invalid dates stay invalid both ways
+        let invalid = rt
+            .invoke_function_sync(None, &["com", "dates"], "invalid", vec![])
+            .expect("func failed");
+        assert!(invalid.is_date());
+        assert_eq!(invalid.get_date(), None);
+        let echoed = rt
+            .invoke_function_sync(
+                None,
+                &["com", "dates"],
+                "echo",
+                vec![JsValueFacade::new_date(f64::NAN)],
+            )
+            .expect("func failed");
+        assert_eq!(echoed.get_date(), None);
+    }
 }
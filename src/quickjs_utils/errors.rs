@@ -1,35 +1,142 @@
 //! utils for getting and reporting exceptions
 
-use crate::jsutils::JsError;
-use crate::quickjs_utils::{objects, primitives};
+use crate::jsutils::{JsError, JsErrorKind, JsValueType};
+use crate::quickjs_utils::{allocator, functions, json, objects, primitives};
 use crate::quickjsrealmadapter::QuickJsRealmAdapter;
-use crate::quickjsvalueadapter::{QuickJsValueAdapter, TAG_EXCEPTION};
+use crate::quickjsvalueadapter::{QuickJsValueAdapter, TAG_EXCEPTION, TAG_UNINITIALIZED};
+use crate::values::JsValueFacade;
 use libquickjs_sys as q;
+use std::time::Duration;
 
 /// Get the last exception from the runtime, and if present, convert it to an JsError.
 /// # Safety
 /// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
 pub unsafe fn get_exception(context: *mut q::JSContext) -> Option<JsError> {
     log::trace!("get_exception");
-    let exception_val = q::JS_GetException(context);
-    log::trace!("get_exception / 2");
-    let exception_ref =
-        QuickJsValueAdapter::new(context, exception_val, false, true, "errors::get_exception");
+    let exception_ref = take_exception(context)?;
+    let err = thrown_to_js_error(context, &exception_ref);
+    // QuickJS throws an InternalError when an allocation fails
+    let err = if allocator::take_limit_hit() && err.get_name().eq("InternalError") {
+        err.with_kind(JsErrorKind::OutOfMemory)
+    } else {
+        err
+    };
+    Some(err.redact())
+}
 
-    if exception_ref.is_null() {
+/// Get the last exception from the runtime, or an Error with a fallback message if no exception is pending
+/// helpers which fail because of a pending exception should use this so the original exception is not lost
+/// # Safety
+/// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
+pub unsafe fn get_exception_or(context: *mut q::JSContext, fallback: &str) -> JsError {
+    get_exception(context).unwrap_or_else(|| JsError::new_str(fallback))
+}
+
+/// take the pending exception from the context without converting it, after this no exception is pending
+/// # Safety
+/// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
+pub unsafe fn take_exception(context: *mut q::JSContext) -> Option<QuickJsValueAdapter> {
+    let exception_val = q::JS_GetException(context);
+    let exception_ref = QuickJsValueAdapter::new(
+        context,
+        exception_val,
+        false,
+        true,
+        "errors::take_exception",
+    );
+    // bellard returns null and quickjs-ng returns uninitialized when no exception is pending
+    if exception_ref.is_null() || exception_ref.get_tag() == TAG_UNINITIALIZED {
         None
     } else {
-        let err = if exception_ref.is_exception() {
-            JsError::new_str("Could not get exception from runtime")
-        } else if exception_ref.is_object() {
-            error_to_js_error(context, &exception_ref)
-        } else {
-            JsError::new_str("no clue what happened")
+        Some(exception_ref)
+    }
+}
+
+/// a TypeError for native code, e.g. for an argument of the wrong type
+pub(crate) fn type_error(message: String) -> JsError {
+    JsError::new("TypeError".to_string(), message, "".to_string())
+}
+
+/// a RangeError for native code, e.g. for a value which is out of range
+pub(crate) fn range_error(message: String) -> JsError {
+    JsError::new("RangeError".to_string(), message, "".to_string())
+}
+
+/// a TimeoutError for what timed out, e.g. `timeout_error("request", timeout)` has the message "request timed out after 1s"
+pub(crate) fn timeout_error(what: &str, timeout: Duration) -> JsError {
+    JsError::new(
+        "TimeoutError".to_string(),
+        format!("{what} timed out after {timeout:?}"),
+        "".to_string(),
+    )
+}
+
+/// make a value the pending exception of the context, this replaces an exception which is already pending
+/// # Safety
+/// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
+pub unsafe fn set_exception(context: *mut q::JSContext, exception: &QuickJsValueAdapter) {
+    q::JS_Throw(context, exception.clone_value_incr_rc());
+}
+
+/// convert any value thrown by script (or a rejection reason) to a JsError
+/// for an instance of Error its custom props are kept, for other values the value itself is kept as [get_thrown_value](JsError::get_thrown_value)
+/// # Safety
+/// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
+pub unsafe fn thrown_to_js_error(
+    context: *mut q::JSContext,
+    thrown: &QuickJsValueAdapter,
+) -> JsError {
+    log::trace!("thrown_to_js_error");
+    if thrown.is_object() {
+        let err = error_to_js_error(context, thrown);
+        let thrown_is_error = is_error(context, thrown);
+        match thrown_json(context, thrown) {
+            Some(serde_json::Value::Object(mut properties)) if thrown_is_error => {
+                for name in ["name", "message", "stack", "stack2"] {
+                    properties.remove(name);
+                }
+                err.with_properties(properties)
+            }
+            Some(value) if !thrown_is_error => {
+                err.with_thrown_value(JsValueFacade::SerdeValue { value })
+            }
+            _ => err,
+        }
+    } else {
+        // a value other than an Error was thrown, e.g. throw 'oops';
+        let err = match functions::call_to_string(context, thrown) {
+            Ok(message) => JsError::new_string(message),
+            Err(e) => JsError::new_string(format!(
+                "a value was thrown which could not be converted to a string: {}",
+                e.get_message()
+            )),
+        };
+        let value = match thrown.get_js_type() {
+            JsValueType::I32 => JsValueFacade::new_i32(thrown.to_i32()),
+            JsValueType::F64 => JsValueFacade::new_f64(thrown.to_f64()),
+            JsValueType::Boolean => JsValueFacade::new_bool(thrown.to_bool()),
+            JsValueType::String => JsValueFacade::new_string(err.get_message().to_string()),
+            JsValueType::Null => JsValueFacade::Null,
+            JsValueType::Undefined => JsValueFacade::Undefined,
+            _ => return err,
         };
-        Some(err)
+        err.with_thrown_value(value)
     }
 }
 
+// the thrown object as json, None if it could not be stringified (e.g. it's circular)
+unsafe fn thrown_json(
+    context: *mut q::JSContext,
+    thrown: &QuickJsValueAdapter,
+) -> Option<serde_json::Value> {
+    let json_ref = json::stringify(context, thrown, None).ok()?;
+    if !json_ref.is_string() {
+        return None;
+    }
+    let json_str = primitives::to_string(context, &json_ref).ok()?;
+    serde_json::from_str(json_str.as_str()).ok()
+}
+
 /// convert an instance of Error to JsError
 /// # Safety
 /// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
@@ -38,33 +145,31 @@ pub unsafe fn error_to_js_error(
     exception_ref: &QuickJsValueAdapter,
 ) -> JsError {
     log::trace!("error_to_js_error");
-    let name_ref = objects::get_property(context, exception_ref, "name")
-        .ok()
-        .unwrap();
-    let name_string = primitives::to_string(context, &name_ref).ok().unwrap();
-    let message_ref = objects::get_property(context, exception_ref, "message")
-        .ok()
-        .unwrap();
-    let message_string = primitives::to_string(context, &message_ref).ok().unwrap();
-    let stack_ref = objects::get_property(context, exception_ref, "stack")
-        .ok()
-        .unwrap();
+    // the props may be getters which throw, in that case the defaults are used
+    let get_string_prop = |name: &str| -> Option<String> {
+        match objects::get_property(context, exception_ref, name) {
+            Ok(prop_ref) if prop_ref.is_string() => primitives::to_string(context, &prop_ref).ok(),
+            Ok(prop_ref) if prop_ref.is_null_or_undefined() => None,
+            Ok(prop_ref) => functions::call_to_string(context, &prop_ref).ok(),
+            Err(_) => None,
+        }
+    };
+    let name_string = get_string_prop("name").unwrap_or_else(|| "Error".to_string());
+    let message_string = match get_string_prop("message") {
+        Some(message) => message,
+        // e.g. throw {code: 1};
+        None if !is_error(context, exception_ref) => {
+            functions::call_to_string(context, exception_ref).unwrap_or_default()
+        }
+        None => "".to_string(),
+    };
     let mut stack_string = "".to_string();
 
-    let stack2_ref = objects::get_property(context, exception_ref, "stack2")
-        .ok()
-        .unwrap();
-    if stack2_ref.is_string() {
-        stack_string.push_str(
-            primitives::to_string(context, &stack2_ref)
-                .ok()
-                .unwrap()
-                .as_str(),
-        );
+    if let Some(stack2) = get_string_prop("stack2") {
+        stack_string.push_str(stack2.as_str());
     }
 
-    if stack_ref.is_string() {
-        let stack_str = primitives::to_string(context, &stack_ref).ok().unwrap();
+    if let Some(stack_str) = get_string_prop("stack") {
         #[cfg(feature = "typescript")]
         let stack_str = crate::typescript::unmap_stack_trace(stack_str.as_str());
 
@@ -150,7 +255,7 @@ pub unsafe fn throw(context: *mut q::JSContext, error: QuickJsValueAdapter) -> q
 pub mod tests {
     use crate::facades::tests::init_test_rt;
     use crate::jsutils::{JsError, Script};
-    use crate::quickjs_utils::functions;
+    use crate::quickjs_utils::{arrays, functions, objects};
     use crate::values::{JsValueConvertable, JsValueFacade};
     use std::thread;
     use std::time::Duration;
@@ -424,4 +529,137 @@ async function a(){
 
         std::thread::sleep(Duration::from_secs(1));
     }
+
+    #[test]
+    fn test_pending_exception() {
+        let rt = init_test_rt();
+        rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let realm = q_js_rt.get_main_realm();
+
+            // stash and restore the pending exception
+            let err = realm
+                .create_error("Error", "original failure", "")
+                .expect("could not create error");
+            assert!(!realm.has_pending_exception());
+            realm.set_pending_exception(err);
+            assert!(realm.has_pending_exception());
+            let stashed = realm.take_pending_exception().expect("no exception");
+            assert!(!realm.has_pending_exception());
+            realm
+                .eval(Script::new("cleanup.js", "globalThis.cleanedUp = true;"))
+                .expect("cleanup failed");
+            realm.set_pending_exception(stashed);
+            let ex = realm.get_exception_ctx().expect("no exception");
+            assert_eq!(ex.get_message(), "original failure");
+            assert!(!realm.has_pending_exception());
+
+            // helpers should return the original exception instead of a generic error
+            let obj = realm
+                .eval(Script::new(
+                    "test_pending_exception.js",
+                    "({get a() {throw Error('getter failed');}, toString() {throw Error('toString failed');}});",
+                ))
+                .expect("script failed");
+            let err = objects::get_property_q(realm, &obj, "a").expect_err("get should fail");
+            assert_eq!(err.get_message(), "getter failed");
+            let proxy = realm
+                .eval(Script::new(
+                    "test_pending_exception.js",
+                    "new Proxy({}, {defineProperty() {throw Error('define failed');}});",
+                ))
+                .expect("script failed");
+            let val = realm.create_i32(1).expect("could not create i32");
+            let err =
+                objects::set_property_q(realm, &proxy, "b", &val).expect_err("set should fail");
+            assert_eq!(err.get_message(), "define failed");
+            let err = unsafe { functions::call_to_string(realm.context, &obj) }
+                .expect_err("toString should fail");
+            assert_eq!(err.get_message(), "toString failed");
+            let err = objects::traverse_properties_q(realm, &obj, |_name, _val| Ok(()))
+                .expect_err("traverse should fail");
+            assert_eq!(err.get_message(), "getter failed");
+
+            let arr = realm
+                .eval(Script::new(
+                    "test_pending_exception.js",
+                    "let arr = [1]; Object.defineProperty(arr, 1, {get() {throw Error('element failed');}}); arr;",
+                ))
+                .expect("script failed");
+            let err = arrays::get_element_q(realm, &arr, 1).expect_err("get should fail");
+            assert_eq!(err.get_message(), "element failed");
+            assert!(!realm.has_pending_exception());
+        });
+
+        // values other than Errors which are thrown
+        let err = rt
+            .eval_sync(
+                None,
+                Script::new("test_pending_exception.js", "throw 'plain failure';"),
+            )
+            .expect_err("script should fail");
+        assert_eq!(err.get_message(), "plain failure");
+        let err = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "test_pending_exception.js",
+                    "throw {name: 'CustomError', message: 'custom failure'};",
+                ),
+            )
+            .expect_err("script should fail");
+        assert_eq!(err.get_name(), "CustomError");
+        assert_eq!(err.get_message(), "custom failure");
+    }
+
+    #[test]
+    fn test_thrown_details() {
+        let rt = init_test_rt();
+
+        let err = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "thrown_props.js",
+                    "throw Object.assign(new TypeError('x'), {code: 7, retry: true});",
+                ),
+            )
+            .expect_err("script should have failed");
+        assert_eq!(err.get_name(), "TypeError");
+        assert_eq!(err.get_message(), "x");
+        assert!(err.get_stack().contains("thrown_props.js"));
+        assert_eq!(err.get_property("code"), Some(&serde_json::json!(7)));
+        assert_eq!(err.get_property("retry"), Some(&serde_json::json!(true)));
+        assert!(err.get_property("message").is_none());
+        assert!(err.get_thrown_value().is_none());
+
+        let err = rt
+            .eval_sync(
+                None,
+                Script::new("thrown_plain.js", "throw new Error('y');"),
+            )
+            .expect_err("script should have failed");
+        assert!(err.get_properties().is_none());
+
+        let err = rt
+            .eval_sync(None, Script::new("thrown_obj.js", "throw {code: 1};"))
+            .expect_err("script should have failed");
+        match err.get_thrown_value() {
+            Some(JsValueFacade::SerdeValue { value }) => {
+                assert_eq!(value, &serde_json::json!({"code": 1}))
+            }
+            _ => panic!("expected a serde value"),
+        }
+
+        let err = rt
+            .eval_sync(None, Script::new("thrown_str.js", "throw 'oops';"))
+            .expect_err("script should have failed");
+        assert_eq!(err.get_message(), "oops");
+        assert_eq!(err.get_thrown_value().unwrap().get_str(), "oops");
+
+        let err = rt
+            .eval_sync(None, Script::new("thrown_num.js", "throw 42;"))
+            .expect_err("script should have failed");
+        assert_eq!(err.get_message(), "42");
+        assert_eq!(err.get_thrown_value().unwrap().get_i32(), 42);
+    }
 }
@@ -7,6 +7,8 @@ use crate::quickjs_utils::{atoms, errors, objects, parse_args, primitives};
 use crate::quickjsrealmadapter::QuickJsRealmAdapter;
 use crate::quickjsruntimeadapter::{make_cstring, QuickJsRuntimeAdapter};
 use crate::quickjsvalueadapter::QuickJsValueAdapter;
+use crate::values::JsValueFacade;
+use futures::Future;
 use hirofa_utils::auto_id_map::AutoIdMap;
 use libquickjs_sys as q;
 use log::trace;
@@ -110,6 +112,8 @@ pub unsafe fn call_function_ref_args(
 ) -> Result<QuickJsValueAdapter, JsError> {
     log::trace!("functions::call_function()");
 
+    function_ref.check_context_alive()?;
+
     debug_assert!(is_function(context, function_ref));
 
     let arg_count = arguments.len() as i32;
@@ -179,6 +183,8 @@ pub unsafe fn invoke_member_function(
     //let member = get_property(context, obj_ref, function_name)?;
     //call_function(context, &member, arguments, Some(obj_ref))
 
+    obj_ref.check_context_alive()?;
+
     let arg_count = arguments.len() as i32;
 
     let atom_ref = atoms::from_string(context, function_name)?;
@@ -261,6 +267,13 @@ pub unsafe fn call_to_string(
 
         log::trace!("called JS_ToString got a {}", res_ref.borrow_value().tag);
 
+        if res_ref.is_exception() {
+            // e.g. a toString method which throws
+            return Err(errors::get_exception_or(
+                context,
+                "Could not convert value to string",
+            ));
+        }
         if !res_ref.is_string() {
             return Err(JsError::new_str("Could not convert value to string"));
         }
@@ -594,6 +607,56 @@ where
     unsafe { new_function(q_ctx.context, name, func_raw, arg_count) }
 }
 
+/// create a new Function which is backed by a closure returning a Future
+///
+/// the Function returns a Promise to script immediately, the Future is run by the executor of the runtime and the Promise
+/// is resolved (or rejected with an Error) in the EventLoop thread when the Future completes
+///
+/// the closure itself runs in the EventLoop thread, so it should convert the arguments it needs before returning the Future
+/// # Example
+/// ```rust
+/// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+/// use quickjs_runtime::jsutils::Script;
+/// use quickjs_runtime::quickjs_utils::functions::new_async_function_q;
+/// use quickjs_runtime::quickjs_utils::get_global_q;
+/// use quickjs_runtime::quickjs_utils::objects::set_property_q;
+/// use quickjs_runtime::values::JsValueFacade;
+/// let rt = QuickJsRuntimeBuilder::new().build();
+/// rt.exe_rt_task_in_event_loop(|q_js_rt| {
+///     let q_ctx = q_js_rt.get_main_realm();
+///     let func_obj = new_async_function_q(q_ctx, "myAsyncFunc", |_q_ctx, _this, args| {
+///         let a = args[0].to_i32();
+///         async move { Ok(JsValueFacade::new_i32(a * 2)) }
+///     }, 1).ok().unwrap();
+///     let global = get_global_q(q_ctx);
+///     set_property_q(q_ctx, &global, "myAsyncFunc", &func_obj).expect("set prop failed");
+/// });
+/// let res = rt.eval_sync(None, Script::new("new_async_function_q.es", "myAsyncFunc(21);")).ok().expect("script failed");
+/// assert!(res.is_js_promise());
+/// ```
+pub fn new_async_function_q<F, R>(
+    q_ctx: &QuickJsRealmAdapter,
+    name: &str,
+    func: F,
+    arg_count: u32,
+) -> Result<QuickJsValueAdapter, JsError>
+where
+    F: Fn(&QuickJsRealmAdapter, &QuickJsValueAdapter, &[QuickJsValueAdapter]) -> R + 'static,
+    R: Future<Output = Result<JsValueFacade, JsError>> + Send + 'static,
+{
+    new_function_q(
+        q_ctx,
+        name,
+        move |realm, this, args| {
+            let producer = func(realm, this, args);
+            realm.create_resolving_promise_async(producer, |realm, res| {
+                realm.from_js_value_facade(res)
+            })
+        },
+        arg_count,
+    )
+}
+
 /// create a new Function which is backed by a closure
 /// # Safety
 /// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
@@ -674,9 +737,11 @@ where
 pub mod tests {
     use crate::facades::tests::init_test_rt;
     use crate::quickjs_utils::functions::{
-        call_function_q, call_to_string_q, invoke_member_function_q, new_function_q,
+        call_function_q, call_to_string_q, invoke_member_function_q, new_async_function_q,
+        new_function_q,
     };
-    use crate::quickjs_utils::{functions, objects, primitives};
+    use crate::quickjs_utils::{functions, get_global_q, objects, primitives};
+    use crate::values::JsValueFacade;
 
     use crate::jsutils::{JsError, Script};
     use std::time::Duration;
@@ -710,6 +775,51 @@ pub mod tests {
         rt.gc_sync();
     }
 
+    #[test]
+    pub fn test_async_function() {
+        let rt = init_test_rt();
+        rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let realm = q_js_rt.get_main_realm();
+            let func = new_async_function_q(
+                realm,
+                "slowDouble",
+                |_realm, _this, args| {
+                    let a = args[0].to_i32();
+                    async move {
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        Ok(JsValueFacade::new_i32(a * 2))
+                    }
+                },
+                1,
+            )
+            .expect("new_async_function_q failed");
+            let global = get_global_q(realm);
+            objects::set_property_q(realm, &global, "slowDouble", &func)
+                .expect("set_property_q failed");
+        });
+
+        // the script which calls the function is done long before the future resolves
+        rt.eval_sync(
+            None,
+            Script::new(
+                "test_async_function.es",
+                "globalThis.late = undefined; slowDouble(8).then((res) => {globalThis.late = res;}); globalThis.late;",
+            ),
+        )
+        .expect("script failed");
+
+        std::thread::sleep(Duration::from_millis(500));
+
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new("test_async_function2.es", "globalThis.late;"),
+            )
+            .expect("script failed");
+        assert!(res.is_i32());
+        assert_eq!(res.get_i32(), 16);
+    }
+
     #[test]
     pub fn test_ret_refcount() {
         let rt = init_test_rt();
@@ -729,7 +839,7 @@ pub mod tests {
             assert_eq!(1, a.get_ref_count());
             assert_eq!(1, b.get_ref_count());
 
-            let i_res = call_function_q(q_ctx, &func_ref, &[a.clone(), b.clone()], None)
+            let i_res = call_function_q(q_ctx, &func_ref, &[a.dup(), b.dup()], None)
                 .expect("a");
             assert!(i_res.is_object());
             assert_eq!(i_res.get_ref_count(), 1);
@@ -990,11 +1100,17 @@ unsafe extern "C" fn callback_function(
             callback(ctx, &this_ref, args_vec.as_slice());
 
         match callback_res {
-            Ok(res) => res.clone_value_incr_rc(),
+            Ok(res) => res.into_raw(),
             Err(e) => {
-                let nat_stack = format!("   at native_function [{}]\n{}", name, e.get_stack());
-                let err = errors::new_error(ctx, e.get_name(), e.get_message(), nat_stack.as_str())
-                    .expect("could not create err");
+                let nat_stack =
+                    format!("   at native_function [{}]\n{}", name, e.get_script_stack());
+                let err = errors::new_error(
+                    ctx,
+                    e.get_name(),
+                    e.get_script_message(),
+                    nat_stack.as_str(),
+                )
+                .expect("could not create err");
                 errors::throw(ctx, err)
             }
         }
@@ -1014,6 +1130,9 @@ pub mod tests2 {
         let rt = QuickJsRuntimeBuilder::new().build();
         rt.exe_rt_task_in_event_loop(|q_js_rt| {
             let q_ctx = q_js_rt.get_main_realm();
+            // functions which are installed by default features are also registered
+            let ct1_start = CALLBACK_REGISTRY.with(|rc| rc.borrow().len());
+            let ct2_start = CALLBACK_IDS.with(|rc| rc.borrow().len());
             let func = new_function_q(
                 q_ctx,
                 "test_func",
@@ -1024,14 +1143,14 @@ pub mod tests2 {
             .unwrap();
             let ct1 = CALLBACK_REGISTRY.with(|rc| rc.borrow().len());
             let ct2 = CALLBACK_IDS.with(|rc| rc.borrow().len());
-            assert_eq!(1, ct1);
-            assert_eq!(1, ct2);
+            assert_eq!(ct1_start + 1, ct1);
+            assert_eq!(ct2_start + 1, ct2);
             drop(func);
 
             let ct1 = CALLBACK_REGISTRY.with(|rc| rc.borrow().len());
             let ct2 = CALLBACK_IDS.with(|rc| rc.borrow().len());
-            assert_eq!(0, ct1);
-            assert_eq!(0, ct2);
+            assert_eq!(ct1_start, ct1);
+            assert_eq!(ct2_start, ct2);
         });
     }
 }
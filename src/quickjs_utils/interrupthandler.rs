@@ -1,9 +1,202 @@
+use crate::jsutils::{microtasks, modulecache, modulegraph, JsError};
+use crate::quickjs_utils::atoms::{self, JSAtomRef};
 use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
 use libquickjs_sys as q;
+use std::cell::{Cell, RefCell};
 use std::ffi::c_void;
 use std::os::raw::c_int;
+use std::time::{Duration, Instant};
 
-//
+// the max number of stack levels which are searched for the module whose top-level code is running
+const MAX_MODULE_STACK_LEVELS: i32 = 256;
+
+/// the default max number of nested evals, see [max_execution_depth](crate::builder::QuickJsRuntimeBuilder::max_execution_depth)
+pub const DEFAULT_MAX_EXECUTION_DEPTH: usize = 64;
+
+thread_local! {
+    // when the job which is running in the worker thread started, see QuickJsRuntimeBuilder::script_timeout
+    static JOB_START: Cell<Option<Instant>> = Cell::new(None);
+    // the id of the realm whose modules are being evaluated, see QuickJsRuntimeBuilder::module_eval_timeout
+    static MODULE_EVAL_REALM: RefCell<Option<String>> = RefCell::new(None);
+    // the module whose top-level code is running and when the interrupt handler first saw it
+    static MODULE_CLOCK: RefCell<Option<(String, Instant)>> = RefCell::new(None);
+    // the realm id and the name of a module which was interrupted by the module eval timeout
+    static TIMED_OUT_MODULE: RefCell<Option<(String, String)>> = RefCell::new(None);
+}
+
+/// marks a job of the worker thread, the script timeout is measured from the start of the outermost job
+pub(crate) struct JobTimer {
+    outermost: bool,
+}
+
+impl JobTimer {
+    pub(crate) fn start() -> Self {
+        let outermost = JOB_START.with(|start| match start.get() {
+            Some(_) => false,
+            None => {
+                start.set(Some(Instant::now()));
+                true
+            }
+        });
+        Self { outermost }
+    }
+}
+
+impl Drop for JobTimer {
+    fn drop(&mut self) {
+        if self.outermost {
+            JOB_START.with(|start| start.set(None));
+        }
+    }
+}
+
+/// marks an eval, an eval from a native function which is called by a script is nested in the eval of that script
+pub(crate) struct ExecutionDepthGuard {}
+
+impl ExecutionDepthGuard {
+    /// fails with a RangeError when the eval would be nested deeper than the max_execution_depth of the runtime
+    pub(crate) fn enter(q_js_rt: &QuickJsRuntimeAdapter) -> Result<Self, JsError> {
+        let depth = q_js_rt.execution_depth.get() + 1;
+        if depth > q_js_rt.max_execution_depth {
+            return Err(JsError::new(
+                "RangeError".to_string(),
+                format!(
+                    "maximum execution depth of {} exceeded",
+                    q_js_rt.max_execution_depth
+                ),
+                "".to_string(),
+            ));
+        }
+        q_js_rt.execution_depth.set(depth);
+        Ok(Self {})
+    }
+}
+
+impl Drop for ExecutionDepthGuard {
+    fn drop(&mut self) {
+        QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+            q_js_rt
+                .execution_depth
+                .set(q_js_rt.execution_depth.get().saturating_sub(1))
+        });
+    }
+}
+
+/// marks the evaluation of the modules of a realm, the top-level code of every module is timed while it exists
+///
+/// a scope which is entered while another scope exists (e.g. by an eval from a native function) keeps the clock of the outer module running
+pub(crate) struct ModuleEvalScope {
+    previous: Option<String>,
+    nested: bool,
+}
+
+impl ModuleEvalScope {
+    /// enter a scope, None for code which does not evaluate modules (e.g. a job which may start a dynamic import)
+    pub(crate) fn enter(realm_id: Option<&str>) -> Self {
+        let previous = MODULE_EVAL_REALM.with(|rc| rc.replace(realm_id.map(|id| id.to_string())));
+        let nested = previous.is_some();
+        if !nested {
+            MODULE_CLOCK.with(|rc| *rc.borrow_mut() = None);
+        }
+        Self { previous, nested }
+    }
+}
+
+impl Drop for ModuleEvalScope {
+    fn drop(&mut self) {
+        MODULE_EVAL_REALM.with(|rc| *rc.borrow_mut() = self.previous.take());
+        if !self.nested {
+            MODULE_CLOCK.with(|rc| *rc.borrow_mut() = None);
+        }
+    }
+}
+
+/// mark the rest of the current scope as the evaluation of the modules of a realm, used for dynamic imports which are loaded and evaluated in a job
+pub(crate) fn start_module_eval(realm_id: &str) {
+    MODULE_EVAL_REALM.with(|rc| *rc.borrow_mut() = Some(realm_id.to_string()));
+}
+
+/// take the module which was interrupted by the module eval timeout, it is invalidated so importing it again loads it again
+///
+/// returns the error for the eval which imported it
+pub(crate) fn take_timed_out_module(q_js_rt: &QuickJsRuntimeAdapter) -> Option<JsError> {
+    let (realm_id, module_name) = TIMED_OUT_MODULE.with(|rc| rc.borrow_mut().take())?;
+    let timeout = q_js_rt.module_eval_timeout.unwrap_or_default();
+    let path = modulecache::strip_generation(module_name.as_str());
+    let err = module_timeout_error(path, timeout);
+    if let Some(realm) = q_js_rt.get_realm(realm_id.as_str()) {
+        modulegraph::record_load_error(realm, path, err.get_message());
+        realm.invalidate_module(path);
+    }
+    Some(err)
+}
+
+fn module_timeout_error(path: &str, timeout: Duration) -> JsError {
+    JsError::new(
+        "TimeoutError".to_string(),
+        format!("the top-level code of module {path} did not finish within {timeout:?}"),
+        "".to_string(),
+    )
+}
+
+/// the script or module of the outermost frame of the stack, when modules are evaluated that is the module whose top-level code runs
+fn outermost_script_or_module_name(q_js_rt: &QuickJsRuntimeAdapter) -> Option<String> {
+    let context = q_js_rt.get_main_realm().context;
+    // frames of native functions do not have a name, the search ends after a few of them in a row
+    let mut name = None;
+    let mut unnamed = 0;
+    for level in 0..MAX_MODULE_STACK_LEVELS {
+        let atom = unsafe { q::JS_GetScriptOrModuleName(context, level) };
+        let atom_ref = JSAtomRef::new(context, atom);
+        match unsafe { atoms::to_string(context, &atom_ref) } {
+            Ok(frame_name) if !frame_name.is_empty() => {
+                name = Some(frame_name);
+                unnamed = 0;
+            }
+            _ => {
+                unnamed += 1;
+                if unnamed > 4 {
+                    break;
+                }
+            }
+        }
+    }
+    name
+}
+
+// check if the top-level code of a module ran longer than the module eval timeout
+fn module_timed_out(q_js_rt: &QuickJsRuntimeAdapter, timeout: Duration) -> bool {
+    let realm_id = match MODULE_EVAL_REALM.with(|rc| rc.borrow().clone()) {
+        Some(realm_id) => realm_id,
+        None => return false,
+    };
+    let module_name = match outermost_script_or_module_name(q_js_rt) {
+        Some(module_name) => module_name,
+        None => return false,
+    };
+    // a module is timed from the first time the handler sees it, the handler runs often enough for that to be close to its start
+    let started = MODULE_CLOCK.with(|rc| {
+        let clock = &mut *rc.borrow_mut();
+        match clock {
+            Some((name, started)) if *name == module_name => *started,
+            _ => {
+                let now = Instant::now();
+                *clock = Some((module_name.clone(), now));
+                now
+            }
+        }
+    });
+    if started.elapsed() < timeout {
+        return false;
+    }
+    log::warn!(
+        "interrupting module {} which ran its top-level code longer than {:?}",
+        module_name,
+        timeout
+    );
+    TIMED_OUT_MODULE.with(|rc| *rc.borrow_mut() = Some((realm_id, module_name)));
+    true
+}
 
 /// set an interrupt handler for the runtime
 /// # Safety
@@ -17,21 +210,53 @@ pub(crate) fn init(q_js_rt: &QuickJsRuntimeAdapter) {
 }
 
 unsafe extern "C" fn interrupt_handler(_rt: *mut q::JSRuntime, _opaque: *mut c_void) -> c_int {
-    QuickJsRuntimeAdapter::do_with(|q_js_rt| {
-        let handler = q_js_rt.interrupt_handler.as_ref().unwrap();
-        i32::from(handler(q_js_rt))
-    })
+    QuickJsRuntimeAdapter::do_with(|q_js_rt| i32::from(should_interrupt(q_js_rt)))
+}
+
+/// check the microtask budget, the deadline, the script timeout, the module eval timeout and the interrupt handler of the runtime, also used by rust code which blocks the worker thread
+/// (e.g. a [sync bridge](crate::jsutils::syncbridge)) and which is not interrupted by QuickJS
+pub(crate) fn should_interrupt(q_js_rt: &QuickJsRuntimeAdapter) -> bool {
+    if microtasks::is_aborting(q_js_rt) {
+        return true;
+    }
+    if let Some(deadline) = q_js_rt.interrupt_deadline.get() {
+        if Instant::now() >= deadline {
+            return true;
+        }
+    }
+    if let Some(timeout) = q_js_rt.script_timeout {
+        if let Some(start) = JOB_START.with(|start| start.get()) {
+            if start.elapsed() >= timeout {
+                log::warn!("interrupting a script which ran longer than {:?}", timeout);
+                return true;
+            }
+        }
+    }
+    if let Some(timeout) = q_js_rt.module_eval_timeout {
+        if module_timed_out(q_js_rt, timeout) {
+            return true;
+        }
+    }
+    match q_js_rt.interrupt_handler.as_ref() {
+        Some(handler) => handler(q_js_rt),
+        None => false,
+    }
 }
 
 #[cfg(test)]
 pub mod tests {
     use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::modules::ScriptModuleLoader;
+    use crate::jsutils::taskscope::EvalOptions;
     use crate::jsutils::Script;
     use crate::quickjs_utils::get_script_or_module_name_q;
+    use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+    use crate::reflection::Proxy;
 
     use std::cell::RefCell;
     use std::panic;
     use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
 
     #[test]
     fn test_interrupt_handler() {
@@ -101,4 +326,244 @@ pub mod tests {
         let lck = called.lock().unwrap();
         assert!(*lck.borrow());
     }
+
+    #[test]
+    fn test_script_timeout() {
+        let rt = QuickJsRuntimeBuilder::new()
+            .script_timeout(Duration::from_millis(100))
+            .build();
+        let start = Instant::now();
+        let err = rt
+            .eval_sync(None, Script::new("test_timeout.es", "while (true) {}"))
+            .expect_err("loop was not interrupted");
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(err.get_message().contains("interrupted"));
+
+        // promise reactions and timers are jobs as well
+        rt.eval_sync(
+            None,
+            Script::new(
+                "test_timeout.es",
+                "setTimeout(() => {while (true) {}}, 1); Promise.resolve().then(() => {while (true) {}});",
+            ),
+        )
+        .expect("script failed");
+        std::thread::sleep(Duration::from_millis(300));
+
+        let res = rt
+            .eval_sync(None, Script::new("test_timeout.es", "1 + 1;"))
+            .expect("script failed");
+        assert_eq!(res.get_i32(), 2);
+    }
+
+    struct SlowLoader {}
+
+    impl ScriptModuleLoader for SlowLoader {
+        fn normalize_path(
+            &self,
+            _realm: &QuickJsRealmAdapter,
+            _ref_path: &str,
+            path: &str,
+        ) -> Option<String> {
+            path.starts_with("slow_").then(|| path.to_string())
+        }
+
+        fn load_module(&self, _realm: &QuickJsRealmAdapter, absolute_path: &str) -> String {
+            match absolute_path {
+                "slow_spin.mes" => "export const x = 1; while (globalThis.spin) {}".to_string(),
+                _ => "const start = Date.now(); while (Date.now() - start < 60) {} export const y = 2;"
+                    .to_string(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_module_eval_timeout() {
+        let rt = QuickJsRuntimeBuilder::new()
+            .script_module_loader(SlowLoader {})
+            .module_eval_timeout(Duration::from_millis(100))
+            .build();
+
+        // every module gets the full timeout
+        rt.eval_module_sync(
+            None,
+            Script::new(
+                "test_module_eval_timeout.mes",
+                "import {y as a} from 'slow_a.mes'; import {y as b} from 'slow_b.mes'; globalThis.ab = a + b;",
+            ),
+        )
+        .expect("module failed");
+
+        rt.eval_sync(None, Script::new("spin.js", "globalThis.spin = true;"))
+            .expect("script failed");
+        let start = Instant::now();
+        let err = rt
+            .eval_module_sync(
+                None,
+                Script::new(
+                    "test_module_eval_timeout.mes",
+                    "import {x} from 'slow_spin.mes'; globalThis.x = x;",
+                ),
+            )
+            .expect_err("module was not interrupted");
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert_eq!(err.get_name(), "TimeoutError");
+        assert!(err.get_message().contains("slow_spin.mes"), "{err}");
+
+        // a dynamic import is rejected
+        rt.eval_module_sync(
+            None,
+            Script::new(
+                "test_module_eval_timeout.mes",
+                "import('slow_spin.mes').catch((e) => {globalThis.dynamic = e.message;});",
+            ),
+        )
+        .expect("module failed");
+        std::thread::sleep(Duration::from_millis(300));
+        let res = rt
+            .eval_sync(None, Script::new("check.js", "globalThis.dynamic"))
+            .expect("script failed");
+        assert_eq!(res.get_str(), "interrupted");
+
+        // the timed out module is loaded again on a retry
+        rt.eval_sync(None, Script::new("spin.js", "globalThis.spin = false;"))
+            .expect("script failed");
+        rt.eval_module_sync(
+            None,
+            Script::new(
+                "test_module_eval_timeout.mes",
+                "import {x} from 'slow_spin.mes'; globalThis.x = x;",
+            ),
+        )
+        .expect("retry failed");
+        let res = rt
+            .eval_sync(None, Script::new("check.js", "`${ab}:${x}`"))
+            .expect("script failed");
+        assert_eq!(res.get_str(), "4:1");
+    }
+
+    #[test]
+    fn test_timeout_applies_to_dynamic_imports() {
+        let rt = QuickJsRuntimeBuilder::new()
+            .script_module_loader(SlowLoader {})
+            .default_eval_options(EvalOptions::new().timeout(Duration::from_millis(200)))
+            .build();
+        let start = Instant::now();
+        rt.eval_sync(
+            None,
+            Script::new(
+                "test_dynamic_timeout.js",
+                "globalThis.spin = true; import('slow_spin.mes').catch((e) => {globalThis.dynamic = e.message;});",
+            ),
+        )
+        .expect("script failed");
+        assert!(start.elapsed() < Duration::from_secs(2));
+        let res = rt
+            .eval_sync(None, Script::new("check.js", "globalThis.dynamic"))
+            .expect("script failed");
+        assert_eq!(res.get_str(), "interrupted");
+    }
+
+    fn install_recurser(rt: &crate::facades::QuickJsRuntimeFacade, refuse_at: Option<usize>) {
+        rt.loop_realm_sync(None, move |_rt, realm| {
+            Proxy::new()
+                .name("Recurser")
+                .static_method("recurse", move |rt, realm, args| {
+                    let n = args[0].to_i32();
+                    if let Some(refuse_at) = refuse_at {
+                        if rt.execution_depth() >= refuse_at {
+                            return realm.create_i32(n);
+                        }
+                    }
+                    // the script calls this method again
+                    realm.eval(Script::new(
+                        "recurse.js",
+                        format!("Recurser.recurse({});", n + 1).as_str(),
+                    ))
+                })
+                .install(realm, true)
+                .expect("install failed");
+        });
+    }
+
+    #[test]
+    fn test_max_execution_depth() {
+        let rt = QuickJsRuntimeBuilder::new().max_execution_depth(8).build();
+        install_recurser(&rt, None);
+        let err = rt
+            .eval_sync(None, Script::new("test_depth.js", "Recurser.recurse(0);"))
+            .expect_err("recursion was not stopped");
+        assert_eq!(err.get_name(), "RangeError");
+        assert!(err
+            .get_message()
+            .contains("maximum execution depth of 8 exceeded"));
+        // the depth is back to 0 after the failed evals
+        assert_eq!(rt.loop_sync(|rt| rt.execution_depth()), 0);
+
+        // code can refuse to recurse before the max is reached
+        let rt = QuickJsRuntimeBuilder::new().build();
+        install_recurser(&rt, Some(3));
+        let res = rt
+            .eval_sync(None, Script::new("test_depth.js", "Recurser.recurse(0);"))
+            .expect("script failed");
+        assert_eq!(res.get_i32(), 2);
+    }
+
+    #[test]
+    fn test_nested_eval_keeps_deadline() {
+        let rt = QuickJsRuntimeBuilder::new()
+            .default_eval_options(EvalOptions::new().timeout(Duration::from_millis(200)))
+            .build();
+        rt.loop_realm_sync(None, |_rt, realm| {
+            Proxy::new()
+                .name("Nested")
+                .static_method("spin", |_rt, realm, _args| {
+                    realm.eval(Script::new("spin.js", "while (true) {}"))
+                })
+                .install(realm, true)
+                .expect("install failed");
+        });
+        let start = Instant::now();
+        let err = rt
+            .eval_sync(None, Script::new("test_nested.js", "Nested.spin();"))
+            .expect_err("nested eval was not interrupted");
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert_eq!(err.get_name(), "TimeoutError");
+
+        // a nested module eval does not restart the clock of the module which runs it
+        let rt = QuickJsRuntimeBuilder::new()
+            .module_eval_timeout(Duration::from_millis(150))
+            .build();
+        rt.loop_realm_sync(None, |_rt, realm| {
+            let counter = RefCell::new(0);
+            Proxy::new()
+                .name("Nested")
+                .static_method("module", move |_rt, realm, _args| {
+                    let i = {
+                        let counter = &mut *counter.borrow_mut();
+                        *counter += 1;
+                        *counter
+                    };
+                    realm.eval_module(Script::new(
+                        format!("nested_{i}.mes").as_str(),
+                        "export const a = 1;",
+                    ))?;
+                    realm.create_null()
+                })
+                .install(realm, true)
+                .expect("install failed");
+        });
+        let start = Instant::now();
+        let err = rt
+            .eval_module_sync(
+                None,
+                Script::new(
+                    "test_nested_module.mes",
+                    "const start = Date.now(); while (Date.now() - start < 3000) { Nested.module(); }",
+                ),
+            )
+            .expect_err("module was not interrupted");
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert_eq!(err.get_name(), "TimeoutError");
+    }
 }
@@ -1,9 +1,12 @@
 //! utils for the iterator protocol
 
 use crate::jsutils::JsError;
-use crate::quickjs_utils::{functions, objects, primitives};
+use crate::quickjs_utils::atoms::JSAtomRef;
+use crate::quickjs_utils::{errors, functions, get_global, objects, primitives};
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
 use crate::quickjsvalueadapter::QuickJsValueAdapter;
 use libquickjs_sys as q;
+use std::ops::ControlFlow;
 
 /// iterate over an object conforming to the [iterator](https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Iteration_protocols#the_iterator_protocol) protocol
 /// # Safety
@@ -27,3 +30,279 @@ pub unsafe fn iterate<C: Fn(QuickJsValueAdapter) -> Result<R, JsError>, R>(
 
     Ok(res)
 }
+
+fn not_iterable_error() -> JsError {
+    JsError::new(
+        "TypeError".to_string(),
+        "value is not iterable".to_string(),
+        "".to_string(),
+    )
+}
+
+// get obj[Symbol.iterator]
+unsafe fn get_iterator_method(
+    ctx: *mut q::JSContext,
+    iterable_ref: &QuickJsValueAdapter,
+) -> Result<QuickJsValueAdapter, JsError> {
+    if iterable_ref.is_null() || iterable_ref.is_undefined() {
+        return Err(not_iterable_error());
+    }
+    let symbol_ctor = objects::get_property(ctx, &get_global(ctx), "Symbol")?;
+    let iterator_symbol = objects::get_property(ctx, &symbol_ctor, "iterator")?;
+    let atom = JSAtomRef::new(ctx, q::JS_ValueToAtom(ctx, *iterator_symbol.borrow_value()));
+    let method_val = q::JS_GetPropertyInternal(
+        ctx,
+        *iterable_ref.borrow_value(),
+        atom.get_atom(),
+        *iterable_ref.borrow_value(),
+        0,
+    );
+    let method_ref = QuickJsValueAdapter::new(
+        ctx,
+        method_val,
+        false,
+        true,
+        "iterators::get_iterator_method result",
+    );
+    if method_ref.is_exception() {
+        return Err(errors::get_exception_or(
+            ctx,
+            "could not get Symbol.iterator",
+        ));
+    }
+    if !method_ref.is_function() {
+        return Err(not_iterable_error());
+    }
+    Ok(method_ref)
+}
+
+// call iterator.return() if the iterator has one
+unsafe fn close_iterator(
+    ctx: *mut q::JSContext,
+    iterator_ref: &QuickJsValueAdapter,
+) -> Result<(), JsError> {
+    let return_ref = objects::get_property(ctx, iterator_ref, "return")?;
+    if return_ref.is_function() {
+        functions::call_function(ctx, &return_ref, &[], Some(iterator_ref))?;
+    }
+    Ok(())
+}
+
+/// consume an iterable (an array, Set, Map, string, generator or any object with a Symbol.iterator method) one value at a time,
+/// a Map yields [key, value] arrays like it does in JS
+///
+/// the iterator is closed (its return() is called) when the consumer breaks or fails or when more than max_items values were produced,
+/// the latter fails with a RangeError
+/// # Example
+/// ```rust
+/// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+/// use quickjs_runtime::jsutils::Script;
+/// use quickjs_runtime::quickjs_utils::iterators;
+/// use std::ops::ControlFlow;
+/// let rt = QuickJsRuntimeBuilder::new().build();
+/// rt.exe_rt_task_in_event_loop(|q_js_rt| {
+///     let realm = q_js_rt.get_main_realm();
+///     let set = realm.eval(Script::new("set.js", "new Set([1, 2, 3, 4])")).expect("script failed");
+///     let mut sum = 0;
+///     iterators::for_each_q(realm, &set, 100, |value| {
+///         sum += value.to_i32();
+///         Ok(if sum >= 3 { ControlFlow::Break(()) } else { ControlFlow::Continue(()) })
+///     }).expect("iteration failed");
+///     assert_eq!(sum, 3);
+/// });
+/// ```
+pub fn for_each_q<C>(
+    realm: &QuickJsRealmAdapter,
+    iterable_ref: &QuickJsValueAdapter,
+    max_items: usize,
+    consumer: C,
+) -> Result<(), JsError>
+where
+    C: FnMut(QuickJsValueAdapter) -> Result<ControlFlow<()>, JsError>,
+{
+    unsafe { for_each(realm.context, iterable_ref, max_items, consumer) }
+}
+
+/// # Safety
+/// please ensure that the QuickjsContext corresponding to the passed JSContext is still valid
+pub unsafe fn for_each<C>(
+    ctx: *mut q::JSContext,
+    iterable_ref: &QuickJsValueAdapter,
+    max_items: usize,
+    mut consumer: C,
+) -> Result<(), JsError>
+where
+    C: FnMut(QuickJsValueAdapter) -> Result<ControlFlow<()>, JsError>,
+{
+    let method_ref = get_iterator_method(ctx, iterable_ref)?;
+    let iterator_ref = functions::call_function(ctx, &method_ref, &[], Some(iterable_ref))?;
+    if !iterator_ref.is_object() {
+        return Err(JsError::new(
+            "TypeError".to_string(),
+            "Symbol.iterator did not return an object".to_string(),
+            "".to_string(),
+        ));
+    }
+    let next_ref = objects::get_property(ctx, &iterator_ref, "next")?;
+
+    let mut count = 0;
+    loop {
+        // when next() throws or returns a bad result the iterator is broken and is not closed
+        let result_ref = functions::call_function(ctx, &next_ref, &[], Some(&iterator_ref))?;
+        if !result_ref.is_object() {
+            return Err(JsError::new(
+                "TypeError".to_string(),
+                "iterator result is not an object".to_string(),
+                "".to_string(),
+            ));
+        }
+        if primitives::to_bool(&objects::get_property(ctx, &result_ref, "done")?).unwrap_or(false) {
+            return Ok(());
+        }
+        let value = objects::get_property(ctx, &result_ref, "value")?;
+        if count >= max_items {
+            let _ = close_iterator(ctx, &iterator_ref);
+            return Err(JsError::new(
+                "RangeError".to_string(),
+                format!("iterable produced more than {max_items} items"),
+                "".to_string(),
+            ));
+        }
+        count += 1;
+        match consumer(value) {
+            Ok(ControlFlow::Continue(())) => {}
+            Ok(ControlFlow::Break(())) => return close_iterator(ctx, &iterator_ref),
+            Err(err) => {
+                // the error of the consumer wins over an error of return()
+                let _ = close_iterator(ctx, &iterator_ref);
+                return Err(err);
+            }
+        }
+    }
+}
+
+/// collect the values of an iterable, fails with a RangeError when the iterable produces more than max_items values, see [for_each_q]
+pub fn collect_q(
+    realm: &QuickJsRealmAdapter,
+    iterable_ref: &QuickJsValueAdapter,
+    max_items: usize,
+) -> Result<Vec<QuickJsValueAdapter>, JsError> {
+    unsafe { collect(realm.context, iterable_ref, max_items) }
+}
+
+/// # Safety
+/// please ensure that the QuickjsContext corresponding to the passed JSContext is still valid
+pub unsafe fn collect(
+    ctx: *mut q::JSContext,
+    iterable_ref: &QuickJsValueAdapter,
+    max_items: usize,
+) -> Result<Vec<QuickJsValueAdapter>, JsError> {
+    let mut res = vec![];
+    for_each(ctx, iterable_ref, max_items, |value| {
+        res.push(value);
+        Ok(ControlFlow::Continue(()))
+    })?;
+    Ok(res)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::facades::tests::init_test_rt;
+    use crate::jsutils::{JsError, Script};
+    use crate::quickjs_utils::{arrays, iterators, objects};
+    use std::ops::ControlFlow;
+
+    #[test]
+    fn test_collect() {
+        let rt = init_test_rt();
+        rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let realm = q_js_rt.get_main_realm();
+            let eval = |code: &str| {
+                realm
+                    .eval(Script::new("test_collect.js", code))
+                    .expect("script failed")
+            };
+            let ints = |values: Vec<crate::quickjsvalueadapter::QuickJsValueAdapter>| {
+                values.iter().map(|v| v.to_i32()).collect::<Vec<i32>>()
+            };
+
+            let arr = iterators::collect_q(realm, &eval("[1, 2, 3]"), 10).expect("array failed");
+            assert_eq!(ints(arr), vec![1, 2, 3]);
+            let set = iterators::collect_q(realm, &eval("new Set([4, 4, 5])"), 10)
+                .expect("set failed");
+            assert_eq!(ints(set), vec![4, 5]);
+            let gen = iterators::collect_q(
+                realm,
+                &eval("(function* () { yield 6; yield 7; })()"),
+                10,
+            )
+            .expect("generator failed");
+            assert_eq!(ints(gen), vec![6, 7]);
+            let custom = iterators::collect_q(
+                realm,
+                &eval("({[Symbol.iterator]() { let i = 0; return {next: () => ({done: i >= 2, value: i++})}; }})"),
+                10,
+            )
+            .expect("custom iterable failed");
+            assert_eq!(ints(custom), vec![0, 1]);
+            let chars = iterators::collect_q(realm, &eval("'ab'"), 10).expect("string failed");
+            assert_eq!(chars.len(), 2);
+
+            // maps yield [key, value] pairs
+            let pairs =
+                iterators::collect_q(realm, &eval("new Map([['a', 1]])"), 10).expect("map failed");
+            assert_eq!(pairs.len(), 1);
+            assert!(pairs[0].is_array());
+            let value = arrays::get_element_q(realm, &pairs[0], 1).expect("no value");
+            assert_eq!(value.to_i32(), 1);
+
+            // not iterable
+            for code in ["({a: 1})", "42", "null"] {
+                let err = iterators::collect_q(realm, &eval(code), 10).expect_err("iterable");
+                assert_eq!(err.get_name(), "TypeError");
+            }
+
+            // the cap guards against infinite generators and closes them
+            let infinite = eval(
+                "globalThis.closed = 0; (function* () { try { let i = 0; while (true) { yield i++; } } finally { closed++; } })()",
+            );
+            let err = iterators::collect_q(realm, &infinite, 100).expect_err("not capped");
+            assert_eq!(err.get_name(), "RangeError");
+            let global = realm.get_global().expect("no global");
+            let closed = || {
+                objects::get_property_q(realm, &global, "closed")
+                    .expect("no closed")
+                    .to_i32()
+            };
+            assert_eq!(closed(), 1);
+
+            // breaking and failing consumers close the iterator
+            let mut seen = 0;
+            iterators::for_each_q(
+                realm,
+                &eval("(function* () { try { yield 1; yield 2; yield 3; } finally { closed++; } })()"),
+                10,
+                |_value| {
+                    seen += 1;
+                    Ok(if seen == 2 {
+                        ControlFlow::Break(())
+                    } else {
+                        ControlFlow::Continue(())
+                    })
+                },
+            )
+            .expect("iteration failed");
+            assert_eq!(seen, 2);
+            assert_eq!(closed(), 2);
+            let err = iterators::for_each_q(
+                realm,
+                &eval("(function* () { try { yield 1; } finally { closed++; } })()"),
+                10,
+                |_value| Err(JsError::new_str("consumer failed")),
+            )
+            .expect_err("consumer did not fail");
+            assert_eq!(err.get_message(), "consumer failed");
+            assert_eq!(closed(), 3);
+        });
+    }
+}
@@ -2,6 +2,7 @@
 
 use crate::jsutils::JsError;
 use crate::quickjs_utils;
+use crate::quickjs_utils::{conversion, functions};
 use crate::quickjsrealmadapter::QuickJsRealmAdapter;
 use crate::quickjsvalueadapter::QuickJsValueAdapter;
 use libquickjs_sys as q;
@@ -81,7 +82,21 @@ pub fn stringify_q(
     input: &QuickJsValueAdapter,
     opt_space: Option<QuickJsValueAdapter>,
 ) -> Result<QuickJsValueAdapter, JsError> {
-    unsafe { stringify(q_ctx.context, input, opt_space) }
+    if conversion::has_caller_deadline() {
+        // a replacer which returns every value unchanged, it only checks if the caller still waits
+        let replacer = functions::new_function_q(
+            q_ctx,
+            "deadlineReplacer",
+            |_realm, _this, args| {
+                conversion::tick()?;
+                Ok(args[1].dup())
+            },
+            2,
+        )?;
+        unsafe { stringify_replaced(q_ctx.context, input, &replacer, opt_space) }
+    } else {
+        unsafe { stringify(q_ctx.context, input, opt_space) }
+    }
 }
 
 /// # Safety
@@ -90,6 +105,15 @@ pub unsafe fn stringify(
     context: *mut q::JSContext,
     input: &QuickJsValueAdapter,
     opt_space: Option<QuickJsValueAdapter>,
+) -> Result<QuickJsValueAdapter, JsError> {
+    stringify_replaced(context, input, &quickjs_utils::new_null_ref(), opt_space)
+}
+
+unsafe fn stringify_replaced(
+    context: *mut q::JSContext,
+    input: &QuickJsValueAdapter,
+    replacer: &QuickJsValueAdapter,
+    opt_space: Option<QuickJsValueAdapter>,
 ) -> Result<QuickJsValueAdapter, JsError> {
     //pub fn JS_JSONStringify(
     //         ctx: *mut JSContext,
@@ -106,7 +130,7 @@ pub unsafe fn stringify(
     let val = q::JS_JSONStringify(
         context,
         *input.borrow_value(),
-        quickjs_utils::new_null(),
+        *replacer.borrow_value(),
         *space_ref.borrow_value(),
     );
     let ret = QuickJsValueAdapter::new(context, val, false, true, "json::stringify result");
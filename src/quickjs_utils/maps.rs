@@ -97,7 +97,7 @@ pub unsafe fn set(
 ///    let my_map: QuickJsValueAdapter = new_map_q(q_ctx).ok().unwrap();
 ///    let key = primitives::from_i32(12);
 ///    let value = primitives::from_i32(23);
-///    set_q(q_ctx, &my_map, key.clone(), value).ok().unwrap();
+///    set_q(q_ctx, &my_map, key.dup(), value).ok().unwrap();
 ///    let val_res = get_q(q_ctx, &my_map, key).ok().unwrap();
 ///    assert_eq!(primitives::to_i32(&val_res).ok().unwrap(), 23);
 /// });
@@ -135,7 +135,7 @@ pub unsafe fn get(
 ///    let my_map: QuickJsValueAdapter = new_map_q(q_ctx).ok().unwrap();
 ///    let key = primitives::from_i32(12);
 ///    let value = primitives::from_i32(23);
-///    set_q(q_ctx, &my_map, key.clone(), value).ok().unwrap();
+///    set_q(q_ctx, &my_map, key.dup(), value).ok().unwrap();
 ///    delete_q(q_ctx, &my_map, key).ok().unwrap();
 /// });
 /// ```
@@ -173,7 +173,7 @@ pub unsafe fn delete(
 ///    let my_map: QuickJsValueAdapter = new_map_q(q_ctx).ok().unwrap();
 ///    let key = primitives::from_i32(12);
 ///    let value = primitives::from_i32(23);
-///    set_q(q_ctx, &my_map, key.clone(), value).ok().unwrap();
+///    set_q(q_ctx, &my_map, key.dup(), value).ok().unwrap();
 ///    let bln_has = has_q(q_ctx, &my_map, key).ok().unwrap();
 ///    assert!(bln_has);
 /// });
@@ -212,7 +212,7 @@ pub unsafe fn has(
 ///    let my_map: QuickJsValueAdapter = new_map_q(q_ctx).ok().unwrap();
 ///    let key = primitives::from_i32(12);
 ///    let value = primitives::from_i32(23);
-///    set_q(q_ctx, &my_map, key.clone(), value).ok().unwrap();
+///    set_q(q_ctx, &my_map, key.dup(), value).ok().unwrap();
 ///    let i_size = size_q(q_ctx, &my_map).ok().unwrap();
 ///    assert_eq!(i_size, 1);
 /// });
@@ -243,7 +243,7 @@ pub unsafe fn size(ctx: *mut q::JSContext, map: &QuickJsValueAdapter) -> Result<
 ///    let my_map: QuickJsValueAdapter = new_map_q(q_ctx).ok().unwrap();
 ///    let key = primitives::from_i32(12);
 ///    let value = primitives::from_i32(23);
-///    set_q(q_ctx, &my_map, key.clone(), value).ok().unwrap();
+///    set_q(q_ctx, &my_map, key.dup(), value).ok().unwrap();
 ///    clear_q(q_ctx, &my_map).ok().unwrap();
 ///    let i_size = size_q(q_ctx, &my_map).ok().unwrap();
 ///    assert_eq!(i_size, 0);
@@ -384,3 +384,91 @@ pub unsafe fn entries<C: Fn(QuickJsValueAdapter, QuickJsValueAdapter) -> Result<
         consumer_producer(key, value)
     })
 }
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::Script;
+    use crate::values::JsValueFacade;
+    use futures::executor::block_on;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_map_facade() {
+        let rt = QuickJsRuntimeBuilder::new().build();
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "maps.js",
+                    "new Map([['a', new Map([[1, 'one'], [true, new Set([1, 2])]])], ['b', 2]]);",
+                ),
+            )
+            .expect("script failed");
+        assert!(res.is_map());
+        assert_eq!(res.get_map().len(), 2);
+        let nested = res.get_map_value("a").expect("no a");
+        let (key, value) = &nested.get_map()[0];
+        assert_eq!(key.get_i32(), 1);
+        assert_eq!(value.get_str(), "one");
+        let (key, value) = &nested.get_map()[1];
+        assert!(key.get_bool());
+        assert_eq!(value.get_set().len(), 2);
+        assert_eq!(
+            block_on(res.to_serde_value()).expect("no serde value"),
+            serde_json::json!({"a": [[1, "one"], [true, [1, 2]]], "b": 2})
+        );
+
+        // a Map which contains itself is copied once, the repeat is a reference to the Map
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "maps.js",
+                    "let m = new Map(); let s = new Set([1]); m.set('self', m).set('s1', s).set('s2', s); m;",
+                ),
+            )
+            .expect("script failed");
+        assert!(res.is_map());
+        assert!(res.get_map_value("self").expect("no self").is_js_object());
+        assert_eq!(res.get_map_value("s1").expect("no s1").get_set().len(), 1);
+        assert!(res.get_map_value("s2").expect("no s2").is_js_object());
+
+        // Maps are nested up to the max_conversion_depth
+        let err = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "maps.js",
+                    "let deep = new Map(); for (let i = 0; i < 2000; i++) { deep = new Map([['d', deep]]); } deep;",
+                ),
+            )
+            .expect_err("conversion should fail");
+        assert!(err.is_depth_exceeded(), "{err}");
+
+        // a HashMap is passed as a real Map
+        rt.eval_sync(
+            None,
+            Script::new(
+                "maps.js",
+                "this.com = {maps: {check: (m, s) => m instanceof Map && s instanceof Set && m.get('x') === 1 && m.get(2) === 'two' && s.has('y')}};",
+            ),
+        )
+        .expect("script failed");
+        let mut map = HashMap::new();
+        map.insert("x".to_string(), JsValueFacade::new_i32(1));
+        let mut map = JsValueFacade::new_map(map);
+        if let JsValueFacade::Map { entries } = &mut map {
+            entries.push((JsValueFacade::new_i32(2), JsValueFacade::new_str("two")));
+        }
+        let res = rt
+            .invoke_function_sync(
+                None,
+                &["com", "maps"],
+                "check",
+                vec![map, JsValueFacade::new_set(vec!["y"])],
+            )
+            .expect("func failed");
+        assert!(res.get_bool());
+    }
+}
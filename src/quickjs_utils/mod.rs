@@ -1,11 +1,16 @@
 //! low level contains utils for calling the quickjs api
+//! the commonly used parts are re-exported in the [prelude](crate::prelude), this path may change in a minor version
 
 use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
 
+pub mod allocator;
 pub mod arrays;
 pub mod atoms;
 pub mod bigints;
+pub mod codecs;
+pub mod columns;
 pub mod compile;
+pub mod conversion;
 pub mod dates;
 pub mod errors;
 pub mod functions;
@@ -15,12 +20,15 @@ pub mod json;
 pub mod maps;
 pub mod modules;
 pub mod objects;
+pub mod pinned;
 pub mod primitives;
 pub mod promises;
 pub mod properties;
 pub mod runtime;
 pub mod sets;
+pub mod structuredclone;
 pub mod typedarrays;
+pub mod watch;
 
 use crate::jsutils::JsError;
 use crate::quickjs_utils::atoms::JSAtomRef;
@@ -28,6 +36,7 @@ use crate::quickjs_utils::objects::get_property;
 use crate::quickjsrealmadapter::QuickJsRealmAdapter;
 use crate::quickjsvalueadapter::{QuickJsValueAdapter, TAG_NULL, TAG_UNDEFINED};
 use libquickjs_sys as q;
+use std::time::Instant;
 
 // todo
 // runtime and context in thread_local here
@@ -36,7 +45,12 @@ use libquickjs_sys as q;
 
 pub fn gc(q_js_rt: &QuickJsRuntimeAdapter) {
     log::trace!("GC called");
+    let started = Instant::now();
     unsafe { q::JS_RunGC(q_js_rt.runtime) }
+    q_js_rt.gc_runs.set(q_js_rt.gc_runs.get() + 1);
+    q_js_rt
+        .gc_time
+        .set(q_js_rt.gc_time.get() + started.elapsed());
     log::trace!("GC done");
 }
 
@@ -1,15 +1,38 @@
 //! utils for working with ES6 Modules
 
+use crate::jsutils::{modulecache, modulegraph};
 use crate::jsutils::{JsError, Script};
 use crate::quickjs_utils::atoms;
 use crate::quickjs_utils::atoms::JSAtomRef;
+use crate::quickjs_utils::{functions, interrupthandler, new_null_ref, objects, primitives};
 use crate::quickjsrealmadapter::QuickJsRealmAdapter;
-use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use crate::quickjsruntimeadapter::{ModuleLoader, QuickJsRuntimeAdapter};
 use crate::quickjsvalueadapter::QuickJsValueAdapter;
 use core::ptr;
+use hirofa_utils::eventloop::EventLoop;
 
 use libquickjs_sys as q;
+use std::cell::Cell;
 use std::ffi::{CStr, CString};
+use std::rc::Rc;
+use std::time::Duration;
+
+thread_local! {
+    static LOADING_STATIC_IMPORTS: Cell<bool> = Cell::new(false);
+}
+
+/// run a consumer while the imports which are loaded are static imports, these are not retried when they fail
+pub(crate) fn with_static_imports<C: FnOnce() -> R, R>(consumer: C) -> R {
+    let was_loading = LOADING_STATIC_IMPORTS.with(|flag| flag.replace(true));
+    let res = consumer();
+    LOADING_STATIC_IMPORTS.with(|flag| flag.set(was_loading));
+    res
+}
+
+/// check if the modules which are currently loaded are static imports
+pub(crate) fn is_loading_static_imports() -> bool {
+    LOADING_STATIC_IMPORTS.with(|flag| flag.get())
+}
 
 /// compile a module, used for module loading
 /// # Safety
@@ -64,6 +87,138 @@ pub fn get_module_def(value: &QuickJsValueAdapter) -> *mut q::JSModuleDef {
     unsafe { value.borrow_value().u.ptr as *mut q::JSModuleDef }
 }
 
+/// populate the import.meta object of a module before it is evaluated
+///
+/// import.meta.url is the absolute path of the module and import.meta.resolve(specifier) resolves a specifier against that path
+/// with the module loaders of the runtime without loading the module
+/// # Safety
+/// please ensure the module was compiled in the context of the realm and was not evaluated yet
+pub(crate) unsafe fn set_import_meta(
+    realm: &QuickJsRealmAdapter,
+    module: *mut q::JSModuleDef,
+    absolute_path: &str,
+) -> Result<(), JsError> {
+    // a module which was loaded again reports the path of the original module
+    let url = modulecache::strip_generation(absolute_path).to_string();
+    let meta_raw = q::JS_GetImportMeta(realm.context, module);
+    let meta = QuickJsValueAdapter::new(realm.context, meta_raw, false, true, "import.meta");
+    if meta.is_exception() {
+        return Err(QuickJsRealmAdapter::get_exception(realm.context)
+            .unwrap_or_else(|| JsError::new_str("could not get import.meta")));
+    }
+
+    objects::set_property_q(realm, &meta, "url", &realm.create_string(url.as_str())?)?;
+    let resolve = functions::new_function_q(
+        realm,
+        "resolve",
+        move |realm, _this, args| {
+            if args.is_empty() || !args[0].is_string() {
+                return Err(JsError::new_str(
+                    "import.meta.resolve expects a specifier string",
+                ));
+            }
+            let specifier = primitives::to_string_q(realm, &args[0])?;
+            let (resolved, _loader_kind) = QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+                q_js_rt.normalize_module_path(realm, url.as_str(), specifier.as_str())
+            })?;
+            realm.create_string(resolved.as_str())
+        },
+        1,
+    )?;
+    objects::set_property_q(realm, &meta, "resolve", &resolve)
+}
+
+/// a module which stands in for a module whose load failed, see [compile_retry_placeholder]
+pub(crate) struct RetryPlaceholder {
+    module: *mut q::JSModuleDef,
+    // the delay before the next attempt, the placeholder is reused for every attempt so this is updated
+    delay: Rc<Cell<Duration>>,
+}
+
+/// get the module which stands in for a module whose load failed and is attempted again
+///
+/// a dynamic import() resolves with the namespace of the module which the loader returned, the namespace of the placeholder
+/// exports a `then` function so it is a thenable and the promise of the import() adopts the result of that function instead,
+/// which is an import of the failed module which is started after the delay
+/// the delay is a timer in the EventLoop so it does not depend on the executor and the EventLoop is not blocked
+///
+/// the placeholder is compiled under its own name (`path#retry`) so the module is not cached under the name of the failed module,
+/// QuickJS can not unload a module so one placeholder per path is compiled and reused for all attempts in the realm
+/// # Safety
+/// please ensure the realm is still valid
+pub(crate) unsafe fn compile_retry_placeholder(
+    realm: &QuickJsRealmAdapter,
+    absolute_path: &str,
+    delay: Duration,
+) -> Result<*mut q::JSModuleDef, JsError> {
+    if let Some(placeholder) = realm.retry_placeholders.borrow().get(absolute_path) {
+        placeholder.delay.set(delay);
+        return Ok(placeholder.module);
+    }
+
+    let path = modulecache::strip_generation(absolute_path);
+    let generation_suffix = &absolute_path[path.len()..];
+    let placeholder_name = format!("{path}#retry{generation_suffix}");
+    let specifier = serde_json::to_string(path).map_err(|e| JsError::new_string(e.to_string()))?;
+    let code = format!(
+        "export function then(resolve, reject) {{ import.meta.backoff().then(() => import({specifier})).then(resolve, reject); }}"
+    );
+    let compiled = compile_module(
+        realm.context,
+        Script::new(placeholder_name.as_str(), code.as_str()),
+    )?;
+    let module = get_module_def(&compiled);
+
+    let meta_raw = q::JS_GetImportMeta(realm.context, module);
+    let meta = QuickJsValueAdapter::new(realm.context, meta_raw, false, true, "import.meta");
+    if meta.is_exception() {
+        return Err(QuickJsRealmAdapter::get_exception(realm.context)
+            .unwrap_or_else(|| JsError::new_str("could not get import.meta")));
+    }
+    let delay = Rc::new(Cell::new(delay));
+    let backoff_delay = delay.clone();
+    let backoff = functions::new_function_q(
+        realm,
+        "backoff",
+        move |realm, _this, _args| backoff_promise(realm, backoff_delay.get()),
+        0,
+    )?;
+    objects::set_property_q(realm, &meta, "backoff", &backoff)?;
+    realm.retry_placeholders.borrow_mut().insert(
+        absolute_path.to_string(),
+        RetryPlaceholder { module, delay },
+    );
+    Ok(module)
+}
+
+// a promise which is resolved by a timer in the EventLoop after the delay
+fn backoff_promise(
+    realm: &QuickJsRealmAdapter,
+    delay: Duration,
+) -> Result<QuickJsValueAdapter, JsError> {
+    let promise = realm.create_promise()?;
+    let res = promise.js_promise_get_value(realm);
+    let id = realm.cache_promise(promise);
+    let realm_id = realm.get_realm_id().to_string();
+    EventLoop::add_timeout(
+        move || {
+            QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+                if let Some(realm) = q_js_rt.get_realm(realm_id.as_str()) {
+                    if let Some(promise) = realm.consume_cached_promise(id) {
+                        let res = promise.js_promise_resolve(realm, &new_null_ref());
+                        if let Err(e) = res {
+                            log::error!("[{}] could not resolve the backoff: {}", realm_id, e);
+                        }
+                    }
+                }
+                q_js_rt.run_pending_jobs_if_any();
+            })
+        },
+        delay,
+    );
+    Ok(res)
+}
+
 #[allow(dead_code)]
 pub fn set_module_loader(q_js_rt: &QuickJsRuntimeAdapter) {
     log::trace!("setting up module loader");
@@ -173,18 +328,33 @@ unsafe extern "C" fn js_module_normalize(
     QuickJsRuntimeAdapter::do_with(|q_js_rt| {
         let q_ctx = q_js_rt.get_quickjs_context(ctx);
 
-        if let Some(res) = q_js_rt.with_all_module_loaders(|loader| {
-            if let Some(normalized_path) = loader.normalize_path(q_ctx, base_str, name_str) {
-                let c_absolute_path = CString::new(normalized_path.as_str()).expect("fail");
-                Some(c_absolute_path.into_raw())
-            } else {
-                None
+        let dynamic = !is_loading_static_imports();
+        // an importer which was loaded again resolves its imports like the original module
+        let base_path = modulecache::strip_generation(base_str);
+        match q_js_rt.normalize_module_path(q_ctx, base_path, name_str) {
+            Ok((normalized_path, loader_kind)) => {
+                modulegraph::record_import(
+                    q_ctx,
+                    base_path,
+                    name_str,
+                    Some((normalized_path.as_str(), loader_kind)),
+                    dynamic,
+                );
+                let module_name = modulecache::module_name_q(
+                    q_js_rt,
+                    q_ctx,
+                    base_str,
+                    normalized_path.as_str(),
+                    loader_kind,
+                );
+                let c_absolute_path = CString::new(module_name).expect("fail");
+                c_absolute_path.into_raw()
+            }
+            Err(err) => {
+                modulegraph::record_import(q_ctx, base_path, name_str, None, dynamic);
+                q_ctx.report_ex(err.get_message());
+                ptr::null_mut()
             }
-        }) {
-            res
-        } else {
-            q_ctx.report_ex(format!("Module {name_str} was not found").as_str());
-            ptr::null_mut()
         }
     })
 }
@@ -203,24 +373,40 @@ unsafe extern "C" fn js_module_loader(
 
     QuickJsRuntimeAdapter::do_with(|q_js_rt| {
         QuickJsRealmAdapter::with_context(ctx, |q_ctx| {
-            if let Some(res) = q_js_rt.with_all_module_loaders(|module_loader| {
-                if module_loader.has_module(q_ctx, module_name) {
-                    let mod_val_res = module_loader.load_module(q_ctx, module_name);
-                    return match mod_val_res {
-                        Ok(mod_val) => Some(mod_val),
-                        Err(e) => {
-                            let err =
-                                format!("Module load failed for {module_name} because of: {e}");
-                            log::error!("{}", err);
-                            q_ctx.report_ex(err.as_str());
-                            Some(std::ptr::null_mut())
-                        }
-                    };
+            let path = modulecache::strip_generation(module_name);
+            if !is_loading_static_imports() {
+                // the modules of a dynamic import are evaluated after they were loaded in the same job
+                interrupthandler::start_module_eval(q_ctx.get_realm_id());
+            }
+            let load = |module_loader: &dyn ModuleLoader| match module_loader
+                .load_module(q_ctx, module_name)
+            {
+                Ok(mod_val) => Some(mod_val),
+                Err(e) => {
+                    let err = format!("Module load failed for {path} because of: {e}");
+                    log::error!("{}", err);
+                    modulegraph::record_load_error(q_ctx, path, err.as_str());
+                    q_ctx.report_ex(err.as_str());
+                    Some(std::ptr::null_mut())
                 }
-                None
-            }) {
+            };
+            // the loader which normalized the path loads it, a module which was named by another module is loaded by the first loader which has it
+            let res = q_js_rt.with_normalizing_loader(path, load).or_else(|| {
+                q_js_rt.with_all_module_loaders(|module_loader| {
+                    if module_loader.has_module(q_ctx, path) {
+                        load(module_loader)
+                    } else {
+                        None
+                    }
+                })
+            });
+            if let Some(res) = res {
                 res
             } else {
+                // throw so a dynamic import rejects with an error instead of an empty exception
+                let err = format!("Module {path} was not found");
+                modulegraph::record_load_error(q_ctx, path, err.as_str());
+                q_ctx.report_ex(err.as_str());
                 std::ptr::null_mut()
             }
         })
@@ -229,10 +415,17 @@ unsafe extern "C" fn js_module_loader(
 
 #[cfg(test)]
 pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
     use crate::facades::tests::init_test_rt;
-    use crate::jsutils::Script;
+    use crate::facades::QuickJsRuntimeFacade;
+    use crate::jsutils::executor::{JsExecutor, JsExecutorFuture, JsExecutorTask};
+    use crate::jsutils::modules::{ResolvedSpecifier, RetryPolicy, ScriptModuleLoader};
+    use crate::jsutils::{JsError, Script};
     use crate::quickjs_utils::modules::detect_module;
+    use crate::quickjsrealmadapter::QuickJsRealmAdapter;
     use crate::values::JsValueFacade;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
     use std::time::Duration;
 
     #[test]
@@ -360,4 +553,392 @@ pub mod tests {
 
         log::info!("< test_module_sandbox");
     }
+
+    struct FlakyModuleLoader {
+        failures: u32,
+        attempts: Arc<AtomicU32>,
+    }
+
+    impl ScriptModuleLoader for FlakyModuleLoader {
+        fn normalize_path(
+            &self,
+            _realm: &QuickJsRealmAdapter,
+            _ref_path: &str,
+            path: &str,
+        ) -> Option<String> {
+            if path.eq("flaky.mes") {
+                Some(path.to_string())
+            } else {
+                None
+            }
+        }
+
+        fn load_module(&self, _realm: &QuickJsRealmAdapter, _absolute_path: &str) -> String {
+            "export const flaky = 'loaded';".to_string()
+        }
+
+        fn try_load_module(
+            &self,
+            realm: &QuickJsRealmAdapter,
+            absolute_path: &str,
+        ) -> Result<String, JsError> {
+            let attempt = self.attempts.fetch_add(1, Ordering::SeqCst) + 1;
+            if attempt <= self.failures {
+                Err(JsError::new_string(format!("network failure {attempt}")))
+            } else {
+                Ok(self.load_module(realm, absolute_path))
+            }
+        }
+    }
+
+    fn import_flaky(rt: &QuickJsRuntimeFacade) -> String {
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "test_retry.es",
+                    "import('flaky.mes').then((m) => {return m.flaky;}, (e) => {return 'error: ' + e.message;});",
+                ),
+            )
+            .expect("script failed");
+        match res {
+            JsValueFacade::JsPromise { cached_promise } => cached_promise
+                .get_promise_result_sync()
+                .expect("promise timed out")
+                .expect("promise failed")
+                .get_str()
+                .to_string(),
+            _ => panic!("not a promise"),
+        }
+    }
+
+    #[test]
+    fn test_module_load_retry() {
+        let attempts = Arc::new(AtomicU32::new(0));
+        let rt = QuickJsRuntimeBuilder::new()
+            .script_module_loader(FlakyModuleLoader {
+                failures: 3,
+                attempts: attempts.clone(),
+            })
+            .module_load_retry(RetryPolicy {
+                attempts: 3,
+                backoff: Duration::from_millis(1),
+            })
+            .build();
+
+        // static imports fail fast
+        let res = rt.eval_module_sync(
+            None,
+            Script::new("test_retry.mes", "import {flaky} from 'flaky.mes';"),
+        );
+        assert!(res.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        // a failed load is not cached so the dynamic import is retried, the last attempt succeeds
+        assert_eq!(import_flaky(&rt), "loaded");
+        assert_eq!(attempts.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn test_module_load_retry_exhausted() {
+        let attempts = Arc::new(AtomicU32::new(0));
+        let rt = QuickJsRuntimeBuilder::new()
+            .script_module_loader(FlakyModuleLoader {
+                failures: 10,
+                attempts: attempts.clone(),
+            })
+            .module_load_retry(RetryPolicy {
+                attempts: 2,
+                backoff: Duration::from_millis(1),
+            })
+            .build();
+
+        let err = import_flaky(&rt);
+        assert!(err.starts_with("error: "));
+        assert!(err.contains("attempt 1: network failure 1"));
+        assert!(err.contains("attempt 2: network failure 2"));
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_module_load_retry_does_not_block() {
+        let attempts = Arc::new(AtomicU32::new(0));
+        let rt = QuickJsRuntimeBuilder::new()
+            .script_module_loader(FlakyModuleLoader {
+                failures: 1,
+                attempts: attempts.clone(),
+            })
+            .module_load_retry(RetryPolicy {
+                attempts: 2,
+                backoff: Duration::from_millis(200),
+            })
+            .build();
+
+        // the timer fires while the import waits for its next attempt
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "test_retry.es",
+                    "let order = []; setTimeout(() => {order.push('timer');}, 1); import('flaky.mes').then((m) => {order.push(m.flaky); return order.join(',');});",
+                ),
+            )
+            .expect("script failed");
+        let res = match res {
+            JsValueFacade::JsPromise { cached_promise } => cached_promise
+                .get_promise_result_sync()
+                .expect("promise timed out")
+                .expect("promise failed"),
+            _ => panic!("not a promise"),
+        };
+        assert_eq!(res.get_str(), "timer,loaded");
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    // runs every future on a thread of its own, there is no tokio reactor
+    struct ThreadExecutor {}
+
+    impl JsExecutor for ThreadExecutor {
+        fn spawn(&self, future: JsExecutorFuture) {
+            std::thread::spawn(move || futures::executor::block_on(future));
+        }
+
+        fn spawn_blocking(&self, task: JsExecutorTask) {
+            std::thread::spawn(task);
+        }
+    }
+
+    #[test]
+    fn test_module_load_retry_without_tokio() {
+        let attempts = Arc::new(AtomicU32::new(0));
+        let rt = QuickJsRuntimeBuilder::new()
+            .executor(ThreadExecutor {})
+            .script_module_loader(FlakyModuleLoader {
+                failures: 2,
+                attempts: attempts.clone(),
+            })
+            .module_load_retry(RetryPolicy {
+                attempts: 3,
+                backoff: Duration::from_millis(1),
+            })
+            .build();
+
+        assert_eq!(import_flaky(&rt), "loaded");
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+        // both retries used the same placeholder
+        let placeholders =
+            rt.loop_realm_sync(None, |_rt, realm| realm.retry_placeholders.borrow().len());
+        assert_eq!(placeholders, 1);
+    }
+
+    struct VendoredModuleLoader {}
+
+    impl ScriptModuleLoader for VendoredModuleLoader {
+        fn normalize_path(
+            &self,
+            _realm: &QuickJsRealmAdapter,
+            _ref_path: &str,
+            path: &str,
+        ) -> Option<String> {
+            if path.starts_with("vendored/") {
+                Some(path.to_string())
+            } else {
+                None
+            }
+        }
+
+        fn load_module(&self, _realm: &QuickJsRealmAdapter, _absolute_path: &str) -> String {
+            "export const version = '4.17.21';".to_string()
+        }
+    }
+
+    fn import_version(rt: &QuickJsRuntimeFacade, specifier: &str) -> String {
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "test_resolver.es",
+                    format!("import('{specifier}').then((m) => {{return m.version;}}, (e) => {{return 'error: ' + e.message;}});").as_str(),
+                ),
+            )
+            .expect("script failed");
+        match res {
+            JsValueFacade::JsPromise { cached_promise } => cached_promise
+                .get_promise_result_sync()
+                .expect("promise timed out")
+                .expect("promise failed")
+                .get_str()
+                .to_string(),
+            _ => panic!("not a promise"),
+        }
+    }
+
+    #[test]
+    fn test_module_resolver() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let resolver_calls = calls.clone();
+        let rt = QuickJsRuntimeBuilder::new()
+            .script_module_loader(VendoredModuleLoader {})
+            .module_resolver(move |_base, specifier| {
+                resolver_calls.fetch_add(1, Ordering::SeqCst);
+                match specifier {
+                    "lodash" => {
+                        ResolvedSpecifier::Rewritten("vendored/lodash@4.17.21/index.js".to_string())
+                    }
+                    "left-pad" => ResolvedSpecifier::Rewritten("unvendored/left-pad".to_string()),
+                    "forbidden" => ResolvedSpecifier::Rejected("not in the lockfile".to_string()),
+                    _ => ResolvedSpecifier::Unchanged,
+                }
+            })
+            .build();
+
+        rt.eval_module_sync(
+            None,
+            Script::new(
+                "test_resolver.mes",
+                "import {version} from 'lodash';\nglobalThis.staticVersion = version;",
+            ),
+        )
+        .expect("module failed");
+        let res = rt
+            .eval_sync(None, Script::new("test_resolver.es", "staticVersion;"))
+            .expect("script failed");
+        assert_eq!(res.get_str(), "4.17.21");
+        // the second import from the same script does not call the resolver
+        assert_eq!(import_version(&rt, "lodash"), "4.17.21");
+        assert_eq!(import_version(&rt, "lodash"), "4.17.21");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        let err = import_version(&rt, "forbidden");
+        assert!(err.contains("rejected by the module resolver: not in the lockfile"));
+        let err = import_version(&rt, "left-pad");
+        assert!(
+            err.contains("resolved it to unvendored/left-pad which no module loader could load")
+        );
+    }
+
+    struct RelativeModuleLoader {}
+
+    impl ScriptModuleLoader for RelativeModuleLoader {
+        fn normalize_path(
+            &self,
+            _realm: &QuickJsRealmAdapter,
+            ref_path: &str,
+            path: &str,
+        ) -> Option<String> {
+            let absolute_path = match path.strip_prefix("./") {
+                Some(relative) => match ref_path.rfind('/') {
+                    Some(index) => format!("{}/{relative}", &ref_path[..index]),
+                    None => relative.to_string(),
+                },
+                None => path.to_string(),
+            };
+            match absolute_path.as_str() {
+                "app/dep.mes" | "app/sub/leaf.mes" | "app/sub/nested.mes" => Some(absolute_path),
+                _ => None,
+            }
+        }
+
+        fn load_module(&self, _realm: &QuickJsRealmAdapter, absolute_path: &str) -> String {
+            match absolute_path {
+                "app/dep.mes" => {
+                    "export const foo = 12;\nexport const leaf = () => import('./sub/leaf.mes');"
+                }
+                "app/sub/nested.mes" => {
+                    "import {url as leafUrl} from './leaf.mes';\nexport const urls = [import.meta.url, leafUrl, import.meta.resolve('./leaf.mes')];"
+                }
+                _ => "export const name = 'leaf';\nexport const url = import.meta.url;",
+            }
+            .to_string()
+        }
+    }
+
+    #[test]
+    fn test_dynamic_import() {
+        let rt = QuickJsRuntimeBuilder::new()
+            .script_module_loader(RelativeModuleLoader {})
+            .build();
+
+        // relative specifiers resolve against the importing script and module
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "app/main.es",
+                    "(async () => {const m = await import('./dep.mes'); const leaf = await m.leaf(); return m.foo === 12 && leaf.name === 'leaf';})();",
+                ),
+            )
+            .expect("script failed");
+        let res = match res {
+            JsValueFacade::JsPromise { cached_promise } => cached_promise
+                .get_promise_result_sync()
+                .expect("promise timed out")
+                .expect("promise failed"),
+            _ => panic!("not a promise"),
+        };
+        assert!(res.get_bool());
+
+        // a module no loader can load rejects with a catchable error
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "app/main.es",
+                    "import('./missing.mes').then(() => 'loaded', (e) => e.message);",
+                ),
+            )
+            .expect("script failed");
+        let msg = match res {
+            JsValueFacade::JsPromise { cached_promise } => cached_promise
+                .get_promise_result_sync()
+                .expect("promise timed out")
+                .expect("promise failed"),
+            _ => panic!("not a promise"),
+        };
+        assert!(msg.get_str().contains("Module ./missing.mes was not found"));
+    }
+
+    #[test]
+    fn test_import_meta() {
+        let rt = QuickJsRuntimeBuilder::new()
+            .script_module_loader(RelativeModuleLoader {})
+            .build();
+
+        // the urls are the absolute paths of the modules, resolve does not load the module
+        rt.eval_module_sync(
+            None,
+            Script::new(
+                "app/main.mes",
+                "import {urls} from './sub/nested.mes';\nglobalThis.urls = [import.meta.url, ...urls, import.meta.resolve('./dep.mes')].join(',');",
+            ),
+        )
+        .expect("module failed");
+        let res = rt
+            .eval_sync(None, Script::new("test_import_meta.js", "urls"))
+            .expect("script failed");
+        assert_eq!(
+            res.get_str(),
+            "app/main.mes,app/sub/nested.mes,app/sub/leaf.mes,app/sub/leaf.mes,app/dep.mes"
+        );
+
+        // a specifier which no loader can load throws
+        rt.eval_module_sync(
+            None,
+            Script::new(
+                "app/main2.mes",
+                "try {import.meta.resolve('./missing.mes');} catch(e) {globalThis.msg = e.message;}",
+            ),
+        )
+        .expect("module failed");
+        let res = rt
+            .eval_sync(None, Script::new("test_import_meta.js", "msg"))
+            .expect("script failed");
+        assert!(res.get_str().contains("Module ./missing.mes was not found"));
+
+        // scripts have no import.meta
+        let err = rt
+            .eval_sync(None, Script::new("test_import_meta.js", "import.meta.url"))
+            .expect_err("script should fail");
+        assert_eq!(err.get_name(), "SyntaxError");
+    }
 }
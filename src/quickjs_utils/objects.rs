@@ -1,12 +1,19 @@
 //! Utils for working with objects
 
-use crate::jsutils::JsError;
+use crate::jsutils::{JsError, JsValueType};
 use crate::quickjs_utils::properties::JSPropertyEnumRef;
-use crate::quickjs_utils::{atoms, functions, get_constructor, get_global};
+use crate::quickjs_utils::{
+    arrays, atoms, bigints, dates, errors, functions, get_constructor, get_global, maps,
+    primitives, sets, typedarrays,
+};
 use crate::quickjsrealmadapter::QuickJsRealmAdapter;
 use crate::quickjsruntimeadapter::{make_cstring, QuickJsRuntimeAdapter};
-use crate::quickjsvalueadapter::QuickJsValueAdapter;
+use crate::quickjsvalueadapter::{
+    QuickJsValueAdapter, TAG_BIG_INT, TAG_BOOL, TAG_FLOAT64, TAG_INT, TAG_NULL, TAG_OBJECT,
+    TAG_STRING, TAG_SYMBOL, TAG_UNDEFINED,
+};
 use libquickjs_sys as q;
+use serde::{Deserialize, Serialize};
 
 /// get a namespace object
 /// this is used to get nested object properties which are used as namespaces
@@ -111,7 +118,7 @@ pub unsafe fn create_object(context: *mut q::JSContext) -> Result<QuickJsValueAd
     let obj = q::JS_NewObject(context);
     let obj_ref = QuickJsValueAdapter::new(context, obj, false, true, "objects::create_object");
     if obj_ref.is_exception() {
-        return Err(JsError::new_str("Could not create object"));
+        return Err(errors::get_exception_or(context, "Could not create object"));
     }
     Ok(obj_ref)
 }
@@ -194,6 +201,8 @@ pub unsafe fn set_property2(
 ) -> Result<(), JsError> {
     log::trace!("set_property2: {}", prop_name);
 
+    obj_ref.check_context_alive()?;
+
     let ckey = make_cstring(prop_name)?;
 
     /*
@@ -220,7 +229,10 @@ pub unsafe fn set_property2(
     );
     log::trace!("set_property2 / 3");
     if ret < 0 {
-        return Err(JsError::new_str("Could not add property to object"));
+        return Err(errors::get_exception_or(
+            context,
+            "Could not add property to object",
+        ));
     }
     log::trace!("set_property2 / 4");
     Ok(())
@@ -316,7 +328,8 @@ pub unsafe fn define_getter_setter(
 
     log::trace!("objects::define_getter_setter 5 {}", res);
 
-    if res != 0 {
+    // -1 on an exception, TRUE when the property was defined
+    if res < 0 {
         if let Some(err) = QuickJsRealmAdapter::get_exception(context) {
             Err(err)
         } else {
@@ -351,6 +364,7 @@ pub unsafe fn get_property(
             "could not get prop from null or undefined",
         ));
     }
+    obj_ref.check_context_alive()?;
 
     let c_prop_name = make_cstring(prop_name)?;
 
@@ -365,6 +379,14 @@ pub unsafe fn get_property(
         format!("object::get_property result: {prop_name}").as_str(),
     );
 
+    if prop_ref.is_exception() {
+        // e.g. a getter which throws
+        return Err(errors::get_exception_or(
+            context,
+            format!("Could not get property {prop_name}").as_str(),
+        ));
+    }
+
     Ok(prop_ref)
 }
 
@@ -484,7 +506,10 @@ where
             "objects::traverse_properties raw_value",
         );
         if prop_val_ref.is_exception() {
-            return Err(JsError::new_str("Could not get object property"));
+            return Err(errors::get_exception_or(
+                context,
+                "Could not get object property",
+            ));
         }
 
         let r = visitor(prop_name, &prop_val_ref)?;
@@ -526,7 +551,10 @@ where
             "objects::traverse_properties raw_value",
         );
         if prop_val_ref.is_exception() {
-            return Err(JsError::new_str("Could not get object property"));
+            return Err(errors::get_exception_or(
+                context,
+                "Could not get object property",
+            ));
         }
 
         visitor(prop_name, &prop_val_ref)?;
@@ -631,15 +659,1092 @@ pub unsafe fn is_instance_of_by_name(
     }
 }
 
+/// delete a property from an object, like `delete obj[propName];`
+pub fn delete_property_q(
+    q_ctx: &QuickJsRealmAdapter,
+    obj_ref: &QuickJsValueAdapter,
+    prop_name: &str,
+) -> Result<(), JsError> {
+    unsafe { delete_property(q_ctx.context, obj_ref, prop_name) }
+}
+
+/// delete a property from an object, like `delete obj[propName];`
+/// # Safety
+/// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
+pub unsafe fn delete_property(
+    context: *mut q::JSContext,
+    obj_ref: &QuickJsValueAdapter,
+    prop_name: &str,
+) -> Result<(), JsError> {
+    let atom_ref = atoms::from_string(context, prop_name)?;
+    let ret = q::JS_DeleteProperty(context, *obj_ref.borrow_value(), atom_ref.get_atom(), 0);
+    if ret < 0 {
+        if let Some(ex) = QuickJsRealmAdapter::get_exception(context) {
+            Err(ex)
+        } else {
+            Err(JsError::new_str(
+                "delete_property failed but could not get ex",
+            ))
+        }
+    } else {
+        Ok(())
+    }
+}
+
+/// a single operation of a JSON-Patch (RFC 6902) style op list as produced by [diff_q]
+/// the path is represented as a list of property names, array elements are addressed by their index
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOp {
+    Add {
+        path: Vec<String>,
+        value: serde_json::Value,
+    },
+    Remove {
+        path: Vec<String>,
+    },
+    Replace {
+        path: Vec<String>,
+        value: serde_json::Value,
+    },
+}
+
+impl PatchOp {
+    pub fn get_path(&self) -> &[String] {
+        match self {
+            PatchOp::Add { path, .. } => path,
+            PatchOp::Remove { path } => path,
+            PatchOp::Replace { path, .. } => path,
+        }
+    }
+}
+
+/// calculate the ops needed to turn old_ref into new_ref
+///
+/// objects are compared per property, arrays are compared by index: elements which exist in both
+/// arrays are diffed, surplus elements of new_ref are added at the end and surplus elements of old_ref
+/// are removed from the end (last index first). Moved or inserted elements thus result in a series of
+/// replace ops instead of a single add.
+///
+/// once max_depth is reached objects and arrays are no longer descended into but replaced as a whole when they differ
+///
+/// # Example
+/// ```rust
+/// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+/// use quickjs_runtime::jsutils::Script;
+/// use quickjs_runtime::quickjs_utils::objects::{apply_patch_q, diff_q};
+/// let rt = QuickJsRuntimeBuilder::new().build();
+/// rt.exe_rt_task_in_event_loop(|q_js_rt| {
+///     let q_ctx = q_js_rt.get_main_realm();
+///     let old = q_ctx.eval(Script::new("diff.js", "({a: 1, b: [1, 2]});")).ok().unwrap();
+///     let new = q_ctx.eval(Script::new("diff.js", "({a: 2, b: [1], c: true});")).ok().unwrap();
+///     let ops = diff_q(q_ctx, &old, &new, 16).ok().unwrap();
+///     assert_eq!(ops.len(), 3);
+///     let patched = apply_patch_q(q_ctx, &old, &ops).ok().unwrap();
+///     assert_eq!(q_ctx.json_stringify(&patched, None).ok().unwrap(), r#"{"a":2,"b":[1],"c":true}"#);
+/// })
+/// ```
+pub fn diff_q(
+    q_ctx: &QuickJsRealmAdapter,
+    old_ref: &QuickJsValueAdapter,
+    new_ref: &QuickJsValueAdapter,
+    max_depth: usize,
+) -> Result<Vec<PatchOp>, JsError> {
+    let mut ops = vec![];
+    let mut path = vec![];
+    diff_values(q_ctx, old_ref, new_ref, &mut path, max_depth, &mut ops)?;
+    Ok(ops)
+}
+
+fn diff_values(
+    q_ctx: &QuickJsRealmAdapter,
+    old_ref: &QuickJsValueAdapter,
+    new_ref: &QuickJsValueAdapter,
+    path: &mut Vec<String>,
+    depth_left: usize,
+    ops: &mut Vec<PatchOp>,
+) -> Result<(), JsError> {
+    let old_type = old_ref.get_js_type();
+    let new_type = new_ref.get_js_type();
+
+    if depth_left > 0 && old_type == new_type {
+        match old_type {
+            JsValueType::Object => {
+                let old_names = get_property_names_q(q_ctx, old_ref)?;
+                let new_names = get_property_names_q(q_ctx, new_ref)?;
+                for name in &old_names {
+                    path.push(name.clone());
+                    if new_names.contains(name) {
+                        let old_val = get_property_q(q_ctx, old_ref, name)?;
+                        let new_val = get_property_q(q_ctx, new_ref, name)?;
+                        diff_values(q_ctx, &old_val, &new_val, path, depth_left - 1, ops)?;
+                    } else {
+                        ops.push(PatchOp::Remove { path: path.clone() });
+                    }
+                    path.pop();
+                }
+                for name in &new_names {
+                    if !old_names.contains(name) {
+                        let new_val = get_property_q(q_ctx, new_ref, name)?;
+                        path.push(name.clone());
+                        ops.push(PatchOp::Add {
+                            path: path.clone(),
+                            value: q_ctx.value_adapter_to_serde_value(&new_val)?,
+                        });
+                        path.pop();
+                    }
+                }
+                return Ok(());
+            }
+            JsValueType::Array => {
+                let old_len = arrays::get_length_q(q_ctx, old_ref)?;
+                let new_len = arrays::get_length_q(q_ctx, new_ref)?;
+                for index in 0..old_len.min(new_len) {
+                    let old_val = arrays::get_element_q(q_ctx, old_ref, index)?;
+                    let new_val = arrays::get_element_q(q_ctx, new_ref, index)?;
+                    path.push(index.to_string());
+                    diff_values(q_ctx, &old_val, &new_val, path, depth_left - 1, ops)?;
+                    path.pop();
+                }
+                for index in old_len..new_len {
+                    let new_val = arrays::get_element_q(q_ctx, new_ref, index)?;
+                    path.push(index.to_string());
+                    ops.push(PatchOp::Add {
+                        path: path.clone(),
+                        value: q_ctx.value_adapter_to_serde_value(&new_val)?,
+                    });
+                    path.pop();
+                }
+                // remove from the end so the indexes of the remaining removals stay valid
+                for index in (new_len..old_len).rev() {
+                    path.push(index.to_string());
+                    ops.push(PatchOp::Remove { path: path.clone() });
+                    path.pop();
+                }
+                return Ok(());
+            }
+            _ => {}
+        }
+    }
+
+    let old_val = q_ctx.value_adapter_to_serde_value(old_ref)?;
+    let new_val = q_ctx.value_adapter_to_serde_value(new_ref)?;
+    if old_type != new_type || old_val != new_val {
+        ops.push(PatchOp::Replace {
+            path: path.clone(),
+            value: new_val,
+        });
+    }
+    Ok(())
+}
+
+/// apply a list of ops (as produced by [diff_q]) to an object
+///
+/// the target is altered in place, the result is the patched target or the new value if the ops replaced the root
+pub fn apply_patch_q(
+    q_ctx: &QuickJsRealmAdapter,
+    target_ref: &QuickJsValueAdapter,
+    ops: &[PatchOp],
+) -> Result<QuickJsValueAdapter, JsError> {
+    let mut root = target_ref.dup();
+    for op in ops {
+        let path = op.get_path();
+        if path.is_empty() {
+            root = match op {
+                PatchOp::Add { value, .. } | PatchOp::Replace { value, .. } => {
+                    q_ctx.serde_value_to_value_adapter(value.clone())?
+                }
+                PatchOp::Remove { .. } => {
+                    return Err(JsError::new_str(
+                        "can not remove the root of a patch target",
+                    ));
+                }
+            };
+            continue;
+        }
+
+        let mut parent = root.dup();
+        for segment in &path[..path.len() - 1] {
+            parent = get_property_q(q_ctx, &parent, segment)?;
+            if !parent.is_object() {
+                return Err(JsError::new_string(format!(
+                    "patch path not found: /{}",
+                    path.join("/")
+                )));
+            }
+        }
+        let key = path.last().unwrap();
+
+        if arrays::is_array_q(q_ctx, &parent) {
+            let index = if key == "-" {
+                arrays::get_length_q(q_ctx, &parent)?
+            } else {
+                key.parse::<u32>().map_err(|_| {
+                    JsError::new_string(format!("invalid array index in patch path: {key}"))
+                })?
+            };
+            let index_ref = primitives::from_i32(index as i32);
+            match op {
+                PatchOp::Add { value, .. } => {
+                    let val = q_ctx.serde_value_to_value_adapter(value.clone())?;
+                    functions::invoke_member_function_q(
+                        q_ctx,
+                        &parent,
+                        "splice",
+                        &[index_ref, primitives::from_i32(0), val],
+                    )?;
+                }
+                PatchOp::Replace { value, .. } => {
+                    let val = q_ctx.serde_value_to_value_adapter(value.clone())?;
+                    arrays::set_element_q(q_ctx, &parent, index, &val)?;
+                }
+                PatchOp::Remove { .. } => {
+                    functions::invoke_member_function_q(
+                        q_ctx,
+                        &parent,
+                        "splice",
+                        &[index_ref, primitives::from_i32(1)],
+                    )?;
+                }
+            }
+        } else {
+            match op {
+                PatchOp::Add { value, .. } | PatchOp::Replace { value, .. } => {
+                    let val = q_ctx.serde_value_to_value_adapter(value.clone())?;
+                    set_property_q(q_ctx, &parent, key, &val)?;
+                }
+                PatchOp::Remove { .. } => {
+                    delete_property_q(q_ctx, &parent, key)?;
+                }
+            }
+        }
+    }
+    Ok(root)
+}
+
+/// a segment of a [JsPath]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathSegment {
+    /// a property name, e.g. `data` or `["content-type"]`
+    Key(String),
+    /// an array index, e.g. `[0]`
+    Index(u32),
+}
+
+/// a parsed property path like `data.items[0].id` or `headers["content-type"]`, used by [get_path_q] and [set_path_q]
+///
+/// parse a path once and reuse it when it is used often
+/// # Example
+/// ```rust
+/// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+/// use quickjs_runtime::jsutils::Script;
+/// use quickjs_runtime::quickjs_utils::objects::JsPath;
+/// let rt = QuickJsRuntimeBuilder::new().build();
+/// rt.exe_rt_task_in_event_loop(|q_js_rt| {
+///     let q_ctx = q_js_rt.get_main_realm();
+///     let path = JsPath::parse("data.items[0].id").ok().unwrap();
+///     let obj = q_ctx.eval(Script::new("path.js", "({data: {items: [{id: 12}]}});")).ok().unwrap();
+///     let id = path.get_q(q_ctx, &obj).ok().unwrap().unwrap();
+///     assert_eq!(id.to_i32(), 12);
+/// })
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsPath {
+    segments: Vec<PathSegment>,
+}
+
+impl JsPath {
+    /// parse a path, keys are separated by dots, indexes and quoted keys are put between brackets
+    pub fn parse(path: &str) -> Result<Self, JsError> {
+        let chars: Vec<char> = path.chars().collect();
+        let syntax_error = |pos: usize, msg: &str| {
+            JsError::new(
+                "SyntaxError".to_string(),
+                format!("invalid path {path:?} at {pos}: {msg}"),
+                "".to_string(),
+            )
+        };
+        let mut segments = vec![];
+        let mut pos = 0;
+        while pos < chars.len() {
+            let key_start = match chars[pos] {
+                '[' => None,
+                '.' if !segments.is_empty() => Some(pos + 1),
+                _ if pos == 0 => Some(0),
+                _ => return Err(syntax_error(pos, "expected . or [")),
+            };
+            if let Some(start) = key_start {
+                pos = start;
+                while pos < chars.len() && chars[pos] != '.' && chars[pos] != '[' {
+                    pos += 1;
+                }
+                if pos == start {
+                    return Err(syntax_error(start, "empty key"));
+                }
+                segments.push(PathSegment::Key(chars[start..pos].iter().collect()));
+                continue;
+            }
+
+            pos += 1;
+            match chars.get(pos) {
+                Some(&quote) if quote == '"' || quote == '\'' => {
+                    pos += 1;
+                    let mut key = String::new();
+                    loop {
+                        match chars.get(pos) {
+                            Some('\\') if pos + 1 < chars.len() => {
+                                key.push(chars[pos + 1]);
+                                pos += 2;
+                            }
+                            Some(c) if *c == quote => {
+                                pos += 1;
+                                break;
+                            }
+                            Some(c) => {
+                                key.push(*c);
+                                pos += 1;
+                            }
+                            None => return Err(syntax_error(pos, "unterminated key")),
+                        }
+                    }
+                    segments.push(PathSegment::Key(key));
+                }
+                _ => {
+                    let start = pos;
+                    while pos < chars.len() && chars[pos].is_ascii_digit() {
+                        pos += 1;
+                    }
+                    let digits: String = chars[start..pos].iter().collect();
+                    let index = digits
+                        .parse::<u32>()
+                        .map_err(|_| syntax_error(start, "expected an index or a quoted key"))?;
+                    segments.push(PathSegment::Index(index));
+                }
+            }
+            if chars.get(pos) != Some(&']') {
+                return Err(syntax_error(pos, "expected ]"));
+            }
+            pos += 1;
+        }
+        Ok(Self { segments })
+    }
+
+    pub fn get_segments(&self) -> &[PathSegment] {
+        self.segments.as_slice()
+    }
+
+    /// get the value at this path, like `obj?.data?.items?.[0]?.id`
+    ///
+    /// a null or undefined value along the way or at the end results in None, indexing a value which
+    /// is not an array or reading a property of a primitive is an error
+    pub fn get_q(
+        &self,
+        q_ctx: &QuickJsRealmAdapter,
+        obj_ref: &QuickJsValueAdapter,
+    ) -> Result<Option<QuickJsValueAdapter>, JsError> {
+        let mut current = obj_ref.dup();
+        for index in 0..self.segments.len() {
+            if current.is_null_or_undefined() {
+                return Ok(None);
+            }
+            current = self.get_segment_q(q_ctx, &current, index)?;
+        }
+        if current.is_undefined() {
+            Ok(None)
+        } else {
+            Ok(Some(current))
+        }
+    }
+
+    /// set the value at this path, like `obj.data.items[0].id = value;`
+    ///
+    /// missing (null or undefined) intermediate values are created, as an array when the next segment is an index
+    /// and as an object otherwise, setting through a primitive or a frozen object is an error
+    pub fn set_q(
+        &self,
+        q_ctx: &QuickJsRealmAdapter,
+        obj_ref: &QuickJsValueAdapter,
+        value_ref: &QuickJsValueAdapter,
+    ) -> Result<(), JsError> {
+        if self.segments.is_empty() {
+            return Err(JsError::new_str("can not set the root of a path"));
+        }
+        let last = self.segments.len() - 1;
+        let mut current = obj_ref.dup();
+        for index in 0..last {
+            let child = self.get_segment_q(q_ctx, &current, index)?;
+            current = if child.is_null_or_undefined() {
+                let created = match self.segments[index + 1] {
+                    PathSegment::Index(_) => arrays::create_array_q(q_ctx)?,
+                    PathSegment::Key(_) => create_object_q(q_ctx)?,
+                };
+                self.set_segment_q(q_ctx, &current, index, &created)?;
+                created
+            } else if child.is_object() {
+                child
+            } else {
+                return Err(self.segment_error(
+                    index + 1,
+                    format!(
+                        "can not set a property of a value of type {}",
+                        child.get_js_type()
+                    )
+                    .as_str(),
+                ));
+            };
+        }
+        self.set_segment_q(q_ctx, &current, last, value_ref)
+    }
+
+    fn get_segment_q(
+        &self,
+        q_ctx: &QuickJsRealmAdapter,
+        container: &QuickJsValueAdapter,
+        index: usize,
+    ) -> Result<QuickJsValueAdapter, JsError> {
+        self.check_container(q_ctx, container, index, "read")?;
+        match &self.segments[index] {
+            PathSegment::Key(key) => get_property_q(q_ctx, container, key),
+            PathSegment::Index(element) => arrays::get_element_q(q_ctx, container, *element),
+        }
+    }
+
+    fn set_segment_q(
+        &self,
+        q_ctx: &QuickJsRealmAdapter,
+        container: &QuickJsValueAdapter,
+        index: usize,
+        value_ref: &QuickJsValueAdapter,
+    ) -> Result<(), JsError> {
+        self.check_container(q_ctx, container, index, "set")?;
+        // unlike set_property this is a real assignment, so setters are called and frozen objects throw
+        let ret = match &self.segments[index] {
+            PathSegment::Key(key) => {
+                let ckey = make_cstring(key.as_str())?;
+                unsafe {
+                    q::JS_SetPropertyStr(
+                        q_ctx.context,
+                        *container.borrow_value(),
+                        ckey.as_ptr(),
+                        value_ref.clone_value_incr_rc(),
+                    )
+                }
+            }
+            PathSegment::Index(element) => unsafe {
+                q::JS_SetPropertyUint32(
+                    q_ctx.context,
+                    *container.borrow_value(),
+                    *element,
+                    value_ref.clone_value_incr_rc(),
+                )
+            },
+        };
+        if ret < 0 {
+            let cause =
+                unsafe { errors::get_exception_or(q_ctx.context, "could not set property") };
+            return Err(self.segment_error(index, cause.get_message()));
+        }
+        Ok(())
+    }
+
+    fn check_container(
+        &self,
+        q_ctx: &QuickJsRealmAdapter,
+        container: &QuickJsValueAdapter,
+        index: usize,
+        action: &str,
+    ) -> Result<(), JsError> {
+        let problem = match &self.segments[index] {
+            PathSegment::Index(_) if !arrays::is_array_q(q_ctx, container) => {
+                format!(
+                    "can not index a value of type {}, it is not an array",
+                    container.get_js_type()
+                )
+            }
+            PathSegment::Key(_) if !container.is_object() => {
+                format!(
+                    "can not {action} a property of a value of type {}",
+                    container.get_js_type()
+                )
+            }
+            _ => return Ok(()),
+        };
+        Err(self.segment_error(index, problem.as_str()))
+    }
+
+    fn segment_error(&self, index: usize, msg: &str) -> JsError {
+        let segment = JsPath {
+            segments: vec![self.segments[index].clone()],
+        };
+        JsError::new(
+            "TypeError".to_string(),
+            format!("{msg} at segment {segment} of path {self}"),
+            "".to_string(),
+        )
+    }
+}
+
+impl std::fmt::Display for JsPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (index, segment) in self.segments.iter().enumerate() {
+            match segment {
+                PathSegment::Index(element) => write!(f, "[{element}]")?,
+                PathSegment::Key(key)
+                    if !key.is_empty() && !key.contains(['.', '[', ']', '"', '\'', '\\']) =>
+                {
+                    if index > 0 {
+                        f.write_str(".")?;
+                    }
+                    f.write_str(key)?;
+                }
+                PathSegment::Key(key) => write!(
+                    f,
+                    "[\"{}\"]",
+                    key.replace('\\', "\\\\").replace('"', "\\\"")
+                )?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// get the value at a path like `data.items[0].id`, see [JsPath::get_q]
+pub fn get_path_q(
+    q_ctx: &QuickJsRealmAdapter,
+    obj_ref: &QuickJsValueAdapter,
+    path: &str,
+) -> Result<Option<QuickJsValueAdapter>, JsError> {
+    JsPath::parse(path)?.get_q(q_ctx, obj_ref)
+}
+
+/// set the value at a path like `data.items[0].id`, creating missing objects and arrays along the way, see [JsPath::set_q]
+pub fn set_path_q(
+    q_ctx: &QuickJsRealmAdapter,
+    obj_ref: &QuickJsValueAdapter,
+    path: &str,
+    value_ref: &QuickJsValueAdapter,
+) -> Result<(), JsError> {
+    JsPath::parse(path)?.set_q(q_ctx, obj_ref, value_ref)
+}
+
+/// the kinds of values deep_equals_q and stable_hash_q know how to compare
+enum DeepValue {
+    Undefined,
+    Null,
+    Boolean(bool),
+    Number(f64),
+    String(String),
+    BigInt(String),
+    Date(f64),
+    // the bytes of an ArrayBuffer
+    ArrayBuffer(Vec<u8>),
+    // the constructor name of a TypedArray and the bytes in its view
+    TypedArray(String, Vec<u8>),
+    // the source and the flags of a RegExp
+    RegExp(String, String),
+    BoxedBoolean(bool),
+    BoxedNumber(f64),
+    BoxedString(String),
+    BoxedBigInt(String),
+    Array,
+    Map,
+    Set,
+    Object,
+}
+
+fn deep_type_error(msg: &str) -> JsError {
+    JsError::new("TypeError".to_string(), msg.to_string(), "".to_string())
+}
+
+fn to_deep_value(
+    q_ctx: &QuickJsRealmAdapter,
+    value: &QuickJsValueAdapter,
+) -> Result<DeepValue, JsError> {
+    match value.get_tag() {
+        TAG_UNDEFINED => Ok(DeepValue::Undefined),
+        TAG_NULL => Ok(DeepValue::Null),
+        TAG_BOOL => Ok(DeepValue::Boolean(value.to_bool())),
+        TAG_INT => Ok(DeepValue::Number(value.to_i32() as f64)),
+        TAG_FLOAT64 => Ok(DeepValue::Number(value.to_f64())),
+        TAG_STRING => Ok(DeepValue::String(value.to_string()?)),
+        TAG_BIG_INT => Ok(DeepValue::BigInt(bigints::to_string_q(q_ctx, value)?)),
+        TAG_SYMBOL => Err(deep_type_error("symbols can not be compared structurally")),
+        TAG_OBJECT => {
+            if functions::is_function_q(q_ctx, value) {
+                Err(deep_type_error(
+                    "functions can not be compared structurally",
+                ))
+            } else if arrays::is_array_q(q_ctx, value) {
+                Ok(DeepValue::Array)
+            } else if dates::is_date_q(q_ctx, value) {
+                Ok(DeepValue::Date(dates::get_time_q(q_ctx, value)?))
+            } else if maps::is_map_q(q_ctx, value)? {
+                Ok(DeepValue::Map)
+            } else if sets::is_set_q(q_ctx, value)? {
+                Ok(DeepValue::Set)
+            } else if typedarrays::is_typed_array_q(q_ctx, value) {
+                let constructor = get_property_q(q_ctx, value, "constructor")?;
+                let constructor_name = get_property_q(q_ctx, &constructor, "name")?;
+                Ok(DeepValue::TypedArray(
+                    constructor_name.to_string()?,
+                    typedarrays::get_bytes_q(q_ctx, value)?,
+                ))
+            } else if typedarrays::is_array_buffer_q(q_ctx, value) {
+                Ok(DeepValue::ArrayBuffer(typedarrays::get_bytes_q(
+                    q_ctx, value,
+                )?))
+            } else if is_instance_of_by_name_q(q_ctx, value, "RegExp")? {
+                Ok(DeepValue::RegExp(
+                    get_property_q(q_ctx, value, "source")?.to_string()?,
+                    get_property_q(q_ctx, value, "flags")?.to_string()?,
+                ))
+            } else if is_instance_of_by_name_q(q_ctx, value, "DataView")? {
+                Err(deep_type_error(
+                    "DataViews can not be compared structurally",
+                ))
+            } else if let Some(boxed) = to_boxed_deep_value(q_ctx, value)? {
+                Ok(boxed)
+            } else {
+                Ok(DeepValue::Object)
+            }
+        }
+        tag => Err(deep_type_error(
+            format!("values with tag {tag} can not be compared structurally").as_str(),
+        )),
+    }
+}
+
+/// the value of a Boolean, Number, String or BigInt object, None for other objects
+fn to_boxed_deep_value(
+    q_ctx: &QuickJsRealmAdapter,
+    value: &QuickJsValueAdapter,
+) -> Result<Option<DeepValue>, JsError> {
+    if is_instance_of_by_name_q(q_ctx, value, "Symbol")? {
+        return Err(deep_type_error("symbols can not be compared structurally"));
+    }
+    for name in ["Boolean", "Number", "String", "BigInt"] {
+        if !is_instance_of_by_name_q(q_ctx, value, name)? {
+            continue;
+        }
+        let primitive = functions::invoke_member_function_q(q_ctx, value, "valueOf", &[])?;
+        return Ok(Some(match to_deep_value(q_ctx, &primitive)? {
+            DeepValue::Boolean(b) => DeepValue::BoxedBoolean(b),
+            DeepValue::Number(n) => DeepValue::BoxedNumber(n),
+            DeepValue::String(s) => DeepValue::BoxedString(s),
+            DeepValue::BigInt(s) => DeepValue::BoxedBigInt(s),
+            _ => return Err(deep_type_error("valueOf did not return a primitive")),
+        }));
+    }
+    Ok(None)
+}
+
+/// SameValueZero, NaN equals NaN and 0 equals -0
+fn same_value_zero(a: f64, b: f64) -> bool {
+    a == b || (a.is_nan() && b.is_nan())
+}
+
+/// compare two values structurally
+///
+/// * numbers are compared with SameValueZero semantics (NaN equals NaN, 0 equals -0)
+/// * objects are equal when they have the same set of own enumerable properties with equal values, property order is ignored
+/// * arrays are equal when they have equal elements in the same order
+/// * Maps and Sets are equal when they contain the same entries in any order
+/// * Dates are equal when they represent the same time
+/// * ArrayBuffers are equal when they contain the same bytes, TypedArrays when they are of the same kind and contain the same bytes
+/// * RegExps are equal when they have the same source and flags
+/// * boxed primitives (e.g. new Number(1)) are equal when their primitive values are equal, they never equal a primitive
+/// * cyclic graphs are equal when they have the same shape, e.g. two objects which reference themselves are equal
+///
+/// when a function, symbol or DataView is encountered a TypeError is returned
+/// # Example
+/// ```rust
+/// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+/// use quickjs_runtime::jsutils::Script;
+/// use quickjs_runtime::quickjs_utils::objects::deep_equals_q;
+/// let rt = QuickJsRuntimeBuilder::new().build();
+/// rt.exe_rt_task_in_event_loop(|q_js_rt| {
+///     let q_ctx = q_js_rt.get_main_realm();
+///     let a = q_ctx.eval(Script::new("eq.js", "({a: NaN, b: [1, 2]});")).ok().unwrap();
+///     let b = q_ctx.eval(Script::new("eq.js", "({b: [1, 2], a: NaN});")).ok().unwrap();
+///     assert!(deep_equals_q(q_ctx, &a, &b).ok().unwrap());
+/// })
+/// ```
+pub fn deep_equals_q(
+    q_ctx: &QuickJsRealmAdapter,
+    a: &QuickJsValueAdapter,
+    b: &QuickJsValueAdapter,
+) -> Result<bool, JsError> {
+    let mut stack_a = vec![];
+    let mut stack_b = vec![];
+    deep_equals_inner(q_ctx, a, b, &mut stack_a, &mut stack_b)
+}
+
+fn deep_equals_inner(
+    q_ctx: &QuickJsRealmAdapter,
+    a: &QuickJsValueAdapter,
+    b: &QuickJsValueAdapter,
+    stack_a: &mut Vec<QuickJsValueAdapter>,
+    stack_b: &mut Vec<QuickJsValueAdapter>,
+) -> Result<bool, JsError> {
+    let deep_a = to_deep_value(q_ctx, a)?;
+    let deep_b = to_deep_value(q_ctx, b)?;
+    match (deep_a, deep_b) {
+        (DeepValue::Undefined, DeepValue::Undefined) => Ok(true),
+        (DeepValue::Null, DeepValue::Null) => Ok(true),
+        (DeepValue::Boolean(x), DeepValue::Boolean(y)) => Ok(x == y),
+        (DeepValue::Number(x), DeepValue::Number(y)) => Ok(same_value_zero(x, y)),
+        (DeepValue::String(x), DeepValue::String(y)) => Ok(x == y),
+        (DeepValue::BigInt(x), DeepValue::BigInt(y)) => Ok(x == y),
+        (DeepValue::Date(x), DeepValue::Date(y)) => Ok(same_value_zero(x, y)),
+        (DeepValue::ArrayBuffer(x), DeepValue::ArrayBuffer(y)) => Ok(x == y),
+        (DeepValue::TypedArray(kind_x, x), DeepValue::TypedArray(kind_y, y)) => {
+            Ok(kind_x == kind_y && x == y)
+        }
+        (DeepValue::RegExp(source_x, flags_x), DeepValue::RegExp(source_y, flags_y)) => {
+            Ok(source_x == source_y && flags_x == flags_y)
+        }
+        (DeepValue::BoxedBoolean(x), DeepValue::BoxedBoolean(y)) => Ok(x == y),
+        (DeepValue::BoxedNumber(x), DeepValue::BoxedNumber(y)) => Ok(same_value_zero(x, y)),
+        (DeepValue::BoxedString(x), DeepValue::BoxedString(y)) => Ok(x == y),
+        (DeepValue::BoxedBigInt(x), DeepValue::BoxedBigInt(y)) => Ok(x == y),
+        (DeepValue::Array, DeepValue::Array)
+        | (DeepValue::Map, DeepValue::Map)
+        | (DeepValue::Set, DeepValue::Set)
+        | (DeepValue::Object, DeepValue::Object) => {
+            // cycles are equal if they point back to the same depth in both graphs
+            let pos_a = stack_a.iter().position(|v| v == a);
+            let pos_b = stack_b.iter().position(|v| v == b);
+            if pos_a.is_some() || pos_b.is_some() {
+                return Ok(pos_a == pos_b);
+            }
+
+            stack_a.push(a.dup());
+            stack_b.push(b.dup());
+            let res = deep_equals_containers(q_ctx, a, b, stack_a, stack_b);
+            stack_a.pop();
+            stack_b.pop();
+            res
+        }
+        _ => Ok(false),
+    }
+}
+
+fn deep_equals_containers(
+    q_ctx: &QuickJsRealmAdapter,
+    a: &QuickJsValueAdapter,
+    b: &QuickJsValueAdapter,
+    stack_a: &mut Vec<QuickJsValueAdapter>,
+    stack_b: &mut Vec<QuickJsValueAdapter>,
+) -> Result<bool, JsError> {
+    if arrays::is_array_q(q_ctx, a) {
+        let len = arrays::get_length_q(q_ctx, a)?;
+        if len != arrays::get_length_q(q_ctx, b)? {
+            return Ok(false);
+        }
+        for index in 0..len {
+            let elem_a = arrays::get_element_q(q_ctx, a, index)?;
+            let elem_b = arrays::get_element_q(q_ctx, b, index)?;
+            if !deep_equals_inner(q_ctx, &elem_a, &elem_b, stack_a, stack_b)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    } else if maps::is_map_q(q_ctx, a)? {
+        let entries_a = maps::entries_q(q_ctx, a, |k, v| Ok((k, v)))?;
+        let mut entries_b = maps::entries_q(q_ctx, b, |k, v| Ok((k, v)))?;
+        if entries_a.len() != entries_b.len() {
+            return Ok(false);
+        }
+        for (key_a, val_a) in &entries_a {
+            let mut found = None;
+            for (index, (key_b, val_b)) in entries_b.iter().enumerate() {
+                if deep_equals_inner(q_ctx, key_a, key_b, stack_a, stack_b)?
+                    && deep_equals_inner(q_ctx, val_a, val_b, stack_a, stack_b)?
+                {
+                    found = Some(index);
+                    break;
+                }
+            }
+            match found {
+                Some(index) => {
+                    entries_b.remove(index);
+                }
+                None => return Ok(false),
+            }
+        }
+        Ok(true)
+    } else if sets::is_set_q(q_ctx, a)? {
+        let values_a = sets::values_q(q_ctx, a, Ok)?;
+        let mut values_b = sets::values_q(q_ctx, b, Ok)?;
+        if values_a.len() != values_b.len() {
+            return Ok(false);
+        }
+        for val_a in &values_a {
+            let mut found = None;
+            for (index, val_b) in values_b.iter().enumerate() {
+                if deep_equals_inner(q_ctx, val_a, val_b, stack_a, stack_b)? {
+                    found = Some(index);
+                    break;
+                }
+            }
+            match found {
+                Some(index) => {
+                    values_b.remove(index);
+                }
+                None => return Ok(false),
+            }
+        }
+        Ok(true)
+    } else {
+        let mut names_a = get_property_names_q(q_ctx, a)?;
+        let mut names_b = get_property_names_q(q_ctx, b)?;
+        names_a.sort();
+        names_b.sort();
+        if names_a != names_b {
+            return Ok(false);
+        }
+        for name in &names_a {
+            let prop_a = get_property_q(q_ctx, a, name)?;
+            let prop_b = get_property_q(q_ctx, b, name)?;
+            if !deep_equals_inner(q_ctx, &prop_a, &prop_b, stack_a, stack_b)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// FNV-1a, used because the output of std's DefaultHasher is not guaranteed to be stable
+pub(crate) struct StableHasher {
+    state: u64,
+}
+
+impl StableHasher {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: 0xcbf29ce484222325,
+        }
+    }
+    pub(crate) fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.state ^= *b as u64;
+            self.state = self.state.wrapping_mul(0x100000001b3);
+        }
+    }
+    fn write_u8(&mut self, v: u8) {
+        self.write(&[v]);
+    }
+    fn write_u64(&mut self, v: u64) {
+        self.write(&v.to_le_bytes());
+    }
+    fn write_str(&mut self, s: &str) {
+        self.write_u64(s.len() as u64);
+        self.write(s.as_bytes());
+    }
+    fn write_f64(&mut self, v: f64) {
+        // normalize so the hash is consistent with SameValueZero
+        let bits = if v.is_nan() {
+            f64::NAN.to_bits()
+        } else if v == 0.0 {
+            0.0_f64.to_bits()
+        } else {
+            v.to_bits()
+        };
+        self.write_u64(bits);
+    }
+    pub(crate) fn finish(&self) -> u64 {
+        self.state
+    }
+}
+
+/// calculate a deterministic hash for a value, values which are equal according to [deep_equals_q] produce the same hash
+///
+/// the hash is stable between runs and processes so it may be used as (part of) a cache key
+///
+/// when a function, symbol or DataView is encountered a TypeError is returned
+pub fn stable_hash_q(
+    q_ctx: &QuickJsRealmAdapter,
+    value: &QuickJsValueAdapter,
+) -> Result<u64, JsError> {
+    stable_hash_all_q(q_ctx, std::slice::from_ref(value), usize::MAX)
+}
+
+/// calculate a single stable hash for a list of values (e.g. the arguments of a function call)
+///
+/// objects nested deeper than max_depth do not contribute to the hash, use usize::MAX to hash the complete values
+pub fn stable_hash_all_q(
+    q_ctx: &QuickJsRealmAdapter,
+    values: &[QuickJsValueAdapter],
+    max_depth: usize,
+) -> Result<u64, JsError> {
+    let mut hasher = StableHasher::new();
+    let mut stack = vec![];
+    hasher.write_u64(values.len() as u64);
+    for value in values {
+        stable_hash_inner(q_ctx, value, &mut stack, max_depth, &mut hasher)?;
+    }
+    Ok(hasher.finish())
+}
+
+fn stable_hash_inner(
+    q_ctx: &QuickJsRealmAdapter,
+    value: &QuickJsValueAdapter,
+    stack: &mut Vec<QuickJsValueAdapter>,
+    max_depth: usize,
+    hasher: &mut StableHasher,
+) -> Result<(), JsError> {
+    let deep_value = to_deep_value(q_ctx, value)?;
+
+    if matches!(
+        deep_value,
+        DeepValue::Array | DeepValue::Map | DeepValue::Set | DeepValue::Object
+    ) {
+        if let Some(pos) = stack.iter().position(|v| v == value) {
+            hasher.write_u8(0xff);
+            hasher.write_u64(pos as u64);
+            return Ok(());
+        }
+        if stack.len() >= max_depth {
+            hasher.write_u8(0xfe);
+            return Ok(());
+        }
+        stack.push(value.dup());
+        let res = stable_hash_container(q_ctx, value, &deep_value, stack, max_depth, hasher);
+        stack.pop();
+        return res;
+    }
+
+    match deep_value {
+        DeepValue::Undefined => hasher.write_u8(0),
+        DeepValue::Null => hasher.write_u8(1),
+        DeepValue::Boolean(b) => {
+            hasher.write_u8(2);
+            hasher.write_u8(b as u8);
+        }
+        DeepValue::Number(n) => {
+            hasher.write_u8(3);
+            hasher.write_f64(n);
+        }
+        DeepValue::String(s) => {
+            hasher.write_u8(4);
+            hasher.write_str(s.as_str());
+        }
+        DeepValue::BigInt(s) => {
+            hasher.write_u8(5);
+            hasher.write_str(s.as_str());
+        }
+        DeepValue::Date(t) => {
+            hasher.write_u8(6);
+            hasher.write_f64(t);
+        }
+        DeepValue::ArrayBuffer(bytes) => {
+            hasher.write_u8(11);
+            hasher.write_u64(bytes.len() as u64);
+            hasher.write(bytes.as_slice());
+        }
+        DeepValue::TypedArray(kind, bytes) => {
+            hasher.write_u8(12);
+            hasher.write_str(kind.as_str());
+            hasher.write_u64(bytes.len() as u64);
+            hasher.write(bytes.as_slice());
+        }
+        DeepValue::RegExp(source, flags) => {
+            hasher.write_u8(13);
+            hasher.write_str(source.as_str());
+            hasher.write_str(flags.as_str());
+        }
+        DeepValue::BoxedBoolean(b) => {
+            hasher.write_u8(14);
+            hasher.write_u8(b as u8);
+        }
+        DeepValue::BoxedNumber(n) => {
+            hasher.write_u8(15);
+            hasher.write_f64(n);
+        }
+        DeepValue::BoxedString(s) => {
+            hasher.write_u8(16);
+            hasher.write_str(s.as_str());
+        }
+        DeepValue::BoxedBigInt(s) => {
+            hasher.write_u8(17);
+            hasher.write_str(s.as_str());
+        }
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+fn stable_hash_container(
+    q_ctx: &QuickJsRealmAdapter,
+    value: &QuickJsValueAdapter,
+    deep_value: &DeepValue,
+    stack: &mut Vec<QuickJsValueAdapter>,
+    max_depth: usize,
+    hasher: &mut StableHasher,
+) -> Result<(), JsError> {
+    match deep_value {
+        DeepValue::Array => {
+            hasher.write_u8(7);
+            let len = arrays::get_length_q(q_ctx, value)?;
+            hasher.write_u64(len as u64);
+            for index in 0..len {
+                let elem = arrays::get_element_q(q_ctx, value, index)?;
+                stable_hash_inner(q_ctx, &elem, stack, max_depth, hasher)?;
+            }
+        }
+        DeepValue::Map | DeepValue::Set => {
+            // entries are hashed separately and sorted so insertion order does not matter
+            let mut entry_hashes = vec![];
+            if matches!(deep_value, DeepValue::Map) {
+                hasher.write_u8(8);
+                for (k, v) in maps::entries_q(q_ctx, value, |k, v| Ok((k, v)))? {
+                    let mut entry_hasher = StableHasher::new();
+                    stable_hash_inner(q_ctx, &k, stack, max_depth, &mut entry_hasher)?;
+                    stable_hash_inner(q_ctx, &v, stack, max_depth, &mut entry_hasher)?;
+                    entry_hashes.push(entry_hasher.finish());
+                }
+            } else {
+                hasher.write_u8(9);
+                for v in sets::values_q(q_ctx, value, Ok)? {
+                    let mut entry_hasher = StableHasher::new();
+                    stable_hash_inner(q_ctx, &v, stack, max_depth, &mut entry_hasher)?;
+                    entry_hashes.push(entry_hasher.finish());
+                }
+            }
+            entry_hashes.sort_unstable();
+            hasher.write_u64(entry_hashes.len() as u64);
+            for entry_hash in entry_hashes {
+                hasher.write_u64(entry_hash);
+            }
+        }
+        _ => {
+            hasher.write_u8(10);
+            let mut names = get_property_names_q(q_ctx, value)?;
+            names.sort();
+            hasher.write_u64(names.len() as u64);
+            for name in &names {
+                hasher.write_str(name.as_str());
+                let prop = get_property_q(q_ctx, value, name)?;
+                stable_hash_inner(q_ctx, &prop, stack, max_depth, hasher)?;
+            }
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 pub mod tests {
     use crate::facades::tests::init_test_rt;
     use crate::jsutils::Script;
     use crate::quickjs_utils::objects::{
-        create_object_q, get_property_names_q, get_property_q, set_property_q,
+        apply_patch_q, create_object_q, deep_equals_q, diff_q, get_path_q, get_property_names_q,
+        get_property_q, set_path_q, set_property_q, stable_hash_q, JsPath, PatchOp, PathSegment,
     };
     use crate::quickjs_utils::primitives::{from_i32, to_i32};
     use crate::quickjs_utils::{get_global_q, primitives};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use serde_json::Value;
 
     #[test]
     fn test_get_refs() {
@@ -783,4 +1888,312 @@ pub mod tests {
 
         log::info!("< test_set_prop");
     }
+
+    #[test]
+    fn test_diff_patch() {
+        let rt = init_test_rt();
+        rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_main_realm();
+
+            let old = q_ctx
+                .eval(Script::new(
+                    "test_diff_patch.es",
+                    "({a: 1, b: {c: 'x', d: [1, 2, 3]}, e: true});",
+                ))
+                .expect("script failed");
+            let new = q_ctx
+                .eval(Script::new(
+                    "test_diff_patch.es",
+                    "({a: 1, b: {c: 'y', d: [1, 4]}, f: null});",
+                ))
+                .expect("script failed");
+
+            let ops = diff_q(q_ctx, &old, &new, 16).expect("diff failed");
+            assert_eq!(
+                ops,
+                vec![
+                    PatchOp::Replace {
+                        path: vec!["b".to_string(), "c".to_string()],
+                        value: Value::from("y"),
+                    },
+                    PatchOp::Replace {
+                        path: vec!["b".to_string(), "d".to_string(), "1".to_string()],
+                        value: Value::from(4),
+                    },
+                    PatchOp::Remove {
+                        path: vec!["b".to_string(), "d".to_string(), "2".to_string()],
+                    },
+                    PatchOp::Remove {
+                        path: vec!["e".to_string()],
+                    },
+                    PatchOp::Add {
+                        path: vec!["f".to_string()],
+                        value: Value::Null,
+                    },
+                ]
+            );
+
+            // with max_depth 1 b is replaced as a whole
+            let shallow_ops = diff_q(q_ctx, &old, &new, 1).expect("diff failed");
+            assert_eq!(shallow_ops.len(), 3);
+
+            let patched = apply_patch_q(q_ctx, &old, &ops).expect("patch failed");
+            assert_eq!(
+                q_ctx.value_adapter_to_serde_value(&patched).unwrap(),
+                q_ctx.value_adapter_to_serde_value(&new).unwrap()
+            );
+            let props = get_property_names_q(q_ctx, &old).expect("could not get props");
+            assert!(!props.contains(&"e".to_string()));
+        });
+    }
+
+    fn random_value(rng: &mut StdRng, depth: usize) -> Value {
+        let kind = if depth == 0 {
+            rng.gen_range(0..4)
+        } else {
+            rng.gen_range(0..6)
+        };
+        match kind {
+            0 => Value::Null,
+            1 => Value::from(rng.gen_bool(0.5)),
+            2 => Value::from(rng.gen_range(-5..5)),
+            3 => Value::from(format!("s{}", rng.gen_range(0..5))),
+            4 => Value::Array(
+                (0..rng.gen_range(0..5))
+                    .map(|_| random_value(rng, depth - 1))
+                    .collect(),
+            ),
+            _ => Value::Object(
+                (0..rng.gen_range(0..5))
+                    .map(|_| {
+                        (
+                            format!("p{}", rng.gen_range(0..6)),
+                            random_value(rng, depth - 1),
+                        )
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    #[test]
+    fn test_diff_patch_round_trip() {
+        let rt = init_test_rt();
+        rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_main_realm();
+            let mut rng = StdRng::seed_from_u64(203);
+            for _ in 0..250 {
+                let old_val = random_value(&mut rng, 3);
+                let new_val = random_value(&mut rng, 3);
+                let max_depth = rng.gen_range(0..5);
+
+                let old = q_ctx
+                    .serde_value_to_value_adapter(old_val.clone())
+                    .expect("could not create old");
+                let new = q_ctx
+                    .serde_value_to_value_adapter(new_val.clone())
+                    .expect("could not create new");
+
+                let ops = diff_q(q_ctx, &old, &new, max_depth).expect("diff failed");
+                let patched = apply_patch_q(q_ctx, &old, &ops).expect("patch failed");
+
+                assert_eq!(
+                    q_ctx.value_adapter_to_serde_value(&patched).unwrap(),
+                    new_val,
+                    "round trip failed for {old_val} -> {new_val} with ops {ops:?}"
+                );
+                // diffing a value with itself should produce no ops
+                assert!(diff_q(q_ctx, &new, &new, max_depth).unwrap().is_empty());
+            }
+        });
+    }
+
+    #[test]
+    fn test_deep_equals_and_hash() {
+        let rt = init_test_rt();
+        rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_main_realm();
+            let eval = |code: &str| {
+                q_ctx
+                    .eval(Script::new("test_deep_equals.es", code))
+                    .expect("script failed")
+            };
+
+            let pairs = [
+                ("({a: NaN, b: [1, 2]})", "({b: [1, 2], a: NaN})", true),
+                ("({a: 1.0, b: -0})", "({b: 0, a: 1})", true),
+                ("[1, 2]", "[2, 1]", false),
+                ("({a: 1})", "({a: 1, b: undefined})", false),
+                ("new Date(1000)", "new Date(1000)", true),
+                ("new Date(1000)", "({})", false),
+                (
+                    "new Map([['a', {x: 1}], ['b', 2]])",
+                    "new Map([['b', 2], ['a', {x: 1}]])",
+                    true,
+                ),
+                ("new Set([1, 'a', NaN])", "new Set([NaN, 'a', 1])", true),
+                ("new Set([1, 2])", "new Set([1, 3])", false),
+                (
+                    "(() => {let a = {v: 1}; a.self = a; return a;})()",
+                    "(() => {let b = {v: 1}; b.self = b; return b;})()",
+                    true,
+                ),
+                (
+                    "(() => {let a = {v: 1}; a.self = a; return a;})()",
+                    "(() => {let b = {v: 1}; b.self = {v: 1, self: b}; return b;})()",
+                    false,
+                ),
+                ("new ArrayBuffer(4)", "new ArrayBuffer(4)", true),
+                ("new ArrayBuffer(4)", "new ArrayBuffer(8)", false),
+                ("new Uint8Array([1, 2])", "new Uint8Array([1, 2])", true),
+                ("new Uint8Array([1, 2])", "new Uint8Array([1, 3])", false),
+                ("new Uint8Array([1])", "new Int8Array([1])", false),
+                ("new Uint8Array([1])", "({0: 1})", false),
+                (
+                    "new Uint8Array([0, 1, 2, 3]).subarray(1, 3)",
+                    "new Uint8Array([1, 2])",
+                    true,
+                ),
+                ("/a/g", "/a/g", true),
+                ("/a/", "/b/", false),
+                ("/a/g", "/a/i", false),
+                ("new Number(-0)", "new Number(0)", true),
+                ("new Number(1)", "new Number(2)", false),
+                ("new Number(1)", "1", false),
+                ("new String('a')", "new String('b')", false),
+                ("new Boolean(true)", "new Boolean(false)", false),
+                ("Object(1n)", "Object(1n)", true),
+            ];
+
+            for (code_a, code_b, expected) in pairs {
+                let a = eval(code_a);
+                let b = eval(code_b);
+                let equal = deep_equals_q(q_ctx, &a, &b).expect("deep_equals failed");
+                assert_eq!(equal, expected, "{code_a} vs {code_b}");
+                if expected {
+                    assert_eq!(
+                        stable_hash_q(q_ctx, &a).expect("hash failed"),
+                        stable_hash_q(q_ctx, &b).expect("hash failed"),
+                        "{code_a} vs {code_b}"
+                    );
+                }
+            }
+
+            let with_func = eval("({f: function(){}})");
+            let err = deep_equals_q(q_ctx, &with_func, &with_func).expect_err("should fail");
+            assert_eq!(err.get_name(), "TypeError");
+            let with_symbol = eval("[Symbol('a')]");
+            let err = stable_hash_q(q_ctx, &with_symbol).expect_err("should fail");
+            assert_eq!(err.get_name(), "TypeError");
+            for code in ["new DataView(new ArrayBuffer(1))", "Object(Symbol('a'))"] {
+                let value = eval(code);
+                let err = stable_hash_q(q_ctx, &value).expect_err(code);
+                assert_eq!(err.get_name(), "TypeError");
+            }
+
+            // these used to hash as empty objects
+            for (code_a, code_b) in [
+                ("new ArrayBuffer(4)", "new ArrayBuffer(8)"),
+                ("/a/", "/b/"),
+                ("new Number(1)", "new Number(2)"),
+            ] {
+                assert_ne!(
+                    stable_hash_q(q_ctx, &eval(code_a)).expect("hash failed"),
+                    stable_hash_q(q_ctx, &eval(code_b)).expect("hash failed"),
+                    "{code_a} vs {code_b}"
+                );
+            }
+        });
+    }
+
+    #[test]
+    fn test_path_parse() {
+        let path =
+            JsPath::parse(r#"data.items[0]["content-type"]['it\'s'].id"#).expect("parse failed");
+        assert_eq!(
+            path.get_segments(),
+            &[
+                PathSegment::Key("data".to_string()),
+                PathSegment::Key("items".to_string()),
+                PathSegment::Index(0),
+                PathSegment::Key("content-type".to_string()),
+                PathSegment::Key("it's".to_string()),
+                PathSegment::Key("id".to_string()),
+            ]
+        );
+        assert_eq!(path.to_string(), r#"data.items[0].content-type["it's"].id"#);
+        assert_eq!(
+            JsPath::parse(path.to_string().as_str()).expect("reparse failed"),
+            path
+        );
+        assert!(JsPath::parse("[1]").is_ok());
+        assert!(JsPath::parse("")
+            .expect("parse failed")
+            .get_segments()
+            .is_empty());
+        for invalid in [".a", "a..b", "a[x]", "a[1", "a[1]b", "a['b]", "a."] {
+            let err = JsPath::parse(invalid).expect_err(invalid);
+            assert_eq!(err.get_name(), "SyntaxError");
+        }
+    }
+
+    #[test]
+    fn test_path_get_set() {
+        let rt = init_test_rt();
+        rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_main_realm();
+            let obj = q_ctx
+                .eval(Script::new(
+                    "test_path.js",
+                    "({data: {items: [{id: 12}], empty: null, name: 'x', frozen: Object.freeze({a: 1})}});",
+                ))
+                .expect("script failed");
+
+            let id = get_path_q(q_ctx, &obj, "data.items[0].id")
+                .expect("get failed")
+                .expect("no id");
+            assert_eq!(to_i32(&id).unwrap(), 12);
+            // optional chaining
+            assert!(get_path_q(q_ctx, &obj, "data.items[3].id")
+                .expect("get failed")
+                .is_none());
+            assert!(get_path_q(q_ctx, &obj, "data.empty.deeper.still")
+                .expect("get failed")
+                .is_none());
+            assert!(get_path_q(q_ctx, &obj, "data.missing")
+                .expect("get failed")
+                .is_none());
+            assert!(get_path_q(q_ctx, &obj, "data.empty")
+                .expect("get failed")
+                .expect("null is a value")
+                .is_null());
+
+            let err = get_path_q(q_ctx, &obj, "data[0]").expect_err("data is not an array");
+            assert_eq!(err.get_name(), "TypeError");
+            assert!(err.get_message().contains("segment [0] of path data[0]"));
+            let err = get_path_q(q_ctx, &obj, "data.name.length").expect_err("name is a string");
+            assert!(err.get_message().contains("segment length"));
+
+            // intermediate objects and arrays are created
+            let path = JsPath::parse("data.created.list[1].name").unwrap();
+            path.set_q(q_ctx, &obj, &primitives::from_string_q(q_ctx, "y").unwrap())
+                .expect("set failed");
+            set_path_q(q_ctx, &obj, "data.items[0].id", &from_i32(13)).expect("set failed");
+            set_path_q(q_ctx, &obj, "data.empty.a", &from_i32(1)).expect("set failed");
+            assert_eq!(
+                q_ctx.json_stringify(&obj, None).unwrap(),
+                r#"{"data":{"items":[{"id":13}],"empty":{"a":1},"name":"x","frozen":{"a":1},"created":{"list":[null,{"name":"y"}]}}}"#
+            );
+
+            let err = set_path_q(q_ctx, &obj, "data.frozen.a", &from_i32(2))
+                .expect_err("frozen object");
+            assert_eq!(err.get_name(), "TypeError");
+            assert!(err.get_message().contains("segment a of path data.frozen.a"));
+            let err = set_path_q(q_ctx, &obj, "data.name.x", &from_i32(2))
+                .expect_err("name is a string");
+            assert!(err.get_message().contains("segment x"));
+            let err = set_path_q(q_ctx, &obj, "data[0]", &from_i32(2)).expect_err("not an array");
+            assert!(err.get_message().contains("not an array"));
+        });
+    }
 }
@@ -0,0 +1,176 @@
+//! pinned handles for values which are held by long-lived native code
+//!
+//! a [PinnedRef] registers a value in the pinned values of its realm, the realm keeps the value alive until the handle is dropped,
+//! unlike a plain [QuickJsValueAdapter] in a rust struct the value is released before its context is freed even when the handle is leaked,
+//! the realm logs the handles which were not released when it is dropped
+//!
+//! a handle can only be used and dropped on the worker thread of the runtime
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::Script;
+//! use quickjs_runtime::quickjs_utils::functions;
+//! use quickjs_runtime::quickjs_utils::pinned::PinnedRef;
+//! let rt = QuickJsRuntimeBuilder::new().build();
+//! rt.exe_rt_task_in_event_loop(|q_js_rt| {
+//!     let realm = q_js_rt.get_main_realm();
+//!     let callback = realm.eval(Script::new("pinned.js", "(() => 42)")).expect("script failed");
+//!     let pinned = PinnedRef::new(realm, &callback);
+//!     drop(callback);
+//!     q_js_rt.gc();
+//!     let callback = pinned.get().expect("realm was dropped");
+//!     let res = functions::call_function_q(realm, &callback, &[], None).expect("call failed");
+//!     assert_eq!(res.to_i32(), 42);
+//! });
+//! ```
+
+use crate::jsutils::JsError;
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsruntimeadapter::{QuickJsRuntimeAdapter, QJS_RT};
+use crate::quickjsvalueadapter::QuickJsValueAdapter;
+use std::marker::PhantomData;
+
+/// a handle to a value which is kept alive by its realm until the handle is dropped
+pub struct PinnedRef {
+    realm_id: String,
+    id: usize,
+    // handles can not leave the worker thread
+    _not_send: PhantomData<*mut ()>,
+}
+
+impl PinnedRef {
+    /// pin a value in a realm
+    pub fn new(realm: &QuickJsRealmAdapter, value: &QuickJsValueAdapter) -> Self {
+        let id = realm.pinned_values.borrow_mut().insert(value.dup());
+        Self {
+            realm_id: realm.id.clone(),
+            id,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// get the pinned value, fails when the realm was dropped
+    pub fn get(&self) -> Result<QuickJsValueAdapter, JsError> {
+        QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+            let realm = q_js_rt
+                .opt_context(self.realm_id.as_str())
+                .ok_or_else(|| JsError::new_string(format!("no such realm: {}", self.realm_id)))?;
+            let pinned = &*realm.pinned_values.borrow();
+            pinned
+                .get(&self.id)
+                .map(QuickJsValueAdapter::dup)
+                .ok_or_else(|| JsError::new_str("pinned value was released"))
+        })
+    }
+
+    /// get the pinned values of a list of handles
+    pub fn get_all(pinned: &[PinnedRef]) -> Result<Vec<QuickJsValueAdapter>, JsError> {
+        pinned.iter().map(|p| p.get()).collect()
+    }
+
+    pub fn get_realm_id(&self) -> &str {
+        self.realm_id.as_str()
+    }
+}
+
+impl Drop for PinnedRef {
+    fn drop(&mut self) {
+        // the runtime may already be gone when a handle is dropped while the worker thread shuts down
+        let _ = QJS_RT.try_with(|rc| {
+            if let Ok(q_js_rt) = rc.try_borrow() {
+                if let Some(realm) = q_js_rt
+                    .as_ref()
+                    .and_then(|q_js_rt| q_js_rt.opt_context(self.realm_id.as_str()))
+                {
+                    if let Ok(mut pinned) = realm.pinned_values.try_borrow_mut() {
+                        if pinned.contains_key(&self.id) {
+                            let _ = pinned.remove(&self.id);
+                        }
+                    }
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::Script;
+    use crate::quickjs_utils::pinned::PinnedRef;
+    use crate::quickjs_utils::{functions, objects};
+    use std::cell::RefCell;
+    use std::time::Duration;
+
+    #[test]
+    fn test_pinned_ref() {
+        let rt = QuickJsRuntimeBuilder::new().build();
+        rt.create_context("pinned_realm")
+            .expect("could not create realm");
+
+        rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let realm = q_js_rt.get_main_realm();
+            // a cycle which is only reachable from rust
+            let obj = realm
+                .eval(Script::new(
+                    "test_pinned_ref.js",
+                    "(() => { const a = {name: 'a'}; a.self = a; return a; })()",
+                ))
+                .expect("script failed");
+            let pinned = PinnedRef::new(realm, &obj);
+            drop(obj);
+            q_js_rt.gc();
+            let obj = pinned.get().expect("no value");
+            let name = objects::get_property_q(realm, &obj, "name").expect("no name");
+            assert_eq!(name.to_string().expect("not a string"), "a");
+            assert_eq!(realm.pinned_values.borrow().len(), 1);
+            drop(pinned);
+            assert_eq!(realm.pinned_values.borrow().len(), 0);
+        });
+
+        // a realm releases the handles which were leaked when it is dropped
+        thread_local! {
+            static KEPT: RefCell<Option<PinnedRef>> = RefCell::new(None);
+        }
+        rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let realm = q_js_rt.get_realm("pinned_realm").expect("no realm");
+            let func = functions::new_function_q(
+                realm,
+                "leaked",
+                |realm, _this, _args| realm.create_null(),
+                0,
+            )
+            .expect("could not create function");
+            std::mem::forget(PinnedRef::new(realm, &func));
+            KEPT.with(|rc| rc.replace(Some(PinnedRef::new(realm, &func))));
+        });
+        rt.destroy_realm("pinned_realm")
+            .expect("could not destroy realm");
+        rt.exe_rt_task_in_event_loop(|_q_js_rt| {
+            let kept = KEPT.with(|rc| rc.take()).expect("no handle");
+            assert!(kept.get().is_err());
+        });
+
+        // timer callbacks are pinned until they ran
+        rt.eval_sync(
+            None,
+            Script::new(
+                "test_pinned_ref.js",
+                "globalThis.ran = false; setTimeout(function() { ran = true; }, 10);",
+            ),
+        )
+        .expect("script failed");
+        let pinned_count = || {
+            rt.exe_rt_task_in_event_loop(|q_js_rt| {
+                q_js_rt.get_main_realm().pinned_values.borrow().len()
+            })
+        };
+        assert!(pinned_count() > 0);
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(pinned_count(), 0);
+        assert!(rt
+            .eval_sync(None, Script::new("test_pinned_ref.js", "ran"))
+            .expect("script failed")
+            .get_bool());
+    }
+}
@@ -1,4 +1,5 @@
 use crate::jsutils::JsError;
+use crate::quickjs_utils::errors;
 use crate::quickjsrealmadapter::QuickJsRealmAdapter;
 use crate::quickjsvalueadapter::QuickJsValueAdapter;
 use core::ptr;
@@ -138,7 +139,10 @@ pub unsafe fn from_string(
     let qval = q::JS_NewStringLen(context, s.as_ptr() as *const c_char, s.len() as _);
     let ret = QuickJsValueAdapter::new(context, qval, false, true, "primitives::from_string qval");
     if ret.is_exception() {
-        return Err(JsError::new_str("Could not create string in runtime"));
+        return Err(errors::get_exception_or(
+            context,
+            "Could not create string in runtime",
+        ));
     }
 
     Ok(ret)
@@ -1,4 +1,5 @@
 use crate::jsutils::JsError;
+use crate::jsutils::{redaction, uncaught};
 use crate::quickjs_utils;
 use crate::quickjs_utils::errors::get_stack;
 use crate::quickjs_utils::functions;
@@ -27,7 +28,7 @@ pub struct QuickJsPromiseAdapter {
 #[allow(dead_code)]
 impl QuickJsPromiseAdapter {
     pub fn get_promise_obj_ref(&self) -> QuickJsValueAdapter {
-        self.promise_obj_ref.clone()
+        self.promise_obj_ref.dup()
     }
 
     pub fn resolve_q(
@@ -81,9 +82,9 @@ impl QuickJsPromiseAdapter {
 impl Clone for QuickJsPromiseAdapter {
     fn clone(&self) -> Self {
         Self {
-            promise_obj_ref: self.promise_obj_ref.clone(),
-            reject_function_obj_ref: self.reject_function_obj_ref.clone(),
-            resolve_function_obj_ref: self.resolve_function_obj_ref.clone(),
+            promise_obj_ref: self.promise_obj_ref.dup(),
+            reject_function_obj_ref: self.reject_function_obj_ref.dup(),
+            resolve_function_obj_ref: self.resolve_function_obj_ref.dup(),
         }
     }
 }
@@ -94,7 +95,7 @@ impl QuickJsPromiseAdapter {
         context: &QuickJsRealmAdapter,
         resolution: &QuickJsValueAdapter,
     ) -> Result<(), JsError> {
-        self.resolve_q(context, resolution.clone())
+        self.resolve_q(context, resolution.dup())
     }
 
     pub fn js_promise_reject(
@@ -102,11 +103,11 @@ impl QuickJsPromiseAdapter {
         context: &QuickJsRealmAdapter,
         rejection: &QuickJsValueAdapter,
     ) -> Result<(), JsError> {
-        self.reject_q(context, rejection.clone())
+        self.reject_q(context, rejection.dup())
     }
 
     pub fn js_promise_get_value(&self, _realm: &QuickJsRealmAdapter) -> QuickJsValueAdapter {
-        self.promise_obj_ref.clone()
+        self.promise_obj_ref.dup()
     }
 }
 
@@ -253,19 +254,24 @@ unsafe extern "C" fn promise_rejection_tracker(
             };
             match reason_str_res {
                 Ok(reason_str) => {
-                    log::error!(
+                    let line = format!(
                         "[{}] unhandled promise rejection, reason: {}{}",
-                        realm_id,
-                        reason_str,
-                        stack
+                        realm_id, reason_str, stack
+                    );
+                    uncaught::report_uncaught_q(
+                        realm,
+                        "unhandled rejection",
+                        &JsError::new("UnhandledRejection".to_string(), reason_str, stack).redact(),
                     );
+                    log::error!("{}", redaction::redact(line.as_str()));
                 }
                 Err(e) => {
+                    // e was already redacted when it was created
                     log::error!(
                         "[{}] unhandled promise rejection, could not get reason: {}{}",
                         realm_id,
                         e,
-                        stack
+                        redaction::redact(stack.as_str())
                     );
                 }
             }
@@ -93,7 +93,7 @@ pub unsafe fn add(
 ///    let q_ctx = q_js_rt.get_main_realm();
 ///    let my_set: QuickJsValueAdapter = new_set_q(q_ctx).ok().unwrap();
 ///    let value = primitives::from_i32(23);
-///    add_q(q_ctx, &my_set, value.clone()).ok().unwrap();
+///    add_q(q_ctx, &my_set, value.dup()).ok().unwrap();
 ///    delete_q(q_ctx, &my_set, value).ok().unwrap();
 /// });
 /// ```
@@ -130,7 +130,7 @@ pub unsafe fn delete(
 ///    let q_ctx = q_js_rt.get_main_realm();
 ///    let my_set: QuickJsValueAdapter = new_set_q(q_ctx).ok().unwrap();
 ///    let value = primitives::from_i32(23);
-///    add_q(q_ctx, &my_set, value.clone()).ok().unwrap();
+///    add_q(q_ctx, &my_set, value.dup()).ok().unwrap();
 ///    let bln_has = has_q(q_ctx, &my_set, value).ok().unwrap();
 ///    assert!(bln_has);
 /// });
@@ -0,0 +1,627 @@
+//! copy values within a realm or between realms of the same runtime with the structured clone algorithm
+//!
+//! * supported are primitives (except symbols), plain objects, arrays, Dates, Errors, Maps, Sets, ArrayBuffers and TypedArrays
+//! * only the own enumerable properties of an object are copied, the clone of a class instance is a plain object
+//! * an object which is referenced more than once is cloned once, the clone is referenced in the same places
+//! * a TypedArray gets a new ArrayBuffer with a copy of the bytes in its view
+//! * functions, promises, symbols, proxy instances and cyclic values fail with a DataCloneError
+//!
+//! in script a value is cloned within its realm with the global `structuredClone()`, see [structured_clone](crate::features::structured_clone)
+//!
+//! a value can also be serialized to bytes with [serialize_q] and deserialized later, or in another runtime, with [deserialize_q]
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::Script;
+//! use quickjs_runtime::quickjs_utils::structuredclone::structured_clone_q;
+//! let rt = QuickJsRuntimeBuilder::new().build();
+//! rt.create_realm("other").expect("could not create realm");
+//! let res = rt.exe_rt_task_in_event_loop(|q_js_rt| {
+//!     let main = q_js_rt.get_main_realm();
+//!     let other = q_js_rt.get_realm("other").expect("no realm");
+//!     let value = main.eval(Script::new("clone.js", "new Map([['when', new Date(0)]]);")).expect("script failed");
+//!     let clone = structured_clone_q(main, &value, other).expect("clone failed");
+//!     other.set_object_property(&other.get_global().unwrap(), "cloned", &clone).expect("set failed");
+//!     other.eval(Script::new("clone.js", "cloned.get('when') instanceof Date;")).expect("script failed").to_bool()
+//! });
+//! assert!(res);
+//! ```
+
+use crate::features::encoding::{decode_base64, encode_base64};
+use crate::jsutils::JsError;
+use crate::quickjs_utils::{
+    arrays, bigints, conversion, dates, errors, functions, get_constructor, maps, new_null_ref,
+    new_undefined_ref, objects, primitives, promises, sets, typedarrays,
+};
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsvalueadapter::{
+    QuickJsValueAdapter, TAG_BIG_INT, TAG_BOOL, TAG_FLOAT64, TAG_INT, TAG_NULL, TAG_OBJECT,
+    TAG_STRING, TAG_SYMBOL, TAG_UNDEFINED,
+};
+use crate::reflection::is_proxy_instance_q;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// the name of the errors which are returned for values which can not be cloned
+pub const DATA_CLONE_ERROR: &str = "DataCloneError";
+
+/// the version of the format written by [serialize_q]
+pub const SERIALIZED_FORMAT_VERSION: u64 = 1;
+
+// the TypedArrays which can be deserialized
+const TYPED_ARRAY_NAMES: &[&str] = &[
+    "Int8Array",
+    "Uint8Array",
+    "Uint8ClampedArray",
+    "Int16Array",
+    "Uint16Array",
+    "Int32Array",
+    "Uint32Array",
+    "Float32Array",
+    "Float64Array",
+    "BigInt64Array",
+    "BigUint64Array",
+];
+
+fn data_clone_error(msg: &str) -> JsError {
+    JsError::new(
+        DATA_CLONE_ERROR.to_string(),
+        msg.to_string(),
+        "".to_string(),
+    )
+}
+
+/// clone a value of the source realm into the target realm, source and target may be the same realm
+pub fn structured_clone_q(
+    source: &QuickJsRealmAdapter,
+    value: &QuickJsValueAdapter,
+    target: &QuickJsRealmAdapter,
+) -> Result<QuickJsValueAdapter, JsError> {
+    let mut cloner = Cloner {
+        source,
+        target,
+        ancestors: vec![],
+        clones: HashMap::new(),
+    };
+    cloner.clone_value(value)
+}
+
+/// serialize a value with the structured clone algorithm, the same values as with [structured_clone_q] are supported
+///
+/// the bytes are a versioned json document, numbers which json can not represent (NaN, Infinity and -0) and undefined are kept
+pub fn serialize_q(
+    realm: &QuickJsRealmAdapter,
+    value: &QuickJsValueAdapter,
+) -> Result<Vec<u8>, JsError> {
+    let mut serializer = Serializer {
+        realm,
+        ancestors: vec![],
+        ids: HashMap::new(),
+    };
+    let serialized = serializer.serialize_value(value)?;
+    serde_json::to_vec(&json!([SERIALIZED_FORMAT_VERSION, serialized]))
+        .map_err(|e| JsError::new_string(format!("{e}")))
+}
+
+/// deserialize a value which was serialized with [serialize_q] in a realm
+pub fn deserialize_q(
+    realm: &QuickJsRealmAdapter,
+    bytes: &[u8],
+) -> Result<QuickJsValueAdapter, JsError> {
+    let document: Value = serde_json::from_slice(bytes)
+        .map_err(|e| data_clone_error(format!("invalid serialized value: {e}").as_str()))?;
+    match document.as_array().map(Vec::as_slice) {
+        Some([version, serialized]) if version.as_u64() == Some(SERIALIZED_FORMAT_VERSION) => {
+            let mut deserializer = Deserializer {
+                realm,
+                objects: vec![],
+            };
+            deserializer.deserialize_value(serialized)
+        }
+        _ => Err(data_clone_error("unsupported serialized value")),
+    }
+}
+
+fn serialize_number(number: f64) -> Value {
+    if number.is_finite() && !(number == 0.0 && number.is_sign_negative()) {
+        json!(["n", number])
+    } else {
+        json!(["n", number.to_string()])
+    }
+}
+
+fn deserialize_number(serialized: &Value) -> Option<f64> {
+    match serialized {
+        Value::Number(number) => number.as_f64(),
+        Value::String(number) => number.parse::<f64>().ok(),
+        _ => None,
+    }
+}
+
+struct Serializer<'a> {
+    realm: &'a QuickJsRealmAdapter,
+    // the objects which are being serialized, a value which refers to one of them is a cycle
+    ancestors: Vec<QuickJsValueAdapter>,
+    // the objects which were serialized completely by the order in which they were completed, a value which is referenced again refers to its id
+    ids: HashMap<QuickJsValueAdapter, usize>,
+}
+
+impl Serializer<'_> {
+    fn serialize_value(&mut self, value: &QuickJsValueAdapter) -> Result<Value, JsError> {
+        conversion::tick()?;
+        match value.get_tag() {
+            TAG_UNDEFINED => Ok(json!(["u"])),
+            TAG_NULL => Ok(Value::Null),
+            TAG_BOOL => Ok(json!(["b", value.to_bool()])),
+            TAG_INT => Ok(json!(["n", value.to_i32()])),
+            TAG_FLOAT64 => Ok(serialize_number(value.to_f64())),
+            TAG_STRING => Ok(json!(["s", value.to_string()?])),
+            TAG_BIG_INT => Ok(json!(["i", bigints::to_string_q(self.realm, value)?])),
+            TAG_SYMBOL => Err(data_clone_error("a symbol could not be serialized")),
+            TAG_OBJECT => self.serialize_object(value),
+            tag => Err(data_clone_error(
+                format!("a value with tag {tag} could not be serialized").as_str(),
+            )),
+        }
+    }
+
+    fn serialize_object(&mut self, value: &QuickJsValueAdapter) -> Result<Value, JsError> {
+        if let Some(id) = self.ids.get(value) {
+            return Ok(json!(["r", id]));
+        }
+        if self.ancestors.contains(value) {
+            return Err(data_clone_error("a cyclic value could not be serialized"));
+        }
+        self.ancestors.push(value.dup());
+        let res = self.serialize_object_contents(value);
+        self.ancestors.pop();
+        let serialized = res?;
+        self.ids.insert(value.dup(), self.ids.len());
+        Ok(serialized)
+    }
+
+    fn serialize_object_contents(&mut self, value: &QuickJsValueAdapter) -> Result<Value, JsError> {
+        let realm = self.realm;
+        if functions::is_function_q(realm, value) {
+            Err(data_clone_error("a function could not be serialized"))
+        } else if is_proxy_instance_q(realm, value) {
+            Err(data_clone_error("a proxy instance could not be serialized"))
+        } else if promises::is_promise_q(realm, value) {
+            Err(data_clone_error("a promise could not be serialized"))
+        } else if arrays::is_array_q(realm, value) {
+            let mut elements = vec![];
+            for index in 0..arrays::get_length_q(realm, value)? {
+                let element = arrays::get_element_q(realm, value, index)?;
+                elements.push(self.serialize_value(&element)?);
+            }
+            Ok(json!(["a", elements]))
+        } else if dates::is_date_q(realm, value) {
+            Ok(json!([
+                "d",
+                serialize_number(dates::get_time_q(realm, value)?)
+            ]))
+        } else if errors::is_error_q(realm, value) {
+            let err = unsafe { errors::error_to_js_error(realm.context, value) };
+            Ok(json!([
+                "e",
+                err.get_name(),
+                err.get_message(),
+                err.get_stack()
+            ]))
+        } else if maps::is_map_q(realm, value)? {
+            let mut entries = vec![];
+            for (key, val) in maps::entries_q(realm, value, |k, v| Ok((k, v)))? {
+                entries.push(json!([
+                    self.serialize_value(&key)?,
+                    self.serialize_value(&val)?
+                ]));
+            }
+            Ok(json!(["m", entries]))
+        } else if sets::is_set_q(realm, value)? {
+            let mut values = vec![];
+            for val in sets::values_q(realm, value, Ok)? {
+                values.push(self.serialize_value(&val)?);
+            }
+            Ok(json!(["t", values]))
+        } else if typedarrays::is_typed_array_q(realm, value) {
+            let constructor = objects::get_property_q(realm, value, "constructor")?;
+            let constructor_name = objects::get_property_q(realm, &constructor, "name")?;
+            Ok(json!([
+                "ta",
+                constructor_name.to_string()?,
+                encode_base64(&typedarrays::get_bytes_q(realm, value)?)
+            ]))
+        } else if typedarrays::is_array_buffer_q(realm, value) {
+            Ok(json!([
+                "ab",
+                encode_base64(&typedarrays::get_bytes_q(realm, value)?)
+            ]))
+        } else {
+            let mut properties = vec![];
+            for name in objects::get_property_names_q(realm, value)? {
+                let prop = objects::get_property_q(realm, value, name.as_str())?;
+                properties.push(json!([name, self.serialize_value(&prop)?]));
+            }
+            Ok(json!(["o", properties]))
+        }
+    }
+}
+
+struct Deserializer<'a> {
+    realm: &'a QuickJsRealmAdapter,
+    // the objects which were deserialized completely, in the same order as the ids of the serializer
+    objects: Vec<QuickJsValueAdapter>,
+}
+
+impl Deserializer<'_> {
+    fn deserialize_value(&mut self, serialized: &Value) -> Result<QuickJsValueAdapter, JsError> {
+        conversion::tick()?;
+        let invalid = || data_clone_error("invalid serialized value");
+        let parts = match serialized {
+            Value::Null => return Ok(new_null_ref()),
+            Value::Array(parts) => parts.as_slice(),
+            _ => return Err(invalid()),
+        };
+        let realm = self.realm;
+        let object = match parts {
+            [Value::String(tag)] if tag == "u" => return Ok(new_undefined_ref()),
+            [Value::String(tag), Value::Bool(b)] if tag == "b" => {
+                return Ok(primitives::from_bool(*b))
+            }
+            [Value::String(tag), Value::Number(n)] if tag == "n" && n.is_i64() => {
+                return match n.as_i64().and_then(|n| i32::try_from(n).ok()) {
+                    Some(n) => Ok(primitives::from_i32(n)),
+                    None => Ok(primitives::from_f64(n.as_f64().ok_or_else(invalid)?)),
+                };
+            }
+            [Value::String(tag), number] if tag == "n" => {
+                return Ok(primitives::from_f64(
+                    deserialize_number(number).ok_or_else(invalid)?,
+                ))
+            }
+            [Value::String(tag), Value::String(s)] if tag == "s" => {
+                return primitives::from_string_q(realm, s)
+            }
+            [Value::String(tag), Value::String(s)] if tag == "i" => {
+                return bigints::new_bigint_str_q(realm, s)
+            }
+            [Value::String(tag), id] if tag == "r" => {
+                return id
+                    .as_u64()
+                    .and_then(|id| self.objects.get(id as usize))
+                    .map(QuickJsValueAdapter::dup)
+                    .ok_or_else(invalid)
+            }
+            [Value::String(tag), Value::Array(elements)] if tag == "a" => {
+                let arr = arrays::create_array_q(realm)?;
+                for (index, element) in (0_u32..).zip(elements.iter()) {
+                    arrays::set_element_q(realm, &arr, index, &self.deserialize_value(element)?)?;
+                }
+                arr
+            }
+            [Value::String(tag), time] if tag == "d" => {
+                let time = match time.as_array().map(Vec::as_slice) {
+                    Some([_, number]) => deserialize_number(number).ok_or_else(invalid)?,
+                    _ => return Err(invalid()),
+                };
+                let date = dates::new_date_q(realm)?;
+                dates::set_time_q(realm, &date, time)?;
+                date
+            }
+            [Value::String(tag), Value::String(name), Value::String(message), Value::String(stack)]
+                if tag == "e" =>
+            {
+                realm.create_error(name, message, stack)?
+            }
+            [Value::String(tag), Value::Array(entries)] if tag == "m" => {
+                let map = maps::new_map_q(realm)?;
+                for entry in entries {
+                    match entry.as_array().map(Vec::as_slice) {
+                        Some([key, val]) => {
+                            let key = self.deserialize_value(key)?;
+                            let val = self.deserialize_value(val)?;
+                            maps::set_q(realm, &map, key, val)?;
+                        }
+                        _ => return Err(invalid()),
+                    }
+                }
+                map
+            }
+            [Value::String(tag), Value::Array(values)] if tag == "t" => {
+                let set = sets::new_set_q(realm)?;
+                for val in values {
+                    sets::add_q(realm, &set, self.deserialize_value(val)?)?;
+                }
+                set
+            }
+            [Value::String(tag), Value::String(data)] if tag == "ab" => {
+                let bytes = decode_base64(data).map_err(|_| invalid())?;
+                typedarrays::new_array_buffer_copy_q(realm, &bytes)?
+            }
+            [Value::String(tag), Value::String(name), Value::String(data)]
+                if tag == "ta" && TYPED_ARRAY_NAMES.contains(&name.as_str()) =>
+            {
+                let bytes = decode_base64(data).map_err(|_| invalid())?;
+                let buffer = typedarrays::new_array_buffer_copy_q(realm, &bytes)?;
+                let constructor = unsafe { get_constructor(realm.context, name) }?;
+                unsafe { objects::construct_object(realm.context, &constructor, &[&buffer]) }?
+            }
+            [Value::String(tag), Value::Array(properties)] if tag == "o" => {
+                let obj = objects::create_object_q(realm)?;
+                for property in properties {
+                    match property.as_array().map(Vec::as_slice) {
+                        Some([Value::String(name), prop]) => {
+                            let prop = self.deserialize_value(prop)?;
+                            objects::set_property_q(realm, &obj, name, &prop)?;
+                        }
+                        _ => return Err(invalid()),
+                    }
+                }
+                obj
+            }
+            _ => return Err(invalid()),
+        };
+        self.objects.push(object.dup());
+        Ok(object)
+    }
+}
+
+struct Cloner<'a> {
+    source: &'a QuickJsRealmAdapter,
+    target: &'a QuickJsRealmAdapter,
+    // the objects which are being cloned, a value which refers to one of them is a cycle
+    ancestors: Vec<QuickJsValueAdapter>,
+    // the objects which were cloned completely
+    clones: HashMap<QuickJsValueAdapter, QuickJsValueAdapter>,
+}
+
+impl Cloner<'_> {
+    fn clone_value(&mut self, value: &QuickJsValueAdapter) -> Result<QuickJsValueAdapter, JsError> {
+        conversion::tick()?;
+        match value.get_tag() {
+            TAG_UNDEFINED => Ok(new_undefined_ref()),
+            TAG_NULL => Ok(new_null_ref()),
+            TAG_BOOL => Ok(primitives::from_bool(value.to_bool())),
+            TAG_INT => Ok(primitives::from_i32(value.to_i32())),
+            TAG_FLOAT64 => Ok(primitives::from_f64(value.to_f64())),
+            TAG_STRING => primitives::from_string_q(self.target, value.to_string()?.as_str()),
+            TAG_BIG_INT => bigints::new_bigint_str_q(
+                self.target,
+                bigints::to_string_q(self.source, value)?.as_str(),
+            ),
+            TAG_SYMBOL => Err(data_clone_error("a symbol could not be cloned")),
+            TAG_OBJECT => self.clone_object(value),
+            tag => Err(data_clone_error(
+                format!("a value with tag {tag} could not be cloned").as_str(),
+            )),
+        }
+    }
+
+    fn clone_object(
+        &mut self,
+        value: &QuickJsValueAdapter,
+    ) -> Result<QuickJsValueAdapter, JsError> {
+        if let Some(clone) = self.clones.get(value) {
+            return Ok(clone.dup());
+        }
+        if self.ancestors.contains(value) {
+            return Err(data_clone_error("a cyclic value could not be cloned"));
+        }
+        self.ancestors.push(value.dup());
+        let res = self.clone_object_contents(value);
+        self.ancestors.pop();
+        let clone = res?;
+        self.clones.insert(value.dup(), clone.dup());
+        Ok(clone)
+    }
+
+    fn clone_object_contents(
+        &mut self,
+        value: &QuickJsValueAdapter,
+    ) -> Result<QuickJsValueAdapter, JsError> {
+        let (source, target) = (self.source, self.target);
+        if functions::is_function_q(source, value) {
+            Err(data_clone_error("a function could not be cloned"))
+        } else if is_proxy_instance_q(source, value) {
+            Err(data_clone_error("a proxy instance could not be cloned"))
+        } else if promises::is_promise_q(source, value) {
+            Err(data_clone_error("a promise could not be cloned"))
+        } else if arrays::is_array_q(source, value) {
+            let clone = arrays::create_array_q(target)?;
+            for index in 0..arrays::get_length_q(source, value)? {
+                let element = arrays::get_element_q(source, value, index)?;
+                arrays::set_element_q(target, &clone, index, &self.clone_value(&element)?)?;
+            }
+            Ok(clone)
+        } else if dates::is_date_q(source, value) {
+            let clone = dates::new_date_q(target)?;
+            dates::set_time_q(target, &clone, dates::get_time_q(source, value)?)?;
+            Ok(clone)
+        } else if errors::is_error_q(source, value) {
+            let err = unsafe { errors::error_to_js_error(source.context, value) };
+            target.create_error(err.get_name(), err.get_message(), err.get_stack())
+        } else if maps::is_map_q(source, value)? {
+            let clone = maps::new_map_q(target)?;
+            for (key, val) in maps::entries_q(source, value, |k, v| Ok((k, v)))? {
+                maps::set_q(
+                    target,
+                    &clone,
+                    self.clone_value(&key)?,
+                    self.clone_value(&val)?,
+                )?;
+            }
+            Ok(clone)
+        } else if sets::is_set_q(source, value)? {
+            let clone = sets::new_set_q(target)?;
+            for val in sets::values_q(source, value, Ok)? {
+                sets::add_q(target, &clone, self.clone_value(&val)?)?;
+            }
+            Ok(clone)
+        } else if typedarrays::is_typed_array_q(source, value) {
+            let constructor = objects::get_property_q(source, value, "constructor")?;
+            let constructor_name = objects::get_property_q(source, &constructor, "name")?;
+            let constructor_name = constructor_name.to_string()?;
+            let target_constructor =
+                unsafe { get_constructor(target.context, constructor_name.as_str()) }.map_err(
+                    |_| {
+                        data_clone_error(
+                            format!("a {constructor_name} could not be cloned").as_str(),
+                        )
+                    },
+                )?;
+            let buffer = typedarrays::new_array_buffer_copy_q(
+                target,
+                &typedarrays::get_bytes_q(source, value)?,
+            )?;
+            unsafe { objects::construct_object(target.context, &target_constructor, &[&buffer]) }
+        } else if typedarrays::is_array_buffer_q(source, value) {
+            typedarrays::new_array_buffer_copy_q(target, &typedarrays::get_bytes_q(source, value)?)
+        } else {
+            let clone = objects::create_object_q(target)?;
+            for name in objects::get_property_names_q(source, value)? {
+                let prop = objects::get_property_q(source, value, name.as_str())?;
+                objects::set_property_q(target, &clone, name.as_str(), &self.clone_value(&prop)?)?;
+            }
+            Ok(clone)
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::Script;
+    use crate::quickjs_utils::structuredclone::{deserialize_q, serialize_q, DATA_CLONE_ERROR};
+
+    #[test]
+    fn test_clone_cyclic() {
+        let rt = QuickJsRuntimeBuilder::new().build();
+        rt.create_realm("target").expect("could not create realm");
+        let err = rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let main = q_js_rt.get_main_realm();
+            let target = q_js_rt.get_realm("target").expect("no realm");
+            let value = main
+                .eval(Script::new(
+                    "test_clone_cyclic.js",
+                    "let o = {a: {b: [1]}}; o.a.b.push(o); o;",
+                ))
+                .expect("script failed");
+            main.structured_clone(&value, target)
+                .expect_err("cyclic value was cloned")
+        });
+        assert_eq!(err.get_name(), DATA_CLONE_ERROR);
+        assert!(err.get_message().contains("cyclic"));
+
+        // shared values which are not cyclic are cloned once
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "test_clone_cyclic.js",
+                    "let shared = {x: 1}; let c = structuredClone({a: shared, b: [shared]}); \
+                     `${c.a === c.b[0]}:${c.a !== shared}`",
+                ),
+            )
+            .expect("script failed");
+        assert_eq!(res.get_str(), "true:true");
+
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "test_clone_cyclic.js",
+                    "let names = []; \
+                     for (let v of [() => 1, Promise.resolve(1), Symbol('s')]) { \
+                         try { structuredClone({v}); } catch (e) { names.push(e.name); } \
+                     } \
+                     try { let c = {}; c.c = c; structuredClone(c); } catch (e) { names.push(e.name); } \
+                     names.join(',');",
+                ),
+            )
+            .expect("script failed");
+        assert_eq!(
+            res.get_str(),
+            "DataCloneError,DataCloneError,DataCloneError,DataCloneError"
+        );
+    }
+
+    #[test]
+    fn test_clone_map_of_typed_arrays() {
+        let rt = QuickJsRuntimeBuilder::new().build();
+        rt.create_realm("target").expect("could not create realm");
+        let res = rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let main = q_js_rt.get_main_realm();
+            let target = q_js_rt.get_realm("target").expect("no realm");
+            let value = main
+                .eval(Script::new(
+                    "test_clone_map.js",
+                    "let bytes = new Uint8Array([0, 1, 2, 3, 4, 5, 6, 7]); \
+                     new Map([\
+                        ['bytes', bytes.subarray(2, 6)], \
+                        ['floats', new Float64Array([1.5, -2])], \
+                        ['nested', new Map([[1, {buf: bytes.buffer, when: new Date(1000), tags: new Set(['a'])}]])]\
+                     ]);",
+                ))
+                .expect("script failed");
+            let clone = main
+                .structured_clone(&value, target)
+                .expect("clone failed");
+            let global = target.get_global().expect("no global");
+            target
+                .set_object_property(&global, "cloned", &clone)
+                .expect("set failed");
+            target
+                .eval(Script::new(
+                    "test_clone_map.js",
+                    "let n = cloned.get('nested').get(1); \
+                     [cloned instanceof Map, \
+                      cloned.get('bytes') instanceof Uint8Array, cloned.get('bytes').join('-'), \
+                      cloned.get('floats') instanceof Float64Array, cloned.get('floats').join('/'), \
+                      n.buf instanceof ArrayBuffer, n.buf.byteLength, \
+                      n.when instanceof Date, n.when.getTime(), \
+                      n.tags instanceof Set, n.tags.has('a')].join(',');",
+                ))
+                .expect("script failed")
+                .to_string()
+                .expect("not a string")
+        });
+        assert_eq!(
+            res,
+            "true,true,2-3-4-5,true,1.5/-2,true,8,true,1000,true,true"
+        );
+    }
+
+    #[test]
+    fn test_serialize() {
+        let rt = QuickJsRuntimeBuilder::new().build();
+        rt.create_realm("target").expect("could not create realm");
+        let res = rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let main = q_js_rt.get_main_realm();
+            let target = q_js_rt.get_realm("target").expect("no realm");
+            let value = main
+                .eval(Script::new(
+                    "test_serialize.js",
+                    "let shared = [1n, -Infinity]; \
+                     ({a: shared, b: new Map([['s', shared]]), e: new RangeError('r'), bytes: new Int16Array([-2, 7])});",
+                ))
+                .expect("script failed");
+            let bytes = serialize_q(main, &value).expect("serialize failed");
+            let value = deserialize_q(target, &bytes).expect("deserialize failed");
+            let global = target.get_global().expect("no global");
+            target
+                .set_object_property(&global, "deserialized", &value)
+                .expect("set failed");
+            target
+                .eval(Script::new(
+                    "test_serialize.js",
+                    "let d = deserialized; \
+                     [d.a === d.b.get('s'), typeof d.a[0], d.a[1], d.e.name, d.e.message, \
+                      d.bytes instanceof Int16Array, d.bytes.join('/')].join(',');",
+                ))
+                .expect("script failed")
+                .to_string()
+                .expect("not a string")
+        });
+        assert_eq!(res, "true,bigint,-Infinity,RangeError,r,true,-2/7");
+    }
+}
@@ -103,7 +103,10 @@ pub unsafe fn new_array_buffer(
     let obj_ref =
         QuickJsValueAdapter::new(ctx, raw, false, true, "typedarrays::new_array_buffer_q");
     if obj_ref.is_exception() {
-        return Err(JsError::new_str("Could not create array buffer"));
+        return Err(crate::quickjs_utils::errors::get_exception_or(
+            ctx,
+            "Could not create array buffer",
+        ));
     }
     let prop_ref = crate::quickjs_utils::primitives::from_i32(buffer_id as i32);
     set_property2(ctx, &obj_ref, "__buffer_id", &prop_ref, 0)?;
@@ -174,7 +177,10 @@ pub unsafe fn new_array_buffer_copy(
         "typedarrays::new_array_buffer_copy_q",
     );
     if obj_ref.is_exception() {
-        return Err(JsError::new_str("Could not create array buffer"));
+        return Err(crate::quickjs_utils::errors::get_exception_or(
+            ctx,
+            "Could not create array buffer",
+        ));
     }
     Ok(obj_ref)
 }
@@ -207,21 +213,21 @@ pub unsafe fn detach_array_buffer_buffer(
         None
     };
 
-    let v = if let Some(id) = id_opt {
+    // fails for a buffer which was already detached, its id may have been reused by now
+    let (_array_buffer, ptr, len) = get_buffer_view(ctx, array_buffer)?;
+
+    let owned = id_opt.and_then(|id| {
         BUFFERS.with(|rc| {
             let buffers = &mut *rc.borrow_mut();
-            buffers.remove(&id)
+            if buffers.contains_key(&id) {
+                Some(buffers.remove(&id))
+            } else {
+                None
+            }
         })
-    } else {
-        #[cfg(target_pointer_width = "64")]
-        let mut len: usize = 0;
-        #[cfg(target_pointer_width = "32")]
-        let mut len: u32 = 0;
-
-        let ptr = q::JS_GetArrayBuffer(ctx, &mut len, *array_buffer.borrow_value());
-
-        Vec::from_raw_parts(ptr, len as _, len as _)
-    };
+    });
+    // the memory of other buffers was allocated by quickjs, so the bytes are copied before the buffer is detached
+    let v = owned.unwrap_or_else(|| std::slice::from_raw_parts(ptr, len).to_vec());
 
     q::JS_DetachArrayBuffer(ctx, *array_buffer.borrow_value());
 
@@ -251,30 +257,465 @@ pub unsafe fn get_array_buffer_buffer_copy(
 
     log::trace!("get_array_buffer_buffer_copy");
 
-    let id_prop = get_property(ctx, array_buffer, "__buffer_id")?;
-    let id_opt = if id_prop.is_i32() {
-        Some(id_prop.to_i32() as usize)
+    // the bytes of our own buffers are the bytes quickjs sees, a detached buffer fails
+    get_bytes(ctx, array_buffer)
+}
+
+/// copy the bytes of an ArrayBuffer or TypedArray to a Vec
+/// for a TypedArray only the bytes in the view (byteOffset / byteLength) are copied, a detached buffer fails with a TypeError
+/// # Example
+/// ```rust
+/// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+/// use quickjs_runtime::jsutils::Script;
+/// use quickjs_runtime::quickjs_utils::typedarrays::get_bytes_q;
+/// let rt = QuickJsRuntimeBuilder::new().build();
+/// let bytes = rt.loop_realm_sync(None, |_rt, realm| {
+///     let arr = realm.eval(Script::new("bytes.js", "new Uint8Array([1, 2, 3, 4]).subarray(1, 3);")).expect("script failed");
+///     get_bytes_q(realm, &arr).expect("get_bytes failed")
+/// });
+/// assert_eq!(bytes, vec![2, 3]);
+/// ```
+pub fn get_bytes_q(
+    q_ctx: &QuickJsRealmAdapter,
+    buffer: &QuickJsValueAdapter,
+) -> Result<Vec<u8>, JsError> {
+    unsafe { get_bytes(q_ctx.context, buffer) }
+}
+
+/// copy the bytes of an ArrayBuffer or TypedArray to a Vec
+/// for a TypedArray only the bytes in the view (byteOffset / byteLength) are copied, a detached buffer fails with a TypeError
+/// # Safety
+/// please ensure that the relevant QuickjsRealmAdapter is not dropped while using this function
+pub unsafe fn get_bytes(
+    ctx: *mut q::JSContext,
+    buffer: &QuickJsValueAdapter,
+) -> Result<Vec<u8>, JsError> {
+    with_buffer_bytes(ctx, buffer, |bytes| bytes.to_vec())
+}
+
+fn detached_error() -> JsError {
+    JsError::new(
+        "TypeError".to_string(),
+        "ArrayBuffer is detached".to_string(),
+        "".to_string(),
+    )
+}
+
+/// borrow the bytes of an ArrayBuffer or TypedArray without copying them
+/// for a TypedArray only the bytes in the view (byteOffset / byteLength) are passed to the consumer
+pub fn with_buffer_bytes_q<C, R>(
+    q_ctx: &QuickJsRealmAdapter,
+    buffer: &QuickJsValueAdapter,
+    consumer: C,
+) -> Result<R, JsError>
+where
+    C: FnOnce(&[u8]) -> R,
+{
+    unsafe { with_buffer_bytes(q_ctx.context, buffer, consumer) }
+}
+
+/// borrow the bytes of an ArrayBuffer or TypedArray without copying them
+/// for a TypedArray only the bytes in the view (byteOffset / byteLength) are passed to the consumer
+/// # Safety
+/// please ensure that the relevant QuickjsRealmAdapter is not dropped while using this function, the consumer should not call into script
+pub unsafe fn with_buffer_bytes<C, R>(
+    ctx: *mut q::JSContext,
+    buffer: &QuickJsValueAdapter,
+    consumer: C,
+) -> Result<R, JsError>
+where
+    C: FnOnce(&[u8]) -> R,
+{
+    let (_array_buffer, ptr, len) = get_buffer_view(ctx, buffer)?;
+    Ok(consumer(std::slice::from_raw_parts(ptr, len)))
+}
+
+/// borrow the bytes of an ArrayBuffer or TypedArray mutably, e.g. to fill them in place
+/// for a TypedArray only the bytes in the view (byteOffset / byteLength) are passed to the consumer
+pub fn with_buffer_bytes_mut_q<C, R>(
+    q_ctx: &QuickJsRealmAdapter,
+    buffer: &QuickJsValueAdapter,
+    consumer: C,
+) -> Result<R, JsError>
+where
+    C: FnOnce(&mut [u8]) -> R,
+{
+    unsafe { with_buffer_bytes_mut(q_ctx.context, buffer, consumer) }
+}
+
+/// borrow the bytes of an ArrayBuffer or TypedArray mutably, e.g. to fill them in place
+/// for a TypedArray only the bytes in the view (byteOffset / byteLength) are passed to the consumer
+/// # Safety
+/// please ensure that the relevant QuickjsRealmAdapter is not dropped while using this function, the consumer should not call into script
+pub unsafe fn with_buffer_bytes_mut<C, R>(
+    ctx: *mut q::JSContext,
+    buffer: &QuickJsValueAdapter,
+    consumer: C,
+) -> Result<R, JsError>
+where
+    C: FnOnce(&mut [u8]) -> R,
+{
+    let (_array_buffer, ptr, len) = get_buffer_view(ctx, buffer)?;
+    Ok(consumer(std::slice::from_raw_parts_mut(ptr, len)))
+}
+
+/// concatenate the bytes of ArrayBuffers and TypedArrays into a new Uint8Array
+/// for a TypedArray only the bytes in the view are used, the result always has a fresh buffer (also for a single input)
+pub fn concat_q(
+    q_ctx: &QuickJsRealmAdapter,
+    buffers: &[&QuickJsValueAdapter],
+) -> Result<QuickJsValueAdapter, JsError> {
+    unsafe { concat(q_ctx.context, buffers) }
+}
+
+/// concatenate the bytes of ArrayBuffers and TypedArrays into a new Uint8Array
+/// for a TypedArray only the bytes in the view are used, the result always has a fresh buffer (also for a single input)
+/// # Safety
+/// please ensure that the relevant QuickjsRealmAdapter is not dropped while using this function or a result of this function
+pub unsafe fn concat(
+    ctx: *mut q::JSContext,
+    buffers: &[&QuickJsValueAdapter],
+) -> Result<QuickJsValueAdapter, JsError> {
+    let mut views = Vec::with_capacity(buffers.len());
+    for buffer in buffers {
+        views.push(get_buffer_view(ctx, buffer)?);
+    }
+    let mut bytes = Vec::with_capacity(views.iter().map(|(_, _, len)| len).sum());
+    for (_array_buffer, ptr, len) in &views {
+        bytes.extend_from_slice(std::slice::from_raw_parts(*ptr, *len));
+    }
+    drop(views);
+    new_uint8_array(ctx, bytes)
+}
+
+/// copy a range of the bytes of an ArrayBuffer or TypedArray into a new Uint8Array
+/// start and end are byte offsets in the view and are clamped to its length, the result always has a fresh buffer
+pub fn slice_buffer_q(
+    q_ctx: &QuickJsRealmAdapter,
+    buffer: &QuickJsValueAdapter,
+    start: usize,
+    end: usize,
+) -> Result<QuickJsValueAdapter, JsError> {
+    unsafe { slice_buffer(q_ctx.context, buffer, start, end) }
+}
+
+/// copy a range of the bytes of an ArrayBuffer or TypedArray into a new Uint8Array
+/// start and end are byte offsets in the view and are clamped to its length, the result always has a fresh buffer
+/// # Safety
+/// please ensure that the relevant QuickjsRealmAdapter is not dropped while using this function or a result of this function
+pub unsafe fn slice_buffer(
+    ctx: *mut q::JSContext,
+    buffer: &QuickJsValueAdapter,
+    start: usize,
+    end: usize,
+) -> Result<QuickJsValueAdapter, JsError> {
+    let bytes = with_buffer_bytes(ctx, buffer, |bytes| {
+        let end = end.min(bytes.len());
+        let start = start.min(end);
+        bytes[start..end].to_vec()
+    })?;
+    new_uint8_array(ctx, bytes)
+}
+
+/// get a ptr to the first byte in the view of an ArrayBuffer or TypedArray and the length of the view in bytes
+/// the returned ArrayBuffer should be kept alive while using the ptr
+unsafe fn get_buffer_view(
+    ctx: *mut q::JSContext,
+    buffer: &QuickJsValueAdapter,
+) -> Result<(QuickJsValueAdapter, *mut u8, usize), JsError> {
+    #[cfg(target_pointer_width = "64")]
+    let (mut offset, mut length, mut bytes_per_element, mut len): (usize, usize, usize, usize) =
+        (0, 0, 0, 0);
+    #[cfg(target_pointer_width = "32")]
+    let (mut offset, mut length, mut bytes_per_element, mut len): (u32, u32, u32, u32) =
+        (0, 0, 0, 0);
+
+    let is_buffer = is_array_buffer(ctx, buffer);
+    let array_buffer = if is_buffer {
+        buffer.dup()
+    } else if is_typed_array(ctx, buffer) {
+        let raw = q::JS_GetTypedArrayBuffer(
+            ctx,
+            *buffer.borrow_value(),
+            &mut offset,
+            &mut length,
+            &mut bytes_per_element,
+        );
+        let array_buffer =
+            QuickJsValueAdapter::new(ctx, raw, false, true, "typedarrays::get_buffer_view");
+        if array_buffer.is_exception() {
+            return Err(QuickJsRealmAdapter::get_exception(ctx)
+                .unwrap_or_else(|| JsError::new_str("could not get buffer of TypedArray")));
+        }
+        array_buffer
     } else {
-        None
+        return Err(JsError::new_str(
+            "value is not an ArrayBuffer or TypedArray",
+        ));
     };
 
-    if let Some(id) = id_opt {
-        let b = BUFFERS.with(|rc| {
-            let buffers = &mut *rc.borrow_mut();
-            buffers.get(&id).expect("invalid buffer state").clone()
-        });
-        Ok(b)
+    let ptr = q::JS_GetArrayBuffer(ctx, &mut len, *array_buffer.borrow_value());
+    if ptr.is_null() {
+        // quickjs throws a TypeError for a detached buffer
+        return Err(QuickJsRealmAdapter::get_exception(ctx).unwrap_or_else(detached_error));
+    }
+
+    let (start, end) = if is_buffer {
+        (0, len as usize)
+    } else {
+        (offset as usize, offset as usize + length as usize)
+    };
+    if end > len as usize {
+        return Err(JsError::new_str("TypedArray is out of bounds"));
+    }
+    Ok((array_buffer, ptr.add(start), end - start))
+}
+
+/// the policy for converting values which can not be represented in an integer TypedArray (NaN, Infinity and out of range values)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NonFinitePolicy {
+    /// fail with a RangeError
+    Error,
+    /// NaN becomes 0, other values are clamped to the min or max value of the integer type
+    Clamp,
+    /// convert like JS does when setting a value in a TypedArray (ToInt32 / ToUint8 etc.), NaN and Infinity become 0 and other values wrap around
+    WrapLikeJs,
+}
+
+/// a rust numeric type which corresponds to the element type of a TypedArray
+pub trait TypedArrayElement: Copy + 'static {
+    /// the name of the TypedArray constructor, e.g. "Float64Array"
+    const CONSTRUCTOR: &'static str;
+    /// convert a number to this type according to a NonFinitePolicy
+    fn from_f64(value: f64, policy: NonFinitePolicy) -> Result<Self, JsError>;
+    fn to_f64(self) -> f64;
+}
+
+fn convert_to_int(
+    value: f64,
+    bits: u32,
+    signed: bool,
+    policy: NonFinitePolicy,
+) -> Result<i64, JsError> {
+    let (min, max) = if signed {
+        (
+            -(2_f64.powi(bits as i32 - 1)),
+            2_f64.powi(bits as i32 - 1) - 1.0,
+        )
+    } else {
+        (0.0, 2_f64.powi(bits as i32) - 1.0)
+    };
+    match policy {
+        NonFinitePolicy::Error => {
+            let truncated = value.trunc();
+            if value.is_finite() && truncated >= min && truncated <= max {
+                Ok(truncated as i64)
+            } else {
+                Err(JsError::new(
+                    "RangeError".to_string(),
+                    format!("{value} can not be converted to a {bits} bit integer"),
+                    "".to_string(),
+                ))
+            }
+        }
+        NonFinitePolicy::Clamp => {
+            if value.is_nan() {
+                Ok(0)
+            } else {
+                Ok(value.trunc().clamp(min, max) as i64)
+            }
+        }
+        NonFinitePolicy::WrapLikeJs => {
+            if !value.is_finite() {
+                return Ok(0);
+            }
+            let modulo = 2_f64.powi(bits as i32);
+            let wrapped = value.trunc().rem_euclid(modulo);
+            if signed && wrapped > max {
+                Ok((wrapped - modulo) as i64)
+            } else {
+                Ok(wrapped as i64)
+            }
+        }
+    }
+}
+
+macro_rules! impl_int_typed_array_element {
+    ($t:ty, $constructor:literal, $bits:literal, $signed:literal) => {
+        impl TypedArrayElement for $t {
+            const CONSTRUCTOR: &'static str = $constructor;
+            fn from_f64(value: f64, policy: NonFinitePolicy) -> Result<Self, JsError> {
+                convert_to_int(value, $bits, $signed, policy).map(|v| v as $t)
+            }
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+        }
+    };
+}
+
+impl_int_typed_array_element!(i8, "Int8Array", 8, true);
+impl_int_typed_array_element!(u8, "Uint8Array", 8, false);
+impl_int_typed_array_element!(i16, "Int16Array", 16, true);
+impl_int_typed_array_element!(u16, "Uint16Array", 16, false);
+impl_int_typed_array_element!(i32, "Int32Array", 32, true);
+impl_int_typed_array_element!(u32, "Uint32Array", 32, false);
+
+impl TypedArrayElement for f32 {
+    const CONSTRUCTOR: &'static str = "Float32Array";
+    fn from_f64(value: f64, _policy: NonFinitePolicy) -> Result<Self, JsError> {
+        Ok(value as f32)
+    }
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl TypedArrayElement for f64 {
+    const CONSTRUCTOR: &'static str = "Float64Array";
+    fn from_f64(value: f64, _policy: NonFinitePolicy) -> Result<Self, JsError> {
+        Ok(value)
+    }
+    fn to_f64(self) -> f64 {
+        self
+    }
+}
+
+/// create a new Float64Array and fill it with the values of an iterator
+/// # Example
+/// ```rust
+/// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+/// use quickjs_runtime::quickjs_utils::typedarrays::{is_typed_array_q, new_float64_array_from_iter_q};
+/// let rt = QuickJsRuntimeBuilder::new().build();
+/// rt.exe_rt_task_in_event_loop(|q_js_rt| {
+///     let realm = q_js_rt.get_main_realm();
+///     let arr = new_float64_array_from_iter_q(realm, (0..4).map(|i| i as f64 * 0.5)).expect("could not create array");
+///     assert!(is_typed_array_q(realm, &arr));
+/// });
+/// ```
+pub fn new_float64_array_from_iter_q<N, I>(
+    q_ctx: &QuickJsRealmAdapter,
+    iter: I,
+) -> Result<QuickJsValueAdapter, JsError>
+where
+    N: Into<f64>,
+    I: IntoIterator<Item = N>,
+{
+    new_typed_array_from_iter_q::<f64, N, I>(q_ctx, iter, NonFinitePolicy::Error)
+}
+
+/// create a new TypedArray (e.g. an Int32Array for T = i32) and fill it with the values of an iterator
+/// when the iterator has an exact size the values are written directly into the buffer of the TypedArray, else they are collected first
+/// values which can not be represented by T are converted according to the policy
+pub fn new_typed_array_from_iter_q<T, N, I>(
+    q_ctx: &QuickJsRealmAdapter,
+    iter: I,
+    policy: NonFinitePolicy,
+) -> Result<QuickJsValueAdapter, JsError>
+where
+    T: TypedArrayElement,
+    N: Into<f64>,
+    I: IntoIterator<Item = N>,
+{
+    unsafe { new_typed_array_from_iter::<T, N, I>(q_ctx.context, iter, policy) }
+}
+
+/// create a new TypedArray (e.g. an Int32Array for T = i32) and fill it with the values of an iterator
+/// # Safety
+/// please ensure that the relevant QuickjsRealmAdapter is not dropped while using this function or a result of this function
+pub unsafe fn new_typed_array_from_iter<T, N, I>(
+    ctx: *mut q::JSContext,
+    iter: I,
+    policy: NonFinitePolicy,
+) -> Result<QuickJsValueAdapter, JsError>
+where
+    T: TypedArrayElement,
+    N: Into<f64>,
+    I: IntoIterator<Item = N>,
+{
+    let iter = iter.into_iter();
+    let (lower, upper) = iter.size_hint();
+    if upper == Some(lower) {
+        let typed_array = new_typed_array::<T>(ctx, lower)?;
+        let (_array_buffer, ptr, _len) = get_buffer_view(ctx, &typed_array)?;
+        let ptr = ptr as *mut T;
+        for (index, value) in iter.take(lower).enumerate() {
+            ptr.add(index)
+                .write_unaligned(T::from_f64(value.into(), policy)?);
+        }
+        Ok(typed_array)
     } else {
-        #[cfg(target_pointer_width = "64")]
-        let mut len: usize = 0;
-        #[cfg(target_pointer_width = "32")]
-        let mut len: u32 = 0;
+        // growable fallback for iterators without an exact size
+        let values = iter
+            .map(|value| T::from_f64(value.into(), policy))
+            .collect::<Result<Vec<T>, JsError>>()?;
+        let typed_array = new_typed_array::<T>(ctx, values.len())?;
+        let (_array_buffer, ptr, _len) = get_buffer_view(ctx, &typed_array)?;
+        std::ptr::copy_nonoverlapping(values.as_ptr(), ptr as *mut T, values.len());
+        Ok(typed_array)
+    }
+}
 
-        let ptr = q::JS_GetArrayBuffer(ctx, &mut len, *array_buffer.borrow_value());
+unsafe fn new_typed_array<T: TypedArrayElement>(
+    ctx: *mut q::JSContext,
+    length: usize,
+) -> Result<QuickJsValueAdapter, JsError> {
+    let constructor = get_constructor(ctx, T::CONSTRUCTOR)?;
+    let length_ref = crate::quickjs_utils::primitives::from_f64(length as f64);
+    construct_object(ctx, &constructor, &[&length_ref])
+}
 
-        let slice = std::slice::from_raw_parts(ptr, len as _);
+/// copy the values of a TypedArray (of any numeric type) to a Vec
+/// values which can not be represented by T are converted according to the policy
+pub fn to_vec_q<T: TypedArrayElement>(
+    q_ctx: &QuickJsRealmAdapter,
+    typed_array: &QuickJsValueAdapter,
+    policy: NonFinitePolicy,
+) -> Result<Vec<T>, JsError> {
+    unsafe { to_vec::<T>(q_ctx.context, typed_array, policy) }
+}
 
-        Ok(slice.to_vec())
+/// copy the values of a TypedArray (of any numeric type) to a Vec
+/// values which can not be represented by T are converted according to the policy
+/// # Safety
+/// please ensure that the relevant QuickjsRealmAdapter is not dropped while using this function or a result of this function
+pub unsafe fn to_vec<T: TypedArrayElement>(
+    ctx: *mut q::JSContext,
+    typed_array: &QuickJsValueAdapter,
+    policy: NonFinitePolicy,
+) -> Result<Vec<T>, JsError> {
+    if !is_typed_array(ctx, typed_array) {
+        return Err(JsError::new_str("value is not a TypedArray"));
+    }
+    macro_rules! read_as {
+        ($source:ty) => {{
+            let (_array_buffer, ptr, len) = get_buffer_view(ctx, typed_array)?;
+            let ptr = ptr as *const $source;
+            (0..len / std::mem::size_of::<$source>())
+                .map(|index| T::from_f64(ptr.add(index).read_unaligned().to_f64(), policy))
+                .collect()
+        }};
+    }
+    if is_instance_of_by_name(ctx, typed_array, "Float64Array")? {
+        read_as!(f64)
+    } else if is_instance_of_by_name(ctx, typed_array, "Float32Array")? {
+        read_as!(f32)
+    } else if is_instance_of_by_name(ctx, typed_array, "Int32Array")? {
+        read_as!(i32)
+    } else if is_instance_of_by_name(ctx, typed_array, "Uint32Array")? {
+        read_as!(u32)
+    } else if is_instance_of_by_name(ctx, typed_array, "Int16Array")? {
+        read_as!(i16)
+    } else if is_instance_of_by_name(ctx, typed_array, "Uint16Array")? {
+        read_as!(u16)
+    } else if is_instance_of_by_name(ctx, typed_array, "Int8Array")? {
+        read_as!(i8)
+    } else if is_instance_of_by_name(ctx, typed_array, "Uint8Array")?
+        || is_instance_of_by_name(ctx, typed_array, "Uint8ClampedArray")?
+    {
+        read_as!(u8)
+    } else {
+        Err(JsError::new_str("unsupported TypedArray type"))
     }
 }
 
@@ -367,15 +808,18 @@ unsafe extern "C" fn free_func(
 pub mod tests {
     use crate::jsutils::{JsError, Script};
     use crate::quickjs_utils::typedarrays::{
-        detach_array_buffer_buffer_q, get_array_buffer_buffer_copy_q, get_array_buffer_q,
-        is_array_buffer_q, is_typed_array_q, new_array_buffer_q, new_uint8_array_copy_q,
-        new_uint8_array_q,
+        concat_q, detach_array_buffer_buffer_q, get_array_buffer_buffer_copy_q, get_array_buffer_q,
+        get_bytes_q, is_array_buffer_q, is_typed_array_q, new_array_buffer_q,
+        new_float64_array_from_iter_q, new_typed_array_from_iter_q, new_uint8_array_copy_q,
+        new_uint8_array_q, slice_buffer_q, to_vec_q, with_buffer_bytes_q, NonFinitePolicy,
     };
     use crate::values::{JsValueFacade, TypedArrayType};
 
     use crate::facades::tests::init_test_rt;
     use crate::quickjs_utils::objects::set_property_q;
     use crate::quickjs_utils::{get_global_q, new_undefined_ref};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
     use std::thread;
     use std::time::Duration;
 
@@ -475,7 +919,7 @@ pub mod tests {
                     assert!(is_typed_array_q(realm, &arr));
 
                     realm
-                        .invoke_function_by_name(&[], "testTyped", &[arr.clone()])
+                        .invoke_function_by_name(&[], "testTyped", &[arr.dup()])
                         .expect("testTyped failed");
 
                     let ab = get_array_buffer_q(realm, &arr).expect("did not get buffer");
@@ -493,7 +937,7 @@ pub mod tests {
 
                     // this still works but all values should be undefined..
                     realm
-                        .invoke_function_by_name(&[], "testTyped", &[arr.clone()])
+                        .invoke_function_by_name(&[], "testTyped", &[arr.dup()])
                         .expect("script failed");
 
                     log::trace!("ab dropped");
@@ -548,4 +992,229 @@ pub mod tests {
 
         thread::sleep(Duration::from_secs(1));
     }
+
+    #[test]
+    fn test_typed_array_from_iter() {
+        let rt = init_test_rt();
+        rt.loop_realm_sync(None, |_rt, realm| {
+            // exact size iterator
+            let arr = new_float64_array_from_iter_q(realm, (0..4).map(|i| i as f64 * 0.5))
+                .expect("could not create array");
+            let global = get_global_q(realm);
+            set_property_q(realm, &global, "f64Arr", &arr).expect("could not set prop");
+            let res = realm
+                .eval(Script::new(
+                    "test_from_iter.js",
+                    "`${f64Arr.constructor.name}:${f64Arr.join(',')}`",
+                ))
+                .expect("script failed");
+            assert_eq!(
+                res.to_string().expect("not a string"),
+                "Float64Array:0,0.5,1,1.5"
+            );
+
+            // growable fallback
+            let arr = new_typed_array_from_iter_q::<i16, _, _>(
+                realm,
+                (0..10).filter(|i| i % 3 == 0),
+                NonFinitePolicy::Error,
+            )
+            .expect("could not create array");
+            let values =
+                to_vec_q::<i16>(realm, &arr, NonFinitePolicy::Error).expect("could not read array");
+            assert_eq!(values, vec![0, 3, 6, 9]);
+
+            // policies
+            let non_finite = [f64::NAN, f64::INFINITY, -300.7, 300.2];
+            assert!(new_typed_array_from_iter_q::<u8, _, _>(
+                realm,
+                non_finite,
+                NonFinitePolicy::Error
+            )
+            .is_err());
+            let arr =
+                new_typed_array_from_iter_q::<u8, _, _>(realm, non_finite, NonFinitePolicy::Clamp)
+                    .expect("could not create array");
+            assert_eq!(
+                to_vec_q::<u8>(realm, &arr, NonFinitePolicy::Error).expect("could not read"),
+                vec![0, 255, 0, 255]
+            );
+            let arr = new_float64_array_from_iter_q(realm, non_finite).expect("could not create");
+            assert_eq!(
+                to_vec_q::<i8>(realm, &arr, NonFinitePolicy::WrapLikeJs).expect("could not read"),
+                vec![0, 0, -44, 44]
+            );
+            assert!(to_vec_q::<i8>(realm, &arr, NonFinitePolicy::Error).is_err());
+        });
+    }
+
+    #[test]
+    fn test_wrap_like_js() {
+        let rt = init_test_rt();
+        let mut rng = StdRng::seed_from_u64(212);
+        for _ in 0..20 {
+            let values: Vec<f64> = (0..64)
+                .map(|_| match rng.gen_range(0..6) {
+                    0 => f64::NAN,
+                    1 => {
+                        if rng.gen_bool(0.5) {
+                            f64::INFINITY
+                        } else {
+                            f64::NEG_INFINITY
+                        }
+                    }
+                    2 => rng.gen_range(-1000.0..1000.0),
+                    3 => rng.gen_range(-1e12..1e12),
+                    4 => rng.gen_range(-1e300..1e300),
+                    _ => rng.gen_range(-70000_i64..70000) as f64,
+                })
+                .collect();
+
+            rt.loop_realm_sync(None, move |_rt, realm| {
+                let arr = new_float64_array_from_iter_q(realm, values.iter().copied())
+                    .expect("could not create array");
+                let global = get_global_q(realm);
+                set_property_q(realm, &global, "wrapSource", &arr).expect("could not set prop");
+
+                let js_int32 = realm
+                    .eval(Script::new("test_wrap.js", "Int32Array.from(wrapSource);"))
+                    .expect("script failed");
+                let js_uint8 = realm
+                    .eval(Script::new("test_wrap.js", "Uint8Array.from(wrapSource);"))
+                    .expect("script failed");
+                let js_int16 = realm
+                    .eval(Script::new("test_wrap.js", "Int16Array.from(wrapSource);"))
+                    .expect("script failed");
+
+                // rust to js
+                let rs_int32 = new_typed_array_from_iter_q::<i32, _, _>(
+                    realm,
+                    values.iter().copied(),
+                    NonFinitePolicy::WrapLikeJs,
+                )
+                .expect("could not create array");
+
+                let policy = NonFinitePolicy::WrapLikeJs;
+                assert_eq!(
+                    to_vec_q::<i32>(realm, &arr, policy).expect("could not read"),
+                    to_vec_q::<i32>(realm, &js_int32, policy).expect("could not read"),
+                );
+                assert_eq!(
+                    to_vec_q::<i32>(realm, &rs_int32, policy).expect("could not read"),
+                    to_vec_q::<i32>(realm, &js_int32, policy).expect("could not read"),
+                );
+                assert_eq!(
+                    to_vec_q::<u8>(realm, &arr, policy).expect("could not read"),
+                    to_vec_q::<u8>(realm, &js_uint8, policy).expect("could not read"),
+                );
+                assert_eq!(
+                    to_vec_q::<i16>(realm, &arr, policy).expect("could not read"),
+                    to_vec_q::<i16>(realm, &js_int16, policy).expect("could not read"),
+                );
+            });
+        }
+    }
+
+    #[test]
+    fn test_concat_and_slice() {
+        let rt = init_test_rt();
+        rt.loop_realm_sync(None, |_rt, realm| {
+            let bytes = |value: &crate::quickjsvalueadapter::QuickJsValueAdapter| {
+                with_buffer_bytes_q(realm, value, |b| b.to_vec()).expect("could not read")
+            };
+            let parts = realm
+                .eval(Script::new(
+                    "test_concat.js",
+                    "const buf = new Uint8Array([0, 1, 2, 3, 4, 5, 6, 7]).buffer; [new Uint8Array(buf, 2, 3), new Uint16Array(buf, 6, 1), new Uint8Array([9]).buffer];",
+                ))
+                .expect("script failed");
+            let elements: Vec<_> = (0..3)
+                .map(|i| crate::quickjs_utils::arrays::get_element_q(realm, &parts, i).unwrap())
+                .collect();
+
+            // views with offsets only contribute their own bytes
+            let joined = concat_q(realm, &elements.iter().collect::<Vec<_>>())
+                .expect("concat failed");
+            assert_eq!(bytes(&joined), vec![2, 3, 4, 6, 7, 9]);
+            let empty = concat_q(realm, &[]).expect("concat failed");
+            assert!(bytes(&empty).is_empty());
+
+            // the result has a fresh buffer
+            let single = concat_q(realm, &[&elements[0]]).expect("concat failed");
+            let global = get_global_q(realm);
+            set_property_q(realm, &global, "single", &single).expect("could not set prop");
+            set_property_q(realm, &global, "view", &elements[0]).expect("could not set prop");
+            let res = realm
+                .eval(Script::new(
+                    "test_concat.js",
+                    "single[0] = 99; view[0] === 2 && single.byteOffset === 0 && single.buffer !== view.buffer",
+                ))
+                .expect("script failed");
+            assert!(res.to_bool());
+
+            let sliced = slice_buffer_q(realm, &elements[0], 1, 10).expect("slice failed");
+            assert_eq!(bytes(&sliced), vec![3, 4]);
+            let sliced = slice_buffer_q(realm, &elements[0], 2, 1).expect("slice failed");
+            assert!(bytes(&sliced).is_empty());
+            assert!(concat_q(realm, &[&global]).is_err());
+        });
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let rt = init_test_rt();
+        let original: Vec<u8> = (0..(10 * 1024 * 1024)).map(|i| (i % 251) as u8).collect();
+
+        rt.eval_sync(
+            None,
+            Script::new(
+                "test_bytes_round_trip.js",
+                "globalThis.echoBytes = (arr) => { if (!(arr instanceof Uint8Array) || arr[1000] !== 1000 % 251) { throw Error('unexpected content'); } return arr.slice(); };",
+            ),
+        )
+        .expect("script failed");
+
+        let res = rt
+            .invoke_function_sync(
+                None,
+                &[],
+                "echoBytes",
+                vec![JsValueFacade::new_uint8_array(original.clone())],
+            )
+            .expect("echoBytes failed");
+        assert!(res.is_typed_array());
+        assert_eq!(res.get_bytes().len(), original.len());
+        assert!(res.into_bytes().expect("not a typed array") == original);
+
+        rt.loop_realm_sync(None, |_rt, realm| {
+            let arr = realm
+                .eval(Script::new(
+                    "test_bytes_view.js",
+                    "new Uint8Array([1, 2, 3, 4, 5]).subarray(1, 4);",
+                ))
+                .expect("script failed");
+            assert_eq!(
+                get_bytes_q(realm, &arr).expect("get_bytes failed"),
+                vec![2, 3, 4]
+            );
+            let facade = realm.to_js_value_facade(&arr).expect("conversion failed");
+            assert_eq!(facade.get_bytes(), &[2, 3, 4]);
+
+            let arr = realm
+                .create_typed_array_uint8(vec![7, 8, 9])
+                .expect("create failed");
+            let buffer = get_array_buffer_q(realm, &arr).expect("no buffer");
+            assert_eq!(
+                detach_array_buffer_buffer_q(realm, &buffer).expect("detach failed"),
+                vec![7, 8, 9]
+            );
+
+            // a detached buffer fails instead of reading freed memory
+            let err = detach_array_buffer_buffer_q(realm, &buffer).expect_err("detached twice");
+            assert_eq!(err.get_name(), "TypeError");
+            assert!(get_bytes_q(realm, &arr).is_err());
+            assert!(get_array_buffer_buffer_copy_q(realm, &buffer).is_err());
+            assert!(realm.to_js_value_facade(&arr).is_err());
+        });
+    }
 }
@@ -0,0 +1,279 @@
+//! watching a property path in an object for changes made by script
+//!
+//! every object along the path gets an accessor for the next key of the path, assignments to the watched key or to any
+//! intermediate key are intercepted, when an intermediate object is replaced the accessors are moved to the new object
+//!
+//! # Limitations
+//! the accessors are configurable, so when script replaces one of them with `Object.defineProperty` or removes it with `delete`
+//! changes to that key are no longer seen until an object higher up in the path is replaced
+//! non-configurable properties can not be watched
+
+use crate::jsutils::{JsError, Script};
+use crate::quickjs_utils::{arrays, functions};
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsvalueadapter::QuickJsValueAdapter;
+
+/// options for watching a property path
+#[derive(Clone, Debug)]
+pub struct WatchOptions {
+    /// emit the current value when the watch starts
+    pub emit_initial: bool,
+    /// do not emit when the value did not change (compared with Object.is), e.g. when the same value is assigned again
+    pub skip_unchanged: bool,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        Self {
+            emit_initial: false,
+            skip_unchanged: true,
+        }
+    }
+}
+
+const WATCH_FUNCTION: &str = r#"
+(function(root, path, notify, emitInitial, skipUnchanged) {
+    'use strict';
+    const attached = [];
+    const isObject = (obj) => obj !== null && (typeof obj === 'object' || typeof obj === 'function');
+    const readLeaf = () => {
+        let obj = root;
+        for (const key of path) {
+            if (!isObject(obj)) {
+                return undefined;
+            }
+            obj = obj[key];
+        }
+        return obj;
+    };
+    let last = readLeaf();
+    const changed = () => {
+        const value = readLeaf();
+        if (skipUnchanged && Object.is(value, last)) {
+            return;
+        }
+        last = value;
+        notify(value);
+    };
+    const attach = (level, obj) => {
+        const key = path[level];
+        const desc = Object.getOwnPropertyDescriptor(obj, key);
+        if (desc && !desc.configurable) {
+            return false;
+        }
+        let own = !!desc;
+        let value = desc ? desc.value : undefined;
+        const get = function() {
+            if (desc && desc.get) {
+                return desc.get.call(this);
+            }
+            if (own) {
+                return value;
+            }
+            const proto = Object.getPrototypeOf(obj);
+            return proto === null ? undefined : proto[key];
+        };
+        const set = function(v) {
+            if (desc && desc.set) {
+                desc.set.call(this, v);
+            } else if (desc && (desc.get || !desc.writable)) {
+                return;
+            } else {
+                value = v;
+                own = true;
+            }
+            onSet(level);
+        };
+        Object.defineProperty(obj, key, {get, set, enumerable: desc ? desc.enumerable : true, configurable: true});
+        attached.push({
+            read: () => get.call(obj),
+            detach: () => {
+                const current = Object.getOwnPropertyDescriptor(obj, key);
+                if (!current || current.get !== get) {
+                    // replaced or deleted by script, leave it alone
+                    return;
+                }
+                if (desc && (desc.get || desc.set)) {
+                    Object.defineProperty(obj, key, desc);
+                } else if (desc) {
+                    Object.defineProperty(obj, key, Object.assign({}, desc, {value}));
+                } else if (own) {
+                    Object.defineProperty(obj, key, {value, writable: true, enumerable: true, configurable: true});
+                } else {
+                    delete obj[key];
+                }
+            }
+        });
+        return true;
+    };
+    const attachFrom = (level) => {
+        let obj = level === 0 ? root : attached[level - 1].read();
+        for (let l = level; l < path.length && isObject(obj); l++) {
+            if (!attach(l, obj)) {
+                // non-configurable
+                return false;
+            }
+            obj = attached[l].read();
+        }
+        return true;
+    };
+    const detachFrom = (level) => {
+        while (attached.length > level) {
+            attached.pop().detach();
+        }
+    };
+    const onSet = (level) => {
+        if (level < path.length - 1) {
+            detachFrom(level + 1);
+            attachFrom(level + 1);
+        }
+        changed();
+    };
+    if (!attachFrom(0)) {
+        const failed = path.slice(0, attached.length + 1).join('.');
+        detachFrom(0);
+        throw new TypeError('can not watch non-configurable property ' + failed);
+    }
+    if (emitInitial) {
+        notify(last);
+    }
+    return () => detachFrom(0);
+});
+"#;
+
+/// watch a property path in an object, notify is called with the new value when script changes the value at the path
+///
+/// returns a function which stops watching and restores the original properties when called
+/// # Example
+/// ```rust
+/// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+/// use quickjs_runtime::jsutils::Script;
+/// use quickjs_runtime::quickjs_utils::watch::{watch_q, WatchOptions};
+/// use quickjs_runtime::quickjs_utils::functions;
+/// let rt = QuickJsRuntimeBuilder::new().build();
+/// rt.exe_rt_task_in_event_loop(|q_js_rt| {
+///     let realm = q_js_rt.get_main_realm();
+///     let state = realm.eval(Script::new("watch.js", "globalThis.state = {user: {theme: 'light'}}; state;")).expect("script failed");
+///     let unwatch = watch_q(realm, &state, &["user", "theme"], &WatchOptions::default(), |_realm, value| {
+///         println!("theme is now {}", value.to_string().unwrap_or_default());
+///     }).expect("watch failed");
+///     realm.eval(Script::new("watch.js", "state.user.theme = 'dark';")).expect("script failed");
+///     functions::call_function_q(realm, &unwatch, &[], None).expect("unwatch failed");
+/// });
+/// ```
+pub fn watch_q<N>(
+    realm: &QuickJsRealmAdapter,
+    obj: &QuickJsValueAdapter,
+    path: &[&str],
+    options: &WatchOptions,
+    notify: N,
+) -> Result<QuickJsValueAdapter, JsError>
+where
+    N: Fn(&QuickJsRealmAdapter, &QuickJsValueAdapter) + 'static,
+{
+    if path.is_empty() {
+        return Err(JsError::new_str("can not watch an empty path"));
+    }
+    let watch_function = realm.eval(Script::new("quickjs_watch.js", WATCH_FUNCTION))?;
+
+    let path_arr = arrays::create_array_q(realm)?;
+    for (index, key) in (0_u32..).zip(path.iter()) {
+        arrays::set_element_q(realm, &path_arr, index, &realm.create_string(key)?)?;
+    }
+    let notify_function = functions::new_function_q(
+        realm,
+        "notify",
+        move |realm, _this, args| {
+            let value = match args.first() {
+                Some(value) => value.dup(),
+                None => realm.create_undefined()?,
+            };
+            notify(realm, &value);
+            realm.create_undefined()
+        },
+        1,
+    )?;
+
+    functions::call_function_q(
+        realm,
+        &watch_function,
+        &[
+            obj.dup(),
+            path_arr,
+            notify_function,
+            realm.create_boolean(options.emit_initial)?,
+            realm.create_boolean(options.skip_unchanged)?,
+        ],
+        None,
+    )
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::facades::tests::init_test_rt;
+    use crate::jsutils::Script;
+    use crate::quickjs_utils::watch::WatchOptions;
+    use crate::values::{JsValueFacade, JsWatch};
+    use futures::executor::block_on;
+    use futures::StreamExt;
+
+    fn next(watch: &mut JsWatch) -> String {
+        block_on(watch.next())
+            .expect("no value")
+            .get_str()
+            .to_string()
+    }
+
+    #[test]
+    fn test_watch() {
+        let rt = init_test_rt();
+        let eval = |code: &str| {
+            rt.eval_sync(None, Script::new("test_watch.js", code))
+                .expect("script failed")
+        };
+        let state =
+            match eval("globalThis.watchState = {user: {settings: {theme: 'light'}}}; watchState;")
+            {
+                JsValueFacade::JsObject { cached_object } => cached_object,
+                _ => panic!("not an object"),
+            };
+        let options = WatchOptions {
+            emit_initial: true,
+            ..Default::default()
+        };
+        let mut watch = block_on(rt.watch(&state, &["user", "settings", "theme"], options))
+            .expect("watch failed");
+        assert_eq!(next(&mut watch), "light");
+
+        eval("watchState.user.settings.theme = 'dark';");
+        assert_eq!(next(&mut watch), "dark");
+
+        // the same value is not emitted again
+        eval("watchState.user.settings.theme = 'dark';");
+        assert!(watch.try_next().is_none());
+
+        // replacing an intermediate object moves the watcher to the new object
+        eval("globalThis.oldSettings = watchState.user.settings; watchState.user = {settings: {theme: 'blue'}};");
+        assert_eq!(next(&mut watch), "blue");
+        eval("oldSettings.theme = 'red';");
+        assert!(watch.try_next().is_none());
+        eval("watchState.user.settings.theme = 'green';");
+        assert_eq!(next(&mut watch), "green");
+
+        // defineProperty bypasses the watcher until an object higher up in the path is replaced
+        eval("Object.defineProperty(watchState.user.settings, 'theme', {value: 'pink', writable: true, enumerable: true, configurable: true}); watchState.user.settings.theme = 'gray';");
+        assert!(watch.try_next().is_none());
+        eval("watchState.user.settings = {theme: 'white'};");
+        assert_eq!(next(&mut watch), "white");
+        eval("watchState.user.settings.theme = 'black';");
+        assert_eq!(next(&mut watch), "black");
+
+        // unwatch restores plain data properties
+        watch.unwatch();
+        let res = eval("watchState.user.settings.theme = 'yellow'; let d = Object.getOwnPropertyDescriptor(watchState.user.settings, 'theme'); `${d.value}:${d.writable}:${typeof d.get}:${JSON.stringify(watchState)}`;");
+        assert_eq!(
+            res.get_str(),
+            "yellow:true:undefined:{\"user\":{\"settings\":{\"theme\":\"yellow\"}}}"
+        );
+    }
+}
@@ -1,20 +1,43 @@
 use crate::facades::QuickjsRuntimeFacadeInner;
+use crate::quickjs_utils::conversion::{self, UndefinedPolicy};
+use crate::quickjs_utils::interrupthandler::{self, ExecutionDepthGuard, ModuleEvalScope};
+use crate::quickjs_utils::modules::RetryPlaceholder;
 use crate::quickjs_utils::objects::construct_object;
 use crate::quickjs_utils::primitives::{from_bool, from_f64, from_i32, from_string_q};
 use crate::quickjs_utils::typedarrays::{
-    detach_array_buffer_buffer_q, get_array_buffer_buffer_copy_q, get_array_buffer_q,
-    new_uint8_array_copy_q, new_uint8_array_q,
+    detach_array_buffer_buffer_q, get_array_buffer_q, get_bytes_q, new_uint8_array_copy_q,
+    new_uint8_array_q,
+};
+use crate::quickjs_utils::{
+    arrays, codecs, compile, dates, errors, functions, get_global_q, json, modules, new_null_ref,
+    objects, structuredclone,
 };
-use crate::quickjs_utils::{arrays, errors, functions, get_global_q, json, new_null_ref, objects};
 use crate::quickjsruntimeadapter::{make_cstring, QuickJsRuntimeAdapter};
+use crate::quickjsvalueadapter;
 use crate::quickjsvalueadapter::{QuickJsValueAdapter, TAG_EXCEPTION};
 use crate::reflection::eventtarget::dispatch_event;
 use crate::reflection::eventtarget::dispatch_static_event;
 use crate::reflection::{new_instance, new_instance3, Proxy};
 use hirofa_utils::auto_id_map::AutoIdMap;
-
+use hirofa_utils::eventloop::EventLoop;
+
+#[cfg(feature = "console")]
+use crate::features::console::ConsoleState;
+use crate::features::coverage::{self, CoverageReport};
+use crate::features::memorypressure::RealmMemoryPressure;
+use crate::features::random::{self, RandomState};
+use crate::features::realms::RealmFamily;
+use crate::jsutils::compileaudit::{self, CompileOrigin};
+use crate::jsutils::debugdump::{self, DebugRecords};
 use crate::jsutils::jsproxies::{JsProxy, JsProxyInstanceId};
-use crate::jsutils::{JsError, JsValueType, Script};
+use crate::jsutils::memoize::{MemoCache, ProxyTracking};
+use crate::jsutils::modulecache::{self, ModuleCacheState};
+use crate::jsutils::modulegraph::ModuleEdge;
+use crate::jsutils::realmhandle::RealmHandleState;
+use crate::jsutils::suspend::TimerSchedule;
+use crate::jsutils::taskscope::{self, ScopedTask};
+use crate::jsutils::timers;
+use crate::jsutils::{JsError, JsValueType, Script, ScriptTemplate};
 use crate::quickjs_utils::promises::QuickJsPromiseAdapter;
 use crate::values::{
     CachedJsArrayRef, CachedJsFunctionRef, CachedJsObjectRef, CachedJsPromiseRef, JsValueFacade,
@@ -22,13 +45,14 @@ use crate::values::{
 };
 use libquickjs_sys as q;
 use serde_json::Value;
-use std::cell::RefCell;
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
 use std::ffi::CString;
 use std::future::Future;
 use std::i32;
 use std::os::raw::c_void;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Weak};
 
 use crate::jsutils::promises::new_resolving_promise;
@@ -60,6 +84,29 @@ type ProxyStaticEventListenerMaps = HashMap<
     >,
 >;
 
+/// options for a realm which is created with [create_realm_with_options](crate::facades::QuickJsRuntimeFacade::create_realm_with_options)
+#[derive(Clone, Debug, Default)]
+pub struct RealmOptions {
+    random_seed: Option<u64>,
+}
+
+impl RealmOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// replace Math.random with a seeded generator so runs are reproducible, see [random](crate::features::random)
+    pub fn random_seed(mut self, seed: u64) -> Self {
+        self.random_seed = Some(seed);
+        self
+    }
+    pub(crate) fn apply(&self, realm: &QuickJsRealmAdapter) -> Result<(), JsError> {
+        if let Some(seed) = self.random_seed {
+            random::install_q(realm, RandomState::from_seed(seed))?;
+        }
+        Ok(())
+    }
+}
+
 pub struct QuickJsRealmAdapter {
     object_cache: RefCell<AutoIdMap<QuickJsValueAdapter>>,
     promise_cache: RefCell<AutoIdMap<QuickJsPromiseAdapter>>,
@@ -67,6 +114,45 @@ pub struct QuickJsRealmAdapter {
     pub(crate) proxy_constructor_refs: RefCell<HashMap<String, QuickJsValueAdapter>>,
     pub(crate) proxy_event_listeners: RefCell<ProxyEventListenerMaps>,
     pub(crate) proxy_static_event_listeners: RefCell<ProxyStaticEventListenerMaps>,
+    pub(crate) memo_caches: RefCell<HashMap<String, Rc<RefCell<MemoCache>>>>,
+    // the proxies which were created by scripts, see memoize
+    pub(crate) proxy_tracking: RefCell<Option<ProxyTracking>>,
+    // set to false when the context is freed, shared with the CachedJsObjectRefs of this realm
+    pub(crate) alive: Arc<AtomicBool>,
+    // timers created by setTimeout and setInterval in this realm, cleared when the realm is dropped
+    pub(crate) timeout_ids: RefCell<HashSet<i32>>,
+    pub(crate) interval_ids: RefCell<HashSet<i32>>,
+    // when the timers are due, see suspend
+    pub(crate) timer_schedules: RefCell<HashMap<ScopedTask, TimerSchedule>>,
+    // the globals after the init hooks ran, these are not suspended
+    pub(crate) initial_global_names: RefCell<HashSet<String>>,
+    // the state of Math.random when the realm was seeded, see RealmOptions::random_seed
+    pub(crate) random_state: Cell<Option<RandomState>>,
+    // the loaded modules and the last console records and errors, see debugdump
+    pub(crate) debug_records: RefCell<DebugRecords>,
+    // values held by PinnedRefs, released when the realm is dropped
+    pub(crate) pinned_values: RefCell<AutoIdMap<QuickJsValueAdapter>>,
+    // the imports which were resolved in this realm, see modulegraph
+    pub(crate) module_graph: RefCell<Vec<ModuleEdge>>,
+    // the line coverage while it is collected, see coverage
+    pub(crate) coverage: RefCell<Option<CoverageReport>>,
+    // the namespaces of the modules which were imported for evals by their normalized name, see imports
+    pub(crate) module_namespaces: RefCell<HashMap<String, QuickJsValueAdapter>>,
+    // the module objects of the files which were loaded with require by absolute path, see commonjs
+    pub(crate) commonjs_modules: RefCell<HashMap<String, QuickJsValueAdapter>>,
+    // the parent and the child realms which were created by scripts, see realms
+    pub(crate) realm_family: RefCell<RealmFamily>,
+    // the listeners of scripts for memory pressure events, see memorypressure
+    pub(crate) memory_pressure: RefCell<RealmMemoryPressure>,
+    // the generations of the modules which were invalidated, see modulecache
+    pub(crate) module_cache: RefCell<ModuleCacheState>,
+    // the modules which stand in for failed dynamic imports by absolute path, see modules::compile_retry_placeholder
+    pub(crate) retry_placeholders: RefCell<HashMap<String, RetryPlaceholder>>,
+    // the state of the RealmHandles of this realm, see realmhandle
+    pub(crate) handle_state: RefCell<Weak<RealmHandleState>>,
+    // the timers, counters and group depth of console
+    #[cfg(feature = "console")]
+    pub(crate) console_state: RefCell<ConsoleState>,
     pub id: String,
     pub context: *mut q::JSContext,
 }
@@ -77,6 +163,16 @@ thread_local! {
 }
 
 impl QuickJsRealmAdapter {
+    /// the number of objects which are cached to be used from rust
+    pub(crate) fn cached_object_count(&self) -> usize {
+        self.object_cache.borrow().len()
+    }
+
+    /// the number of promises which are cached to be resolved from rust
+    pub(crate) fn cached_promise_count(&self) -> usize {
+        self.promise_cache.borrow().len()
+    }
+
     pub fn print_stats(&self) {
         println!(
             "QuickJsRealmAdapter.object_cache.len = {}",
@@ -95,7 +191,7 @@ impl QuickJsRealmAdapter {
             println!("---- > {} len:{}", p.0, mappings.len());
             print!("------ ids: ");
             for i in mappings {
-                print!("{}, ", i.0);
+                print!("{}, ", i);
             }
             println!("\n---- < {}", p.0);
         }
@@ -164,6 +260,53 @@ impl QuickJsRealmAdapter {
         };
         all_constructor_refs.clear();
 
+        let all_memo_caches = {
+            let memo_caches = &mut *self.memo_caches.borrow_mut();
+            std::mem::take(memo_caches)
+        };
+        for cache in all_memo_caches.values() {
+            cache.borrow_mut().clear();
+        }
+
+        timers::clear_all_timers_q(self);
+        drop(self.module_namespaces.take());
+        drop(self.commonjs_modules.take());
+        drop(self.proxy_tracking.take());
+        drop(self.memory_pressure.take());
+        // the timers which were not started by scripts, e.g. the timeout of an isolated eval
+        for id in self.timeout_ids.take() {
+            EventLoop::clear_timeout(id);
+        }
+        for id in self.interval_ids.take() {
+            EventLoop::clear_interval(id);
+        }
+        taskscope::remove_realm_scopes(self.id.as_str());
+
+        let leaked_values = {
+            let pinned_values = &mut *self.pinned_values.borrow_mut();
+            let leaked = pinned_values.len();
+            if leaked > 0 {
+                log::warn!(
+                    "QuickJsContext:free {}, releasing {} pinned values which were not dropped",
+                    self.id,
+                    leaked
+                );
+            }
+            std::mem::replace(
+                pinned_values,
+                AutoIdMap::new_with_max_size(i32::MAX as usize),
+            )
+        };
+        // drop outside of borrow_mut so finalizers may use the map
+        drop(leaked_values);
+
+        // instances which are still referenced won't be collected while the realm exists
+        QuickJsRuntimeAdapter::do_with(|rt| {
+            crate::reflection::finalize_remaining_instances(rt, self)
+        });
+
+        self.alive.store(false, Ordering::SeqCst);
+
         unsafe { q::JS_FreeContext(self.context) };
 
         log::trace!("after QuickJsContext:free {}", self.id);
@@ -187,6 +330,8 @@ impl QuickJsRealmAdapter {
             panic!("ContextCreationFailed");
         }
 
+        quickjsvalueadapter::register_context(context);
+
         Self {
             id,
             context,
@@ -196,6 +341,27 @@ impl QuickJsRealmAdapter {
             proxy_constructor_refs: RefCell::new(Default::default()),
             proxy_event_listeners: RefCell::new(Default::default()),
             proxy_static_event_listeners: RefCell::new(Default::default()),
+            memo_caches: RefCell::new(Default::default()),
+            proxy_tracking: RefCell::new(None),
+            alive: Arc::new(AtomicBool::new(true)),
+            timeout_ids: RefCell::new(HashSet::new()),
+            interval_ids: RefCell::new(HashSet::new()),
+            timer_schedules: RefCell::new(HashMap::new()),
+            initial_global_names: RefCell::new(HashSet::new()),
+            random_state: Cell::new(None),
+            debug_records: RefCell::new(Default::default()),
+            pinned_values: RefCell::new(AutoIdMap::new_with_max_size(i32::MAX as usize)),
+            module_graph: RefCell::new(vec![]),
+            coverage: RefCell::new(None),
+            module_namespaces: RefCell::new(HashMap::new()),
+            commonjs_modules: RefCell::new(HashMap::new()),
+            realm_family: RefCell::new(Default::default()),
+            module_cache: RefCell::new(Default::default()),
+            retry_placeholders: RefCell::new(HashMap::new()),
+            memory_pressure: RefCell::new(Default::default()),
+            handle_state: RefCell::new(Weak::new()),
+            #[cfg(feature = "console")]
+            console_state: RefCell::new(ConsoleState::default()),
         }
     }
     /// get the id of a QuickJsContext from a JSContext
@@ -222,6 +388,74 @@ impl QuickJsRealmAdapter {
         unsafe { Self::eval_ctx(self.context, script, None) }
     }
 
+    /// evaluate a template script, the params of the template are bound as `$name` variables in the scope of the script
+    pub fn eval_template(&self, template: ScriptTemplate) -> Result<QuickJsValueAdapter, JsError> {
+        let (script, params) = template.into_parts();
+
+        let mut bindings = vec![];
+        for (name, value) in params {
+            let valid = !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+            if !valid {
+                return Err(JsError::new_string(format!(
+                    "invalid template param name: {name}"
+                )));
+            }
+            bindings.push((format!("${name}"), self.from_js_value_facade(value)?));
+        }
+        self.eval_with_scope(script, bindings)
+    }
+
+    /// evaluate a script with variables which are in scope of the script, the script runs in a function so its var declarations are not globals
+    ///
+    /// the script is pre-processed like any other script and becomes the body of an arrow function which is compiled under the path
+    /// of the script, the bound values are passed as its parameters so the script sees no `arguments` of its own
+    ///
+    /// the result is the value the script returns, e.g. `return a + b;`
+    pub fn eval_with_scope(
+        &self,
+        script: Script,
+        bindings: Vec<(String, QuickJsValueAdapter)>,
+    ) -> Result<QuickJsValueAdapter, JsError> {
+        let mut names = vec![];
+        let mut args = vec![];
+        for (name, value) in bindings {
+            let valid = name
+                .chars()
+                .next()
+                .map(|c| !c.is_ascii_digit())
+                .unwrap_or(false)
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+                && name != "eval"
+                && name != "arguments";
+            if !valid {
+                return Err(JsError::new_string(format!("invalid binding name: {name}")));
+            }
+            names.push(name);
+            args.push(value);
+        }
+
+        let script = unsafe { Self::prepare_script(self.context, script)? };
+        // the code starts on the first line of the wrapper so the line numbers of errors match the script
+        let wrapper_code = format!(
+            "(({}) => {{{}\n}});",
+            names.join(", "),
+            script.get_runnable_code()
+        );
+        let wrapper = unsafe {
+            Self::eval_prepared_ctx(
+                self.context,
+                Script::new(script.get_path(), wrapper_code.as_str()),
+                None,
+            )?
+        };
+        functions::call_function_q(self, &wrapper, args.as_slice(), None)
+    }
+
     pub fn eval_this(
         &self,
         script: Script,
@@ -230,6 +464,26 @@ impl QuickJsRealmAdapter {
         unsafe { Self::eval_ctx(self.context, script, Some(this)) }
     }
 
+    // pass the code which is about to be compiled to the compile audit of the runtime, if any
+    pub(crate) unsafe fn record_compile(
+        context: *mut q::JSContext,
+        origin: CompileOrigin,
+        script: &Script,
+    ) {
+        QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+            if q_js_rt.compile_audit.is_some() {
+                let realm = q_js_rt.get_quickjs_context(context);
+                compileaudit::record(
+                    q_js_rt,
+                    realm.get_realm_id(),
+                    origin,
+                    script.get_path(),
+                    script.get_runnable_code(),
+                );
+            }
+        })
+    }
+
     /// # Safety
     /// when passing a context ptr please be sure that the corresponding QuickJsContext is still active
     pub unsafe fn eval_ctx(
@@ -239,7 +493,28 @@ impl QuickJsRealmAdapter {
     ) -> Result<QuickJsValueAdapter, JsError> {
         log::debug!("q_js_rt.eval file {}", script.get_path());
 
-        script = QuickJsRuntimeAdapter::pre_process(script)?;
+        script = Self::prepare_script(context, script)?;
+        Self::eval_prepared_ctx(context, script, this_opt)
+    }
+
+    // pre-process a script which is about to be evaluated, record it in the compile audit and instrument it for coverage
+    unsafe fn prepare_script(
+        context: *mut q::JSContext,
+        script: Script,
+    ) -> Result<Script, JsError> {
+        let mut script = QuickJsRuntimeAdapter::pre_process(script)?;
+        Self::record_compile(context, CompileOrigin::Eval, &script);
+        coverage::instrument(context, &mut script);
+        Ok(script)
+    }
+
+    // evaluate a script which was already prepared with prepare_script
+    unsafe fn eval_prepared_ctx(
+        context: *mut q::JSContext,
+        script: Script,
+        this_opt: Option<QuickJsValueAdapter>,
+    ) -> Result<QuickJsValueAdapter, JsError> {
+        let _depth = QuickJsRuntimeAdapter::do_with(ExecutionDepthGuard::enter)?;
 
         let code_str = script.get_runnable_code();
 
@@ -289,9 +564,30 @@ impl QuickJsRealmAdapter {
 
     /// evaluate a Module
     pub fn eval_module(&self, script: Script) -> Result<QuickJsValueAdapter, JsError> {
+        debugdump::record_module(
+            self,
+            script.get_path(),
+            "eval",
+            Some(script.get_code().as_bytes()),
+        );
         unsafe { Self::eval_module_ctx(self.context, script) }
     }
 
+    /// compile a script to bytecode, see [compile_q](crate::quickjs_utils::compile::compile_q)
+    pub fn compile(&self, script: Script) -> Result<Vec<u8>, JsError> {
+        compile::compile_q(self, script)
+    }
+
+    /// compile a module to bytecode, see [compile_module_q](crate::quickjs_utils::compile::compile_module_q)
+    pub fn compile_module(&self, script: Script) -> Result<Vec<u8>, JsError> {
+        compile::compile_module_q(self, script)
+    }
+
+    /// evaluate the bytecode of [compile](Self::compile) or [compile_module](Self::compile_module)
+    pub fn eval_compiled(&self, bytes: &[u8]) -> Result<QuickJsValueAdapter, JsError> {
+        compile::eval_compiled_q(self, bytes)
+    }
+
     /// # Safety
     /// when passing a context ptr please be sure that the corresponding QuickJsContext is still active
     pub unsafe fn eval_module_ctx(
@@ -301,19 +597,26 @@ impl QuickJsRealmAdapter {
         log::debug!("q_js_rt.eval_module file {}", script.get_path());
 
         script = QuickJsRuntimeAdapter::pre_process(script)?;
-
-        let code_str = script.get_runnable_code();
-
-        let filename_c = make_cstring(script.get_path())?;
-        let code_c = make_cstring(code_str)?;
-
-        let value_raw = q::JS_Eval(
-            context,
-            code_c.as_ptr(),
-            code_str.len() as _,
-            filename_c.as_ptr(),
-            q::JS_EVAL_TYPE_MODULE as i32,
-        );
+        Self::record_compile(context, CompileOrigin::Module, &script);
+        coverage::instrument(context, &mut script);
+        let _depth = QuickJsRuntimeAdapter::do_with(ExecutionDepthGuard::enter)?;
+
+        let value_raw = QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+            let realm = q_js_rt.get_quickjs_context(context);
+            let _module_eval = ModuleEvalScope::enter(Some(realm.get_realm_id()));
+            // static imports are resolved while evaluating and fail fast, dynamic imports run later as jobs
+            modules::with_static_imports(|| {
+                // the module is compiled first so its import.meta is populated before it runs
+                let compiled = modules::compile_module(context, script.clone())?;
+                modules::set_import_meta(
+                    realm,
+                    modules::get_module_def(&compiled),
+                    script.get_path(),
+                )?;
+                // the engine frees the module when it fails to evaluate so the reference is handed over instead of copied
+                Ok::<_, JsError>(q::JS_EvalFunction(context, compiled.into_raw()))
+            })
+        })?;
 
         let ret = QuickJsValueAdapter::new(
             context,
@@ -325,6 +628,11 @@ impl QuickJsRealmAdapter {
 
         log::trace!("evalled module yielded a {}", ret.borrow_value().tag);
 
+        // the module which timed out rejected the promise of the module, the eval fails instead
+        if let Some(err) = QuickJsRuntimeAdapter::do_with(interrupthandler::take_timed_out_module) {
+            return Err(err);
+        }
+
         // check for error
 
         if ret.is_exception() {
@@ -362,6 +670,28 @@ impl QuickJsRealmAdapter {
         unsafe { errors::get_exception(self.context) }
     }
 
+    /// take the pending exception without converting it, after this no exception is pending
+    /// use this with [set_pending_exception](Self::set_pending_exception) to stash the original exception while running cleanup code
+    pub fn take_pending_exception(&self) -> Option<QuickJsValueAdapter> {
+        unsafe { errors::take_exception(self.context) }
+    }
+
+    /// make a value the pending exception, this replaces an exception which is already pending
+    pub fn set_pending_exception(&self, exception: QuickJsValueAdapter) {
+        unsafe { errors::set_exception(self.context, &exception) }
+    }
+
+    /// check if an exception is pending
+    pub fn has_pending_exception(&self) -> bool {
+        match self.take_pending_exception() {
+            Some(exception) => {
+                self.set_pending_exception(exception);
+                true
+            }
+            None => false,
+        }
+    }
+
     /// Get the last exception from the runtime, and if present, convert it to a JsError.
     /// # Safety
     /// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
@@ -403,7 +733,7 @@ impl QuickJsRealmAdapter {
             let cache_map = &*self.object_cache.borrow();
             let opt = cache_map.get(&(id as usize));
             let cached_ref = opt.expect("no such obj in cache");
-            cached_ref.clone()
+            cached_ref.dup()
         };
         // prevent running consumer while borrowed
 
@@ -531,7 +861,7 @@ impl QuickJsRealmAdapter {
         let proxy_map = self.proxy_registry.borrow();
         let proxy = proxy_map.get(cn.as_str()).expect("class not found");
 
-        dispatch_event(self, proxy, *proxy_instance_id, event_id, event_obj.clone())
+        dispatch_event(self, proxy, *proxy_instance_id, event_id, event_obj.dup())
     }
 
     pub fn dispatch_static_proxy_event(
@@ -555,7 +885,7 @@ impl QuickJsRealmAdapter {
             self,
             proxy.get_class_name().as_str(),
             event_id,
-            event_obj.clone(),
+            event_obj.dup(),
         )
     }
 
@@ -953,9 +1283,29 @@ impl QuickJsRealmAdapter {
         detach_array_buffer_buffer_q(self, &abuf)
     }
 
+    /// copy the bytes in the view of a TypedArray, fails for a detached buffer
     pub fn copy_typed_array_buffer(&self, array: &QuickJsValueAdapter) -> Result<Vec<u8>, JsError> {
-        let abuf = get_array_buffer_q(self, array)?;
-        get_array_buffer_buffer_copy_q(self, &abuf)
+        get_bytes_q(self, array)
+    }
+
+    /// load a module again on its next import, the modules which import it are loaded again as well, see [modulecache](crate::jsutils::modulecache)
+    /// returns the paths of the invalidated modules, starting with absolute_path
+    pub fn invalidate_module(&self, absolute_path: &str) -> Vec<String> {
+        modulecache::invalidate_q(self, absolute_path)
+    }
+
+    /// load every module again on its next import, see [modulecache](crate::jsutils::modulecache)
+    pub fn clear_module_cache(&self) {
+        modulecache::clear_q(self)
+    }
+
+    /// copy a value of this realm to another realm (or this realm) with the structured clone algorithm, see [structuredclone](crate::quickjs_utils::structuredclone)
+    pub fn structured_clone(
+        &self,
+        value: &QuickJsValueAdapter,
+        target: &QuickJsRealmAdapter,
+    ) -> Result<QuickJsValueAdapter, JsError> {
+        structuredclone::structured_clone_q(self, value, target)
     }
 
     pub fn get_proxy_instance_info(
@@ -1003,15 +1353,23 @@ impl QuickJsRealmAdapter {
                         buffer: self.copy_typed_array_buffer(js_value)?,
                         array_type: TypedArrayType::Uint8,
                     }
+                } else if dates::is_date_q(self, js_value) {
+                    JsValueFacade::new_date(dates::get_time_q(self, js_value)?)
+                } else if let Some(collection) =
+                    conversion::collection_to_js_value_facade_q(self, js_value)?
+                {
+                    collection
+                } else if let Some(encoded) = codecs::encode_q(self, js_value)? {
+                    encoded
                 } else {
                     JsValueFacade::JsObject {
-                        cached_object: CachedJsObjectRef::new(self, js_value.clone()),
+                        cached_object: CachedJsObjectRef::new(self, js_value.dup()),
                     }
                 }
             }
             JsValueType::Function => JsValueFacade::JsFunction {
                 cached_function: CachedJsFunctionRef {
-                    cached_object: CachedJsObjectRef::new(self, js_value.clone()),
+                    cached_object: CachedJsObjectRef::new(self, js_value.dup()),
                 },
             },
             JsValueType::BigInt => {
@@ -1019,32 +1377,22 @@ impl QuickJsRealmAdapter {
             }
             JsValueType::Promise => JsValueFacade::JsPromise {
                 cached_promise: CachedJsPromiseRef {
-                    cached_object: CachedJsObjectRef::new(self, js_value.clone()),
+                    cached_object: CachedJsObjectRef::new(self, js_value.dup()),
                 },
             },
-            JsValueType::Date => {
-                todo!();
-            }
+            JsValueType::Date => JsValueFacade::new_date(dates::get_time_q(self, js_value)?),
             JsValueType::Null => JsValueFacade::Null,
             JsValueType::Undefined => JsValueFacade::Undefined,
 
             JsValueType::Array => JsValueFacade::JsArray {
                 cached_array: CachedJsArrayRef {
-                    cached_object: CachedJsObjectRef::new(self, js_value.clone()),
+                    cached_object: CachedJsObjectRef::new(self, js_value.dup()),
                 },
             },
-            JsValueType::Error => {
-                let name = self.get_object_property(js_value, "name")?.to_string()?;
-                let message = self.get_object_property(js_value, "message")?.to_string()?;
-                let stack = self.get_object_property(js_value, "stack")?.to_string()?;
-
-                #[cfg(feature = "typescript")]
-                let stack = crate::typescript::unmap_stack_trace(stack.as_str());
-
-                JsValueFacade::JsError {
-                    val: JsError::new(name, message, stack),
-                }
-            }
+            // this also includes the stack of errors which were created by native code
+            JsValueType::Error => JsValueFacade::JsError {
+                val: unsafe { errors::error_to_js_error(self.context, js_value) },
+            },
         };
         Ok(res)
     }
@@ -1052,6 +1400,7 @@ impl QuickJsRealmAdapter {
     /// convert a JSValueFacade into a JSValueAdapter
     /// you need this to move values into the worker thread from a different thread (JSValueAdapter cannot leave the worker thread)
     #[allow(clippy::wrong_self_convention)]
+    /// convert a facade to a value, see [conversion](crate::quickjs_utils::conversion) for the limits on nested values
     pub fn from_js_value_facade(
         &self,
         value_facade: JsValueFacade,
@@ -1059,6 +1408,14 @@ impl QuickJsRealmAdapter {
     where
         Self: Sized + 'static,
     {
+        conversion::from_js_value_facade_q(self, value_facade)
+    }
+
+    /// convert a facade which is not a container, containers are converted by [conversion](crate::quickjs_utils::conversion)
+    pub(crate) fn leaf_from_js_value_facade(
+        &self,
+        value_facade: JsValueFacade,
+    ) -> Result<QuickJsValueAdapter, JsError> {
         match value_facade {
             JsValueFacade::I32 { val } => self.create_i32(val),
             JsValueFacade::F64 { val } => self.create_f64(val),
@@ -1066,35 +1423,19 @@ impl QuickJsRealmAdapter {
             JsValueFacade::Boolean { val } => self.create_boolean(val),
             JsValueFacade::JsObject { cached_object } => {
                 // todo check realm (else copy? or error?)
-                self.with_cached_object(cached_object.id, |obj| Ok(obj.clone()))
+                self.with_cached_object(cached_object.id, |obj| Ok(obj.dup()))
             }
             JsValueFacade::JsPromise { cached_promise } => {
                 // todo check realm (else copy? or error?)
-                self.with_cached_object(cached_promise.cached_object.id, |obj| Ok(obj.clone()))
+                self.with_cached_object(cached_promise.cached_object.id, |obj| Ok(obj.dup()))
             }
             JsValueFacade::JsArray { cached_array } => {
                 // todo check realm (else copy? or error?)
-                self.with_cached_object(cached_array.cached_object.id, |obj| Ok(obj.clone()))
+                self.with_cached_object(cached_array.cached_object.id, |obj| Ok(obj.dup()))
             }
             JsValueFacade::JsFunction { cached_function } => {
                 // todo check realm (else copy? or error?)
-                self.with_cached_object(cached_function.cached_object.id, |obj| Ok(obj.clone()))
-            }
-            JsValueFacade::Object { val } => {
-                let obj = self.create_object()?;
-                for entry in val {
-                    let prop = self.from_js_value_facade(entry.1)?;
-                    self.set_object_property(&obj, entry.0.as_str(), &prop)?;
-                }
-                Ok(obj)
-            }
-            JsValueFacade::Array { val } => {
-                let obj = self.create_array()?;
-                for (x, entry) in val.into_iter().enumerate() {
-                    let prop = self.from_js_value_facade(entry)?;
-                    self.set_array_element(&obj, x as u32, &prop)?;
-                }
-                Ok(obj)
+                self.with_cached_object(cached_function.cached_object.id, |obj| Ok(obj.dup()))
             }
             JsValueFacade::Promise { producer } => {
                 let producer = &mut *producer.lock("from_js_value_facade").unwrap();
@@ -1133,9 +1474,11 @@ impl QuickJsRealmAdapter {
             }
             JsValueFacade::Null => self.create_null(),
             JsValueFacade::Undefined => self.create_undefined(),
-            JsValueFacade::JsError { val } => {
-                self.create_error(val.get_name(), val.get_message(), val.get_stack())
-            }
+            JsValueFacade::JsError { val } => self.create_error(
+                val.get_name(),
+                val.get_script_message(),
+                val.get_script_stack(),
+            ),
             JsValueFacade::ProxyInstance {
                 instance_id,
                 namespace,
@@ -1145,93 +1488,44 @@ impl QuickJsRealmAdapter {
                 TypedArrayType::Uint8 => self.create_typed_array_uint8(buffer),
             },
             JsValueFacade::JsonStr { json } => self.json_parse(json.as_str()),
-            JsValueFacade::SerdeValue { value } => self.serde_value_to_value_adapter(value),
+            container @ (JsValueFacade::Object { .. }
+            | JsValueFacade::Array { .. }
+            | JsValueFacade::Map { .. }
+            | JsValueFacade::Set { .. }
+            | JsValueFacade::SerdeValue { .. }) => {
+                conversion::from_js_value_facade_q(self, container)
+            }
+            JsValueFacade::Custom { tag, data } => codecs::decode_q(self, tag.as_str(), &data),
+            JsValueFacade::JsDate { millis } => {
+                let date = dates::new_date_q(self)?;
+                dates::set_time_q(self, &date, millis.unwrap_or(f64::NAN))?;
+                Ok(date)
+            }
         }
     }
 
+    /// convert a value to a serde value, undefined array elements and object properties are converted to null, see [conversion](crate::quickjs_utils::conversion)
     pub fn value_adapter_to_serde_value(
         &self,
         value_adapter: &QuickJsValueAdapter,
     ) -> Result<serde_json::Value, JsError> {
-        match value_adapter.get_js_type() {
-            JsValueType::I32 => Ok(Value::from(value_adapter.to_i32())),
-            JsValueType::F64 => Ok(Value::from(value_adapter.to_f64())),
-            JsValueType::String => Ok(Value::from(value_adapter.to_string()?)),
-            JsValueType::Boolean => Ok(Value::from(value_adapter.to_bool())),
-            JsValueType::Object => {
-                let mut map: serde_json::Map<String, serde_json::Value> = serde_json::Map::new();
-                self.traverse_object_mut(value_adapter, |k, v| {
-                    map.insert(k.to_string(), self.value_adapter_to_serde_value(v)?);
-                    Ok(())
-                })?;
-                let obj_val = serde_json::Value::Object(map);
-                Ok(obj_val)
-            }
-            JsValueType::Array => {
-                let mut arr: Vec<serde_json::Value> = vec![];
-                self.traverse_array_mut(value_adapter, |_i, v| {
-                    arr.push(self.value_adapter_to_serde_value(v)?);
-                    Ok(())
-                })?;
-                let arr_val = serde_json::Value::Array(arr);
-                Ok(arr_val)
-            }
-            JsValueType::Null => Ok(serde_json::Value::Null),
-            JsValueType::Undefined => Ok(serde_json::Value::Null),
-            JsValueType::Function => Ok(serde_json::Value::Null),
-            JsValueType::BigInt => Ok(serde_json::Value::Null),
-            JsValueType::Promise => Ok(serde_json::Value::Null),
-            JsValueType::Date => Ok(serde_json::Value::Null),
-            JsValueType::Error => Ok(serde_json::Value::Null),
-        }
+        conversion::to_serde_value_q(self, value_adapter, UndefinedPolicy::Keep)
+    }
+
+    /// convert a value to a serde value with an [UndefinedPolicy]
+    pub fn value_adapter_to_serde_value_with_policy(
+        &self,
+        value_adapter: &QuickJsValueAdapter,
+        policy: UndefinedPolicy,
+    ) -> Result<serde_json::Value, JsError> {
+        conversion::to_serde_value_q(self, value_adapter, policy)
     }
 
     pub fn serde_value_to_value_adapter(
         &self,
         value: Value,
     ) -> Result<QuickJsValueAdapter, JsError> {
-        match value {
-            Value::Null => self.create_null(),
-            Value::Bool(b) => self.create_boolean(b),
-            Value::Number(n) => {
-                if n.is_i64() {
-                    let i = n.as_i64().unwrap();
-                    if i <= i32::MAX as i64 {
-                        self.create_i32(i as i32)
-                    } else {
-                        self.create_f64(i as f64)
-                    }
-                } else if n.is_u64() {
-                    let i = n.as_u64().unwrap();
-                    if i <= i32::MAX as u64 {
-                        self.create_i32(i as i32)
-                    } else {
-                        self.create_f64(i as f64)
-                    }
-                } else {
-                    // f64
-                    let i = n.as_f64().unwrap();
-                    self.create_f64(i)
-                }
-            }
-            Value::String(s) => self.create_string(s.as_str()),
-            Value::Array(a) => {
-                let arr = self.create_array()?;
-                for (x, aval) in (0_u32..).zip(a.into_iter()) {
-                    let entry = self.serde_value_to_value_adapter(aval)?;
-                    self.set_array_element(&arr, x, &entry)?;
-                }
-                Ok(arr)
-            }
-            Value::Object(o) => {
-                let obj = self.create_object()?;
-                for oval in o {
-                    let entry = self.serde_value_to_value_adapter(oval.1)?;
-                    self.set_object_property(&obj, oval.0.as_str(), &entry)?;
-                }
-                Ok(obj)
-            }
-        }
+        conversion::from_js_value_facade_q(self, JsValueFacade::SerdeValue { value })
     }
     /// create a new Promise with a Future which will run async and then resolve or reject the promise
     /// the mapper is used to convert the result of the future into a JSValueAdapter
@@ -1268,10 +1562,82 @@ impl QuickJsRealmAdapter {
 pub mod tests {
     use crate::builder::QuickJsRuntimeBuilder;
     use crate::facades::tests::init_test_rt;
-    use crate::jsutils::Script;
+    use crate::jsutils::{JsError, Script, ScriptPreProcessor};
     use crate::quickjs_utils;
     use crate::quickjs_utils::primitives::to_i32;
     use crate::quickjs_utils::{functions, get_global_q, objects};
+    use crate::values::JsValueConvertable;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_eval_template() {
+        let rt = init_test_rt();
+        let mut params = HashMap::new();
+        params.insert("a", 3i32.to_js_value_facade());
+        params.insert("b", "\"); throw Error('x'); (\"".to_js_value_facade());
+        params.insert("unused", true.to_js_value_facade());
+        let res = rt
+            .eval_template_sync(
+                None,
+                Script::template(
+                    "test_eval_template.js",
+                    "let c = $a * 2; return `${c}${$b}`;",
+                    params,
+                ),
+            )
+            .expect("script failed");
+        assert_eq!(res.get_str(), "6\"); throw Error('x'); (\"");
+
+        let mut params = HashMap::new();
+        params.insert("a", 3i32.to_js_value_facade());
+        let err = rt
+            .eval_template_sync(
+                None,
+                Script::template(
+                    "test_eval_template.js",
+                    "var b = 1;\nreturn $a + $missing;",
+                    params,
+                ),
+            )
+            .expect_err("script should fail");
+        assert_eq!(err.get_name(), "ReferenceError");
+        assert!(err.get_message().contains("$missing"));
+        assert!(err.get_stack().contains("test_eval_template.js:2"));
+        let b_global = rt
+            .eval_sync(None, Script::new("test_eval_template.js", "typeof b;"))
+            .expect("script failed");
+        assert_eq!(b_global.get_str(), "undefined");
+    }
+
+    struct VersionPreProcessor {}
+
+    impl ScriptPreProcessor for VersionPreProcessor {
+        fn process(&self, script: &mut Script) -> Result<(), JsError> {
+            let code = script.get_code().replace("__VERSION__", "'1.2'");
+            script.set_code(code);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_eval_template_pre_processed() {
+        let rt = QuickJsRuntimeBuilder::new()
+            .script_pre_processor(VersionPreProcessor {})
+            .build();
+        let mut params = HashMap::new();
+        params.insert("a", 3i32.to_js_value_facade());
+        let res = rt
+            .eval_template_sync(
+                None,
+                Script::template(
+                    "test_eval_template.js",
+                    "return `${__VERSION__}:${$a}:${typeof arguments}`;",
+                    params,
+                ),
+            )
+            .expect("script failed");
+        assert_eq!(res.get_str(), "1.2:3:undefined");
+    }
 
     #[test]
     fn test_eval() {
@@ -1332,7 +1698,7 @@ pub mod tests {
                 .expect("script failed");
             assert!(v4.is_i32());
         });
-        rt.drop_context("b");
+        rt.drop_context("b").expect("could not drop ctx b");
 
         rt.exe_rt_task_in_event_loop(|q_js_rt| {
             q_js_rt.gc();
@@ -1375,7 +1741,7 @@ pub mod tests {
             assert!(v.is_i32());
             q_js_rt.gc();
         });
-        rt.drop_context("c");
+        rt.drop_context("c").expect("could not drop ctx c");
         rt.exe_rt_task_in_event_loop(|q_js_rt| {
             q_js_rt.gc();
             let ctx_a = q_js_rt.get_context("a");
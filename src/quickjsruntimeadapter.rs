@@ -1,25 +1,45 @@
 // store in thread_local
 
+use crate::builder::BuilderSummary;
+#[cfg(feature = "console")]
+use crate::builder::ConsolePrinter;
 use crate::facades::QuickjsRuntimeFacadeInner;
-use crate::jsutils::modules::{CompiledModuleLoader, NativeModuleLoader, ScriptModuleLoader};
+use crate::features::coverage;
+use crate::features::realms;
+use crate::jsutils::bytecodecache::{BytecodeCache, CachedKind};
+use crate::jsutils::compileaudit::{CompileAudit, CompileOrigin};
+use crate::jsutils::idle::{IdleCallback, IdleTracker};
+use crate::jsutils::microtasks::{MicrotaskDrain, MicrotaskLimiter};
+use crate::jsutils::modules::{
+    CompiledModuleLoader, ModuleResolver, NativeModuleLoader, ResolvedSpecifier, RetryPolicy,
+    ScriptModuleLoader,
+};
+use crate::jsutils::syncbridge::SyncBridgeStats;
+use crate::jsutils::taskscope::EvalOptions;
+use crate::jsutils::uncaught::UncaughtErrorHook;
+use crate::jsutils::{compileaudit, debugdump, jobcontext, modulecache, suspend};
 use crate::jsutils::{JsError, Script, ScriptPreProcessor};
+use crate::quickjs_utils::allocator::Allocator;
 use crate::quickjs_utils::compile::from_bytecode;
+use crate::quickjs_utils::interrupthandler::{JobTimer, ModuleEvalScope};
 use crate::quickjs_utils::modules::{
-    add_module_export, compile_module, get_module_def, get_module_name, new_module,
-    set_module_export,
+    add_module_export, compile_module, compile_retry_placeholder, get_module_def, get_module_name,
+    is_loading_static_imports, new_module, set_import_meta, set_module_export,
 };
 use crate::quickjs_utils::runtime::new_class_id;
-use crate::quickjs_utils::{gc, interrupthandler, modules, promises};
+use crate::quickjs_utils::{conversion, gc, interrupthandler, modules, promises};
 use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsvalueadapter;
 use libquickjs_sys as q;
 use serde::Serialize;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::ffi::CString;
 use std::fmt::{Debug, Formatter};
 use std::os::raw::c_int;
 use std::panic;
 use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
 
 /// this is the internal abstract loader which is used to actually load the modules
 pub trait ModuleLoader {
@@ -31,7 +51,7 @@ pub trait ModuleLoader {
         ref_path: &str,
         path: &str,
     ) -> Option<String>;
-    /// load the Module
+    /// load the Module, absolute_path may have the suffix of a module which is loaded again, see [modulecache](crate::jsutils::modulecache)
     fn load_module(
         &self,
         q_ctx: &QuickJsRealmAdapter,
@@ -47,6 +67,10 @@ pub trait ModuleLoader {
         q_ctx: &QuickJsRealmAdapter,
         module: *mut q::JSModuleDef,
     ) -> Result<(), JsError>;
+    /// the kind of loader, used in the [module graph](crate::jsutils::modulegraph)
+    fn loader_kind(&self) -> &'static str {
+        "custom"
+    }
 }
 
 // these are the external (util) loaders (todo move these to esruntime?)
@@ -69,6 +93,61 @@ impl ScriptModuleLoaderAdapter {
     pub fn new(loader: Box<dyn ScriptModuleLoader>) -> Self {
         Self { inner: loader }
     }
+
+    /// attempt to load a module for a dynamic import, when the attempt fails and the policy allows another one
+    /// the next attempt is made after the backoff, see [compile_retry_placeholder]
+    fn load_module_with_retry(
+        &self,
+        realm: &QuickJsRealmAdapter,
+        absolute_path: &str,
+        policy: RetryPolicy,
+    ) -> Result<ModuleLoadAttempt, JsError> {
+        let path = modulecache::strip_generation(absolute_path);
+        let key = (realm.get_realm_id().to_string(), absolute_path.to_string());
+        match self.inner.try_load_module(realm, path) {
+            Ok(code) => {
+                FAILED_LOAD_ATTEMPTS.with(|rc| rc.borrow_mut().remove(&key));
+                Ok(ModuleLoadAttempt::Loaded(code))
+            }
+            Err(err) => {
+                let failed_attempts = FAILED_LOAD_ATTEMPTS.with(|rc| {
+                    let map = &mut *rc.borrow_mut();
+                    let errors = map.entry(key.clone()).or_default();
+                    errors.push(format!(
+                        "attempt {}: {}",
+                        errors.len() + 1,
+                        err.get_message()
+                    ));
+                    errors.clone()
+                });
+                let attempt = failed_attempts.len() as u32;
+                log::debug!(
+                    "load of module {} failed (attempt {}): {}",
+                    path,
+                    attempt,
+                    err
+                );
+                if attempt < policy.attempts {
+                    Ok(ModuleLoadAttempt::Retry(policy.get_delay(attempt)))
+                } else {
+                    FAILED_LOAD_ATTEMPTS.with(|rc| rc.borrow_mut().remove(&key));
+                    Err(JsError::new_string(format!(
+                        "loading module {} failed after {} attempts [{}]",
+                        path,
+                        failed_attempts.len(),
+                        failed_attempts.join(", ")
+                    )))
+                }
+            }
+        }
+    }
+}
+
+/// the outcome of an attempt to load a module for a dynamic import
+enum ModuleLoadAttempt {
+    Loaded(String),
+    /// the load failed and is attempted again after a delay
+    Retry(Duration),
 }
 
 impl ModuleLoader for CompiledModuleLoaderAdapter {
@@ -88,8 +167,11 @@ impl ModuleLoader for CompiledModuleLoaderAdapter {
     ) -> Result<*mut q::JSModuleDef, JsError> {
         let bytes = self.inner.load_module(q_ctx, absolute_path);
 
+        debugdump::record_module(q_ctx, absolute_path, "compiled", Some(bytes.as_slice()));
         let compiled_module = unsafe { from_bytecode(q_ctx.context, &bytes)? };
-        Ok(get_module_def(&compiled_module))
+        let module = get_module_def(&compiled_module);
+        unsafe { set_import_meta(q_ctx, module, absolute_path)? };
+        Ok(module)
     }
 
     fn has_module(&self, q_ctx: &QuickJsRealmAdapter, absolute_path: &str) -> bool {
@@ -104,6 +186,10 @@ impl ModuleLoader for CompiledModuleLoaderAdapter {
     ) -> Result<(), JsError> {
         Ok(())
     }
+
+    fn loader_kind(&self) -> &'static str {
+        "compiled"
+    }
 }
 
 impl ModuleLoader for ScriptModuleLoaderAdapter {
@@ -122,14 +208,56 @@ impl ModuleLoader for ScriptModuleLoaderAdapter {
         absolute_path: &str,
     ) -> Result<*mut q::JSModuleDef, JsError> {
         log::trace!("load_module");
-        let code = self.inner.load_module(realm, absolute_path);
+        // the loader gets the path without the reload suffix, the module is compiled with it
+        let path = modulecache::strip_generation(absolute_path);
+        let retry_policy = QuickJsRuntimeAdapter::do_with(|q_js_rt| q_js_rt.module_load_retry);
+        let code = match retry_policy {
+            Some(policy) if !is_loading_static_imports() => {
+                match self.load_module_with_retry(realm, absolute_path, policy)? {
+                    ModuleLoadAttempt::Loaded(code) => code,
+                    ModuleLoadAttempt::Retry(delay) => {
+                        return unsafe { compile_retry_placeholder(realm, absolute_path, delay) };
+                    }
+                }
+            }
+            _ => self.inner.try_load_module(realm, path)?,
+        };
 
+        debugdump::record_module(realm, path, "script", Some(code.as_bytes()));
         let mut script = Script::new(absolute_path, code.as_str());
         script = QuickJsRuntimeAdapter::pre_process(script)?;
+        let origin = if is_loading_static_imports() {
+            CompileOrigin::StaticImport
+        } else {
+            CompileOrigin::DynamicImport
+        };
+        QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+            compileaudit::record(
+                q_js_rt,
+                realm.get_realm_id(),
+                origin,
+                absolute_path,
+                script.get_runnable_code(),
+            )
+        });
+        coverage::instrument_q(realm, &mut script);
         log::trace!("load_module / 2");
-        let compiled_module = unsafe { compile_module(realm.context, script)? };
+        let bytecode_cache =
+            QuickJsRuntimeAdapter::do_with(|q_js_rt| q_js_rt.bytecode_cache.clone());
+        let compiled_module = unsafe {
+            match bytecode_cache {
+                Some(cache) => {
+                    cache.compile_cached(realm.context, CachedKind::Module, &script, || {
+                        compile_module(realm.context, script.clone())
+                    })?
+                }
+                None => compile_module(realm.context, script)?,
+            }
+        };
         log::trace!("load_module / 3");
-        Ok(get_module_def(&compiled_module))
+        let module = get_module_def(&compiled_module);
+        unsafe { set_import_meta(realm, module, absolute_path)? };
+        Ok(module)
     }
 
     fn has_module(&self, q_ctx: &QuickJsRealmAdapter, absolute_path: &str) -> bool {
@@ -144,6 +272,10 @@ impl ModuleLoader for ScriptModuleLoaderAdapter {
     ) -> Result<(), JsError> {
         Ok(())
     }
+
+    fn loader_kind(&self) -> &'static str {
+        "script"
+    }
 }
 
 pub struct NativeModuleLoaderAdapter {
@@ -177,8 +309,10 @@ impl ModuleLoader for NativeModuleLoaderAdapter {
     ) -> Result<*mut q::JSModuleDef, JsError> {
         // create module
         let module = unsafe { new_module(q_ctx.context, absolute_path, Some(native_module_init))? };
+        let path = modulecache::strip_generation(absolute_path);
+        debugdump::record_module(q_ctx, path, "native", None);
 
-        for name in self.inner.get_module_export_names(q_ctx, absolute_path) {
+        for name in self.inner.get_module_export_names(q_ctx, path) {
             unsafe { add_module_export(q_ctx.context, module, name)? }
         }
 
@@ -196,12 +330,17 @@ impl ModuleLoader for NativeModuleLoaderAdapter {
         module: *mut q::JSModuleDef,
     ) -> Result<(), JsError> {
         let module_name = get_module_name(q_ctx.context, module)?;
+        let path = modulecache::strip_generation(module_name.as_str());
 
-        for (name, val) in self.inner.get_module_exports(q_ctx, module_name.as_str()) {
+        for (name, val) in self.inner.try_get_module_exports(q_ctx, path)? {
             set_module_export(q_ctx.context, module, name, val)?;
         }
         Ok(())
     }
+
+    fn loader_kind(&self) -> &'static str {
+        "native"
+    }
 }
 
 unsafe extern "C" fn native_module_init(
@@ -214,7 +353,7 @@ unsafe extern "C" fn native_module_init(
     QuickJsRuntimeAdapter::do_with(|q_js_rt| {
         QuickJsRealmAdapter::with_context(ctx, |q_ctx| {
             if let Some(res) = q_js_rt.with_all_module_loaders(|module_loader| {
-                if module_loader.has_module(q_ctx, module_name.as_str()) {
+                if module_loader.has_module(q_ctx, modulecache::strip_generation(&module_name)) {
                     match module_loader.init_module(q_ctx, module) {
                         Ok(_) => {
                             Some(0) // ok
@@ -255,12 +394,17 @@ thread_local! {
 pub type ContextInitHooks =
     Vec<Box<dyn Fn(&QuickJsRuntimeAdapter, &QuickJsRealmAdapter) -> Result<(), JsError>>>;
 
+pub type ContextDestroyHooks =
+    Vec<Box<dyn Fn(&QuickJsRuntimeAdapter, &QuickJsRealmAdapter) -> Result<(), JsError>>>;
+
 pub struct QuickJsRuntimeAdapter {
     pub(crate) runtime: *mut q::JSRuntime,
     pub(crate) contexts: HashMap<String, QuickJsRealmAdapter>,
     rti_ref: Option<Weak<QuickjsRuntimeFacadeInner>>,
     id: String,
     pub(crate) context_init_hooks: RefCell<ContextInitHooks>,
+    // run before a realm is removed, see add_context_destroy_hook
+    context_destroy_hooks: RefCell<ContextDestroyHooks>,
     script_module_loaders: Vec<ScriptModuleLoaderAdapter>,
     native_module_loaders: Vec<NativeModuleLoaderAdapter>,
     compiled_module_loaders: Vec<CompiledModuleLoaderAdapter>,
@@ -268,13 +412,72 @@ pub struct QuickJsRuntimeAdapter {
     pub(crate) script_pre_processors: Vec<Box<dyn ScriptPreProcessor + Send>>,
     #[allow(clippy::type_complexity)]
     pub(crate) interrupt_handler: Option<Box<dyn Fn(&QuickJsRuntimeAdapter) -> bool>>,
+    pub(crate) module_load_retry: Option<RetryPolicy>,
+    // see QuickJsRuntimeBuilder::module_resolver, its results are kept per (base, specifier)
+    pub(crate) module_resolver: Option<ModuleResolver>,
+    resolved_specifiers: RefCell<HashMap<(String, String), ResolvedSpecifier>>,
+    // the position of the loader which normalized a path, that loader also loads the module
+    normalizing_loaders: RefCell<HashMap<String, usize>>,
+    // false when every import loads its module again, see QuickJsRuntimeBuilder::module_cache
+    pub(crate) module_cache: bool,
+    // scripts are interrupted after this instant, used by isolated evals
+    pub(crate) interrupt_deadline: Cell<Option<Instant>>,
+    // jobs are interrupted when they run longer, see QuickJsRuntimeBuilder::script_timeout
+    pub(crate) script_timeout: Option<Duration>,
+    // the top-level code of a module is interrupted when it runs longer, see QuickJsRuntimeBuilder::module_eval_timeout
+    pub(crate) module_eval_timeout: Option<Duration>,
+    // see QuickJsRuntimeBuilder::max_conversion_depth
+    pub(crate) max_conversion_depth: usize,
+    // the number of evals which are running, see execution_depth
+    pub(crate) execution_depth: Cell<usize>,
+    // see QuickJsRuntimeBuilder::max_execution_depth
+    pub(crate) max_execution_depth: usize,
+    // the number and the total duration of the garbage collections, see metrics
+    pub(crate) gc_runs: Cell<u64>,
+    pub(crate) gc_time: Cell<Duration>,
+    // enforces the memory limit, it is dropped after the runtime is freed
+    pub(crate) allocator: Box<Allocator>,
+    // the max number of frames which are recorded for async stack traces, None when disabled
+    pub(crate) async_stack_depth: Cell<Option<usize>>,
+    // the number of console records and errors which are kept per realm, 0 when disabled
+    pub(crate) debug_record_capacity: Cell<usize>,
+    // record the imports of every realm, see modulegraph
+    pub(crate) record_module_graph: Cell<bool>,
+    // the configuration the runtime was built with, included in debug dumps
+    pub(crate) builder_summary: Option<BuilderSummary>,
+    // the defaults for evals, see QuickJsRuntimeBuilder::default_eval_options
+    pub(crate) default_eval_options: EvalOptions,
+    // called for errors which have no caller, see uncaught
+    pub(crate) uncaught_error_hook: Option<UncaughtErrorHook>,
+    // see compileaudit, None when disabled
+    pub(crate) compile_audit: Option<CompileAudit>,
+    // see QuickJsRuntimeBuilder::microtask_budget, None when the drains are unbounded
+    pub(crate) microtask_limiter: Option<MicrotaskLimiter>,
+    // see QuickJsRuntimeBuilder::console_printer
+    #[cfg(feature = "console")]
+    pub(crate) console_printer: Option<ConsolePrinter>,
+    // run evals whose future was dropped as if it was still awaited, see QuickJsRuntimeBuilder::detach_dropped_futures
+    pub(crate) detach_dropped_futures: bool,
+    // drop a realm when its last RealmHandle is dropped, see QuickJsRuntimeBuilder::drop_realms_with_last_handle
+    pub(crate) drop_realms_with_last_handle: bool,
+    // see QuickJsRuntimeBuilder::allow_sync_bridge
+    pub(crate) allow_sync_bridge: bool,
+    pub(crate) sync_bridge_stats: RefCell<SyncBridgeStats>,
+    // see QuickJsRuntimeBuilder::bytecode_cache_dir
+    pub(crate) bytecode_cache: Option<Arc<BytecodeCache>>,
+    // see QuickJsRuntimeBuilder::idle_callback, the tracker is shared with the facade
+    pub(crate) idle_callback: Option<IdleCallback>,
+    pub(crate) idle_tracker: Option<Arc<IdleTracker>>,
 }
 
 thread_local! {
     static NESTED: RefCell<bool> = RefCell::new(false);
+    // the errors of the failed attempts to load a module for a dynamic import per realm and module name
+    static FAILED_LOAD_ATTEMPTS: RefCell<HashMap<(String, String), Vec<String>>> = RefCell::new(HashMap::new());
 }
 
-#[derive(Serialize)]
+/// the memory usage of a runtime as computed by JS_ComputeMemoryUsage, the sizes are in bytes
+#[derive(Serialize, Clone)]
 pub struct MemoryUsage {
     pub realm_ct: usize,
     pub malloc_size: i64,
@@ -383,7 +586,8 @@ impl QuickJsRuntimeAdapter {
         MemoryUsage {
             realm_ct: self.contexts.len(),
             malloc_size: mu.malloc_size,
-            malloc_limit: mu.malloc_limit,
+            // the limit is enforced by the allocator, a runtime without a limit reports the max size_t like QuickJS does
+            malloc_limit: self.allocator.malloc_limit() as i64,
             memory_used_size: mu.memory_used_size,
             malloc_count: mu.malloc_count,
             memory_used_count: mu.memory_used_count,
@@ -436,7 +640,7 @@ impl QuickJsRuntimeAdapter {
         let hooks = &*self.context_init_hooks.borrow();
         let hook = hooks.get(i).expect("invalid state");
         for ctx in self.contexts.values() {
-            if let Err(e) = hook(self, ctx) {
+            if let Err(e) = suspend::run_init_hook(ctx, || hook(self, ctx)) {
                 panic!("hook failed {}", e);
             }
         }
@@ -449,28 +653,65 @@ impl QuickJsRuntimeAdapter {
     // EsRuntime should have extra methods like eval_sync_ctx(ctx: &str, script: &Script) etc
     pub fn create_context(id: &str) -> Result<(), JsError> {
         let ctx = Self::do_with(|q_js_rt| {
-            assert!(!q_js_rt.has_context(id));
-            QuickJsRealmAdapter::new(id.to_string(), q_js_rt)
-        });
+            if q_js_rt.has_context(id) {
+                return Err(JsError::new_string(format!(
+                    "a realm with id {id} already exists"
+                )));
+            }
+            Ok(QuickJsRealmAdapter::new(id.to_string(), q_js_rt))
+        })?;
 
         QuickJsRuntimeAdapter::do_with_mut(|q_js_rt| {
             q_js_rt.contexts.insert(id.to_string(), ctx);
         });
 
-        Self::do_with(|q_js_rt| {
+        let res = Self::do_with(|q_js_rt| {
             let ctx = q_js_rt.get_context(id);
             let hooks = &*q_js_rt.context_init_hooks.borrow();
             for hook in hooks {
                 hook(q_js_rt, ctx)?;
             }
-            Ok(())
-        })
+            suspend::record_initial_globals(ctx)
+        });
+        // a realm whose init hooks failed is not registered, its destroy hooks do not run
+        if res.is_err() {
+            Self::remove_context_opt_hooks(id, false);
+        }
+        res
+    }
+    /// add a hook which is called before a realm is removed, e.g. to clean up native state of the realm
+    ///
+    /// an error of a destroy hook is logged and does not stop the removal
+    pub fn add_context_destroy_hook<H>(&self, hook: H)
+    where
+        H: Fn(&QuickJsRuntimeAdapter, &QuickJsRealmAdapter) -> Result<(), JsError> + 'static,
+    {
+        self.context_destroy_hooks.borrow_mut().push(Box::new(hook));
     }
     pub fn remove_context(id: &str) {
+        Self::remove_context_opt_hooks(id, true)
+    }
+    fn remove_context_opt_hooks(id: &str, destroy_hooks: bool) {
         log::debug!("QuickJsRuntime::drop_context: {}", id);
 
+        // the realms which were created by scripts of this realm are dropped with it, see realms
+        let children = QuickJsRuntimeAdapter::do_with(|rt| realms::detach_q(rt.get_context(id)));
+        for child_id in children {
+            if QuickJsRuntimeAdapter::do_with(|rt| rt.has_context(child_id.as_str())) {
+                Self::remove_context(child_id.as_str());
+            }
+        }
+
         QuickJsRuntimeAdapter::do_with(|rt| {
             let q_ctx = rt.get_context(id);
+            if destroy_hooks {
+                let hooks = &*rt.context_destroy_hooks.borrow();
+                for hook in hooks {
+                    if let Err(e) = hook(rt, q_ctx) {
+                        log::error!("realm destroy hook failed for {}: {}", id, e);
+                    }
+                }
+            }
             log::trace!("QuickJsRuntime::q_ctx.free: {}", id);
             q_ctx.free();
             log::trace!("after QuickJsRuntime::q_ctx.free: {}", id);
@@ -481,7 +722,10 @@ impl QuickJsRuntimeAdapter {
             m_rt.contexts.remove(id).expect("no such context")
         });
 
+        let context = ctx.context;
         drop(ctx);
+        // values which are still referenced after this point belong to a destroyed context
+        quickjsvalueadapter::unregister_context(context);
     }
     pub(crate) fn get_context_ids() -> Vec<String> {
         QuickJsRuntimeAdapter::do_with(|q_js_rt| {
@@ -515,7 +759,7 @@ impl QuickJsRuntimeAdapter {
         }
     }
 
-    pub(crate) fn new(runtime: *mut q::JSRuntime) -> Self {
+    pub(crate) fn new(runtime: *mut q::JSRuntime, allocator: Box<Allocator>) -> Self {
         log::trace!("creating new QuickJsRuntime");
 
         if runtime.is_null() {
@@ -539,11 +783,43 @@ impl QuickJsRuntimeAdapter {
             rti_ref: None,
             id,
             context_init_hooks: RefCell::new(vec![]),
+            context_destroy_hooks: RefCell::new(vec![]),
             script_module_loaders: vec![],
             native_module_loaders: vec![],
             compiled_module_loaders: vec![],
             script_pre_processors: vec![],
+            module_load_retry: None,
+            module_resolver: None,
+            module_cache: true,
+            resolved_specifiers: RefCell::new(HashMap::new()),
+            normalizing_loaders: RefCell::new(HashMap::new()),
             interrupt_handler: None,
+            interrupt_deadline: Cell::new(None),
+            script_timeout: None,
+            module_eval_timeout: None,
+            max_conversion_depth: conversion::DEFAULT_MAX_CONVERSION_DEPTH,
+            execution_depth: Cell::new(0),
+            max_execution_depth: interrupthandler::DEFAULT_MAX_EXECUTION_DEPTH,
+            async_stack_depth: Cell::new(None),
+            debug_record_capacity: Cell::new(0),
+            record_module_graph: Cell::new(false),
+            builder_summary: None,
+            default_eval_options: EvalOptions::default(),
+            uncaught_error_hook: None,
+            compile_audit: None,
+            microtask_limiter: None,
+            #[cfg(feature = "console")]
+            console_printer: None,
+            detach_dropped_futures: false,
+            drop_realms_with_last_handle: false,
+            allow_sync_bridge: false,
+            sync_bridge_stats: RefCell::new(SyncBridgeStats::default()),
+            bytecode_cache: None,
+            idle_callback: None,
+            idle_tracker: None,
+            gc_runs: Cell::new(0),
+            gc_time: Cell::new(Duration::ZERO),
+            allocator,
         };
 
         modules::set_module_loader(&q_rt);
@@ -606,11 +882,92 @@ impl QuickJsRuntimeAdapter {
         None
     }
 
+    /// run a consumer with the loader which normalized a path, None when the path was not normalized by a loader
+    pub(crate) fn with_normalizing_loader<C, R>(&self, path: &str, consumer: C) -> Option<R>
+    where
+        C: Fn(&dyn ModuleLoader) -> Option<R>,
+    {
+        let target = *self.normalizing_loaders.borrow().get(path)?;
+        let position = Cell::new(0);
+        self.with_all_module_loaders(|loader| {
+            let current = position.replace(position.get() + 1);
+            if current == target {
+                consumer(loader)
+            } else {
+                None
+            }
+        })
+    }
+
+    // the module resolver is called once per (base, specifier), later imports use the kept result
+    fn resolve_specifier(&self, base: &str, specifier: &str) -> ResolvedSpecifier {
+        let resolver = match &self.module_resolver {
+            Some(resolver) => resolver,
+            None => return ResolvedSpecifier::Unchanged,
+        };
+        let key = (base.to_string(), specifier.to_string());
+        if let Some(resolved) = self.resolved_specifiers.borrow().get(&key) {
+            return resolved.clone();
+        }
+        let resolved = resolver(base, specifier);
+        self.resolved_specifiers
+            .borrow_mut()
+            .insert(key, resolved.clone());
+        resolved
+    }
+
+    /// resolve the specifier of an import with the module resolver and the module loaders
+    /// returns the normalized path and the kind of the loader, the error says if the resolver or the loaders rejected the specifier
+    pub fn normalize_module_path(
+        &self,
+        realm: &QuickJsRealmAdapter,
+        base: &str,
+        specifier: &str,
+    ) -> Result<(String, &'static str), JsError> {
+        let rewritten = match self.resolve_specifier(base, specifier) {
+            ResolvedSpecifier::Unchanged => None,
+            ResolvedSpecifier::Rewritten(rewritten) => Some(rewritten),
+            ResolvedSpecifier::Rejected(reason) => {
+                return Err(JsError::new_string(format!(
+                    "Module {specifier} was rejected by the module resolver: {reason}"
+                )));
+            }
+        };
+        let name = rewritten.as_deref().unwrap_or(specifier);
+        let position = Cell::new(0);
+        self.with_all_module_loaders(|loader| {
+            let res = loader
+                .normalize_path(realm, base, name)
+                .map(|path| (path, loader.loader_kind()));
+            match &res {
+                Some((path, _)) => {
+                    self.normalizing_loaders
+                        .borrow_mut()
+                        .insert(path.clone(), position.get());
+                }
+                None => position.set(position.get() + 1),
+            }
+            res
+        })
+        .ok_or_else(|| match &rewritten {
+            Some(rewritten) => JsError::new_string(format!(
+                "Module {specifier} was not found, the module resolver resolved it to {rewritten} which no module loader could load"
+            )),
+            None => JsError::new_string(format!("Module {specifier} was not found")),
+        })
+    }
+
     /// run the garbage collector
     pub fn gc(&self) {
         gc(self);
     }
 
+    /// get a value of the [JobContext](crate::jsutils::jobcontext::JobContext) of the job which is currently running,
+    /// returns None outside of a job which was started by [eval_with_context](crate::facades::QuickJsRuntimeFacade::eval_with_context)
+    pub fn current_job_context<T: Clone + 'static>() -> Option<T> {
+        jobcontext::current_value::<T>()
+    }
+
     pub fn do_with<C, R>(task: C) -> R
     where
         C: FnOnce(&QuickJsRuntimeAdapter) -> R,
@@ -678,12 +1035,22 @@ impl QuickJsRuntimeAdapter {
     }
 
     /// run pending jobs if avail
+    ///
+    /// the drain stops early when it exceeds the [microtask_budget](crate::builder::QuickJsRuntimeBuilder::microtask_budget) of the runtime
     /// # todo
     /// move this to a quickjs_utils::pending_jobs so it can be used without doing QuickjsRuntime.do_with()
     pub fn run_pending_jobs_if_any(&self) {
         log::trace!("quick_js_rt.run_pending_jobs_if_any");
+        if let Some(tracker) = &self.idle_tracker {
+            tracker.touch(Instant::now());
+        }
+        let mut drain = MicrotaskDrain::start();
         while self.has_pending_jobs() {
+            if !drain.next(self) {
+                break;
+            }
             log::trace!("quick_js_rt.has_pending_jobs!");
+            let _job_timer = JobTimer::start();
             let res = self.run_pending_job();
             match res {
                 Ok(_) => {
@@ -703,10 +1070,17 @@ impl QuickJsRuntimeAdapter {
 
     pub fn run_pending_job(&self) -> Result<(), JsError> {
         let mut ctx: *mut q::JSContext = std::ptr::null_mut();
+        // a job which runs a dynamic import also evaluates the imported modules
+        let module_eval = ModuleEvalScope::enter(None);
         let flag = unsafe {
             // ctx is a return arg here
             q::JS_ExecutePendingJob(self.runtime, &mut ctx)
         };
+        drop(module_eval);
+        // the dynamic import was already rejected
+        if let Some(err) = interrupthandler::take_timed_out_module(self) {
+            log::error!("dynamic import failed: {}", err);
+        }
         if flag < 0 {
             let e = unsafe { QuickJsRealmAdapter::get_exception(ctx) }
                 .unwrap_or_else(|| JsError::new_str("Unknown exception while running pending job"));
@@ -782,6 +1156,15 @@ impl QuickJsRuntimeAdapter {
         //        }
     }
 
+    /// the number of evals which are running, 1 in a native function which was called by a script that is evaluated from rust
+    /// and more when that function evals a script as well
+    ///
+    /// nested evals keep the deadlines of the evals they run in, code which may recurse can check this to refuse deep recursion early,
+    /// evals nested deeper than [max_execution_depth](crate::builder::QuickJsRuntimeBuilder::max_execution_depth) fail
+    pub fn execution_depth(&self) -> usize {
+        self.execution_depth.get()
+    }
+
     pub fn get_realm(&self, id: &str) -> Option<&QuickJsRealmAdapter> {
         if self.has_context(id) {
             Some(self.get_context(id))
@@ -5,12 +5,51 @@ use crate::quickjs_utils::typedarrays::is_typed_array;
 use crate::quickjs_utils::{arrays, errors, functions, primitives, promises};
 use crate::reflection::is_proxy_instance;
 use libquickjs_sys as q;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
 use std::ptr::null_mut;
 
+thread_local! {
+    // the generation of every live context, a freed JSContext ptr may be reused for a new context so the generation is used to tell them apart
+    static CONTEXT_GENERATIONS: RefCell<HashMap<usize, u32>> = RefCell::new(HashMap::new());
+    static NEXT_CONTEXT_GENERATION: Cell<u32> = Cell::new(1);
+    // values outlive their context, they are freed via the runtime after the context was destroyed
+    static CONTEXT_RUNTIME: Cell<*mut q::JSRuntime> = Cell::new(null_mut());
+}
+
+/// register a new context, values created for the context are valid until it is unregistered
+pub(crate) fn register_context(context: *mut q::JSContext) {
+    let generation = NEXT_CONTEXT_GENERATION.with(|next| {
+        let generation = next.get();
+        next.set(generation.wrapping_add(1).max(1));
+        generation
+    });
+    CONTEXT_GENERATIONS.with(|rc| rc.borrow_mut().insert(context as usize, generation));
+    CONTEXT_RUNTIME.with(|rt| rt.set(unsafe { q::JS_GetRuntime(context) }));
+}
+
+/// unregister a context after it was freed
+pub(crate) fn unregister_context(context: *mut q::JSContext) {
+    CONTEXT_GENERATIONS.with(|rc| rc.borrow_mut().remove(&(context as usize)));
+}
+
+/// get the generation of a live context, 0 means the context is not alive
+fn get_context_generation(context: *mut q::JSContext) -> u32 {
+    if context.is_null() {
+        return 0;
+    }
+    CONTEXT_GENERATIONS.with(|rc| rc.borrow().get(&(context as usize)).copied().unwrap_or(0))
+}
+
+/// a JSValue which decrements its refcount when dropped
+///
+/// adapters are not Clone so a copy of a reference is always explicit, use [dup](Self::dup) for a second reference and
+/// [into_raw](Self::into_raw) or [forget](Self::forget) when the reference is handed to the engine
 #[allow(clippy::upper_case_acronyms)]
 pub struct QuickJsValueAdapter {
     pub(crate) context: *mut q::JSContext,
+    context_generation: u32,
     value: q::JSValue,
     ref_ct_decr_on_drop: bool,
     label: String,
@@ -54,18 +93,6 @@ impl QuickJsValueAdapter {
     }
 }
 
-impl Clone for QuickJsValueAdapter {
-    fn clone(&self) -> Self {
-        Self::new(
-            self.context,
-            self.value,
-            true,
-            true,
-            format!("clone of {}", self.label).as_str(),
-        )
-    }
-}
-
 impl Drop for QuickJsValueAdapter {
     fn drop(&mut self) {
         //log::debug!(
@@ -81,6 +108,12 @@ impl Drop for QuickJsValueAdapter {
             // pointer.
 
             if self.ref_ct_decr_on_drop {
+                if !self.is_context_alive() {
+                    log::debug!(
+                        "dropping ref after its context was destroyed: {}",
+                        self.label
+                    );
+                }
                 if self.get_ref_count() <= 0 {
                     log::error!(
                         "dropping ref while refcount already 0, which is bad mmkay.. {}",
@@ -118,13 +151,24 @@ impl std::fmt::Debug for QuickJsValueAdapter {
 impl QuickJsValueAdapter {
     pub(crate) fn increment_ref_count(&self) {
         if self.get_tag() < 0 {
-            unsafe { libquickjs_sys::JS_DupValue(self.context, *self.borrow_value()) }
+            if self.is_context_alive() {
+                unsafe { libquickjs_sys::JS_DupValue(self.context, *self.borrow_value()) }
+            } else {
+                // the context ptr may already be freed, so use the runtime
+                let runtime = CONTEXT_RUNTIME.with(|rt| rt.get());
+                unsafe { libquickjs_sys::JS_DupValueRT(runtime, *self.borrow_value()) }
+            }
         }
     }
 
     pub(crate) fn decrement_ref_count(&self) {
         if self.get_tag() < 0 {
-            unsafe { libquickjs_sys::JS_FreeValue(self.context, *self.borrow_value()) }
+            if self.is_context_alive() {
+                unsafe { libquickjs_sys::JS_FreeValue(self.context, *self.borrow_value()) }
+            } else {
+                let runtime = CONTEXT_RUNTIME.with(|rt| rt.get());
+                unsafe { libquickjs_sys::JS_FreeValueRT(runtime, *self.borrow_value()) }
+            }
         }
     }
 
@@ -132,9 +176,24 @@ impl QuickJsValueAdapter {
         self.value.tag
     }
 
+    /// check if the context of this value was not destroyed, values without a context are always alive
+    pub fn is_context_alive(&self) -> bool {
+        self.context.is_null() || get_context_generation(self.context) == self.context_generation
+    }
+
+    /// fail with a ContextDestroyed error if the context of this value was destroyed
+    pub fn check_context_alive(&self) -> Result<(), JsError> {
+        if self.is_context_alive() {
+            Ok(())
+        } else {
+            Err(JsError::new_context_destroyed())
+        }
+    }
+
     pub fn new_no_context(value: q::JSValue, label: &str) -> Self {
         Self {
             context: null_mut(),
+            context_generation: 0,
             value,
             ref_ct_decr_on_drop: false,
             label: label.to_string(),
@@ -152,6 +211,7 @@ impl QuickJsValueAdapter {
 
         let s = Self {
             context,
+            context_generation: get_context_generation(context),
             value,
             ref_ct_decr_on_drop,
             label: label.to_string(),
@@ -174,7 +234,52 @@ impl QuickJsValueAdapter {
         }
     }
 
+    /// get a second adapter for the value, the refcount is incremented and the new adapter decrements it again when it is dropped
+    pub fn dup(&self) -> Self {
+        let s = Self {
+            context: self.context,
+            context_generation: self.context_generation,
+            value: self.value,
+            ref_ct_decr_on_drop: true,
+            label: format!("clone of {}", self.label),
+        };
+        s.increment_ref_count();
+        s
+    }
+
+    /// hand the reference of this adapter to the caller without changing the refcount, e.g. to return the value from a native
+    /// function or to pass it to an engine call which consumes it
+    ///
+    /// the caller owns one reference to the returned value and must free it or pass it on, an adapter which did not own a
+    /// reference (it was created with `ref_ct_decr_on_drop == false`) increments the refcount first
+    pub fn into_raw(mut self) -> q::JSValue {
+        if !self.ref_ct_decr_on_drop {
+            self.increment_ref_count();
+        }
+        self.ref_ct_decr_on_drop = false;
+        self.value
+    }
+
+    /// give up the reference of this adapter without decrementing the refcount
+    ///
+    /// use this when the reference was handed to an engine call which consumes it (e.g. `*value.borrow_value()` was passed to
+    /// `JS_SetProperty`) or when the engine already freed the value, an adapter which did not own a reference has nothing to
+    /// give up so the refcount stays as it was
+    pub fn forget(mut self) {
+        self.ref_ct_decr_on_drop = false;
+    }
+
+    /// take over a reference to a value, the refcount is decremented when the adapter is dropped
+    /// # Safety
+    /// the caller must own the reference (e.g. it was returned by [into_raw](Self::into_raw) or by an engine call which returns
+    /// a new value) and may not use or free the raw value afterwards, the value must belong to the context
+    pub unsafe fn from_raw(context: *mut q::JSContext, value: q::JSValue, label: &str) -> Self {
+        Self::new(context, value, false, true, label)
+    }
+
     /// borrow the value but first increment the refcount, this is useful for when the value is returned or passed to functions
+    ///
+    /// use [into_raw](Self::into_raw) when the adapter is not used afterwards
     pub fn clone_value_incr_rc(&self) -> q::JSValue {
         self.increment_ref_count();
         self.value
@@ -255,7 +360,7 @@ pub(crate) const TAG_BIG_INT: i64 = -10;
 #[cfg(feature = "quickjs-ng")]
 pub(crate) const TAG_BIG_INT: i64 = -9;
 //pub(crate) const TAG_BIG_FLOAT: i64 = -9;
-//pub(crate) const TAG_SYMBOL: i64 = -8;
+pub(crate) const TAG_SYMBOL: i64 = -8;
 pub(crate) const TAG_STRING: i64 = -7;
 pub(crate) const TAG_MODULE: i64 = -3;
 pub(crate) const TAG_FUNCTION_BYTECODE: i64 = -2;
@@ -264,6 +369,7 @@ pub(crate) const TAG_INT: i64 = 0;
 pub(crate) const TAG_BOOL: i64 = 1;
 pub(crate) const TAG_NULL: i64 = 2;
 pub(crate) const TAG_UNDEFINED: i64 = 3;
+pub(crate) const TAG_UNINITIALIZED: i64 = 4;
 pub(crate) const TAG_EXCEPTION: i64 = 6;
 pub(crate) const TAG_FLOAT64: i64 = 7;
 
@@ -396,7 +502,134 @@ impl QuickJsValueAdapter {
 #[cfg(test)]
 pub mod tests {
     use crate::facades::tests::init_test_rt;
-    use crate::jsutils::{JsValueType, Script};
+    use crate::jsutils::{JsErrorKind, JsValueType, Script};
+    use crate::quickjs_utils::{functions, objects};
+    use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+    use crate::quickjsvalueadapter::QuickJsValueAdapter;
+    use crate::values::JsValueFacade;
+    use futures::executor::block_on;
+    use libquickjs_sys as q;
+
+    #[test]
+    fn test_context_destroyed() {
+        let rt = init_test_rt();
+        rt.create_context("destroyed_realm")
+            .expect("could not create realm");
+        rt.exe_task_in_event_loop(|| {
+            let (obj, func) = QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+                let realm = q_js_rt.get_context("destroyed_realm");
+                let obj = realm
+                    .eval(Script::new("test_context_destroyed.js", "({a: 1});"))
+                    .expect("script failed");
+                let func = realm
+                    .eval(Script::new("test_context_destroyed.js", "(function() {});"))
+                    .expect("script failed");
+                (obj, func)
+            });
+            assert!(obj.is_context_alive());
+
+            QuickJsRuntimeAdapter::remove_context("destroyed_realm");
+
+            assert!(!obj.is_context_alive());
+            let err = unsafe { objects::get_property(obj.context, &obj, "a") }
+                .expect_err("get_property should fail");
+            assert!(err.is_context_destroyed());
+            let err = unsafe { functions::call_function(func.context, &func, &[], None) }
+                .expect_err("call_function should fail");
+            assert!(err.is_context_destroyed());
+        });
+
+        rt.create_context("destroyed_realm")
+            .expect("could not create realm");
+        let res = rt
+            .eval_sync(
+                Some("destroyed_realm"),
+                Script::new("test_context_destroyed.js", "({a: 1});"),
+            )
+            .expect("script failed");
+        rt.drop_context("destroyed_realm")
+            .expect("could not drop destroyed_realm");
+        // a new realm with the same id should not be mistaken for the dropped one
+        rt.create_context("destroyed_realm")
+            .expect("could not create realm");
+        match res {
+            JsValueFacade::JsObject { cached_object } => {
+                assert!(!cached_object.is_realm_alive());
+                let err = block_on(cached_object.get_serde_value())
+                    .expect_err("get_serde_value should fail");
+                assert_eq!(err.kind(), JsErrorKind::ContextDestroyed);
+                let err = cached_object
+                    .with_obj_void(|_realm, _obj| {})
+                    .expect_err("with_obj_void should fail");
+                assert_eq!(err.kind(), JsErrorKind::ContextDestroyed);
+            }
+            _ => panic!("not an object"),
+        }
+        rt.drop_context("destroyed_realm")
+            .expect("could not drop destroyed_realm");
+    }
+
+    #[test]
+    fn test_ownership_transfers() {
+        let rt = init_test_rt();
+        rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let realm = q_js_rt.get_main_realm();
+            let obj = realm
+                .eval(Script::new("test_ownership.js", "({a: 1});"))
+                .expect("script failed");
+            let base = obj.get_ref_count();
+
+            // dup increments and the copy decrements when dropped
+            let copy = obj.dup();
+            assert_eq!(obj.get_ref_count(), base + 1);
+            drop(copy);
+            assert_eq!(obj.get_ref_count(), base);
+
+            // into_raw keeps the reference of the adapter, from_raw takes it over again
+            let raw = obj.dup().into_raw();
+            assert_eq!(obj.get_ref_count(), base + 1);
+            let restored = unsafe { QuickJsValueAdapter::from_raw(realm.context, raw, "restored") };
+            assert_eq!(obj.get_ref_count(), base + 1);
+            drop(restored);
+            assert_eq!(obj.get_ref_count(), base);
+
+            // an adapter which does not own a reference gets one for the caller
+            let borrowed = QuickJsValueAdapter::new(
+                realm.context,
+                *obj.borrow_value(),
+                false,
+                false,
+                "borrowed",
+            );
+            let raw = borrowed.into_raw();
+            assert_eq!(obj.get_ref_count(), base + 1);
+            drop(unsafe { QuickJsValueAdapter::from_raw(realm.context, raw, "owned") });
+            assert_eq!(obj.get_ref_count(), base);
+
+            // a value which is consumed by the engine
+            let global = crate::quickjs_utils::get_global_q(realm);
+            unsafe {
+                objects::set_property(realm.context, &global, "kept", &obj)
+                    .expect("set_property failed");
+            }
+            assert_eq!(obj.get_ref_count(), base + 1);
+
+            // forget gives up the reference which was consumed by the engine
+            let consumed = obj.dup();
+            let name = std::ffi::CString::new("forgotten").expect("invalid name");
+            let res = unsafe {
+                q::JS_SetPropertyStr(
+                    realm.context,
+                    *global.borrow_value(),
+                    name.as_ptr(),
+                    *consumed.borrow_value(),
+                )
+            };
+            assert_eq!(res, 1);
+            consumed.forget();
+            assert_eq!(obj.get_ref_count(), base + 2);
+        });
+    }
 
     #[test]
     fn test_to_str() {
@@ -7,6 +7,7 @@ use crate::quickjs_utils::objects::{create_object_q, set_property_q};
 use crate::quickjs_utils::primitives::from_bool;
 use crate::quickjs_utils::{functions, objects, parse_args, primitives};
 use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
 use crate::quickjsvalueadapter::QuickJsValueAdapter;
 use crate::reflection::{get_proxy, get_proxy_instance_info, Proxy};
 use libquickjs_sys as q;
@@ -239,26 +240,76 @@ pub fn dispatch_static_event(
     event_id: &str,
     event: QuickJsValueAdapter,
 ) -> Result<bool, JsError> {
-    with_static_listener_map(
-        q_ctx,
-        proxy_class_name,
-        event_id,
-        |listeners| -> Result<(), JsError> {
-            let func_args = [event];
-            for entry in listeners {
-                let listener = entry.0;
-                let _res = functions::call_function_q(q_ctx, listener, &func_args, None)?;
+    // copy the listeners so a listener may add or remove listeners
+    let listeners: Vec<QuickJsValueAdapter> =
+        with_static_listener_map(q_ctx, proxy_class_name, event_id, |listeners| {
+            listeners.keys().map(QuickJsValueAdapter::dup).collect()
+        });
+    let func_args = [event];
+    for listener in &listeners {
+        let _res = functions::call_function_q(q_ctx, listener, &func_args, None)?;
 
-                // todo chekc if _res is bool, for cancel and such
-                // and if event is cancelabble and preventDefault was called and such
-            }
-            Ok(())
-        },
-    )?;
+        // todo chekc if _res is bool, for cancel and such
+        // and if event is cancelabble and preventDefault was called and such
+    }
 
     Ok(true)
 }
 
+/// the number of static listeners of a Proxy class in a realm, for a single event or for all events
+pub fn static_listener_count(
+    q_ctx: &QuickJsRealmAdapter,
+    proxy_class_name: &str,
+    event_id: Option<&str>,
+) -> usize {
+    let static_listeners = &*q_ctx.proxy_static_event_listeners.borrow();
+    match static_listeners.get(proxy_class_name) {
+        Some(event_map) => match event_id {
+            Some(event_id) => event_map.get(event_id).map(|l| l.len()).unwrap_or(0),
+            None => event_map.values().map(|l| l.len()).sum(),
+        },
+        None => 0,
+    }
+}
+
+/// dispatch an Event on a Proxy class in every realm in which the class is installed as a static event target,
+/// see [dispatch_proxy_static_event](crate::facades::QuickJsRuntimeFacade::dispatch_proxy_static_event)
+pub(crate) fn dispatch_static_event_in_all_realms(
+    q_js_rt: &QuickJsRuntimeAdapter,
+    proxy_class_name: &str,
+    event_id: &str,
+    payload: &serde_json::Value,
+) -> HashMap<String, JsError> {
+    let mut errors = HashMap::new();
+    for (realm_id, realm) in &q_js_rt.contexts {
+        let is_installed = realm
+            .proxy_registry
+            .borrow()
+            .get(proxy_class_name)
+            .map(|proxy| proxy.is_static_event_target)
+            .unwrap_or(false);
+        if !is_installed {
+            continue;
+        }
+        // the delivery in a realm does not depend on the listeners of other realms
+        let res = realm
+            .serde_value_to_value_adapter(payload.clone())
+            .and_then(|event| dispatch_static_event(realm, proxy_class_name, event_id, event));
+        if let Err(err) = res {
+            log::error!(
+                "[{}] static event {} of {} failed: {}",
+                realm_id,
+                event_id,
+                proxy_class_name,
+                err
+            );
+            errors.insert(realm_id.clone(), err);
+        }
+    }
+    q_js_rt.run_pending_jobs_if_any();
+    errors
+}
+
 pub fn _set_event_bubble_target() {
     unimplemented!()
 }
@@ -323,11 +374,11 @@ unsafe extern "C" fn ext_add_event_listener(
             Err(JsError::new_str("addEventListener requires at least 2 arguments (eventId: String and Listener: Function"))
         } else {
             let event_id = primitives::to_string_q(q_ctx, &args[0])?;
-            let listener_func = args[1].clone();
+            let listener_func = args[1].dup();
 
             // use the passed options arg or create a new obj
             let options_obj = if args.len() == 3 && args[2].is_object() {
-                args[2].clone()
+                args[2].dup()
             } else {
                 create_object_q(q_ctx)?
             };
@@ -372,7 +423,7 @@ unsafe extern "C" fn ext_remove_event_listener(
             Err(JsError::new_str("removeEventListener requires at least 2 arguments (eventId: String and Listener: Function"))
         } else {
             let event_id = primitives::to_string_q(q_ctx, &args[0])?;
-            let listener_func = args[1].clone();
+            let listener_func = args[1].dup();
 
             remove_event_listener(
                 q_ctx,
@@ -411,7 +462,7 @@ unsafe extern "C" fn ext_dispatch_event(
             ))
         } else {
             let event_id = primitives::to_string_q(q_ctx, &args[0])?;
-            let evt_obj = args[1].clone();
+            let evt_obj = args[1].dup();
 
             let proxy = get_proxy(q_ctx, proxy_info.class_name.as_str()).unwrap();
 
@@ -423,7 +474,7 @@ unsafe extern "C" fn ext_dispatch_event(
     match res {
         Ok(res) => {
             let b_ref = from_bool(res);
-            b_ref.clone_value_incr_rc()
+            b_ref.into_raw()
         }
         Err(e) => QuickJsRealmAdapter::report_ex_ctx(ctx, format!("{e}").as_str()),
     }
@@ -459,11 +510,11 @@ unsafe extern "C" fn ext_add_static_event_listener(
             Err(JsError::new_str("addEventListener requires at least 2 arguments (eventId: String and Listener: Function"))
         } else {
             let event_id = primitives::to_string_q(q_ctx, &args[0])?;
-            let listener_func = args[1].clone();
+            let listener_func = args[1].dup();
 
             // use the passed options arg or create a new obj
             let options_obj = if args.len() == 3 && args[2].is_object() {
-                args[2].clone()
+                args[2].dup()
             } else {
                 create_object_q(q_ctx)?
             };
@@ -507,7 +558,7 @@ unsafe extern "C" fn ext_remove_static_event_listener(
             Err(JsError::new_str("removeEventListener requires at least 2 arguments (eventId: String and Listener: Function"))
         } else {
             let event_id = primitives::to_string_q(q_ctx, &args[0])?;
-            let listener_func = args[1].clone();
+            let listener_func = args[1].dup();
 
             remove_static_event_listener(
                 q_ctx,
@@ -545,7 +596,7 @@ unsafe extern "C" fn ext_dispatch_static_event(
             ))
         } else {
             let event_id = primitives::to_string_q(q_ctx, &args[0])?;
-            let evt_obj = args[1].clone();
+            let evt_obj = args[1].dup();
 
             let res =
                 dispatch_static_event(q_ctx, proxy_name.as_str(), event_id.as_str(), evt_obj)?;
@@ -556,7 +607,7 @@ unsafe extern "C" fn ext_dispatch_static_event(
     match res {
         Ok(res) => {
             let b_ref = from_bool(res);
-            b_ref.clone_value_incr_rc()
+            b_ref.into_raw()
         }
         Err(e) => QuickJsRealmAdapter::report_ex_ctx(ctx, format!("{e}").as_str()),
     }
@@ -571,6 +622,7 @@ pub mod tests {
     use crate::quickjs_utils::primitives::to_i32;
     use crate::reflection::eventtarget::dispatch_event;
     use crate::reflection::{get_proxy, Proxy};
+    use futures::executor::block_on;
     use std::sync::{Arc, Mutex};
 
     #[test]
@@ -670,4 +722,65 @@ pub mod tests {
             assert_eq!(target_ref.get_ref_count(), 2); // one for me one for global
         });
     }
+
+    #[test]
+    fn test_static_event_fan_out() {
+        let rt = init_test_rt();
+        for realm_id in ["static_evt_a", "static_evt_b", "static_evt_c"] {
+            rt.create_context(realm_id).expect("could not create realm");
+        }
+        for realm_id in ["static_evt_a", "static_evt_b"] {
+            rt.loop_realm_sync(Some(realm_id), |_rt, realm| {
+                Proxy::new()
+                    .namespace(&["test"])
+                    .name("Settings")
+                    .static_event_target()
+                    .install(realm, true)
+                    .map(|_| ())
+            })
+            .expect("proxy failed");
+        }
+        rt.eval_sync(
+            Some("static_evt_a"),
+            Script::new(
+                "test_static_event.js",
+                "globalThis.got = []; \
+                 test.Settings.addEventListener('configChanged', (evt) => { got.push(evt.key); test.Settings.addEventListener('configChanged', () => {}); });",
+            ),
+        )
+        .expect("script failed");
+        rt.eval_sync(
+            Some("static_evt_b"),
+            Script::new(
+                "test_static_event.js",
+                "test.Settings.addEventListener('configChanged', (evt) => { throw Error('listener failed'); });",
+            ),
+        )
+        .expect("script failed");
+        assert_eq!(rt.proxy_static_listener_count("test.Settings", None), 2);
+
+        let errors = block_on(rt.dispatch_proxy_static_event(
+            "test.Settings",
+            "configChanged",
+            serde_json::json!({"key": "level"}),
+        ));
+        // realm b failed, realm a still received the event and realm c does not have the class
+        assert_eq!(errors.len(), 1);
+        assert!(errors.contains_key("static_evt_b"));
+        let got = rt
+            .eval_sync(
+                Some("static_evt_a"),
+                Script::new("test_static_event.js", "got.join(',')"),
+            )
+            .expect("script failed");
+        assert_eq!(got.get_str(), "level");
+        assert_eq!(
+            rt.proxy_static_listener_count("test.Settings", Some("configChanged")),
+            3
+        );
+        assert_eq!(
+            rt.proxy_static_listener_count("test.Settings", Some("other")),
+            0
+        );
+    }
 }
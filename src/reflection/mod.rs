@@ -1,4 +1,5 @@
 //! utils for implementing proxy classes which can be used to use rust structs from JS (define method/getters/setters/etc)
+//! the commonly used parts are re-exported in the [prelude](crate::prelude), this path may change in a minor version
 
 use crate::jsutils::JsError;
 use crate::quickjs_utils;
@@ -9,17 +10,19 @@ use crate::quickjs_utils::{atoms, errors, functions, objects, parse_args, primit
 use crate::quickjsrealmadapter::QuickJsRealmAdapter;
 use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
 use crate::quickjsvalueadapter::QuickJsValueAdapter;
+use crate::reflection::overloads::{Overloads, Signature};
 use libquickjs_sys as q;
 use log::trace;
 use rand::{thread_rng, Rng};
 use std::cell::RefCell;
-use std::collections::HashMap;
-use std::os::raw::{c_char, c_void};
+use std::collections::{HashMap, HashSet};
+use std::os::raw::{c_char, c_int, c_void};
 use std::rc::Rc;
 
 pub type JsProxyInstanceId = usize;
 
 pub mod eventtarget;
+pub mod overloads;
 
 pub type ProxyConstructor = dyn Fn(
         &QuickJsRuntimeAdapter,
@@ -29,6 +32,10 @@ pub type ProxyConstructor = dyn Fn(
     ) -> Result<(), JsError>
     + 'static;
 pub type ProxyFinalizer = dyn Fn(&QuickJsRuntimeAdapter, &QuickJsRealmAdapter, usize) + 'static;
+pub type ProxyDisposer =
+    dyn Fn(&QuickJsRuntimeAdapter, &QuickJsRealmAdapter, usize) -> Result<(), JsError> + 'static;
+pub type ProxyAsyncDisposer = dyn Fn(&QuickJsRuntimeAdapter, &QuickJsRealmAdapter, usize) -> Result<QuickJsValueAdapter, JsError>
+    + 'static;
 pub type ProxyMethod = dyn Fn(
         &QuickJsRuntimeAdapter,
         &QuickJsRealmAdapter,
@@ -66,6 +73,10 @@ pub type ProxyCatchAllGetter = dyn Fn(
         &str,
     ) -> Result<QuickJsValueAdapter, JsError>
     + 'static;
+pub type ProxyCatchAllDeleter = dyn Fn(&QuickJsRuntimeAdapter, &QuickJsRealmAdapter, &usize, &str) -> Result<bool, JsError>
+    + 'static;
+pub type ProxyCatchAllOwnKeys = dyn Fn(&QuickJsRuntimeAdapter, &QuickJsRealmAdapter, &usize) -> Result<Vec<String>, JsError>
+    + 'static;
 pub type ProxySetter = dyn Fn(
         &QuickJsRuntimeAdapter,
         &QuickJsRealmAdapter,
@@ -92,15 +103,16 @@ thread_local! {
         get_own_property_names: None,
         delete_property: None,
         define_own_property: None,
-        has_property: Some(proxy_static_has_prop),
+        // the static members are not known here, so `in` only sees the own and inherited props
+        has_property: None,
         get_property: Some(proxy_static_get_prop),
         set_property: Some(proxy_static_set_prop),
     });
 
     static PROXY_INSTANCE_EXOTIC: RefCell<q::JSClassExoticMethods> = RefCell::new(q::JSClassExoticMethods {
-        get_own_property: None,
-        get_own_property_names: None,
-        delete_property: None,
+        get_own_property: Some(proxy_instance_get_own_prop),
+        get_own_property_names: Some(proxy_instance_get_own_prop_names),
+        delete_property: Some(proxy_instance_delete_prop),
         define_own_property: None,
         has_property: Some(proxy_instance_has_prop),
         get_property: Some(proxy_instance_get_prop),
@@ -189,7 +201,7 @@ fn next_id(proxy: &Proxy) -> usize {
     }
     let mut rng = thread_rng();
     let mut r: usize = rng.gen();
-    while mappings.contains_key(&r) {
+    while mappings.contains(&r) {
         r += 1;
     }
     r
@@ -285,20 +297,40 @@ pub struct Proxy {
     namespace: Option<Vec<String>>,
     pub(crate) constructor: Option<Box<ProxyConstructor>>,
     finalizers: Vec<Box<ProxyFinalizer>>,
+    disposer: Option<Box<ProxyDisposer>>,
+    async_disposer: Option<Box<ProxyAsyncDisposer>>,
+    // instances which were disposed and not finalized yet
+    disposed_instances: Rc<RefCell<HashSet<usize>>>,
     methods: HashMap<String, Box<ProxyMethod>>,
     native_methods: HashMap<String, ProxyNativeMethod>,
     static_methods: HashMap<String, Box<ProxyStaticMethod>>,
+    method_overloads: HashMap<String, Vec<(Signature, Box<ProxyMethod>)>>,
+    static_method_overloads: HashMap<String, Vec<(Signature, Box<ProxyStaticMethod>)>>,
     static_native_methods: HashMap<String, ProxyStaticNativeMethod>,
-    static_getters_setters: HashMap<String, (Box<ProxyStaticGetter>, Box<ProxyStaticSetter>)>,
-    getters_setters: HashMap<String, (Box<ProxyGetter>, Box<ProxySetter>)>,
-    catch_all: Option<(Box<ProxyCatchAllGetter>, Box<ProxyCatchAllSetter>)>,
+    // a getter without a setter is read-only
+    static_getters_setters:
+        HashMap<String, (Box<ProxyStaticGetter>, Option<Box<ProxyStaticSetter>>)>,
+    getters_setters: HashMap<String, (Box<ProxyGetter>, Option<Box<ProxySetter>>)>,
+    catch_all: CatchAll,
     static_catch_all: Option<(
         Box<ProxyStaticCatchAllGetter>,
         Box<ProxyStaticCatchAllSetter>,
     )>,
     is_event_target: bool,
     is_static_event_target: bool,
-    pub(crate) proxy_instance_id_mappings: RefCell<HashMap<usize, Box<ProxyInstanceInfo>>>,
+    // members which were added more than once, reported by validate
+    duplicate_members: Vec<String>,
+    // the ids of the instances which were not finalized yet
+    pub(crate) proxy_instance_id_mappings: RefCell<HashSet<usize>>,
+}
+
+// the handlers for the properties of an instance which are not a member (method, getter, ...) of its Proxy
+#[derive(Default)]
+struct CatchAll {
+    getter: Option<Box<ProxyCatchAllGetter>>,
+    setter: Option<Box<ProxyCatchAllSetter>>,
+    deleter: Option<Box<ProxyCatchAllDeleter>>,
+    own_keys: Option<Box<ProxyCatchAllOwnKeys>>,
 }
 
 impl Default for crate::reflection::Proxy {
@@ -313,6 +345,38 @@ pub fn get_proxy(q_ctx: &QuickJsRealmAdapter, class_name: &str) -> Option<Rc<Pro
     registry.get(class_name).cloned()
 }
 
+/// report names which are used by more than one kind of member and reserved names
+fn check_members(
+    problems: &mut Vec<String>,
+    mut members: Vec<(&str, &str)>,
+    member_type: &str,
+    reserved: &[&str],
+) {
+    members.sort();
+    members.dedup();
+    let mut index = 0;
+    while index < members.len() {
+        let name = members[index].0;
+        let kinds: Vec<&str> = members[index..]
+            .iter()
+            .take_while(|(n, _)| *n == name)
+            .map(|(_, kind)| *kind)
+            .collect();
+        if kinds.len() > 1 {
+            problems.push(format!(
+                "{member_type} member {name} is defined as {}",
+                kinds.join(" and as ")
+            ));
+        }
+        if reserved.contains(&name) {
+            problems.push(format!(
+                "{name} is a reserved name and can not be used for {member_type} members"
+            ));
+        }
+        index += kinds.len();
+    }
+}
+
 impl Proxy {
     #[allow(dead_code)]
     pub fn new() -> Self {
@@ -321,16 +385,22 @@ impl Proxy {
             namespace: None,
             constructor: None,
             finalizers: Default::default(),
+            disposer: None,
+            async_disposer: None,
+            disposed_instances: Default::default(),
             methods: Default::default(),
             native_methods: Default::default(),
             static_methods: Default::default(),
+            method_overloads: Default::default(),
+            static_method_overloads: Default::default(),
             static_native_methods: Default::default(),
             static_getters_setters: Default::default(),
             getters_setters: Default::default(),
-            catch_all: None,
+            catch_all: Default::default(),
             static_catch_all: None,
             is_event_target: false,
             is_static_event_target: false,
+            duplicate_members: vec![],
             proxy_instance_id_mappings: RefCell::new(Default::default()),
         }
     }
@@ -395,7 +465,8 @@ impl Proxy {
         self
     }
     /// add a finalizer for the Proxy class
-    /// this will be called when an instance of the Proxy class is dropped or garbage collected
+    /// this will be called when an instance of the Proxy class is dropped or garbage collected, it is called exactly once per instance
+    /// instances which are still alive when their realm is dropped are finalized before the realm is freed
     pub fn finalizer<C>(mut self, finalizer: C) -> Self
     where
         C: Fn(&QuickJsRuntimeAdapter, &QuickJsRealmAdapter, usize) + 'static,
@@ -403,6 +474,43 @@ impl Proxy {
         self.finalizers.push(Box::new(finalizer));
         self
     }
+    /// add a dispose handler, it is installed as `Symbol.dispose` so `using inst = new MyClass();` disposes the instance at the end of the block
+    ///
+    /// the handler is called at most once per instance, also when it is called again from script, the finalizers still run when the
+    /// instance is garbage collected and can check [is_disposed](Self::is_disposed)
+    ///
+    /// on engines without explicit resource management `Symbol.dispose` is added to the realm, scripts then call `inst[Symbol.dispose]()` themselves
+    pub fn dispose<D>(mut self, disposer: D) -> Self
+    where
+        D: Fn(&QuickJsRuntimeAdapter, &QuickJsRealmAdapter, usize) -> Result<(), JsError> + 'static,
+    {
+        if self.disposer.replace(Box::new(disposer)).is_some() {
+            self.duplicate_members.push("dispose handler".to_string());
+        }
+        self
+    }
+    /// add an async dispose handler, it is installed as `Symbol.asyncDispose` for `await using` and should return a promise
+    ///
+    /// an instance is disposed once, by whichever of the dispose handlers is called first
+    pub fn async_dispose<D>(mut self, disposer: D) -> Self
+    where
+        D: Fn(
+                &QuickJsRuntimeAdapter,
+                &QuickJsRealmAdapter,
+                usize,
+            ) -> Result<QuickJsValueAdapter, JsError>
+            + 'static,
+    {
+        if self.async_disposer.replace(Box::new(disposer)).is_some() {
+            self.duplicate_members
+                .push("async dispose handler".to_string());
+        }
+        self
+    }
+    /// check if an instance was disposed, e.g. from a finalizer
+    pub fn is_disposed(&self, instance_id: usize) -> bool {
+        self.disposed_instances.borrow().contains(&instance_id)
+    }
     /// add a method to the Proxy class, this method will be available as a member of instances of the Proxy class
     pub fn method<M>(mut self, name: &str, method: M) -> Self
     where
@@ -414,12 +522,24 @@ impl Proxy {
             ) -> Result<QuickJsValueAdapter, JsError>
             + 'static,
     {
-        self.methods.insert(name.to_string(), Box::new(method));
+        if self
+            .methods
+            .insert(name.to_string(), Box::new(method))
+            .is_some()
+        {
+            self.duplicate_members.push(format!("method {name}"));
+        }
         self
     }
     /// add a method to the Proxy class, this method will be available as a member of instances of the Proxy class
     pub fn native_method(mut self, name: &str, method: ProxyNativeMethod) -> Self {
-        self.native_methods.insert(name.to_string(), method);
+        if self
+            .native_methods
+            .insert(name.to_string(), method)
+            .is_some()
+        {
+            self.duplicate_members.push(format!("native method {name}"));
+        }
         self
     }
     /// add a static method to the Proxy class, this method will be available as a member of the Proxy class itself
@@ -432,18 +552,65 @@ impl Proxy {
             ) -> Result<QuickJsValueAdapter, JsError>
             + 'static,
     {
-        self.static_methods
-            .insert(name.to_string(), Box::new(method));
+        if self
+            .static_methods
+            .insert(name.to_string(), Box::new(method))
+            .is_some()
+        {
+            self.duplicate_members.push(format!("static method {name}"));
+        }
+        self
+    }
+    /// add an overload of a method to the Proxy class, see [overloads](crate::reflection::overloads)
+    ///
+    /// when the method is called the first overload whose signature matches the arguments is invoked, a TypeError is thrown when no overload matches
+    pub fn method_overload<M>(mut self, name: &str, signature: Signature, method: M) -> Self
+    where
+        M: Fn(
+                &QuickJsRuntimeAdapter,
+                &QuickJsRealmAdapter,
+                &usize,
+                &[QuickJsValueAdapter],
+            ) -> Result<QuickJsValueAdapter, JsError>
+            + 'static,
+    {
+        self.method_overloads
+            .entry(name.to_string())
+            .or_default()
+            .push((signature, Box::new(method)));
+        self
+    }
+    /// add an overload of a static method to the Proxy class, see [overloads](crate::reflection::overloads)
+    pub fn static_method_overload<M>(mut self, name: &str, signature: Signature, method: M) -> Self
+    where
+        M: Fn(
+                &QuickJsRuntimeAdapter,
+                &QuickJsRealmAdapter,
+                &[QuickJsValueAdapter],
+            ) -> Result<QuickJsValueAdapter, JsError>
+            + 'static,
+    {
+        self.static_method_overloads
+            .entry(name.to_string())
+            .or_default()
+            .push((signature, Box::new(method)));
         self
     }
     /// add a static method to the Proxy class, this method will be available as a member of the Proxy class itself
     pub fn static_native_method(mut self, name: &str, method: ProxyStaticNativeMethod) -> Self {
-        self.static_native_methods.insert(name.to_string(), method);
+        if self
+            .static_native_methods
+            .insert(name.to_string(), method)
+            .is_some()
+        {
+            self.duplicate_members
+                .push(format!("static native method {name}"));
+        }
         self
     }
 
     /// add a static getter and setter to the Proxy class
-    pub fn static_getter_setter<G, S>(mut self, name: &str, getter: G, setter: S) -> Self
+    pub fn static_getter_setter<G, S>(self, name: &str, getter: G, setter: S) -> Self
     where
         G: Fn(&QuickJsRuntimeAdapter, &QuickJsRealmAdapter) -> Result<QuickJsValueAdapter, JsError>
             + 'static,
@@ -454,8 +621,30 @@ impl Proxy {
             ) -> Result<(), JsError>
             + 'static,
     {
-        self.static_getters_setters
-            .insert(name.to_string(), (Box::new(getter), Box::new(setter)));
+        self.add_static_getter_setter(name, Box::new(getter), Some(Box::new(setter)))
+    }
+    /// add a static getter without a setter to the Proxy class, assigning to it throws a TypeError
+    pub fn static_getter<G>(self, name: &str, getter: G) -> Self
+    where
+        G: Fn(&QuickJsRuntimeAdapter, &QuickJsRealmAdapter) -> Result<QuickJsValueAdapter, JsError>
+            + 'static,
+    {
+        self.add_static_getter_setter(name, Box::new(getter), None)
+    }
+    fn add_static_getter_setter(
+        mut self,
+        name: &str,
+        getter: Box<ProxyStaticGetter>,
+        setter: Option<Box<ProxyStaticSetter>>,
+    ) -> Self {
+        if self
+            .static_getters_setters
+            .insert(name.to_string(), (getter, setter))
+            .is_some()
+        {
+            self.duplicate_members
+                .push(format!("static getter/setter {name}"));
+        }
         self
     }
     /// add a static getter and setter to the Proxy class
@@ -479,7 +668,8 @@ impl Proxy {
         self
     }
     /// add a getter and setter to the Proxy class, these will be available as a member of an instance of this Proxy class
-    pub fn getter_setter<G, S>(mut self, name: &str, getter: G, setter: S) -> Self
+    /// when the setter returns an Err it is thrown in script as a TypeError
+    pub fn getter_setter<G, S>(self, name: &str, getter: G, setter: S) -> Self
     where
         G: Fn(
                 &QuickJsRuntimeAdapter,
@@ -495,11 +685,9 @@ impl Proxy {
             ) -> Result<(), JsError>
             + 'static,
     {
-        self.getters_setters
-            .insert(name.to_string(), (Box::new(getter), Box::new(setter)));
-        self
+        self.add_getter_setter(name, Box::new(getter), Some(Box::new(setter)))
     }
-    /// add a getter and setter to the Proxy class, these will be available as a member of an instance of this Proxy class
+    /// add a getter without a setter to the Proxy class, assigning to it throws a TypeError
     pub fn getter<G>(self, name: &str, getter: G) -> Self
     where
         G: Fn(
@@ -509,7 +697,22 @@ impl Proxy {
             ) -> Result<QuickJsValueAdapter, JsError>
             + 'static,
     {
-        self.getter_setter(name, getter, |_rt, _realm, _id, _val| Ok(()))
+        self.add_getter_setter(name, Box::new(getter), None)
+    }
+    fn add_getter_setter(
+        mut self,
+        name: &str,
+        getter: Box<ProxyGetter>,
+        setter: Option<Box<ProxySetter>>,
+    ) -> Self {
+        if self
+            .getters_setters
+            .insert(name.to_string(), (getter, setter))
+            .is_some()
+        {
+            self.duplicate_members.push(format!("getter/setter {name}"));
+        }
+        self
     }
     /// add a catchall getter and setter to the Proxy class, these will be used for properties which are not specifically defined as getter, setter or method in this Proxy
     pub fn catch_all_getter_setter<G, S>(mut self, getter: G, setter: S) -> Self
@@ -530,10 +733,124 @@ impl Proxy {
             ) -> Result<(), JsError>
             + 'static,
     {
-        self.catch_all = Some((Box::new(getter), Box::new(setter)));
-
+        self.catch_all.getter = Some(Box::new(getter));
+        self.catch_all.setter = Some(Box::new(setter));
+        self
+    }
+    /// add a catchall getter to the Proxy class, it is called with the name of every property which is not a member of this Proxy
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+    /// use quickjs_runtime::jsutils::Script;
+    /// use quickjs_runtime::reflection::Proxy;
+    /// use std::cell::RefCell;
+    /// use std::collections::HashMap;
+    /// use std::rc::Rc;
+    /// let rt = QuickJsRuntimeBuilder::new().build();
+    /// rt.exe_rt_task_in_event_loop(|q_js_rt| {
+    ///     let q_ctx = q_js_rt.get_main_realm();
+    ///     let entries: Rc<RefCell<HashMap<String, i32>>> = Default::default();
+    ///     let (e1, e2, e3) = (entries.clone(), entries.clone(), entries.clone());
+    ///     Proxy::new()
+    ///         .name("Dict")
+    ///         .constructor(|_rt, _realm, _id, _args| Ok(()))
+    ///         .catch_all_getter(move |_rt, realm, _id, name| match e1.borrow().get(name) {
+    ///             Some(val) => realm.create_i32(*val),
+    ///             None => realm.create_undefined(),
+    ///         })
+    ///         .catch_all_setter(move |_rt, _realm, _id, name, val| {
+    ///             e2.borrow_mut().insert(name.to_string(), val.to_i32());
+    ///             Ok(())
+    ///         })
+    ///         .catch_all_own_keys(move |_rt, _realm, _id| Ok(e3.borrow().keys().cloned().collect()))
+    ///         .install(q_ctx, true)
+    ///         .expect("could not install Dict");
+    ///     let res = q_ctx.eval(Script::new("dict.js", "let d = new Dict(); d.a = 1; Object.keys(d).join();")).ok().unwrap();
+    ///     assert_eq!(res.to_string().ok().unwrap(), "a");
+    /// })
+    /// ```
+    pub fn catch_all_getter<G>(mut self, getter: G) -> Self
+    where
+        G: Fn(
+                &QuickJsRuntimeAdapter,
+                &QuickJsRealmAdapter,
+                &usize,
+                &str,
+            ) -> Result<QuickJsValueAdapter, JsError>
+            + 'static,
+    {
+        self.catch_all.getter = Some(Box::new(getter));
         self
     }
+    /// add a catchall setter to the Proxy class, it is called when a property which is not a member of this Proxy is set
+    pub fn catch_all_setter<S>(mut self, setter: S) -> Self
+    where
+        S: Fn(
+                &QuickJsRuntimeAdapter,
+                &QuickJsRealmAdapter,
+                &usize,
+                &str,
+                QuickJsValueAdapter,
+            ) -> Result<(), JsError>
+            + 'static,
+    {
+        self.catch_all.setter = Some(Box::new(setter));
+        self
+    }
+    /// add a catchall deleter to the Proxy class, it is called for `delete instance.name;` and should return false if the property can not be deleted
+    pub fn catch_all_deleter<D>(mut self, deleter: D) -> Self
+    where
+        D: Fn(&QuickJsRuntimeAdapter, &QuickJsRealmAdapter, &usize, &str) -> Result<bool, JsError>
+            + 'static,
+    {
+        self.catch_all.deleter = Some(Box::new(deleter));
+        self
+    }
+    /// add a callback which lists the catchall properties of an instance, these are used for `Object.keys()`, `for in` and the `in` operator
+    ///
+    /// without it a property exists when the catchall getter does not return undefined
+    pub fn catch_all_own_keys<K>(mut self, own_keys: K) -> Self
+    where
+        K: Fn(&QuickJsRuntimeAdapter, &QuickJsRealmAdapter, &usize) -> Result<Vec<String>, JsError>
+            + 'static,
+    {
+        self.catch_all.own_keys = Some(Box::new(own_keys));
+        self
+    }
+    // members take precedence over the catchall handlers
+    fn has_instance_member(&self, name: &str) -> bool {
+        self.methods.contains_key(name)
+            || self.native_methods.contains_key(name)
+            || self.getters_setters.contains_key(name)
+    }
+    // the value of a catchall property, None when the instance does not have it
+    fn catch_all_value(
+        &self,
+        rt: &QuickJsRuntimeAdapter,
+        realm: &QuickJsRealmAdapter,
+        id: usize,
+        name: &str,
+    ) -> Result<Option<QuickJsValueAdapter>, JsError> {
+        if self.has_instance_member(name) {
+            return Ok(None);
+        }
+        let listed = match &self.catch_all.own_keys {
+            Some(own_keys) => Some(own_keys(rt, realm, &id)?.iter().any(|key| key == name)),
+            None => None,
+        };
+        if listed == Some(false) {
+            return Ok(None);
+        }
+        let value = match &self.catch_all.getter {
+            Some(getter) => getter(rt, realm, &id, name)?,
+            None => realm.create_undefined()?,
+        };
+        if listed.is_none() && value.is_undefined() {
+            Ok(None)
+        } else {
+            Ok(Some(value))
+        }
+    }
     /// indicate the Proxy class should implement the EventTarget interface, this will result in the addEventListener, removeEventListener and dispatchEvent methods to be available on instances of the Proxy class
     pub fn event_target(mut self) -> Self {
         self.is_event_target = true;
@@ -544,16 +861,151 @@ impl Proxy {
         self.is_static_event_target = true;
         self
     }
+    /// check the definition of the Proxy class, this is also done by [install](Self::install)
+    ///
+    /// the error lists every problem: a missing name, members which were added more than once, names which are used by more than one kind of member
+    /// (e.g. a getter and a method) and reserved names (`constructor`, `prototype` and `__proto__` for instance members, `prototype`, `__proto__`, `name` and `length` for static members)
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::reflection::Proxy;
+    /// let res = Proxy::new()
+    ///     .name("Broken")
+    ///     .method("size", |_rt, realm, _id, _args| realm.create_null())
+    ///     .getter("size", |_rt, realm, _id| realm.create_null())
+    ///     .validate();
+    /// assert!(res.is_err());
+    /// ```
+    pub fn validate(&self) -> Result<(), JsError> {
+        let mut problems = vec![];
+        if self.name.is_none() {
+            problems.push("the Proxy has no name".to_string());
+        }
+        for member in &self.duplicate_members {
+            problems.push(format!("{member} is defined more than once"));
+        }
+
+        const EVENT_TARGET_METHODS: [&str; 3] =
+            ["addEventListener", "removeEventListener", "dispatchEvent"];
+
+        let mut instance_members: Vec<(&str, &str)> = vec![];
+        instance_members.extend(self.methods.keys().map(|n| (n.as_str(), "method")));
+        instance_members.extend(
+            self.native_methods
+                .keys()
+                .map(|n| (n.as_str(), "native method")),
+        );
+        instance_members.extend(
+            self.method_overloads
+                .keys()
+                .map(|n| (n.as_str(), "method overload")),
+        );
+        instance_members.extend(
+            self.getters_setters
+                .keys()
+                .map(|n| (n.as_str(), "getter/setter")),
+        );
+        if self.is_event_target {
+            instance_members.extend(EVENT_TARGET_METHODS.map(|n| (n, "event target method")));
+        }
+        if self.disposer.is_some() {
+            instance_members.push(("Symbol.dispose", "dispose handler"));
+        }
+        if self.async_disposer.is_some() {
+            instance_members.push(("Symbol.asyncDispose", "async dispose handler"));
+        }
+        check_members(
+            &mut problems,
+            instance_members,
+            "instance",
+            &["constructor", "prototype", "__proto__"],
+        );
+
+        let mut static_members: Vec<(&str, &str)> = vec![];
+        static_members.extend(
+            self.static_methods
+                .keys()
+                .map(|n| (n.as_str(), "static method")),
+        );
+        static_members.extend(
+            self.static_native_methods
+                .keys()
+                .map(|n| (n.as_str(), "static native method")),
+        );
+        static_members.extend(
+            self.static_method_overloads
+                .keys()
+                .map(|n| (n.as_str(), "static method overload")),
+        );
+        static_members.extend(
+            self.static_getters_setters
+                .keys()
+                .map(|n| (n.as_str(), "static getter/setter")),
+        );
+        if self.is_static_event_target {
+            static_members.extend(EVENT_TARGET_METHODS.map(|n| (n, "static event target method")));
+        }
+        check_members(
+            &mut problems,
+            static_members,
+            "static",
+            &["prototype", "__proto__", "name", "length"],
+        );
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(JsError::new_string(format!(
+                "invalid Proxy {}: {}",
+                self.get_class_name(),
+                problems.join("; ")
+            )))
+        }
+    }
+
+    /// check that the namespace path can hold the class and that the class does not replace an existing global
+    fn check_global_path(&self, q_ctx: &QuickJsRealmAdapter) -> Result<(), JsError> {
+        let mut obj = quickjs_utils::get_global_q(q_ctx);
+        let mut path: Vec<&str> = vec![];
+        for segment in self.namespace.iter().flatten() {
+            path.push(segment.as_str());
+            let value = objects::get_property_q(q_ctx, &obj, segment)?;
+            if value.is_null_or_undefined() {
+                // the rest of the namespace is created on install
+                return Ok(());
+            }
+            if !value.is_object() {
+                return Err(JsError::new_string(format!(
+                    "{segment} already exists at path {} and is not an object",
+                    path.join(".")
+                )));
+            }
+            obj = value;
+        }
+        let name = self.name.as_deref().unwrap_or_default();
+        if !objects::get_property_q(q_ctx, &obj, name)?.is_undefined() {
+            path.push(name);
+            return Err(JsError::new_string(format!(
+                "{name} already exists at path {}",
+                path.join(".")
+            )));
+        }
+        Ok(())
+    }
+
     /// install the Proxy class in a QuickJsContext, this is always needed as a final step to actually make the Proxy class work
     pub fn install(
         mut self,
         q_ctx: &QuickJsRealmAdapter,
         add_variable_to_global: bool,
     ) -> Result<QuickJsValueAdapter, JsError> {
-        if self.name.is_none() {
-            return Err(JsError::new_str("Proxy needs a name"));
+        self.validate()?;
+        if add_variable_to_global {
+            self.check_global_path(q_ctx)?;
         }
 
+        self = self.install_overloads()?;
+        self = self.install_disposers(q_ctx)?;
+
         let prim_cn = self.get_class_name();
         let prim_cn2 = prim_cn.clone();
 
@@ -589,6 +1041,67 @@ impl Proxy {
         Ok(ret)
     }
 
+    /// turn the overloads into dispatching methods
+    fn install_overloads(mut self) -> Result<Self, JsError> {
+        for (name, entries) in std::mem::take(&mut self.method_overloads) {
+            if self.methods.contains_key(&name) {
+                return Err(JsError::new_string(format!(
+                    "method {name} has overloads and can not also be added with method()"
+                )));
+            }
+            let overloads = Overloads::new(entries);
+            let method_name = name.clone();
+            self = self.method(name.as_str(), move |rt, realm, id, args| {
+                let method = overloads.select(method_name.as_str(), args)?;
+                method(rt, realm, id, args)
+            });
+        }
+        for (name, entries) in std::mem::take(&mut self.static_method_overloads) {
+            if self.static_methods.contains_key(&name) {
+                return Err(JsError::new_string(format!(
+                    "static method {name} has overloads and can not also be added with static_method()"
+                )));
+            }
+            let overloads = Overloads::new(entries);
+            let method_name = name.clone();
+            self = self.static_method(name.as_str(), move |rt, realm, args| {
+                let method = overloads.select(method_name.as_str(), args)?;
+                method(rt, realm, args)
+            });
+        }
+        Ok(self)
+    }
+
+    /// turn the dispose handlers into methods which dispose an instance once
+    fn install_disposers(mut self, q_ctx: &QuickJsRealmAdapter) -> Result<Self, JsError> {
+        if self.disposer.is_none() && self.async_disposer.is_none() {
+            return Ok(self);
+        }
+        ensure_dispose_symbols_q(q_ctx)?;
+        if let Some(disposer) = self.disposer.take() {
+            let disposed = self.disposed_instances.clone();
+            self = self.method("Symbol.dispose", move |rt, realm, id, _args| {
+                let first = disposed.borrow_mut().insert(*id);
+                if first {
+                    disposer(rt, realm, *id)?;
+                }
+                realm.create_undefined()
+            });
+        }
+        if let Some(disposer) = self.async_disposer.take() {
+            let disposed = self.disposed_instances.clone();
+            self = self.method("Symbol.asyncDispose", move |rt, realm, id, _args| {
+                let first = disposed.borrow_mut().insert(*id);
+                if first {
+                    disposer(rt, realm, *id)
+                } else {
+                    realm.create_undefined()
+                }
+            });
+        }
+        Ok(self)
+    }
+
     fn install_move_to_registry(self, q_ctx: &QuickJsRealmAdapter) {
         let proxy = self;
         let reg_map = &mut *q_ctx.proxy_registry.borrow_mut();
@@ -697,7 +1210,7 @@ impl Proxy {
         log::trace!("reflection::Proxy::install_class_prop / 10");
 
         let proxy_constructor_refs = &mut *q_ctx.proxy_constructor_refs.borrow_mut();
-        proxy_constructor_refs.insert(self.get_class_name(), constructor_ref.clone());
+        proxy_constructor_refs.insert(self.get_class_name(), constructor_ref.dup());
 
         log::trace!("install_class_prop done");
 
@@ -705,6 +1218,27 @@ impl Proxy {
     }
 }
 
+/// add Symbol.dispose and Symbol.asyncDispose to a realm whose engine does not have them, the methods of proxy instances are found by the
+/// description of the symbol so the added symbols work the same way
+fn ensure_dispose_symbols_q(q_ctx: &QuickJsRealmAdapter) -> Result<(), JsError> {
+    let symbol = unsafe { quickjs_utils::get_constructor(q_ctx.context, "Symbol")? };
+    for (name, description) in [
+        ("dispose", "Symbol.dispose"),
+        ("asyncDispose", "Symbol.asyncDispose"),
+    ] {
+        if objects::get_property_q(q_ctx, &symbol, name)?.is_undefined() {
+            let sym = functions::call_function_q(
+                q_ctx,
+                &symbol,
+                &[primitives::from_string_q(q_ctx, description)?],
+                None,
+            )?;
+            objects::set_property2_q(q_ctx, &symbol, name, &sym, 0)?;
+        }
+    }
+    Ok(())
+}
+
 pub fn get_proxy_instance_proxy_and_instance_id_q(
     q_ctx: &QuickJsRealmAdapter,
     obj: &QuickJsValueAdapter,
@@ -828,18 +1362,16 @@ pub(crate) fn new_instance3(
     }
 
     let mappings = &mut *proxy.proxy_instance_id_mappings.borrow_mut();
-    assert!(!mappings.contains_key(&instance_id));
+    assert!(!mappings.contains(&instance_id));
 
-    let mut bx = Box::new(ProxyInstanceInfo {
+    // the info is owned by the instance and freed in its finalizer, so it outlives the realm if the instance does
+    let info_ptr = Box::into_raw(Box::new(ProxyInstanceInfo {
         id: instance_id,
         class_name: proxy.get_class_name(),
         context_id: q_ctx.id.clone(),
-    });
-
-    let ibp: &mut ProxyInstanceInfo = &mut bx;
-    let info_ptr = ibp as *mut _ as *mut c_void;
+    })) as *mut c_void;
 
-    mappings.insert(instance_id, bx);
+    mappings.insert(instance_id);
     unsafe { q::JS_SetOpaque(*class_val_ref.borrow_value(), info_ptr) };
 
     // todo this is a workaround.. i need to set a prototype for classes using JS_setClassProto per context on init..
@@ -918,7 +1450,7 @@ unsafe extern "C" fn constructor(
                         let instance_ref_res = new_instance3(proxy, instance_id, q_ctx);
 
                         match instance_ref_res {
-                            Ok(instance_ref) => instance_ref.clone_value_incr_rc(),
+                            Ok(instance_ref) => instance_ref.into_raw(),
 
                             Err(e) => q_ctx.report_ex(
                                 format!(
@@ -928,12 +1460,36 @@ unsafe extern "C" fn constructor(
                             ),
                         }
                     }
-                    Err(es_err) => q_ctx.report_ex(
-                        format!("constructor for {class_name} failed with {es_err}").as_str(),
-                    ),
+                    Err(es_err) => {
+                        // keep the name of the error so scripts can tell a RangeError from a TypeError
+                        let msg = format!(
+                            "constructor for {class_name} failed: {}",
+                            es_err.get_script_message()
+                        );
+                        let nat_stack = format!(
+                            "    at Proxy constructor [{class_name}]\n{}",
+                            es_err.get_script_stack()
+                        );
+                        let err = errors::new_error(
+                            context,
+                            es_err.get_name(),
+                            msg.as_str(),
+                            nat_stack.as_str(),
+                        )
+                        .expect("create error failed");
+                        errors::throw(context, err)
+                    }
                 }
             } else {
-                q_ctx.report_ex("not a constructor")
+                let err = errors::new_error(
+                    context,
+                    "TypeError",
+                    format!("{class_name} has no constructor and can not be created with new")
+                        .as_str(),
+                    "",
+                )
+                .expect("could not create error");
+                errors::throw(context, err)
             }
         } else {
             q_ctx.report_ex("no such proxy")
@@ -958,7 +1514,12 @@ fn get_proxy_instance_info(val: &q::JSValue) -> &ProxyInstanceInfo {
 unsafe extern "C" fn finalizer(_rt: *mut q::JSRuntime, val: q::JSValue) {
     log::trace!("finalizer called");
 
-    let info: &ProxyInstanceInfo = get_proxy_instance_info(&val);
+    let class_id = PROXY_INSTANCE_CLASS_ID.with(|rc| *rc.borrow());
+    let info_ptr = q::JS_GetOpaque(val, class_id) as *mut ProxyInstanceInfo;
+    if info_ptr.is_null() {
+        return;
+    }
+    let info = Box::from_raw(info_ptr);
     trace!(
         "finalize id:{} class:{} context:{}",
         info.id,
@@ -967,29 +1528,55 @@ unsafe extern "C" fn finalizer(_rt: *mut q::JSRuntime, val: q::JSValue) {
     );
 
     QuickJsRuntimeAdapter::do_with(|q_js_rt| {
-        let q_ctx = q_js_rt.get_context(&info.context_id);
-        log::trace!("finalizer called, got q_ctx");
-        let registry = &*q_ctx.proxy_registry.borrow();
-        let proxy = registry.get(&info.class_name).unwrap();
-
-        for finalizer in &proxy.finalizers {
-            log::trace!("calling Proxy's finalizer");
-            finalizer(q_js_rt, q_ctx, info.id);
-            log::trace!("after calling Proxy's finalizer");
-        }
-
+        // the realm may be gone, its instances were finalized when it was freed
+        let q_ctx = match q_js_rt.opt_context(&info.context_id) {
+            Some(q_ctx) => q_ctx,
+            None => return,
+        };
+        let proxy = match q_ctx.proxy_registry.borrow().get(&info.class_name) {
+            Some(proxy) => proxy.clone(),
+            None => return,
+        };
+        if !proxy
+            .proxy_instance_id_mappings
+            .borrow_mut()
+            .remove(&info.id)
         {
-            log::trace!("reflection::finalizer: remove from INSTANCE_ID_MAPPINGS");
-            let id_map = &mut *proxy.proxy_instance_id_mappings.borrow_mut();
-            let _ = id_map.remove(&info.id).expect("no such id to finalize");
-            log::trace!("reflection::finalizer: remove from INSTANCE_ID_MAPPINGS -> done");
+            return;
         }
-        log::trace!("reflection::finalizer: 2");
-
-        log::trace!("reflection::finalizer: 3, exit");
+        run_finalizers(q_js_rt, q_ctx, &proxy, info.id);
     });
 }
 
+fn run_finalizers(
+    q_js_rt: &QuickJsRuntimeAdapter,
+    q_ctx: &QuickJsRealmAdapter,
+    proxy: &Proxy,
+    id: usize,
+) {
+    for finalizer in &proxy.finalizers {
+        log::trace!("calling Proxy's finalizer");
+        finalizer(q_js_rt, q_ctx, id);
+        log::trace!("after calling Proxy's finalizer");
+    }
+    proxy.disposed_instances.borrow_mut().remove(&id);
+}
+
+/// run the finalizers of the instances of all proxies in a realm which were not collected yet, this is called when the realm is freed
+/// so every finalizer runs exactly once per instance, an instance which is collected later is not finalized again
+pub(crate) fn finalize_remaining_instances(
+    q_js_rt: &QuickJsRuntimeAdapter,
+    q_ctx: &QuickJsRealmAdapter,
+) {
+    let proxies: Vec<Rc<Proxy>> = q_ctx.proxy_registry.borrow().values().cloned().collect();
+    for proxy in proxies {
+        let ids = std::mem::take(&mut *proxy.proxy_instance_id_mappings.borrow_mut());
+        for id in ids {
+            run_finalizers(q_js_rt, q_ctx, &proxy, id);
+        }
+    }
+}
+
 #[allow(dead_code)]
 unsafe extern "C" fn proxy_static_get_prop(
     context: *mut q::JSContext,
@@ -1049,7 +1636,7 @@ unsafe extern "C" fn proxy_static_get_prop(
                 objects::set_property(context, &receiver_ref, prop_name, &func_ref)
                     .expect("set_property 9656738 failed");
 
-                func_ref.clone_value_incr_rc()
+                func_ref.into_raw()
             } else if let Some(native_static_method) = proxy.static_native_methods.get(prop_name) {
                 trace!("found static native method for {}", prop_name);
 
@@ -1065,13 +1652,13 @@ unsafe extern "C" fn proxy_static_get_prop(
                 objects::set_property(context, &receiver_ref, prop_name, &func_ref)
                     .expect("set_property 36099 failed");
 
-                func_ref.clone_value_incr_rc()
+                func_ref.into_raw()
             } else if let Some(getter_setter) = proxy.static_getters_setters.get(prop_name) {
                 // call the getter
                 let getter = &getter_setter.0;
                 let res: Result<QuickJsValueAdapter, JsError> = getter(q_js_rt, q_ctx);
                 match res {
-                    Ok(g_val) => g_val.clone_value_incr_rc(),
+                    Ok(g_val) => g_val.into_raw(),
                     Err(e) => {
                         let es = format!("proxy_static_get_prop failed: {e}");
                         q_ctx.report_ex(es.as_str())
@@ -1082,7 +1669,7 @@ unsafe extern "C" fn proxy_static_get_prop(
                 let getter = &catch_all_getter_setter.0;
                 let res: Result<QuickJsValueAdapter, JsError> = getter(q_js_rt, q_ctx, prop_name);
                 match res {
-                    Ok(g_val) => g_val.clone_value_incr_rc(),
+                    Ok(g_val) => g_val.into_raw(),
                     Err(e) => {
                         let es = format!("proxy_static_get_prop failed: {e}");
                         q_ctx.report_ex(es.as_str())
@@ -1150,10 +1737,17 @@ unsafe extern "C" fn proxy_instance_get_prop(
             )
             .expect("could not create func");
 
-            objects::set_property(context, &receiver_ref, prop_name, &func_ref)
-                .expect("set_property 96385 failed"); // todo report ex
+            // cached methods are not enumerable so they do not show up in Object.keys()
+            objects::set_property2(
+                context,
+                &receiver_ref,
+                prop_name,
+                &func_ref,
+                (q::JS_PROP_CONFIGURABLE | q::JS_PROP_WRITABLE) as i32,
+            )
+            .expect("set_property 96385 failed"); // todo report ex
 
-            func_ref.clone_value_incr_rc()
+            func_ref.into_raw()
         } else if let Some(native_method) = proxy.native_methods.get(prop_name) {
             trace!("found native method for {}", prop_name);
 
@@ -1161,22 +1755,29 @@ unsafe extern "C" fn proxy_instance_get_prop(
                 functions::new_native_function(context, prop_name, *native_method, 1, false)
                     .expect("could not create func"); // tyodo report ex
 
-            objects::set_property(context, &receiver_ref, prop_name, &func_ref)
-                .expect("set_property 49671 failed"); // todo report ex
+            // cached methods are not enumerable so they do not show up in Object.keys()
+            objects::set_property2(
+                context,
+                &receiver_ref,
+                prop_name,
+                &func_ref,
+                (q::JS_PROP_CONFIGURABLE | q::JS_PROP_WRITABLE) as i32,
+            )
+            .expect("set_property 49671 failed"); // todo report ex
 
-            func_ref.clone_value_incr_rc()
+            func_ref.into_raw()
         } else if let Some(getter_setter) = proxy.getters_setters.get(prop_name) {
             // call the getter
             let getter = &getter_setter.0;
             let res: Result<QuickJsValueAdapter, JsError> = getter(q_js_rt, q_ctx, &info.id);
             match res {
-                Ok(g_val) => g_val.clone_value_incr_rc(),
+                Ok(g_val) => g_val.into_raw(),
                 Err(e) => {
-                    let msg = format!("proxy_instance_get failed: {}", e.get_message());
+                    let msg = format!("proxy_instance_get failed: {}", e.get_script_message());
                     let nat_stack = format!(
                         "    at Proxy instance getter [{}]\n{}",
                         prop_name,
-                        e.get_stack()
+                        e.get_script_stack()
                     );
                     let err =
                         errors::new_error(context, e.get_name(), msg.as_str(), nat_stack.as_str())
@@ -1184,19 +1785,21 @@ unsafe extern "C" fn proxy_instance_get_prop(
                     errors::throw(context, err)
                 }
             }
-        } else if let Some(catch_all_getter_setter) = &proxy.catch_all {
+        } else if let Some(getter) = &proxy.catch_all.getter {
             // call the getter
-            let getter = &catch_all_getter_setter.0;
             let res: Result<QuickJsValueAdapter, JsError> =
                 getter(q_js_rt, q_ctx, &info.id, prop_name);
             match res {
-                Ok(g_val) => g_val.clone_value_incr_rc(),
+                Ok(g_val) => g_val.into_raw(),
                 Err(e) => {
-                    let msg = format!("proxy_instance_catch_all_get failed: {}", e.get_message());
+                    let msg = format!(
+                        "proxy_instance_catch_all_get failed: {}",
+                        e.get_script_message()
+                    );
                     let nat_stack = format!(
                         "    at Proxy instance getter [{}]\n{}",
                         prop_name,
-                        e.get_stack()
+                        e.get_script_stack()
                     );
                     let err =
                         errors::new_error(context, e.get_name(), msg.as_str(), nat_stack.as_str())
@@ -1215,21 +1818,173 @@ unsafe extern "C" fn proxy_instance_get_prop(
     // get method or getter or setter
     // return native func (cache those?)
 }
-#[allow(dead_code)]
+// get the proxy of an instance, the registry is not borrowed while the handlers of the proxy run
+fn get_instance_proxy(realm: &QuickJsRealmAdapter, info: &ProxyInstanceInfo) -> Option<Rc<Proxy>> {
+    realm.proxy_registry.borrow().get(&info.class_name).cloned()
+}
+
+unsafe fn throw_catch_all_error(context: *mut q::JSContext, prop_name: &str, e: JsError) -> c_int {
+    let nat_stack = format!(
+        "    at Proxy catch all [{}]\n{}",
+        prop_name,
+        e.get_script_stack()
+    );
+    match errors::new_error(
+        context,
+        e.get_name(),
+        e.get_script_message(),
+        nat_stack.as_str(),
+    ) {
+        Ok(err) => {
+            errors::throw(context, err);
+        }
+        Err(_) => {
+            QuickJsRealmAdapter::report_ex_ctx(context, e.get_script_message());
+        }
+    }
+    -1
+}
+
 unsafe extern "C" fn proxy_instance_has_prop(
-    _context: *mut q::JSContext,
-    _obj: q::JSValue,
-    _atom: q::JSAtom,
-) -> ::std::os::raw::c_int {
-    todo!()
+    context: *mut q::JSContext,
+    obj: q::JSValue,
+    atom: q::JSAtom,
+) -> c_int {
+    trace!("proxy_instance_has_prop");
+    // own props, this includes the catchall props and the methods which were already cached on the instance
+    let own = q::JS_GetOwnProperty(context, std::ptr::null_mut(), obj, atom);
+    if own != 0 {
+        return own;
+    }
+    QuickJsRuntimeAdapter::do_with(|rt| {
+        let realm = rt.get_quickjs_context(context);
+        let prop_name = atoms::to_str(context, &atom).expect("could not get name");
+        let info = get_proxy_instance_info(&obj);
+        if let Some(proxy) = get_instance_proxy(realm, info) {
+            if proxy.has_instance_member(prop_name) {
+                return 1;
+            }
+        }
+        let obj_ref = QuickJsValueAdapter::new(
+            context,
+            obj,
+            false,
+            false,
+            "reflection::proxy_instance_has_prop obj",
+        );
+        match objects::get_prototype_of(context, &obj_ref) {
+            Ok(proto) if proto.is_object() => {
+                q::JS_HasProperty(context, *proto.borrow_value(), atom)
+            }
+            _ => 0,
+        }
+    })
 }
-#[allow(dead_code)]
-unsafe extern "C" fn proxy_static_has_prop(
-    _context: *mut q::JSContext,
-    _obj: q::JSValue,
-    _atom: q::JSAtom,
-) -> ::std::os::raw::c_int {
-    todo!()
+
+unsafe extern "C" fn proxy_instance_get_own_prop(
+    context: *mut q::JSContext,
+    desc: *mut q::JSPropertyDescriptor,
+    obj: q::JSValue,
+    atom: q::JSAtom,
+) -> c_int {
+    trace!("proxy_instance_get_own_prop");
+    QuickJsRuntimeAdapter::do_with(|rt| {
+        let realm = rt.get_quickjs_context(context);
+        let prop_name = atoms::to_str(context, &atom).expect("could not get name");
+        let info = get_proxy_instance_info(&obj);
+        let proxy = match get_instance_proxy(realm, info) {
+            Some(proxy) => proxy,
+            None => return 0,
+        };
+        match proxy.catch_all_value(rt, realm, info.id, prop_name) {
+            Ok(Some(value)) => {
+                if !desc.is_null() {
+                    (*desc).flags = q::JS_PROP_C_W_E as _;
+                    (*desc).value = value.clone_value_incr_rc();
+                    (*desc).getter = quickjs_utils::new_undefined();
+                    (*desc).setter = quickjs_utils::new_undefined();
+                }
+                1
+            }
+            Ok(None) => 0,
+            Err(e) => throw_catch_all_error(context, prop_name, e),
+        }
+    })
+}
+
+unsafe extern "C" fn proxy_instance_get_own_prop_names(
+    context: *mut q::JSContext,
+    ptab: *mut *mut q::JSPropertyEnum,
+    plen: *mut u32,
+    obj: q::JSValue,
+) -> c_int {
+    trace!("proxy_instance_get_own_prop_names");
+    QuickJsRuntimeAdapter::do_with(|rt| {
+        let realm = rt.get_quickjs_context(context);
+        let info = get_proxy_instance_info(&obj);
+        let proxy = get_instance_proxy(realm, info);
+        let names = match proxy.as_ref().and_then(|p| p.catch_all.own_keys.as_ref()) {
+            Some(own_keys) => match own_keys(rt, realm, &info.id) {
+                Ok(names) => names,
+                Err(e) => return throw_catch_all_error(context, "ownKeys", e),
+            },
+            None => vec![],
+        };
+        let names: Vec<String> = match &proxy {
+            Some(proxy) => names
+                .into_iter()
+                .filter(|name| !proxy.has_instance_member(name))
+                .collect(),
+            None => names,
+        };
+        // quickjs frees the table and its atoms, the is_enumerable flags are set by quickjs
+        let tab = q::js_mallocz(
+            context,
+            (std::mem::size_of::<q::JSPropertyEnum>() * names.len().max(1)) as _,
+        ) as *mut q::JSPropertyEnum;
+        if tab.is_null() {
+            return -1;
+        }
+        for (index, name) in names.iter().enumerate() {
+            let atom_ref = match atoms::from_string(context, name) {
+                Ok(atom_ref) => atom_ref,
+                Err(e) => return throw_catch_all_error(context, name, e),
+            };
+            atom_ref.increment_ref_ct();
+            (*tab.add(index)).atom = atom_ref.get_atom();
+        }
+        *ptab = tab;
+        *plen = names.len() as u32;
+        0
+    })
+}
+
+unsafe extern "C" fn proxy_instance_delete_prop(
+    context: *mut q::JSContext,
+    obj: q::JSValue,
+    atom: q::JSAtom,
+) -> c_int {
+    trace!("proxy_instance_delete_prop");
+    QuickJsRuntimeAdapter::do_with(|rt| {
+        let realm = rt.get_quickjs_context(context);
+        let prop_name = atoms::to_str(context, &atom).expect("could not get name");
+        let info = get_proxy_instance_info(&obj);
+        let proxy = match get_instance_proxy(realm, info) {
+            Some(proxy) => proxy,
+            None => return 1,
+        };
+        if proxy.has_instance_member(prop_name) {
+            return 1;
+        }
+        match &proxy.catch_all.deleter {
+            Some(deleter) => match deleter(rt, realm, &info.id, prop_name) {
+                Ok(true) => 1,
+                Ok(false) => 0,
+                Err(e) => throw_catch_all_error(context, prop_name, e),
+            },
+            None => 1,
+        }
+    })
 }
 
 unsafe extern "C" fn proxy_instance_method(
@@ -1270,13 +2025,13 @@ unsafe extern "C" fn proxy_instance_method(
                 method(q_js_rt, q_ctx, &proxy_instance_info.id, &args_vec);
 
             match m_res {
-                Ok(m_res_ref) => m_res_ref.clone_value_incr_rc(),
+                Ok(m_res_ref) => m_res_ref.into_raw(),
                 Err(e) => {
-                    let msg = format!("proxy_instance_method failed: {}", e.get_message());
+                    let msg = format!("proxy_instance_method failed: {}", e.get_script_message());
                     let nat_stack = format!(
                         "    at Proxy instance method [{}]\n{}",
                         func_name,
-                        e.get_stack()
+                        e.get_script_stack()
                     );
                     let err =
                         errors::new_error(context, e.get_name(), msg.as_str(), nat_stack.as_str())
@@ -1336,13 +2091,13 @@ unsafe extern "C" fn proxy_static_method(
         if let Some(method) = proxy.static_methods.get(func_name.as_str()) {
             let m_res: Result<QuickJsValueAdapter, JsError> = method(q_js_rt, q_ctx, &args_vec);
             match m_res {
-                Ok(m_res_ref) => m_res_ref.clone_value_incr_rc(),
+                Ok(m_res_ref) => m_res_ref.into_raw(),
                 Err(e) => {
-                    let msg = format!("proxy_static_method failed: {}", e.get_message());
+                    let msg = format!("proxy_static_method failed: {}", e.get_script_message());
                     let nat_stack = format!(
                         "    at Proxy static method [{}]\n{}",
                         func_name,
-                        e.get_stack()
+                        e.get_script_stack()
                     );
                     let err =
                         errors::new_error(context, e.get_name(), msg.as_str(), nat_stack.as_str())
@@ -1363,7 +2118,7 @@ unsafe extern "C" fn proxy_static_set_prop(
     atom: q::JSAtom,
     value: q::JSValue,
     receiver: q::JSValue,
-    _flags: ::std::os::raw::c_int,
+    flags: ::std::os::raw::c_int,
 ) -> ::std::os::raw::c_int {
     trace!("proxy_static_set_prop");
 
@@ -1402,31 +2157,19 @@ unsafe extern "C" fn proxy_static_set_prop(
         if let Some(proxy) = registry.get(proxy_name.as_str()) {
             if let Some(getter_setter) = proxy.static_getters_setters.get(prop_name) {
                 // call the setter
-                let setter = &getter_setter.1;
-                let res: Result<(), JsError> = setter(rt, realm, value_ref);
-                match res {
-                    Ok(_) => 0,
-                    Err(e) => {
-                        // fail, todo do i need ex?
-                        let err = format!("proxy_static_set_prop failed: {e}");
-                        log::error!("{}", err);
-                        let _ = realm.report_ex(err.as_str());
-                        -1
-                    }
+                match &getter_setter.1 {
+                    Some(setter) => match setter(rt, realm, value_ref) {
+                        Ok(_) => 0,
+                        Err(e) => throw_setter_error(context, prop_name, e),
+                    },
+                    None => throw_read_only(context, flags, proxy_name.as_str(), prop_name),
                 }
             } else if let Some(catch_all_getter_setter) = &proxy.static_catch_all {
                 // call the setter
                 let setter = &catch_all_getter_setter.1;
-                let res: Result<(), JsError> = setter(rt, realm, prop_name, value_ref);
-                match res {
+                match setter(rt, realm, prop_name, value_ref) {
                     Ok(_) => 0,
-                    Err(e) => {
-                        // fail, todo do i need ex?
-                        let err = format!("proxy_static_set_prop failed: {e}");
-                        log::error!("{}", err);
-                        let _ = realm.report_ex(err.as_str());
-                        -1
-                    }
+                    Err(e) => throw_setter_error(context, prop_name, e),
                 }
             } else {
                 let receiver_ref = QuickJsValueAdapter::new(
@@ -1463,13 +2206,58 @@ unsafe extern "C" fn proxy_static_set_prop(
     })
 }
 
+// a setter which fails throws a TypeError in script, unless its error has a more specific name (e.g. RangeError)
+unsafe fn throw_setter_error(context: *mut q::JSContext, prop_name: &str, e: JsError) -> c_int {
+    let name = match e.get_name() {
+        "Error" => "TypeError",
+        name => name,
+    };
+    let nat_stack = format!(
+        "    at Proxy setter [{}]\n{}",
+        prop_name,
+        e.get_script_stack()
+    );
+    match errors::new_error(context, name, e.get_script_message(), nat_stack.as_str()) {
+        Ok(err) => {
+            errors::throw(context, err);
+        }
+        Err(_) => {
+            QuickJsRealmAdapter::report_ex_ctx(context, e.get_script_message());
+        }
+    }
+    -1
+}
+
+// assigning to a getter without a setter, quickjs does not tell us if the calling code is strict
+// so every assignment throws, like it would in a module or class body, only Reflect.set just returns false
+unsafe fn throw_read_only(
+    context: *mut q::JSContext,
+    flags: c_int,
+    class_name: &str,
+    prop_name: &str,
+) -> c_int {
+    if flags & (q::JS_PROP_THROW | q::JS_PROP_THROW_STRICT) as c_int == 0 {
+        return 0;
+    }
+    let msg = format!("Cannot set property {prop_name} of {class_name} which has only a getter");
+    match errors::new_error(context, "TypeError", msg.as_str(), "") {
+        Ok(err) => {
+            errors::throw(context, err);
+        }
+        Err(_) => {
+            QuickJsRealmAdapter::report_ex_ctx(context, msg.as_str());
+        }
+    }
+    -1
+}
+
 unsafe extern "C" fn proxy_instance_set_prop(
     context: *mut q::JSContext,
     obj: q::JSValue,
     atom: q::JSAtom,
     value: q::JSValue,
     receiver: q::JSValue,
-    _flags: ::std::os::raw::c_int,
+    flags: ::std::os::raw::c_int,
 ) -> ::std::os::raw::c_int {
     trace!("proxy_instance_set_prop");
 
@@ -1498,31 +2286,18 @@ unsafe extern "C" fn proxy_instance_set_prop(
 
         if let Some(getter_setter) = proxy.getters_setters.get(prop_name) {
             // call the setter
-            let setter = &getter_setter.1;
-            let res: Result<(), JsError> = setter(rt, realm, &info.id, value_ref);
-            match res {
-                Ok(_) => 0,
-                Err(e) => {
-                    // fail, todo do i need ex?
-                    let err = format!("proxy_instance_set_prop failed: {e}");
-                    log::error!("{}", err);
-                    let _ = realm.report_ex(err.as_str());
-                    -1
-                }
+            match &getter_setter.1 {
+                Some(setter) => match setter(rt, realm, &info.id, value_ref) {
+                    Ok(_) => 0,
+                    Err(e) => throw_setter_error(context, prop_name, e),
+                },
+                None => throw_read_only(context, flags, info.class_name.as_str(), prop_name),
             }
-        } else if let Some(catch_all_getter_setter) = &proxy.catch_all {
+        } else if let Some(setter) = &proxy.catch_all.setter {
             // call the setter
-            let setter = &catch_all_getter_setter.1;
-            let res: Result<(), JsError> = setter(rt, realm, &info.id, prop_name, value_ref);
-            match res {
+            match setter(rt, realm, &info.id, prop_name, value_ref) {
                 Ok(_) => 0,
-                Err(e) => {
-                    // fail, todo do i need ex?
-                    let err = format!("proxy_instance_set_prop failed: {e}");
-                    log::error!("{}", err);
-                    let _ = realm.report_ex(err.as_str());
-                    -1
-                }
+                Err(e) => throw_setter_error(context, prop_name, e),
             }
         } else {
             // if not handler just add to receiver
@@ -1566,7 +2341,7 @@ pub mod tests {
     use crate::quickjs_utils::objects::create_object_q;
     use crate::quickjs_utils::{functions, primitives};
     use crate::reflection::{
-        get_proxy_instance_proxy_and_instance_id_q, is_proxy_instance_q, Proxy,
+        get_proxy, get_proxy_instance_proxy_and_instance_id_q, is_proxy_instance_q, Proxy,
         PROXY_INSTANCE_CLASS_ID,
     };
     use libquickjs_sys as q;
@@ -1574,6 +2349,8 @@ pub mod tests {
     use std::cell::RefCell;
     use std::collections::HashMap;
     use std::panic;
+    use std::rc::Rc;
+    use std::sync::{Arc, Mutex};
     use std::time::Duration;
 
     thread_local! {
@@ -1935,4 +2712,338 @@ pub mod tests {
             );
         });
     }
+
+    #[test]
+    pub fn test_validate() {
+        let res = Proxy::new()
+            .name("Broken")
+            .method("size", |_rt, realm, _id, _args| realm.create_null())
+            .method("size", |_rt, realm, _id, _args| realm.create_null())
+            .getter("size", |_rt, realm, _id| realm.create_null())
+            .method("constructor", |_rt, realm, _id, _args| realm.create_null())
+            .static_method("name", |_rt, realm, _args| realm.create_null())
+            .native_method("addEventListener", None)
+            .event_target()
+            .validate();
+        let msg = res
+            .expect_err("validate should fail")
+            .get_message()
+            .to_string();
+        for problem in [
+            "method size is defined more than once",
+            "instance member size is defined as getter/setter and as method",
+            "constructor is a reserved name and can not be used for instance members",
+            "name is a reserved name and can not be used for static members",
+            "instance member addEventListener is defined as event target method and as native method",
+        ] {
+            assert!(msg.contains(problem), "{problem} not in {msg}");
+        }
+        assert!(Proxy::new().name("Fine").validate().is_ok());
+
+        let rt = init_test_rt();
+        rt.loop_realm_sync(None, |_rt, realm| {
+            realm
+                .eval(Script::new(
+                    "test_validate.js",
+                    "globalThis.com = {taken: {Existing: 1}, scalar: 1};",
+                ))
+                .expect("script failed");
+            let err = Proxy::new()
+                .namespace(&["com", "taken"])
+                .name("Existing")
+                .install(realm, true)
+                .expect_err("install should fail");
+            assert_eq!(
+                err.get_message(),
+                "Existing already exists at path com.taken.Existing"
+            );
+            let err = Proxy::new()
+                .namespace(&["com", "scalar", "sub"])
+                .name("Other")
+                .install(realm, true)
+                .expect_err("install should fail");
+            assert_eq!(
+                err.get_message(),
+                "scalar already exists at path com.scalar and is not an object"
+            );
+
+            Proxy::new()
+                .name("NoConstructor")
+                .install(realm, true)
+                .expect("install failed");
+            let res = realm
+                .eval(Script::new(
+                    "test_validate.js",
+                    "let m; try {new NoConstructor();} catch(e) {m = `${e.name}: ${e.message}`;} m;",
+                ))
+                .expect("script failed");
+            assert_eq!(
+                res.to_string().expect("not a string"),
+                "TypeError: NoConstructor has no constructor and can not be created with new"
+            );
+        });
+    }
+
+    #[test]
+    pub fn test_dispose() {
+        let rt = init_test_rt();
+        let (disposed, finalized_after_dispose) = rt.loop_realm_sync(None, |rt, realm| {
+            let disposed = Rc::new(RefCell::new(vec![]));
+            let finalized = Rc::new(RefCell::new(vec![]));
+            let disposed2 = disposed.clone();
+            let finalized2 = finalized.clone();
+            Proxy::new()
+                .name("Conn")
+                .constructor(|_rt, _realm, _id, _args| Ok(()))
+                .dispose(move |_rt, _realm, id| {
+                    disposed2.borrow_mut().push(id);
+                    Ok(())
+                })
+                .finalizer(move |_rt, realm, id| {
+                    let proxy = get_proxy(realm, "Conn").expect("no proxy");
+                    finalized2.borrow_mut().push(proxy.is_disposed(id));
+                })
+                .install(realm, true)
+                .expect("install failed");
+
+            let res = realm.eval(Script::new(
+                "test_dispose.js",
+                "{let conn = new Conn(); try { throw Error('fail'); } finally { conn[Symbol.dispose](); conn[Symbol.dispose](); }}",
+            ));
+            assert!(res.is_err());
+            rt.gc();
+            let disposed = disposed.borrow().len();
+            let finalized = finalized.borrow().clone();
+            (disposed, finalized)
+        });
+        assert_eq!(disposed, 1);
+        assert_eq!(finalized_after_dispose, vec![true]);
+    }
+
+    #[test]
+    pub fn test_finalizer() {
+        let rt = init_test_rt();
+        rt.create_context("finalizer_realm").expect("create failed");
+        let created = Arc::new(Mutex::new(vec![]));
+        let finalized = Arc::new(Mutex::new(vec![]));
+        let other_finalized = Arc::new(Mutex::new(vec![]));
+        let created2 = created.clone();
+        let finalized2 = finalized.clone();
+        let other_finalized2 = other_finalized.clone();
+        let kept = rt.loop_realm_sync(Some("finalizer_realm"), move |rt, realm| {
+            Proxy::new()
+                .name("Backed")
+                .constructor(move |_rt, _realm, id, _args| {
+                    created2.lock().unwrap().push(id);
+                    Ok(())
+                })
+                .finalizer(move |_rt, _realm, id| {
+                    finalized2.lock().unwrap().push(id);
+                })
+                .install(realm, true)
+                .expect("install failed");
+            Proxy::new()
+                .name("Other")
+                .constructor(|_rt, _realm, _id, _args| Ok(()))
+                .finalizer(move |_rt, _realm, id| {
+                    other_finalized2.lock().unwrap().push(id);
+                })
+                .install(realm, true)
+                .expect("install failed");
+
+            realm
+                .eval(Script::new(
+                    "test_finalizer.js",
+                    "globalThis.kept = new Backed(); for (let i = 0; i < 10; i++) { new Backed(); } new Other();",
+                ))
+                .expect("script failed");
+            rt.gc();
+            let kept = realm
+                .eval(Script::new("test_finalizer2.js", "kept"))
+                .expect("script failed");
+            realm.get_proxy_instance_info(&kept).expect("not an instance").1
+        });
+
+        let created_ids = created.lock().unwrap().clone();
+        assert_eq!(created_ids.len(), 11);
+        {
+            let finalized_ids = finalized.lock().unwrap();
+            assert_eq!(finalized_ids.len(), 10);
+            assert!(!finalized_ids.contains(&kept));
+            assert!(finalized_ids.iter().all(|id| created_ids.contains(id)));
+        }
+        assert_eq!(other_finalized.lock().unwrap().len(), 1);
+
+        // the kept instance is finalized when the realm is dropped, and only then
+        rt.drop_context("finalizer_realm")
+            .expect("could not drop finalizer_realm");
+        rt.gc_sync();
+        let mut finalized_ids = finalized.lock().unwrap().clone();
+        assert_eq!(finalized_ids.len(), 11);
+        assert_eq!(finalized_ids.last(), Some(&kept));
+        finalized_ids.sort();
+        let mut created_ids = created_ids;
+        created_ids.sort();
+        assert_eq!(finalized_ids, created_ids);
+        assert_eq!(other_finalized.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    pub fn test_getter_setter() {
+        let rt = init_test_rt();
+        let res = rt.loop_realm_sync(None, |_rt, realm| {
+            let celsius = Rc::new(RefCell::new(HashMap::<usize, f64>::new()));
+            let celsius_get = celsius.clone();
+            let celsius_set = celsius.clone();
+            let celsius_init = celsius.clone();
+            Proxy::new()
+                .name("Thermometer")
+                .constructor(move |_rt, _realm, id, _args| {
+                    celsius_init.borrow_mut().insert(id, 0.0);
+                    Ok(())
+                })
+                .getter_setter(
+                    "fahrenheit",
+                    move |_rt, realm, id| {
+                        let c = *celsius_get.borrow().get(id).expect("no such instance");
+                        realm.create_f64(c * 9.0 / 5.0 + 32.0)
+                    },
+                    move |_rt, _realm, id, val| {
+                        if !val.is_f64() && !val.is_i32() {
+                            return Err(JsError::new_str("fahrenheit should be a number"));
+                        }
+                        let f = if val.is_i32() {
+                            val.to_i32() as f64
+                        } else {
+                            val.to_f64()
+                        };
+                        celsius_set.borrow_mut().insert(*id, (f - 32.0) * 5.0 / 9.0);
+                        Ok(())
+                    },
+                )
+                .getter("unit", |_rt, realm, _id| realm.create_string("F"))
+                .static_getter("scale", |_rt, realm| realm.create_string("kelvin"))
+                .install(realm, true)
+                .expect("install failed");
+
+            realm
+                .eval(Script::new(
+                    "test_getter_setter.js",
+                    r#"
+                    let t = new Thermometer();
+                    let res = [t.fahrenheit];
+                    t.fahrenheit = 212;
+                    res.push(t.fahrenheit);
+                    try { t.fahrenheit = 'hot'; } catch (e) { res.push(e.name + ': ' + e.message); }
+                    res.push(t.fahrenheit);
+                    try { t.unit = 'C'; } catch (e) { res.push(e.name); }
+                    res.push(Reflect.set(t, 'unit', 'C'));
+                    try { Thermometer.scale = 'C'; } catch (e) { res.push(e.name); }
+                    res.push(t.unit + Thermometer.scale);
+                    res.join('|');
+                    "#,
+                ))
+                .expect("script failed")
+                .to_string()
+                .expect("to_string failed")
+        });
+        assert_eq!(
+            res,
+            "32|212|TypeError: fahrenheit should be a number|212|TypeError|false|TypeError|Fkelvin"
+        );
+    }
+
+    #[test]
+    pub fn test_catch_all() {
+        let rt = init_test_rt();
+        let res = rt.loop_realm_sync(None, |_rt, realm| {
+            let entries = Rc::new(RefCell::new(HashMap::<(usize, String), i32>::new()));
+            let (e_get, e_set, e_del, e_keys, e_size) = (
+                entries.clone(),
+                entries.clone(),
+                entries.clone(),
+                entries.clone(),
+                entries.clone(),
+            );
+            Proxy::new()
+                .name("Dict")
+                .constructor(|_rt, _realm, _id, _args| Ok(()))
+                .method("size", move |_rt, realm, id, _args| {
+                    let size = e_size.borrow().keys().filter(|k| k.0 == *id).count();
+                    realm.create_i32(size as i32)
+                })
+                .getter("kind", |_rt, realm, _id| realm.create_string("dict"))
+                .catch_all_getter(move |_rt, realm, id, name| {
+                    match e_get.borrow().get(&(*id, name.to_string())) {
+                        Some(val) => realm.create_i32(*val),
+                        None => realm.create_undefined(),
+                    }
+                })
+                .catch_all_setter(move |_rt, _realm, id, name, val| {
+                    if name == "kind" || name == "size" {
+                        return Err(JsError::new_str("members are not routed to the catch all"));
+                    }
+                    e_set
+                        .borrow_mut()
+                        .insert((*id, name.to_string()), val.to_i32());
+                    Ok(())
+                })
+                .catch_all_deleter(move |_rt, _realm, id, name| {
+                    Ok(e_del
+                        .borrow_mut()
+                        .remove(&(*id, name.to_string()))
+                        .is_some())
+                })
+                .catch_all_own_keys(move |_rt, _realm, id| {
+                    let mut keys: Vec<String> = e_keys
+                        .borrow()
+                        .keys()
+                        .filter(|k| k.0 == *id)
+                        .map(|k| k.1.clone())
+                        .collect();
+                    keys.sort();
+                    Ok(keys)
+                })
+                .install(realm, true)
+                .expect("install failed");
+
+            realm
+                .eval(Script::new(
+                    "test_catch_all.js",
+                    r#"
+                    "use strict";
+                    let d = new Dict();
+                    let other = new Dict();
+                    d.anything = 1;
+                    d.more = 2;
+                    other.elsewhere = 3;
+                    let res = [
+                        d.anything === 1,
+                        'anything' in d,
+                        'missing' in d,
+                        d.missing === undefined,
+                        'size' in d,
+                        'kind' in d,
+                        d.kind,
+                        d.size(),
+                        Object.keys(d).join(),
+                        JSON.stringify(d),
+                        Object.getOwnPropertyDescriptor(d, 'more').value,
+                        delete d.anything,
+                        'anything' in d,
+                        Object.keys(d).join(),
+                        Object.keys(other).join(),
+                    ];
+                    res.join('|');
+                    "#,
+                ))
+                .expect("script failed")
+                .to_string()
+                .expect("to_string failed")
+        });
+        assert_eq!(
+            res,
+            r#"true|true|false|true|true|true|dict|2|anything,more|{"anything":1,"more":2}|2|true|false|more|elsewhere"#
+        );
+    }
 }
@@ -0,0 +1,316 @@
+//! overloading of Proxy methods by the types of their arguments
+//!
+//! overloads are registered with [method_overload](crate::reflection::Proxy::method_overload) or [static_method_overload](crate::reflection::Proxy::static_method_overload),
+//! when the method is called the first overload (in registration order) whose [Signature] matches the arguments is invoked
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::Script;
+//! use quickjs_runtime::reflection::Proxy;
+//! use quickjs_runtime::sig;
+//! let rt = QuickJsRuntimeBuilder::new().build();
+//! rt.exe_rt_task_in_event_loop(|q_js_rt| {
+//!     let realm = q_js_rt.get_main_realm();
+//!     Proxy::new()
+//!         .name("Users")
+//!         .static_method_overload("get", sig![Number], |_rt, realm, _args| realm.create_string("by id"))
+//!         .static_method_overload("get", sig![Object, Boolean?], |_rt, realm, _args| realm.create_string("by query"))
+//!         .install(realm, true)
+//!         .expect("could not install proxy");
+//!     let res = realm.eval(Script::new("overloads.js", "Users.get(1) + ', ' + Users.get({name: 'a'});")).expect("script failed");
+//!     assert_eq!(res.to_string().expect("not a string"), "by id, by query");
+//! });
+//! ```
+
+use crate::jsutils::{JsError, JsValueType};
+use crate::quickjsvalueadapter::QuickJsValueAdapter;
+use std::fmt::{Display, Formatter};
+
+/// the type of an argument in a [Signature]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArgType {
+    /// matches any value
+    Any,
+    /// matches ints and floats
+    Number,
+    String,
+    Boolean,
+    BigInt,
+    /// matches objects which are not an Array or a Function (e.g. also Dates and Promises)
+    Object,
+    Array,
+    Function,
+    Null,
+    Undefined,
+}
+
+impl ArgType {
+    /// check if a value is of this type
+    pub fn matches(&self, value: &QuickJsValueAdapter) -> bool {
+        let js_type = value.get_js_type();
+        match self {
+            ArgType::Any => true,
+            ArgType::Number => matches!(js_type, JsValueType::I32 | JsValueType::F64),
+            ArgType::String => js_type == JsValueType::String,
+            ArgType::Boolean => js_type == JsValueType::Boolean,
+            ArgType::BigInt => js_type == JsValueType::BigInt,
+            ArgType::Object => matches!(
+                js_type,
+                JsValueType::Object | JsValueType::Date | JsValueType::Promise | JsValueType::Error
+            ),
+            ArgType::Array => js_type == JsValueType::Array,
+            ArgType::Function => js_type == JsValueType::Function,
+            ArgType::Null => js_type == JsValueType::Null,
+            ArgType::Undefined => js_type == JsValueType::Undefined,
+        }
+    }
+}
+
+impl Display for ArgType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ArgType::Any => "Any",
+            ArgType::Number => "Number",
+            ArgType::String => "String",
+            ArgType::Boolean => "Boolean",
+            ArgType::BigInt => "BigInt",
+            ArgType::Object => "Object",
+            ArgType::Array => "Array",
+            ArgType::Function => "Function",
+            ArgType::Null => "Null",
+            ArgType::Undefined => "Undefined",
+        };
+        f.write_str(name)
+    }
+}
+
+/// the argument types of an overload, required params are followed by optional params and an optional rest param
+///
+/// optional params match when the argument is missing or undefined, the rest param matches all remaining arguments
+/// a Signature is usually created with the [sig](crate::sig) macro, e.g. `sig![String, Number?, ..Any]`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Signature {
+    required: Vec<ArgType>,
+    optional: Vec<ArgType>,
+    rest: Option<ArgType>,
+}
+
+impl Signature {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// add a required param
+    /// # Panics
+    /// when optional params or a rest param were already added
+    pub fn required(mut self, arg_type: ArgType) -> Self {
+        assert!(
+            self.optional.is_empty() && self.rest.is_none(),
+            "required params must precede optional and rest params"
+        );
+        self.required.push(arg_type);
+        self
+    }
+    /// add an optional param
+    /// # Panics
+    /// when a rest param was already added
+    pub fn optional(mut self, arg_type: ArgType) -> Self {
+        assert!(
+            self.rest.is_none(),
+            "optional params must precede the rest param"
+        );
+        self.optional.push(arg_type);
+        self
+    }
+    /// set the type of all remaining arguments
+    pub fn rest(mut self, arg_type: ArgType) -> Self {
+        self.rest = Some(arg_type);
+        self
+    }
+    /// check if the arguments match this signature
+    pub fn matches(&self, args: &[QuickJsValueAdapter]) -> bool {
+        if args.len() < self.required.len() {
+            return false;
+        }
+        if self.rest.is_none() && args.len() > self.required.len() + self.optional.len() {
+            return false;
+        }
+        args.iter().enumerate().all(|(index, arg)| {
+            if let Some(arg_type) = self.required.get(index) {
+                arg_type.matches(arg)
+            } else if let Some(arg_type) = self.optional.get(index - self.required.len()) {
+                arg.is_undefined() || arg_type.matches(arg)
+            } else {
+                // the length check above guarantees a rest param here
+                self.rest
+                    .map(|arg_type| arg_type.matches(arg))
+                    .unwrap_or(false)
+            }
+        })
+    }
+}
+
+impl Display for Signature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut params: Vec<String> = self.required.iter().map(|t| t.to_string()).collect();
+        params.extend(self.optional.iter().map(|t| format!("{t}?")));
+        if let Some(rest) = self.rest {
+            params.push(format!("...{rest}"));
+        }
+        f.write_str(params.join(", ").as_str())
+    }
+}
+
+/// create a [Signature](crate::reflection::overloads::Signature)
+///
+/// params are names of [ArgType](crate::reflection::overloads::ArgType) variants, optional params are suffixed with `?` and the rest param is prefixed with `..`
+/// # Example
+/// ```rust
+/// use quickjs_runtime::sig;
+/// use quickjs_runtime::reflection::overloads::{ArgType, Signature};
+/// let signature = sig![String, Number?, ..Any];
+/// assert_eq!(signature, Signature::new().required(ArgType::String).optional(ArgType::Number).rest(ArgType::Any));
+/// ```
+#[macro_export]
+macro_rules! sig {
+    (@acc $sig:expr;) => {
+        $sig
+    };
+    (@acc $sig:expr; .. $t:ident) => {
+        $sig.rest($crate::reflection::overloads::ArgType::$t)
+    };
+    (@acc $sig:expr; $t:ident ? $(, $($rest:tt)*)?) => {
+        $crate::sig!(@acc $sig.optional($crate::reflection::overloads::ArgType::$t); $($($rest)*)?)
+    };
+    (@acc $sig:expr; $t:ident $(, $($rest:tt)*)?) => {
+        $crate::sig!(@acc $sig.required($crate::reflection::overloads::ArgType::$t); $($($rest)*)?)
+    };
+    ($($params:tt)*) => {
+        $crate::sig!(@acc $crate::reflection::overloads::Signature::new(); $($params)*)
+    };
+}
+
+/// the overloads of a single method, in registration order
+pub(crate) struct Overloads<M: ?Sized> {
+    entries: Vec<(Signature, Box<M>)>,
+    // the lowest and highest arg count which any overload accepts, usize::MAX when an overload has a rest param
+    min_args: usize,
+    max_args: usize,
+}
+
+impl<M: ?Sized> Overloads<M> {
+    pub(crate) fn new(entries: Vec<(Signature, Box<M>)>) -> Self {
+        let min_args = entries
+            .iter()
+            .map(|(sig, _)| sig.required.len())
+            .min()
+            .unwrap_or(0);
+        let max_args = entries
+            .iter()
+            .map(|(sig, _)| match sig.rest {
+                Some(_) => usize::MAX,
+                None => sig.required.len() + sig.optional.len(),
+            })
+            .max()
+            .unwrap_or(0);
+        Self {
+            entries,
+            min_args,
+            max_args,
+        }
+    }
+
+    /// find the first overload which matches the arguments or a TypeError which lists the supported signatures
+    pub(crate) fn select(
+        &self,
+        method_name: &str,
+        args: &[QuickJsValueAdapter],
+    ) -> Result<&M, JsError> {
+        if args.len() >= self.min_args && args.len() <= self.max_args {
+            if let Some((_, method)) = self.entries.iter().find(|(sig, _)| sig.matches(args)) {
+                return Ok(method.as_ref());
+            }
+        }
+        let arg_types: Vec<String> = args.iter().map(|a| a.get_js_type().to_string()).collect();
+        let supported: Vec<String> = self
+            .entries
+            .iter()
+            .map(|(sig, _)| format!("{method_name}({sig})"))
+            .collect();
+        Err(JsError::new(
+            "TypeError".to_string(),
+            format!(
+                "no overload of {method_name} matches ({}), supported signatures are: {}",
+                arg_types.join(", "),
+                supported.join(", ")
+            ),
+            "".to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::facades::tests::init_test_rt;
+    use crate::jsutils::Script;
+    use crate::reflection::Proxy;
+
+    #[test]
+    fn test_method_overloads() {
+        let rt = init_test_rt();
+        rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let realm = q_js_rt.get_main_realm();
+            Proxy::new()
+                .name("OverloadTest")
+                .constructor(|_rt, _realm, _id, _args| Ok(()))
+                .method_overload("get", sig![Number], |_rt, realm, _id, args| {
+                    realm.create_string(format!("id:{}", args[0].to_i32()).as_str())
+                })
+                .method_overload("get", sig![Object], |_rt, realm, _id, _args| {
+                    realm.create_string("query")
+                })
+                .method_overload("get", sig![String, Number?, ..Any], |_rt, realm, _id, args| {
+                    realm.create_string(format!("name:{}", args.len()).as_str())
+                })
+                .static_method_overload("of", sig![], |_rt, realm, _args| {
+                    realm.create_string("empty")
+                })
+                .static_method_overload("of", sig![Array], |_rt, realm, _args| {
+                    realm.create_string("array")
+                })
+                .install(realm, true)
+                .expect("could not install proxy");
+
+            let res = realm
+                .eval(Script::new(
+                    "test_method_overloads.js",
+                    "let o = new OverloadTest(); [o.get(1), o.get({}), o.get('a'), o.get('a', undefined), o.get('a', 2, true, null), OverloadTest.of(), OverloadTest.of([])].join('|');",
+                ))
+                .expect("script failed");
+            assert_eq!(
+                res.to_string().expect("not a string"),
+                "id:1|query|name:1|name:2|name:4|empty|array"
+            );
+
+            let res = realm
+                .eval(Script::new(
+                    "test_method_overloads.js",
+                    "let msg; try {o.get(true);} catch(e) {msg = `${e.name}: ${e.message}`;} msg;",
+                ))
+                .expect("script failed");
+            let msg = res.to_string().expect("not a string");
+            assert!(msg.starts_with("TypeError: "), "{msg}");
+            assert!(
+                msg.contains("get(Number), get(Object), get(String, Number?, ...Any)"),
+                "{msg}"
+            );
+
+            let res = realm
+                .eval(Script::new(
+                    "test_method_overloads.js",
+                    "let msg2; try {o.get('a', 'b');} catch(e) {msg2 = e.name;} msg2;",
+                ))
+                .expect("script failed");
+            assert_eq!(res.to_string().expect("not a string"), "TypeError");
+        });
+    }
+}
@@ -1,10 +1,18 @@
 use crate::facades::QuickjsRuntimeFacadeInner;
+use crate::jsutils::remoteref::RemoteRefGuard;
 use crate::jsutils::{JsError, JsValueType};
+use crate::quickjs_utils::columns::{extract_columns_q, ColumnType, Columns, TypeMismatchPolicy};
+use crate::quickjs_utils::conversion::{self, UndefinedPolicy};
+use crate::quickjs_utils::objects::PatchOp;
+use crate::quickjs_utils::watch::{watch_q, WatchOptions};
+use crate::quickjs_utils::{functions, objects};
 use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
 use crate::quickjsvalueadapter::QuickJsValueAdapter;
 use crate::reflection::JsProxyInstanceId;
+use flume::r#async::RecvStream;
 use futures::executor::block_on;
-use futures::Future;
+use futures::{Future, Stream};
 use hirofa_utils::debug_mutex::DebugMutex;
 use serde::Serialize;
 use serde_json::Value;
@@ -12,16 +20,19 @@ use std::collections::HashMap;
 use std::error::Error;
 use std::fmt::{Debug, Formatter};
 use std::pin::Pin;
-use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU16, Ordering};
 use std::sync::{Arc, Weak};
-use std::time::Duration;
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use string_cache::DefaultAtom;
 
 pub struct CachedJsObjectRef {
     pub(crate) id: i32,
     rti: Weak<QuickjsRuntimeFacadeInner>,
     realm_id: String,
-    drop_action: DebugMutex<Option<Box<dyn FnOnce() + Send>>>,
+    realm_alive: Arc<AtomicBool>,
+    // releases the cached object when this ref is dropped
+    guard: RemoteRefGuard,
 }
 
 pub struct CachedJsPromiseRef {
@@ -39,46 +50,51 @@ pub struct CachedJsFunctionRef {
 impl CachedJsObjectRef {
     pub(crate) fn new(realm: &QuickJsRealmAdapter, obj: QuickJsValueAdapter) -> Self {
         let id = realm.cache_object(obj);
-        let rti_ref = realm.get_runtime_facade_inner();
-
-        let drop_id = id;
-        let drop_realm_name = realm.get_realm_id().to_string();
-
-        Self::new2(
-            id,
-            rti_ref.clone(),
-            realm.get_realm_id().to_string(),
-            move || {
-                if let Some(rti) = rti_ref.upgrade() {
-                    rti.add_rt_task_to_event_loop_void(move |rt| {
-                        if let Some(realm) = rt.get_realm(drop_realm_name.as_str()) {
-                            realm.dispose_cached_object(drop_id);
-                        }
-                    })
-                }
-            },
-        )
-    }
-    fn new2<F: FnOnce() + Send + 'static>(
-        id: i32,
-        rti: Weak<QuickjsRuntimeFacadeInner>,
-        realm_name: String,
-        drop_action: F,
-    ) -> Self {
         Self {
             id,
-            rti,
-            realm_id: realm_name,
-            drop_action: DebugMutex::new(
-                Some(Box::new(drop_action)),
-                "CachedJsObjectRef.drop_action",
-            ),
+            rti: realm.get_runtime_facade_inner(),
+            realm_id: realm.get_realm_id().to_string(),
+            realm_alive: realm.alive.clone(),
+            guard: RemoteRefGuard::new(realm, id),
         }
     }
+    pub(crate) fn realm_id(&self) -> &str {
+        self.realm_id.as_str()
+    }
+    /// check if the realm of this object was not dropped
+    pub fn is_realm_alive(&self) -> bool {
+        self.realm_alive.load(Ordering::SeqCst)
+    }
+    fn check_realm_alive(&self) -> Result<(), JsError> {
+        if self.is_realm_alive() {
+            Ok(())
+        } else {
+            Err(JsError::new_context_destroyed())
+        }
+    }
+    // the runtime may have been dropped before this ref
+    fn runtime(&self) -> Result<Arc<QuickjsRuntimeFacadeInner>, JsError> {
+        self.rti
+            .upgrade()
+            .ok_or_else(|| JsError::new_str("the runtime of the cached object was dropped"))
+    }
+    // the realm of the object, a realm with the same id may have been created after the original one was dropped
+    fn lookup_realm<'a>(
+        rt: &'a QuickJsRuntimeAdapter,
+        realm_id: &str,
+        realm_alive: &AtomicBool,
+    ) -> Result<&'a QuickJsRealmAdapter, JsError> {
+        if !realm_alive.load(Ordering::SeqCst) {
+            return Err(JsError::new_context_destroyed());
+        }
+        rt.get_realm(realm_id)
+            .ok_or_else(|| JsError::new_str("Realm was disposed"))
+    }
     pub async fn to_json_string(&self) -> Result<String, JsError> {
+        self.check_realm_alive()?;
         let id = self.id;
         let realm_name = self.realm_id.clone();
-        let rti = self.rti.upgrade().expect("invalid state");
+        let rti = self.runtime()?;
         rti.add_rt_task_to_event_loop(move |rt| {
             if let Some(realm) = rt.get_realm(realm_name.as_str()) {
                 //let realm: JsRealmAdapter<JsRuntimeAdapterType = (), JsValueAdapterType = ()> = realm;
@@ -94,42 +110,100 @@ impl CachedJsObjectRef {
     }
 
     pub async fn get_object(&self) -> Result<HashMap<String, JsValueFacade>, JsError> {
+        self.get_object_with_policy(UndefinedPolicy::Keep).await
+    }
+    /// get the properties of the object, see [conversion](crate::quickjs_utils::conversion) for how undefined properties are converted
+    pub async fn get_object_with_policy(
+        &self,
+        policy: UndefinedPolicy,
+    ) -> Result<HashMap<String, JsValueFacade>, JsError> {
+        self.with_obj(move |realm, obj| conversion::to_map_q(realm, obj, policy))
+            .await?
+    }
+    pub async fn get_serde_value(&self) -> Result<serde_json::Value, JsError> {
+        self.check_realm_alive()?;
         let id = self.id;
         let realm_name = self.realm_id.clone();
-        let rti = self.rti.upgrade().expect("invalid state");
+        let rti = self.runtime()?;
         rti.add_rt_task_to_event_loop(move |rt| {
             if let Some(realm) = rt.get_realm(realm_name.as_str()) {
-                //let realm: JsRealmAdapter = realm;
-                let mut ret = HashMap::new();
-                let results = realm.with_cached_object(id, |obj| {
-                    realm.traverse_object(obj, |name, value| {
-                        //
-                        Ok((name.to_string(), realm.to_js_value_facade(value)))
+                realm.with_cached_object(id, |obj| realm.value_adapter_to_serde_value(obj))
+            } else {
+                Err(JsError::new_str("no such realm"))
+            }
+        })
+        .await
+    }
+    /// get the object as a serde value with an [UndefinedPolicy]
+    pub async fn get_serde_value_with_policy(
+        &self,
+        policy: UndefinedPolicy,
+    ) -> Result<serde_json::Value, JsError> {
+        self.with_obj(move |realm, obj| conversion::to_serde_value_q(realm, obj, policy))
+            .await?
+    }
+    /// calculate the ops needed to turn this object into other, see [objects::diff_q](crate::quickjs_utils::objects::diff_q)
+    pub async fn diff(
+        &self,
+        other: &CachedJsObjectRef,
+        max_depth: usize,
+    ) -> Result<Vec<PatchOp>, JsError> {
+        self.check_realm_alive()?;
+        if self.realm_id != other.realm_id {
+            return Err(JsError::new_str(
+                "can not diff objects from different realms",
+            ));
+        }
+        let id = self.id;
+        let other_id = other.id;
+        let realm_name = self.realm_id.clone();
+        let rti = self.runtime()?;
+        rti.add_rt_task_to_event_loop(move |rt| {
+            if let Some(realm) = rt.get_realm(realm_name.as_str()) {
+                realm.with_cached_object(id, |old| {
+                    realm.with_cached_object(other_id, |new| {
+                        objects::diff_q(realm, old, new, max_depth)
                     })
-                })?;
-                for result in results {
-                    ret.insert(result.0, result.1?);
-                }
-                Ok(ret)
+                })
             } else {
                 Err(JsError::new_str("no such realm"))
             }
         })
         .await
     }
-    pub async fn get_serde_value(&self) -> Result<serde_json::Value, JsError> {
+    pub fn diff_sync(
+        &self,
+        other: &CachedJsObjectRef,
+        max_depth: usize,
+    ) -> Result<Vec<PatchOp>, JsError> {
+        block_on(self.diff(other, max_depth))
+    }
+    /// apply a list of ops to this object, see [objects::apply_patch_q](crate::quickjs_utils::objects::apply_patch_q)
+    /// ops which replace the root of the object are not supported here
+    pub async fn apply_patch(&self, ops: Vec<PatchOp>) -> Result<(), JsError> {
+        self.check_realm_alive()?;
+        if ops.iter().any(|op| op.get_path().is_empty()) {
+            return Err(JsError::new_str(
+                "can not replace a cached object with apply_patch",
+            ));
+        }
         let id = self.id;
         let realm_name = self.realm_id.clone();
-        let rti = self.rti.upgrade().expect("invalid state");
+        let rti = self.runtime()?;
         rti.add_rt_task_to_event_loop(move |rt| {
             if let Some(realm) = rt.get_realm(realm_name.as_str()) {
-                realm.with_cached_object(id, |obj| realm.value_adapter_to_serde_value(obj))
+                realm
+                    .with_cached_object(id, |obj| objects::apply_patch_q(realm, obj, &ops))
+                    .map(|_| ())
             } else {
                 Err(JsError::new_str("no such realm"))
             }
         })
         .await
     }
+    pub fn apply_patch_sync(&self, ops: Vec<PatchOp>) -> Result<(), JsError> {
+        block_on(self.apply_patch(ops))
+    }
     pub fn with_obj_sync<
         S: Send + 'static,
         C: FnOnce(&QuickJsRealmAdapter, &QuickJsValueAdapter) -> S + Send + 'static,
@@ -137,15 +211,14 @@ impl CachedJsObjectRef {
         &self,
         consumer: C,
     ) -> Result<S, JsError> {
+        self.check_realm_alive()?;
         let id = self.id;
         let realm_id = self.realm_id.clone();
-        let rti = self.rti.upgrade().expect("invalid state");
+        let realm_alive = self.realm_alive.clone();
+        let rti = self.runtime()?;
         rti.exe_rt_task_in_event_loop(move |rt| {
-            if let Some(realm) = rt.get_realm(realm_id.as_str()) {
-                Ok(realm.with_cached_object(id, |obj| consumer(realm, obj)))
-            } else {
-                Err(JsError::new_str("Realm was disposed"))
-            }
+            let realm = Self::lookup_realm(rt, realm_id.as_str(), &realm_alive)?;
+            Ok(realm.with_cached_object(id, |obj| consumer(realm, obj)))
         })
     }
     pub fn with_obj_void<
@@ -154,17 +227,23 @@ impl CachedJsObjectRef {
     >(
         &self,
         consumer: C,
-    ) {
+    ) -> Result<(), JsError> {
+        self.check_realm_alive()?;
         let id = self.id;
         let realm_id = self.realm_id.clone();
-        let rti = self.rti.upgrade().expect("invalid state");
+        let realm_alive = self.realm_alive.clone();
+        let rti = self.runtime()?;
         rti.add_rt_task_to_event_loop_void(move |rt| {
-            if let Some(realm) = rt.get_realm(realm_id.as_str()) {
-                realm.with_cached_object(id, |obj| consumer(realm, obj));
-            } else {
-                log::error!("no such realm");
+            match Self::lookup_realm(rt, realm_id.as_str(), &realm_alive) {
+                Ok(realm) => {
+                    realm.with_cached_object(id, |obj| consumer(realm, obj));
+                }
+                Err(err) => {
+                    log::error!("{}", err);
+                }
             }
-        })
+        });
+        Ok(())
     }
     pub async fn with_obj<
         S: Send + 'static,
@@ -173,29 +252,169 @@ impl CachedJsObjectRef {
         &self,
         consumer: C,
     ) -> Result<S, JsError> {
+        self.check_realm_alive()?;
         let id = self.id;
         let realm_id = self.realm_id.clone();
-        let rti = self.rti.upgrade().expect("invalid state");
+        let realm_alive = self.realm_alive.clone();
+        let rti = self.runtime()?;
         rti.add_rt_task_to_event_loop(move |rt| {
-            if let Some(realm) = rt.get_realm(realm_id.as_str()) {
-                Ok(realm.with_cached_object(id, |obj| consumer(realm, obj)))
-            } else {
-                Err(JsError::new_str("Realm was disposed"))
-            }
+            let realm = Self::lookup_realm(rt, realm_id.as_str(), &realm_alive)?;
+            Ok(realm.with_cached_object(id, |obj| consumer(realm, obj)))
         })
         .await
     }
+    /// invoke a method of the object with the object as receiver
+    pub async fn invoke_method(
+        &self,
+        method_name: &str,
+        args: Vec<JsValueFacade>,
+    ) -> Result<JsValueFacade, JsError> {
+        let method_name = method_name.to_string();
+        self.with_obj(move |realm, obj| Self::invoke_method_q(realm, obj, &method_name, args))
+            .await?
+    }
+    /// invoke a method of the object with the object as receiver and wait for the result
+    pub fn invoke_method_sync(
+        &self,
+        method_name: &str,
+        args: Vec<JsValueFacade>,
+    ) -> Result<JsValueFacade, JsError> {
+        let method_name = method_name.to_string();
+        self.with_obj_sync(move |realm, obj| Self::invoke_method_q(realm, obj, &method_name, args))?
+    }
+    fn invoke_method_q(
+        realm: &QuickJsRealmAdapter,
+        obj: &QuickJsValueAdapter,
+        method_name: &str,
+        args: Vec<JsValueFacade>,
+    ) -> Result<JsValueFacade, JsError> {
+        let args = args
+            .into_iter()
+            .map(|arg| realm.from_js_value_facade(arg))
+            .collect::<Result<Vec<_>, _>>()?;
+        let res = realm.invoke_function_on_object_by_name(obj, method_name, &args)?;
+        realm.to_js_value_facade(&res)
+    }
+    /// release the object now instead of in a later job of the EventLoop like dropping this ref does
+    ///
+    /// fails when the realm or the runtime of the object was dropped, the object was already released then
+    pub fn consume(mut self) -> Result<(), JsError> {
+        self.check_realm_alive()?;
+        let rti = self.runtime()?;
+        let id = self.id;
+        let realm_id = self.realm_id.clone();
+        let realm_alive = self.realm_alive.clone();
+        // the object is released here and not by the guard
+        self.guard.disarm();
+        rti.exe_rt_task_in_event_loop(move |rt| {
+            let realm = Self::lookup_realm(rt, realm_id.as_str(), &realm_alive)?;
+            realm.dispose_cached_object(id);
+            Ok(())
+        })
+    }
+    /// watch a property path of this object for changes made by script, see [watch_q](crate::quickjs_utils::watch::watch_q) for the limitations
+    ///
+    /// the returned [JsWatch] is a Stream of the new values, watching stops when it is dropped or [JsWatch::unwatch] is called
+    pub async fn watch(&self, path: &[&str], options: WatchOptions) -> Result<JsWatch, JsError> {
+        self.check_realm_alive()?;
+        let path: Vec<String> = path.iter().map(|p| p.to_string()).collect();
+        let (tx, rx) = flume::unbounded();
+        let unwatch_id = self
+            .with_obj(move |realm, obj| {
+                let path_refs: Vec<&str> = path.iter().map(|p| p.as_str()).collect();
+                let unwatch =
+                    watch_q(
+                        realm,
+                        obj,
+                        &path_refs,
+                        &options,
+                        move |realm, value| match realm.to_js_value_facade(value) {
+                            Ok(jsvf) => {
+                                let _ = tx.send(jsvf);
+                            }
+                            Err(err) => log::error!("could not convert watched value: {err}"),
+                        },
+                    )?;
+                Ok::<_, JsError>(realm.cache_object(unwatch))
+            })
+            .await??;
+
+        let rti_ref = self.rti.clone();
+        let realm_id = self.realm_id.clone();
+        let realm_alive = self.realm_alive.clone();
+        Ok(JsWatch {
+            receiver: rx.clone(),
+            stream: rx.into_stream(),
+            unwatch_action: Some(Box::new(move || {
+                if let Some(rti) = rti_ref.upgrade() {
+                    rti.add_rt_task_to_event_loop_void(move |rt| {
+                        if let Some(realm) = get_live_realm(rt, realm_id.as_str(), &realm_alive) {
+                            let unwatch = realm.consume_cached_object(unwatch_id);
+                            if let Err(err) = functions::call_function_q(realm, &unwatch, &[], None)
+                            {
+                                log::error!("unwatch failed: {err}");
+                            }
+                        }
+                    })
+                }
+            })),
+        })
+    }
+    pub fn watch_sync(&self, path: &[&str], options: WatchOptions) -> Result<JsWatch, JsError> {
+        block_on(self.watch(path, options))
+    }
 }
 
-impl Drop for CachedJsObjectRef {
-    fn drop(&mut self) {
-        let lck = &mut *self.drop_action.lock("drop").unwrap();
-        if let Some(da) = lck.take() {
-            da();
+/// a Stream of the values of a watched property path, see [CachedJsObjectRef::watch]
+pub struct JsWatch {
+    receiver: flume::Receiver<JsValueFacade>,
+    stream: RecvStream<'static, JsValueFacade>,
+    unwatch_action: Option<Box<dyn FnOnce() + Send>>,
+}
+
+impl JsWatch {
+    /// get the next value if one was already emitted
+    pub fn try_next(&self) -> Option<JsValueFacade> {
+        self.receiver.try_recv().ok()
+    }
+    /// stop watching, the original properties are restored
+    pub fn unwatch(mut self) {
+        self.do_unwatch();
+    }
+    fn do_unwatch(&mut self) {
+        if let Some(action) = self.unwatch_action.take() {
+            action();
         }
     }
 }
 
+impl Stream for JsWatch {
+    type Item = JsValueFacade;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.stream).poll_next(cx)
+    }
+}
+
+impl Drop for JsWatch {
+    fn drop(&mut self) {
+        self.do_unwatch();
+    }
+}
+
+/// get a realm by id, but only if it is the realm of the cached object and not a new realm with the same id
+fn get_live_realm<'a>(
+    rt: &'a QuickJsRuntimeAdapter,
+    realm_id: &str,
+    realm_alive: &AtomicBool,
+) -> Option<&'a QuickJsRealmAdapter> {
+    if realm_alive.load(Ordering::SeqCst) {
+        rt.get_realm(realm_id)
+    } else {
+        None
+    }
+}
+
 impl CachedJsPromiseRef {
     pub async fn get_serde_value(&self) -> Result<serde_json::Value, JsError> {
         self.cached_object.get_serde_value().await
@@ -239,12 +458,13 @@ impl CachedJsPromiseRef {
 
         let tx1 = tx.clone();
         let tx2 = tx.clone();
+        let tx3 = tx.clone();
 
         let state = Arc::new(AtomicU16::new(0));
         let state_then = state.clone();
         let state_catch = state.clone();
 
-        self.cached_object.with_obj_void(move |realm, obj| {
+        let added = self.cached_object.with_obj_void(move |realm, obj| {
             let res = || {
                 let then_func = realm.create_function(
                     "then",
@@ -310,6 +530,10 @@ impl CachedJsPromiseRef {
                 }
             }
         });
+        if let Err(err) = added {
+            // the realm of the promise was dropped
+            let _ = tx3.send(Err(err));
+        }
 
         rx
     }
@@ -322,15 +546,42 @@ impl CachedJsArrayRef {
     pub async fn to_json_string(&self) -> Result<String, JsError> {
         self.cached_object.to_json_string().await
     }
+    /// get the elements of the array, undefined elements and holes are converted to [JsValueFacade::Undefined]
     pub async fn get_array(&self) -> Result<Vec<JsValueFacade>, JsError> {
+        self.get_array_with_policy(UndefinedPolicy::Keep).await
+    }
+    /// get the elements of the array, see [conversion](crate::quickjs_utils::conversion) for how undefined elements are converted
+    pub async fn get_array_with_policy(
+        &self,
+        policy: UndefinedPolicy,
+    ) -> Result<Vec<JsValueFacade>, JsError> {
+        self.cached_object
+            .with_obj(move |realm, arr| conversion::to_array_q(realm, arr, policy))
+            .await?
+    }
+    /// get the elements of the array, a hole of a sparse array is None
+    pub async fn get_sparse_array(&self) -> Result<Vec<Option<JsValueFacade>>, JsError> {
         self.cached_object
-            .with_obj(|realm, arr| {
-                let mut vec: Vec<JsValueFacade> = vec![];
-                realm.traverse_array_mut(arr, |_index, element| {
-                    vec.push(realm.to_js_value_facade(element)?);
-                    Ok(())
-                })?;
-                Ok(vec)
+            .with_obj(conversion::to_sparse_array_q)
+            .await?
+    }
+    /// read the named properties of every element into typed columns, see [extract_columns_q](crate::quickjs_utils::columns::extract_columns_q)
+    pub async fn extract_columns(
+        &self,
+        columns: &[(&str, ColumnType)],
+        mismatch_policy: TypeMismatchPolicy,
+    ) -> Result<Columns, JsError> {
+        let columns: Vec<(String, ColumnType)> = columns
+            .iter()
+            .map(|(name, column_type)| (name.to_string(), *column_type))
+            .collect();
+        self.cached_object
+            .with_obj(move |realm, arr| {
+                let columns: Vec<(&str, ColumnType)> = columns
+                    .iter()
+                    .map(|(name, column_type)| (name.as_str(), *column_type))
+                    .collect();
+                extract_columns_q(realm, arr, &columns, mismatch_policy)
             })
             .await?
     }
@@ -348,9 +599,13 @@ impl CachedJsFunctionRef {
         //Pin<Box<dyn futures::Future<Output = Result<JsValueFacade, JsError>>>>
         let cached_obj_id = self.cached_object.id;
         let realm_id = self.cached_object.realm_id.clone();
+        let realm_alive = self.cached_object.realm_alive.clone();
         let rti = self.cached_object.rti.upgrade().expect("invalid state");
         rti.add_rt_task_to_event_loop(move |rt| {
             //
+            if !realm_alive.load(Ordering::SeqCst) {
+                return Err(JsError::new_context_destroyed());
+            }
             if let Some(realm) = rt.get_realm(realm_id.as_str()) {
                 realm.with_cached_object(cached_obj_id, move |func_adapter| {
                     let mut adapter_args = vec![];
@@ -426,6 +681,14 @@ pub enum JsValueFacade {
     Array {
         val: Vec<JsValueFacade>,
     },
+    // a copy of a Map, the keys are in insertion order and may be any value
+    Map {
+        entries: Vec<(JsValueFacade, JsValueFacade)>,
+    },
+    // a copy of a Set
+    Set {
+        values: Vec<JsValueFacade>,
+    },
     // promise created from rust which will run an async producer
     Promise {
         producer: DebugMutex<
@@ -456,6 +719,15 @@ pub enum JsValueFacade {
     SerdeValue {
         value: serde_json::Value,
     },
+    /// a Date, millis is the time since the epoch or None for an invalid date
+    JsDate {
+        millis: Option<f64>,
+    },
+    /// a value of a user-defined class which was encoded by a [value codec](crate::quickjs_utils::codecs)
+    Custom {
+        tag: String,
+        data: Vec<u8>,
+    },
     Null,
     Undefined,
 }
@@ -488,6 +760,45 @@ impl JsValueFacade {
             val: DefaultAtom::from(val),
         }
     }
+    /// create a Uint8Array, the buffer is moved to script without copying it
+    pub fn new_uint8_array(buffer: Vec<u8>) -> Self {
+        Self::TypedArray {
+            buffer,
+            array_type: TypedArrayType::Uint8,
+        }
+    }
+    /// create a Date from the millis since the epoch, NaN creates an invalid date
+    pub fn new_date(millis: f64) -> Self {
+        Self::JsDate {
+            millis: (!millis.is_nan()).then_some(millis),
+        }
+    }
+    /// create a Map, unlike a HashMap converted with [to_js_value_facade](JsValueConvertable::to_js_value_facade) this is a real Map in script
+    /// create a custom value, it is converted to a value in script by the [value codec](crate::quickjs_utils::codecs) which accepts the tag
+    pub fn new_custom(tag: &str, data: Vec<u8>) -> Self {
+        Self::Custom {
+            tag: tag.to_string(),
+            data,
+        }
+    }
+    pub fn new_map<K: JsValueConvertable, V: JsValueConvertable, I: IntoIterator<Item = (K, V)>>(
+        entries: I,
+    ) -> Self {
+        Self::Map {
+            entries: entries
+                .into_iter()
+                .map(|(key, value)| (key.to_js_value_facade(), value.to_js_value_facade()))
+                .collect(),
+        }
+    }
+    pub fn new_set<V: JsValueConvertable, I: IntoIterator<Item = V>>(values: I) -> Self {
+        Self::Set {
+            values: values
+                .into_iter()
+                .map(JsValueConvertable::to_js_value_facade)
+                .collect(),
+        }
+    }
     pub fn new_callback<
         F: Fn(&[JsValueFacade]) -> Result<JsValueFacade, JsError> + Send + Sync + 'static,
     >(
@@ -543,6 +854,21 @@ impl JsValueFacade {
     pub fn is_js_array(&self) -> bool {
         matches!(self, JsValueFacade::JsArray { .. })
     }
+    pub fn is_map(&self) -> bool {
+        matches!(self, JsValueFacade::Map { .. })
+    }
+    pub fn is_set(&self) -> bool {
+        matches!(self, JsValueFacade::Set { .. })
+    }
+    pub fn is_date(&self) -> bool {
+        matches!(self, JsValueFacade::JsDate { .. })
+    }
+    pub fn is_typed_array(&self) -> bool {
+        matches!(self, JsValueFacade::TypedArray { .. })
+    }
+    pub fn is_custom(&self) -> bool {
+        matches!(self, JsValueFacade::Custom { .. })
+    }
 
     pub fn get_i32(&self) -> i32 {
         match self {
@@ -584,6 +910,54 @@ impl JsValueFacade {
             }
         }
     }
+    /// get the millis since the epoch of a Date, None for an invalid date
+    pub fn get_date(&self) -> Option<f64> {
+        match self {
+            JsValueFacade::JsDate { millis } => *millis,
+            _ => {
+                panic!("Not a date");
+            }
+        }
+    }
+    pub fn get_map(&self) -> &[(JsValueFacade, JsValueFacade)] {
+        match self {
+            JsValueFacade::Map { entries } => entries,
+            _ => {
+                panic!("Not a map");
+            }
+        }
+    }
+    /// get a value of a Map by a string key
+    pub fn get_map_value(&self, key: &str) -> Option<&JsValueFacade> {
+        self.get_map()
+            .iter()
+            .find(|(k, _)| matches!(k, JsValueFacade::String { val } if &**val == key))
+            .map(|(_, value)| value)
+    }
+    pub fn get_set(&self) -> &[JsValueFacade] {
+        match self {
+            JsValueFacade::Set { values } => values,
+            _ => {
+                panic!("Not a set");
+            }
+        }
+    }
+    /// get the bytes of a TypedArray, a TypedArray from script holds a copy of the bytes in its view
+    pub fn get_bytes(&self) -> &[u8] {
+        match self {
+            JsValueFacade::TypedArray { buffer, .. } => buffer,
+            _ => {
+                panic!("Not a typed array");
+            }
+        }
+    }
+    /// take the bytes of a TypedArray
+    pub fn into_bytes(self) -> Option<Vec<u8>> {
+        match self {
+            JsValueFacade::TypedArray { buffer, .. } => Some(buffer),
+            _ => None,
+        }
+    }
     pub fn is_null_or_undefined(&self) -> bool {
         matches!(self, JsValueFacade::Null | JsValueFacade::Undefined)
     }
@@ -598,6 +972,8 @@ impl JsValueFacade {
             JsValueFacade::Undefined => JsValueType::Undefined,
             JsValueFacade::Object { .. } => JsValueType::Object,
             JsValueFacade::Array { .. } => JsValueType::Array,
+            JsValueFacade::Map { .. } => JsValueType::Object,
+            JsValueFacade::Set { .. } => JsValueType::Object,
             JsValueFacade::Promise { .. } => JsValueType::Promise,
             JsValueFacade::Function { .. } => JsValueType::Function,
             JsValueFacade::JsPromise { .. } => JsValueType::Promise,
@@ -607,6 +983,8 @@ impl JsValueFacade {
             JsValueFacade::ProxyInstance { .. } => JsValueType::Object,
             JsValueFacade::TypedArray { .. } => JsValueType::Object,
             JsValueFacade::JsonStr { .. } => JsValueType::Object,
+            JsValueFacade::JsDate { .. } => JsValueType::Date,
+            JsValueFacade::Custom { .. } => JsValueType::Object,
             JsValueFacade::SerdeValue { value } => match value {
                 serde_json::Value::Null => JsValueType::Null,
                 serde_json::Value::Bool(_) => JsValueType::Boolean,
@@ -680,6 +1058,12 @@ impl JsValueFacade {
             JsValueFacade::Array { val } => {
                 format!("Array: [len={}]", val.len())
             }
+            JsValueFacade::Map { entries } => {
+                format!("Map: [len={}]", entries.len())
+            }
+            JsValueFacade::Set { values } => {
+                format!("Set: [len={}]", values.len())
+            }
             JsValueFacade::Promise { .. } => "Promise".to_string(),
             JsValueFacade::Function { .. } => "Function".to_string(),
             JsValueFacade::Null => "Null".to_string(),
@@ -689,6 +1073,11 @@ impl JsValueFacade {
             JsValueFacade::TypedArray { .. } => "TypedArray".to_string(),
             JsValueFacade::JsonStr { json } => format!("JsonStr: '{json}'"),
             JsValueFacade::SerdeValue { value } => format!("Serde value: {value}"),
+            JsValueFacade::JsDate { millis } => match millis {
+                Some(millis) => format!("Date: {millis}"),
+                None => "Date: Invalid".to_string(),
+            },
+            JsValueFacade::Custom { tag, data } => format!("Custom: {tag} [len={}]", data.len()),
         }
     }
     pub async fn to_serde_value(&self) -> Result<serde_json::Value, JsError> {
@@ -703,6 +1092,38 @@ impl JsValueFacade {
             JsValueFacade::JsFunction { .. } => Ok(Value::Null),
             JsValueFacade::Object { .. } => Ok(Value::Null),
             JsValueFacade::Array { .. } => Ok(Value::Null),
+            // a Map with only string keys becomes an object, other Maps become an array of [key, value] arrays
+            JsValueFacade::Map { entries } => {
+                let string_keys = entries
+                    .iter()
+                    .all(|(key, _)| matches!(key, JsValueFacade::String { .. }));
+                if string_keys {
+                    let mut obj = serde_json::Map::new();
+                    for (key, value) in entries {
+                        obj.insert(
+                            key.get_str().to_string(),
+                            Box::pin(value.to_serde_value()).await?,
+                        );
+                    }
+                    Ok(Value::Object(obj))
+                } else {
+                    let mut arr = vec![];
+                    for (key, value) in entries {
+                        arr.push(Value::Array(vec![
+                            Box::pin(key.to_serde_value()).await?,
+                            Box::pin(value.to_serde_value()).await?,
+                        ]));
+                    }
+                    Ok(Value::Array(arr))
+                }
+            }
+            JsValueFacade::Set { values } => {
+                let mut arr = vec![];
+                for value in values {
+                    arr.push(Box::pin(value.to_serde_value()).await?);
+                }
+                Ok(Value::Array(arr))
+            }
             JsValueFacade::Promise { .. } => Ok(Value::Null),
             JsValueFacade::Function { .. } => Ok(Value::Null),
             JsValueFacade::Null => Ok(Value::Null),
@@ -712,6 +1133,8 @@ impl JsValueFacade {
             JsValueFacade::TypedArray { .. } => Ok(Value::Null),
             JsValueFacade::JsonStr { json } => Ok(serde_json::from_str(json).unwrap()),
             JsValueFacade::SerdeValue { value } => Ok(value.clone()),
+            JsValueFacade::JsDate { millis } => Ok(millis.map(Value::from).unwrap_or(Value::Null)),
+            JsValueFacade::Custom { .. } => Ok(Value::Null),
         }
     }
     pub async fn to_json_string(&self) -> Result<String, JsError> {
@@ -726,6 +1149,9 @@ impl JsValueFacade {
             JsValueFacade::JsFunction { .. } => Ok("function () {}".to_string()),
             JsValueFacade::Object { .. } => Ok("{}".to_string()),
             JsValueFacade::Array { .. } => Ok("{}".to_string()),
+            JsValueFacade::Map { .. } | JsValueFacade::Set { .. } => {
+                Ok(serde_json::to_string(&self.to_serde_value().await?).unwrap())
+            }
             JsValueFacade::Promise { .. } => Ok("{}".to_string()),
             JsValueFacade::Function { .. } => Ok("function () {}".to_string()),
             JsValueFacade::Null => Ok("null".to_string()),
@@ -735,6 +1161,11 @@ impl JsValueFacade {
             JsValueFacade::TypedArray { .. } => Ok("[]".to_string()),
             JsValueFacade::JsonStr { json } => Ok(json.clone()),
             JsValueFacade::SerdeValue { value } => Ok(serde_json::to_string(value).unwrap()),
+            JsValueFacade::JsDate { millis } => match millis {
+                Some(millis) => Ok(format!("{millis}")),
+                None => Ok("null".to_string()),
+            },
+            JsValueFacade::Custom { .. } => Ok("{}".to_string()),
         }
     }
 }
@@ -749,6 +1180,12 @@ pub trait JsValueConvertable {
     fn to_js_value_facade(self) -> JsValueFacade;
 }
 
+impl JsValueConvertable for JsValueFacade {
+    fn to_js_value_facade(self) -> JsValueFacade {
+        self
+    }
+}
+
 impl JsValueConvertable for serde_json::Value {
     fn to_js_value_facade(self) -> JsValueFacade {
         JsValueFacade::SerdeValue { value: self }
@@ -785,12 +1222,19 @@ impl JsValueConvertable for String {
     }
 }
 
+impl JsValueConvertable for SystemTime {
+    fn to_js_value_facade(self) -> JsValueFacade {
+        let millis = match self.duration_since(UNIX_EPOCH) {
+            Ok(since) => since.as_secs_f64() * 1000.0,
+            Err(e) => -(e.duration().as_secs_f64() * 1000.0),
+        };
+        JsValueFacade::new_date(millis.trunc())
+    }
+}
+
 impl JsValueConvertable for Vec<u8> {
     fn to_js_value_facade(self) -> JsValueFacade {
-        JsValueFacade::TypedArray {
-            buffer: self,
-            array_type: TypedArrayType::Uint8,
-        }
+        JsValueFacade::new_uint8_array(self)
     }
 }
 